@@ -0,0 +1,77 @@
+use injective_consumer::config::{RedisKeySchema, ScyllaDBConfig};
+use injective_consumer::secrets::{self, RedisRole};
+use serde::Serialize;
+use std::env;
+use std::error::Error;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiConfig {
+    pub listen_addr: String,
+    pub redis_url: String,
+    pub scylladb_nodes: Vec<String>,
+    // Session settings and credentials, read like the consumer's
+    pub scylladb: ScyllaDBConfig,
+    // Must match the schema the consumers write with
+    pub redis_keys: RedisKeySchema,
+    // Depth levels per side when a request does not ask for a number
+    pub default_depth_levels: usize,
+    // How long a read of the liquidatable positions is served to later requests
+    pub liquidatable_cache_ms: u64,
+    // Serve the effective configuration on /admin/config. Off by default,
+    // since the API is usually public.
+    pub expose_config: bool,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        ApiConfig {
+            listen_addr: "0.0.0.0:8080".to_string(),
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            scylladb_nodes: vec!["127.0.0.1:9042".to_string()],
+            scylladb: ScyllaDBConfig::default(),
+            redis_keys: RedisKeySchema::default(),
+            default_depth_levels: 20,
+            liquidatable_cache_ms: 1000,
+            expose_config: false,
+        }
+    }
+}
+
+impl ApiConfig {
+    // REDIS_READ_URL (or REDIS_URL) and SCYLLADB_NODES point at the stores
+    // the consumers write; the API only reads them
+    pub fn from_env() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let consumer = injective_consumer::Config::from_env()?;
+        let mut config = ApiConfig {
+            scylladb: consumer.scylladb,
+            redis_keys: consumer.redis_keys,
+            ..ApiConfig::default()
+        };
+
+        if let Ok(addr) = env::var("API_LISTEN_ADDR") {
+            config.listen_addr = addr;
+        }
+
+        if let Some(url) = secrets::role_redis_url(RedisRole::Reader, None)? {
+            config.redis_url = url;
+        }
+
+        if let Ok(nodes) = env::var("SCYLLADB_NODES") {
+            config.scylladb_nodes = nodes.split(',').map(|s| s.to_string()).collect();
+        }
+
+        if let Ok(levels) = env::var("API_DEPTH_LEVELS") {
+            config.default_depth_levels = levels.parse()?;
+        }
+
+        if let Ok(ms) = env::var("API_LIQUIDATABLE_CACHE_MS") {
+            config.liquidatable_cache_ms = ms.parse()?;
+        }
+
+        if let Ok(expose) = env::var("API_EXPOSE_CONFIG") {
+            config.expose_config = expose.parse()?;
+        }
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,5 @@
+// The REST API's routes, configuration and store interfaces, shared by the
+// binary and the route tests
+pub mod config;
+pub mod routes;
+pub mod store;
@@ -0,0 +1,64 @@
+use injective_api::config::ApiConfig;
+use injective_api::routes::{self, AppState, LiquidatableCache};
+use injective_consumer::trade_history::TieredTradeHistory;
+use injective_consumer::udf::{CandleSource, MarketSource, UdfDatafeed};
+use injective_consumer::{admin, redis_keys};
+use injective_consumer::{RedisReader, ScyllaDBProcessor};
+use log::{error, info};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::signal::ctrl_c;
+
+// Read-only REST API over what the consumers index. Current markets,
+// positions and books come from Redis; trade history continues from
+// ScyllaDB once the recent trades kept in Redis run out, and candles are
+// read from ScyllaDB.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Initialize logging
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    info!("Starting Injective REST API");
+
+    let config = ApiConfig::from_env()?;
+    redis_keys::install(config.redis_keys.clone());
+    admin::runtime().set_config("api", &config);
+
+    info!("Connecting to Redis at {}", config.redis_url);
+    let reader = RedisReader::new(&config.redis_url).await?;
+
+    info!("Connecting to ScyllaDB at {:?}", config.scylladb_nodes);
+    let scylladb = ScyllaDBProcessor::new(config.scylladb_nodes.clone(), &config.scylladb).await?;
+
+    let candles: Arc<dyn CandleSource> = Arc::new(scylladb.candle_source());
+    let markets: Arc<dyn MarketSource> = Arc::new(reader.clone());
+    let state = Arc::new(AppState {
+        trades: Box::new(TieredTradeHistory::new(
+            reader.clone(),
+            scylladb.trade_history(),
+        )),
+        udf: UdfDatafeed::new(markets, candles.clone()),
+        candles,
+        stats: Box::new(scylladb),
+        reader: Arc::new(reader),
+        default_depth_levels: config.default_depth_levels,
+        liquidatable: LiquidatableCache::new(Duration::from_millis(config.liquidatable_cache_ms)),
+    });
+
+    let listener = TcpListener::bind(&config.listen_addr).await?;
+    info!("REST API listening on {}", config.listen_addr);
+
+    axum::serve(listener, routes::router(state, config.expose_config))
+        .with_graceful_shutdown(async {
+            match ctrl_c().await {
+                Ok(()) => info!("Received shutdown signal, stopping REST API..."),
+                Err(e) => error!("Error waiting for shutdown signal: {}", e),
+            }
+        })
+        .await?;
+
+    info!("REST API stopped");
+    Ok(())
+}
@@ -0,0 +1,518 @@
+use crate::store::{HourlyStats, StateReader};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use injective_consumer::admin;
+use injective_consumer::candles::{CandleQuery, UdfHistory};
+use injective_consumer::correlation::CorrelationMatrix;
+use injective_consumer::impact::{FillEstimate, Side};
+use injective_consumer::models::{
+    time, AddressSummary, LiquidatablePosition, MarketData, MarketSummary, PositionData, TopOfBook,
+};
+use injective_consumer::orderbook::L2Book;
+use injective_consumer::scylladb_consumer::{TradeClassification, WriteCount};
+use injective_consumer::trade_history::{self, TradeCursor, TradeHistorySource, TradePage};
+use injective_consumer::udf::{
+    self, CandleSource, MarketSource, UdfConfig, UdfDatafeed, UdfSymbol,
+};
+use injective_consumer::StorageError;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// Deepest book a request can ask for
+const MAX_DEPTH_LEVELS: usize = 100;
+
+pub struct AppState {
+    pub reader: Arc<dyn StateReader>,
+    // Recent trades from Redis, continued from ScyllaDB
+    pub trades: Box<dyn TradeHistorySource>,
+    // Hourly trade classification and write counts from ScyllaDB
+    pub stats: Box<dyn HourlyStats>,
+    // Candle history from ScyllaDB
+    pub candles: Arc<dyn CandleSource>,
+    // TradingView UDF endpoints over the markets and candles above
+    pub udf: UdfDatafeed<Arc<dyn MarketSource>, Arc<dyn CandleSource>>,
+    pub default_depth_levels: usize,
+    pub liquidatable: LiquidatableCache,
+}
+
+// Liquidatable positions of every market as last read from Redis. Bots poll
+// this endpoint hard, so requests within `ttl` of each other share one read,
+// and the lock makes concurrent requests wait for a refresh in flight
+// instead of starting their own.
+pub struct LiquidatableCache {
+    ttl: Duration,
+    entry: Mutex<Option<(Instant, Arc<Vec<LiquidatablePosition>>)>>,
+}
+
+impl LiquidatableCache {
+    // A zero ttl reads Redis on every request
+    pub fn new(ttl: Duration) -> Self {
+        LiquidatableCache {
+            ttl,
+            entry: Mutex::new(None),
+        }
+    }
+
+    async fn get(
+        &self,
+        reader: &dyn StateReader,
+    ) -> Result<Arc<Vec<LiquidatablePosition>>, StorageError> {
+        let mut entry = self.entry.lock().await;
+        if let Some((read_at, positions)) = entry.as_ref() {
+            if read_at.elapsed() < self.ttl {
+                return Ok(positions.clone());
+            }
+        }
+        let positions = Arc::new(reader.get_liquidatable_details(None).await?);
+        *entry = Some((Instant::now(), positions.clone()));
+        Ok(positions)
+    }
+}
+
+pub fn router(state: Arc<AppState>, expose_config: bool) -> Router {
+    let router = Router::new()
+        .route("/markets", get(markets))
+        .route("/markets/summary", get(market_summaries))
+        .route("/markets/{id}/summary", get(market_summary))
+        .route("/markets/{id}/orderbook", get(orderbook))
+        .route("/markets/{id}/estimate_fill", get(estimate_fill))
+        .route("/positions/{subaccount}", get(positions))
+        .route("/addresses/{address}", get(address))
+        .route("/addresses/{address}/positions", get(address_positions))
+        .route("/liquidatable", get(liquidatable))
+        .route("/correlations", get(correlations))
+        .route("/trades", get(trades))
+        .route("/candles", get(candles))
+        .route("/write_counts", get(write_counts))
+        .route("/udf/config", get(udf_config))
+        .route("/udf/symbols", get(udf_symbols))
+        .route("/udf/history", get(udf_history))
+        .route("/udf/time", get(udf_time));
+    let router = if expose_config {
+        router.route("/admin/config", get(effective_config))
+    } else {
+        router
+    };
+    router.with_state(state)
+}
+
+// Errors become a status code and a `{"error": ...}` body
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    Internal(String),
+}
+
+impl From<StorageError> for ApiError {
+    fn from(e: StorageError) -> Self {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            ApiError::Internal(message) => {
+                // Store errors are logged here and not passed on to clients
+                error!("Request failed: {}", message);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal error".to_string(),
+                )
+            }
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+async fn markets(State(state): State<Arc<AppState>>) -> Result<Json<Vec<MarketData>>, ApiError> {
+    Ok(Json(state.reader.get_markets().await?))
+}
+
+async fn market_summaries(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<MarketSummary>>, ApiError> {
+    Ok(Json(state.reader.get_market_summaries().await?))
+}
+
+async fn market_summary(
+    State(state): State<Arc<AppState>>,
+    Path(market_id): Path<String>,
+) -> Result<Json<MarketSummary>, ApiError> {
+    match state.reader.get_market_summary(&market_id).await? {
+        Some(summary) => Ok(Json(summary)),
+        None => Err(ApiError::NotFound(format!(
+            "No summary for market {}",
+            market_id
+        ))),
+    }
+}
+
+#[derive(Deserialize)]
+struct OrderbookQuery {
+    levels: Option<usize>,
+    tick: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct OrderbookResponse {
+    market_id: String,
+    top_of_book: Option<TopOfBook>,
+    // Only kept for markets with depth publishing enabled on the consumer
+    depth: Option<L2Book>,
+}
+
+async fn orderbook(
+    State(state): State<Arc<AppState>>,
+    Path(market_id): Path<String>,
+    Query(query): Query<OrderbookQuery>,
+) -> Result<Json<OrderbookResponse>, ApiError> {
+    let levels = query
+        .levels
+        .unwrap_or(state.default_depth_levels)
+        .clamp(1, MAX_DEPTH_LEVELS);
+    let tick = query.tick.filter(|tick| *tick > 0.0);
+
+    let top_of_book = state.reader.get_top_of_book(&market_id).await?;
+    let depth = state.reader.get_depth(&market_id, tick, levels).await?;
+    if top_of_book.is_none() && depth.is_none() {
+        return Err(ApiError::NotFound(format!(
+            "No orderbook stored for market {}",
+            market_id
+        )));
+    }
+
+    Ok(Json(OrderbookResponse {
+        market_id,
+        top_of_book,
+        depth,
+    }))
+}
+
+async fn positions(
+    State(state): State<Arc<AppState>>,
+    Path(subaccount_id): Path<String>,
+) -> Result<Json<Vec<PositionData>>, ApiError> {
+    Ok(Json(
+        state
+            .reader
+            .get_subaccount_positions(&subaccount_id)
+            .await?,
+    ))
+}
+
+async fn address(
+    State(state): State<Arc<AppState>>,
+    Path(address): Path<String>,
+) -> Result<Json<AddressSummary>, ApiError> {
+    match state.reader.get_address_summary(&address).await? {
+        Some(summary) => Ok(Json(summary)),
+        None => Err(ApiError::NotFound(format!(
+            "No positions stored for address {}",
+            address
+        ))),
+    }
+}
+
+async fn address_positions(
+    State(state): State<Arc<AppState>>,
+    Path(address): Path<String>,
+) -> Result<Json<Vec<PositionData>>, ApiError> {
+    Ok(Json(state.reader.get_address_positions(&address).await?))
+}
+
+#[derive(Deserialize)]
+struct EstimateFillQuery {
+    side: Option<String>,
+    quantity: Option<f64>,
+}
+
+async fn estimate_fill(
+    State(state): State<Arc<AppState>>,
+    Path(market_id): Path<String>,
+    Query(query): Query<EstimateFillQuery>,
+) -> Result<Json<FillEstimate>, ApiError> {
+    let side = match query.side.as_deref() {
+        Some("buy") => Side::Buy,
+        Some("sell") => Side::Sell,
+        _ => return Err(ApiError::BadRequest("side must be buy or sell".to_string())),
+    };
+    let quantity = match query.quantity {
+        Some(quantity) if quantity > 0.0 => quantity,
+        _ => {
+            return Err(ApiError::BadRequest(
+                "quantity must be positive".to_string(),
+            ))
+        }
+    };
+
+    match state
+        .reader
+        .estimate_fill(&market_id, side, quantity)
+        .await?
+    {
+        Some(estimate) => Ok(Json(estimate)),
+        None => Err(ApiError::NotFound(format!(
+            "No book stored for market {}",
+            market_id
+        ))),
+    }
+}
+
+#[derive(Deserialize)]
+struct CorrelationQuery {
+    // Candle resolution of the returns; the consumer's job correlates hourly
+    // closes
+    resolution: Option<String>,
+}
+
+async fn correlations(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CorrelationQuery>,
+) -> Result<Json<CorrelationMatrix>, ApiError> {
+    let resolution = query.resolution.unwrap_or_else(|| "60".to_string());
+    match state.reader.get_correlation_matrix(&resolution).await? {
+        Some(matrix) => Ok(Json(matrix)),
+        None => Err(ApiError::NotFound(format!(
+            "No correlation matrix at resolution {}",
+            resolution
+        ))),
+    }
+}
+
+#[derive(Deserialize)]
+struct LiquidatableQuery {
+    market: Option<String>,
+    min_notional: Option<f64>,
+    // `distance` (the default) puts the positions furthest past their
+    // liquidation price first, `notional` the largest ones
+    sort: Option<String>,
+    limit: Option<usize>,
+}
+
+async fn liquidatable(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<LiquidatableQuery>,
+) -> Result<Json<Vec<LiquidatablePosition>>, ApiError> {
+    let by_notional = match query.sort.as_deref() {
+        None | Some("distance") => false,
+        Some("notional") => true,
+        Some(other) => {
+            return Err(ApiError::BadRequest(format!(
+                "Unknown sort {}, expected distance or notional",
+                other
+            )))
+        }
+    };
+
+    let cached = state.liquidatable.get(state.reader.as_ref()).await?;
+    let mut positions: Vec<LiquidatablePosition> = cached
+        .iter()
+        .filter(|p| {
+            query
+                .market
+                .as_deref()
+                .is_none_or(|market| p.position.market_id == market)
+        })
+        .filter(|p| query.min_notional.is_none_or(|min| p.notional >= min))
+        .cloned()
+        .collect();
+
+    if by_notional {
+        positions.sort_by(|a, b| b.notional.total_cmp(&a.notional));
+    } else {
+        positions.sort_by(|a, b| a.distance_pct.total_cmp(&b.distance_pct));
+    }
+    if let Some(limit) = query.limit {
+        positions.truncate(limit);
+    }
+    Ok(Json(positions))
+}
+
+#[derive(Deserialize)]
+struct TradesQuery {
+    subaccount_id: Option<String>,
+    cursor: Option<String>,
+    limit: Option<usize>,
+    // Market to classify the trades of, during `hour` (unix seconds, the
+    // current hour by default)
+    market_id: Option<String>,
+    hour: Option<i64>,
+}
+
+// A trade page, with the maker/taker split of `market_id` when one is given.
+// `classification` is null without a market or when it had no trades that
+// hour.
+#[derive(Serialize)]
+struct TradesResponse {
+    #[serde(flatten)]
+    page: TradePage,
+    classification: Option<TradeClassification>,
+}
+
+async fn trades(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<TradesQuery>,
+) -> Result<Json<TradesResponse>, ApiError> {
+    let Some(subaccount_id) = query.subaccount_id else {
+        return Err(ApiError::BadRequest(
+            "subaccount_id is required".to_string(),
+        ));
+    };
+    // Checked up front so a bad cursor is the caller's error, not ours
+    if let Some(cursor) = &query.cursor {
+        cursor
+            .parse::<TradeCursor>()
+            .map_err(ApiError::BadRequest)?;
+    }
+
+    let page = trade_history::page(
+        state.trades.as_ref(),
+        &subaccount_id,
+        query.cursor.as_deref(),
+        query.limit,
+    )
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    let classification = match &query.market_id {
+        Some(market_id) => {
+            let hour = time::hour_bucket(query.hour.unwrap_or_else(time::now_millis));
+            state
+                .stats
+                .trade_classification(market_id, hour)
+                .await
+                .map_err(|e| ApiError::Internal(e.to_string()))?
+        }
+        None => None,
+    };
+    Ok(Json(TradesResponse {
+        page,
+        classification,
+    }))
+}
+
+#[derive(Deserialize)]
+struct WriteCountsQuery {
+    // Unix seconds within the hour, the current hour by default
+    hour: Option<i64>,
+}
+
+async fn write_counts(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<WriteCountsQuery>,
+) -> Result<Json<Vec<WriteCount>>, ApiError> {
+    let hour = time::hour_bucket(query.hour.unwrap_or_else(time::now_millis));
+    let counts = state
+        .stats
+        .write_counts(hour)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(counts))
+}
+
+#[derive(Deserialize)]
+struct CandlesQuery {
+    market_id: Option<String>,
+    resolution: Option<String>,
+    // Unix seconds
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+async fn candles(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CandlesQuery>,
+) -> Result<Json<UdfHistory>, ApiError> {
+    let (Some(market_id), Some(resolution), Some(from), Some(to)) =
+        (query.market_id, query.resolution, query.from, query.to)
+    else {
+        return Err(ApiError::BadRequest(
+            "market_id, resolution, from and to are required".to_string(),
+        ));
+    };
+    let query = CandleQuery::new(&market_id, &resolution, from, to)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    let history = udf::candle_history(state.candles.as_ref(), &query)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(history))
+}
+
+async fn udf_config(State(state): State<Arc<AppState>>) -> Json<UdfConfig> {
+    Json(state.udf.config())
+}
+
+// UDF answers the server time as a bare number
+async fn udf_time(State(state): State<Arc<AppState>>) -> String {
+    state.udf.time().to_string()
+}
+
+#[derive(Deserialize)]
+struct UdfSymbolQuery {
+    symbol: String,
+}
+
+async fn udf_symbols(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UdfSymbolQuery>,
+) -> Result<Json<UdfSymbol>, ApiError> {
+    match state.udf.symbols(&query.symbol).await {
+        Ok(Some(symbol)) => Ok(Json(symbol)),
+        Ok(None) => Err(ApiError::NotFound(format!(
+            "Unknown symbol: {}",
+            query.symbol
+        ))),
+        Err(e) => Err(ApiError::Internal(e.to_string())),
+    }
+}
+
+#[derive(Deserialize)]
+struct UdfHistoryQuery {
+    symbol: String,
+    resolution: String,
+    from: i64,
+    to: i64,
+}
+
+async fn udf_history(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UdfHistoryQuery>,
+) -> Result<Json<UdfHistory>, ApiError> {
+    // Checked up front so only store failures are reported as ours
+    CandleQuery::new(&query.symbol, &query.resolution, query.from, query.to)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let known = state
+        .udf
+        .symbols(&query.symbol)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    if known.is_none() {
+        return Err(ApiError::NotFound(format!(
+            "Unknown symbol: {}",
+            query.symbol
+        )));
+    }
+
+    let history = state
+        .udf
+        .history(&query.symbol, &query.resolution, query.from, query.to)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(history))
+}
+
+// The configuration this process resolved, with secrets masked
+async fn effective_config() -> Json<serde_json::Value> {
+    Json(admin::runtime().snapshot())
+}
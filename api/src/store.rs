@@ -0,0 +1,168 @@
+use async_trait::async_trait;
+use injective_consumer::correlation::CorrelationMatrix;
+use injective_consumer::impact::{FillEstimate, Side};
+use injective_consumer::models::{
+    time, AddressSummary, LiquidatablePosition, MarketData, MarketSummary, PositionData, TopOfBook,
+};
+use injective_consumer::orderbook::L2Book;
+use injective_consumer::scylladb_consumer::{TradeClassification, WriteCount};
+use injective_consumer::{RedisReader, ScyllaDBProcessor, StorageError};
+use std::error::Error;
+
+// The current state the routes read. RedisReader serves it in production;
+// the route tests seed fakes instead.
+#[async_trait]
+pub trait StateReader: Send + Sync {
+    async fn get_markets(&self) -> Result<Vec<MarketData>, StorageError>;
+
+    async fn get_top_of_book(&self, market_id: &str) -> Result<Option<TopOfBook>, StorageError>;
+
+    async fn get_depth(
+        &self,
+        market_id: &str,
+        tick: Option<f64>,
+        levels: usize,
+    ) -> Result<Option<L2Book>, StorageError>;
+
+    async fn get_subaccount_positions(
+        &self,
+        subaccount_id: &str,
+    ) -> Result<Vec<PositionData>, StorageError>;
+
+    async fn get_liquidatable_details(
+        &self,
+        market_id: Option<&str>,
+    ) -> Result<Vec<LiquidatablePosition>, StorageError>;
+
+    async fn get_market_summaries(&self) -> Result<Vec<MarketSummary>, StorageError>;
+
+    async fn estimate_fill(
+        &self,
+        market_id: &str,
+        side: Side,
+        quantity: f64,
+    ) -> Result<Option<FillEstimate>, StorageError>;
+
+    async fn get_correlation_matrix(
+        &self,
+        resolution: &str,
+    ) -> Result<Option<CorrelationMatrix>, StorageError>;
+
+    async fn get_market_summary(
+        &self,
+        market_id: &str,
+    ) -> Result<Option<MarketSummary>, StorageError>;
+
+    async fn get_address_summary(
+        &self,
+        owner: &str,
+    ) -> Result<Option<AddressSummary>, StorageError>;
+
+    async fn get_address_positions(&self, owner: &str) -> Result<Vec<PositionData>, StorageError>;
+}
+
+#[async_trait]
+impl StateReader for RedisReader {
+    async fn get_markets(&self) -> Result<Vec<MarketData>, StorageError> {
+        RedisReader::get_markets(self).await
+    }
+
+    async fn get_top_of_book(&self, market_id: &str) -> Result<Option<TopOfBook>, StorageError> {
+        RedisReader::get_top_of_book(self, market_id).await
+    }
+
+    async fn get_depth(
+        &self,
+        market_id: &str,
+        tick: Option<f64>,
+        levels: usize,
+    ) -> Result<Option<L2Book>, StorageError> {
+        RedisReader::get_depth(self, market_id, tick, levels).await
+    }
+
+    async fn get_subaccount_positions(
+        &self,
+        subaccount_id: &str,
+    ) -> Result<Vec<PositionData>, StorageError> {
+        RedisReader::get_subaccount_positions(self, subaccount_id).await
+    }
+
+    async fn get_liquidatable_details(
+        &self,
+        market_id: Option<&str>,
+    ) -> Result<Vec<LiquidatablePosition>, StorageError> {
+        RedisReader::get_liquidatable_details(self, market_id).await
+    }
+
+    async fn get_market_summaries(&self) -> Result<Vec<MarketSummary>, StorageError> {
+        RedisReader::get_market_summaries(self).await
+    }
+
+    async fn estimate_fill(
+        &self,
+        market_id: &str,
+        side: Side,
+        quantity: f64,
+    ) -> Result<Option<FillEstimate>, StorageError> {
+        RedisReader::estimate_fill(self, market_id, side, quantity).await
+    }
+
+    async fn get_correlation_matrix(
+        &self,
+        resolution: &str,
+    ) -> Result<Option<CorrelationMatrix>, StorageError> {
+        RedisReader::get_correlation_matrix(self, resolution).await
+    }
+
+    async fn get_market_summary(
+        &self,
+        market_id: &str,
+    ) -> Result<Option<MarketSummary>, StorageError> {
+        RedisReader::get_market_summary(self, market_id).await
+    }
+
+    async fn get_address_summary(
+        &self,
+        owner: &str,
+    ) -> Result<Option<AddressSummary>, StorageError> {
+        RedisReader::get_address_summary(self, owner).await
+    }
+
+    async fn get_address_positions(&self, owner: &str) -> Result<Vec<PositionData>, StorageError> {
+        RedisReader::get_address_positions(self, owner).await
+    }
+}
+
+// Hourly statistics from ScyllaDB. `hour` is the start of the hour in unix
+// milliseconds.
+#[async_trait]
+pub trait HourlyStats: Send + Sync {
+    async fn trade_classification(
+        &self,
+        market_id: &str,
+        hour: i64,
+    ) -> Result<Option<TradeClassification>, Box<dyn Error + Send + Sync>>;
+
+    async fn write_counts(
+        &self,
+        hour: i64,
+    ) -> Result<Vec<WriteCount>, Box<dyn Error + Send + Sync>>;
+}
+
+#[async_trait]
+impl HourlyStats for ScyllaDBProcessor {
+    async fn trade_classification(
+        &self,
+        market_id: &str,
+        hour: i64,
+    ) -> Result<Option<TradeClassification>, Box<dyn Error + Send + Sync>> {
+        ScyllaDBProcessor::trade_classification(self, market_id, time::to_datetime(hour)).await
+    }
+
+    async fn write_counts(
+        &self,
+        hour: i64,
+    ) -> Result<Vec<WriteCount>, Box<dyn Error + Send + Sync>> {
+        ScyllaDBProcessor::write_counts(self, time::to_datetime(hour)).await
+    }
+}
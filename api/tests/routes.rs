@@ -0,0 +1,694 @@
+// Requests against the router over seeded in-memory stores, checking the JSON
+// each endpoint answers with.
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use http_body_util::BodyExt;
+use injective_api::routes::{self, AppState, LiquidatableCache};
+use injective_api::store::{HourlyStats, StateReader};
+use injective_consumer::candles::{Candle, Resolution};
+use injective_consumer::correlation::CorrelationMatrix;
+use injective_consumer::impact::{self, FillEstimate, Side};
+use injective_consumer::models::{
+    time, AddressSummary, LiquidatablePosition, MarketData, MarketSummary, PositionData,
+    SubaccountTrade, TopOfBook,
+};
+use injective_consumer::orderbook::{BookLevel, L2Book};
+use injective_consumer::scylladb_consumer::{TradeClassification, WriteCount};
+use injective_consumer::trade_history::{TradeCursor, TradeHistorySource};
+use injective_consumer::udf::{CandleSource, MarketSource, UdfDatafeed};
+use injective_consumer::StorageError;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tower::ServiceExt;
+
+const MARKET: &str = "0xmarket";
+const HOUR: i64 = 3_600;
+// 2024-01-01T00:00:00Z
+const MIDNIGHT: i64 = 1_704_067_200;
+
+// What the fakes answer with; anything not seeded reads as empty
+#[derive(Default)]
+struct Seed {
+    markets: Vec<MarketData>,
+    // 1h candles of MARKET
+    candles: Vec<Candle>,
+    summaries: Vec<MarketSummary>,
+    addresses: Vec<AddressSummary>,
+    positions: Vec<PositionData>,
+    correlations: Vec<CorrelationMatrix>,
+    // Stored book of MARKET, best level first
+    bids: Vec<BookLevel>,
+    asks: Vec<BookLevel>,
+    classifications: Vec<TradeClassification>,
+    // Write counts of the hour starting at MIDNIGHT
+    write_counts: Vec<WriteCount>,
+}
+
+struct FakeState(Arc<Seed>);
+
+#[async_trait]
+impl StateReader for FakeState {
+    async fn get_markets(&self) -> Result<Vec<MarketData>, StorageError> {
+        Ok(self.0.markets.clone())
+    }
+
+    async fn get_top_of_book(&self, _market_id: &str) -> Result<Option<TopOfBook>, StorageError> {
+        Ok(None)
+    }
+
+    async fn get_depth(
+        &self,
+        _market_id: &str,
+        _tick: Option<f64>,
+        _levels: usize,
+    ) -> Result<Option<L2Book>, StorageError> {
+        Ok(None)
+    }
+
+    async fn get_subaccount_positions(
+        &self,
+        subaccount_id: &str,
+    ) -> Result<Vec<PositionData>, StorageError> {
+        Ok(self
+            .0
+            .positions
+            .iter()
+            .filter(|p| p.subaccount_id == subaccount_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_liquidatable_details(
+        &self,
+        _market_id: Option<&str>,
+    ) -> Result<Vec<LiquidatablePosition>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    async fn get_market_summaries(&self) -> Result<Vec<MarketSummary>, StorageError> {
+        Ok(self.0.summaries.clone())
+    }
+
+    async fn estimate_fill(
+        &self,
+        market_id: &str,
+        side: Side,
+        quantity: f64,
+    ) -> Result<Option<FillEstimate>, StorageError> {
+        if market_id != MARKET {
+            return Ok(None);
+        }
+        let levels = match side {
+            Side::Buy => &self.0.asks,
+            Side::Sell => &self.0.bids,
+        };
+        Ok(impact::estimate_fill(market_id, levels, side, quantity))
+    }
+
+    async fn get_correlation_matrix(
+        &self,
+        resolution: &str,
+    ) -> Result<Option<CorrelationMatrix>, StorageError> {
+        Ok(self
+            .0
+            .correlations
+            .iter()
+            .find(|m| m.resolution == resolution)
+            .cloned())
+    }
+
+    async fn get_market_summary(
+        &self,
+        market_id: &str,
+    ) -> Result<Option<MarketSummary>, StorageError> {
+        Ok(self
+            .0
+            .summaries
+            .iter()
+            .find(|s| s.market_id == market_id)
+            .cloned())
+    }
+
+    async fn get_address_summary(
+        &self,
+        owner: &str,
+    ) -> Result<Option<AddressSummary>, StorageError> {
+        Ok(self
+            .0
+            .addresses
+            .iter()
+            .find(|a| a.address == owner)
+            .cloned())
+    }
+
+    async fn get_address_positions(&self, owner: &str) -> Result<Vec<PositionData>, StorageError> {
+        let Some(summary) = self.0.addresses.iter().find(|a| a.address == owner) else {
+            return Ok(Vec::new());
+        };
+        Ok(self
+            .0
+            .positions
+            .iter()
+            .filter(|p| summary.subaccount_ids.contains(&p.subaccount_id))
+            .cloned()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl MarketSource for FakeState {
+    async fn market(
+        &self,
+        market_id: &str,
+    ) -> Result<Option<MarketData>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .0
+            .markets
+            .iter()
+            .find(|m| m.market_id == market_id)
+            .cloned())
+    }
+
+    async fn markets(&self) -> Result<Vec<MarketData>, Box<dyn Error + Send + Sync>> {
+        Ok(self.0.markets.clone())
+    }
+}
+
+struct FakeCandles(Arc<Seed>);
+
+#[async_trait]
+impl CandleSource for FakeCandles {
+    async fn candles(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Candle>, Box<dyn Error + Send + Sync>> {
+        if market_id != MARKET || resolution != Resolution::OneHour {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .0
+            .candles
+            .iter()
+            .filter(|c| c.time >= from && c.time <= to)
+            .copied()
+            .collect())
+    }
+
+    async fn candle_before(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        before: i64,
+    ) -> Result<Option<Candle>, Box<dyn Error + Send + Sync>> {
+        if market_id != MARKET || resolution != Resolution::OneHour {
+            return Ok(None);
+        }
+        Ok(self
+            .0
+            .candles
+            .iter()
+            .rev()
+            .find(|c| c.time < before)
+            .copied())
+    }
+}
+
+struct NoTrades;
+
+#[async_trait]
+impl TradeHistorySource for NoTrades {
+    async fn subaccount_trades(
+        &self,
+        _subaccount_id: &str,
+        _before: Option<&TradeCursor>,
+        _limit: usize,
+    ) -> Result<Vec<SubaccountTrade>, Box<dyn Error + Send + Sync>> {
+        Ok(Vec::new())
+    }
+}
+
+struct FakeStats(Arc<Seed>);
+
+#[async_trait]
+impl HourlyStats for FakeStats {
+    async fn trade_classification(
+        &self,
+        market_id: &str,
+        hour: i64,
+    ) -> Result<Option<TradeClassification>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .0
+            .classifications
+            .iter()
+            .find(|c| c.market_id == market_id && c.date_hour.timestamp_millis() == hour)
+            .cloned())
+    }
+
+    async fn write_counts(
+        &self,
+        hour: i64,
+    ) -> Result<Vec<WriteCount>, Box<dyn Error + Send + Sync>> {
+        if hour != MIDNIGHT * 1_000 {
+            return Ok(Vec::new());
+        }
+        Ok(self.0.write_counts.clone())
+    }
+}
+
+fn app(seed: Seed) -> Router {
+    let seed = Arc::new(seed);
+    let candles: Arc<dyn CandleSource> = Arc::new(FakeCandles(seed.clone()));
+    let markets: Arc<dyn MarketSource> = Arc::new(FakeState(seed.clone()));
+    let state = AppState {
+        reader: Arc::new(FakeState(seed.clone())),
+        trades: Box::new(NoTrades),
+        stats: Box::new(FakeStats(seed)),
+        udf: UdfDatafeed::new(markets, candles.clone()),
+        candles,
+        default_depth_levels: 20,
+        liquidatable: LiquidatableCache::new(Duration::ZERO),
+    };
+    routes::router(Arc::new(state), false)
+}
+
+async fn get(app: Router, uri: &str) -> (StatusCode, Value) {
+    let response = app
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+fn market() -> MarketData {
+    MarketData {
+        market_id: MARKET.to_string(),
+        ticker: "INJ/USDT PERP".to_string(),
+        mark_price: 25.5,
+        maintenance_margin_ratio: 0.05,
+        cumulative_funding: 0.0,
+        status: "Active".to_string(),
+        block_height: 100,
+        timestamp: time::to_datetime(MIDNIGHT * 1_000),
+    }
+}
+
+fn position(subaccount_id: &str, is_long: bool) -> PositionData {
+    PositionData {
+        market_id: MARKET.to_string(),
+        subaccount_id: subaccount_id.to_string(),
+        is_long,
+        quantity: 2.5,
+        entry_price: 25.0,
+        margin: 10.0,
+        cumulative_funding_entry: 0.0,
+        liquidation_price: 21.0,
+        is_liquidatable: false,
+        block_height: 100,
+        timestamp: time::to_datetime(MIDNIGHT * 1_000),
+    }
+}
+
+fn candle(time: i64, close: f64) -> Candle {
+    Candle {
+        time,
+        open: close,
+        high: close,
+        low: close,
+        close,
+        volume: 1.0,
+    }
+}
+
+#[tokio::test]
+async fn candles_are_gap_filled_in_the_udf_shape() {
+    let app = app(Seed {
+        candles: vec![candle(MIDNIGHT, 10.0), candle(MIDNIGHT + 2 * HOUR, 12.0)],
+        ..Seed::default()
+    });
+
+    let uri = format!(
+        "/candles?market_id={}&resolution=60&from={}&to={}",
+        MARKET,
+        MIDNIGHT,
+        MIDNIGHT + 2 * HOUR
+    );
+    let (status, body) = get(app, &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["s"], "ok");
+    assert_eq!(
+        body["t"],
+        json!([MIDNIGHT, MIDNIGHT + HOUR, MIDNIGHT + 2 * HOUR])
+    );
+    // The empty hour carries the previous close
+    assert_eq!(body["c"], json!([10.0, 10.0, 12.0]));
+    assert_eq!(body["v"], json!([1.0, 0.0, 1.0]));
+}
+
+#[tokio::test]
+async fn candles_need_a_market_and_a_valid_resolution() {
+    let (status, _) = get(app(Seed::default()), "/candles?resolution=60&from=0&to=1").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let uri = format!("/candles?market_id={}&resolution=7&from=0&to=1", MARKET);
+    let (status, body) = get(app(Seed::default()), &uri).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "Unsupported resolution: 7");
+}
+
+#[tokio::test]
+async fn udf_config_lists_the_resolutions() {
+    let (status, body) = get(app(Seed::default()), "/udf/config").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["supported_resolutions"],
+        json!(["1", "5", "15", "60", "240", "1D"])
+    );
+    assert_eq!(body["supports_time"], true);
+    assert_eq!(body["supports_search"], false);
+}
+
+#[tokio::test]
+async fn udf_time_is_a_bare_unix_timestamp() {
+    let (status, body) = get(app(Seed::default()), "/udf/time").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.as_i64().is_some_and(|now| now > MIDNIGHT));
+}
+
+#[tokio::test]
+async fn udf_symbols_resolve_tickers_and_market_ids() {
+    let seed = || Seed {
+        markets: vec![market()],
+        ..Seed::default()
+    };
+
+    let (status, body) = get(app(seed()), "/udf/symbols?symbol=inj/usdt%20perp").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["name"], "INJ/USDT PERP");
+    assert_eq!(body["type"], "futures");
+    assert_eq!(body["session"], "24x7");
+    assert_eq!(body["pricescale"], 10_000);
+
+    let uri = format!("/udf/symbols?symbol={}", MARKET);
+    let (status, body) = get(app(seed()), &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["ticker"], "INJ/USDT PERP");
+
+    let (status, _) = get(app(seed()), "/udf/symbols?symbol=ATOM").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn udf_history_answers_with_candle_arrays() {
+    let seed = || Seed {
+        markets: vec![market()],
+        candles: vec![candle(MIDNIGHT, 10.0), candle(MIDNIGHT + HOUR, 11.0)],
+        ..Seed::default()
+    };
+
+    let uri = format!(
+        "/udf/history?symbol=INJ/USDT%20PERP&resolution=60&from={}&to={}",
+        MIDNIGHT,
+        MIDNIGHT + HOUR
+    );
+    let (status, body) = get(app(seed()), &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["s"], "ok");
+    assert_eq!(body["t"], json!([MIDNIGHT, MIDNIGHT + HOUR]));
+    assert_eq!(body["o"], json!([10.0, 11.0]));
+    assert_eq!(body["c"], json!([10.0, 11.0]));
+
+    // Before the first candle there is nothing to carry forward
+    let uri = format!(
+        "/udf/history?symbol=INJ/USDT%20PERP&resolution=60&from={}&to={}",
+        MIDNIGHT - 2 * HOUR,
+        MIDNIGHT - HOUR
+    );
+    let (status, body) = get(app(seed()), &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "s": "no_data" }));
+}
+
+#[tokio::test]
+async fn market_summaries_list_every_market() {
+    let app = app(Seed {
+        summaries: vec![MarketSummary {
+            market_id: MARKET.to_string(),
+            last_price: 25.5,
+            high_24h: 26.0,
+            low_24h: 24.0,
+            volume_24h: 1_000.0,
+            open_interest: 2.5,
+            block_height: 100,
+            timestamp: time::to_datetime(MIDNIGHT * 1_000),
+            ..MarketSummary::default()
+        }],
+        ..Seed::default()
+    });
+
+    let (status, body) = get(app, "/markets/summary").await;
+    assert_eq!(status, StatusCode::OK);
+    let summaries = body.as_array().unwrap();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0]["market_id"], MARKET);
+    assert_eq!(summaries[0]["last_price"], 25.5);
+    assert_eq!(summaries[0]["volume_24h"], 1_000.0);
+    assert_eq!(summaries[0]["open_interest"], 2.5);
+}
+
+#[tokio::test]
+async fn a_market_summary_carries_its_funding_apr_and_carry() {
+    let seed = || Seed {
+        summaries: vec![MarketSummary {
+            market_id: MARKET.to_string(),
+            last_price: 25.5,
+            funding_apr: 10.95,
+            carry_bps_per_day: 3.0,
+            block_height: 100,
+            timestamp: time::to_datetime(MIDNIGHT * 1_000),
+            ..MarketSummary::default()
+        }],
+        ..Seed::default()
+    };
+
+    let (status, body) = get(app(seed()), "/markets/0xmarket/summary").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["market_id"], MARKET);
+    assert_eq!(body["funding_apr"], 10.95);
+    assert_eq!(body["carry_bps_per_day"], 3.0);
+
+    let (status, body) = get(app(seed()), "/markets/0xother/summary").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body["error"].as_str().unwrap().contains("0xother"));
+}
+
+#[tokio::test]
+async fn addresses_aggregate_their_subaccounts() {
+    const OWNER: &str = "inj1owner";
+    const FIRST: &str = "0xfirst";
+    const SECOND: &str = "0xsecond";
+    let seed = || Seed {
+        addresses: vec![AddressSummary {
+            address: OWNER.to_string(),
+            subaccount_ids: vec![FIRST.to_string(), SECOND.to_string()],
+            position_count: 2,
+            total_margin: 20.0,
+            unrealized_pnl: 1.25,
+            total_equity: 21.25,
+            block_height: 100,
+            timestamp: time::to_datetime(MIDNIGHT * 1_000),
+        }],
+        positions: vec![
+            position(FIRST, true),
+            position(SECOND, false),
+            position("0xsomeone_else", true),
+        ],
+        ..Seed::default()
+    };
+
+    let (status, body) = get(app(seed()), "/addresses/inj1owner").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["subaccount_ids"], json!([FIRST, SECOND]));
+    assert_eq!(body["position_count"], 2);
+    assert_eq!(body["total_equity"], 21.25);
+
+    let (status, body) = get(app(seed()), "/addresses/inj1owner/positions").await;
+    assert_eq!(status, StatusCode::OK);
+    let subaccounts: Vec<&str> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["subaccount_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(subaccounts, [FIRST, SECOND]);
+
+    let (status, _) = get(app(seed()), "/addresses/inj1nobody").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = get(app(seed()), "/addresses/inj1nobody/positions").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!([]));
+}
+
+#[tokio::test]
+async fn correlations_serve_the_matrix_of_a_resolution() {
+    let seed = || Seed {
+        correlations: vec![CorrelationMatrix {
+            resolution: "60".to_string(),
+            markets: vec![MARKET.to_string(), "0xother".to_string()],
+            correlations: vec![vec![Some(1.0), Some(0.5)], vec![Some(0.5), Some(1.0)]],
+            samples: vec![vec![23, 23], vec![23, 23]],
+            computed_at: MIDNIGHT,
+        }],
+        ..Seed::default()
+    };
+
+    // Hourly is the default
+    let (status, body) = get(app(seed()), "/correlations").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["markets"], json!([MARKET, "0xother"]));
+    assert_eq!(body["correlations"], json!([[1.0, 0.5], [0.5, 1.0]]));
+    assert_eq!(body["samples"][0][1], 23);
+
+    let (status, body) = get(app(seed()), "/correlations?resolution=60").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["resolution"], "60");
+
+    let (status, _) = get(app(seed()), "/correlations?resolution=1D").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn estimate_fill_walks_the_stored_book() {
+    let level = |price, quantity| BookLevel { price, quantity };
+    let seed = || Seed {
+        bids: vec![level(9.0, 1.0)],
+        asks: vec![level(10.0, 1.0), level(11.0, 2.0)],
+        ..Seed::default()
+    };
+
+    let (status, body) = get(
+        app(seed()),
+        "/markets/0xmarket/estimate_fill?side=buy&quantity=2",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["side"], "buy");
+    assert_eq!(body["filled_quantity"], 2.0);
+    assert_eq!(body["average_price"], 10.5);
+    assert_eq!(body["reference_price"], 10.0);
+    assert_eq!(body["slippage_bps"], 500.0);
+    assert_eq!(body["levels_consumed"], 2);
+    assert_eq!(body["worst_price"], 11.0);
+
+    // A thin side fills what it holds
+    let (status, body) = get(
+        app(seed()),
+        "/markets/0xmarket/estimate_fill?side=sell&quantity=3",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["requested_quantity"], 3.0);
+    assert_eq!(body["filled_quantity"], 1.0);
+    assert_eq!(body["slippage_bps"], 0.0);
+
+    for uri in [
+        "/markets/0xmarket/estimate_fill?side=hold&quantity=1",
+        "/markets/0xmarket/estimate_fill?quantity=1",
+        "/markets/0xmarket/estimate_fill?side=buy&quantity=0",
+        "/markets/0xmarket/estimate_fill?side=buy",
+    ] {
+        let (status, _) = get(app(seed()), uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+
+    let (status, _) = get(
+        app(seed()),
+        "/markets/0xother/estimate_fill?side=buy&quantity=1",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn trades_carry_the_classification_of_a_market() {
+    let seed = || Seed {
+        classifications: vec![TradeClassification {
+            market_id: MARKET.to_string(),
+            date_hour: time::to_datetime(MIDNIGHT * 1_000),
+            maker_volume: 100.0,
+            taker_volume: 100.0,
+            taker_buy_volume: 75.0,
+            taker_sell_volume: 25.0,
+            taker_buy_count: 3,
+            taker_sell_count: 1,
+            buy_volume_ratio: 0.75,
+        }],
+        ..Seed::default()
+    };
+
+    // Any time within the hour classifies that hour
+    let uri = format!(
+        "/trades?subaccount_id=0xsub&market_id=0xmarket&hour={}",
+        MIDNIGHT + 600
+    );
+    let (status, body) = get(app(seed()), &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["trades"], json!([]));
+    assert_eq!(body["classification"]["maker_volume"], 100.0);
+    assert_eq!(body["classification"]["taker_buy_volume"], 75.0);
+    assert_eq!(body["classification"]["taker_sell_count"], 1);
+    assert_eq!(body["classification"]["buy_volume_ratio"], 0.75);
+
+    let uri = format!(
+        "/trades?subaccount_id=0xsub&market_id=0xmarket&hour={}",
+        MIDNIGHT + HOUR
+    );
+    let (status, body) = get(app(seed()), &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["classification"].is_null());
+
+    let (status, body) = get(app(seed()), "/trades?subaccount_id=0xsub").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["classification"].is_null());
+    assert!(body["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn write_counts_list_the_rows_written_in_an_hour() {
+    let count = |table_name: &str, message_type: &str, rows| WriteCount {
+        table_name: table_name.to_string(),
+        message_type: message_type.to_string(),
+        rows,
+    };
+    let seed = || Seed {
+        write_counts: vec![
+            count("trades", "DerivativeTrade", 120),
+            count("positions", "DerivativePosition", 40),
+        ],
+        ..Seed::default()
+    };
+
+    let uri = format!("/write_counts?hour={}", MIDNIGHT + 1_800);
+    let (status, body) = get(app(seed()), &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!([
+            {"table_name": "trades", "message_type": "DerivativeTrade", "rows": 120},
+            {"table_name": "positions", "message_type": "DerivativePosition", "rows": 40},
+        ])
+    );
+
+    let uri = format!("/write_counts?hour={}", MIDNIGHT - HOUR);
+    let (status, body) = get(app(seed()), &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!([]));
+}
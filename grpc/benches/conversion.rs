@@ -0,0 +1,124 @@
+// Cost of turning one stream response into Kafka messages, for block sizes seen
+// on mainnet. Compare against an earlier commit with:
+//   cargo bench --bench conversion -- --save-baseline before   (on the old commit)
+//   cargo bench --bench conversion -- --baseline before
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use grpc::models::{KafkaMessage, StreamResponse};
+use grpc::proto::injective::exchange::v1beta1::{Level, PositionDelta};
+use grpc::proto::injective::stream::v1beta1::{
+    DerivativeTrade, Orderbook, OrderbookUpdate, SpotTrade,
+};
+
+const MARKETS: usize = 20;
+
+fn hex_id(prefix: u8, n: usize) -> String {
+    format!("0x{:02x}{:062x}", prefix, n)
+}
+
+fn levels(depth: usize, start: u64) -> Vec<Level> {
+    (0..depth as u64)
+        .map(|i| Level {
+            p: format!("{}000000000000000000000", start + i),
+            q: format!("{}500000000000000000", i + 1),
+        })
+        .collect()
+}
+
+fn orderbook_response(depth: usize) -> StreamResponse {
+    let updates = (0..MARKETS)
+        .map(|m| OrderbookUpdate {
+            seq: m as u64,
+            orderbook: Some(Orderbook {
+                market_id: hex_id(1, m),
+                buy_levels: levels(depth, 30_000),
+                sell_levels: levels(depth, 30_000 + depth as u64),
+            }),
+        })
+        .collect::<Vec<_>>();
+
+    StreamResponse {
+        block_height: 1,
+        block_time: 1_700_000_000_000,
+        spot_orderbook_updates: updates.clone(),
+        derivative_orderbook_updates: updates,
+        ..Default::default()
+    }
+}
+
+fn trade_response(trades: usize) -> StreamResponse {
+    StreamResponse {
+        block_height: 1,
+        block_time: 1_700_000_000_000,
+        spot_trades: (0..trades)
+            .map(|i| SpotTrade {
+                market_id: hex_id(2, i % MARKETS),
+                is_buy: i % 2 == 0,
+                execution_type: "LimitMatchNewOrder".to_string(),
+                quantity: "1000000000000000000".to_string(),
+                price: "25000000".to_string(),
+                subaccount_id: hex_id(3, i),
+                fee: "10000".to_string(),
+                order_hash: hex_id(4, i),
+                fee_recipient_address: "inj1feerecipient".to_string(),
+                cid: String::new(),
+                trade_id: format!("1_{}", i),
+            })
+            .collect(),
+        derivative_trades: (0..trades)
+            .map(|i| DerivativeTrade {
+                market_id: hex_id(1, i % MARKETS),
+                is_buy: i % 2 == 0,
+                execution_type: "LimitMatchNewOrder".to_string(),
+                subaccount_id: hex_id(3, i),
+                position_delta: Some(PositionDelta {
+                    is_long: i % 2 == 0,
+                    execution_quantity: "1000000000000000000".to_string(),
+                    execution_margin: "5000000000000000000000000".to_string(),
+                    execution_price: "30000000000000000000000000000".to_string(),
+                }),
+                payout: "0".to_string(),
+                fee: "10000".to_string(),
+                order_hash: hex_id(4, i),
+                fee_recipient_address: "inj1feerecipient".to_string(),
+                cid: String::new(),
+                trade_id: format!("1_{}", i),
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn bench_orderbooks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("orderbook_conversion");
+    for depth in [10, 100, 500] {
+        let response = orderbook_response(depth);
+        group.throughput(Throughput::Elements((2 * MARKETS * 2 * depth) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &response, |b, r| {
+            b.iter_batched(
+                || r.clone(),
+                Vec::<KafkaMessage>::from,
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_trades(c: &mut Criterion) {
+    let mut group = c.benchmark_group("trade_conversion");
+    for trades in [10, 100, 1_000] {
+        let response = trade_response(trades);
+        group.throughput(Throughput::Elements((2 * trades) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(trades), &response, |b, r| {
+            b.iter_batched(
+                || r.clone(),
+                Vec::<KafkaMessage>::from,
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_orderbooks, bench_trades);
+criterion_main!(benches);
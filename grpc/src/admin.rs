@@ -0,0 +1,70 @@
+use crate::secrets;
+use log::warn;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::sync::{Mutex, OnceLock};
+
+// What the ingester reports on GET /config of its metrics address, and
+// `--print-config` before it starts: the resolved configuration with secrets
+// masked and the filters in effect, such as the lite mode market set.
+#[derive(Default)]
+pub struct RuntimeConfig {
+    config: Mutex<Map<String, Value>>,
+    filters: Mutex<Map<String, Value>>,
+}
+
+pub fn runtime() -> &'static RuntimeConfig {
+    static RUNTIME: OnceLock<RuntimeConfig> = OnceLock::new();
+    RUNTIME.get_or_init(RuntimeConfig::default)
+}
+
+impl RuntimeConfig {
+    // Record a configuration section, replacing an earlier one of that name.
+    // Password fields are never serialized; URLs are masked here since the
+    // endpoints can embed a provider API key.
+    pub fn set_config<T: Serialize>(&self, name: &str, config: &T) {
+        if let Some(value) = masked(name, config) {
+            self.config.lock().unwrap().insert(name.to_string(), value);
+        }
+    }
+
+    // Record a filter in effect, replacing an earlier one of that name
+    pub fn set_filter<T: Serialize>(&self, name: &str, filter: &T) {
+        if let Some(value) = masked(name, filter) {
+            self.filters.lock().unwrap().insert(name.to_string(), value);
+        }
+    }
+
+    pub fn snapshot(&self) -> Value {
+        json!({
+            "config": Value::Object(self.config.lock().unwrap().clone()),
+            "filters": Value::Object(self.filters.lock().unwrap().clone()),
+        })
+    }
+
+    pub fn render(&self) -> String {
+        serde_json::to_string_pretty(&self.snapshot()).unwrap_or_default()
+    }
+}
+
+fn masked<T: Serialize>(name: &str, section: &T) -> Option<Value> {
+    match serde_json::to_value(section) {
+        Ok(mut value) => {
+            mask_urls(&mut value);
+            Some(value)
+        }
+        Err(e) => {
+            warn!("Failed to record the {} configuration: {}", name, e);
+            None
+        }
+    }
+}
+
+fn mask_urls(value: &mut Value) {
+    match value {
+        Value::String(s) if s.contains("://") => *s = secrets::mask_url(s),
+        Value::Array(items) => items.iter_mut().for_each(mask_urls),
+        Value::Object(fields) => fields.values_mut().for_each(mask_urls),
+        _ => {}
+    }
+}
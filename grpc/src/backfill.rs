@@ -0,0 +1,422 @@
+use crate::config::{BackfillConfig, Config};
+use crate::lite_mode::LiteMarketSet;
+use crate::models::{
+    DerivativeTradePayload, FullLimitOrderbookPayload, KafkaMessage, KafkaPayload, MessageType,
+    PositionDeltaPayload, SpotTradePayload,
+};
+use crate::producer::BatchKafkaProducer;
+use crate::query_client::{self, BlockEvent, ExchangeQueryClient};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::error::Error;
+use tokio::sync::watch;
+
+// Historical backfill: replays a range of past blocks into Kafka as the
+// messages the live ingester would have sent for them, so an indexer
+// started late can still build history. Markets and positions come from
+// exchange queries pinned to each height, trades from the execution events
+// of the block. Past heights need an archive node.
+
+const SPOT_EXECUTION_EVENT: &str = "injective.exchange.v1beta1.EventBatchSpotExecution";
+const DERIVATIVE_EXECUTION_EVENT: &str = "injective.exchange.v1beta1.EventBatchDerivativeExecution";
+
+// Decimal places of the chain's Dec type; the stream sends Decs as integers
+// scaled by 10^18, events as decimal strings
+const DEC_PRECISION: usize = 18;
+
+// How often progress is logged
+const PROGRESS_EVERY: u64 = 100;
+
+pub struct Backfill {
+    client: ExchangeQueryClient,
+    producer: BatchKafkaProducer,
+    config: BackfillConfig,
+}
+
+impl Backfill {
+    pub async fn new(config: &Config) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut client = ExchangeQueryClient::connect(&config.grpc).await?;
+
+        // No checkpoint: backfilled blocks are behind the live stream and
+        // must not move its resume point
+        let mut producer = BatchKafkaProducer::new(&config.kafka, "backfill").await?;
+        if config.lite.enabled {
+            let markets = LiteMarketSet::new(config.lite.top_n);
+            markets.refresh(&mut client).await?;
+            producer = producer.with_market_filter(markets);
+        }
+
+        Ok(Backfill {
+            client,
+            producer,
+            config: config.backfill.clone(),
+        })
+    }
+
+    // Send every block from `from` to `to`, both included. Stops at the first
+    // block that can't be queried or delivered, so a rerun can start there.
+    pub async fn run(
+        mut self,
+        from: u64,
+        to: u64,
+        shutdown_rx: watch::Receiver<bool>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        info!("Backfilling blocks {} to {}", from, to);
+        let snapshot_every = self.config.snapshot_every.max(1);
+
+        for height in from..=to {
+            if *shutdown_rx.borrow() {
+                info!("Backfill interrupted before block {}", height);
+                return Ok(());
+            }
+
+            let snapshot = (height - from).is_multiple_of(snapshot_every) || height == to;
+            let messages = self.block_messages(height, snapshot).await.map_err(|e| {
+                error!("Backfill stopped at block {}: {}", height, e);
+                e
+            })?;
+
+            let results = self.producer.send_batch(messages).await;
+            if let Some(Err(e)) = results.iter().find(|result| result.is_err()) {
+                error!("Backfill stopped at block {}: {}", height, e);
+                return Err(format!("failed to send block {}: {}", height, e).into());
+            }
+
+            if (height - from + 1).is_multiple_of(PROGRESS_EVERY) {
+                info!(
+                    "Backfilled {} of {} blocks",
+                    height - from + 1,
+                    to - from + 1
+                );
+            }
+        }
+
+        info!("Backfill of blocks {} to {} complete", from, to);
+        Ok(())
+    }
+
+    // The messages for one block: its trades and, for snapshot blocks, the
+    // markets, positions and optionally orderbooks at that height
+    async fn block_messages(
+        &mut self,
+        height: u64,
+        snapshot: bool,
+    ) -> Result<Vec<KafkaMessage>, Box<dyn Error + Send + Sync>> {
+        let block_time = self.client.get_block_time(height).await?;
+        let message = |message_type, payload| KafkaMessage {
+            message_type,
+            block_height: height,
+            block_time,
+            payload,
+        };
+        let mut messages = Vec::new();
+
+        let (spot_trades, derivative_trades) =
+            trades_from_events(height, &self.client.get_block_events(height).await?);
+        if !spot_trades.is_empty() {
+            messages.push(message(
+                MessageType::SpotTrade,
+                KafkaPayload::SpotTrades(spot_trades),
+            ));
+        }
+        if !derivative_trades.is_empty() {
+            messages.push(message(
+                MessageType::DerivativeTrade,
+                KafkaPayload::DerivativeTrades(derivative_trades),
+            ));
+        }
+        if !snapshot {
+            return Ok(messages);
+        }
+
+        self.client.at_height(Some(height));
+        let snapshots = self.snapshot_payloads(block_time as i64).await;
+        self.client.at_height(None);
+        for (message_type, payload) in snapshots? {
+            messages.push(message(message_type, payload));
+        }
+        Ok(messages)
+    }
+
+    // Markets, positions and orderbooks as the heartbeat sends them, queried
+    // at the client's height
+    async fn snapshot_payloads(
+        &mut self,
+        block_time: i64,
+    ) -> Result<Vec<(MessageType, KafkaPayload)>, Box<dyn Error + Send + Sync>> {
+        let active = Some("Active".to_string());
+        let mut payloads = Vec::new();
+
+        let markets = self.client.get_derivative_markets(active.clone()).await?;
+        let market_ids: Vec<String> = markets
+            .iter()
+            .filter_map(|market| market.market.as_ref())
+            .map(|market| market.market_id.clone())
+            .collect();
+        payloads.push((
+            MessageType::DerivativeMarket,
+            KafkaPayload::DerivativeMarkets(
+                markets
+                    .into_iter()
+                    .map(query_client::convert_derivative_market)
+                    .collect(),
+            ),
+        ));
+
+        let positions = self.client.get_positions().await?;
+        payloads.push((
+            MessageType::ExchangePosition,
+            KafkaPayload::ExchangePositions(
+                positions
+                    .into_iter()
+                    .map(query_client::convert_position)
+                    .collect(),
+            ),
+        ));
+
+        let spot_markets = self.client.get_spot_markets(active).await?;
+        let spot_market_ids: Vec<String> = spot_markets
+            .iter()
+            .map(|market| market.market_id.clone())
+            .collect();
+        payloads.push((
+            MessageType::SpotMarket,
+            KafkaPayload::SpotMarkets(
+                spot_markets
+                    .into_iter()
+                    .map(query_client::convert_spot_market)
+                    .collect(),
+            ),
+        ));
+
+        if self.config.orderbooks {
+            let mut books = Vec::with_capacity(market_ids.len());
+            for market_id in market_ids {
+                let book = self
+                    .client
+                    .get_full_derivative_orderbook(&market_id)
+                    .await?;
+                books.push(FullLimitOrderbookPayload {
+                    market_id,
+                    bids: book
+                        .bids
+                        .into_iter()
+                        .map(query_client::convert_limit_order)
+                        .collect(),
+                    asks: book
+                        .asks
+                        .into_iter()
+                        .map(query_client::convert_limit_order)
+                        .collect(),
+                    timestamp: block_time,
+                });
+            }
+            payloads.push((
+                MessageType::DerivativeFullOrderbook,
+                KafkaPayload::DerivativeFullOrderbooks(books),
+            ));
+
+            let mut books = Vec::with_capacity(spot_market_ids.len());
+            for market_id in spot_market_ids {
+                let book = self.client.get_full_spot_orderbook(&market_id).await?;
+                books.push(FullLimitOrderbookPayload {
+                    market_id,
+                    bids: book
+                        .bids
+                        .into_iter()
+                        .map(query_client::convert_limit_order)
+                        .collect(),
+                    asks: book
+                        .asks
+                        .into_iter()
+                        .map(query_client::convert_limit_order)
+                        .collect(),
+                    timestamp: block_time,
+                });
+            }
+            payloads.push((
+                MessageType::SpotFullOrderbook,
+                KafkaPayload::SpotFullOrderbooks(books),
+            ));
+        }
+
+        Ok(payloads)
+    }
+}
+
+// Parse `<from> <to>` from the arguments after the subcommand
+pub fn parse_range(args: &[String]) -> Result<(u64, u64), Box<dyn Error + Send + Sync>> {
+    let usage = "usage: grpc backfill <from_height> <to_height>";
+    let [from, to] = args else {
+        return Err(usage.into());
+    };
+    let from: u64 = from.parse().map_err(|_| usage)?;
+    let to: u64 = to.parse().map_err(|_| usage)?;
+    if from == 0 || from > to {
+        return Err(format!("invalid block range {} to {}", from, to).into());
+    }
+    Ok((from, to))
+}
+
+#[derive(Deserialize)]
+struct SpotTradeLog {
+    quantity: Option<String>,
+    price: Option<String>,
+    subaccount_id: Option<String>,
+    fee: Option<String>,
+    order_hash: Option<String>,
+    fee_recipient_address: Option<String>,
+    cid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DerivativeTradeLog {
+    subaccount_id: Option<String>,
+    position_delta: Option<PositionDeltaLog>,
+    payout: Option<String>,
+    fee: Option<String>,
+    order_hash: Option<String>,
+    fee_recipient_address: Option<String>,
+    cid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PositionDeltaLog {
+    is_long: Option<bool>,
+    execution_quantity: Option<String>,
+    execution_margin: Option<String>,
+    execution_price: Option<String>,
+}
+
+// Trades of a block from its batch execution events, in the stream's
+// format. Trade ids count the block's trades in event order, so they follow
+// the stream's `{height}_{index}` form but need not match its numbering.
+pub fn trades_from_events(
+    height: u64,
+    events: &[BlockEvent],
+) -> (Vec<SpotTradePayload>, Vec<DerivativeTradePayload>) {
+    let mut spot_trades = Vec::new();
+    let mut derivative_trades = Vec::new();
+    let mut index = 0;
+    let mut next_trade_id = || {
+        index += 1;
+        format!("{}_{}", height, index)
+    };
+
+    for event in events {
+        let kind = event.kind.as_str();
+        if kind != SPOT_EXECUTION_EVENT && kind != DERIVATIVE_EXECUTION_EVENT {
+            continue;
+        }
+        let (Some(market_id), Some(is_buy)) = (
+            attribute::<String>(event, "market_id"),
+            attribute::<bool>(event, "is_buy"),
+        ) else {
+            warn!(
+                "Skipping {} event without a market at block {}",
+                kind, height
+            );
+            continue;
+        };
+        let execution_type = attribute::<String>(event, "executionType")
+            .or_else(|| attribute(event, "execution_type"))
+            .unwrap_or_default();
+
+        if kind == SPOT_EXECUTION_EVENT {
+            for trade in attribute::<Vec<SpotTradeLog>>(event, "trades").unwrap_or_default() {
+                spot_trades.push(SpotTradePayload {
+                    market_id: market_id.clone(),
+                    is_buy,
+                    execution_type: execution_type.clone(),
+                    quantity: chain_integer(trade.quantity),
+                    price: chain_integer(trade.price),
+                    subaccount_id: hex_bytes(trade.subaccount_id),
+                    fee: chain_integer(trade.fee),
+                    order_hash: hex_bytes(trade.order_hash),
+                    fee_recipient_address: bech32_address(trade.fee_recipient_address),
+                    cid: trade.cid.unwrap_or_default(),
+                    trade_id: next_trade_id(),
+                });
+            }
+        } else {
+            for trade in attribute::<Vec<DerivativeTradeLog>>(event, "trades").unwrap_or_default() {
+                let delta = trade.position_delta;
+                derivative_trades.push(DerivativeTradePayload {
+                    market_id: market_id.clone(),
+                    is_buy,
+                    execution_type: execution_type.clone(),
+                    subaccount_id: hex_bytes(trade.subaccount_id),
+                    position_delta: PositionDeltaPayload {
+                        is_long: delta.as_ref().and_then(|d| d.is_long).unwrap_or(false),
+                        execution_quantity: chain_integer(
+                            delta.as_ref().and_then(|d| d.execution_quantity.clone()),
+                        ),
+                        execution_margin: chain_integer(
+                            delta.as_ref().and_then(|d| d.execution_margin.clone()),
+                        ),
+                        execution_price: chain_integer(
+                            delta.as_ref().and_then(|d| d.execution_price.clone()),
+                        ),
+                    },
+                    payout: chain_integer(trade.payout),
+                    fee: chain_integer(trade.fee),
+                    order_hash: hex_bytes(trade.order_hash),
+                    fee_recipient_address: bech32_address(trade.fee_recipient_address),
+                    cid: trade.cid.unwrap_or_default(),
+                    trade_id: next_trade_id(),
+                });
+            }
+        }
+    }
+
+    (spot_trades, derivative_trades)
+}
+
+// A JSON encoded event attribute
+fn attribute<T: DeserializeOwned>(event: &BlockEvent, key: &str) -> Option<T> {
+    serde_json::from_str(event.attribute(key)?).ok()
+}
+
+// A Dec string ("1.5") as the integer scaled by 10^18 the stream sends
+fn chain_integer(dec: Option<String>) -> String {
+    let dec = dec.unwrap_or_default();
+    let (negative, unsigned) = match dec.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, dec.as_str()),
+    };
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let fraction = &fraction[..fraction.len().min(DEC_PRECISION)];
+    let digits = format!("{}{:0<width$}", integer, fraction, width = DEC_PRECISION);
+    let digits = digits.trim_start_matches('0');
+    match (digits.is_empty(), negative) {
+        (true, _) => "0".to_string(),
+        (false, true) => format!("-{}", digits),
+        (false, false) => digits.to_string(),
+    }
+}
+
+// Base64 bytes (subaccount ids, order hashes) as the 0x hex the stream uses
+fn hex_bytes(encoded: Option<String>) -> String {
+    let bytes = BASE64
+        .decode(encoded.unwrap_or_default())
+        .unwrap_or_default();
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("0x{}", hex)
+}
+
+// Base64 account bytes as the bech32 inj address the stream uses
+fn bech32_address(encoded: Option<String>) -> String {
+    let Ok(bytes) = BASE64.decode(encoded.unwrap_or_default()) else {
+        return String::new();
+    };
+    if bytes.is_empty() {
+        return String::new();
+    }
+    bech32::Hrp::parse("inj")
+        .ok()
+        .and_then(|hrp| bech32::encode::<bech32::Bech32>(hrp, &bytes).ok())
+        .unwrap_or_default()
+}
@@ -0,0 +1,101 @@
+use log::info;
+use prost::Message;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::CaptureConfig;
+use crate::models::StreamResponse;
+
+// Records the raw stream responses the ingester receives, so traffic can be
+// replayed later without a node. Each file holds length-delimited protobuf
+// StreamResponses in arrival order and is named after its first block.
+
+pub const CAPTURE_EXTENSION: &str = "capture";
+
+pub struct CaptureWriter {
+    dir: PathBuf,
+    blocks_per_file: u64,
+    file: Option<BufWriter<File>>,
+    first_block: u64,
+}
+
+impl CaptureWriter {
+    pub fn new(dir: impl Into<PathBuf>, blocks_per_file: u64) -> std::io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(CaptureWriter {
+            dir,
+            blocks_per_file: blocks_per_file.max(1),
+            file: None,
+            first_block: 0,
+        })
+    }
+
+    /// Build the configured writer, or `None` if capturing is off
+    pub fn from_config(config: &CaptureConfig) -> std::io::Result<Option<Self>> {
+        match &config.dir {
+            Some(dir) => Ok(Some(Self::new(dir, config.blocks_per_file)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn record(&mut self, response: &StreamResponse) -> std::io::Result<()> {
+        let rotate = match self.file {
+            Some(_) => response.block_height >= self.first_block + self.blocks_per_file,
+            None => true,
+        };
+        if rotate {
+            self.start_file(response.block_height)?;
+        }
+
+        let file = self.file.as_mut().expect("capture file was just opened");
+        file.write_all(&response.encode_length_delimited_to_vec())?;
+        // Flushed per response so a crash loses at most the block in flight
+        file.flush()
+    }
+
+    fn start_file(&mut self, first_block: u64) -> std::io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+
+        let path = self
+            .dir
+            .join(format!("stream-{:012}.{}", first_block, CAPTURE_EXTENSION));
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        info!("Capturing stream responses to {}", path.display());
+
+        self.file = Some(BufWriter::new(file));
+        self.first_block = first_block;
+        Ok(())
+    }
+}
+
+// Read back every response of a capture file, in the order it was recorded
+pub fn read_capture(
+    path: impl AsRef<Path>,
+) -> Result<Vec<StreamResponse>, Box<dyn Error + Send + Sync>> {
+    let bytes = fs::read(path)?;
+    let mut buf = &bytes[..];
+    let mut responses = Vec::new();
+    while !buf.is_empty() {
+        responses.push(StreamResponse::decode_length_delimited(&mut buf)?);
+    }
+    Ok(responses)
+}
+
+// Capture files of a directory, oldest first
+pub fn capture_files(dir: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == CAPTURE_EXTENSION))
+        .collect();
+    // Zero-padded block heights sort by name
+    files.sort();
+    Ok(files)
+}
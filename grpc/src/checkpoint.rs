@@ -0,0 +1,91 @@
+use async_trait::async_trait;
+use redis::AsyncCommands;
+use std::error::Error;
+use std::path::PathBuf;
+
+use crate::config::{CheckpointBackend, CheckpointConfig};
+
+// Where the producer records the highest block it has delivered to Kafka, so
+// a restart knows where the stream left off
+#[async_trait]
+pub trait CheckpointStore: Send + Sync {
+    /// The last checkpointed block height, if one was ever written
+    async fn load(&self) -> Result<Option<u64>, Box<dyn Error + Send + Sync>>;
+
+    async fn save(&self, block_height: u64) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+/// Build the configured store, or `None` if checkpointing is off
+pub async fn from_config(
+    config: &CheckpointConfig,
+) -> Result<Option<Box<dyn CheckpointStore>>, Box<dyn Error + Send + Sync>> {
+    let store: Box<dyn CheckpointStore> = match config.backend {
+        CheckpointBackend::Off => return Ok(None),
+        CheckpointBackend::File => Box::new(FileCheckpointStore::new(&config.path)),
+        CheckpointBackend::Redis => {
+            Box::new(RedisCheckpointStore::new(&config.redis_url()?, &config.redis_key).await?)
+        }
+    };
+    Ok(Some(store))
+}
+
+// Keeps the block height as text in a local file
+pub struct FileCheckpointStore {
+    path: PathBuf,
+}
+
+impl FileCheckpointStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileCheckpointStore { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for FileCheckpointStore {
+    async fn load(&self) -> Result<Option<u64>, Box<dyn Error + Send + Sync>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(contents) => Ok(Some(contents.trim().parse()?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save(&self, block_height: u64) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // Write then rename, so a crash never leaves a half-written file
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, block_height.to_string()).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
+
+// Keeps the block height in a Redis string key
+pub struct RedisCheckpointStore {
+    connection: redis::aio::MultiplexedConnection,
+    key: String,
+}
+
+impl RedisCheckpointStore {
+    pub async fn new(redis_url: &str, key: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let client = redis::Client::open(redis_url)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+        Ok(RedisCheckpointStore {
+            connection,
+            key: key.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for RedisCheckpointStore {
+    async fn load(&self) -> Result<Option<u64>, Box<dyn Error + Send + Sync>> {
+        let mut conn = self.connection.clone();
+        Ok(conn.get(&self.key).await?)
+    }
+
+    async fn save(&self, block_height: u64) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.connection.clone();
+        conn.set::<_, _, ()>(&self.key, block_height).await?;
+        Ok(())
+    }
+}
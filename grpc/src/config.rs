@@ -1,3 +1,6 @@
+use crate::models::MessageType;
+use crate::secrets::{self, Secret};
+use rdkafka::config::ClientConfig;
 use serde::{Deserialize, Serialize};
 use std::env;
 use std::fs::File;
@@ -8,6 +11,22 @@ use std::path::Path;
 pub struct Config {
     pub grpc: GrpcConfig,
     pub kafka: KafkaConfig,
+    #[serde(default)]
+    pub lite: LiteModeConfig,
+    #[serde(default)]
+    pub checkpoint: CheckpointConfig,
+    #[serde(default)]
+    pub capture: CaptureConfig,
+    #[serde(default)]
+    pub backfill: BackfillConfig,
+    #[serde(default)]
+    pub query_server: QueryServerConfig,
+    #[serde(default)]
+    pub resync: ResyncConfig,
+    #[serde(default)]
+    pub redis_keys: RedisKeySchema,
+    #[serde(default)]
+    pub standby: StandbyConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +40,592 @@ pub struct KafkaConfig {
     pub brokers: Vec<String>,
     pub topic: String,
     pub client_id: String,
+    #[serde(default)]
+    pub topics: TopicRouting,
+    #[serde(default)]
+    pub format: SerializationFormat,
+    #[serde(default)]
+    pub security: KafkaSecurity,
+    #[serde(default)]
+    pub orderbook_depth: OrderbookDepthConfig,
+    #[serde(default)]
+    pub orderbook_retention: TopicRetentionConfig,
+    #[serde(default)]
+    pub delivery: DeliveryConfig,
+}
+
+// Producer delivery guarantees. The default favours latency: a leader
+// acknowledgement and few retries, so a retry can duplicate a message.
+// Idempotence makes retries safe; a transactional id additionally commits
+// every batch as one transaction, which consumers reading committed
+// messages (the librdkafka default) see in full or not at all.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeliveryConfig {
+    pub idempotent: bool,
+    // Each producer of the process appends its role, e.g. "<id>-ingester",
+    // so a restarted instance fences off the one it replaces
+    pub transactional_id: Option<String>,
+    // librdkafka's transaction.timeout.ms when unset
+    pub transaction_timeout_ms: Option<u64>,
+}
+
+impl DeliveryConfig {
+    pub fn transactional(&self) -> bool {
+        self.transactional_id.is_some()
+    }
+
+    pub fn transactional_id(&self, role: &str) -> Option<String> {
+        self.transactional_id
+            .as_ref()
+            .map(|id| format!("{}-{}", id, role))
+    }
+
+    // Applied after the latency settings, which idempotence overrides:
+    // librdkafka refuses acks other than all and needs retries to be on
+    pub fn apply(&self, client: &mut ClientConfig, role: &str) {
+        if !self.idempotent && !self.transactional() {
+            return;
+        }
+        client
+            .set("enable.idempotence", "true")
+            .set("acks", "all")
+            .set("max.in.flight.requests.per.connection", "5")
+            .set("message.send.max.retries", "2147483647");
+        if let Some(id) = self.transactional_id(role) {
+            client.set("transactional.id", id);
+        }
+        if let Some(timeout) = self.transaction_timeout_ms {
+            client.set("transaction.timeout.ms", timeout.to_string());
+        }
+    }
+}
+
+// Broker authentication and encryption for managed clusters (MSK,
+// Confluent Cloud). The SASL credentials and the key password are better
+// supplied as secrets (KAFKA_SASL_USERNAME, KAFKA_SASL_PASSWORD,
+// KAFKA_SSL_KEY_PASSWORD) than in the config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KafkaSecurity {
+    // Plaintext when unset
+    pub protocol: Option<SecurityProtocol>,
+    // PLAIN when the protocol uses SASL and no mechanism is set
+    pub sasl_mechanism: Option<SaslMechanism>,
+    pub sasl_username: Option<String>,
+    #[serde(skip_serializing)]
+    pub sasl_password: Option<Secret>,
+    // PEM CA bundle for brokers with a private CA; the system store otherwise
+    pub ssl_ca_location: Option<String>,
+    // Client certificate and key, for brokers that require mutual TLS
+    pub ssl_certificate_location: Option<String>,
+    pub ssl_key_location: Option<String>,
+    #[serde(skip_serializing)]
+    pub ssl_key_password: Option<Secret>,
+    // Broker hostname verification, on unless set to false
+    pub ssl_endpoint_identification: Option<bool>,
+}
+
+impl KafkaSecurity {
+    pub fn apply(&self, client: &mut ClientConfig) {
+        let protocol = match self.protocol {
+            Some(protocol) => protocol,
+            None => return,
+        };
+        client.set("security.protocol", protocol.as_str());
+
+        if protocol.uses_sasl() {
+            let mechanism = self.sasl_mechanism.unwrap_or_default();
+            client.set("sasl.mechanism", mechanism.as_str());
+            if let Some(username) = &self.sasl_username {
+                client.set("sasl.username", username);
+            }
+            if let Some(password) = &self.sasl_password {
+                client.set("sasl.password", password.expose());
+            }
+        }
+
+        if protocol.uses_ssl() {
+            if let Some(location) = &self.ssl_ca_location {
+                client.set("ssl.ca.location", location);
+            }
+            if let Some(location) = &self.ssl_certificate_location {
+                client.set("ssl.certificate.location", location);
+            }
+            if let Some(location) = &self.ssl_key_location {
+                client.set("ssl.key.location", location);
+            }
+            if let Some(password) = &self.ssl_key_password {
+                client.set("ssl.key.password", password.expose());
+            }
+            if let Some(verify) = self.ssl_endpoint_identification {
+                let algorithm = if verify { "https" } else { "none" };
+                client.set("ssl.endpoint.identification.algorithm", algorithm);
+            }
+        }
+    }
+
+    // Settings librdkafka would only reject once it tries to connect
+    pub fn validate(&self) -> Result<(), String> {
+        let protocol = self.protocol.unwrap_or_default();
+        if protocol.uses_sasl() && (self.sasl_username.is_none() || self.sasl_password.is_none()) {
+            return Err(format!(
+                "{} needs KAFKA_SASL_USERNAME and KAFKA_SASL_PASSWORD",
+                protocol.as_str()
+            ));
+        }
+        if !protocol.uses_sasl() && (self.sasl_mechanism.is_some() || self.sasl_username.is_some())
+        {
+            return Err(format!(
+                "SASL credentials are set but the protocol is {}",
+                protocol.as_str()
+            ));
+        }
+        if self.ssl_certificate_location.is_some() != self.ssl_key_location.is_some() {
+            return Err("ssl_certificate_location and ssl_key_location go together".to_string());
+        }
+        let ssl_set = self.ssl_ca_location.is_some() || self.ssl_certificate_location.is_some();
+        if ssl_set && !protocol.uses_ssl() {
+            return Err(format!(
+                "SSL files are set but the protocol is {}",
+                protocol.as_str()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SecurityProtocol {
+    #[default]
+    Plaintext,
+    Ssl,
+    SaslPlaintext,
+    SaslSsl,
+}
+
+impl SecurityProtocol {
+    // Value of librdkafka's security.protocol
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecurityProtocol::Plaintext => "PLAINTEXT",
+            SecurityProtocol::Ssl => "SSL",
+            SecurityProtocol::SaslPlaintext => "SASL_PLAINTEXT",
+            SecurityProtocol::SaslSsl => "SASL_SSL",
+        }
+    }
+
+    pub fn uses_sasl(&self) -> bool {
+        matches!(
+            self,
+            SecurityProtocol::SaslPlaintext | SecurityProtocol::SaslSsl
+        )
+    }
+
+    pub fn uses_ssl(&self) -> bool {
+        matches!(self, SecurityProtocol::Ssl | SecurityProtocol::SaslSsl)
+    }
+}
+
+impl std::str::FromStr for SecurityProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().replace('-', "_").as_str() {
+            "PLAINTEXT" => Ok(SecurityProtocol::Plaintext),
+            "SSL" => Ok(SecurityProtocol::Ssl),
+            "SASL_PLAINTEXT" => Ok(SecurityProtocol::SaslPlaintext),
+            "SASL_SSL" => Ok(SecurityProtocol::SaslSsl),
+            other => Err(format!("Unknown Kafka security protocol: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SaslMechanism {
+    #[default]
+    #[serde(rename = "PLAIN")]
+    Plain,
+    #[serde(rename = "SCRAM-SHA-256")]
+    ScramSha256,
+    #[serde(rename = "SCRAM-SHA-512")]
+    ScramSha512,
+}
+
+impl SaslMechanism {
+    // Value of librdkafka's sasl.mechanism
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SaslMechanism::Plain => "PLAIN",
+            SaslMechanism::ScramSha256 => "SCRAM-SHA-256",
+            SaslMechanism::ScramSha512 => "SCRAM-SHA-512",
+        }
+    }
+}
+
+impl std::str::FromStr for SaslMechanism {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().replace('_', "-").as_str() {
+            "PLAIN" => Ok(SaslMechanism::Plain),
+            "SCRAM-SHA-256" => Ok(SaslMechanism::ScramSha256),
+            "SCRAM-SHA-512" => Ok(SaslMechanism::ScramSha512),
+            other => Err(format!("Unknown SASL mechanism: {}", other)),
+        }
+    }
+}
+
+// Encoding of Kafka message values. Producer and consumers must agree; the
+// producer also names the format in a header, which consumers prefer.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SerializationFormat {
+    #[default]
+    Json,
+    Flatbuffers,
+    Protobuf,
+}
+
+impl SerializationFormat {
+    // Value of the format header
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SerializationFormat::Json => "json",
+            SerializationFormat::Flatbuffers => "flatbuffers",
+            SerializationFormat::Protobuf => "protobuf",
+        }
+    }
+}
+
+impl std::str::FromStr for SerializationFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(SerializationFormat::Json),
+            "flatbuffers" => Ok(SerializationFormat::Flatbuffers),
+            "protobuf" => Ok(SerializationFormat::Protobuf),
+            other => Err(format!("Unknown serialization format: {}", other)),
+        }
+    }
+}
+
+// Per-stream topics. Streams without their own topic go to `KafkaConfig::topic`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicRouting {
+    pub trades: Option<String>,
+    pub orderbooks: Option<String>,
+    pub positions: Option<String>,
+    pub markets: Option<String>,
+    // Untrimmed full orderbooks, when the depth of the `orderbooks` ones is capped
+    pub orderbooks_full: Option<String>,
+}
+
+// Retention of the orderbook topics, which the producer creates with it when
+// they don't exist yet. Unset leaves the topics to the cluster.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicRetentionConfig {
+    pub retention_ms: Option<u64>,
+    // Broker defaults when unset
+    pub partitions: Option<i32>,
+    pub replication_factor: Option<i32>,
+}
+
+// Depth of the full orderbooks sent to Kafka. Off by default, so the whole
+// L3 book is sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OrderbookDepthConfig {
+    // Orders, or levels when aggregating, kept per side best first; 0 keeps all
+    pub max_orders_per_side: usize,
+    // Merge orders into L2 price levels
+    pub aggregate: bool,
+    // Width of a level in minimum price ticks of the market
+    pub tick_multiple: u64,
+}
+
+impl Default for OrderbookDepthConfig {
+    fn default() -> Self {
+        OrderbookDepthConfig {
+            max_orders_per_side: 0,
+            aggregate: false,
+            tick_multiple: 1,
+        }
+    }
+}
+
+impl KafkaConfig {
+    // Topic a message of the given type is sent to
+    pub fn topic_for(&self, message_type: &MessageType) -> &str {
+        let routed = match message_type {
+            MessageType::SpotTrade | MessageType::DerivativeTrade => &self.topics.trades,
+            MessageType::StreamSpotOrderbook
+            | MessageType::StreamDerivativeOrderbook
+            | MessageType::DerivativeFullOrderbook
+            | MessageType::SpotFullOrderbook => &self.topics.orderbooks,
+            MessageType::StreamPosition | MessageType::ExchangePosition => &self.topics.positions,
+            MessageType::DerivativeMarket | MessageType::SpotMarket => &self.topics.markets,
+            _ => &None,
+        };
+        routed.as_deref().unwrap_or(&self.topic)
+    }
+}
+
+// Lite mode restricts the whole pipeline to the top N markets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiteModeConfig {
+    pub enabled: bool,
+    pub top_n: usize,
+    pub refresh_interval_secs: u64,
+}
+
+impl Default for LiteModeConfig {
+    fn default() -> Self {
+        LiteModeConfig {
+            enabled: false,
+            top_n: 10,
+            refresh_interval_secs: 86400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckpointBackend {
+    Off,
+    File,
+    Redis,
+}
+
+// Where the last delivered block height is recorded for resuming after a restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CheckpointConfig {
+    pub backend: CheckpointBackend,
+    pub path: String,
+    pub redis_url: String,
+    pub redis_key: String,
+    // Filled into redis_url when set; CHECKPOINT_REDIS_PASSWORD
+    #[serde(skip_serializing)]
+    pub redis_password: Option<Secret>,
+}
+
+impl CheckpointConfig {
+    pub fn redis_url(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.redis_password {
+            Some(password) => secrets::with_redis_password(&self.redis_url, password),
+            None => Ok(self.redis_url.clone()),
+        }
+    }
+}
+
+// The IndexerQuery gRPC service run by `grpc query-server`, answering from
+// the Redis the consumers write
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryServerConfig {
+    pub listen_addr: String,
+    pub redis_url: String,
+    // Filled into redis_url when set; QUERY_REDIS_PASSWORD
+    #[serde(skip_serializing)]
+    pub redis_password: Option<Secret>,
+    // Liquidation alerts queued per stream; a client further behind misses alerts
+    pub stream_buffer: usize,
+}
+
+impl QueryServerConfig {
+    pub fn redis_url(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.redis_password {
+            Some(password) => secrets::with_redis_password(&self.redis_url, password),
+            None => Ok(self.redis_url.clone()),
+        }
+    }
+}
+
+impl Default for QueryServerConfig {
+    fn default() -> Self {
+        QueryServerConfig {
+            listen_addr: "0.0.0.0:9910".to_string(),
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            redis_password: None,
+            stream_buffer: 256,
+        }
+    }
+}
+
+// Orderbook resync requests from consumers that found a gap in the streamed
+// deltas, answered with a fresh L3 snapshot on the orderbook topic
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResyncConfig {
+    pub enabled: bool,
+    pub redis_url: String,
+    // Filled into redis_url when set; RESYNC_REDIS_PASSWORD
+    #[serde(skip_serializing)]
+    pub redis_password: Option<Secret>,
+    // Requests for a market resynced more recently than this are ignored
+    pub min_interval_secs: u64,
+}
+
+impl ResyncConfig {
+    pub fn redis_url(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.redis_password {
+            Some(password) => secrets::with_redis_password(&self.redis_url, password),
+            None => Ok(self.redis_url.clone()),
+        }
+    }
+}
+
+// Warm standby: every ingester streams and tracks the block height, but only
+// the holder of a Redis lease publishes. A standby takes the lease once the
+// leader stops renewing it, and publishes from the next block on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StandbyConfig {
+    pub enabled: bool,
+    pub redis_url: String,
+    // Filled into redis_url when set; STANDBY_REDIS_PASSWORD
+    #[serde(skip_serializing)]
+    pub redis_password: Option<Secret>,
+    pub lease_key: String,
+    // What this instance holds the lease as; the host name and pid when empty
+    pub instance_id: String,
+    // How long the lease outlives its last renewal, which bounds the gap a
+    // failover leaves
+    pub lease_ttl_ms: u64,
+    pub renew_interval_ms: u64,
+}
+
+impl StandbyConfig {
+    pub fn redis_url(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.redis_password {
+            Some(password) => secrets::with_redis_password(&self.redis_url, password),
+            None => Ok(self.redis_url.clone()),
+        }
+    }
+
+    pub fn instance_id(&self) -> String {
+        if !self.instance_id.is_empty() {
+            return self.instance_id.clone();
+        }
+        let host = env::var("HOSTNAME").unwrap_or_else(|_| "ingester".to_string());
+        format!("{}-{}", host, std::process::id())
+    }
+}
+
+impl Default for StandbyConfig {
+    fn default() -> Self {
+        StandbyConfig {
+            enabled: false,
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            redis_password: None,
+            lease_key: "ingester:leader".to_string(),
+            instance_id: String::new(),
+            lease_ttl_ms: 2000,
+            renew_interval_ms: 500,
+        }
+    }
+}
+
+// Namespace of the consumers' Redis keys and channels, which the query server
+// and the resync listener read. Must match the consumers' redis_keys config.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct RedisKeySchema {
+    pub prefix: String,
+    pub separator: String,
+    pub environment: Option<String>,
+}
+
+impl RedisKeySchema {
+    // The namespace followed by the segments, joined by the separator
+    pub fn key(&self, segments: &[&str]) -> String {
+        let namespace = [Some(self.prefix.as_str()), self.environment.as_deref()];
+        namespace
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.is_empty())
+            .chain(segments.iter().copied())
+            .collect::<Vec<_>>()
+            .join(&self.separator)
+    }
+}
+
+impl Default for RedisKeySchema {
+    fn default() -> Self {
+        RedisKeySchema {
+            prefix: String::new(),
+            separator: ":".to_string(),
+            environment: None,
+        }
+    }
+}
+
+impl Default for ResyncConfig {
+    fn default() -> Self {
+        ResyncConfig {
+            enabled: false,
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            redis_password: None,
+            min_interval_secs: 5,
+        }
+    }
+}
+
+impl Default for CheckpointConfig {
+    fn default() -> Self {
+        CheckpointConfig {
+            backend: CheckpointBackend::Off,
+            path: "producer.checkpoint".to_string(),
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            redis_key: "producer:checkpoint".to_string(),
+            redis_password: None,
+        }
+    }
+}
+
+// Capture mode records every raw stream response to files for replay tests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+    // Directory the capture files go to; capturing is off when unset
+    pub dir: Option<String>,
+    // Blocks recorded per file before starting the next one
+    pub blocks_per_file: u64,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        CaptureConfig {
+            dir: None,
+            blocks_per_file: 1000,
+        }
+    }
+}
+
+// Historical backfill (`grpc backfill <from> <to>`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackfillConfig {
+    // Market and position snapshots are queried every this many blocks;
+    // trades are read from every block
+    pub snapshot_every: u64,
+    // Also query full orderbooks with each snapshot
+    pub orderbooks: bool,
+}
+
+impl Default for BackfillConfig {
+    fn default() -> Self {
+        BackfillConfig {
+            snapshot_every: 1,
+            orderbooks: false,
+        }
+    }
 }
 
 impl Default for Config {
@@ -34,7 +639,21 @@ impl Default for Config {
                 brokers: vec!["localhost:9092".to_string()],
                 topic: "injective-data".to_string(),
                 client_id: "injective-client".to_string(),
+                topics: TopicRouting::default(),
+                format: SerializationFormat::default(),
+                security: KafkaSecurity::default(),
+                orderbook_depth: OrderbookDepthConfig::default(),
+                orderbook_retention: TopicRetentionConfig::default(),
+                delivery: DeliveryConfig::default(),
             },
+            lite: LiteModeConfig::default(),
+            checkpoint: CheckpointConfig::default(),
+            capture: CaptureConfig::default(),
+            backfill: BackfillConfig::default(),
+            query_server: QueryServerConfig::default(),
+            resync: ResyncConfig::default(),
+            redis_keys: RedisKeySchema::default(),
+            standby: StandbyConfig::default(),
         }
     }
 }
@@ -47,18 +666,20 @@ impl Config {
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
 
-        let config: Config = serde_json::from_str(&contents)?;
+        let mut config: Config = serde_json::from_str(&contents)?;
+        config.load_secrets()?;
         Ok(config)
     }
 
     pub fn from_env() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let mut config = Config::default();
 
-        if let Ok(stream_endpoint) = env::var("GRPC_STREAM_ENDPOINT") {
+        // Endpoints may embed a provider API key, so they are read as secrets
+        if let Some(stream_endpoint) = secrets::load("GRPC_STREAM_ENDPOINT")? {
             config.grpc.stream_endpoint = stream_endpoint;
         }
 
-        if let Ok(query_endpoint) = env::var("GRPC_QUERY_ENDPOINT") {
+        if let Some(query_endpoint) = secrets::load("GRPC_QUERY_ENDPOINT")? {
             config.grpc.query_endpoint = query_endpoint;
         }
 
@@ -74,6 +695,189 @@ impl Config {
             config.kafka.client_id = client_id;
         }
 
+        config.kafka.topics = TopicRouting {
+            trades: env::var("KAFKA_TOPIC_TRADES").ok(),
+            orderbooks: env::var("KAFKA_TOPIC_ORDERBOOKS").ok(),
+            positions: env::var("KAFKA_TOPIC_POSITIONS").ok(),
+            markets: env::var("KAFKA_TOPIC_MARKETS").ok(),
+            orderbooks_full: env::var("KAFKA_TOPIC_ORDERBOOKS_FULL").ok(),
+        };
+
+        if let Ok(retention) = env::var("KAFKA_TOPIC_ORDERBOOKS_RETENTION_MS") {
+            config.kafka.orderbook_retention.retention_ms = Some(retention.parse()?);
+        }
+
+        if let Ok(partitions) = env::var("KAFKA_TOPIC_ORDERBOOKS_PARTITIONS") {
+            config.kafka.orderbook_retention.partitions = Some(partitions.parse()?);
+        }
+
+        if let Ok(replication) = env::var("KAFKA_TOPIC_ORDERBOOKS_REPLICATION") {
+            config.kafka.orderbook_retention.replication_factor = Some(replication.parse()?);
+        }
+
+        if let Ok(max_orders) = env::var("ORDERBOOK_MAX_ORDERS_PER_SIDE") {
+            config.kafka.orderbook_depth.max_orders_per_side = max_orders.parse()?;
+        }
+
+        if let Ok(aggregate) = env::var("ORDERBOOK_AGGREGATE") {
+            config.kafka.orderbook_depth.aggregate = aggregate.parse()?;
+        }
+
+        if let Ok(tick_multiple) = env::var("ORDERBOOK_TICK_MULTIPLE") {
+            config.kafka.orderbook_depth.tick_multiple = tick_multiple.parse()?;
+        }
+
+        if let Ok(format) = env::var("KAFKA_FORMAT") {
+            config.kafka.format = format.parse()?;
+        }
+
+        if let Ok(protocol) = env::var("KAFKA_SECURITY_PROTOCOL") {
+            config.kafka.security.protocol = Some(protocol.parse()?);
+        }
+
+        if let Ok(mechanism) = env::var("KAFKA_SASL_MECHANISM") {
+            config.kafka.security.sasl_mechanism = Some(mechanism.parse()?);
+        }
+
+        config.kafka.security.ssl_ca_location = env::var("KAFKA_SSL_CA_LOCATION").ok();
+        config.kafka.security.ssl_certificate_location =
+            env::var("KAFKA_SSL_CERTIFICATE_LOCATION").ok();
+        config.kafka.security.ssl_key_location = env::var("KAFKA_SSL_KEY_LOCATION").ok();
+
+        if let Ok(verify) = env::var("KAFKA_SSL_ENDPOINT_IDENTIFICATION") {
+            config.kafka.security.ssl_endpoint_identification = Some(verify.parse()?);
+        }
+
+        if let Ok(idempotent) = env::var("KAFKA_IDEMPOTENT") {
+            config.kafka.delivery.idempotent = idempotent.parse()?;
+        }
+
+        config.kafka.delivery.transactional_id = env::var("KAFKA_TRANSACTIONAL_ID").ok();
+
+        if let Ok(timeout) = env::var("KAFKA_TRANSACTION_TIMEOUT_MS") {
+            config.kafka.delivery.transaction_timeout_ms = Some(timeout.parse()?);
+        }
+
+        // Setting the market count is enough to turn lite mode on
+        if let Ok(top_n) = env::var("LITE_MODE_TOP_N") {
+            config.lite.enabled = true;
+            config.lite.top_n = top_n.parse()?;
+        }
+
+        // Setting a location is enough to pick the checkpoint backend
+        if let Ok(path) = env::var("CHECKPOINT_FILE") {
+            config.checkpoint.backend = CheckpointBackend::File;
+            config.checkpoint.path = path;
+        }
+
+        if let Ok(redis_url) = env::var("CHECKPOINT_REDIS_URL") {
+            config.checkpoint.backend = CheckpointBackend::Redis;
+            config.checkpoint.redis_url = redis_url;
+        }
+
+        if let Ok(redis_key) = env::var("CHECKPOINT_REDIS_KEY") {
+            config.checkpoint.redis_key = redis_key;
+        }
+
+        if let Ok(addr) = env::var("QUERY_SERVER_ADDR") {
+            config.query_server.listen_addr = addr;
+        }
+
+        if let Ok(redis_url) = env::var("QUERY_REDIS_URL") {
+            config.query_server.redis_url = redis_url;
+        }
+
+        if let Ok(buffer) = env::var("QUERY_STREAM_BUFFER") {
+            config.query_server.stream_buffer = buffer.parse()?;
+        }
+
+        // Setting a location is enough to answer resync requests
+        if let Ok(redis_url) = env::var("RESYNC_REDIS_URL") {
+            config.resync.enabled = true;
+            config.resync.redis_url = redis_url;
+        }
+
+        if let Ok(secs) = env::var("RESYNC_MIN_INTERVAL_SECS") {
+            config.resync.min_interval_secs = secs.parse()?;
+        }
+
+        // Setting a lease location is enough to run as a warm standby pair
+        if let Ok(redis_url) = env::var("STANDBY_REDIS_URL") {
+            config.standby.enabled = true;
+            config.standby.redis_url = redis_url;
+        }
+
+        if let Ok(lease_key) = env::var("STANDBY_LEASE_KEY") {
+            config.standby.lease_key = lease_key;
+        }
+
+        if let Ok(instance_id) = env::var("STANDBY_INSTANCE_ID") {
+            config.standby.instance_id = instance_id;
+        }
+
+        if let Ok(ttl) = env::var("STANDBY_LEASE_TTL_MS") {
+            config.standby.lease_ttl_ms = ttl.parse()?;
+        }
+
+        if let Ok(interval) = env::var("STANDBY_RENEW_INTERVAL_MS") {
+            config.standby.renew_interval_ms = interval.parse()?;
+        }
+
+        if let Ok(prefix) = env::var("REDIS_KEY_PREFIX") {
+            config.redis_keys.prefix = prefix;
+        }
+
+        if let Ok(separator) = env::var("REDIS_KEY_SEPARATOR") {
+            if separator.is_empty() {
+                return Err("REDIS_KEY_SEPARATOR must not be empty".into());
+            }
+            config.redis_keys.separator = separator;
+        }
+
+        config.redis_keys.environment = env::var("REDIS_KEY_ENV").ok();
+
+        config.capture.dir = env::var("CAPTURE_DIR").ok();
+
+        if let Ok(blocks) = env::var("CAPTURE_BLOCKS_PER_FILE") {
+            config.capture.blocks_per_file = blocks.parse()?;
+        }
+
+        if let Ok(blocks) = env::var("BACKFILL_SNAPSHOT_EVERY") {
+            config.backfill.snapshot_every = blocks.parse()?;
+        }
+
+        if let Ok(orderbooks) = env::var("BACKFILL_ORDERBOOKS") {
+            config.backfill.orderbooks = orderbooks.parse()?;
+        }
+
+        config.load_secrets()?;
         Ok(config)
     }
+
+    // Credentials from the environment, secret files or SECRETS_DIR take
+    // precedence over the config file
+    pub fn load_secrets(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(username) = secrets::load("KAFKA_SASL_USERNAME")? {
+            self.kafka.security.sasl_username = Some(username);
+        }
+        if let Some(password) = secrets::load("KAFKA_SASL_PASSWORD")? {
+            self.kafka.security.sasl_password = Some(Secret::new(password));
+        }
+        if let Some(password) = secrets::load("KAFKA_SSL_KEY_PASSWORD")? {
+            self.kafka.security.ssl_key_password = Some(Secret::new(password));
+        }
+        if let Some(password) = secrets::load("CHECKPOINT_REDIS_PASSWORD")? {
+            self.checkpoint.redis_password = Some(Secret::new(password));
+        }
+        if let Some(password) = secrets::load("QUERY_REDIS_PASSWORD")? {
+            self.query_server.redis_password = Some(Secret::new(password));
+        }
+        if let Some(password) = secrets::load("RESYNC_REDIS_PASSWORD")? {
+            self.resync.redis_password = Some(Secret::new(password));
+        }
+        if let Some(password) = secrets::load("STANDBY_REDIS_PASSWORD")? {
+            self.standby.redis_password = Some(Secret::new(password));
+        }
+        Ok(())
+    }
 }
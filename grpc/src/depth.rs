@@ -0,0 +1,171 @@
+use crate::config::OrderbookDepthConfig;
+use crate::models::{
+    FullLimitOrderbookPayload, KafkaMessage, KafkaPayload, TrimmedLimitOrderPayload,
+};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+// Trims the full L3 orderbooks the heartbeat sends before they reach Kafka.
+// Orders can be merged into L2 price levels, optionally on a grid a multiple
+// of the market's minimum price tick wide, and each side capped to its best
+// entries. Tick sizes are learned from the market messages passing through
+// the producer, so a book is only put on the grid once its market was sent.
+pub struct OrderbookDepth {
+    config: OrderbookDepthConfig,
+    // Minimum price tick per market id, in chain units
+    ticks: RwLock<HashMap<String, u128>>,
+}
+
+impl OrderbookDepth {
+    pub fn new(config: OrderbookDepthConfig) -> Self {
+        OrderbookDepth {
+            config,
+            ticks: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.max_orders_per_side > 0 || self.config.aggregate
+    }
+
+    // Remember the minimum price ticks of the markets in a market message
+    pub fn observe(&self, message: &KafkaMessage) {
+        let ticks: Vec<(&String, &String)> = match &message.payload {
+            KafkaPayload::DerivativeMarkets(markets) => markets
+                .iter()
+                .map(|market| (&market.market_id, &market.min_price_tick))
+                .collect(),
+            KafkaPayload::SpotMarkets(markets) => markets
+                .iter()
+                .map(|market| (&market.market_id, &market.min_price_tick))
+                .collect(),
+            _ => return,
+        };
+        let mut known = self.ticks.write().unwrap();
+        for (market_id, tick) in ticks {
+            if let Ok(tick) = tick.parse::<u128>() {
+                known.insert(market_id.clone(), tick);
+            }
+        }
+    }
+
+    // A trimmed copy of a full orderbook message; None for other messages or
+    // when trimming is off
+    pub fn trim(&self, message: &KafkaMessage) -> Option<KafkaMessage> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let payload = match &message.payload {
+            KafkaPayload::DerivativeFullOrderbooks(books) => {
+                KafkaPayload::DerivativeFullOrderbooks(self.trim_books(books))
+            }
+            KafkaPayload::SpotFullOrderbooks(books) => {
+                KafkaPayload::SpotFullOrderbooks(self.trim_books(books))
+            }
+            _ => return None,
+        };
+        Some(KafkaMessage {
+            message_type: message.message_type.clone(),
+            block_height: message.block_height,
+            block_time: message.block_time,
+            payload,
+        })
+    }
+
+    fn trim_books(&self, books: &[FullLimitOrderbookPayload]) -> Vec<FullLimitOrderbookPayload> {
+        let ticks = self.ticks.read().unwrap();
+        books
+            .iter()
+            .map(|book| {
+                let level = ticks
+                    .get(&book.market_id)
+                    .map(|tick| tick.saturating_mul(self.config.tick_multiple.max(1) as u128));
+                FullLimitOrderbookPayload {
+                    market_id: book.market_id.clone(),
+                    bids: self.trim_side(&book.bids, true, level),
+                    asks: self.trim_side(&book.asks, false, level),
+                    timestamp: book.timestamp,
+                }
+            })
+            .collect()
+    }
+
+    fn trim_side(
+        &self,
+        orders: &[TrimmedLimitOrderPayload],
+        is_bid: bool,
+        level: Option<u128>,
+    ) -> Vec<TrimmedLimitOrderPayload> {
+        let mut orders = if self.config.aggregate {
+            aggregate(orders, is_bid, level)
+        } else {
+            best_first(orders, is_bid)
+        };
+        if self.config.max_orders_per_side > 0 {
+            orders.truncate(self.config.max_orders_per_side);
+        }
+        orders
+    }
+}
+
+// Merge orders into one level per price with their total quantity, best
+// price first. With a level width, bids are rounded down and asks up to a
+// multiple of it. Levels carry no order hash or subaccount. A side with a
+// price or quantity that isn't a chain integer is left as it is.
+fn aggregate(
+    orders: &[TrimmedLimitOrderPayload],
+    is_bid: bool,
+    width: Option<u128>,
+) -> Vec<TrimmedLimitOrderPayload> {
+    let mut levels: BTreeMap<u128, u128> = BTreeMap::new();
+    for order in orders {
+        let (Ok(price), Ok(quantity)) =
+            (order.price.parse::<u128>(), order.quantity.parse::<u128>())
+        else {
+            return orders.to_vec();
+        };
+        let price = match width {
+            Some(width) if width > 0 && price % width != 0 => {
+                let floor = price - price % width;
+                if is_bid {
+                    floor
+                } else {
+                    floor.saturating_add(width)
+                }
+            }
+            _ => price,
+        };
+        let total = levels.entry(price).or_default();
+        *total = total.saturating_add(quantity);
+    }
+
+    let level = |(price, quantity): (u128, u128)| TrimmedLimitOrderPayload {
+        price: price.to_string(),
+        quantity: quantity.to_string(),
+        order_hash: String::new(),
+        subaccount_id: String::new(),
+    };
+    if is_bid {
+        levels.into_iter().rev().map(level).collect()
+    } else {
+        levels.into_iter().map(level).collect()
+    }
+}
+
+// Orders sorted best price first, or as given if a price isn't a chain integer
+fn best_first(orders: &[TrimmedLimitOrderPayload], is_bid: bool) -> Vec<TrimmedLimitOrderPayload> {
+    let mut orders = orders.to_vec();
+    if orders
+        .iter()
+        .all(|order| order.price.parse::<u128>().is_ok())
+    {
+        let price = |order: &TrimmedLimitOrderPayload| order.price.parse::<u128>().unwrap_or(0);
+        if is_bid {
+            orders.sort_by_cached_key(|order| Reverse(price(order)));
+        } else {
+            orders.sort_by_cached_key(price);
+        }
+    }
+    orders
+}
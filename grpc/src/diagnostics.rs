@@ -0,0 +1,255 @@
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{BaseProducer, Producer};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::time::Duration;
+use tonic::transport::Endpoint;
+
+use crate::capture::CaptureWriter;
+use crate::checkpoint;
+use crate::config::{CheckpointBackend, Config};
+use crate::standby::LeaderLease;
+
+// `--check-config`: validates the configuration against the services it
+// names and reports every problem at once, without starting the ingester
+
+// How long each network check may take before it counts as unreachable
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn push(&mut self, name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    // True when nothing failed; warnings alone do not fail the check
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Failed)
+    }
+
+    fn count(&self, status: CheckStatus) -> usize {
+        self.checks.iter().filter(|c| c.status == status).count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let tag = match check.status {
+                CheckStatus::Ok => " ok ",
+                CheckStatus::Warning => "warn",
+                CheckStatus::Failed => "FAIL",
+            };
+            writeln!(f, "  [{}] {}: {}", tag, check.name, check.detail)?;
+        }
+        write!(
+            f,
+            "{} ok, {} warnings, {} failed",
+            self.count(CheckStatus::Ok),
+            self.count(CheckStatus::Warning),
+            self.count(CheckStatus::Failed)
+        )
+    }
+}
+
+pub async fn check_config(config: &Config) -> Report {
+    let mut report = Report::default();
+
+    check_endpoint(
+        &mut report,
+        "grpc.stream_endpoint",
+        &config.grpc.stream_endpoint,
+    )
+    .await;
+    check_endpoint(
+        &mut report,
+        "grpc.query_endpoint",
+        &config.grpc.query_endpoint,
+    )
+    .await;
+    check_kafka(&mut report, config).await;
+    check_checkpoint(&mut report, config).await;
+    check_standby(&mut report, config).await;
+
+    if config.capture.dir.is_some() {
+        match CaptureWriter::from_config(&config.capture) {
+            Ok(_) => report.push("capture.dir", CheckStatus::Ok, "writable"),
+            Err(e) => report.push("capture.dir", CheckStatus::Failed, e.to_string()),
+        }
+    }
+
+    report
+}
+
+async fn check_endpoint(report: &mut Report, name: &str, url: &str) {
+    if let Err(e) = url::Url::parse(url) {
+        report.push(
+            name,
+            CheckStatus::Failed,
+            format!("{} is not a URL: {}", url, e),
+        );
+        return;
+    }
+    let endpoint = match Endpoint::from_shared(url.to_string()) {
+        Ok(endpoint) => endpoint.connect_timeout(CHECK_TIMEOUT),
+        Err(e) => {
+            report.push(name, CheckStatus::Failed, format!("{}: {}", url, e));
+            return;
+        }
+    };
+    match endpoint.connect().await {
+        Ok(_) => report.push(name, CheckStatus::Ok, format!("{} reachable", url)),
+        Err(e) => report.push(
+            name,
+            CheckStatus::Failed,
+            format!("{} unreachable: {}", url, e),
+        ),
+    }
+}
+
+async fn check_kafka(report: &mut Report, config: &Config) {
+    let kafka = &config.kafka;
+    if kafka.brokers.is_empty() {
+        report.push(
+            "kafka.brokers",
+            CheckStatus::Failed,
+            "no brokers configured",
+        );
+        return;
+    }
+
+    if let Err(e) = kafka.security.validate() {
+        report.push("kafka.security", CheckStatus::Failed, e);
+        return;
+    }
+
+    let brokers = kafka.brokers.join(",");
+    let client_id = format!("{}-check", kafka.client_id);
+    let security = kafka.security.clone();
+    let metadata = tokio::task::spawn_blocking(move || {
+        let mut client = ClientConfig::new();
+        security.apply(&mut client);
+        let producer: BaseProducer = client
+            .set("bootstrap.servers", &brokers)
+            .set("client.id", &client_id)
+            .create()?;
+        producer.client().fetch_metadata(None, CHECK_TIMEOUT)
+    })
+    .await;
+
+    let metadata = match metadata {
+        Ok(Ok(metadata)) => metadata,
+        Ok(Err(e)) => {
+            report.push(
+                "kafka.brokers",
+                CheckStatus::Failed,
+                format!("{} unreachable: {}", kafka.brokers.join(","), e),
+            );
+            return;
+        }
+        Err(e) => {
+            report.push("kafka.brokers", CheckStatus::Failed, e.to_string());
+            return;
+        }
+    };
+    report.push(
+        "kafka.brokers",
+        CheckStatus::Ok,
+        format!("{} brokers in the cluster", metadata.brokers().len()),
+    );
+
+    // Every topic the producer may route a message to
+    let existing: BTreeSet<&str> = metadata.topics().iter().map(|t| t.name()).collect();
+    let routed = [
+        &kafka.topics.trades,
+        &kafka.topics.orderbooks,
+        &kafka.topics.positions,
+        &kafka.topics.markets,
+        &kafka.topics.orderbooks_full,
+    ];
+    let wanted: BTreeSet<&str> = std::iter::once(kafka.topic.as_str())
+        .chain(routed.into_iter().flatten().map(|t| t.as_str()))
+        .collect();
+    for topic in wanted {
+        let name = format!("kafka topic {}", topic);
+        if existing.contains(topic) {
+            report.push(name, CheckStatus::Ok, "exists");
+        } else {
+            report.push(name, CheckStatus::Failed, "does not exist");
+        }
+    }
+}
+
+async fn check_checkpoint(report: &mut Report, config: &Config) {
+    if config.checkpoint.backend == CheckpointBackend::Off {
+        return;
+    }
+
+    // Loading reads the file or authenticates against Redis, like a restart would
+    let result = tokio::time::timeout(CHECK_TIMEOUT, async {
+        match checkpoint::from_config(&config.checkpoint).await? {
+            Some(store) => store.load().await,
+            None => Ok(None),
+        }
+    })
+    .await;
+
+    match result {
+        Ok(Ok(Some(height))) => report.push(
+            "checkpoint",
+            CheckStatus::Ok,
+            format!("resumes from block {}", height),
+        ),
+        Ok(Ok(None)) => report.push(
+            "checkpoint",
+            CheckStatus::Ok,
+            "readable, no checkpoint written yet",
+        ),
+        Ok(Err(e)) => report.push("checkpoint", CheckStatus::Failed, e.to_string()),
+        Err(_) => report.push("checkpoint", CheckStatus::Failed, "timed out"),
+    }
+}
+
+async fn check_standby(report: &mut Report, config: &Config) {
+    if !config.standby.enabled {
+        return;
+    }
+
+    let result = tokio::time::timeout(CHECK_TIMEOUT, async {
+        LeaderLease::new(&config.standby).await?.holder().await
+    })
+    .await;
+
+    match result {
+        Ok(Ok(Some(holder))) => report.push(
+            "standby",
+            CheckStatus::Ok,
+            format!("lease held by {}", holder),
+        ),
+        Ok(Ok(None)) => report.push("standby", CheckStatus::Ok, "lease free"),
+        Ok(Err(e)) => report.push("standby", CheckStatus::Failed, e.to_string()),
+        Err(_) => report.push("standby", CheckStatus::Failed, "timed out"),
+    }
+}
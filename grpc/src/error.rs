@@ -0,0 +1,46 @@
+use rdkafka::error::KafkaError;
+use std::fmt;
+
+/// Why a message could not be handed to Kafka
+#[derive(Debug)]
+pub enum ProducerError {
+    /// The message could not be encoded as JSON (binary formats cannot fail)
+    Serialize(serde_json::Error),
+    /// Kafka rejected or timed out the message
+    Kafka(KafkaError),
+    /// The message was delivered, but the transaction of its batch was
+    /// aborted because another message of the batch failed
+    Aborted,
+}
+
+impl fmt::Display for ProducerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProducerError::Serialize(e) => write!(f, "failed to serialize message: {}", e),
+            ProducerError::Kafka(e) => write!(f, "kafka error: {}", e),
+            ProducerError::Aborted => write!(f, "transaction aborted"),
+        }
+    }
+}
+
+impl std::error::Error for ProducerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProducerError::Serialize(e) => Some(e),
+            ProducerError::Kafka(e) => Some(e),
+            ProducerError::Aborted => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for ProducerError {
+    fn from(e: serde_json::Error) -> Self {
+        ProducerError::Serialize(e)
+    }
+}
+
+impl From<KafkaError> for ProducerError {
+    fn from(e: KafkaError) -> Self {
+        ProducerError::Kafka(e)
+    }
+}
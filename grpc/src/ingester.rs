@@ -0,0 +1,450 @@
+use futures::StreamExt;
+use log::{error, info, warn};
+use std::error::Error;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task;
+
+use crate::capture::CaptureWriter;
+use crate::checkpoint;
+use crate::config::{self, Config};
+use crate::lite_mode::LiteMarketSet;
+use crate::models::{self, build_stream_request, StreamRequest, StreamResponse};
+use crate::producer::BatchKafkaProducer;
+use crate::proto::injective::stream::v1beta1::stream_client::StreamClient;
+use crate::resync::ResyncListener;
+use crate::standby::LeaderLease;
+use crate::{query_client, topics};
+
+// How long a stopping ingester waits for Kafka to take the messages it queued
+const SHUTDOWN_FLUSH_TIMEOUT_MS: u64 = 10_000;
+
+// The chain stream ingester: the heartbeat snapshots plus the stream that
+// feeds Kafka. Built once, then run until the shutdown flag flips to true.
+pub struct Ingester {
+    config: Config,
+    producer: Arc<BatchKafkaProducer>,
+    lite_markets: Option<LiteMarketSet>,
+    capture: Option<CaptureWriter>,
+    // Standby mode lease; the ingester always publishes when None
+    lease: Option<LeaderLease>,
+}
+
+impl Ingester {
+    pub async fn new(config: Config) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        // In lite mode, restrict everything to the top N markets
+        let lite_markets = if config.lite.enabled {
+            let markets = LiteMarketSet::new(config.lite.top_n);
+            let mut client = query_client::ExchangeQueryClient::connect(&config.grpc).await?;
+            markets.refresh(&mut client).await?;
+            markets.spawn_refresh(client, config.lite.refresh_interval_secs);
+            info!(
+                "Lite mode enabled for top {} markets: {:?}",
+                config.lite.top_n,
+                markets.market_ids()
+            );
+            Some(markets)
+        } else {
+            None
+        };
+
+        // Orderbook topics with their own retention; the producer still works
+        // against topics that can't be created here
+        if let Err(e) = topics::ensure_orderbook_topics(&config.kafka).await {
+            error!("Failed to set up orderbook topics: {}", e);
+        }
+
+        // Create Kafka producer for streaming service
+        let mut producer = BatchKafkaProducer::new(&config.kafka, "ingester").await?;
+        if let Some(markets) = &lite_markets {
+            producer = producer.with_market_filter(markets.clone());
+        }
+        if let Some(store) = checkpoint::from_config(&config.checkpoint).await? {
+            producer = producer.with_checkpoint(store);
+        }
+        let lease = if config.standby.enabled {
+            let lease = LeaderLease::new(&config.standby).await?;
+            producer = producer.with_leadership(lease.leadership());
+            Some(lease)
+        } else {
+            None
+        };
+        let producer = Arc::new(producer);
+        info!("Connected to Kafka: {}", config.kafka.brokers.join(","));
+
+        let capture = CaptureWriter::from_config(&config.capture)?;
+        if let Some(dir) = &config.capture.dir {
+            info!(
+                "Capture mode enabled, recording stream responses to {}",
+                dir
+            );
+        }
+
+        // Initialize with current block height
+        initialize_with_current_block(&producer, &config.grpc).await?;
+
+        Ok(Ingester {
+            config,
+            producer,
+            lite_markets,
+            capture,
+            lease,
+        })
+    }
+
+    // Highest block height sent to Kafka so far
+    pub fn latest_block(&self) -> u64 {
+        self.producer.get_latest_block()
+    }
+
+    pub fn producer(&self) -> Arc<BatchKafkaProducer> {
+        self.producer.clone()
+    }
+
+    pub async fn run(
+        self,
+        mut shutdown_rx: watch::Receiver<bool>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Ingester {
+            config,
+            producer,
+            lite_markets,
+            mut capture,
+            lease,
+        } = self;
+
+        // Compete for the publishing lease while streaming. It is released
+        // only once the producer is flushed, so the standby doesn't publish
+        // blocks this instance is still delivering.
+        let (lease_stop, lease_stop_rx) = watch::channel(false);
+        let lease_handle = lease.map(|lease| task::spawn(lease.run(lease_stop_rx)));
+
+        // Start the heartbeat service in a separate task
+        let heartbeat_config = config.clone();
+        let heartbeat_producer = Arc::clone(&producer);
+        let heartbeat_markets = lite_markets.clone();
+        let heartbeat_handle = task::spawn(async move {
+            match query_client::ExchangeHeartbeat::new_with_producer(
+                &heartbeat_config.grpc,
+                heartbeat_producer,
+                200,
+            )
+            .await
+            {
+                Ok(heartbeat) => {
+                    let mut heartbeat = match heartbeat_markets {
+                        Some(markets) => heartbeat.with_market_filter(markets),
+                        None => heartbeat,
+                    };
+                    info!("Starting heartbeat service");
+                    if let Err(e) = heartbeat.start().await {
+                        error!("Heartbeat service error: {}", e);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to create heartbeat service: {}", e);
+                }
+            }
+        });
+
+        // Answer the consumers' orderbook resync requests
+        let resync_handle = if config.resync.enabled {
+            let listener = ResyncListener::new(&config, producer.clone()).await?;
+            Some(task::spawn(async move {
+                if let Err(e) = listener.run().await {
+                    error!("Orderbook resync listener error: {}", e);
+                }
+            }))
+        } else {
+            None
+        };
+
+        // Stream data, reconnecting with new filters whenever the lite mode
+        // market set changes
+        let stream_endpoint = config.grpc.stream_endpoint.clone();
+        let result = async {
+            loop {
+                // Create the streaming client
+                let stream_client = connect_to_stream_service(&stream_endpoint).await?;
+                info!("Connected to stream service: {}", stream_endpoint);
+
+                // Create a stream request
+                let market_ids = lite_markets.as_ref().map(|markets| markets.market_ids());
+                let request = create_stream_request(market_ids);
+                info!("Stream request created");
+
+                let market_changes = lite_markets.as_ref().map(|markets| markets.subscribe());
+                let restart = stream_and_process(
+                    stream_client,
+                    request,
+                    producer.clone(),
+                    &mut shutdown_rx,
+                    market_changes,
+                    &mut capture,
+                )
+                .await?;
+                if !restart {
+                    return Ok::<(), Box<dyn Error + Send + Sync>>(());
+                }
+                info!("Lite mode market set changed, restarting stream with new filters");
+            }
+        }
+        .await;
+
+        // The heartbeat loop never ends on its own
+        heartbeat_handle.abort();
+        let _ = heartbeat_handle.await;
+        if let Some(resync_handle) = resync_handle {
+            resync_handle.abort();
+        }
+
+        // Deliver what the stream and the heartbeat already handed to the
+        // producer before the process exits
+        info!("Flushing Kafka producer");
+        if let Err(e) = producer.flush(SHUTDOWN_FLUSH_TIMEOUT_MS).await {
+            error!("Failed to flush Kafka producer: {}", e);
+        }
+        if let Some(lease_handle) = lease_handle {
+            let _ = lease_stop.send(true);
+            let _ = lease_handle.await;
+        }
+        result
+    }
+}
+
+async fn connect_to_stream_service(
+    endpoint: &str,
+) -> Result<StreamClient<tonic::transport::Channel>, Box<dyn Error + Send + Sync>> {
+    let client = StreamClient::connect(endpoint.to_string()).await?;
+    Ok(client)
+}
+
+async fn initialize_with_current_block(
+    producer: &Arc<BatchKafkaProducer>,
+    config: &config::GrpcConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Create a temporary exchange client to get current block height
+    let exchange_client = query_client::ExchangeQueryClient::connect(config).await?;
+
+    let checkpoint = producer.load_checkpoint().await;
+
+    // Get the current block height from the chain
+    match (exchange_client.get_current_block_height().await, checkpoint) {
+        (Ok(height), Some(checkpoint)) if checkpoint <= height => {
+            // The stream only carries new blocks, so anything in between is
+            // left to the heartbeat snapshots
+            info!(
+                "Resuming from checkpoint at block {} (chain is at {})",
+                checkpoint, height
+            );
+            if height - checkpoint > 1 {
+                warn!(
+                    "{} blocks were produced while the ingester was down",
+                    height - checkpoint - 1
+                );
+            }
+            producer.update_latest_block(checkpoint);
+            Ok(())
+        }
+        (Ok(height), checkpoint) => {
+            if let Some(checkpoint) = checkpoint {
+                warn!(
+                    "Ignoring checkpoint at block {} ahead of the chain at {}",
+                    checkpoint, height
+                );
+            }
+            info!("Initializing with current block height: {}", height);
+            producer.update_latest_block(height);
+            Ok(())
+        }
+        (Err(e), Some(checkpoint)) => {
+            error!("Failed to get initial block height: {}", e);
+            info!("Resuming from checkpoint at block {}", checkpoint);
+            producer.update_latest_block(checkpoint);
+            Ok(())
+        }
+        (Err(e), None) => {
+            error!("Failed to get initial block height: {}", e);
+            // Continue anyway, the system will self-correct
+            Ok(())
+        }
+    }
+}
+
+// Build the stream request. In lite mode the market filters are limited to
+// the given derivative markets and spot streams are disabled.
+fn create_stream_request(lite_market_ids: Option<Vec<String>>) -> StreamRequest {
+    let mut request = build_stream_request();
+    let wild_card_match = vec!["*".to_string()];
+    let lite = lite_market_ids.is_some();
+    let derivative_market_ids = lite_market_ids.unwrap_or_else(|| wild_card_match.clone());
+
+    // Configure what data to receive
+    request.bank_balances_filter = None;
+
+    request.spot_trades_filter = Some(models::TradesFilter {
+        market_ids: wild_card_match.clone(), // Wildcard to match all markets
+        subaccount_ids: wild_card_match.clone(), // Wildcard to match all subaccounts
+    });
+
+    request.derivative_trades_filter = Some(models::TradesFilter {
+        market_ids: derivative_market_ids.clone(), // Wildcard unless in lite mode
+        subaccount_ids: wild_card_match.clone(),   // Wildcard to match all subaccounts
+    });
+
+    request.spot_orderbooks_filter = Some(models::OrderbookFilter {
+        market_ids: wild_card_match.clone(),
+    });
+
+    // Adding the remaining filters
+    request.derivative_orderbooks_filter = Some(models::OrderbookFilter {
+        market_ids: derivative_market_ids,
+    });
+
+    request.spot_orders_filter = None;
+
+    request.derivative_orders_filter = None;
+
+    request.subaccount_deposits_filter = None;
+    // We're polling positions anyway so no need to do it again here
+    request.positions_filter = None;
+
+    request.oracle_price_filter = Some(models::OraclePriceFilter {
+        symbol: wild_card_match.clone(),
+    });
+
+    if lite {
+        request.spot_trades_filter = None;
+        request.spot_orderbooks_filter = None;
+    }
+
+    request
+}
+
+// Returns true if the stream should be restarted because the lite mode market set changed
+async fn stream_and_process(
+    mut client: StreamClient<tonic::transport::Channel>,
+    request: StreamRequest,
+    producer: Arc<BatchKafkaProducer>,
+    shutdown_rx: &mut watch::Receiver<bool>,
+    mut market_changes: Option<watch::Receiver<u64>>,
+    capture: &mut Option<CaptureWriter>,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    // Start streaming
+    let mut stream = client.stream(request).await?.into_inner();
+    info!("Stream established, waiting for data...");
+
+    // Process stream until we get a shutdown signal
+    loop {
+        tokio::select! {
+            _ = stop_requested(shutdown_rx) => {
+                info!("Received shutdown signal, stopping stream...");
+                break;
+            }
+            _ = market_set_changed(&mut market_changes) => {
+                return Ok(true);
+            }
+            message = stream.next() => {
+                match message {
+                    Some(Ok(response)) => {
+                        // Record everything the node sends, outdated blocks included
+                        if let Some(writer) = capture.as_mut() {
+                            if let Err(e) = writer.record(&response) {
+                                error!("Failed to capture block {}: {}", response.block_height, e);
+                            }
+                        }
+
+                        // Check if this is a new block before processing
+                        let block_height = response.block_height;
+                        let current_block = producer.get_latest_block();
+
+                        if block_height >= current_block {
+                            // Only process if it's current or new
+                            process_stream_response(response, &producer).await?;
+                        } else {
+                            // Log that we're skipping old data
+                            info!("Skipping outdated block data: {} (current: {})",
+                                  block_height, current_block);
+                        }
+                    }
+                    Some(Err(e)) => {
+                        error!("Error from stream: {}", e);
+                        // Consider reconnection strategy here
+                        break;
+                    }
+                    None => {
+                        info!("Stream ended, exiting...");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    info!("Stream processing ended");
+    Ok(false)
+}
+
+// Resolves once shutdown is requested. The watch guard is dropped inside, so
+// the stream loop stays Send and can run on a spawned task.
+async fn stop_requested(shutdown_rx: &mut watch::Receiver<bool>) {
+    let _ = shutdown_rx.wait_for(|stop| *stop).await;
+}
+
+// Resolves when the lite mode market set changes; never resolves otherwise
+async fn market_set_changed(market_changes: &mut Option<watch::Receiver<u64>>) {
+    if let Some(changes) = market_changes {
+        if changes.changed().await.is_ok() {
+            return;
+        }
+    }
+    std::future::pending().await
+}
+
+async fn process_stream_response(
+    response: StreamResponse,
+    producer: &BatchKafkaProducer,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let messages = Vec::<models::KafkaMessage>::from(response);
+
+    if !messages.is_empty() {
+        let max_block_height = messages
+            .iter()
+            .map(|msg| msg.block_height)
+            .max()
+            .unwrap_or(0);
+        let latest_processed = producer.get_latest_block();
+
+        let results = producer.send_batch_current_only(messages).await;
+
+        // Log errors if any
+        for (i, result) in results.iter().enumerate() {
+            if let Err(e) = result {
+                error!("Failed to send message {}: {}", i, e);
+            }
+        }
+
+        let success_count = results.iter().filter(|r| r.is_ok()).count();
+
+        // Enhanced logging to show block height information
+        if success_count > 0 {
+            info!(
+                "Block height: {} (was {}). Sent {}/{} messages to Kafka",
+                max_block_height,
+                latest_processed,
+                success_count,
+                results.len()
+            );
+        } else if max_block_height < latest_processed {
+            // Log when we're skipping old blocks
+            info!(
+                "Skipped {} messages from old block height {} (current: {})",
+                results.len(),
+                max_block_height,
+                latest_processed
+            );
+        }
+    }
+
+    Ok(())
+}
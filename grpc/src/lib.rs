@@ -0,0 +1,24 @@
+// Library target exposing the ingester and stream conversions to the
+// all-in-one binary, benchmarks and tools
+pub mod admin;
+pub mod backfill;
+pub mod capture;
+pub mod checkpoint;
+pub mod clock;
+pub mod config;
+pub mod depth;
+pub mod diagnostics;
+pub mod error;
+pub mod ingester;
+pub mod lite_mode;
+pub mod metrics;
+pub mod models;
+pub mod producer;
+pub mod proto;
+pub mod query_client;
+pub mod query_server;
+pub mod resync;
+pub mod secrets;
+pub mod standby;
+pub mod topics;
+pub mod wire;
@@ -0,0 +1,171 @@
+use crate::admin;
+use crate::models::{KafkaMessage, KafkaPayload};
+use crate::query_client::ExchangeQueryClient;
+use log::{error, info};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+use tokio::task;
+use tokio::time::{interval, Duration};
+
+// The set of derivative markets the pipeline is restricted to in lite mode.
+// Clones share the same set, so the refresher, stream and producer all see
+// updates at once.
+#[derive(Clone)]
+pub struct LiteMarketSet {
+    top_n: usize,
+    markets: Arc<RwLock<HashSet<String>>>,
+    generation: Arc<watch::Sender<u64>>,
+}
+
+impl LiteMarketSet {
+    pub fn new(top_n: usize) -> Self {
+        let (generation, _) = watch::channel(0);
+        LiteMarketSet {
+            top_n,
+            markets: Arc::new(RwLock::new(HashSet::new())),
+            generation: Arc::new(generation),
+        }
+    }
+
+    pub fn contains(&self, market_id: &str) -> bool {
+        self.markets.read().unwrap().contains(market_id)
+    }
+
+    pub fn market_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.markets.read().unwrap().iter().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    // Notified whenever a refresh changes the set
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.generation.subscribe()
+    }
+
+    // Re-rank active derivative markets and keep the top N. The chain query API
+    // exposes no traded volume, so markets are ranked by open interest notional
+    // (sum of position quantity times mark price), which tracks volume closely
+    // for the majors lite mode is meant for.
+    pub async fn refresh(
+        &self,
+        client: &mut ExchangeQueryClient,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let markets = client
+            .get_derivative_markets(Some("Active".to_string()))
+            .await?;
+        let positions = client.get_positions().await?;
+
+        let mut open_interest: HashMap<String, f64> = HashMap::new();
+        for position in positions {
+            if let Some(state) = position.position {
+                let quantity = state.quantity.parse::<f64>().unwrap_or(0.0);
+                *open_interest.entry(position.market_id).or_default() += quantity;
+            }
+        }
+
+        let mut ranked: Vec<(String, f64)> = markets
+            .into_iter()
+            .filter_map(|market| {
+                let market_id = market.market?.market_id;
+                let mark_price = market.mark_price.parse::<f64>().unwrap_or(0.0);
+                let notional = open_interest.get(&market_id).copied().unwrap_or(0.0) * mark_price;
+                Some((market_id, notional))
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked.truncate(self.top_n);
+
+        let selected: HashSet<String> = ranked.into_iter().map(|(id, _)| id).collect();
+        let changed = {
+            let mut markets = self.markets.write().unwrap();
+            if *markets != selected {
+                *markets = selected;
+                true
+            } else {
+                false
+            }
+        };
+
+        if changed {
+            let market_ids = self.market_ids();
+            info!("Lite mode market set updated: {:?}", market_ids);
+            admin::runtime().set_filter("lite_markets", &market_ids);
+            self.generation.send_modify(|generation| *generation += 1);
+        }
+        Ok(())
+    }
+
+    // Refresh the set periodically in the background
+    pub fn spawn_refresh(
+        &self,
+        mut client: ExchangeQueryClient,
+        interval_secs: u64,
+    ) -> task::JoinHandle<()> {
+        let set = self.clone();
+        task::spawn(async move {
+            let mut timer = interval(Duration::from_secs(interval_secs));
+            // The first tick fires immediately and the set was just loaded
+            timer.tick().await;
+            loop {
+                timer.tick().await;
+                if let Err(e) = set.refresh(&mut client).await {
+                    error!("Failed to refresh lite mode market set: {}", e);
+                }
+            }
+        })
+    }
+
+    // Strip entries for markets outside the set. Returns false if nothing is
+    // left to send. Payloads that are not market specific pass through.
+    pub fn retain(&self, message: &mut KafkaMessage) -> bool {
+        let markets = self.markets.read().unwrap();
+        let keep = |market_id: &String| markets.contains(market_id);
+
+        match &mut message.payload {
+            KafkaPayload::StreamSpotOrderbooks(items)
+            | KafkaPayload::StreamDerivativeOrderbooks(items) => {
+                items.retain(|o| keep(&o.market_id));
+                !items.is_empty()
+            }
+            KafkaPayload::StreamPositions(items) | KafkaPayload::ExchangePositions(items) => {
+                items.retain(|p| keep(&p.market_id));
+                !items.is_empty()
+            }
+            KafkaPayload::SpotTrades(items) => {
+                items.retain(|t| keep(&t.market_id));
+                !items.is_empty()
+            }
+            KafkaPayload::DerivativeTrades(items) => {
+                items.retain(|t| keep(&t.market_id));
+                !items.is_empty()
+            }
+            KafkaPayload::SpotOrders(items) => {
+                items.retain(|o| keep(&o.market_id));
+                !items.is_empty()
+            }
+            KafkaPayload::DerivativeOrders(items) => {
+                items.retain(|o| keep(&o.market_id));
+                !items.is_empty()
+            }
+            KafkaPayload::DerivativeMarkets(items) => {
+                items.retain(|m| keep(&m.market_id));
+                !items.is_empty()
+            }
+            KafkaPayload::DerivativeFullOrderbooks(items)
+            | KafkaPayload::SpotFullOrderbooks(items) => {
+                items.retain(|o| keep(&o.market_id));
+                !items.is_empty()
+            }
+            KafkaPayload::SpotMarkets(items) => {
+                items.retain(|m| keep(&m.market_id));
+                !items.is_empty()
+            }
+            KafkaPayload::StreamBankBalances(_)
+            | KafkaPayload::StreamSubaccountDeposits(_)
+            | KafkaPayload::StreamOraclePrices(_)
+            | KafkaPayload::ExchangeBalances(_) => true,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::admin;
+use crate::error::ProducerError;
+use axum::http::header::CONTENT_TYPE;
+use axum::routing::get;
+use axum::Router;
+use log::{error, info};
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, Encoder, HistogramVec,
+    IntCounterVec, IntGauge, TextEncoder,
+};
+use std::error::Error;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task;
+
+// Producer metrics. They live in the default Prometheus registry, so a process
+// that also runs the consumers serves everything from one endpoint.
+pub struct ProducerMetrics {
+    pub messages: IntCounterVec,
+    pub delivery_errors: IntCounterVec,
+    pub delivery_seconds: HistogramVec,
+    pub latest_block: IntGauge,
+    pub leader: IntGauge,
+}
+
+pub fn producer() -> &'static ProducerMetrics {
+    static METRICS: OnceLock<ProducerMetrics> = OnceLock::new();
+    METRICS.get_or_init(|| ProducerMetrics {
+        messages: register_int_counter_vec!(
+            "injective_producer_messages_total",
+            "Messages delivered to Kafka",
+            &["topic"]
+        )
+        .expect("producer metric registered twice"),
+        delivery_errors: register_int_counter_vec!(
+            "injective_producer_delivery_errors_total",
+            "Messages that failed to serialize or be delivered to Kafka",
+            &["topic", "kind"]
+        )
+        .expect("producer metric registered twice"),
+        delivery_seconds: register_histogram_vec!(
+            "injective_producer_delivery_seconds",
+            "Time from handing a message to librdkafka until the broker acknowledged it",
+            &["topic"],
+            vec![0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0]
+        )
+        .expect("producer metric registered twice"),
+        latest_block: register_int_gauge!(
+            "injective_producer_latest_block",
+            "Highest block height seen by the producer"
+        )
+        .expect("producer metric registered twice"),
+        leader: register_int_gauge!(
+            "injective_producer_leader",
+            "1 while this ingester holds the standby lease and publishes"
+        )
+        .expect("producer metric registered twice"),
+    })
+}
+
+impl ProducerMetrics {
+    pub fn record_delivery(
+        &self,
+        topic: &str,
+        result: &Result<(), ProducerError>,
+        elapsed: Duration,
+    ) {
+        match result {
+            Ok(()) => {
+                self.messages.with_label_values(&[topic]).inc();
+                self.delivery_seconds
+                    .with_label_values(&[topic])
+                    .observe(elapsed.as_secs_f64());
+            }
+            Err(e) => {
+                let kind = match e {
+                    ProducerError::Serialize(_) => "serialize",
+                    ProducerError::Kafka(_) => "kafka",
+                    ProducerError::Aborted => "aborted",
+                };
+                self.delivery_errors.with_label_values(&[topic, kind]).inc();
+            }
+        }
+    }
+}
+
+// Content type of the Prometheus text format
+pub const PROMETHEUS_TEXT: &str = "text/plain; version=0.0.4";
+
+// Every registered metric in the Prometheus text format
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        error!("Failed to encode metrics: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+// Answer GET /metrics, and GET /config with the effective configuration, on
+// `addr` until the process exits
+pub async fn serve(addr: &str) -> Result<task::JoinHandle<()>, Box<dyn Error + Send + Sync>> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving metrics on {}/metrics", addr);
+
+    let router = Router::new()
+        .route(
+            "/metrics",
+            get(|| async { ([(CONTENT_TYPE, PROMETHEUS_TEXT)], render()) }),
+        )
+        .route(
+            "/config",
+            get(|| async {
+                (
+                    [(CONTENT_TYPE, "application/json")],
+                    admin::runtime().render(),
+                )
+            }),
+        );
+    Ok(task::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            error!("Metrics server stopped: {}", e);
+        }
+    }))
+}
@@ -30,6 +30,40 @@ pub enum MessageType {
     ExchangeBalance,
     ExchangePosition,
     DerivativeFullOrderbook,
+    SpotMarket,
+    SpotFullOrderbook,
+}
+
+// Kafka header carrying the message type, so consumers can skip messages they
+// don't handle without parsing the payload
+pub const MESSAGE_TYPE_HEADER: &str = "message_type";
+
+// Kafka header naming the encoding of the message value (json, flatbuffers or
+// protobuf); messages without it are JSON unless the consumer is told otherwise
+pub const FORMAT_HEADER: &str = "format";
+
+impl MessageType {
+    // Same spelling as the serialized `message_type` field
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageType::StreamBankBalance => "StreamBankBalance",
+            MessageType::StreamSubaccountDeposit => "StreamSubaccountDeposit",
+            MessageType::StreamPosition => "StreamPosition",
+            MessageType::StreamSpotOrderbook => "StreamSpotOrderbook",
+            MessageType::StreamDerivativeOrderbook => "StreamDerivativeOrderbook",
+            MessageType::StreamOraclePrice => "StreamOraclePrice",
+            MessageType::SpotTrade => "SpotTrade",
+            MessageType::DerivativeTrade => "DerivativeTrade",
+            MessageType::SpotOrder => "SpotOrder",
+            MessageType::DerivativeOrder => "DerivativeOrder",
+            MessageType::DerivativeMarket => "DerivativeMarket",
+            MessageType::ExchangeBalance => "ExchangeBalance",
+            MessageType::ExchangePosition => "ExchangePosition",
+            MessageType::DerivativeFullOrderbook => "DerivativeFullOrderbook",
+            MessageType::SpotMarket => "SpotMarket",
+            MessageType::SpotFullOrderbook => "SpotFullOrderbook",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,181 +83,360 @@ pub enum KafkaPayload {
     ExchangePositions(Vec<PositionPayload>),
     ExchangeBalances(Vec<ExchangeBalancePayload>),
     DerivativeFullOrderbooks(Vec<FullLimitOrderbookPayload>),
+    SpotMarkets(Vec<SpotMarketPayload>),
+    // Same shape as DerivativeFullOrderbooks, which JSON decodes it as; the
+    // message type tells them apart
+    SpotFullOrderbooks(Vec<FullLimitOrderbookPayload>),
 }
 
 // Custom serializable structs for each message type
-// These mirror the protobuf structs but are optimized for JSON serialization
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// These mirror the protobuf structs but are optimized for JSON serialization.
+// The prost tags define their protobuf wire format (see wire), so a field
+// keeps its tag for good once a producer has published it.
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct BankBalancePayload {
+    #[prost(string, tag = "1")]
     pub account: String,
+    #[prost(message, repeated, tag = "2")]
     pub balances: Vec<CoinPayload>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct CoinPayload {
+    #[prost(string, tag = "1")]
     pub denom: String,
+    #[prost(string, tag = "2")]
     pub amount: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct SubaccountDepositPayload {
+    #[prost(string, tag = "1")]
     pub subaccount_id: String,
+    #[prost(string, tag = "2")]
     pub denom: String,
+    #[prost(string, tag = "3")]
     pub available_balance: String,
+    #[prost(string, tag = "4")]
     pub total_balance: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct SpotTradePayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(bool, tag = "2")]
     pub is_buy: bool,
+    #[prost(string, tag = "3")]
     pub execution_type: String,
+    #[prost(string, tag = "4")]
     pub quantity: String,
+    #[prost(string, tag = "5")]
     pub price: String,
+    #[prost(string, tag = "6")]
     pub subaccount_id: String,
+    #[prost(string, tag = "7")]
     pub fee: String,
+    #[prost(string, tag = "8")]
     pub order_hash: String,
+    #[prost(string, tag = "9")]
     pub fee_recipient_address: String,
+    #[prost(string, tag = "10")]
     pub cid: String,
+    #[prost(string, tag = "11")]
     pub trade_id: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct DerivativeTradePayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(bool, tag = "2")]
     pub is_buy: bool,
+    #[prost(string, tag = "3")]
     pub execution_type: String,
+    #[prost(string, tag = "4")]
     pub subaccount_id: String,
+    #[prost(message, required, tag = "5")]
     pub position_delta: PositionDeltaPayload,
+    #[prost(string, tag = "6")]
     pub payout: String,
+    #[prost(string, tag = "7")]
     pub fee: String,
+    #[prost(string, tag = "8")]
     pub order_hash: String,
+    #[prost(string, tag = "9")]
     pub fee_recipient_address: String,
+    #[prost(string, tag = "10")]
     pub cid: String,
+    #[prost(string, tag = "11")]
     pub trade_id: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct PositionDeltaPayload {
+    #[prost(bool, tag = "1")]
     pub is_long: bool,
+    #[prost(string, tag = "2")]
     pub execution_quantity: String,
+    #[prost(string, tag = "3")]
     pub execution_margin: String,
+    #[prost(string, tag = "4")]
     pub execution_price: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct SpotOrderPayload {
+    #[prost(string, tag = "1")]
     pub status: String,
+    #[prost(string, tag = "2")]
     pub order_hash: String,
+    #[prost(string, tag = "3")]
     pub cid: String,
+    #[prost(string, tag = "4")]
     pub market_id: String,
+    #[prost(string, tag = "5")]
     pub subaccount_id: String,
+    #[prost(string, tag = "6")]
     pub price: String,
+    #[prost(string, tag = "7")]
     pub quantity: String,
+    #[prost(string, tag = "8")]
     pub fillable: String,
+    #[prost(bool, tag = "9")]
     pub is_buy: bool,
+    #[prost(string, tag = "10")]
     pub order_type: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct DerivativeOrderPayload {
+    #[prost(string, tag = "1")]
     pub status: String,
+    #[prost(string, tag = "2")]
     pub order_hash: String,
+    #[prost(string, tag = "3")]
     pub cid: String,
+    #[prost(string, tag = "4")]
     pub market_id: String,
+    #[prost(string, tag = "5")]
     pub subaccount_id: String,
+    #[prost(string, tag = "6")]
     pub price: String,
+    #[prost(string, tag = "7")]
     pub quantity: String,
+    #[prost(string, tag = "8")]
     pub margin: String,
+    #[prost(string, tag = "9")]
     pub fillable: String,
+    #[prost(bool, tag = "10")]
     pub is_buy: bool,
+    #[prost(string, tag = "11")]
     pub order_type: String,
+    #[prost(bool, tag = "12")]
     pub is_market: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct OrderbookPayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(message, repeated, tag = "2")]
     pub buy_levels: Vec<PriceLevelPayload>,
+    #[prost(message, repeated, tag = "3")]
     pub sell_levels: Vec<PriceLevelPayload>,
+    #[prost(uint64, tag = "4")]
     pub sequence: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct PriceLevelPayload {
+    #[prost(string, tag = "1")]
     pub price: String,
+    #[prost(string, tag = "2")]
     pub quantity: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct PositionPayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(string, tag = "2")]
     pub subaccount_id: String,
+    #[prost(bool, tag = "3")]
     pub is_long: bool,
+    #[prost(string, tag = "4")]
     pub quantity: String,
+    #[prost(string, tag = "5")]
     pub entry_price: String,
+    #[prost(string, tag = "6")]
     pub margin: String,
+    #[prost(string, tag = "7")]
     pub cumulative_funding_entry: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct OraclePricePayload {
+    #[prost(string, tag = "1")]
     pub symbol: String,
+    #[prost(string, tag = "2")]
     pub price: String,
+    #[prost(string, tag = "3")]
     pub oracle_type: String,
 }
 
 // Additional data models for the new message types
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct DerivativeMarketPayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(string, tag = "2")]
     pub ticker: String,
+    #[prost(string, tag = "3")]
     pub oracle_base: String,
+    #[prost(string, tag = "4")]
     pub oracle_quote: String,
+    #[prost(string, tag = "5")]
     pub quote_denom: String,
+    #[prost(string, tag = "6")]
     pub maker_fee_rate: String,
+    #[prost(string, tag = "7")]
     pub taker_fee_rate: String,
+    #[prost(string, tag = "8")]
     pub initial_margin_ratio: String,
+    #[prost(string, tag = "9")]
     pub maintenance_margin_ratio: String,
+    #[prost(bool, tag = "10")]
     pub is_perpetual: bool,
+    #[prost(string, tag = "11")]
     pub status: String,
+    #[prost(string, tag = "12")]
     pub mark_price: String,
+    #[prost(string, tag = "13")]
     pub min_price_tick: String,
+    #[prost(string, tag = "14")]
     pub min_quantity_tick: String,
+    #[prost(string, tag = "15")]
     pub min_notional: String,
+    #[prost(string, tag = "16")]
     pub hfr: String,
+    #[prost(string, tag = "17")]
     pub hir: String,
+    #[prost(string, tag = "18")]
     pub funding_interval: String,
+    #[prost(string, tag = "19")]
     pub cumulative_funding: String,
+    #[prost(string, tag = "20")]
     pub cumulative_price: String,
+    // Expiry futures only: expiry in seconds, and the settlement price once
+    // the chain has settled the market
+    #[prost(string, tag = "21")]
+    pub expiration_timestamp: String,
+    #[prost(string, tag = "22")]
+    pub settlement_price: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Spot prices and quantities depend on the base and quote decimals, which
+// are carried along so consumers can scale them
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
+pub struct SpotMarketPayload {
+    #[prost(string, tag = "1")]
+    pub market_id: String,
+    #[prost(string, tag = "2")]
+    pub ticker: String,
+    #[prost(string, tag = "3")]
+    pub base_denom: String,
+    #[prost(string, tag = "4")]
+    pub quote_denom: String,
+    #[prost(string, tag = "5")]
+    pub maker_fee_rate: String,
+    #[prost(string, tag = "6")]
+    pub taker_fee_rate: String,
+    #[prost(string, tag = "7")]
+    pub status: String,
+    #[prost(string, tag = "8")]
+    pub min_price_tick: String,
+    #[prost(string, tag = "9")]
+    pub min_quantity_tick: String,
+    #[prost(string, tag = "10")]
+    pub min_notional: String,
+    #[prost(uint32, tag = "11")]
+    pub base_decimals: u32,
+    #[prost(uint32, tag = "12")]
+    pub quote_decimals: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct ExchangeBalancePayload {
+    #[prost(string, tag = "1")]
     pub subaccount_id: String,
+    #[prost(string, tag = "2")]
     pub denom: String,
+    #[prost(string, tag = "3")]
     pub available_balance: String,
+    #[prost(string, tag = "4")]
     pub total_balance: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct FullLimitOrderbookPayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(message, repeated, tag = "2")]
     pub bids: Vec<TrimmedLimitOrderPayload>,
+    #[prost(message, repeated, tag = "3")]
     pub asks: Vec<TrimmedLimitOrderPayload>,
+    #[prost(int64, tag = "4")]
     pub timestamp: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct TrimmedLimitOrderPayload {
+    #[prost(string, tag = "1")]
     pub price: String,
+    #[prost(string, tag = "2")]
     pub quantity: String,
+    #[prost(string, tag = "3")]
     pub order_hash: String,
+    #[prost(string, tag = "4")]
     pub subaccount_id: String,
 }
 
-// Functions to convert from proto types to our serializable types
+// Functions to convert from proto types to our serializable types. The response
+// is owned, so strings are moved into the payloads rather than cloned.
+impl From<crate::proto::injective::stream::v1beta1::OrderbookUpdate> for OrderbookPayload {
+    fn from(update: crate::proto::injective::stream::v1beta1::OrderbookUpdate) -> Self {
+        let sequence = update.seq;
+        match update.orderbook {
+            Some(ob) => OrderbookPayload {
+                market_id: ob.market_id,
+                buy_levels: price_levels(ob.buy_levels),
+                sell_levels: price_levels(ob.sell_levels),
+                sequence,
+            },
+            None => OrderbookPayload {
+                market_id: String::new(),
+                buy_levels: Vec::new(),
+                sell_levels: Vec::new(),
+                sequence,
+            },
+        }
+    }
+}
+
+fn price_levels(
+    levels: Vec<crate::proto::injective::exchange::v1beta1::Level>,
+) -> Vec<PriceLevelPayload> {
+    levels
+        .into_iter()
+        .map(|l| PriceLevelPayload {
+            price: l.p,
+            quantity: l.q,
+        })
+        .collect()
+}
+
 impl From<crate::proto::injective::stream::v1beta1::StreamResponse> for Vec<KafkaMessage> {
     fn from(response: crate::proto::injective::stream::v1beta1::StreamResponse) -> Self {
         let mut messages = Vec::new();
@@ -262,21 +475,19 @@ impl From<crate::proto::injective::stream::v1beta1::StreamResponse> for Vec<Kafk
                 .subaccount_deposits
                 .into_iter()
                 .flat_map(|sd| {
-                    let subaccount_id = sd.subaccount_id.clone();
-                    sd.deposits
-                        .into_iter()
-                        .map(move |d| SubaccountDepositPayload {
+                    let subaccount_id = sd.subaccount_id;
+                    sd.deposits.into_iter().map(move |d| {
+                        let (available_balance, total_balance) = d.deposit.map_or_else(
+                            || ("0".to_string(), "0".to_string()),
+                            |dep| (dep.available_balance, dep.total_balance),
+                        );
+                        SubaccountDepositPayload {
                             subaccount_id: subaccount_id.clone(),
                             denom: d.denom,
-                            available_balance: d.deposit.as_ref().map_or_else(
-                                || "0".to_string(),
-                                |dep| dep.available_balance.clone(),
-                            ),
-                            total_balance: d
-                                .deposit
-                                .as_ref()
-                                .map_or_else(|| "0".to_string(), |dep| dep.total_balance.clone()),
-                        })
+                            available_balance,
+                            total_balance,
+                        }
+                    })
                 })
                 .collect();
 
@@ -326,21 +537,20 @@ impl From<crate::proto::injective::stream::v1beta1::StreamResponse> for Vec<Kafk
                     is_buy: t.is_buy,
                     execution_type: t.execution_type,
                     subaccount_id: t.subaccount_id,
-                    position_delta: PositionDeltaPayload {
-                        is_long: t.position_delta.as_ref().map_or(false, |pd| pd.is_long),
-                        execution_quantity: t
-                            .position_delta
-                            .as_ref()
-                            .map_or_else(|| "0".to_string(), |pd| pd.execution_quantity.clone()),
-                        execution_margin: t
-                            .position_delta
-                            .as_ref()
-                            .map_or_else(|| "0".to_string(), |pd| pd.execution_margin.clone()),
-                        execution_price: t
-                            .position_delta
-                            .as_ref()
-                            .map_or_else(|| "0".to_string(), |pd| pd.execution_price.clone()),
-                    },
+                    position_delta: t.position_delta.map_or_else(
+                        || PositionDeltaPayload {
+                            is_long: false,
+                            execution_quantity: "0".to_string(),
+                            execution_margin: "0".to_string(),
+                            execution_price: "0".to_string(),
+                        },
+                        |pd| PositionDeltaPayload {
+                            is_long: pd.is_long,
+                            execution_quantity: pd.execution_quantity,
+                            execution_margin: pd.execution_margin,
+                            execution_price: pd.execution_price,
+                        },
+                    ),
                     payout: t.payout,
                     fee: t.fee,
                     order_hash: t.order_hash,
@@ -521,31 +731,7 @@ impl From<crate::proto::injective::stream::v1beta1::StreamResponse> for Vec<Kafk
             let spot_orderbooks = response
                 .spot_orderbook_updates
                 .into_iter()
-                .map(|o| OrderbookPayload {
-                    market_id: o
-                        .orderbook
-                        .as_ref()
-                        .map_or_else(|| "".to_string(), |ob| ob.market_id.clone()),
-                    buy_levels: o.orderbook.as_ref().map_or_else(Vec::new, |ob| {
-                        ob.buy_levels
-                            .iter()
-                            .map(|l| PriceLevelPayload {
-                                price: l.p.clone(),
-                                quantity: l.q.clone(),
-                            })
-                            .collect()
-                    }),
-                    sell_levels: o.orderbook.as_ref().map_or_else(Vec::new, |ob| {
-                        ob.sell_levels
-                            .iter()
-                            .map(|l| PriceLevelPayload {
-                                price: l.p.clone(),
-                                quantity: l.q.clone(),
-                            })
-                            .collect()
-                    }),
-                    sequence: o.seq,
-                })
+                .map(OrderbookPayload::from)
                 .collect();
 
             messages.push(KafkaMessage {
@@ -562,31 +748,7 @@ impl From<crate::proto::injective::stream::v1beta1::StreamResponse> for Vec<Kafk
             let derivative_orderbooks = response
                 .derivative_orderbook_updates
                 .into_iter()
-                .map(|o| OrderbookPayload {
-                    market_id: o
-                        .orderbook
-                        .as_ref()
-                        .map_or_else(|| "".to_string(), |ob| ob.market_id.clone()),
-                    buy_levels: o.orderbook.as_ref().map_or_else(Vec::new, |ob| {
-                        ob.buy_levels
-                            .iter()
-                            .map(|l| PriceLevelPayload {
-                                price: l.p.clone(),
-                                quantity: l.q.clone(),
-                            })
-                            .collect()
-                    }),
-                    sell_levels: o.orderbook.as_ref().map_or_else(Vec::new, |ob| {
-                        ob.sell_levels
-                            .iter()
-                            .map(|l| PriceLevelPayload {
-                                price: l.p.clone(),
-                                quantity: l.q.clone(),
-                            })
-                            .collect()
-                    }),
-                    sequence: o.seq,
-                })
+                .map(OrderbookPayload::from)
                 .collect();
 
             messages.push(KafkaMessage {
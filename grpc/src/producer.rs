@@ -1,26 +1,48 @@
-use crate::config::KafkaConfig;
-use crate::models::KafkaMessage;
+use crate::checkpoint::CheckpointStore;
+use crate::config::{KafkaConfig, SerializationFormat};
+use crate::depth::OrderbookDepth;
+use crate::error::ProducerError;
+use crate::lite_mode::LiteMarketSet;
+use crate::metrics;
+use crate::models::{KafkaMessage, FORMAT_HEADER, MESSAGE_TYPE_HEADER};
+use crate::wire;
 use futures::future::join_all;
-use log::error;
+use log::{error, warn};
 use rdkafka::config::ClientConfig;
 use rdkafka::error::KafkaError;
+use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
 use rdkafka::util::Timeout;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::Semaphore;
 const MAX_CONCURRENT_REQUESTS: usize = 100;
 const BATCH_SIZE: usize = 1000;
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct BatchKafkaProducer {
     producer: Arc<FutureProducer>,
-    topic: String,
+    topics: KafkaConfig,
     request_limiter: Arc<Semaphore>,
     latest_processed_block: Arc<std::sync::atomic::AtomicU64>,
+    market_filter: Option<LiteMarketSet>,
+    depth: OrderbookDepth,
+    checkpoint: Option<Box<dyn CheckpointStore>>,
+    // Highest block height written to the checkpoint store
+    checkpointed_block: std::sync::atomic::AtomicU64,
+    // Held for the length of a batch when the producer is transactional,
+    // since a producer has one open transaction at a time
+    transaction: Option<tokio::sync::Mutex<()>>,
+    // Whether this instance publishes, in standby mode; always when None
+    leadership: Option<tokio::sync::watch::Receiver<bool>>,
 }
 impl BatchKafkaProducer {
-    pub fn new(config: &KafkaConfig) -> Result<Self, KafkaError> {
-        let producer = ClientConfig::new()
+    /// `role` names this producer within the process, and keeps its
+    /// transactional id apart from the other producers'
+    pub async fn new(config: &KafkaConfig, role: &str) -> Result<Self, KafkaError> {
+        let mut client = ClientConfig::new();
+        config.security.apply(&mut client);
+        client
             .set("bootstrap.servers", config.brokers.join(","))
             .set("client.id", &config.client_id)
             // Ultra-low latency optimizations
@@ -36,16 +58,130 @@ impl BatchKafkaProducer {
             .set("retry.backoff.ms", "1")
             .set("acks", "1")
             .set("delivery.timeout.ms", "30000")
-            .set("request.timeout.ms", "1000")
-            .create()?;
+            .set("request.timeout.ms", "1000");
+        config.delivery.apply(&mut client, role);
+        let producer: Arc<FutureProducer> = Arc::new(client.create()?);
+
+        // Fences off any earlier instance with the same transactional id and
+        // aborts the transaction it left open
+        let transaction = if config.delivery.transactional() {
+            blocking(&producer, |producer| {
+                producer.init_transactions(Timeout::After(TRANSACTION_TIMEOUT))
+            })
+            .await?;
+            Some(tokio::sync::Mutex::new(()))
+        } else {
+            None
+        };
 
         Ok(BatchKafkaProducer {
-            producer: Arc::new(producer),
-            topic: config.topic.clone(),
+            producer,
+            topics: config.clone(),
             request_limiter: Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS)),
             latest_processed_block: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            market_filter: None,
+            depth: OrderbookDepth::new(config.orderbook_depth.clone()),
+            checkpoint: None,
+            checkpointed_block: std::sync::atomic::AtomicU64::new(0),
+            transaction,
+            leadership: None,
         })
     }
+
+    /// Record the highest fully delivered block height after every batch
+    pub fn with_checkpoint(mut self, store: Box<dyn CheckpointStore>) -> Self {
+        self.checkpoint = Some(store);
+        self
+    }
+
+    /// The last checkpointed block height, if checkpointing is on and one was written
+    pub async fn load_checkpoint(&self) -> Option<u64> {
+        let store = self.checkpoint.as_ref()?;
+        match store.load().await {
+            Ok(block_height) => {
+                if let Some(height) = block_height {
+                    self.checkpointed_block
+                        .fetch_max(height, std::sync::atomic::Ordering::Relaxed);
+                }
+                block_height
+            }
+            Err(e) => {
+                warn!("Failed to load producer checkpoint: {}", e);
+                None
+            }
+        }
+    }
+
+    // Move the checkpoint forward once a batch is fully delivered. A failed
+    // write is only logged; the next batch tries again.
+    async fn checkpoint_batch(&self, block_height: u64, results: &[Result<(), ProducerError>]) {
+        let Some(store) = &self.checkpoint else {
+            return;
+        };
+        if results.iter().any(|r| r.is_err()) {
+            return;
+        }
+        let previous = self
+            .checkpointed_block
+            .fetch_max(block_height, std::sync::atomic::Ordering::Relaxed);
+        if block_height <= previous {
+            return;
+        }
+        if let Err(e) = store.save(block_height).await {
+            warn!("Failed to checkpoint block {}: {}", block_height, e);
+        }
+    }
+
+    /// Only publish while `leadership` is true (standby mode). A standby
+    /// still tracks the block height, so it takes over where the leader was.
+    pub fn with_leadership(mut self, leadership: tokio::sync::watch::Receiver<bool>) -> Self {
+        self.leadership = Some(leadership);
+        self
+    }
+
+    fn publishing(&self) -> bool {
+        self.leadership
+            .as_ref()
+            .is_none_or(|leadership| *leadership.borrow())
+    }
+
+    /// Only send data for markets in the given set (lite mode)
+    pub fn with_market_filter(mut self, markets: LiteMarketSet) -> Self {
+        self.market_filter = Some(markets);
+        self
+    }
+
+    /// Apply the lite mode market filter, if any
+    fn filter_markets(&self, messages: Vec<KafkaMessage>) -> Vec<KafkaMessage> {
+        match &self.market_filter {
+            Some(markets) => messages
+                .into_iter()
+                .filter_map(|mut message| markets.retain(&mut message).then_some(message))
+                .collect(),
+            None => messages,
+        }
+    }
+
+    /// Pair every message with its topic, trimming full orderbooks to the
+    /// configured depth. The untrimmed books also go to the full orderbook
+    /// topic, if there is one.
+    fn route(&self, messages: Vec<KafkaMessage>) -> Vec<(String, KafkaMessage)> {
+        let mut routed = Vec::with_capacity(messages.len());
+        for message in messages {
+            self.depth.observe(&message);
+            let topic = self.topics.topic_for(&message.message_type).to_string();
+            match self.depth.trim(&message) {
+                Some(trimmed) => {
+                    routed.push((topic, trimmed));
+                    if let Some(full_topic) = &self.topics.topics.orderbooks_full {
+                        routed.push((full_topic.clone(), message));
+                    }
+                }
+                None => routed.push((topic, message)),
+            }
+        }
+        routed
+    }
     pub fn update_latest_block(&self, block_height: u64) {
         let current = self
             .latest_processed_block
@@ -53,6 +189,7 @@ impl BatchKafkaProducer {
         if block_height > current {
             self.latest_processed_block
                 .store(block_height, std::sync::atomic::Ordering::Relaxed);
+            metrics::producer().latest_block.set(block_height as i64);
         }
     }
     /// Get the latest processed block height
@@ -65,7 +202,7 @@ impl BatchKafkaProducer {
     pub async fn send_batch_current_only(
         &self,
         messages: Vec<KafkaMessage>,
-    ) -> Vec<Result<(), KafkaError>> {
+    ) -> Vec<Result<(), ProducerError>> {
         if messages.is_empty() {
             return Vec::new();
         }
@@ -97,11 +234,38 @@ impl BatchKafkaProducer {
         self.send_batch(filtered_messages).await
     }
     /// Sends a batch of messages with extreme throughput optimization
-    pub async fn send_batch(&self, messages: Vec<KafkaMessage>) -> Vec<Result<(), KafkaError>> {
+    pub async fn send_batch(&self, messages: Vec<KafkaMessage>) -> Vec<Result<(), ProducerError>> {
+        if !self.publishing() {
+            return Vec::new();
+        }
+        let messages = self.filter_markets(messages);
         if messages.is_empty() {
             return Vec::new();
         }
 
+        let max_block_height = messages.iter().map(|m| m.block_height).max().unwrap_or(0);
+        let messages = self.route(messages);
+
+        let results = match &self.transaction {
+            Some(transaction) => {
+                let _open = transaction.lock().await;
+                if let Err(e) = self.producer.begin_transaction() {
+                    return failed(messages.len(), &e);
+                }
+                let results = self.send_routed(messages).await;
+                self.end_transaction(results).await
+            }
+            None => self.send_routed(messages).await,
+        };
+
+        self.checkpoint_batch(max_block_height, &results).await;
+        results
+    }
+
+    async fn send_routed(
+        &self,
+        messages: Vec<(String, KafkaMessage)>,
+    ) -> Vec<Result<(), ProducerError>> {
         // Pre-allocate results with the exact capacity needed
         let mut results = Vec::with_capacity(messages.len());
 
@@ -118,17 +282,62 @@ impl BatchKafkaProducer {
         for mut chunk_result in chunk_results {
             results.append(&mut chunk_result);
         }
+        results
+    }
+
+    // Commit the batch's transaction when every message was delivered, and
+    // abort it otherwise. Nothing of an aborted batch is visible to
+    // consumers, so its delivered messages are failures too.
+    async fn end_transaction(
+        &self,
+        results: Vec<Result<(), ProducerError>>,
+    ) -> Vec<Result<(), ProducerError>> {
+        if results.iter().all(|r| r.is_ok()) {
+            let committed = blocking(&self.producer, |producer| {
+                producer.commit_transaction(Timeout::After(TRANSACTION_TIMEOUT))
+            })
+            .await;
+            match committed {
+                Ok(()) => return results,
+                Err(e) => {
+                    error!(
+                        "Failed to commit a batch of {} messages: {}",
+                        results.len(),
+                        e
+                    );
+                    self.abort_transaction().await;
+                    return failed(results.len(), &e);
+                }
+            }
+        }
 
+        self.abort_transaction().await;
         results
+            .into_iter()
+            .map(|r| r.and(Err(ProducerError::Aborted)))
+            .collect()
+    }
+
+    async fn abort_transaction(&self) {
+        let aborted = blocking(&self.producer, |producer| {
+            producer.abort_transaction(Timeout::After(TRANSACTION_TIMEOUT))
+        })
+        .await;
+        if let Err(e) = aborted {
+            error!("Failed to abort a transaction: {}", e);
+        }
     }
 
     /// Process a chunk of messages
-    async fn process_chunk(&self, chunk: Vec<KafkaMessage>) -> Vec<Result<(), KafkaError>> {
+    async fn process_chunk(
+        &self,
+        chunk: Vec<(String, KafkaMessage)>,
+    ) -> Vec<Result<(), ProducerError>> {
         let mut results = Vec::with_capacity(chunk.len());
-        let futures = chunk.into_iter().map(|message| {
+        let futures = chunk.into_iter().map(|(topic, message)| {
             let producer = Arc::clone(&self.producer);
-            let topic = self.topic.clone();
             let request_limiter = Arc::clone(&self.request_limiter);
+            let format = self.topics.format;
 
             async move {
                 // Acquire semaphore permit to limit concurrent requests
@@ -136,25 +345,27 @@ impl BatchKafkaProducer {
 
                 // Serialize message
                 let key = format!("{}-{}", message.block_height, message.block_time);
-                let result = match serde_json::to_string(&message) {
+                let start = Instant::now();
+                let result = match wire::encode(&message, format) {
                     Ok(payload) => {
                         // Send message
-                        let record = FutureRecord::to(&topic).payload(&payload).key(&key);
+                        let record = FutureRecord::to(&topic)
+                            .payload(&payload)
+                            .key(&key)
+                            .headers(message_headers(&message, format));
 
                         producer
                             .send(record, Timeout::Never)
                             .await
                             .map(|_| ())
-                            .map_err(|(e, _)| e)
+                            .map_err(|(e, _)| ProducerError::Kafka(e))
                     }
                     Err(e) => {
                         error!("Failed to serialize message: {}", e);
-                        // Use a valid KafkaError variant
-                        Err(KafkaError::MessageProduction(
-                            rdkafka::types::RDKafkaErrorCode::InvalidRecord,
-                        ))
+                        Err(ProducerError::Serialize(e))
                     }
                 };
+                metrics::producer().record_delivery(&topic, &result, start.elapsed());
                 result
             }
         });
@@ -165,7 +376,10 @@ impl BatchKafkaProducer {
     }
 
     /// Partition messages for optimal processing
-    fn partition_messages(&self, messages: Vec<KafkaMessage>) -> Vec<Vec<KafkaMessage>> {
+    fn partition_messages(
+        &self,
+        messages: Vec<(String, KafkaMessage)>,
+    ) -> Vec<Vec<(String, KafkaMessage)>> {
         // Split messages into chunks of BATCH_SIZE
         let mut chunks = Vec::new();
         for chunk in messages.chunks(BATCH_SIZE) {
@@ -178,32 +392,55 @@ impl BatchKafkaProducer {
     pub async fn send_batch_low_latency(
         &self,
         messages: Vec<KafkaMessage>,
-    ) -> Vec<Result<(), KafkaError>> {
+    ) -> Vec<Result<(), ProducerError>> {
+        if !self.publishing() {
+            return Vec::new();
+        }
+        let messages = self.filter_markets(messages);
         if messages.is_empty() {
             return Vec::new();
         }
+        let max_block_height = messages.iter().map(|m| m.block_height).max().unwrap_or(0);
+        let messages = self.route(messages);
+        let _open = match &self.transaction {
+            Some(transaction) => {
+                let open = transaction.lock().await;
+                if let Err(e) = self.producer.begin_transaction() {
+                    return failed(messages.len(), &e);
+                }
+                Some(open)
+            }
+            None => None,
+        };
         let mut results = Vec::with_capacity(messages.len());
-        for message in messages {
+        for (topic, message) in messages {
             let key = format!("{}-{}", message.block_height, message.block_time);
-            let result = match serde_json::to_string(&message) {
+            let start = Instant::now();
+            let result = match wire::encode(&message, self.topics.format) {
                 Ok(payload) => {
-                    let record = FutureRecord::to(&self.topic).payload(&payload).key(&key);
+                    let record = FutureRecord::to(&topic)
+                        .payload(&payload)
+                        .key(&key)
+                        .headers(message_headers(&message, self.topics.format));
                     self.producer
                         .send(record, Timeout::After(Duration::from_micros(1)))
                         .await
                         .map(|_| ())
-                        .map_err(|(e, _)| e)
+                        .map_err(|(e, _)| ProducerError::Kafka(e))
                 }
                 Err(e) => {
                     error!("Failed to serialize message: {}", e);
-                    Err(KafkaError::MessageProduction(
-                        rdkafka::types::RDKafkaErrorCode::InvalidRecord,
-                    ))
+                    Err(ProducerError::Serialize(e))
                 }
             };
+            metrics::producer().record_delivery(&topic, &result, start.elapsed());
             results.push(result);
         }
+        if self.transaction.is_some() {
+            results = self.end_transaction(results).await;
+        }
 
+        self.checkpoint_batch(max_block_height, &results).await;
         results
     }
 
@@ -213,3 +450,38 @@ impl BatchKafkaProducer {
             .flush(Timeout::After(Duration::from_millis(timeout_ms)))
     }
 }
+
+// librdkafka's transaction calls block until the brokers answer, so they run
+// on the blocking pool instead of holding up a runtime worker
+async fn blocking(
+    producer: &Arc<FutureProducer>,
+    call: impl FnOnce(&FutureProducer) -> Result<(), KafkaError> + Send + 'static,
+) -> Result<(), KafkaError> {
+    let producer = producer.clone();
+    match tokio::task::spawn_blocking(move || call(&producer)).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(KafkaError::Canceled),
+    }
+}
+
+// Every message of a batch fails with the error that stopped its transaction
+fn failed(count: usize, error: &KafkaError) -> Vec<Result<(), ProducerError>> {
+    (0..count)
+        .map(|_| Err(ProducerError::Kafka(error.clone())))
+        .collect()
+}
+
+// Headers let consumers route on the message type before parsing the payload,
+// and pick the decoder without being configured for the producer's format
+fn message_headers(message: &KafkaMessage, format: SerializationFormat) -> OwnedHeaders {
+    OwnedHeaders::new()
+        .insert(Header {
+            key: MESSAGE_TYPE_HEADER,
+            value: Some(message.message_type.as_str()),
+        })
+        .insert(Header {
+            key: FORMAT_HEADER,
+            value: Some(format.as_str()),
+        })
+}
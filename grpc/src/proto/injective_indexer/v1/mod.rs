@@ -0,0 +1,4 @@
+include!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/src/proto/injective_indexer.v1.rs"
+));
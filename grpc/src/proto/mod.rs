@@ -1,2 +1,3 @@
 pub mod cosmos;
 pub mod injective;
+pub mod injective_indexer;
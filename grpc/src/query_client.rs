@@ -3,17 +3,26 @@ use crate::proto::injective::exchange::v1beta1::full_derivative_market::Info;
 use crate::proto::injective::exchange::v1beta1::query_client::QueryClient;
 use crate::proto::injective::exchange::v1beta1::{
     DerivativePosition, FullDerivativeMarket, QueryDerivativeMarketsRequest,
-    QueryExchangeBalancesRequest, QueryPositionsRequest,
+    QueryExchangeBalancesRequest, QueryFullSpotOrderbookRequest, QueryFullSpotOrderbookResponse,
+    QueryPositionsRequest, QuerySpotMarketsRequest, SpotMarket,
 };
 use log::{debug, error, info};
 use std::error::Error;
 use tokio::time::{interval, Duration};
 use tonic::Request;
 
+// Cosmos SDK gRPC metadata selecting the block height a query is answered at
+const BLOCK_HEIGHT_HEADER: &str = "x-cosmos-block-height";
+
+// How long after its expiry an expiry futures market is still published
+const RECENTLY_EXPIRED_SECS: i64 = 86_400;
+
 pub struct ExchangeQueryClient {
     client: QueryClient<tonic::transport::Channel>,
     http_client: reqwest::Client,
     tendermint_rpc_endpoint: String,
+    // Block height queries are answered at; the latest block when unset
+    height: Option<u64>,
 }
 
 impl ExchangeQueryClient {
@@ -46,9 +55,27 @@ impl ExchangeQueryClient {
             client,
             http_client,
             tendermint_rpc_endpoint,
+            height: None,
         })
     }
 
+    // Answer the following queries with the state at `height`, or at the
+    // latest block for None. Past heights need a node that hasn't pruned them.
+    pub fn at_height(&mut self, height: Option<u64>) {
+        self.height = height;
+    }
+
+    // Wrap a query, pinned to the selected height if any
+    fn request<T>(&self, message: T) -> Result<Request<T>, Box<dyn Error + Send + Sync>> {
+        let mut request = Request::new(message);
+        if let Some(height) = self.height {
+            request
+                .metadata_mut()
+                .insert(BLOCK_HEIGHT_HEADER, height.to_string().parse()?);
+        }
+        Ok(request)
+    }
+
     // Properly async method to fetch current block height from Tendermint RPC
     pub async fn get_current_block_height(&self) -> Result<u64, Box<dyn Error + Send + Sync>> {
         #[derive(serde::Deserialize)]
@@ -81,15 +108,101 @@ impl ExchangeQueryClient {
         Ok(height)
     }
 
+    // Time of the block at `height`, in milliseconds since the epoch
+    pub async fn get_block_time(&self, height: u64) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        #[derive(serde::Deserialize)]
+        struct BlockResponse {
+            result: BlockResult,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct BlockResult {
+            block: Block,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct Block {
+            header: Header,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct Header {
+            time: String,
+        }
+
+        let response = self
+            .http_client
+            .get(format!("{}/block", self.tendermint_rpc_endpoint))
+            .query(&[("height", height)])
+            .send()
+            .await?
+            .json::<BlockResponse>()
+            .await?;
+
+        let time = chrono::DateTime::parse_from_rfc3339(&response.result.block.header.time)?;
+        Ok(time.timestamp_millis() as u64)
+    }
+
+    // Events the block at `height` emitted: begin block, then transaction,
+    // then end or finalize block events
+    pub async fn get_block_events(
+        &self,
+        height: u64,
+    ) -> Result<Vec<BlockEvent>, Box<dyn Error + Send + Sync>> {
+        #[derive(serde::Deserialize)]
+        struct BlockResultsResponse {
+            result: BlockResults,
+        }
+
+        // begin/end block events before CometBFT 0.38, finalize block events after
+        #[derive(serde::Deserialize)]
+        struct BlockResults {
+            txs_results: Option<Vec<TxResult>>,
+            begin_block_events: Option<Vec<BlockEvent>>,
+            end_block_events: Option<Vec<BlockEvent>>,
+            finalize_block_events: Option<Vec<BlockEvent>>,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct TxResult {
+            events: Option<Vec<BlockEvent>>,
+        }
+
+        let response = self
+            .http_client
+            .get(format!("{}/block_results", self.tendermint_rpc_endpoint))
+            .query(&[("height", height)])
+            .send()
+            .await?
+            .json::<BlockResultsResponse>()
+            .await?;
+
+        let results = response.result;
+        let tx_events = results
+            .txs_results
+            .into_iter()
+            .flatten()
+            .flat_map(|tx| tx.events.unwrap_or_default());
+        let events = results
+            .begin_block_events
+            .into_iter()
+            .flatten()
+            .chain(tx_events)
+            .chain(results.end_block_events.into_iter().flatten())
+            .chain(results.finalize_block_events.into_iter().flatten())
+            .collect();
+        Ok(events)
+    }
+
     pub async fn get_derivative_markets(
         &mut self,
         status: Option<String>,
     ) -> Result<Vec<FullDerivativeMarket>, Box<dyn Error + Send + Sync>> {
-        let request = Request::new(QueryDerivativeMarketsRequest {
+        let request = self.request(QueryDerivativeMarketsRequest {
             status: status.unwrap_or_default(),
             market_ids: vec![],
             with_mid_price_and_tob: true,
-        });
+        })?;
 
         let response = self.client.derivative_markets(request).await?;
         let markets = response.into_inner().markets;
@@ -98,10 +211,26 @@ impl ExchangeQueryClient {
         Ok(markets)
     }
 
+    pub async fn get_spot_markets(
+        &mut self,
+        status: Option<String>,
+    ) -> Result<Vec<SpotMarket>, Box<dyn Error + Send + Sync>> {
+        let request = self.request(QuerySpotMarketsRequest {
+            status: status.unwrap_or_default(),
+            market_ids: vec![],
+        })?;
+
+        let response = self.client.spot_markets(request).await?;
+        let markets = response.into_inner().markets;
+
+        info!("Retrieved {} spot markets", markets.len());
+        Ok(markets)
+    }
+
     pub async fn get_positions(
         &mut self,
     ) -> Result<Vec<DerivativePosition>, Box<dyn Error + Send + Sync>> {
-        let request = Request::new(QueryPositionsRequest {});
+        let request = self.request(QueryPositionsRequest {})?;
 
         let response = self.client.positions(request).await?;
         let positions = response.into_inner().state;
@@ -116,7 +245,7 @@ impl ExchangeQueryClient {
         Vec<crate::proto::injective::exchange::v1beta1::Balance>,
         Box<dyn Error + Send + Sync>,
     > {
-        let request = Request::new(QueryExchangeBalancesRequest {});
+        let request = self.request(QueryExchangeBalancesRequest {})?;
 
         let response = self.client.exchange_balances(request).await?;
         let balances = response.into_inner().balances;
@@ -133,17 +262,57 @@ impl ExchangeQueryClient {
         crate::proto::injective::exchange::v1beta1::QueryFullDerivativeOrderbookResponse,
         Box<dyn Error + Send + Sync>,
     > {
-        let request = Request::new(
+        let request = self.request(
             crate::proto::injective::exchange::v1beta1::QueryFullDerivativeOrderbookRequest {
                 market_id: market_id.to_string(),
             },
-        );
+        )?;
 
         let response = self.client.l3_derivative_order_book(request).await?;
         info!("Retrieved full orderbook for market {}", market_id);
 
         Ok(response.into_inner())
     }
+
+    // Get full L3 orderbook for a spot market
+    pub async fn get_full_spot_orderbook(
+        &mut self,
+        market_id: &str,
+    ) -> Result<QueryFullSpotOrderbookResponse, Box<dyn Error + Send + Sync>> {
+        let request = self.request(QueryFullSpotOrderbookRequest {
+            market_id: market_id.to_string(),
+        })?;
+
+        let response = self.client.l3_spot_order_book(request).await?;
+        info!("Retrieved full orderbook for spot market {}", market_id);
+
+        Ok(response.into_inner())
+    }
+}
+
+// An ABCI event from Tendermint RPC. Attribute values of typed events are
+// JSON encoded.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct BlockEvent {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub attributes: Vec<EventAttribute>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct EventAttribute {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl BlockEvent {
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.key == key)
+            .and_then(|attribute| attribute.value.as_deref())
+    }
 }
 
 // A heartbeat service that periodically fetches data from the exchange
@@ -152,6 +321,7 @@ pub struct ExchangeHeartbeat {
     client: ExchangeQueryClient,
     producer: std::sync::Arc<crate::producer::BatchKafkaProducer>,
     interval_seconds: u64,
+    market_filter: Option<crate::lite_mode::LiteMarketSet>,
 }
 
 impl ExchangeHeartbeat {
@@ -161,12 +331,15 @@ impl ExchangeHeartbeat {
         interval_seconds: u64,
     ) -> Result<Self, Box<dyn Error + Send + Sync>> {
         let client = ExchangeQueryClient::connect(config).await?;
-        let producer = std::sync::Arc::new(crate::producer::BatchKafkaProducer::new(kafka_config)?);
+        let producer = std::sync::Arc::new(
+            crate::producer::BatchKafkaProducer::new(kafka_config, "query").await?,
+        );
 
         Ok(Self {
             client,
             producer,
             interval_seconds,
+            market_filter: None,
         })
     }
 
@@ -182,9 +355,16 @@ impl ExchangeHeartbeat {
             client,
             producer,
             interval_seconds,
+            market_filter: None,
         })
     }
 
+    // Skip orderbook queries for markets outside the lite mode set
+    pub fn with_market_filter(mut self, markets: crate::lite_mode::LiteMarketSet) -> Self {
+        self.market_filter = Some(markets);
+        self
+    }
+
     pub async fn start(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
         info!(
             "Starting exchange heartbeat service with interval of {}s",
@@ -212,13 +392,29 @@ impl ExchangeHeartbeat {
                 }
             };
 
-            // Fetch derivative markets
+            // Fetch derivative markets, with the expiry futures that expired
+            // recently so consumers see them settle
             match self
                 .client
                 .get_derivative_markets(Some("Active".to_string()))
                 .await
             {
-                Ok(markets) => {
+                Ok(mut markets) => {
+                    match self
+                        .client
+                        .get_derivative_markets(Some("Expired".to_string()))
+                        .await
+                    {
+                        Ok(expired) => {
+                            let now = chrono::Utc::now().timestamp();
+                            markets.extend(
+                                expired
+                                    .into_iter()
+                                    .filter(|market| recently_expired(market, now)),
+                            );
+                        }
+                        Err(e) => error!("Failed to fetch expired derivative markets: {}", e),
+                    }
                     // Handle markets data and send to Kafka using the batch_current_only
                     self.process_derivative_markets(markets, block_height)
                         .await?;
@@ -261,6 +457,13 @@ impl ExchangeHeartbeat {
 
                 for market in markets {
                     if let Some(market_data) = market.market {
+                        if self
+                            .market_filter
+                            .as_ref()
+                            .is_some_and(|filter| !filter.contains(&market_data.market_id))
+                        {
+                            continue;
+                        }
                         if let Ok(orderbook) = self
                             .client
                             .get_full_derivative_orderbook(&market_data.market_id)
@@ -277,7 +480,114 @@ impl ExchangeHeartbeat {
                         .await?;
                 }
             }
+
+            // Spot markets and their orderbooks; lite mode leaves spot out
+            if self.market_filter.is_none() {
+                match self
+                    .client
+                    .get_spot_markets(Some("Active".to_string()))
+                    .await
+                {
+                    Ok(markets) => {
+                        let mut all_orderbooks = Vec::new();
+                        for market in &markets {
+                            if let Ok(orderbook) =
+                                self.client.get_full_spot_orderbook(&market.market_id).await
+                            {
+                                all_orderbooks.push((market.market_id.clone(), orderbook));
+                            }
+                        }
+
+                        self.process_spot_markets(markets, block_height).await?;
+                        if !all_orderbooks.is_empty() {
+                            self.process_all_spot_orderbooks(all_orderbooks, block_height)
+                                .await?;
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to fetch spot markets: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn process_spot_markets(
+        &self,
+        markets: Vec<SpotMarket>,
+        block_height: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if markets.is_empty() {
+            return Ok(());
+        }
+        let length = markets.len();
+
+        let market_payloads = markets.into_iter().map(convert_spot_market).collect();
+
+        let message = crate::models::KafkaMessage {
+            message_type: crate::models::MessageType::SpotMarket,
+            block_height,
+            block_time: chrono::Utc::now().timestamp_millis() as u64,
+            payload: crate::models::KafkaPayload::SpotMarkets(market_payloads),
+        };
+
+        let results = self.producer.send_batch_current_only(vec![message]).await;
+        if let Some(Err(e)) = results.first() {
+            error!("Failed to send spot markets to Kafka: {}", e);
+        } else {
+            info!(
+                "Sent {} spot markets to Kafka as single batch at block height {}",
+                length, block_height
+            );
         }
+
+        Ok(())
+    }
+
+    async fn process_all_spot_orderbooks(
+        &self,
+        orderbooks: Vec<(String, QueryFullSpotOrderbookResponse)>,
+        block_height: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let orderbook_payloads: Vec<crate::models::FullLimitOrderbookPayload> = orderbooks
+            .into_iter()
+            .map(
+                |(market_id, orderbook)| crate::models::FullLimitOrderbookPayload {
+                    market_id,
+                    bids: orderbook
+                        .bids
+                        .into_iter()
+                        .map(convert_limit_order)
+                        .collect(),
+                    asks: orderbook
+                        .asks
+                        .into_iter()
+                        .map(convert_limit_order)
+                        .collect(),
+                    timestamp: chrono::Utc::now().timestamp_millis(),
+                },
+            )
+            .collect();
+        let length = orderbook_payloads.len();
+
+        let message = crate::models::KafkaMessage {
+            message_type: crate::models::MessageType::SpotFullOrderbook,
+            block_height,
+            block_time: chrono::Utc::now().timestamp_millis() as u64,
+            payload: crate::models::KafkaPayload::SpotFullOrderbooks(orderbook_payloads),
+        };
+
+        let results = self.producer.send_batch_current_only(vec![message]).await;
+        if let Some(Err(e)) = results.first() {
+            error!("Failed to send spot orderbooks to Kafka: {}", e);
+        } else {
+            info!(
+                "Sent {} spot orderbooks to Kafka as single batch at block height {}",
+                length, block_height
+            );
+        }
+
+        Ok(())
     }
     async fn process_all_derivative_orderbooks(
         &self,
@@ -295,12 +605,12 @@ impl ExchangeHeartbeat {
                     bids: orderbook
                         .bids
                         .into_iter()
-                        .map(|order| self.convert_limit_order(order))
+                        .map(convert_limit_order)
                         .collect(),
                     asks: orderbook
                         .asks
                         .into_iter()
-                        .map(|order| self.convert_limit_order(order))
+                        .map(convert_limit_order)
                         .collect(),
                     timestamp: chrono::Utc::now().timestamp_millis(),
                 },
@@ -337,10 +647,7 @@ impl ExchangeHeartbeat {
         let length = markets.len();
 
         // Convert all markets to payloads
-        let market_payloads = markets
-            .into_iter()
-            .map(|market| self.convert_derivative_market(market))
-            .collect();
+        let market_payloads = markets.into_iter().map(convert_derivative_market).collect();
 
         // Create a single message containing all markets
         let message = crate::models::KafkaMessage {
@@ -371,10 +678,7 @@ impl ExchangeHeartbeat {
         }
         let length = positions.len();
         // Convert all positions into a single payload
-        let position_payloads = positions
-            .into_iter()
-            .map(|position| self.convert_position(position))
-            .collect();
+        let position_payloads = positions.into_iter().map(convert_position).collect();
 
         // Create just one message containing all positions
         let message = crate::models::KafkaMessage {
@@ -406,10 +710,7 @@ impl ExchangeHeartbeat {
         }
         let length = balances.len();
         // Convert all balances into a single payload
-        let balance_payloads = balances
-            .into_iter()
-            .map(|balance| self.convert_exchange_balance(balance))
-            .collect();
+        let balance_payloads = balances.into_iter().map(convert_exchange_balance).collect();
 
         // Create one message containing all balances
         let message = crate::models::KafkaMessage {
@@ -436,28 +737,7 @@ impl ExchangeHeartbeat {
         orderbook: crate::proto::injective::exchange::v1beta1::QueryFullDerivativeOrderbookResponse,
         block_height: u64,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        // Convert to Kafka message format
-        let message = crate::models::KafkaMessage {
-            message_type: crate::models::MessageType::DerivativeFullOrderbook,
-            block_height,
-            block_time: chrono::Utc::now().timestamp_millis() as u64,
-            payload: crate::models::KafkaPayload::DerivativeFullOrderbooks(vec![
-                crate::models::FullLimitOrderbookPayload {
-                    market_id: market_id.to_string(),
-                    bids: orderbook
-                        .bids
-                        .into_iter()
-                        .map(|order| self.convert_limit_order(order))
-                        .collect(),
-                    asks: orderbook
-                        .asks
-                        .into_iter()
-                        .map(|order| self.convert_limit_order(order))
-                        .collect(),
-                    timestamp: chrono::Utc::now().timestamp_millis(),
-                },
-            ]),
-        };
+        let message = derivative_orderbook_message(market_id, orderbook, block_height);
 
         // Send to Kafka
         let results = self.producer.send_batch_current_only(vec![message]).await;
@@ -472,108 +752,175 @@ impl ExchangeHeartbeat {
 
         Ok(())
     }
+}
 
-    fn convert_derivative_market(
-        &self,
-        market: FullDerivativeMarket,
-    ) -> crate::models::DerivativeMarketPayload {
-        // Extract market details
-        let market_data = market.market.unwrap_or_default();
-
-        // Extract perpetual market state
-        let perp_state = match &market.info {
-            Some(Info::PerpetualInfo(state)) => Some(state),
-            _ => None,
-        };
+// One market's L3 orderbook as a full orderbook message
+pub fn derivative_orderbook_message(
+    market_id: &str,
+    orderbook: crate::proto::injective::exchange::v1beta1::QueryFullDerivativeOrderbookResponse,
+    block_height: u64,
+) -> crate::models::KafkaMessage {
+    crate::models::KafkaMessage {
+        message_type: crate::models::MessageType::DerivativeFullOrderbook,
+        block_height,
+        block_time: chrono::Utc::now().timestamp_millis() as u64,
+        payload: crate::models::KafkaPayload::DerivativeFullOrderbooks(vec![
+            crate::models::FullLimitOrderbookPayload {
+                market_id: market_id.to_string(),
+                bids: orderbook
+                    .bids
+                    .into_iter()
+                    .map(convert_limit_order)
+                    .collect(),
+                asks: orderbook
+                    .asks
+                    .into_iter()
+                    .map(convert_limit_order)
+                    .collect(),
+                timestamp: chrono::Utc::now().timestamp_millis(),
+            },
+        ]),
+    }
+}
 
-        // Extract market_info and funding_info separately
-        let market_info = perp_state.and_then(|state| state.market_info.as_ref());
-        let funding_info = perp_state.and_then(|state| state.funding_info.as_ref());
-
-        crate::models::DerivativeMarketPayload {
-            market_id: market_data.market_id,
-            ticker: market_data.ticker,
-            oracle_base: market_data.oracle_base,
-            oracle_quote: market_data.oracle_quote,
-            quote_denom: market_data.quote_denom,
-            maker_fee_rate: market_data.maker_fee_rate,
-            taker_fee_rate: market_data.taker_fee_rate,
-            initial_margin_ratio: market_data.initial_margin_ratio,
-            maintenance_margin_ratio: market_data.maintenance_margin_ratio,
-            is_perpetual: market_data.is_perpetual,
-            status: self.map_market_status(market_data.status),
-            mark_price: market.mark_price,
-
-            min_price_tick: market_data.min_price_tick_size,
-            min_quantity_tick: market_data.min_quantity_tick_size,
-            min_notional: market_data.min_notional,
-
-            // Get fields from market_info
-            hfr: market_info
-                .map(|info| info.hourly_funding_rate_cap.clone())
-                .unwrap_or_default(),
-            hir: market_info
-                .map(|info| info.hourly_interest_rate.clone())
-                .unwrap_or_default(),
-            funding_interval: market_info
-                .map(|info| info.funding_interval.to_string())
-                .unwrap_or_default(),
-
-            // Get fields from funding_info
-            cumulative_funding: funding_info
-                .map(|info| info.cumulative_funding.clone())
-                .unwrap_or_default(),
-            cumulative_price: funding_info
-                .map(|info| info.cumulative_price.clone())
-                .unwrap_or_default(),
-        }
+// Expired at most RECENTLY_EXPIRED_SECS before `now` (in seconds)
+fn recently_expired(market: &FullDerivativeMarket, now: i64) -> bool {
+    match &market.info {
+        Some(Info::FuturesInfo(info)) => now - info.expiration_timestamp <= RECENTLY_EXPIRED_SECS,
+        _ => false,
     }
+}
 
-    fn map_market_status(&self, status: i32) -> String {
-        match status {
-            1 => "Active".to_string(),
-            2 => "Paused".to_string(),
-            3 => "Demolished".to_string(),
-            4 => "Expired".to_string(),
-            _ => "Unknown".to_string(),
-        }
+// Conversions from query responses to the Kafka payloads, shared by the
+// heartbeat and the backfill
+pub fn convert_derivative_market(
+    market: FullDerivativeMarket,
+) -> crate::models::DerivativeMarketPayload {
+    // Extract market details
+    let market_data = market.market.unwrap_or_default();
+
+    // Extract perpetual market state
+    let perp_state = match &market.info {
+        Some(Info::PerpetualInfo(state)) => Some(state),
+        _ => None,
+    };
+
+    // Expiry futures carry their expiry and, once settled, the settlement price
+    let futures_info = match &market.info {
+        Some(Info::FuturesInfo(info)) => Some(info),
+        _ => None,
+    };
+
+    // Extract market_info and funding_info separately
+    let market_info = perp_state.and_then(|state| state.market_info.as_ref());
+    let funding_info = perp_state.and_then(|state| state.funding_info.as_ref());
+
+    crate::models::DerivativeMarketPayload {
+        market_id: market_data.market_id,
+        ticker: market_data.ticker,
+        oracle_base: market_data.oracle_base,
+        oracle_quote: market_data.oracle_quote,
+        quote_denom: market_data.quote_denom,
+        maker_fee_rate: market_data.maker_fee_rate,
+        taker_fee_rate: market_data.taker_fee_rate,
+        initial_margin_ratio: market_data.initial_margin_ratio,
+        maintenance_margin_ratio: market_data.maintenance_margin_ratio,
+        is_perpetual: market_data.is_perpetual,
+        status: map_market_status(market_data.status),
+        mark_price: market.mark_price,
+
+        min_price_tick: market_data.min_price_tick_size,
+        min_quantity_tick: market_data.min_quantity_tick_size,
+        min_notional: market_data.min_notional,
+
+        // Get fields from market_info
+        hfr: market_info
+            .map(|info| info.hourly_funding_rate_cap.clone())
+            .unwrap_or_default(),
+        hir: market_info
+            .map(|info| info.hourly_interest_rate.clone())
+            .unwrap_or_default(),
+        funding_interval: market_info
+            .map(|info| info.funding_interval.to_string())
+            .unwrap_or_default(),
+
+        // Get fields from funding_info
+        cumulative_funding: funding_info
+            .map(|info| info.cumulative_funding.clone())
+            .unwrap_or_default(),
+        cumulative_price: funding_info
+            .map(|info| info.cumulative_price.clone())
+            .unwrap_or_default(),
+
+        // Get fields from futures_info
+        expiration_timestamp: futures_info
+            .map(|info| info.expiration_timestamp.to_string())
+            .unwrap_or_default(),
+        settlement_price: futures_info
+            .map(|info| info.settlement_price.clone())
+            .unwrap_or_default(),
     }
+}
 
-    fn convert_position(&self, position: DerivativePosition) -> crate::models::PositionPayload {
-        let position_data = position.position.unwrap_or_default();
-        crate::models::PositionPayload {
-            market_id: position.market_id,
-            subaccount_id: position.subaccount_id,
-            is_long: position_data.is_long,
-            quantity: position_data.quantity,
-            entry_price: position_data.entry_price,
-            margin: position_data.margin,
-            cumulative_funding_entry: position_data.cumulative_funding_entry,
-        }
+pub fn convert_spot_market(market: SpotMarket) -> crate::models::SpotMarketPayload {
+    crate::models::SpotMarketPayload {
+        status: map_market_status(market.status),
+        market_id: market.market_id,
+        ticker: market.ticker,
+        base_denom: market.base_denom,
+        quote_denom: market.quote_denom,
+        maker_fee_rate: market.maker_fee_rate,
+        taker_fee_rate: market.taker_fee_rate,
+        min_price_tick: market.min_price_tick_size,
+        min_quantity_tick: market.min_quantity_tick_size,
+        min_notional: market.min_notional,
+        base_decimals: market.base_decimals,
+        quote_decimals: market.quote_decimals,
     }
+}
 
-    fn convert_exchange_balance(
-        &self,
-        balance: crate::proto::injective::exchange::v1beta1::Balance,
-    ) -> crate::models::ExchangeBalancePayload {
-        let deposit = balance.deposits.unwrap_or_default();
-        crate::models::ExchangeBalancePayload {
-            subaccount_id: balance.subaccount_id,
-            denom: balance.denom,
-            available_balance: deposit.available_balance,
-            total_balance: deposit.total_balance,
-        }
+pub fn map_market_status(status: i32) -> String {
+    match status {
+        1 => "Active".to_string(),
+        2 => "Paused".to_string(),
+        3 => "Demolished".to_string(),
+        4 => "Expired".to_string(),
+        _ => "Unknown".to_string(),
     }
+}
 
-    fn convert_limit_order(
-        &self,
-        order: crate::proto::injective::exchange::v1beta1::TrimmedLimitOrder,
-    ) -> crate::models::TrimmedLimitOrderPayload {
-        crate::models::TrimmedLimitOrderPayload {
-            price: order.price,
-            quantity: order.quantity,
-            order_hash: order.order_hash,
-            subaccount_id: order.subaccount_id,
-        }
+pub fn convert_position(position: DerivativePosition) -> crate::models::PositionPayload {
+    let position_data = position.position.unwrap_or_default();
+    crate::models::PositionPayload {
+        market_id: position.market_id,
+        subaccount_id: position.subaccount_id,
+        is_long: position_data.is_long,
+        quantity: position_data.quantity,
+        entry_price: position_data.entry_price,
+        margin: position_data.margin,
+        cumulative_funding_entry: position_data.cumulative_funding_entry,
+    }
+}
+
+pub fn convert_exchange_balance(
+    balance: crate::proto::injective::exchange::v1beta1::Balance,
+) -> crate::models::ExchangeBalancePayload {
+    let deposit = balance.deposits.unwrap_or_default();
+    crate::models::ExchangeBalancePayload {
+        subaccount_id: balance.subaccount_id,
+        denom: balance.denom,
+        available_balance: deposit.available_balance,
+        total_balance: deposit.total_balance,
+    }
+}
+
+pub fn convert_limit_order(
+    order: crate::proto::injective::exchange::v1beta1::TrimmedLimitOrder,
+) -> crate::models::TrimmedLimitOrderPayload {
+    crate::models::TrimmedLimitOrderPayload {
+        price: order.price,
+        quantity: order.quantity,
+        order_hash: order.order_hash,
+        subaccount_id: order.subaccount_id,
     }
 }
@@ -0,0 +1,305 @@
+use crate::config::{QueryServerConfig, RedisKeySchema};
+use crate::proto::injective_indexer::v1::indexer_query_server::{IndexerQuery, IndexerQueryServer};
+use crate::proto::injective_indexer::v1::{
+    GetMarketRequest, GetMarketResponse, GetOrderbookSnapshotRequest, GetOrderbookSnapshotResponse,
+    GetPositionRequest, GetPositionResponse, LiquidationAlert, Market, Position, PriceLevel,
+    StreamLiquidationsRequest,
+};
+use futures::StreamExt;
+use log::{info, warn};
+use redis::aio::MultiplexedConnection;
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+// The IndexerQuery gRPC service: the state the consumers keep in Redis, for
+// clients that would rather not read the key layout themselves. Keys follow
+// layout version 2 of injective-consumer's redis_keys module, namespaced by
+// the same key schema.
+
+// Block times above this are milliseconds, below it seconds
+const MILLIS_THRESHOLD: i64 = 10_000_000_000;
+
+type LiquidationStream =
+    Pin<Box<dyn tokio_stream::Stream<Item = Result<LiquidationAlert, Status>> + Send>>;
+
+pub struct QueryService {
+    client: redis::Client,
+    connection: MultiplexedConnection,
+    keys: RedisKeySchema,
+    stream_buffer: usize,
+}
+
+impl QueryService {
+    pub async fn new(
+        config: &QueryServerConfig,
+        keys: &RedisKeySchema,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let client = redis::Client::open(config.redis_url()?)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+        Ok(QueryService {
+            client,
+            connection,
+            keys: keys.clone(),
+            stream_buffer: config.stream_buffer.max(1),
+        })
+    }
+
+    async fn hgetall(&self, key: &str) -> Result<HashMap<String, String>, Status> {
+        let mut conn = self.connection.clone();
+        redis::cmd("HGETALL")
+            .arg(key)
+            .query_async(&mut conn)
+            .await
+            .map_err(internal)
+    }
+}
+
+// Serve IndexerQuery on the configured address until the shutdown flag flips
+pub async fn serve(
+    config: &QueryServerConfig,
+    keys: &RedisKeySchema,
+    mut shutdown_rx: watch::Receiver<bool>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let addr: SocketAddr = config.listen_addr.parse()?;
+    let service = QueryService::new(config, keys).await?;
+
+    info!("Query server listening on {}", addr);
+    Server::builder()
+        .add_service(IndexerQueryServer::new(service))
+        .serve_with_shutdown(addr, async move {
+            let _ = shutdown_rx.wait_for(|stop| *stop).await;
+        })
+        .await?;
+    info!("Query server stopped");
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl IndexerQuery for QueryService {
+    type StreamLiquidationsStream = LiquidationStream;
+
+    async fn get_market(
+        &self,
+        request: Request<GetMarketRequest>,
+    ) -> Result<Response<GetMarketResponse>, Status> {
+        let market_id = request.into_inner().market_id;
+        let fields = self
+            .hgetall(&self.keys.key(&["market", "derivative", &market_id]))
+            .await?;
+        if fields.is_empty() {
+            return Err(Status::not_found(format!("market {} not found", market_id)));
+        }
+
+        Ok(Response::new(GetMarketResponse {
+            market: Some(Market {
+                ticker: fields.get("ticker").cloned().unwrap_or_default(),
+                mark_price: parse_field(&fields, "mark_price"),
+                maintenance_margin_ratio: parse_field(&fields, "maintenance_margin_ratio"),
+                cumulative_funding: parse_field(&fields, "cumulative_funding"),
+                status: fields.get("status").cloned().unwrap_or_default(),
+                block_height: parse_field(&fields, "block_height"),
+                timestamp: timestamp_millis(&fields),
+                market_id,
+            }),
+        }))
+    }
+
+    async fn get_position(
+        &self,
+        request: Request<GetPositionRequest>,
+    ) -> Result<Response<GetPositionResponse>, Status> {
+        let GetPositionRequest {
+            market_id,
+            subaccount_id,
+        } = request.into_inner();
+        let fields = self
+            .hgetall(&self.keys.key(&["position", &market_id, &subaccount_id]))
+            .await?;
+        if fields.is_empty() {
+            return Err(Status::not_found(format!(
+                "no position of {} in market {}",
+                subaccount_id, market_id
+            )));
+        }
+
+        Ok(Response::new(GetPositionResponse {
+            position: Some(Position {
+                is_long: parse_field(&fields, "is_long"),
+                quantity: parse_field(&fields, "quantity"),
+                entry_price: parse_field(&fields, "entry_price"),
+                margin: parse_field(&fields, "margin"),
+                cumulative_funding_entry: parse_field(&fields, "cumulative_funding_entry"),
+                liquidation_price: parse_field(&fields, "liquidation_price"),
+                is_liquidatable: parse_field(&fields, "is_liquidatable"),
+                block_height: parse_field(&fields, "block_height"),
+                timestamp: timestamp_millis(&fields),
+                market_id,
+                subaccount_id,
+            }),
+        }))
+    }
+
+    async fn stream_liquidations(
+        &self,
+        request: Request<StreamLiquidationsRequest>,
+    ) -> Result<Response<Self::StreamLiquidationsStream>, Status> {
+        let market_ids = request.into_inner().market_ids;
+        let mut pubsub = self.client.get_async_pubsub().await.map_err(internal)?;
+        pubsub
+            // Plain JSON alerts, published alongside the pub/sub events
+            .subscribe(self.keys.key(&["liquidation_alerts"]))
+            .await
+            .map_err(internal)?;
+
+        // Slow clients miss alerts rather than holding up the subscription
+        let (tx, rx) = mpsc::channel(self.stream_buffer);
+        tokio::spawn(async move {
+            let mut messages = pubsub.into_on_message();
+            loop {
+                // A quiet market would otherwise keep the subscription open
+                // long after its client went away
+                let message = tokio::select! {
+                    _ = tx.closed() => break,
+                    message = messages.next() => match message {
+                        Some(message) => message,
+                        None => break,
+                    },
+                };
+                let Some(alert) = message
+                    .get_payload::<String>()
+                    .ok()
+                    .and_then(|payload| parse_alert(&payload))
+                else {
+                    warn!("Skipping malformed liquidation alert");
+                    continue;
+                };
+                if !market_ids.is_empty() && !market_ids.contains(&alert.market_id) {
+                    continue;
+                }
+                match tx.try_send(Ok(alert)) {
+                    Ok(()) => {}
+                    Err(mpsc::error::TrySendError::Full(_)) => {
+                        warn!("Liquidation stream client is behind, dropping an alert");
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => break,
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn get_orderbook_snapshot(
+        &self,
+        request: Request<GetOrderbookSnapshotRequest>,
+    ) -> Result<Response<GetOrderbookSnapshotResponse>, Status> {
+        let GetOrderbookSnapshotRequest { market_id, depth } = request.into_inner();
+        let top = self
+            .hgetall(&self.keys.key(&["orderbook", "derivative", &market_id]))
+            .await?;
+        let levels = self
+            .hgetall(&self.keys.key(&["orderbook", "depth", &market_id]))
+            .await?;
+        if top.is_empty() && levels.is_empty() {
+            return Err(Status::not_found(format!(
+                "no orderbook stored for market {}",
+                market_id
+            )));
+        }
+
+        // Aggregated levels when the consumer keeps them, the top of book otherwise
+        let (mut bids, mut asks) =
+            match (parse_levels(&levels, "bids"), parse_levels(&levels, "asks")) {
+                (Some(bids), Some(asks)) => (bids, asks),
+                _ => (
+                    top_level(&top, "best_bid", "best_bid_quantity"),
+                    top_level(&top, "best_ask", "best_ask_quantity"),
+                ),
+            };
+        if depth > 0 {
+            bids.truncate(depth as usize);
+            asks.truncate(depth as usize);
+        }
+
+        let source = if top.is_empty() { &levels } else { &top };
+        Ok(Response::new(GetOrderbookSnapshotResponse {
+            market_id,
+            bids,
+            asks,
+            block_height: parse_field(source, "block_height"),
+            timestamp: timestamp_millis(&top),
+        }))
+    }
+}
+
+fn internal(e: redis::RedisError) -> Status {
+    Status::internal(format!("Redis error: {}", e))
+}
+
+fn parse_field<T: std::str::FromStr + Default>(fields: &HashMap<String, String>, name: &str) -> T {
+    fields
+        .get(name)
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_default()
+}
+
+fn timestamp_millis(fields: &HashMap<String, String>) -> i64 {
+    let timestamp: i64 = parse_field(fields, "timestamp");
+    if timestamp > MILLIS_THRESHOLD {
+        timestamp
+    } else {
+        timestamp * 1_000
+    }
+}
+
+// One side of the stored L2 book, a JSON list of {price, quantity}
+fn parse_levels(fields: &HashMap<String, String>, side: &str) -> Option<Vec<PriceLevel>> {
+    let levels: Vec<serde_json::Value> = serde_json::from_str(fields.get(side)?).ok()?;
+    Some(
+        levels
+            .iter()
+            .map(|level| PriceLevel {
+                price: number(&level["price"]),
+                quantity: number(&level["quantity"]),
+            })
+            .collect(),
+    )
+}
+
+fn top_level(fields: &HashMap<String, String>, price: &str, quantity: &str) -> Vec<PriceLevel> {
+    match fields.get(price).and_then(|v| v.parse().ok()) {
+        Some(price) => vec![PriceLevel {
+            price,
+            quantity: parse_field(fields, quantity),
+        }],
+        None => Vec::new(),
+    }
+}
+
+// Alerts carry prices as numbers and sizes as strings
+fn parse_alert(payload: &str) -> Option<LiquidationAlert> {
+    let alert: serde_json::Value = serde_json::from_str(payload).ok()?;
+    Some(LiquidationAlert {
+        market_id: alert["market_id"].as_str()?.to_string(),
+        subaccount_id: alert["subaccount_id"].as_str()?.to_string(),
+        is_long: alert["is_long"].as_bool().unwrap_or_default(),
+        quantity: number(&alert["quantity"]),
+        entry_price: number(&alert["entry_price"]),
+        margin: number(&alert["margin"]),
+        liquidation_price: number(&alert["liquidation_price"]),
+        mark_price: number(&alert["mark_price"]),
+    })
+}
+
+fn number(value: &serde_json::Value) -> f64 {
+    match value {
+        serde_json::Value::String(s) => s.parse().unwrap_or_default(),
+        other => other.as_f64().unwrap_or_default(),
+    }
+}
@@ -0,0 +1,118 @@
+use crate::clock::{self, Clock};
+use crate::config::Config;
+use crate::producer::BatchKafkaProducer;
+use crate::query_client::{self, ExchangeQueryClient};
+use futures::StreamExt;
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Consumers that find a gap in a market's streamed orderbook deltas publish
+// {"market_id":..,"expected":..,"received":..} here. The listener answers
+// with the market's L3 orderbook as a full orderbook message, the same one
+// the heartbeat sends, without waiting for the next heartbeat. The channel is
+// namespaced by the key schema, like the consumers' keys.
+pub const RESYNC_CHANNEL: &str = "orderbook_resync";
+
+pub struct ResyncListener {
+    client: ExchangeQueryClient,
+    producer: Arc<BatchKafkaProducer>,
+    redis: redis::Client,
+    channel: String,
+    min_interval: Duration,
+    // When each market was last resynced, in clock millis
+    last_resync: HashMap<String, i64>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ResyncListener {
+    pub async fn new(
+        config: &Config,
+        producer: Arc<BatchKafkaProducer>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(ResyncListener {
+            client: ExchangeQueryClient::connect(&config.grpc).await?,
+            producer,
+            redis: redis::Client::open(config.resync.redis_url()?)?,
+            channel: config.redis_keys.key(&[RESYNC_CHANNEL]),
+            min_interval: Duration::from_secs(config.resync.min_interval_secs),
+            last_resync: HashMap::new(),
+            clock: clock::system(),
+        })
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Answer requests until the subscription ends
+    pub async fn run(mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut pubsub = self.redis.get_async_pubsub().await?;
+        pubsub.subscribe(&self.channel).await?;
+        info!(
+            "Listening for orderbook resync requests on {}",
+            self.channel
+        );
+
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let request = message
+                .get_payload::<String>()
+                .ok()
+                .and_then(|payload| serde_json::from_str::<serde_json::Value>(&payload).ok());
+            let Some(market_id) = request
+                .as_ref()
+                .and_then(|request| request["market_id"].as_str())
+            else {
+                warn!("Skipping malformed orderbook resync request");
+                continue;
+            };
+            self.resync(market_id).await;
+        }
+
+        Err("orderbook resync subscription closed".into())
+    }
+
+    async fn resync(&mut self, market_id: &str) {
+        // Several consumers usually report the same gap
+        let now = self.clock.now_millis();
+        let min_interval = self.min_interval.as_millis() as i64;
+        if self
+            .last_resync
+            .get(market_id)
+            .is_some_and(|last| now - *last < min_interval)
+        {
+            return;
+        }
+        self.last_resync.insert(market_id.to_string(), now);
+
+        let orderbook = match self.client.get_full_derivative_orderbook(market_id).await {
+            Ok(orderbook) => orderbook,
+            Err(e) => {
+                error!(
+                    "Failed to fetch orderbook of {} for a resync: {}",
+                    market_id, e
+                );
+                return;
+            }
+        };
+        let block_height = self.producer.get_latest_block();
+        let message =
+            query_client::derivative_orderbook_message(market_id, orderbook, block_height);
+        match self
+            .producer
+            .send_batch_current_only(vec![message])
+            .await
+            .first()
+        {
+            Some(Err(e)) => error!("Failed to send resynced orderbook of {}: {}", market_id, e),
+            _ => info!(
+                "Resynced orderbook of {} at block height {}",
+                market_id, block_height
+            ),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Deserializer};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// Credentials are looked up by name, in order: the NAME environment variable,
+// a file named by NAME_FILE, then a file called NAME in SECRETS_DIR (such as
+// /run/secrets). Values read from files lose their trailing newline.
+
+pub fn load(name: &str) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    if let Ok(value) = env::var(name) {
+        return Ok(Some(value));
+    }
+
+    if let Ok(path) = env::var(format!("{}_FILE", name)) {
+        return read_secret_file(Path::new(&path)).map(Some);
+    }
+
+    if let Ok(dir) = env::var("SECRETS_DIR") {
+        let path = Path::new(&dir).join(name);
+        if path.is_file() {
+            return read_secret_file(&path).map(Some);
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_secret_file(path: &Path) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read secret file {}: {}", path.display(), e))?;
+    Ok(contents.trim_end_matches(['\r', '\n']).to_string())
+}
+
+// Fill the password of a Redis URL that does not carry one
+pub fn with_redis_password(
+    redis_url: &str,
+    password: &Secret,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let mut url = url::Url::parse(redis_url)?;
+    if url.password().is_none() {
+        url.set_password(Some(password.expose()))
+            .map_err(|_| format!("Cannot set a password on {}", redis_url))?;
+    }
+    Ok(url.to_string())
+}
+
+// A URL cut down to its scheme, host and port, for showing a resolved
+// configuration. Credentials, paths and queries can carry a password or a
+// provider API key, so whatever is cut is marked as redacted.
+pub fn mask_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, tail) = rest.split_at(end);
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    if host.len() < authority.len() || !tail.trim_start_matches('/').is_empty() {
+        format!("{}://{}/[redacted]", scheme, host)
+    } else {
+        format!("{}://{}", scheme, host)
+    }
+}
+
+// A credential that never shows up in logs or serialized configs
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Secret)
+    }
+}
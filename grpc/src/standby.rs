@@ -0,0 +1,168 @@
+use crate::config::StandbyConfig;
+use crate::metrics;
+use log::{info, warn};
+use redis::AsyncCommands;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+// Extends the lease only while this instance still holds it
+const RENEW_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return 0
+";
+
+// Deletes the lease only while this instance still holds it
+const RELEASE_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+// The publishing lease of a warm standby pair: a Redis key holding the id of
+// the leader, set with NX and a TTL and renewed well before it expires.
+// Instances that don't hold it keep trying to take it, so a standby takes
+// over once the leader stops renewing. A leader that can't reach Redis steps
+// down when its lease would have expired, so two instances never both
+// believe they hold it.
+pub struct LeaderLease {
+    connection: redis::aio::MultiplexedConnection,
+    key: String,
+    id: String,
+    ttl: Duration,
+    renew_every: Duration,
+    leader: watch::Sender<bool>,
+}
+
+impl LeaderLease {
+    pub async fn new(config: &StandbyConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if config.renew_interval_ms >= config.lease_ttl_ms {
+            return Err("standby renew_interval_ms must be below lease_ttl_ms".into());
+        }
+        let client = redis::Client::open(config.redis_url()?)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+        Ok(LeaderLease {
+            connection,
+            key: config.lease_key.clone(),
+            id: config.instance_id(),
+            ttl: Duration::from_millis(config.lease_ttl_ms),
+            renew_every: Duration::from_millis(config.renew_interval_ms),
+            leader: watch::channel(false).0,
+        })
+    }
+
+    /// True while this instance holds the lease
+    pub fn leadership(&self) -> watch::Receiver<bool> {
+        self.leader.subscribe()
+    }
+
+    /// The instance holding the lease, if any
+    pub async fn holder(&self) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+        let mut conn = self.connection.clone();
+        Ok(conn.get(&self.key).await?)
+    }
+
+    // Take or renew the lease until `stop` flips to true, then release it
+    pub async fn run(self, mut stop: watch::Receiver<bool>) {
+        info!(
+            "Standby mode: competing for lease {} as {}",
+            self.key, self.id
+        );
+        // When the lease was last taken or renewed, by the time the request
+        // was sent rather than answered, so this side expires it first
+        let mut renewed = Instant::now();
+
+        loop {
+            let attempt = Instant::now();
+            if *self.leader.borrow() {
+                // A renewal that hangs counts as failed, so the expiry check
+                // below still runs before the next one is due
+                let renewal = tokio::time::timeout(self.renew_every, self.renew())
+                    .await
+                    .unwrap_or_else(|_| Err((redis::ErrorKind::IoError, "timed out").into()));
+                match renewal {
+                    Ok(true) => renewed = attempt,
+                    Ok(false) => self.step_down("the lease was taken over"),
+                    Err(e) => {
+                        warn!("Failed to renew lease {}: {}", self.key, e);
+                        // The next attempt is a full interval away, so step down
+                        // now if the lease would expire before it
+                        if renewed.elapsed() + self.renew_every >= self.ttl {
+                            self.step_down("the lease is about to expire");
+                        }
+                    }
+                }
+            } else {
+                match self.try_acquire().await {
+                    Ok(true) => {
+                        renewed = attempt;
+                        info!("Took lease {} as {}, publishing", self.key, self.id);
+                        self.leader.send_replace(true);
+                        metrics::producer().leader.set(1);
+                    }
+                    Ok(false) => {}
+                    Err(e) => warn!("Failed to take lease {}: {}", self.key, e),
+                }
+            }
+
+            tokio::select! {
+                _ = stop.wait_for(|stop| *stop) => break,
+                _ = tokio::time::sleep(self.renew_every) => {}
+            }
+        }
+
+        if *self.leader.borrow() {
+            self.leader.send_replace(false);
+            metrics::producer().leader.set(0);
+            // Hand over at once instead of leaving the standby to wait out the TTL
+            if let Err(e) = self.release().await {
+                warn!("Failed to release lease {}: {}", self.key, e);
+            } else {
+                info!("Released lease {}", self.key);
+            }
+        }
+    }
+
+    fn step_down(&self, reason: &str) {
+        warn!("Lost lease {} ({}), standing by", self.key, reason);
+        self.leader.send_replace(false);
+        metrics::producer().leader.set(0);
+    }
+
+    async fn try_acquire(&self) -> redis::RedisResult<bool> {
+        let mut conn = self.connection.clone();
+        let taken: Option<String> = redis::cmd("SET")
+            .arg(&self.key)
+            .arg(&self.id)
+            .arg("NX")
+            .arg("PX")
+            .arg(self.ttl.as_millis() as u64)
+            .query_async(&mut conn)
+            .await?;
+        Ok(taken.is_some())
+    }
+
+    async fn renew(&self) -> redis::RedisResult<bool> {
+        let mut conn = self.connection.clone();
+        let renewed: i64 = redis::Script::new(RENEW_SCRIPT)
+            .key(&self.key)
+            .arg(&self.id)
+            .arg(self.ttl.as_millis() as u64)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(renewed == 1)
+    }
+
+    async fn release(&self) -> redis::RedisResult<()> {
+        let mut conn = self.connection.clone();
+        redis::Script::new(RELEASE_SCRIPT)
+            .key(&self.key)
+            .arg(&self.id)
+            .invoke_async::<i64>(&mut conn)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use crate::config::KafkaConfig;
+use log::{info, warn};
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, ResourceSpecifier, TopicReplication};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::RDKafkaErrorCode;
+use std::error::Error;
+use std::time::Duration;
+
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Creates the orderbook topics with a retention of their own, so orderbook
+// traffic can expire long before trades and positions do. Topics that
+// already exist are left alone; a retention other than the configured one is
+// only reported, as changing it is up to whoever runs the cluster.
+pub async fn ensure_orderbook_topics(
+    config: &KafkaConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let settings = &config.orderbook_retention;
+    let Some(retention_ms) = settings.retention_ms else {
+        return Ok(());
+    };
+    let topics: Vec<&str> = [&config.topics.orderbooks, &config.topics.orderbooks_full]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    if topics.is_empty() {
+        warn!("Orderbook retention is set but orderbooks have no topic of their own; ignoring it");
+        return Ok(());
+    }
+
+    let mut client = ClientConfig::new();
+    config.security.apply(&mut client);
+    let admin: AdminClient<DefaultClientContext> = client
+        .set("bootstrap.servers", config.brokers.join(","))
+        .set("client.id", &config.client_id)
+        .create()?;
+    let options = AdminOptions::new().operation_timeout(Some(ADMIN_TIMEOUT));
+
+    // -1 leaves partitions and replication to the broker defaults
+    let retention = retention_ms.to_string();
+    let new_topics: Vec<NewTopic> = topics
+        .iter()
+        .map(|topic| {
+            NewTopic::new(
+                topic,
+                settings.partitions.unwrap_or(-1),
+                TopicReplication::Fixed(settings.replication_factor.unwrap_or(-1)),
+            )
+            .set("retention.ms", &retention)
+        })
+        .collect();
+
+    for result in admin.create_topics(&new_topics, &options).await? {
+        match result {
+            Ok(topic) => info!("Created topic {} with retention.ms={}", topic, retention),
+            Err((topic, RDKafkaErrorCode::TopicAlreadyExists)) => {
+                check_retention(&admin, &options, &topic, &retention).await?
+            }
+            Err((topic, code)) => {
+                return Err(format!("failed to create topic {}: {}", topic, code).into())
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn check_retention(
+    admin: &AdminClient<DefaultClientContext>,
+    options: &AdminOptions,
+    topic: &str,
+    retention: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let resource = ResourceSpecifier::Topic(topic);
+    for result in admin.describe_configs([&resource], options).await? {
+        let config =
+            result.map_err(|code| format!("failed to describe topic {}: {}", topic, code))?;
+        let current = config
+            .get("retention.ms")
+            .and_then(|entry| entry.value.as_deref());
+        if current != Some(retention) {
+            warn!(
+                "Topic {} has retention.ms={} rather than the configured {}",
+                topic,
+                current.unwrap_or("unset"),
+                retention
+            );
+        }
+    }
+    Ok(())
+}
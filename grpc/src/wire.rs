@@ -0,0 +1,209 @@
+use crate::config::SerializationFormat;
+use crate::models::{
+    BankBalancePayload, DerivativeMarketPayload, DerivativeOrderPayload, DerivativeTradePayload,
+    ExchangeBalancePayload, FullLimitOrderbookPayload, KafkaMessage, KafkaPayload,
+    OraclePricePayload, OrderbookPayload, PositionPayload, SpotMarketPayload, SpotOrderPayload,
+    SpotTradePayload, SubaccountDepositPayload,
+};
+use flatbuffers::{FlatBufferBuilder, VOffsetT};
+use prost::Message;
+
+// Binary encodings of KafkaMessage. The consumer's wire module decodes them;
+// the schemas are injective-consumer/src/wire/kafka_message.proto and
+// injective-consumer/src/flatbuf/kafka_message.fbs.
+
+// Protobuf has no repeated oneof fields, so each payload list is wrapped
+macro_rules! payload_lists {
+    ($($name:ident($item:ty);)*) => {
+        $(
+            #[derive(Clone, prost::Message)]
+            pub struct $name {
+                #[prost(message, repeated, tag = "1")]
+                pub items: Vec<$item>,
+            }
+        )*
+    };
+}
+
+payload_lists! {
+    BankBalanceList(BankBalancePayload);
+    SubaccountDepositList(SubaccountDepositPayload);
+    OrderbookList(OrderbookPayload);
+    PositionList(PositionPayload);
+    OraclePriceList(OraclePricePayload);
+    SpotTradeList(SpotTradePayload);
+    DerivativeTradeList(DerivativeTradePayload);
+    SpotOrderList(SpotOrderPayload);
+    DerivativeOrderList(DerivativeOrderPayload);
+    DerivativeMarketList(DerivativeMarketPayload);
+    ExchangeBalanceList(ExchangeBalancePayload);
+    FullOrderbookList(FullLimitOrderbookPayload);
+    SpotMarketList(SpotMarketPayload);
+}
+
+#[derive(Clone, prost::Oneof)]
+pub enum Kind {
+    #[prost(message, tag = "1")]
+    BankBalances(BankBalanceList),
+    #[prost(message, tag = "2")]
+    SubaccountDeposits(SubaccountDepositList),
+    #[prost(message, tag = "3")]
+    SpotOrderbooks(OrderbookList),
+    #[prost(message, tag = "4")]
+    DerivativeOrderbooks(OrderbookList),
+    #[prost(message, tag = "5")]
+    StreamPositions(PositionList),
+    #[prost(message, tag = "6")]
+    OraclePrices(OraclePriceList),
+    #[prost(message, tag = "7")]
+    SpotTrades(SpotTradeList),
+    #[prost(message, tag = "8")]
+    DerivativeTrades(DerivativeTradeList),
+    #[prost(message, tag = "9")]
+    SpotOrders(SpotOrderList),
+    #[prost(message, tag = "10")]
+    DerivativeOrders(DerivativeOrderList),
+    #[prost(message, tag = "11")]
+    DerivativeMarkets(DerivativeMarketList),
+    #[prost(message, tag = "12")]
+    ExchangePositions(PositionList),
+    #[prost(message, tag = "13")]
+    ExchangeBalances(ExchangeBalanceList),
+    #[prost(message, tag = "14")]
+    DerivativeFullOrderbooks(FullOrderbookList),
+    #[prost(message, tag = "15")]
+    SpotMarkets(SpotMarketList),
+    #[prost(message, tag = "16")]
+    SpotFullOrderbooks(FullOrderbookList),
+}
+
+#[derive(Clone, prost::Message)]
+pub struct WirePayload {
+    #[prost(
+        oneof = "Kind",
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16"
+    )]
+    pub kind: Option<Kind>,
+}
+
+#[derive(Clone, prost::Message)]
+pub struct WireMessage {
+    #[prost(string, tag = "1")]
+    pub message_type: String,
+    #[prost(uint64, tag = "2")]
+    pub block_height: u64,
+    #[prost(uint64, tag = "3")]
+    pub block_time: u64,
+    #[prost(message, optional, tag = "4")]
+    pub payload: Option<WirePayload>,
+}
+
+impl From<&KafkaPayload> for WirePayload {
+    fn from(payload: &KafkaPayload) -> Self {
+        let kind = match payload {
+            KafkaPayload::StreamBankBalances(items) => Kind::BankBalances(BankBalanceList {
+                items: items.clone(),
+            }),
+            KafkaPayload::StreamSubaccountDeposits(items) => {
+                Kind::SubaccountDeposits(SubaccountDepositList {
+                    items: items.clone(),
+                })
+            }
+            KafkaPayload::StreamSpotOrderbooks(items) => Kind::SpotOrderbooks(OrderbookList {
+                items: items.clone(),
+            }),
+            KafkaPayload::StreamDerivativeOrderbooks(items) => {
+                Kind::DerivativeOrderbooks(OrderbookList {
+                    items: items.clone(),
+                })
+            }
+            KafkaPayload::StreamPositions(items) => Kind::StreamPositions(PositionList {
+                items: items.clone(),
+            }),
+            KafkaPayload::StreamOraclePrices(items) => Kind::OraclePrices(OraclePriceList {
+                items: items.clone(),
+            }),
+            KafkaPayload::SpotTrades(items) => Kind::SpotTrades(SpotTradeList {
+                items: items.clone(),
+            }),
+            KafkaPayload::DerivativeTrades(items) => Kind::DerivativeTrades(DerivativeTradeList {
+                items: items.clone(),
+            }),
+            KafkaPayload::SpotOrders(items) => Kind::SpotOrders(SpotOrderList {
+                items: items.clone(),
+            }),
+            KafkaPayload::DerivativeOrders(items) => Kind::DerivativeOrders(DerivativeOrderList {
+                items: items.clone(),
+            }),
+            KafkaPayload::DerivativeMarkets(items) => {
+                Kind::DerivativeMarkets(DerivativeMarketList {
+                    items: items.clone(),
+                })
+            }
+            KafkaPayload::ExchangePositions(items) => Kind::ExchangePositions(PositionList {
+                items: items.clone(),
+            }),
+            KafkaPayload::ExchangeBalances(items) => Kind::ExchangeBalances(ExchangeBalanceList {
+                items: items.clone(),
+            }),
+            KafkaPayload::DerivativeFullOrderbooks(items) => {
+                Kind::DerivativeFullOrderbooks(FullOrderbookList {
+                    items: items.clone(),
+                })
+            }
+            KafkaPayload::SpotMarkets(items) => Kind::SpotMarkets(SpotMarketList {
+                items: items.clone(),
+            }),
+            KafkaPayload::SpotFullOrderbooks(items) => {
+                Kind::SpotFullOrderbooks(FullOrderbookList {
+                    items: items.clone(),
+                })
+            }
+        };
+        WirePayload { kind: Some(kind) }
+    }
+}
+
+// Slots of the flatbuffers KafkaMessage table, in schema order
+const VT_MESSAGE_TYPE: VOffsetT = 4;
+const VT_BLOCK_HEIGHT: VOffsetT = 6;
+const VT_BLOCK_TIME: VOffsetT = 8;
+const VT_PAYLOAD: VOffsetT = 10;
+
+// Encode a message value in the given format. Only JSON encoding can fail.
+pub fn encode(
+    message: &KafkaMessage,
+    format: SerializationFormat,
+) -> Result<Vec<u8>, serde_json::Error> {
+    match format {
+        SerializationFormat::Json => serde_json::to_vec(message),
+        SerializationFormat::Protobuf => Ok(WireMessage {
+            message_type: message.message_type.as_str().to_string(),
+            block_height: message.block_height,
+            block_time: message.block_time,
+            payload: Some(WirePayload::from(&message.payload)),
+        }
+        .encode_to_vec()),
+        SerializationFormat::Flatbuffers => Ok(encode_flatbuffers(message)),
+    }
+}
+
+// The envelope is built with the raw builder API, in the layout flatc would
+// generate, so the build needs no flatc
+fn encode_flatbuffers(message: &KafkaMessage) -> Vec<u8> {
+    let payload_bytes = WirePayload::from(&message.payload).encode_to_vec();
+
+    let mut builder = FlatBufferBuilder::with_capacity(payload_bytes.len() + 64);
+    let message_type = builder.create_string(message.message_type.as_str());
+    let payload = builder.create_vector(&payload_bytes[..]);
+
+    let table = builder.start_table();
+    builder.push_slot::<u64>(VT_BLOCK_HEIGHT, message.block_height, 0);
+    builder.push_slot::<u64>(VT_BLOCK_TIME, message.block_time, 0);
+    builder.push_slot_always(VT_MESSAGE_TYPE, message_type);
+    builder.push_slot_always(VT_PAYLOAD, payload);
+    let table = builder.end_table(table);
+    builder.finish_minimal(table);
+
+    builder.finished_data().to_vec()
+}
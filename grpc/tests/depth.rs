@@ -0,0 +1,186 @@
+// Trimming full orderbooks before they reach Kafka: orders sorted best first
+// and capped, or merged into L2 levels on the market's tick grid.
+use grpc::config::OrderbookDepthConfig;
+use grpc::depth::OrderbookDepth;
+use grpc::models::{
+    DerivativeMarketPayload, FullLimitOrderbookPayload, KafkaMessage, KafkaPayload, MessageType,
+    TrimmedLimitOrderPayload,
+};
+
+const MARKET: &str = "0xmarket";
+
+fn order(price: &str, quantity: &str, hash: &str) -> TrimmedLimitOrderPayload {
+    TrimmedLimitOrderPayload {
+        price: price.to_string(),
+        quantity: quantity.to_string(),
+        order_hash: hash.to_string(),
+        subaccount_id: "0xsubaccount".to_string(),
+    }
+}
+
+fn book_message(
+    bids: Vec<TrimmedLimitOrderPayload>,
+    asks: Vec<TrimmedLimitOrderPayload>,
+) -> KafkaMessage {
+    KafkaMessage {
+        message_type: MessageType::DerivativeFullOrderbook,
+        block_height: 100,
+        block_time: 1_700_000_000_000,
+        payload: KafkaPayload::DerivativeFullOrderbooks(vec![FullLimitOrderbookPayload {
+            market_id: MARKET.to_string(),
+            bids,
+            asks,
+            timestamp: 1_700_000_000_000,
+        }]),
+    }
+}
+
+fn market_message(min_price_tick: &str) -> KafkaMessage {
+    KafkaMessage {
+        message_type: MessageType::DerivativeMarket,
+        block_height: 100,
+        block_time: 1_700_000_000_000,
+        payload: KafkaPayload::DerivativeMarkets(vec![DerivativeMarketPayload {
+            market_id: MARKET.to_string(),
+            min_price_tick: min_price_tick.to_string(),
+            ..Default::default()
+        }]),
+    }
+}
+
+fn trimmed_book(depth: &OrderbookDepth, message: &KafkaMessage) -> FullLimitOrderbookPayload {
+    match depth.trim(message).expect("book should be trimmed").payload {
+        KafkaPayload::DerivativeFullOrderbooks(mut books) => books.remove(0),
+        _ => panic!("trimming changed the payload type"),
+    }
+}
+
+fn levels(orders: &[TrimmedLimitOrderPayload]) -> Vec<(&str, &str)> {
+    orders
+        .iter()
+        .map(|order| (order.price.as_str(), order.quantity.as_str()))
+        .collect()
+}
+
+#[test]
+fn disabled_depth_leaves_messages_alone() {
+    let depth = OrderbookDepth::new(OrderbookDepthConfig::default());
+    assert!(!depth.is_enabled());
+    assert!(depth
+        .trim(&book_message(vec![order("100", "1", "a")], vec![]))
+        .is_none());
+}
+
+#[test]
+fn only_full_orderbooks_are_trimmed() {
+    let depth = OrderbookDepth::new(OrderbookDepthConfig {
+        max_orders_per_side: 1,
+        ..Default::default()
+    });
+    assert!(depth.trim(&market_message("1000")).is_none());
+}
+
+#[test]
+fn orders_are_sorted_best_first_and_capped() {
+    let depth = OrderbookDepth::new(OrderbookDepthConfig {
+        max_orders_per_side: 2,
+        ..Default::default()
+    });
+    let message = book_message(
+        vec![
+            order("99", "1", "a"),
+            order("101", "1", "b"),
+            order("100", "1", "c"),
+        ],
+        vec![
+            order("105", "1", "d"),
+            order("103", "1", "e"),
+            order("104", "1", "f"),
+        ],
+    );
+    let book = trimmed_book(&depth, &message);
+
+    let hashes = |orders: &[TrimmedLimitOrderPayload]| {
+        orders
+            .iter()
+            .map(|order| order.order_hash.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(hashes(&book.bids), ["b", "c"]);
+    assert_eq!(hashes(&book.asks), ["e", "f"]);
+    assert_eq!(book.timestamp, 1_700_000_000_000);
+}
+
+#[test]
+fn aggregation_sums_orders_per_price() {
+    let depth = OrderbookDepth::new(OrderbookDepthConfig {
+        aggregate: true,
+        ..Default::default()
+    });
+    let message = book_message(
+        vec![
+            order("100", "2", "a"),
+            order("101", "1", "b"),
+            order("100", "3", "c"),
+        ],
+        vec![order("102", "4", "d"), order("102", "1", "e")],
+    );
+    let book = trimmed_book(&depth, &message);
+
+    assert_eq!(levels(&book.bids), [("101", "1"), ("100", "5")]);
+    assert_eq!(levels(&book.asks), [("102", "5")]);
+    // Levels carry no order identity
+    assert!(book.bids.iter().all(|level| level.order_hash.is_empty()));
+    assert!(book.bids.iter().all(|level| level.subaccount_id.is_empty()));
+}
+
+#[test]
+fn aggregation_uses_the_observed_tick_grid() {
+    let depth = OrderbookDepth::new(OrderbookDepthConfig {
+        aggregate: true,
+        tick_multiple: 10,
+        ..Default::default()
+    });
+    let message = book_message(
+        vec![
+            order("1015", "1", "a"),
+            order("1000", "2", "b"),
+            order("995", "1", "c"),
+        ],
+        vec![
+            order("1001", "1", "d"),
+            order("1010", "3", "e"),
+            order("1019", "1", "f"),
+        ],
+    );
+
+    // Before the market is seen there is no grid to put the book on
+    let book = trimmed_book(&depth, &message);
+    assert_eq!(
+        levels(&book.bids),
+        [("1015", "1"), ("1000", "2"), ("995", "1")]
+    );
+
+    // A tick of 1.0 in chain units, so levels are 10 ticks wide
+    depth.observe(&market_message("1"));
+    let book = trimmed_book(&depth, &message);
+    assert_eq!(
+        levels(&book.bids),
+        [("1010", "1"), ("1000", "2"), ("990", "1")]
+    );
+    assert_eq!(levels(&book.asks), [("1010", "4"), ("1020", "1")]);
+}
+
+#[test]
+fn sides_with_non_integer_prices_are_left_as_they_are() {
+    let depth = OrderbookDepth::new(OrderbookDepthConfig {
+        aggregate: true,
+        ..Default::default()
+    });
+    let message = book_message(
+        vec![order("100.5", "1", "a"), order("101", "1", "b")],
+        vec![],
+    );
+    let book = trimmed_book(&depth, &message);
+    assert_eq!(levels(&book.bids), [("100.5", "1"), ("101", "1")]);
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// One config for the whole pipeline. Each side keeps its own section, so
+// existing ingester and consumer config files can be pasted in as they are.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexerConfig {
+    pub ingester: grpc::config::Config,
+    pub consumer: injective_consumer::Config,
+    #[serde(default = "default_metrics_addr")]
+    pub metrics_addr: String,
+}
+
+fn default_metrics_addr() -> String {
+    "0.0.0.0:9100".to_string()
+}
+
+impl IndexerConfig {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        let mut config: IndexerConfig = serde_json::from_str(&contents)?;
+        config.ingester.load_secrets()?;
+        config.consumer.load_secrets()?;
+        Ok(config)
+    }
+
+    // Both sides read the same variables (KAFKA_BROKERS, KAFKA_TOPIC, ...),
+    // so one environment configures the ingester and the consumers
+    pub fn from_env() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(IndexerConfig {
+            ingester: grpc::config::Config::from_env()?,
+            consumer: injective_consumer::Config::from_env()?,
+            metrics_addr: env::var("METRICS_ADDR").unwrap_or_else(|_| default_metrics_addr()),
+        })
+    }
+}
@@ -0,0 +1,139 @@
+use grpc::ingester::Ingester;
+use log::{error, info};
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+use tokio::signal::ctrl_c;
+use tokio::sync::watch;
+use tokio::task;
+
+mod config;
+mod metrics;
+
+use config::IndexerConfig;
+use metrics::SupervisorMetrics;
+
+// Runs the gRPC ingester and every consumer in one process, for small
+// deployments and local development. Ctrl+C, or any component stopping,
+// shuts the whole pipeline down.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Initialize logging
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    info!("Starting Injective indexer (ingester and consumers in one process)");
+
+    // Load configuration
+    let config = match env::var("CONFIG_FILE") {
+        Ok(path) => IndexerConfig::from_file(&path)?,
+        Err(_) => IndexerConfig::from_env()?,
+    };
+
+    info!("Configuration loaded");
+    let IndexerConfig {
+        ingester: ingester_config,
+        consumer: consumer_config,
+        metrics_addr,
+    } = config;
+    grpc::admin::runtime().set_config("ingester", &ingester_config);
+    injective_consumer::admin::runtime().set_config("consumer", &consumer_config);
+
+    // Print the resolved configuration of both halves and exit: `indexer --print-config`
+    if env::args().any(|arg| arg == "--print-config") {
+        println!(
+            "Ingester configuration\n{}",
+            grpc::admin::runtime().render()
+        );
+        println!(
+            "Consumer configuration\n{}",
+            injective_consumer::admin::runtime().render()
+        );
+        return Ok(());
+    }
+
+    // Validate both halves of the configuration and exit: `indexer --check-config`
+    if env::args().any(|arg| arg == "--check-config") {
+        let ingester_report = grpc::diagnostics::check_config(&ingester_config).await;
+        let consumer_report = injective_consumer::diagnostics::check_config(&consumer_config).await;
+        println!("Ingester configuration\n{}", ingester_report);
+        println!("Consumer configuration\n{}", consumer_report);
+        if !ingester_report.passed() || !consumer_report.passed() {
+            return Err("configuration check failed".into());
+        }
+        return Ok(());
+    }
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let shutdown_tx = Arc::new(shutdown_tx);
+
+    let ingester = Ingester::new(ingester_config).await?;
+    let metrics = Arc::new(SupervisorMetrics::new(Some(ingester.producer())));
+    metrics.clone().serve(&metrics_addr).await?;
+
+    // Start the ingester
+    let ingester_handle = {
+        let metrics = metrics.clone();
+        let shutdown_tx = shutdown_tx.clone();
+        let shutdown_rx = shutdown_rx.clone();
+        task::spawn(async move {
+            metrics.set_ingester_up(true);
+            let result = ingester.run(shutdown_rx).await;
+            metrics.set_ingester_up(false);
+            stop_all(&shutdown_tx, "Ingester", &result);
+            result
+        })
+    };
+
+    // Start the consumers
+    let consumer_handle = {
+        let metrics = metrics.clone();
+        let shutdown_tx = shutdown_tx.clone();
+        let shutdown_rx = shutdown_rx.clone();
+        task::spawn(async move {
+            metrics.set_consumer_up(true);
+            let result = injective_consumer::service::run(consumer_config, shutdown_rx).await;
+            metrics.set_consumer_up(false);
+            stop_all(&shutdown_tx, "Consumers", &result);
+            result
+        })
+    };
+
+    // Set up signal handler for graceful shutdown
+    {
+        let shutdown_tx = shutdown_tx.clone();
+        task::spawn(async move {
+            match ctrl_c().await {
+                Ok(()) => {
+                    info!("Received shutdown signal, stopping all components...");
+                    let _ = shutdown_tx.send(true);
+                }
+                Err(e) => {
+                    error!("Error waiting for shutdown signal: {}", e);
+                }
+            }
+        });
+    }
+
+    let (ingester_result, consumer_result) = tokio::join!(ingester_handle, consumer_handle);
+
+    info!("Application shutting down");
+    ingester_result.map_err(|e| format!("Ingester task panicked: {}", e))??;
+    consumer_result.map_err(|e| format!("Consumer task panicked: {}", e))??;
+    Ok(())
+}
+
+// A component stopped; take the rest of the pipeline down with it
+fn stop_all(
+    shutdown_tx: &watch::Sender<bool>,
+    component: &str,
+    result: &Result<(), Box<dyn Error + Send + Sync>>,
+) {
+    if *shutdown_tx.borrow() {
+        return;
+    }
+    match result {
+        Ok(()) => error!("{} stopped, shutting down", component),
+        Err(e) => error!("{} failed: {}, shutting down", component, e),
+    }
+    let _ = shutdown_tx.send(true);
+}
@@ -0,0 +1,114 @@
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use log::{error, info};
+use std::error::Error;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tokio::task;
+
+use grpc::producer::BatchKafkaProducer;
+
+// Process-wide state shared by every component, served in the Prometheus
+// text format on one endpoint
+pub struct SupervisorMetrics {
+    started: Instant,
+    ingester_up: AtomicBool,
+    consumer_up: AtomicBool,
+    producer: Option<Arc<BatchKafkaProducer>>,
+}
+
+impl SupervisorMetrics {
+    pub fn new(producer: Option<Arc<BatchKafkaProducer>>) -> Self {
+        SupervisorMetrics {
+            started: Instant::now(),
+            ingester_up: AtomicBool::new(false),
+            consumer_up: AtomicBool::new(false),
+            producer,
+        }
+    }
+
+    pub fn set_ingester_up(&self, up: bool) {
+        self.ingester_up.store(up, Ordering::Relaxed);
+    }
+
+    pub fn set_consumer_up(&self, up: bool) {
+        self.consumer_up.store(up, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE injective_indexer_up gauge");
+        for (component, up) in [
+            ("ingester", &self.ingester_up),
+            ("consumer", &self.consumer_up),
+        ] {
+            let _ = writeln!(
+                out,
+                "injective_indexer_up{{component=\"{}\"}} {}",
+                component,
+                up.load(Ordering::Relaxed) as u8
+            );
+        }
+        if let Some(producer) = &self.producer {
+            let _ = writeln!(out, "# TYPE injective_indexer_latest_block gauge");
+            let _ = writeln!(
+                out,
+                "injective_indexer_latest_block {}",
+                producer.get_latest_block()
+            );
+        }
+        let _ = writeln!(out, "# TYPE injective_indexer_uptime_seconds counter");
+        let _ = writeln!(
+            out,
+            "injective_indexer_uptime_seconds {}",
+            self.started.elapsed().as_secs()
+        );
+        // Producer, consumer and sink metrics share the default registry
+        out.push_str(&injective_consumer::metrics::render());
+        out
+    }
+
+    // Answer GET /config on `addr` with both halves' effective configuration,
+    // and every other request with the current metrics
+    pub async fn serve(
+        self: Arc<Self>,
+        addr: &str,
+    ) -> Result<task::JoinHandle<()>, Box<dyn Error + Send + Sync>> {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Serving metrics on {}", addr);
+
+        let router = Router::new()
+            .route("/config", get(config))
+            .fallback(metrics)
+            .with_state(self);
+        Ok(task::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                error!("Metrics server stopped: {}", e);
+            }
+        }))
+    }
+}
+
+async fn config() -> impl IntoResponse {
+    let config = serde_json::json!({
+        "ingester": grpc::admin::runtime().snapshot(),
+        "consumer": injective_consumer::admin::runtime().snapshot(),
+    });
+    (
+        [(CONTENT_TYPE, "application/json")],
+        serde_json::to_string_pretty(&config).unwrap_or_default(),
+    )
+}
+
+async fn metrics(State(metrics): State<Arc<SupervisorMetrics>>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, injective_consumer::metrics::PROMETHEUS_TEXT)],
+        metrics.render(),
+    )
+}
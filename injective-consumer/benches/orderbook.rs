@@ -0,0 +1,100 @@
+// Top of book and L2 aggregation on full orderbooks of 5k orders per side,
+// against cloning both sides and sorting them whole (how the processors used
+// to find the best levels). Runs without any services:
+//   cargo bench --bench orderbook
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use injective_consumer::models::{FullLimitOrderbookPayload, TrimmedLimitOrderPayload};
+use injective_consumer::orderbook;
+use injective_consumer::scaling::{CHAIN_DECIMAL, PRICE_DECIMAL};
+use std::hint::black_box;
+
+const BOOK_SIZES: [usize; 2] = [500, 5_000];
+
+// Deterministic prices spread over a few thousand ticks either side of
+// 30,000, so some orders share a level
+fn side(count: usize, is_bid: bool) -> Vec<TrimmedLimitOrderPayload> {
+    let mut seed: u64 = if is_bid { 0x9e37_79b9 } else { 0x85eb_ca6b };
+    (0..count)
+        .map(|i| {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            let offset = (seed >> 33) % 4_000 + 1;
+            let price = if is_bid {
+                30_000 - offset
+            } else {
+                30_000 + offset
+            };
+            TrimmedLimitOrderPayload {
+                price: format!("{}{:024}", price, 0),
+                quantity: format!("{}{:015}", (seed >> 17) % 1_000 + 1, 0),
+                order_hash: format!("0x{:064x}", i),
+                subaccount_id: format!("0x{:064x}", i % 97),
+            }
+        })
+        .collect()
+}
+
+fn book(count: usize) -> FullLimitOrderbookPayload {
+    FullLimitOrderbookPayload {
+        market_id: format!("0x{:064x}", 1),
+        bids: side(count, true),
+        asks: side(count, false),
+        timestamp: 0,
+    }
+}
+
+// Clone both sides, sort them by price and read the first order of each
+fn clone_and_sort(orderbook: &FullLimitOrderbookPayload) -> (Option<f64>, Option<f64>) {
+    let price = |order: &TrimmedLimitOrderPayload| {
+        order.price.parse::<f64>().unwrap_or(0.0) / PRICE_DECIMAL
+    };
+    let mut bids = orderbook.bids.clone();
+    let mut asks = orderbook.asks.clone();
+    bids.sort_by(|a, b| price(b).total_cmp(&price(a)));
+    asks.sort_by(|a, b| price(a).total_cmp(&price(b)));
+    (bids.first().map(price), asks.first().map(price))
+}
+
+fn top_of_book(c: &mut Criterion) {
+    let mut group = c.benchmark_group("top_of_book");
+    for size in BOOK_SIZES {
+        let orderbook = book(size);
+        group.throughput(Throughput::Elements(2 * size as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("clone_and_sort", size),
+            &orderbook,
+            |b, orderbook| b.iter(|| clone_and_sort(black_box(orderbook))),
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("single_pass", size),
+            &orderbook,
+            |b, orderbook| {
+                b.iter(|| {
+                    orderbook::top_of_book(black_box(orderbook), PRICE_DECIMAL, CHAIN_DECIMAL)
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("aggregate_book", size),
+            &orderbook,
+            |b, orderbook| {
+                b.iter(|| {
+                    orderbook::aggregate_book(
+                        black_box(orderbook),
+                        PRICE_DECIMAL,
+                        CHAIN_DECIMAL,
+                        None,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, top_of_book);
+criterion_main!(benches);
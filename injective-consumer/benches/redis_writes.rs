@@ -0,0 +1,127 @@
+// Position writes against a live Redis, one command per field (how the
+// processor used to write), one pipeline per position, and one pipeline per
+// batch (what RedisStateStore::put_positions does). Needs a scratch Redis:
+//   BENCH_REDIS_URL=redis://127.0.0.1:6379 cargo bench --bench redis_writes
+// Keys are written under the normal layout, so never point it at a live keyspace.
+use chrono::Utc;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use injective_consumer::dual_write::MirroredConnection;
+use injective_consumer::models::PositionData;
+use injective_consumer::redis_keys;
+use injective_consumer::storage::{RedisStateStore, StateStore};
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Client};
+use std::env;
+use tokio::runtime::Runtime;
+
+const BATCH_SIZES: [usize; 3] = [10, 100, 1_000];
+
+fn hex_id(prefix: u8, n: usize) -> String {
+    format!("0x{:02x}{:062x}", prefix, n)
+}
+
+fn positions(count: usize) -> Vec<PositionData> {
+    (0..count)
+        .map(|i| PositionData {
+            market_id: hex_id(1, i % 20),
+            subaccount_id: hex_id(3, i),
+            is_long: i % 2 == 0,
+            quantity: 1.5,
+            entry_price: 30_000.0,
+            margin: 5_000.0,
+            cumulative_funding_entry: 0.0,
+            liquidation_price: 26_000.0,
+            is_liquidatable: false,
+            block_height: 1,
+            timestamp: Utc::now(),
+        })
+        .collect()
+}
+
+// One round trip per hash field and index set
+async fn write_per_command(conn: &mut ConnectionManager, position: &PositionData) {
+    let key = redis_keys::position(&position.market_id, &position.subaccount_id);
+    let fields = [
+        ("is_long", position.is_long.to_string()),
+        ("quantity", position.quantity.to_string()),
+        ("entry_price", position.entry_price.to_string()),
+        ("margin", position.margin.to_string()),
+        (
+            "cumulative_funding_entry",
+            position.cumulative_funding_entry.to_string(),
+        ),
+        ("liquidation_price", position.liquidation_price.to_string()),
+        ("is_liquidatable", position.is_liquidatable.to_string()),
+        ("block_height", position.block_height.to_string()),
+        (
+            "timestamp",
+            position.timestamp.timestamp_millis().to_string(),
+        ),
+    ];
+    for (field, value) in fields {
+        conn.hset::<_, _, _, ()>(&key, field, value).await.unwrap();
+    }
+    conn.sadd::<_, _, ()>(
+        redis_keys::positions_by_market(&position.market_id),
+        &position.subaccount_id,
+    )
+    .await
+    .unwrap();
+    conn.sadd::<_, _, ()>(
+        redis_keys::positions_by_subaccount(&position.subaccount_id),
+        &position.market_id,
+    )
+    .await
+    .unwrap();
+}
+
+fn position_writes(c: &mut Criterion) {
+    let Ok(url) = env::var("BENCH_REDIS_URL") else {
+        eprintln!("BENCH_REDIS_URL is not set, skipping the Redis write benchmarks");
+        return;
+    };
+    let runtime = Runtime::new().unwrap();
+    let connection = runtime
+        .block_on(async { ConnectionManager::new(Client::open(url)?).await })
+        .expect("failed to connect to BENCH_REDIS_URL");
+    let store = RedisStateStore::new(MirroredConnection::new(connection.clone()));
+
+    let mut group = c.benchmark_group("position_writes");
+    for size in BATCH_SIZES {
+        let batch = positions(size);
+        group.throughput(Throughput::Elements(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("per_command", size), &batch, |b, batch| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let mut conn = connection.clone();
+                    for position in batch {
+                        write_per_command(&mut conn, position).await;
+                    }
+                })
+            })
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("per_position", size),
+            &batch,
+            |b, batch| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        for position in batch {
+                            store.put_position(position).await.unwrap();
+                        }
+                    })
+                })
+            },
+        );
+
+        group.bench_with_input(BenchmarkId::new("pipelined", size), &batch, |b, batch| {
+            b.iter(|| runtime.block_on(async { store.put_positions(batch).await.unwrap() }))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, position_writes);
+criterion_main!(benches);
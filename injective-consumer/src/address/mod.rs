@@ -0,0 +1,84 @@
+// Owner address derivation. A subaccount id is the owner's 20 byte account
+// address followed by a 12 byte nonce, hex encoded:
+//
+//   0x{40 hex: address}{24 hex: nonce}
+//
+// The owner is shown as a bech32 `inj1...` address.
+
+const HRP: &str = "inj";
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+// Hex of the owner address embedded in a subaccount id
+pub fn owner_hex(subaccount_id: &str) -> Option<&str> {
+    let hex = subaccount_id.strip_prefix("0x").unwrap_or(subaccount_id);
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(&hex[..40])
+}
+
+// Bech32 owner address of a subaccount, or None if the id is malformed
+pub fn owner_address(subaccount_id: &str) -> Option<String> {
+    let hex = owner_hex(subaccount_id)?;
+    let bytes: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    Some(bech32_encode(HRP, &bytes))
+}
+
+fn bech32_encode(hrp: &str, data: &[u8]) -> String {
+    let words = to_base32(data);
+
+    let mut values: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|b| b & 31));
+    values.extend(&words);
+    values.extend([0u8; 6]);
+    let checksum = polymod(&values) ^ 1;
+
+    let mut encoded = format!("{}1", hrp);
+    for word in words {
+        encoded.push(BECH32_CHARSET[word as usize] as char);
+    }
+    for i in 0..6 {
+        let word = (checksum >> (5 * (5 - i))) & 31;
+        encoded.push(BECH32_CHARSET[word as usize] as char);
+    }
+    encoded
+}
+
+// Regroup 8 bit bytes into 5 bit words, padding the final word
+fn to_base32(data: &[u8]) -> Vec<u8> {
+    let mut words = Vec::with_capacity(data.len() * 8 / 5 + 1);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for byte in data {
+        acc = (acc << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            words.push(((acc >> bits) & 31) as u8);
+        }
+    }
+    if bits > 0 {
+        words.push(((acc << (5 - bits)) & 31) as u8);
+    }
+    words
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum: u32 = 1;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ffffff) << 5) ^ *value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
@@ -0,0 +1,129 @@
+use crate::secrets;
+use log::warn;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, OnceLock};
+
+// What a running process reports on GET /config of its metrics address, and
+// `--print-config` before it starts: the resolved configuration with secrets
+// masked, the features it was built with, the filters in effect and the
+// Kafka partitions each consumer group holds. Sections are recorded as they
+// are resolved, so the report is whatever the process is actually using.
+#[derive(Default)]
+pub struct RuntimeConfig {
+    config: Mutex<Map<String, Value>>,
+    filters: Mutex<Map<String, Value>>,
+    groups: Mutex<BTreeMap<String, GroupAssignment>>,
+}
+
+#[derive(Default, Serialize)]
+struct GroupAssignment {
+    topics: Vec<String>,
+    // topic/partition
+    partitions: BTreeSet<String>,
+}
+
+pub fn runtime() -> &'static RuntimeConfig {
+    static RUNTIME: OnceLock<RuntimeConfig> = OnceLock::new();
+    RUNTIME.get_or_init(RuntimeConfig::default)
+}
+
+// Cargo features this build of the consumer has
+pub fn features() -> Vec<&'static str> {
+    let features = [
+        ("redis-sink", cfg!(feature = "redis-sink")),
+        ("scylla-sink", cfg!(feature = "scylla-sink")),
+        ("pubsub", cfg!(feature = "pubsub")),
+        ("api", cfg!(feature = "api")),
+        ("gateway", cfg!(feature = "gateway")),
+        ("notifications", cfg!(feature = "notifications")),
+        ("trade-qa", cfg!(feature = "trade-qa")),
+        ("scripting", cfg!(feature = "scripting")),
+    ];
+    features
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+impl RuntimeConfig {
+    // Record a configuration section, replacing an earlier one of that name.
+    // Password fields are never serialized; URLs are masked here since they
+    // can embed credentials or a provider API key.
+    pub fn set_config<T: Serialize>(&self, name: &str, config: &T) {
+        if let Some(value) = masked(name, config) {
+            self.config.lock().unwrap().insert(name.to_string(), value);
+        }
+    }
+
+    // Record a filter in effect, such as routing rules
+    pub fn set_filter<T: Serialize>(&self, name: &str, filter: &T) {
+        if let Some(value) = masked(name, filter) {
+            self.filters.lock().unwrap().insert(name.to_string(), value);
+        }
+    }
+
+    pub fn subscribed(&self, group: &str, topics: &[&str]) {
+        let mut groups = self.groups.lock().unwrap();
+        groups.entry(group.to_string()).or_default().topics =
+            topics.iter().map(|topic| topic.to_string()).collect();
+    }
+
+    pub fn assigned<'a>(&self, group: &str, partitions: impl IntoIterator<Item = (&'a str, i32)>) {
+        let mut groups = self.groups.lock().unwrap();
+        let assignment = groups.entry(group.to_string()).or_default();
+        for (topic, partition) in partitions {
+            assignment
+                .partitions
+                .insert(format!("{}/{}", topic, partition));
+        }
+    }
+
+    pub fn revoked<'a>(&self, group: &str, partitions: impl IntoIterator<Item = (&'a str, i32)>) {
+        let mut groups = self.groups.lock().unwrap();
+        if let Some(assignment) = groups.get_mut(group) {
+            for (topic, partition) in partitions {
+                assignment
+                    .partitions
+                    .remove(&format!("{}/{}", topic, partition));
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Value {
+        json!({
+            "features": features(),
+            "config": Value::Object(self.config.lock().unwrap().clone()),
+            "filters": Value::Object(self.filters.lock().unwrap().clone()),
+            "consumer_groups": &*self.groups.lock().unwrap(),
+        })
+    }
+
+    pub fn render(&self) -> String {
+        serde_json::to_string_pretty(&self.snapshot()).unwrap_or_default()
+    }
+}
+
+fn masked<T: Serialize>(name: &str, section: &T) -> Option<Value> {
+    match serde_json::to_value(section) {
+        Ok(mut value) => {
+            mask_urls(&mut value);
+            Some(value)
+        }
+        Err(e) => {
+            warn!("Failed to record the {} configuration: {}", name, e);
+            None
+        }
+    }
+}
+
+fn mask_urls(value: &mut Value) {
+    match value {
+        Value::String(s) if s.contains("://") => *s = secrets::mask_url(s),
+        Value::Array(items) => items.iter_mut().for_each(mask_urls),
+        Value::Object(fields) => fields.values_mut().for_each(mask_urls),
+        _ => {}
+    }
+}
@@ -0,0 +1,34 @@
+use injective_consumer::notifications::{LiquidationNotifier, NotifierConfig};
+use log::{error, info};
+use std::error::Error;
+use tokio::signal::ctrl_c;
+use tokio::sync::watch;
+use tokio::task;
+
+// Posts the consumer's liquidation alerts to chat webhooks
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    info!("Starting liquidation notifier");
+    let config = NotifierConfig::from_env()?;
+    let notifier = LiquidationNotifier::new(config)?;
+
+    // Post what is queued and stop on Ctrl+C
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    task::spawn(async move {
+        match ctrl_c().await {
+            Ok(()) => {
+                let _ = shutdown_tx.send(true);
+            }
+            Err(e) => {
+                error!("Error waiting for shutdown signal: {}", e);
+            }
+        }
+    });
+
+    notifier.run(shutdown_rx).await?;
+
+    info!("Liquidation notifier stopped");
+    Ok(())
+}
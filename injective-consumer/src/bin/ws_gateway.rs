@@ -0,0 +1,38 @@
+use injective_consumer::config::RedisKeySchema;
+use injective_consumer::gateway::{GatewayConfig, WsGateway};
+use injective_consumer::redis_keys;
+use log::{error, info};
+use std::error::Error;
+use tokio::signal::ctrl_c;
+use tokio::sync::watch;
+use tokio::task;
+
+// Serves the consumer's pub/sub events to WebSocket clients
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    info!("Starting WebSocket gateway");
+    let config = GatewayConfig::from_env()?;
+    // Client subscriptions are stored under the consumers' key schema
+    redis_keys::install(RedisKeySchema::from_env()?);
+    let gateway = WsGateway::new(config).await?;
+
+    // Stop accepting clients on Ctrl+C
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    task::spawn(async move {
+        match ctrl_c().await {
+            Ok(()) => {
+                let _ = shutdown_tx.send(true);
+            }
+            Err(e) => {
+                error!("Error waiting for shutdown signal: {}", e);
+            }
+        }
+    });
+
+    gateway.run(shutdown_rx).await?;
+
+    info!("WebSocket gateway stopped");
+    Ok(())
+}
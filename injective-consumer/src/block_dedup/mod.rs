@@ -0,0 +1,183 @@
+use crate::config::{DedupBackend, IngestDedupConfig};
+use crate::metrics;
+use crate::models::KafkaMessage;
+use crate::redis_keys;
+use log::warn;
+use redis::aio::ConnectionManager;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Lets a consumer group apply each logical message once. Copies reach the
+// consumers when two ingesters run side by side for redundancy, and when a
+// rebalance hands a partition to a member that resumes before the previous
+// owner's last commit. The first copy to claim (block_height, message_type,
+// content hash) is processed and the others are skipped. Claims are scoped to
+// a consumer group, so every group still applies the message once.
+//
+// Claims live in Redis, where every member of the group sees them, or in an
+// in-process window, which only catches copies the same member receives but
+// costs no round trip.
+//
+// Only messages built from the chain stream hash the same on both ingesters.
+// Messages the producer stamps with the wall clock (market and position
+// heartbeats) differ and are applied by both, which is harmless since they
+// carry latest state.
+pub struct DedupWindow {
+    store: ClaimStore,
+}
+
+enum ClaimStore {
+    Redis(ConnectionManager, Duration),
+    Memory(Mutex<RecentKeys>),
+}
+
+impl DedupWindow {
+    pub async fn from_config(
+        config: &IngestDedupConfig,
+        redis_url: &str,
+    ) -> Result<Self, redis::RedisError> {
+        let ttl = Duration::from_secs(config.ttl_secs);
+        let store = match config.backend {
+            DedupBackend::Redis => {
+                let client = redis::Client::open(redis_url)?;
+                ClaimStore::Redis(ConnectionManager::new(client).await?, ttl)
+            }
+            DedupBackend::Memory => {
+                ClaimStore::Memory(Mutex::new(RecentKeys::new(config.memory_capacity, ttl)))
+            }
+        };
+        Ok(DedupWindow { store })
+    }
+
+    // Identifies the message within the group's claims
+    pub fn key(&self, scope: &str, message: &KafkaMessage) -> String {
+        redis_keys::ingest_claim(
+            scope,
+            &format!("{:?}", message.message_type),
+            message.block_height,
+            message.content_hash(),
+        )
+    }
+
+    // Whether this is the first copy of the message. Duplicates are counted
+    // under the scope.
+    pub async fn claim(&self, scope: &str, key: &str) -> bool {
+        let claimed = match &self.store {
+            ClaimStore::Redis(conn, ttl) => claim_in_redis(conn, *ttl, key).await,
+            ClaimStore::Memory(recent) => recent.lock().unwrap().claim(key, Instant::now()),
+        };
+        if !claimed {
+            metrics::consumer()
+                .ingest_duplicates
+                .with_label_values(&[scope])
+                .inc();
+        }
+        claimed
+    }
+
+    // Give the message back so a redelivery, or the other ingester's copy if
+    // it hasn't arrived yet, can still apply it
+    pub async fn release(&self, key: &str) {
+        match &self.store {
+            ClaimStore::Redis(conn, _) => {
+                let released: Result<(), _> = redis::cmd("DEL")
+                    .arg(key)
+                    .query_async(&mut conn.clone())
+                    .await;
+                if let Err(e) = released {
+                    warn!("Failed to release message claim {}: {}", key, e);
+                }
+            }
+            ClaimStore::Memory(recent) => recent.lock().unwrap().release(key),
+        }
+    }
+}
+
+// If Redis can't be asked the message is processed, since writes are safer to
+// repeat than to lose
+async fn claim_in_redis(conn: &ConnectionManager, ttl: Duration, key: &str) -> bool {
+    let claimed: Result<Option<String>, _> = redis::cmd("SET")
+        .arg(key)
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(ttl.as_secs().max(1))
+        .query_async(&mut conn.clone())
+        .await;
+    match claimed {
+        Ok(claim) => claim.is_some(),
+        Err(e) => {
+            warn!(
+                "Failed to claim message, processing without deduplication: {}",
+                e
+            );
+            true
+        }
+    }
+}
+
+// Keys claimed within the last TTL, at most `capacity` of them; the oldest
+// are forgotten first
+pub struct RecentKeys {
+    capacity: usize,
+    ttl: Duration,
+    // When each claimed key expires
+    expiry: HashMap<String, Instant>,
+    // Claims in the order they were made
+    order: VecDeque<(String, Instant)>,
+}
+
+impl RecentKeys {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        RecentKeys {
+            capacity: capacity.max(1),
+            ttl,
+            expiry: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    // True if the key wasn't claimed within the window, and claims it
+    pub fn claim(&mut self, key: &str, now: Instant) -> bool {
+        while self
+            .order
+            .front()
+            .is_some_and(|(_, expires)| *expires <= now)
+        {
+            self.forget_oldest();
+        }
+        if self.expiry.contains_key(key) {
+            return false;
+        }
+        while self.order.len() >= self.capacity {
+            self.forget_oldest();
+        }
+        let expires = now + self.ttl;
+        self.expiry.insert(key.to_string(), expires);
+        self.order.push_back((key.to_string(), expires));
+        true
+    }
+
+    pub fn release(&mut self, key: &str) {
+        self.expiry.remove(key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.expiry.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expiry.is_empty()
+    }
+
+    // A key claimed again after a release has a newer entry further back,
+    // which forgetting the older one leaves in place
+    fn forget_oldest(&mut self) {
+        if let Some((key, expires)) = self.order.pop_front() {
+            if self.expiry.get(&key) == Some(&expires) {
+                self.expiry.remove(&key);
+            }
+        }
+    }
+}
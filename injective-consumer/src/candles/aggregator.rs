@@ -0,0 +1,151 @@
+use super::{Candle, Resolution};
+use crate::window::{Aggregate, WindowSpec, WindowedAggregator};
+use serde::Serialize;
+use std::time::Duration;
+
+// Rolling OHLCV bars built from taker fills, one tumbling window per
+// resolution. A bar stays open until the block time passes its end plus the
+// allowed lateness, then it is final. Fills that only belong to final bars
+// are dropped, since those bars have already been published. Volume is quote
+// notional, like the hourly summary buckets.
+
+// A bar that will not change any more
+#[derive(Debug, Clone, Serialize)]
+pub struct ClosedCandle {
+    pub market_id: String,
+    pub resolution: Resolution,
+    #[serde(flatten)]
+    pub candle: Candle,
+}
+
+// A fill is (price, quantity); window starts are in milliseconds while candle
+// times are in seconds
+impl Aggregate for Candle {
+    type Event = (f64, f64);
+
+    fn first(start: i64, &(price, quantity): &(f64, f64)) -> Self {
+        Candle {
+            time: start / 1_000,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: price * quantity,
+        }
+    }
+
+    fn add(&mut self, &(price, quantity): &(f64, f64)) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += price * quantity;
+    }
+}
+
+#[derive(Debug)]
+pub struct CandleAggregator {
+    windows: Vec<(Resolution, WindowedAggregator<Candle>)>,
+}
+
+impl CandleAggregator {
+    pub fn new(resolutions: Vec<Resolution>) -> Self {
+        CandleAggregator {
+            windows: resolutions
+                .into_iter()
+                .map(|resolution| {
+                    let size = Duration::from_secs(resolution.seconds() as u64);
+                    (
+                        resolution,
+                        WindowedAggregator::new(WindowSpec::tumbling(size)),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    // Keep bars open this long after they end for fills from late blocks
+    pub fn set_allowed_lateness(&mut self, allowed_lateness: Duration) {
+        for (_, windows) in &mut self.windows {
+            windows.set_allowed_lateness(allowed_lateness);
+        }
+    }
+
+    pub fn resolutions(&self) -> Vec<Resolution> {
+        self.windows
+            .iter()
+            .map(|(resolution, _)| *resolution)
+            .collect()
+    }
+
+    fn windows(&self, resolution: Resolution) -> Option<&WindowedAggregator<Candle>> {
+        self.windows
+            .iter()
+            .find(|(r, _)| *r == resolution)
+            .map(|(_, windows)| windows)
+    }
+
+    // Whether the bar containing `time` (unix seconds) is still open but not
+    // in memory, i.e. whether stored state should be resumed before a fill
+    pub fn needs_resume(&self, market_id: &str, resolution: Resolution, time: i64) -> bool {
+        self.windows(resolution).is_some_and(|windows| {
+            let start = resolution.bucket(time) * 1_000;
+            windows.accepts(time * 1_000) && !windows.is_tracking(market_id, start)
+        })
+    }
+
+    // Continue a bar written before a restart instead of starting it empty
+    pub fn resume(&mut self, market_id: &str, resolution: Resolution, candle: Candle) {
+        if let Some((_, windows)) = self.windows.iter_mut().find(|(r, _)| *r == resolution) {
+            windows.resume(market_id, candle.time * 1_000, candle);
+        }
+    }
+
+    // Bars of the market that are not final yet, oldest first
+    pub fn open_candles(&self, market_id: &str, resolution: Resolution) -> Vec<&Candle> {
+        self.windows(resolution)
+            .map(|windows| windows.windows(market_id).map(|(_, c)| c).collect())
+            .unwrap_or_default()
+    }
+
+    // Fold one fill into every resolution; `time` is unix seconds. Returns
+    // false when the fill was too late for every resolution.
+    pub fn apply(&mut self, market_id: &str, time: i64, price: f64, quantity: f64) -> bool {
+        if price <= 0.0 || quantity <= 0.0 {
+            return true;
+        }
+
+        let mut accepted = false;
+        for (_, windows) in &mut self.windows {
+            accepted |= windows.add(market_id, time * 1_000, &(price, quantity));
+        }
+        accepted
+    }
+
+    // Close every open bar that is final at `time` (unix seconds), so quiet
+    // markets still get their bars closed as blocks go by
+    pub fn close_elapsed(&mut self, time: i64) -> Vec<ClosedCandle> {
+        let mut closed: Vec<ClosedCandle> = self
+            .windows
+            .iter_mut()
+            .flat_map(|(resolution, windows)| {
+                let resolution = *resolution;
+                windows
+                    .advance(time * 1_000)
+                    .into_iter()
+                    .map(move |window| ClosedCandle {
+                        market_id: window.key,
+                        resolution,
+                        candle: window.value,
+                    })
+            })
+            .collect();
+        closed.sort_by(|a, b| {
+            (&a.market_id, a.candle.time, a.resolution.seconds()).cmp(&(
+                &b.market_id,
+                b.candle.time,
+                b.resolution.seconds(),
+            ))
+        });
+        closed
+    }
+}
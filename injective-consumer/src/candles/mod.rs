@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+mod aggregator;
+pub use aggregator::{CandleAggregator, ClosedCandle};
+
+// Upper bound on candles returned by one history request
+pub const MAX_CANDLES: usize = 5_000;
+
+// Candle resolutions, named as TradingView names them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Resolution {
+    #[serde(rename = "1")]
+    OneMinute,
+    #[serde(rename = "5")]
+    FiveMinutes,
+    #[serde(rename = "15")]
+    FifteenMinutes,
+    #[serde(rename = "60")]
+    OneHour,
+    #[serde(rename = "240")]
+    FourHours,
+    #[serde(rename = "1D")]
+    OneDay,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 6] = [
+        Resolution::OneMinute,
+        Resolution::FiveMinutes,
+        Resolution::FifteenMinutes,
+        Resolution::OneHour,
+        Resolution::FourHours,
+        Resolution::OneDay,
+    ];
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Resolution::OneMinute => 60,
+            Resolution::FiveMinutes => 300,
+            Resolution::FifteenMinutes => 900,
+            Resolution::OneHour => 3_600,
+            Resolution::FourHours => 14_400,
+            Resolution::OneDay => 86_400,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resolution::OneMinute => "1",
+            Resolution::FiveMinutes => "5",
+            Resolution::FifteenMinutes => "15",
+            Resolution::OneHour => "60",
+            Resolution::FourHours => "240",
+            Resolution::OneDay => "1D",
+        }
+    }
+
+    // Start of the candle containing `time` (unix seconds)
+    pub fn bucket(&self, time: i64) -> i64 {
+        time - time.rem_euclid(self.seconds())
+    }
+}
+
+impl std::str::FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "1" => Ok(Resolution::OneMinute),
+            "5" => Ok(Resolution::FiveMinutes),
+            "15" => Ok(Resolution::FifteenMinutes),
+            "60" => Ok(Resolution::OneHour),
+            "240" => Ok(Resolution::FourHours),
+            "D" | "1D" => Ok(Resolution::OneDay),
+            other => Err(format!("Unsupported resolution: {}", other)),
+        }
+    }
+}
+
+// One OHLCV candle; `time` is the candle start in unix seconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    pub time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+// A validated `/candles?market&resolution&from&to` request
+#[derive(Debug, Clone)]
+pub struct CandleQuery {
+    pub market_id: String,
+    pub resolution: Resolution,
+    pub from: i64,
+    pub to: i64,
+}
+
+impl CandleQuery {
+    // Align the range to candle boundaries and cap it at MAX_CANDLES, keeping
+    // the most recent end of the range
+    pub fn new(
+        market_id: &str,
+        resolution: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let resolution: Resolution = resolution.parse()?;
+        if from > to {
+            return Err(format!("Invalid range: from {} is after to {}", from, to).into());
+        }
+
+        let to = resolution.bucket(to);
+        let earliest = to - (MAX_CANDLES as i64 - 1) * resolution.seconds();
+        let from = resolution.bucket(from).max(earliest);
+
+        Ok(CandleQuery {
+            market_id: market_id.to_string(),
+            resolution,
+            from,
+            to,
+        })
+    }
+}
+
+// Fill missing candles in `[from, to]` with empty candles carrying the previous
+// close forward. `candles` must be sorted by time. Gaps before the first stored
+// candle are filled from `previous`, the last candle before the range, when
+// known; otherwise the range starts at the first stored candle.
+pub fn fill_gaps(
+    candles: &[Candle],
+    previous: Option<&Candle>,
+    resolution: Resolution,
+    from: i64,
+    to: i64,
+) -> Vec<Candle> {
+    let step = resolution.seconds();
+    let mut filled = Vec::new();
+    let mut stored = candles
+        .iter()
+        .filter(|c| c.time >= from && c.time <= to)
+        .peekable();
+    let mut last_close = previous.map(|c| c.close);
+
+    let mut time = resolution.bucket(from);
+    while time <= to && filled.len() < MAX_CANDLES {
+        match stored.peek() {
+            Some(candle) if candle.time == time => {
+                last_close = Some(candle.close);
+                filled.push(**candle);
+                stored.next();
+            }
+            _ => {
+                if let Some(close) = last_close {
+                    filled.push(Candle {
+                        time,
+                        open: close,
+                        high: close,
+                        low: close,
+                        close,
+                        volume: 0.0,
+                    });
+                }
+            }
+        }
+        // Skip duplicates and candles not aligned to the resolution
+        while stored.peek().is_some_and(|c| c.time < time + step) {
+            stored.next();
+        }
+        time += step;
+    }
+
+    filled
+}
+
+// TradingView UDF `/history` response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UdfHistory {
+    pub s: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub t: Vec<i64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub o: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub h: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub l: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub c: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub v: Vec<f64>,
+    // With status "no_data", the time of the newest candle before the range
+    #[serde(rename = "nextTime", skip_serializing_if = "Option::is_none")]
+    pub next_time: Option<i64>,
+}
+
+impl UdfHistory {
+    pub fn new(candles: &[Candle], previous: Option<&Candle>) -> Self {
+        if candles.is_empty() {
+            return UdfHistory {
+                s: "no_data".to_string(),
+                t: Vec::new(),
+                o: Vec::new(),
+                h: Vec::new(),
+                l: Vec::new(),
+                c: Vec::new(),
+                v: Vec::new(),
+                next_time: previous.map(|c| c.time),
+            };
+        }
+
+        UdfHistory {
+            s: "ok".to_string(),
+            t: candles.iter().map(|c| c.time).collect(),
+            o: candles.iter().map(|c| c.open).collect(),
+            h: candles.iter().map(|c| c.high).collect(),
+            l: candles.iter().map(|c| c.low).collect(),
+            c: candles.iter().map(|c| c.close).collect(),
+            v: candles.iter().map(|c| c.volume).collect(),
+            next_time: None,
+        }
+    }
+}
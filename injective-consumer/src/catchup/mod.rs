@@ -0,0 +1,281 @@
+use crate::clock::{self, Clock};
+use crate::metrics;
+use crate::models::time;
+#[cfg(feature = "pubsub")]
+use crate::pubsub::{EventType, RedisPubSubService, StreamEvent};
+use log::info;
+#[cfg(feature = "pubsub")]
+use log::warn;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task;
+
+// Progress of a consumer group working through a Kafka backlog, such as a new
+// environment replaying days of history. The offset lag comes from
+// librdkafka's statistics and the block from the messages themselves. The
+// consumer never talks to the chain, so the head block is estimated from how
+// far the block time trails the wall clock and the block rate seen so far.
+
+// Messages whose block time lags the wall clock by more than this mean the
+// group is replaying or has fallen behind
+pub const STALE_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CatchUpSnapshot {
+    pub group: String,
+    // Share of the backlog processed since catching up started, 0 to 100
+    pub percent: f64,
+    pub processed: u64,
+    // Messages left across the group's partitions
+    pub remaining: i64,
+    pub eta_secs: Option<u64>,
+    pub block_height: u64,
+    pub head_block_height: Option<u64>,
+    pub lag_secs: u64,
+    pub caught_up: bool,
+}
+
+#[derive(Default)]
+struct Progress {
+    // When the current backlog started being tracked, in clock millis
+    started: Option<i64>,
+    processed: u64,
+    // Lag per (topic, partition) from the latest statistics
+    lag: HashMap<(String, i32), i64>,
+    // (height, block time in millis) of the first and latest message
+    first_block: Option<(u64, i64)>,
+    last_block: Option<(u64, i64)>,
+}
+
+impl Progress {
+    fn remaining(&self) -> Option<i64> {
+        (!self.lag.is_empty()).then(|| self.lag.values().sum())
+    }
+}
+
+// Shared between a consumer, its statistics callback and the reporter
+pub struct CatchUpProgress {
+    group: String,
+    progress: Mutex<Progress>,
+    clock: Arc<dyn Clock>,
+}
+
+impl CatchUpProgress {
+    pub fn new(group: &str) -> Self {
+        CatchUpProgress {
+            group: group.to_string(),
+            progress: Mutex::new(Progress::default()),
+            clock: clock::system(),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    // Partition lag as reported with the consumer's statistics; None drops a
+    // partition that is no longer assigned
+    pub fn record_lag(&self, topic: &str, partition: i32, lag: Option<i64>) {
+        let mut progress = self.progress.lock().unwrap();
+        let key = (topic.to_string(), partition);
+        match lag {
+            Some(lag) => progress.lag.insert(key, lag),
+            None => progress.lag.remove(&key),
+        };
+    }
+
+    // A handled message, whatever its outcome
+    pub fn record_message(&self) {
+        let now = self.clock.now_millis();
+        let mut progress = self.progress.lock().unwrap();
+        progress.started.get_or_insert(now);
+        progress.processed += 1;
+    }
+
+    // The block of a decoded message
+    pub fn record_block(&self, height: u64, block_time: u64) {
+        let mut progress = self.progress.lock().unwrap();
+        let block = (height, time::to_millis(block_time as i64));
+        progress.first_block.get_or_insert(block);
+        if progress.last_block.is_none_or(|(last, _)| height >= last) {
+            progress.last_block = Some(block);
+        }
+    }
+
+    // Where the group stands. Once it has caught up, counting starts over so
+    // that a later backlog is measured on its own.
+    pub fn snapshot(&self) -> Option<CatchUpSnapshot> {
+        let now = self.clock.now_millis();
+        let mut progress = self.progress.lock().unwrap();
+        let (block_height, block_millis) = progress.last_block?;
+        let lag_secs = self.clock.age(block_millis).as_secs();
+        // Until the first statistics arrive only the block time tells
+        let remaining = progress.remaining().map(|lag| lag.max(0));
+        let caught_up = remaining == Some(0) || lag_secs <= STALE_AFTER.as_secs();
+        let remaining = remaining.unwrap_or(0);
+
+        let elapsed = progress.started.map_or(Duration::ZERO, |started| {
+            Duration::from_millis((now - started).max(0) as u64)
+        });
+        let processed = progress.processed;
+        let percent = if caught_up {
+            100.0
+        } else {
+            processed as f64 / (processed + remaining as u64) as f64 * 100.0
+        };
+        let rate = processed as f64 / elapsed.as_secs_f64();
+        let eta_secs = (!caught_up && rate.is_finite() && rate > 0.0)
+            .then(|| (remaining as f64 / rate) as u64);
+
+        // Milliseconds per block over the blocks processed so far
+        let head_block_height = progress.first_block.and_then(|(first, first_millis)| {
+            let blocks = block_height.checked_sub(first).filter(|b| *b > 0)?;
+            let block_millis_each = (block_millis - first_millis) as f64 / blocks as f64;
+            (block_millis_each > 0.0)
+                .then(|| block_height + (lag_secs as f64 * 1_000.0 / block_millis_each) as u64)
+        });
+
+        if caught_up {
+            progress.started = None;
+            progress.processed = 0;
+            progress.first_block = progress.last_block;
+        }
+
+        Some(CatchUpSnapshot {
+            group: self.group.clone(),
+            percent,
+            processed,
+            remaining,
+            eta_secs,
+            block_height,
+            head_block_height,
+            lag_secs,
+            caught_up,
+        })
+    }
+}
+
+// Logs each group's progress while it is behind, keeps the catch-up metrics
+// current and publishes a SystemEvent per report, plus one when a group has
+// caught up
+pub struct CatchUpReporter {
+    groups: Vec<Arc<CatchUpProgress>>,
+    interval: Duration,
+    #[cfg(feature = "pubsub")]
+    pubsub: Option<Arc<RedisPubSubService>>,
+}
+
+impl CatchUpReporter {
+    pub fn new(groups: Vec<Arc<CatchUpProgress>>, interval: Duration) -> Self {
+        CatchUpReporter {
+            groups,
+            interval,
+            #[cfg(feature = "pubsub")]
+            pubsub: None,
+        }
+    }
+
+    #[cfg(feature = "pubsub")]
+    pub fn with_pubsub(mut self, pubsub: Arc<RedisPubSubService>) -> Self {
+        self.pubsub = Some(pubsub);
+        self
+    }
+
+    pub fn spawn(self) -> task::JoinHandle<()> {
+        task::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            // Groups start out behind until their first report says otherwise
+            let mut behind: HashMap<String, bool> = HashMap::new();
+            loop {
+                interval.tick().await;
+                for group in &self.groups {
+                    let Some(snapshot) = group.snapshot() else {
+                        continue;
+                    };
+                    record_metrics(&snapshot);
+                    let was_behind = behind.insert(snapshot.group.clone(), !snapshot.caught_up);
+                    if snapshot.caught_up {
+                        if was_behind != Some(false) {
+                            info!(
+                                "Group {} caught up at block {}",
+                                snapshot.group, snapshot.block_height
+                            );
+                            self.publish("catchup_complete", &snapshot).await;
+                        }
+                        continue;
+                    }
+
+                    info!(
+                        "Group {} catching up: {:.1}% ({} processed, {} to go), block {} of ~{}, {}s behind, ETA {}",
+                        snapshot.group,
+                        snapshot.percent,
+                        snapshot.processed,
+                        snapshot.remaining,
+                        snapshot.block_height,
+                        snapshot
+                            .head_block_height
+                            .map_or("?".to_string(), |head| head.to_string()),
+                        snapshot.lag_secs,
+                        snapshot
+                            .eta_secs
+                            .map_or("unknown".to_string(), format_eta),
+                    );
+                    self.publish("catchup_progress", &snapshot).await;
+                }
+            }
+        })
+    }
+
+    #[cfg(feature = "pubsub")]
+    async fn publish(&self, event: &str, snapshot: &CatchUpSnapshot) {
+        let Some(pubsub) = &self.pubsub else {
+            return;
+        };
+        let mut payload = serde_json::to_value(snapshot).unwrap_or_default();
+        payload["event"] = serde_json::Value::from(event);
+        let event = StreamEvent::new(EventType::SystemEvent, time::now_millis() as u64, payload);
+        if let Err(e) = pubsub.publish_event(event).await {
+            warn!("Failed to publish catch-up progress: {}", e);
+        }
+    }
+
+    #[cfg(not(feature = "pubsub"))]
+    async fn publish(&self, _event: &str, _snapshot: &CatchUpSnapshot) {}
+}
+
+fn record_metrics(snapshot: &CatchUpSnapshot) {
+    let metrics = metrics::consumer();
+    let group = [snapshot.group.as_str()];
+    metrics
+        .catchup_progress
+        .with_label_values(&group)
+        .set(snapshot.percent / 100.0);
+    metrics
+        .catchup_eta_seconds
+        .with_label_values(&group)
+        .set(snapshot.eta_secs.unwrap_or(0) as i64);
+    metrics
+        .block_height
+        .with_label_values(&group)
+        .set(snapshot.block_height as i64);
+    match snapshot.head_block_height {
+        Some(head) => metrics
+            .head_block_height
+            .with_label_values(&group)
+            .set(head as i64),
+        None => {
+            let _ = metrics.head_block_height.remove_label_values(&group);
+        }
+    }
+}
+
+fn format_eta(secs: u64) -> String {
+    format!("{}h{:02}m{:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
@@ -0,0 +1,65 @@
+use crate::models::time;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Wall clock for the parts of the consumer that measure real time: TTLs,
+// cooldowns, catch-up rates and jobs that bucket by the current time. Block
+// times still come from the messages. Components take a clock through
+// `with_clock` and default to the system clock, so tests can drive them with
+// a MockClock instead of sleeping.
+pub trait Clock: Send + Sync {
+    fn now_millis(&self) -> i64;
+
+    // How far the block time lags this clock. Block times ahead of it (skew)
+    // count as zero.
+    fn age(&self, block_time: i64) -> Duration {
+        let lag = self.now_millis() - time::to_millis(block_time);
+        Duration::from_millis(lag.max(0) as u64)
+    }
+
+    fn is_stale(&self, block_time: i64, max_age: Duration) -> bool {
+        self.age(block_time) > max_age
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        time::now_millis()
+    }
+}
+
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+// A clock that only moves when told to
+#[derive(Default)]
+pub struct MockClock {
+    millis: AtomicI64,
+}
+
+impl MockClock {
+    pub fn new(millis: i64) -> Self {
+        MockClock {
+            millis: AtomicI64::new(millis),
+        }
+    }
+
+    pub fn set(&self, millis: i64) {
+        self.millis.store(millis, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.millis
+            .fetch_add(by.as_millis() as i64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_millis(&self) -> i64 {
+        self.millis.load(Ordering::SeqCst)
+    }
+}
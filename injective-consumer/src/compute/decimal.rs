@@ -0,0 +1,166 @@
+use rust_decimal::Decimal;
+
+// Largest scale and number of significant digits a Decimal holds
+const MAX_DIGITS: i64 = 28;
+
+// Value of a chain integer (or decimal string) divided by 10^decimals, e.g. a
+// 1e24-scaled price. Digits past the 28 a Decimal holds are truncated. None
+// if the string is not a number or the value does not fit.
+pub fn from_chain(raw: &str, decimals: u32) -> Option<Decimal> {
+    let raw = raw.trim();
+    let (negative, unsigned) = match raw.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, raw),
+    };
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let digits = format!("{}{}", integer, fraction);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    // value = digits * 10^-scale; drop trailing digits until both the digits
+    // and the scale fit
+    let digits = digits.trim_start_matches('0');
+    let scale = fraction.len() as i64 + decimals as i64;
+    let len = digits.len() as i64;
+    let dropped = (len - MAX_DIGITS).max(scale - MAX_DIGITS).clamp(0, len);
+    let kept = &digits[..(len - dropped) as usize];
+    let scale = scale - dropped;
+
+    let mut mantissa: i128 = if kept.is_empty() {
+        0
+    } else {
+        kept.parse().ok()?
+    };
+    if negative {
+        mantissa = -mantissa;
+    }
+    if scale >= 0 {
+        Decimal::try_from_i128_with_scale(mantissa, scale as u32).ok()
+    } else {
+        let mantissa = mantissa.checked_mul(10i128.checked_pow((-scale) as u32)?)?;
+        Decimal::try_from_i128_with_scale(mantissa, 0).ok()
+    }
+}
+
+// Price at which the position is liquidated. None for a non-positive
+// quantity, entry price or maintenance margin ratio, or on overflow.
+pub fn liquidation_price(
+    is_long: bool,
+    entry_price: Decimal,
+    margin: Decimal,
+    quantity: Decimal,
+    maintenance_margin_ratio: Decimal,
+    market_cumulative_funding: Decimal,
+    position_cumulative_funding_entry: Decimal,
+) -> Option<Decimal> {
+    if quantity <= Decimal::ZERO
+        || entry_price <= Decimal::ZERO
+        || maintenance_margin_ratio <= Decimal::ZERO
+    {
+        return None;
+    }
+
+    let adjusted_margin = margin.checked_add(funding_payment(
+        is_long,
+        quantity,
+        market_cumulative_funding,
+        position_cumulative_funding_entry,
+    )?)?;
+    let unit_margin = adjusted_margin.checked_div(quantity)?;
+
+    if is_long {
+        // (entry_price - unit_margin) / (1 - maintenance_margin_ratio)
+        entry_price
+            .checked_sub(unit_margin)?
+            .checked_div(Decimal::ONE - maintenance_margin_ratio)
+    } else {
+        // (entry_price + unit_margin) / (1 + maintenance_margin_ratio)
+        entry_price
+            .checked_add(unit_margin)?
+            .checked_div(Decimal::ONE + maintenance_margin_ratio)
+    }
+}
+
+// Funding a position has received since it was opened, negative when it has
+// paid. Cumulative funding is paid by longs to shorts, per unit of quantity.
+pub fn funding_payment(
+    is_long: bool,
+    quantity: Decimal,
+    market_cumulative_funding: Decimal,
+    position_cumulative_funding_entry: Decimal,
+) -> Option<Decimal> {
+    let paid_per_unit = market_cumulative_funding.checked_sub(position_cumulative_funding_entry)?;
+    let payment = quantity.checked_mul(paid_per_unit)?;
+    Some(if is_long { -payment } else { payment })
+}
+
+pub fn is_liquidatable(is_long: bool, liquidation_price: Decimal, mark_price: Decimal) -> bool {
+    if is_long {
+        mark_price <= liquidation_price
+    } else {
+        mark_price >= liquidation_price
+    }
+}
+
+// Percentage the mark price has to move before the position is liquidated,
+// negative past the liquidation price. None for a non-positive mark price.
+pub fn distance_to_liquidation(
+    is_long: bool,
+    liquidation_price: Decimal,
+    mark_price: Decimal,
+) -> Option<Decimal> {
+    if mark_price <= Decimal::ZERO {
+        return None;
+    }
+    let distance = if is_long {
+        mark_price.checked_sub(liquidation_price)?
+    } else {
+        liquidation_price.checked_sub(mark_price)?
+    };
+    distance
+        .checked_div(mark_price)?
+        .checked_mul(Decimal::ONE_HUNDRED)
+}
+
+// Profit or loss of the position at the mark price, before funding
+pub fn unrealized_pnl(
+    is_long: bool,
+    entry_price: Decimal,
+    mark_price: Decimal,
+    quantity: Decimal,
+) -> Option<Decimal> {
+    let per_unit = if is_long {
+        mark_price.checked_sub(entry_price)?
+    } else {
+        entry_price.checked_sub(mark_price)?
+    };
+    per_unit.checked_mul(quantity)
+}
+
+// Margin left after unrealized PnL and funding, as a fraction of the notional
+// at the mark price. The position is liquidated once this falls to the
+// maintenance margin ratio. None for a non-positive notional or on overflow.
+pub fn margin_ratio(
+    is_long: bool,
+    entry_price: Decimal,
+    mark_price: Decimal,
+    quantity: Decimal,
+    margin: Decimal,
+    market_cumulative_funding: Decimal,
+    position_cumulative_funding_entry: Decimal,
+) -> Option<Decimal> {
+    let notional = mark_price.checked_mul(quantity)?;
+    if notional <= Decimal::ZERO {
+        return None;
+    }
+    let equity = margin
+        .checked_add(unrealized_pnl(is_long, entry_price, mark_price, quantity)?)?
+        .checked_add(funding_payment(
+            is_long,
+            quantity,
+            market_cumulative_funding,
+            position_cumulative_funding_entry,
+        )?)?;
+    equity.checked_div(notional)
+}
@@ -1,4 +1,12 @@
-// Calculates the liquidation price for a position
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+
+// Liquidation math. The arithmetic lives in `decimal` on rust_decimal, so
+// large notional positions keep the digits the chain gives them; the f64
+// functions here convert at the edges for callers that store floats.
+pub mod decimal;
+
+// Calculates the liquidation price for a position, 0 when it can't be priced
 pub fn calculate_liquidation_price(
     is_long: bool,
     entry_price: f64,
@@ -8,34 +16,119 @@ pub fn calculate_liquidation_price(
     market_cumulative_funding: f64,
     position_cumulative_funding_entry: f64,
 ) -> f64 {
-    // Skip calculation if any required data is missing or invalid
-    if quantity <= 0.0 || entry_price <= 0.0 || maintenance_margin_ratio <= 0.0 {
-        return 0.0;
-    }
+    let to_decimal = |value: f64| Decimal::from_f64(value).unwrap_or_default();
+    decimal::liquidation_price(
+        is_long,
+        to_decimal(entry_price),
+        to_decimal(margin),
+        to_decimal(quantity),
+        to_decimal(maintenance_margin_ratio),
+        to_decimal(market_cumulative_funding),
+        to_decimal(position_cumulative_funding_entry),
+    )
+    .and_then(|price| price.to_f64())
+    .unwrap_or(0.0)
+}
 
-    // Calculate funding-adjusted margin
-    let unrealized_funding_payment =
-        quantity * (market_cumulative_funding - position_cumulative_funding_entry);
+// Funding a position has received since it was opened, negative when it has
+// paid, 0 when it can't be computed
+pub fn funding_payment(
+    is_long: bool,
+    quantity: f64,
+    market_cumulative_funding: f64,
+    position_cumulative_funding_entry: f64,
+) -> f64 {
+    let to_decimal = |value: f64| Decimal::from_f64(value).unwrap_or_default();
+    decimal::funding_payment(
+        is_long,
+        to_decimal(quantity),
+        to_decimal(market_cumulative_funding),
+        to_decimal(position_cumulative_funding_entry),
+    )
+    .and_then(|payment| payment.to_f64())
+    .unwrap_or(0.0)
+}
 
-    // For longs, Margin -= Funding
-    // For shorts, Margin += Funding
-    let adjusted_margin = if is_long {
-        margin - unrealized_funding_payment
-    } else {
-        margin + unrealized_funding_payment
-    };
+// Profit or loss of a position at the mark price before funding, 0 when it
+// can't be computed
+pub fn calculate_unrealized_pnl(
+    is_long: bool,
+    entry_price: f64,
+    mark_price: f64,
+    quantity: f64,
+) -> f64 {
+    let to_decimal = |value: f64| Decimal::from_f64(value).unwrap_or_default();
+    decimal::unrealized_pnl(
+        is_long,
+        to_decimal(entry_price),
+        to_decimal(mark_price),
+        to_decimal(quantity),
+    )
+    .and_then(|pnl| pnl.to_f64())
+    .unwrap_or(0.0)
+}
 
-    // Calculate unit margin (margin per contract)
-    let unit_margin = adjusted_margin / quantity;
+// Margin left after unrealized PnL and funding over the notional at the mark
+// price, 0 when it can't be computed
+pub fn calculate_margin_ratio(
+    is_long: bool,
+    entry_price: f64,
+    mark_price: f64,
+    quantity: f64,
+    margin: f64,
+    market_cumulative_funding: f64,
+    position_cumulative_funding_entry: f64,
+) -> f64 {
+    let to_decimal = |value: f64| Decimal::from_f64(value).unwrap_or_default();
+    decimal::margin_ratio(
+        is_long,
+        to_decimal(entry_price),
+        to_decimal(mark_price),
+        to_decimal(quantity),
+        to_decimal(margin),
+        to_decimal(market_cumulative_funding),
+        to_decimal(position_cumulative_funding_entry),
+    )
+    .and_then(|ratio| ratio.to_f64())
+    .unwrap_or(0.0)
+}
 
-    // Calculate liquidation price
-    if is_long {
-        // For long positions: liquidation_price = (entry_price - unit_margin) / (1 - maintenance_margin_ratio)
-        (entry_price - unit_margin) / (1.0 - maintenance_margin_ratio)
+// Hourly funding rate the chain settles at for a premium: the premium plus
+// the hourly interest rate, capped at the hourly funding rate cap either way.
+// Positive when longs pay shorts.
+pub fn funding_rate(premium: f64, hourly_interest_rate: f64, hourly_funding_rate_cap: f64) -> f64 {
+    let cap = hourly_funding_rate_cap.abs();
+    (premium + hourly_interest_rate).clamp(-cap, cap)
+}
+
+// Rate the next funding is heading for. The chain accumulates the daily
+// premium of the market over its index in cumulative_price, second by
+// second since the last funding, and averages it over the hour.
+pub fn estimated_funding_rate(
+    cumulative_price: f64,
+    secs_since_funding: i64,
+    hourly_interest_rate: f64,
+    hourly_funding_rate_cap: f64,
+) -> f64 {
+    let premium = if secs_since_funding > 0 {
+        cumulative_price / (secs_since_funding as f64 * 24.0)
     } else {
-        // For short positions: liquidation_price = (entry_price + unit_margin) / (1 + maintenance_margin_ratio)
-        (entry_price + unit_margin) / (1.0 + maintenance_margin_ratio)
+        0.0
+    };
+    funding_rate(premium, hourly_interest_rate, hourly_funding_rate_cap)
+}
+
+// Rate paid between two cumulative funding values, as a fraction of the mark
+// price; 0 without a mark price
+pub fn paid_funding_rate(
+    previous_cumulative_funding: f64,
+    cumulative_funding: f64,
+    mark_price: f64,
+) -> f64 {
+    if mark_price <= 0.0 {
+        return 0.0;
     }
+    (cumulative_funding - previous_cumulative_funding) / mark_price
 }
 
 /// Checks if a position is liquidatable
@@ -48,3 +141,16 @@ pub fn is_liquidatable(is_long: bool, liquidation_price: f64, mark_price: f64) -
         mark_price >= liquidation_price
     }
 }
+
+/// Percentage the mark price has to move before a position is liquidated.
+/// Negative once the position is past its liquidation price.
+pub fn distance_to_liquidation(is_long: bool, liquidation_price: f64, mark_price: f64) -> f64 {
+    if mark_price <= 0.0 {
+        return f64::INFINITY;
+    }
+    if is_long {
+        (mark_price - liquidation_price) / mark_price * 100.0
+    } else {
+        (liquidation_price - mark_price) / mark_price * 100.0
+    }
+}
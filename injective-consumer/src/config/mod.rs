@@ -1,14 +1,57 @@
+use crate::candles::Resolution;
+use crate::secrets::{self, Secret};
+use rdkafka::ClientConfig;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::error::Error;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub grpc: GrpcConfig,
     pub kafka: KafkaConfig,
+    #[serde(default)]
+    pub scylladb: ScyllaDBConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub position_diff: PositionDiffConfig,
+    #[serde(default)]
+    pub spot_trades: SpotTradesConfig,
+    #[serde(default)]
+    pub subaccount_trades: SubaccountTradesConfig,
+    #[serde(default)]
+    pub pubsub_dedup: PubSubDedupConfig,
+    #[serde(default)]
+    pub ingest_dedup: IngestDedupConfig,
+    #[serde(default)]
+    pub payload_log: PayloadLogConfig,
+    #[serde(default)]
+    pub liquidation: LiquidationConfig,
+    #[serde(default)]
+    pub correlation: CorrelationConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub candles: CandlesConfig,
+    #[serde(default)]
+    pub window: WindowConfig,
+    #[serde(default)]
+    pub depth: DepthConfig,
+    #[serde(default)]
+    pub mid_price: MidPriceConfig,
+    #[serde(default)]
+    pub live_books: LiveBooksConfig,
+    #[serde(default)]
+    pub catch_up: CatchUpConfig,
+    #[serde(default)]
+    pub reaper: IndexReaperConfig,
+    #[serde(default)]
+    pub redis_keys: RedisKeySchema,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +70,848 @@ pub struct KafkaConfig {
     pub redis_consumer_group: Option<String>,
     #[serde(default)]
     pub scylladb_consumer_group: Option<String>,
+    // Subscribe to these instead of `topic`, for producers that route each
+    // stream to its own topic
+    #[serde(default)]
+    pub topics: Vec<String>,
+    // Topic carrying market snapshots, the only one the market preloader reads
+    #[serde(default)]
+    pub markets_topic: Option<String>,
+    // Topic carrying orderbooks, read only by consumers that apply them
+    #[serde(default)]
+    pub orderbooks_topic: Option<String>,
+    // Encoding of messages without a format header
+    #[serde(default)]
+    pub format: SerializationFormat,
+    // Messages that fail to decode or process are republished here
+    #[serde(default)]
+    pub dead_letter_topic: Option<String>,
+    #[serde(default)]
+    pub security: KafkaSecurity,
+    // Static group membership: a stable id per replica (such as the pod name)
+    // lets a restarted consumer take its partitions back without a rebalance,
+    // as long as it returns within the session timeout
+    #[serde(default)]
+    pub group_instance_id: Option<String>,
+    // 6s by default, 45s with a group instance id to cover a restart
+    #[serde(default)]
+    pub session_timeout_ms: Option<u64>,
+}
+
+/// Broker authentication and encryption for managed clusters (MSK,
+/// Confluent Cloud). The SASL credentials and the key password are better
+/// supplied as secrets (KAFKA_SASL_USERNAME, KAFKA_SASL_PASSWORD,
+/// KAFKA_SSL_KEY_PASSWORD) than in the config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KafkaSecurity {
+    // Plaintext when unset
+    pub protocol: Option<SecurityProtocol>,
+    // PLAIN when the protocol uses SASL and no mechanism is set
+    pub sasl_mechanism: Option<SaslMechanism>,
+    pub sasl_username: Option<String>,
+    #[serde(skip_serializing)]
+    pub sasl_password: Option<Secret>,
+    // PEM CA bundle for brokers with a private CA; the system store otherwise
+    pub ssl_ca_location: Option<String>,
+    // Client certificate and key, for brokers that require mutual TLS
+    pub ssl_certificate_location: Option<String>,
+    pub ssl_key_location: Option<String>,
+    #[serde(skip_serializing)]
+    pub ssl_key_password: Option<Secret>,
+    // Broker hostname verification, on unless set to false
+    pub ssl_endpoint_identification: Option<bool>,
+}
+
+impl KafkaSecurity {
+    pub fn apply(&self, client: &mut ClientConfig) {
+        let protocol = match self.protocol {
+            Some(protocol) => protocol,
+            None => return,
+        };
+        client.set("security.protocol", protocol.as_str());
+
+        if protocol.uses_sasl() {
+            let mechanism = self.sasl_mechanism.unwrap_or_default();
+            client.set("sasl.mechanism", mechanism.as_str());
+            if let Some(username) = &self.sasl_username {
+                client.set("sasl.username", username);
+            }
+            if let Some(password) = &self.sasl_password {
+                client.set("sasl.password", password.expose());
+            }
+        }
+
+        if protocol.uses_ssl() {
+            if let Some(location) = &self.ssl_ca_location {
+                client.set("ssl.ca.location", location);
+            }
+            if let Some(location) = &self.ssl_certificate_location {
+                client.set("ssl.certificate.location", location);
+            }
+            if let Some(location) = &self.ssl_key_location {
+                client.set("ssl.key.location", location);
+            }
+            if let Some(password) = &self.ssl_key_password {
+                client.set("ssl.key.password", password.expose());
+            }
+            if let Some(verify) = self.ssl_endpoint_identification {
+                let algorithm = if verify { "https" } else { "none" };
+                client.set("ssl.endpoint.identification.algorithm", algorithm);
+            }
+        }
+    }
+
+    // Settings librdkafka would only reject once it tries to connect
+    pub fn validate(&self) -> Result<(), String> {
+        let protocol = self.protocol.unwrap_or_default();
+        if protocol.uses_sasl() && (self.sasl_username.is_none() || self.sasl_password.is_none()) {
+            return Err(format!(
+                "{} needs KAFKA_SASL_USERNAME and KAFKA_SASL_PASSWORD",
+                protocol.as_str()
+            ));
+        }
+        if !protocol.uses_sasl() && (self.sasl_mechanism.is_some() || self.sasl_username.is_some())
+        {
+            return Err(format!(
+                "SASL credentials are set but the protocol is {}",
+                protocol.as_str()
+            ));
+        }
+        if self.ssl_certificate_location.is_some() != self.ssl_key_location.is_some() {
+            return Err("ssl_certificate_location and ssl_key_location go together".to_string());
+        }
+        let ssl_set = self.ssl_ca_location.is_some() || self.ssl_certificate_location.is_some();
+        if ssl_set && !protocol.uses_ssl() {
+            return Err(format!(
+                "SSL files are set but the protocol is {}",
+                protocol.as_str()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SecurityProtocol {
+    #[default]
+    Plaintext,
+    Ssl,
+    SaslPlaintext,
+    SaslSsl,
+}
+
+impl SecurityProtocol {
+    // Value of librdkafka's security.protocol
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecurityProtocol::Plaintext => "PLAINTEXT",
+            SecurityProtocol::Ssl => "SSL",
+            SecurityProtocol::SaslPlaintext => "SASL_PLAINTEXT",
+            SecurityProtocol::SaslSsl => "SASL_SSL",
+        }
+    }
+
+    pub fn uses_sasl(&self) -> bool {
+        matches!(
+            self,
+            SecurityProtocol::SaslPlaintext | SecurityProtocol::SaslSsl
+        )
+    }
+
+    pub fn uses_ssl(&self) -> bool {
+        matches!(self, SecurityProtocol::Ssl | SecurityProtocol::SaslSsl)
+    }
+}
+
+impl std::str::FromStr for SecurityProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().replace('-', "_").as_str() {
+            "PLAINTEXT" => Ok(SecurityProtocol::Plaintext),
+            "SSL" => Ok(SecurityProtocol::Ssl),
+            "SASL_PLAINTEXT" => Ok(SecurityProtocol::SaslPlaintext),
+            "SASL_SSL" => Ok(SecurityProtocol::SaslSsl),
+            other => Err(format!("Unknown Kafka security protocol: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SaslMechanism {
+    #[default]
+    #[serde(rename = "PLAIN")]
+    Plain,
+    #[serde(rename = "SCRAM-SHA-256")]
+    ScramSha256,
+    #[serde(rename = "SCRAM-SHA-512")]
+    ScramSha512,
+}
+
+impl SaslMechanism {
+    // Value of librdkafka's sasl.mechanism
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SaslMechanism::Plain => "PLAIN",
+            SaslMechanism::ScramSha256 => "SCRAM-SHA-256",
+            SaslMechanism::ScramSha512 => "SCRAM-SHA-512",
+        }
+    }
+}
+
+impl std::str::FromStr for SaslMechanism {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().replace('_', "-").as_str() {
+            "PLAIN" => Ok(SaslMechanism::Plain),
+            "SCRAM-SHA-256" => Ok(SaslMechanism::ScramSha256),
+            "SCRAM-SHA-512" => Ok(SaslMechanism::ScramSha512),
+            other => Err(format!("Unknown SASL mechanism: {}", other)),
+        }
+    }
+}
+
+impl KafkaConfig {
+    pub fn session_timeout_ms(&self) -> u64 {
+        match (self.session_timeout_ms, &self.group_instance_id) {
+            (Some(timeout), _) => timeout,
+            (None, Some(_)) => 45_000,
+            (None, None) => 6_000,
+        }
+    }
+
+    // Topics a consumer reads; the orderbook topic only if it applies books
+    pub fn subscribed_topics(&self, orderbooks: bool) -> Vec<&str> {
+        let mut topics: Vec<&str> = if self.topics.is_empty() {
+            vec![self.topic.as_str()]
+        } else {
+            self.topics.iter().map(|t| t.as_str()).collect()
+        };
+        if let Some(orderbooks_topic) = self.orderbooks_topic.as_deref() {
+            topics.retain(|topic| *topic != orderbooks_topic);
+            if orderbooks {
+                topics.push(orderbooks_topic);
+            }
+        }
+        topics
+    }
+}
+
+/// Encoding of Kafka message values. Producer and consumers must agree; the
+/// producer also names the format in a header, which consumers prefer.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SerializationFormat {
+    #[default]
+    Json,
+    Flatbuffers,
+    Protobuf,
+}
+
+impl SerializationFormat {
+    // Value of the format header
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SerializationFormat::Json => "json",
+            SerializationFormat::Flatbuffers => "flatbuffers",
+            SerializationFormat::Protobuf => "protobuf",
+        }
+    }
+}
+
+impl std::str::FromStr for SerializationFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(SerializationFormat::Json),
+            "flatbuffers" => Ok(SerializationFormat::Flatbuffers),
+            "protobuf" => Ok(SerializationFormat::Protobuf),
+            other => Err(format!("Unknown serialization format: {}", other)),
+        }
+    }
+}
+
+/// Where the write timestamp (`USING TIMESTAMP`) for Scylla mutations comes from.
+/// Deriving it from the block keeps replayed history from clobbering newer rows.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteTimestampSource {
+    #[default]
+    BlockTime,
+    BlockHeight,
+    Server,
+}
+
+impl std::str::FromStr for WriteTimestampSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "block_time" => Ok(WriteTimestampSource::BlockTime),
+            "block_height" => Ok(WriteTimestampSource::BlockHeight),
+            "server" => Ok(WriteTimestampSource::Server),
+            other => Err(format!("Unknown write timestamp source: {}", other)),
+        }
+    }
+}
+
+/// How the Scylla processor guards against applying a replayed message twice.
+/// Lightweight transactions are exact but cost a Paxos round per message.
+/// Entity mode keys on each position, trade and market of the block instead
+/// of the whole message, so it also catches replays that were batched
+/// differently from the first delivery.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IdempotencyMode {
+    #[default]
+    Off,
+    ContentHash,
+    LightweightTransactions,
+    Entity,
+}
+
+impl std::str::FromStr for IdempotencyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(IdempotencyMode::Off),
+            "content_hash" => Ok(IdempotencyMode::ContentHash),
+            "lwt" | "lightweight_transactions" => Ok(IdempotencyMode::LightweightTransactions),
+            "entity" => Ok(IdempotencyMode::Entity),
+            other => Err(format!("Unknown idempotency mode: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScyllaDBConfig {
+    pub write_timestamp_source: WriteTimestampSource,
+    pub idempotency: IdempotencyMode,
+    // Default client-side timeout for every statement
+    pub statement_timeout_ms: u64,
+    // Per-table overrides of the statement timeout, keyed by table name
+    pub statement_timeouts_ms: HashMap<String, u64>,
+    // Enable Scylla tracing for one in every N statements (0 disables tracing)
+    pub tracing_sample_every: u64,
+    // Statements slower than this are logged with their table and duration
+    pub slow_query_threshold_ms: u64,
+    // Orderbook order rows per unlogged batch
+    pub orderbook_batch_size: usize,
+    // Upper bound on the values of one orderbook batch, kept below the
+    // server's batch_size_fail_threshold_in_kb
+    pub orderbook_batch_max_bytes: usize,
+    // Orderbook batches in flight at once
+    pub orderbook_write_concurrency: usize,
+    // Password authentication; SCYLLADB_USERNAME and SCYLLADB_PASSWORD
+    pub username: Option<String>,
+    #[serde(skip_serializing)]
+    pub password: Option<Secret>,
+}
+
+#[cfg(feature = "scylla-sink")]
+impl ScyllaDBConfig {
+    // Session settings shared by the processor and the config check
+    pub fn session_builder(&self, nodes: &[String]) -> scylla::SessionBuilder {
+        let builder = scylla::SessionBuilder::new().known_nodes(nodes);
+        match (&self.username, &self.password) {
+            (Some(username), Some(password)) => builder.user(username, password.expose()),
+            _ => builder,
+        }
+    }
+}
+
+impl Default for ScyllaDBConfig {
+    fn default() -> Self {
+        ScyllaDBConfig {
+            write_timestamp_source: WriteTimestampSource::default(),
+            idempotency: IdempotencyMode::default(),
+            statement_timeout_ms: 5000,
+            statement_timeouts_ms: HashMap::new(),
+            tracing_sample_every: 0,
+            slow_query_threshold_ms: 250,
+            orderbook_batch_size: 100,
+            orderbook_batch_max_bytes: 32 * 1024,
+            orderbook_write_concurrency: 8,
+            username: None,
+            password: None,
+        }
+    }
+}
+
+impl ScyllaDBConfig {
+    pub fn statement_timeout(&self, table: &str) -> Duration {
+        let millis = self
+            .statement_timeouts_ms
+            .get(table)
+            .copied()
+            .unwrap_or(self.statement_timeout_ms);
+        Duration::from_millis(millis)
+    }
+}
+
+/// Operator scripts run over every message and event before it is stored or
+/// published. Script hooks require the `scripting` feature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    // Paths of Rhai scripts, applied in order
+    pub scripts: Vec<String>,
+    // Upper bound on operations per script call, so a runaway script can't stall the pipeline
+    pub max_operations: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        HooksConfig {
+            scripts: Vec::new(),
+            max_operations: 100_000,
+        }
+    }
+}
+
+/// When enabled, position snapshots from the heartbeat are turned into deltas,
+/// so only changed or closed positions are published and written to history.
+/// Off by default, so every snapshot is published in full.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PositionDiffConfig {
+    pub enabled: bool,
+    // Emit every position once per this many snapshots so consumers can resync
+    pub full_snapshot_every: u64,
+}
+
+impl Default for PositionDiffConfig {
+    fn default() -> Self {
+        PositionDiffConfig {
+            enabled: false,
+            full_snapshot_every: 60,
+        }
+    }
+}
+
+/// Spot trade handling in the Redis processor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpotTradesConfig {
+    pub enabled: bool,
+    // Most recent trades kept per spot market
+    pub recent_trades: usize,
+}
+
+impl Default for SpotTradesConfig {
+    fn default() -> Self {
+        SpotTradesConfig {
+            enabled: true,
+            recent_trades: 100,
+        }
+    }
+}
+
+/// Per-subaccount recent-trade lists in Redis, the cache in front of the
+/// ScyllaDB trades_by_subaccount table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubaccountTradesConfig {
+    pub enabled: bool,
+    // Most recent trade sides kept per subaccount
+    pub recent_trades: usize,
+}
+
+impl Default for SubaccountTradesConfig {
+    fn default() -> Self {
+        SubaccountTradesConfig {
+            enabled: true,
+            recent_trades: 200,
+        }
+    }
+}
+
+/// Publish each event once when several consumer replicas process the same
+/// blocks, e.g. while partitions are rebalanced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PubSubDedupConfig {
+    pub enabled: bool,
+    // How long a published event blocks identical ones
+    pub ttl_secs: u64,
+}
+
+impl Default for PubSubDedupConfig {
+    fn default() -> Self {
+        PubSubDedupConfig {
+            enabled: false,
+            ttl_secs: 30,
+        }
+    }
+}
+
+/// Apply each message once per consumer group, when two ingesters publish
+/// the same blocks for redundancy or a rebalance redelivers messages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IngestDedupConfig {
+    pub enabled: bool,
+    pub backend: DedupBackend,
+    // How long an applied message blocks its copies; covers how far the
+    // ingesters can drift apart
+    pub ttl_secs: u64,
+    // Messages the memory backend remembers per process
+    pub memory_capacity: usize,
+}
+
+impl Default for IngestDedupConfig {
+    fn default() -> Self {
+        IngestDedupConfig {
+            enabled: false,
+            backend: DedupBackend::default(),
+            ttl_secs: 600,
+            memory_capacity: 100_000,
+        }
+    }
+}
+
+/// Where message claims are kept. Redis claims are shared by every member of
+/// a group; memory claims only by the consumers of one process.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupBackend {
+    #[default]
+    Redis,
+    Memory,
+}
+
+impl std::str::FromStr for DedupBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "redis" => Ok(DedupBackend::Redis),
+            "memory" => Ok(DedupBackend::Memory),
+            other => Err(format!("Unknown dedup backend: {}", other)),
+        }
+    }
+}
+
+/// Which raw Kafka payloads are written to the log
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadLogMode {
+    Off,
+    // Every Nth payload, plus payloads that failed
+    Sampled,
+    #[default]
+    ErrorsOnly,
+}
+
+impl std::str::FromStr for PayloadLogMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(PayloadLogMode::Off),
+            "sampled" => Ok(PayloadLogMode::Sampled),
+            "errors_only" => Ok(PayloadLogMode::ErrorsOnly),
+            other => Err(format!("Unknown payload log mode: {}", other)),
+        }
+    }
+}
+
+/// Logging of raw Kafka payloads. Logged payloads have subaccount ids redacted
+/// and are truncated, and the policy can be changed at runtime through Redis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PayloadLogConfig {
+    pub mode: PayloadLogMode,
+    // With the sampled mode, log one in every N payloads
+    pub sample_every: u64,
+    // Logged payloads are cut to this many bytes
+    pub max_bytes: usize,
+    pub redact_subaccounts: bool,
+}
+
+impl Default for PayloadLogConfig {
+    fn default() -> Self {
+        PayloadLogConfig {
+            mode: PayloadLogMode::ErrorsOnly,
+            sample_every: 1_000,
+            max_bytes: 2_048,
+            redact_subaccounts: true,
+        }
+    }
+}
+
+/// Periodic re-evaluation of stored positions against cached mark prices,
+/// independent of incoming market and position messages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiquidationConfig {
+    // Seconds between recompute passes (0 disables the task)
+    pub recompute_interval_secs: u64,
+    // Positions closest to liquidation published after each pass (0 disables)
+    pub at_risk_top_k: usize,
+    // How long a replayed block is kept from alerting a position again
+    // (0 alerts on every update of a liquidatable position)
+    pub alert_dedup_ttl_secs: u64,
+    // Recompute every position as soon as a market settles funding, also
+    // when the timed recompute is off
+    pub recompute_on_funding: bool,
+}
+
+impl Default for LiquidationConfig {
+    fn default() -> Self {
+        LiquidationConfig {
+            recompute_interval_secs: 5,
+            at_risk_top_k: 50,
+            alert_dedup_ttl_secs: 86400,
+            recompute_on_funding: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorrelationConfig {
+    // Seconds between correlation matrix updates (0 disables the job)
+    pub interval_secs: u64,
+    // Markets to correlate; every derivative market when empty
+    pub markets: Vec<String>,
+    // Hourly candles the returns are taken from
+    pub lookback_hours: usize,
+}
+
+impl Default for CorrelationConfig {
+    fn default() -> Self {
+        CorrelationConfig {
+            interval_secs: 3600,
+            markets: Vec::new(),
+            lookback_hours: 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CandlesConfig {
+    pub enabled: bool,
+    // Bars maintained from trades, written to ScyllaDB
+    pub resolutions: Vec<Resolution>,
+}
+
+impl Default for CandlesConfig {
+    fn default() -> Self {
+        CandlesConfig {
+            enabled: true,
+            resolutions: vec![
+                Resolution::OneMinute,
+                Resolution::FiveMinutes,
+                Resolution::FifteenMinutes,
+                Resolution::OneHour,
+                Resolution::OneDay,
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    // Seconds a candle, hourly statistics or summary hour stays open after it
+    // ends, by block time; trades from later blocks than that are dropped
+    pub allowed_lateness_secs: u64,
+}
+
+// L2 levels built from full orderbooks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DepthConfig {
+    // Level width in human price units per market id; markets without one
+    // get a level per price
+    pub tick_sizes: HashMap<String, f64>,
+    // Publish the best levels of every full book as DepthSnapshot events
+    pub publish: bool,
+    // Levels per side in a DepthSnapshot event
+    pub publish_levels: usize,
+}
+
+impl Default for DepthConfig {
+    fn default() -> Self {
+        DepthConfig {
+            tick_sizes: HashMap::new(),
+            publish: false,
+            publish_levels: 20,
+        }
+    }
+}
+
+// Derivative books kept from the stream's orderbook deltas, resnapshotted
+// with the L3 query on grpc.query_endpoint when deltas go missing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiveBooksConfig {
+    pub enabled: bool,
+    // How long an L3 snapshot query may take
+    pub snapshot_timeout_secs: u64,
+}
+
+impl Default for LiveBooksConfig {
+    fn default() -> Self {
+        LiveBooksConfig {
+            enabled: true,
+            snapshot_timeout_secs: 10,
+        }
+    }
+}
+
+// Progress reports while the consumers work through a Kafka backlog
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CatchUpConfig {
+    // Seconds between reports (0 disables them)
+    pub report_interval_secs: u64,
+}
+
+impl Default for CatchUpConfig {
+    fn default() -> Self {
+        CatchUpConfig {
+            report_interval_secs: 30,
+        }
+    }
+}
+
+// The background task pruning stale members from the Redis index sets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexReaperConfig {
+    pub enabled: bool,
+    // Seconds between passes over the index sets
+    pub interval_secs: u64,
+    // Keys or set members fetched per SCAN / SSCAN round trip
+    pub scan_count: usize,
+    // Pause between batches so a pass never monopolises Redis
+    pub batch_pause_ms: u64,
+}
+
+impl Default for IndexReaperConfig {
+    fn default() -> Self {
+        IndexReaperConfig {
+            enabled: true,
+            interval_secs: 300,
+            scan_count: 200,
+            batch_pause_ms: 50,
+        }
+    }
+}
+
+// Mid prices of full books and their divergence from the mark price
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidPriceConfig {
+    // Points kept per market in Redis (0 keeps none)
+    pub recent_points: usize,
+    // Mark against mid, in basis points, past which an alert is raised (0
+    // disables alerts)
+    pub divergence_alert_bps: f64,
+}
+
+impl Default for MidPriceConfig {
+    fn default() -> Self {
+        MidPriceConfig {
+            recent_points: 1_000,
+            divergence_alert_bps: 100.0,
+        }
+    }
+}
+
+/// Where the processors keep market, position and book state and trade and
+/// funding history
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// State in Redis, history in ScyllaDB
+    #[default]
+    Native,
+    /// Both in process memory, lost on restart
+    Memory,
+}
+
+impl std::str::FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "native" => Ok(StorageBackend::Native),
+            "memory" => Ok(StorageBackend::Memory),
+            other => Err(format!("Unknown storage backend: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    // Trades and funding points kept per market by the memory backend
+    pub memory_history_limit: usize,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: StorageBackend::Native,
+            memory_history_limit: 10_000,
+        }
+    }
+}
+
+/// Namespace for every Redis key and plain channel, so several indexers can
+/// share one Redis. The defaults keep the unprefixed layout.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct RedisKeySchema {
+    // First segment of every key, e.g. the indexer's name; empty for none
+    pub prefix: String,
+    // Joins the segments of a key
+    pub separator: String,
+    // Deployment tag after the prefix, e.g. "testnet"
+    pub environment: Option<String>,
+}
+
+impl Default for RedisKeySchema {
+    fn default() -> Self {
+        RedisKeySchema {
+            prefix: String::new(),
+            separator: ":".to_string(),
+            environment: None,
+        }
+    }
+}
+
+impl RedisKeySchema {
+    // REDIS_KEY_PREFIX, REDIS_KEY_SEPARATOR and REDIS_KEY_ENV, for the binaries
+    // that read the consumer's keys without its full config
+    pub fn from_env() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut schema = RedisKeySchema::default();
+
+        if let Ok(prefix) = env::var("REDIS_KEY_PREFIX") {
+            schema.prefix = prefix;
+        }
+
+        if let Ok(separator) = env::var("REDIS_KEY_SEPARATOR") {
+            if separator.is_empty() {
+                return Err("REDIS_KEY_SEPARATOR must not be empty".into());
+            }
+            schema.separator = separator;
+        }
+
+        schema.environment = env::var("REDIS_KEY_ENV").ok();
+        Ok(schema)
+    }
 }
 
 impl Default for Config {
@@ -43,7 +928,34 @@ impl Default for Config {
                 consumer_group: "injective-consumer".to_string(),
                 redis_consumer_group: None,
                 scylladb_consumer_group: None,
+                topics: Vec::new(),
+                markets_topic: None,
+                orderbooks_topic: None,
+                format: SerializationFormat::default(),
+                dead_letter_topic: None,
+                security: KafkaSecurity::default(),
+                group_instance_id: None,
+                session_timeout_ms: None,
             },
+            scylladb: ScyllaDBConfig::default(),
+            hooks: HooksConfig::default(),
+            position_diff: PositionDiffConfig::default(),
+            spot_trades: SpotTradesConfig::default(),
+            subaccount_trades: SubaccountTradesConfig::default(),
+            pubsub_dedup: PubSubDedupConfig::default(),
+            ingest_dedup: IngestDedupConfig::default(),
+            payload_log: PayloadLogConfig::default(),
+            liquidation: LiquidationConfig::default(),
+            correlation: CorrelationConfig::default(),
+            storage: StorageConfig::default(),
+            candles: CandlesConfig::default(),
+            window: WindowConfig::default(),
+            depth: DepthConfig::default(),
+            mid_price: MidPriceConfig::default(),
+            live_books: LiveBooksConfig::default(),
+            catch_up: CatchUpConfig::default(),
+            reaper: IndexReaperConfig::default(),
+            redis_keys: RedisKeySchema::default(),
         }
     }
 }
@@ -54,18 +966,20 @@ impl Config {
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
 
-        let config: Config = serde_json::from_str(&contents)?;
+        let mut config: Config = serde_json::from_str(&contents)?;
+        config.load_secrets()?;
         Ok(config)
     }
 
     pub fn from_env() -> Result<Self, Box<dyn Error + Send + Sync>> {
         let mut config = Config::default();
 
-        if let Ok(stream_endpoint) = env::var("GRPC_STREAM_ENDPOINT") {
+        // Endpoints may embed a provider API key, so they are read as secrets
+        if let Some(stream_endpoint) = secrets::load("GRPC_STREAM_ENDPOINT")? {
             config.grpc.stream_endpoint = stream_endpoint;
         }
 
-        if let Ok(query_endpoint) = env::var("GRPC_QUERY_ENDPOINT") {
+        if let Some(query_endpoint) = secrets::load("GRPC_QUERY_ENDPOINT")? {
             config.grpc.query_endpoint = query_endpoint;
         }
 
@@ -77,10 +991,30 @@ impl Config {
             config.kafka.topic = topic;
         }
 
+        if let Ok(topics) = env::var("KAFKA_TOPICS") {
+            config.kafka.topics = topics.split(',').map(|s| s.to_string()).collect();
+        }
+
+        if let Ok(topic) = env::var("KAFKA_MARKETS_TOPIC") {
+            config.kafka.markets_topic = Some(topic);
+        }
+
+        if let Ok(topic) = env::var("KAFKA_ORDERBOOKS_TOPIC") {
+            config.kafka.orderbooks_topic = Some(topic);
+        }
+
         if let Ok(client_id) = env::var("KAFKA_CLIENT_ID") {
             config.kafka.client_id = client_id;
         }
 
+        if let Ok(format) = env::var("KAFKA_FORMAT") {
+            config.kafka.format = format.parse()?;
+        }
+
+        if let Ok(topic) = env::var("KAFKA_DLQ_TOPIC") {
+            config.kafka.dead_letter_topic = Some(topic);
+        }
+
         if let Ok(consumer_group) = env::var("KAFKA_CONSUMER_GROUP") {
             config.kafka.consumer_group = consumer_group;
         }
@@ -93,6 +1027,254 @@ impl Config {
             config.kafka.scylladb_consumer_group = Some(scylladb_group);
         }
 
+        config.kafka.group_instance_id = env::var("KAFKA_GROUP_INSTANCE_ID").ok();
+
+        if let Ok(timeout) = env::var("KAFKA_SESSION_TIMEOUT_MS") {
+            config.kafka.session_timeout_ms = Some(timeout.parse()?);
+        }
+
+        if let Ok(source) = env::var("SCYLLADB_WRITE_TIMESTAMP") {
+            config.scylladb.write_timestamp_source = source.parse()?;
+        }
+
+        if let Ok(mode) = env::var("SCYLLADB_IDEMPOTENCY") {
+            config.scylladb.idempotency = mode.parse()?;
+        }
+
+        if let Ok(timeout) = env::var("SCYLLADB_STATEMENT_TIMEOUT_MS") {
+            config.scylladb.statement_timeout_ms = timeout.parse()?;
+        }
+
+        if let Ok(sample_every) = env::var("SCYLLADB_TRACING_SAMPLE_EVERY") {
+            config.scylladb.tracing_sample_every = sample_every.parse()?;
+        }
+
+        if let Ok(threshold) = env::var("SCYLLADB_SLOW_QUERY_THRESHOLD_MS") {
+            config.scylladb.slow_query_threshold_ms = threshold.parse()?;
+        }
+
+        if let Ok(size) = env::var("SCYLLADB_ORDERBOOK_BATCH_SIZE") {
+            config.scylladb.orderbook_batch_size = size.parse()?;
+        }
+
+        if let Ok(max_bytes) = env::var("SCYLLADB_ORDERBOOK_BATCH_MAX_BYTES") {
+            config.scylladb.orderbook_batch_max_bytes = max_bytes.parse()?;
+        }
+
+        if let Ok(concurrency) = env::var("SCYLLADB_ORDERBOOK_WRITE_CONCURRENCY") {
+            config.scylladb.orderbook_write_concurrency = concurrency.parse()?;
+        }
+
+        if let Ok(enabled) = env::var("POSITION_DIFF_ENABLED") {
+            config.position_diff.enabled = enabled.parse()?;
+        }
+
+        if let Ok(every) = env::var("POSITION_FULL_SNAPSHOT_EVERY") {
+            config.position_diff.full_snapshot_every = every.parse()?;
+        }
+
+        if let Ok(enabled) = env::var("SPOT_TRADES_ENABLED") {
+            config.spot_trades.enabled = enabled.parse()?;
+        }
+
+        if let Ok(recent) = env::var("SPOT_RECENT_TRADES") {
+            config.spot_trades.recent_trades = recent.parse()?;
+        }
+
+        if let Ok(enabled) = env::var("SUBACCOUNT_TRADES_ENABLED") {
+            config.subaccount_trades.enabled = enabled.parse()?;
+        }
+
+        if let Ok(recent) = env::var("SUBACCOUNT_RECENT_TRADES") {
+            config.subaccount_trades.recent_trades = recent.parse()?;
+        }
+
+        if let Ok(enabled) = env::var("PUBSUB_DEDUP_ENABLED") {
+            config.pubsub_dedup.enabled = enabled.parse()?;
+        }
+
+        if let Ok(ttl) = env::var("PUBSUB_DEDUP_TTL_SECS") {
+            config.pubsub_dedup.ttl_secs = ttl.parse()?;
+        }
+
+        if let Ok(enabled) = env::var("INGEST_DEDUP_ENABLED") {
+            config.ingest_dedup.enabled = enabled.parse()?;
+        }
+
+        if let Ok(ttl) = env::var("INGEST_DEDUP_TTL_SECS") {
+            config.ingest_dedup.ttl_secs = ttl.parse()?;
+        }
+
+        if let Ok(backend) = env::var("INGEST_DEDUP_BACKEND") {
+            config.ingest_dedup.backend = backend.parse()?;
+        }
+
+        if let Ok(capacity) = env::var("INGEST_DEDUP_MEMORY_CAPACITY") {
+            config.ingest_dedup.memory_capacity = capacity.parse()?;
+        }
+
+        if let Ok(mode) = env::var("PAYLOAD_LOG_MODE") {
+            config.payload_log.mode = mode.parse()?;
+        }
+
+        if let Ok(every) = env::var("PAYLOAD_LOG_SAMPLE_EVERY") {
+            config.payload_log.sample_every = every.parse()?;
+        }
+
+        if let Ok(max_bytes) = env::var("PAYLOAD_LOG_MAX_BYTES") {
+            config.payload_log.max_bytes = max_bytes.parse()?;
+        }
+
+        if let Ok(redact) = env::var("PAYLOAD_LOG_REDACT_SUBACCOUNTS") {
+            config.payload_log.redact_subaccounts = redact.parse()?;
+        }
+
+        if let Ok(interval) = env::var("LIQUIDATION_RECOMPUTE_INTERVAL_SECS") {
+            config.liquidation.recompute_interval_secs = interval.parse()?;
+        }
+
+        if let Ok(top_k) = env::var("AT_RISK_TOP_K") {
+            config.liquidation.at_risk_top_k = top_k.parse()?;
+        }
+
+        if let Ok(ttl) = env::var("LIQUIDATION_ALERT_DEDUP_TTL_SECS") {
+            config.liquidation.alert_dedup_ttl_secs = ttl.parse()?;
+        }
+
+        if let Ok(enabled) = env::var("LIQUIDATION_RECOMPUTE_ON_FUNDING") {
+            config.liquidation.recompute_on_funding = enabled.parse()?;
+        }
+
+        if let Ok(interval) = env::var("CORRELATION_INTERVAL_SECS") {
+            config.correlation.interval_secs = interval.parse()?;
+        }
+
+        if let Ok(markets) = env::var("CORRELATION_MARKETS") {
+            config.correlation.markets = markets.split(',').map(|s| s.to_string()).collect();
+        }
+
+        if let Ok(backend) = env::var("STORAGE_BACKEND") {
+            config.storage.backend = backend.parse()?;
+        }
+
+        if let Ok(enabled) = env::var("CANDLES_ENABLED") {
+            config.candles.enabled = enabled.parse()?;
+        }
+
+        if let Ok(resolutions) = env::var("CANDLE_RESOLUTIONS") {
+            config.candles.resolutions = resolutions
+                .split(',')
+                .map(|s| s.trim().parse())
+                .collect::<Result<_, _>>()?;
+        }
+
+        if let Ok(lateness) = env::var("WINDOW_ALLOWED_LATENESS_SECS") {
+            config.window.allowed_lateness_secs = lateness.parse()?;
+        }
+
+        // market_id=tick pairs, comma separated
+        if let Ok(tick_sizes) = env::var("DEPTH_TICK_SIZES") {
+            config.depth.tick_sizes.clear();
+            for pair in tick_sizes.split(',') {
+                let (market_id, tick) = pair
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid DEPTH_TICK_SIZES entry: {}", pair))?;
+                config
+                    .depth
+                    .tick_sizes
+                    .insert(market_id.trim().to_string(), tick.trim().parse()?);
+            }
+        }
+
+        if let Ok(publish) = env::var("DEPTH_PUBLISH") {
+            config.depth.publish = publish.parse()?;
+        }
+
+        if let Ok(levels) = env::var("DEPTH_PUBLISH_LEVELS") {
+            config.depth.publish_levels = levels.parse()?;
+        }
+
+        if let Ok(points) = env::var("MID_PRICE_RECENT_POINTS") {
+            config.mid_price.recent_points = points.parse()?;
+        }
+
+        if let Ok(bps) = env::var("MID_PRICE_DIVERGENCE_ALERT_BPS") {
+            config.mid_price.divergence_alert_bps = bps.parse()?;
+        }
+
+        if let Ok(enabled) = env::var("LIVE_BOOKS_ENABLED") {
+            config.live_books.enabled = enabled.parse()?;
+        }
+
+        if let Ok(secs) = env::var("LIVE_BOOKS_SNAPSHOT_TIMEOUT_SECS") {
+            config.live_books.snapshot_timeout_secs = secs.parse()?;
+        }
+
+        if let Ok(secs) = env::var("CATCHUP_REPORT_INTERVAL_SECS") {
+            config.catch_up.report_interval_secs = secs.parse()?;
+        }
+
+        if let Ok(enabled) = env::var("REAPER_ENABLED") {
+            config.reaper.enabled = enabled.parse()?;
+        }
+
+        if let Ok(secs) = env::var("REAPER_INTERVAL_SECS") {
+            config.reaper.interval_secs = secs.parse()?;
+        }
+
+        if let Ok(count) = env::var("REAPER_SCAN_COUNT") {
+            config.reaper.scan_count = count.parse()?;
+        }
+
+        if let Ok(ms) = env::var("REAPER_BATCH_PAUSE_MS") {
+            config.reaper.batch_pause_ms = ms.parse()?;
+        }
+
+        config.redis_keys = RedisKeySchema::from_env()?;
+
+        if let Ok(scripts) = env::var("CONSUMER_HOOK_SCRIPTS") {
+            config.hooks.scripts = scripts.split(',').map(|s| s.to_string()).collect();
+        }
+
+        if let Ok(protocol) = env::var("KAFKA_SECURITY_PROTOCOL") {
+            config.kafka.security.protocol = Some(protocol.parse()?);
+        }
+
+        if let Ok(mechanism) = env::var("KAFKA_SASL_MECHANISM") {
+            config.kafka.security.sasl_mechanism = Some(mechanism.parse()?);
+        }
+
+        config.kafka.security.ssl_ca_location = env::var("KAFKA_SSL_CA_LOCATION").ok();
+        config.kafka.security.ssl_certificate_location =
+            env::var("KAFKA_SSL_CERTIFICATE_LOCATION").ok();
+        config.kafka.security.ssl_key_location = env::var("KAFKA_SSL_KEY_LOCATION").ok();
+
+        if let Ok(verify) = env::var("KAFKA_SSL_ENDPOINT_IDENTIFICATION") {
+            config.kafka.security.ssl_endpoint_identification = Some(verify.parse()?);
+        }
+
+        config.load_secrets()?;
         Ok(config)
     }
+
+    // Credentials from the environment, secret files or SECRETS_DIR take
+    // precedence over the config file
+    pub fn load_secrets(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(username) = secrets::load("KAFKA_SASL_USERNAME")? {
+            self.kafka.security.sasl_username = Some(username);
+        }
+        if let Some(password) = secrets::load("KAFKA_SASL_PASSWORD")? {
+            self.kafka.security.sasl_password = Some(Secret::new(password));
+        }
+        if let Some(password) = secrets::load("KAFKA_SSL_KEY_PASSWORD")? {
+            self.kafka.security.ssl_key_password = Some(Secret::new(password));
+        }
+        if let Some(username) = secrets::load("SCYLLADB_USERNAME")? {
+            self.scylladb.username = Some(username);
+        }
+        if let Some(password) = secrets::load("SCYLLADB_PASSWORD")? {
+            self.scylladb.password = Some(Secret::new(password));
+        }
+        Ok(())
+    }
 }
@@ -1,72 +1,421 @@
-use crate::config::KafkaConfig;
-use crate::models::KafkaMessage;
+use crate::admin;
+#[cfg(feature = "redis")]
+use crate::block_dedup::DedupWindow;
+use crate::catchup::{CatchUpProgress, STALE_AFTER};
+use crate::config::{KafkaConfig, SerializationFormat};
+use crate::dead_letter::{DeadLetterQueue, FailureStage};
+use crate::error::{ConsumerError, IndexerError};
+use crate::hooks::HookChain;
+use crate::metrics;
+use crate::models::{time, KafkaMessage, MessageType, FORMAT_HEADER, MESSAGE_TYPE_HEADER};
+use crate::payload_log::PayloadLogger;
+use crate::readiness::{Prerequisite, ReadinessGate};
+use crate::wire;
 use async_trait::async_trait;
-use log::{error, info};
+#[cfg(feature = "redis")]
+use log::debug;
+use log::{error, info, warn};
 use rdkafka::{
-    consumer::{Consumer, StreamConsumer},
-    ClientConfig, Message,
+    consumer::{BaseConsumer, CommitMode, Consumer, ConsumerContext, Rebalance, StreamConsumer},
+    error::RDKafkaErrorCode,
+    message::{BorrowedMessage, Headers},
+    statistics::Statistics,
+    ClientConfig, ClientContext, Message,
 };
-use std::error::Error;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[async_trait]
 pub trait MessageProcessor: Send + Sync {
-    async fn process_message(
-        &self,
-        message: KafkaMessage,
-    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn process_message(&self, message: KafkaMessage) -> Result<(), IndexerError>;
+
+    // Checked against the producer's message type header before the payload
+    // is parsed; returning false skips the message. Messages without the
+    // header are always parsed.
+    fn handles(&self, _message_type: &str) -> bool {
+        true
+    }
+
+    // What has to be in place before the processor can apply messages. A
+    // consumer with a readiness gate waits for these before it starts and
+    // pauses while they are lost.
+    fn prerequisites(&self) -> &[Prerequisite] {
+        &[]
+    }
+}
+
+// Commits what has been processed before partitions are revoked, so the
+// next owner starts right after it instead of at the last auto-commit
+pub struct RebalanceContext {
+    group: String,
+    progress: Arc<CatchUpProgress>,
+}
+
+impl ClientContext for RebalanceContext {
+    // librdkafka reports each assigned partition's lag with its statistics
+    fn stats(&self, statistics: Statistics) {
+        let lag = &metrics::consumer().lag;
+        for (topic_name, topic) in &statistics.topics {
+            for (id, partition) in &topic.partitions {
+                // -1 is librdkafka's unassigned partition
+                if *id < 0 {
+                    continue;
+                }
+                let partition_label = id.to_string();
+                let labels = [
+                    self.group.as_str(),
+                    topic_name.as_str(),
+                    partition_label.as_str(),
+                ];
+                if partition.consumer_lag >= 0 {
+                    lag.with_label_values(&labels).set(partition.consumer_lag);
+                    self.progress
+                        .record_lag(topic_name, *id, Some(partition.consumer_lag));
+                } else {
+                    let _ = lag.remove_label_values(&labels);
+                    self.progress.record_lag(topic_name, *id, None);
+                }
+            }
+        }
+    }
+}
+
+impl ConsumerContext for RebalanceContext {
+    fn pre_rebalance(&self, consumer: &BaseConsumer<Self>, rebalance: &Rebalance<'_>) {
+        match rebalance {
+            Rebalance::Revoke(partitions) => {
+                info!(
+                    "Group {} revoking {} partitions",
+                    self.group,
+                    partitions.count()
+                );
+                commit_processed(consumer, &self.group);
+                admin::runtime().revoked(
+                    &self.group,
+                    partitions
+                        .elements()
+                        .iter()
+                        .map(|element| (element.topic(), element.partition())),
+                );
+            }
+            Rebalance::Assign(partitions) => {
+                info!(
+                    "Group {} assigned {} partitions",
+                    self.group,
+                    partitions.count()
+                );
+                admin::runtime().assigned(
+                    &self.group,
+                    partitions
+                        .elements()
+                        .iter()
+                        .map(|element| (element.topic(), element.partition())),
+                );
+            }
+            Rebalance::Error(e) => {
+                error!("Group {} rebalance failed: {}", self.group, e);
+            }
+        }
+    }
 }
 
 pub struct KafkaConsumer<P: MessageProcessor> {
-    consumer: StreamConsumer,
+    consumer: StreamConsumer<RebalanceContext>,
+    group: String,
     processor: P,
+    hooks: Option<Arc<HookChain>>,
+    payload_log: Option<Arc<PayloadLogger>>,
+    dead_letter: Option<Arc<DeadLetterQueue>>,
+    readiness: Option<Arc<ReadinessGate>>,
+    #[cfg(feature = "redis")]
+    dedup: Option<Arc<DedupWindow>>,
+    behind: AtomicBool,
+    progress: Arc<CatchUpProgress>,
+    // Encoding of messages without a format header
+    format: SerializationFormat,
 }
 
 impl<P: MessageProcessor> KafkaConsumer<P> {
-    pub fn new(
-        kafka_config: &KafkaConfig,
-        processor: P,
-    ) -> Result<Self, rdkafka::error::KafkaError> {
-        let consumer: StreamConsumer = ClientConfig::new()
+    pub fn new(kafka_config: &KafkaConfig, processor: P) -> Result<Self, ConsumerError> {
+        let mut client = ClientConfig::new();
+        kafka_config.security.apply(&mut client);
+        if let Some(instance_id) = &kafka_config.group_instance_id {
+            client.set("group.instance.id", instance_id);
+        }
+        let progress = Arc::new(CatchUpProgress::new(&kafka_config.consumer_group));
+        let context = RebalanceContext {
+            group: kafka_config.consumer_group.clone(),
+            progress: progress.clone(),
+        };
+        let consumer: StreamConsumer<RebalanceContext> = client
             .set("group.id", &kafka_config.consumer_group)
             .set("bootstrap.servers", &kafka_config.brokers.join(","))
             .set("enable.auto.commit", "true")
+            .set("enable.auto.offset.store", "false")
             .set("auto.offset.reset", "earliest")
-            .set("session.timeout.ms", "6000")
+            // Skip batches a transactional producer aborted
+            .set("isolation.level", "read_committed")
+            .set(
+                "session.timeout.ms",
+                kafka_config.session_timeout_ms().to_string(),
+            )
             .set("max.poll.interval.ms", "300000") // 5 minutes
-            .create()?;
+            .set("statistics.interval.ms", "15000")
+            .create_with_context(context)?;
 
-        consumer.subscribe(&[&kafka_config.topic])?;
+        let orderbooks = MessageType::ORDERBOOKS
+            .iter()
+            .any(|message_type| processor.handles(message_type.as_str()));
+        let topics = kafka_config.subscribed_topics(orderbooks);
+        consumer.subscribe(&topics)?;
+        admin::runtime().subscribed(&kafka_config.consumer_group, &topics);
 
         Ok(KafkaConsumer {
             consumer,
+            group: kafka_config.consumer_group.clone(),
             processor,
+            hooks: None,
+            payload_log: None,
+            dead_letter: None,
+            readiness: None,
+            #[cfg(feature = "redis")]
+            dedup: None,
+            behind: AtomicBool::new(false),
+            progress,
+            format: kafka_config.format,
         })
     }
 
-    pub async fn start(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Run operator hooks over every message before it reaches the processor
+    pub fn with_hooks(mut self, hooks: Arc<HookChain>) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    // Log raw payloads according to the logger's policy
+    pub fn with_payload_log(mut self, payload_log: Arc<PayloadLogger>) -> Self {
+        self.payload_log = Some(payload_log);
+        self
+    }
+
+    // Republish messages that fail to decode or process to a dead-letter
+    // topic; None keeps dropping them
+    pub fn with_dead_letter(mut self, dead_letter: Option<Arc<DeadLetterQueue>>) -> Self {
+        self.dead_letter = dead_letter;
+        self
+    }
+
+    // Hold consumption back until the processor's prerequisites are met
+    pub fn with_readiness(mut self, readiness: Arc<ReadinessGate>) -> Self {
+        self.readiness = Some(readiness);
+        self
+    }
+
+    // Skip copies of messages the group already applied; None passes every
+    // message through
+    #[cfg(feature = "redis")]
+    pub fn with_dedup(mut self, dedup: Option<Arc<DedupWindow>>) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    // How far the consumer is through its backlog, for a CatchUpReporter
+    pub fn catch_up_progress(&self) -> Arc<CatchUpProgress> {
+        self.progress.clone()
+    }
+
+    // Wait for the processor's prerequisites when they are due to be checked.
+    // Messages stay in Kafka meanwhile; a wait longer than
+    // max.poll.interval.ms hands the partitions to another member until this
+    // one resumes.
+    async fn wait_until_ready(&self, last_check: &mut Option<Instant>) {
+        let Some(readiness) = &self.readiness else {
+            return;
+        };
+        let prerequisites = self.processor.prerequisites();
+        if prerequisites.is_empty()
+            || last_check.is_some_and(|checked| checked.elapsed() < readiness.recheck_interval())
+        {
+            return;
+        }
+        readiness.wait(&self.group, prerequisites).await;
+        *last_check = Some(Instant::now());
+    }
+
+    fn log_received(&self, payload: &[u8]) {
+        if let Some(payload_log) = &self.payload_log {
+            payload_log.received(payload);
+        }
+    }
+
+    fn log_failed(&self, payload: &[u8], reason: &dyn std::fmt::Display) {
+        if let Some(payload_log) = &self.payload_log {
+            payload_log.failed(payload, reason);
+        }
+    }
+
+    async fn send_to_dead_letter<M: Message>(
+        &self,
+        message: &M,
+        stage: FailureStage,
+        reason: &(dyn std::fmt::Display + Sync),
+    ) {
+        if let Some(dead_letter) = &self.dead_letter {
+            if let Err(e) = dead_letter.send(message, stage, reason).await {
+                error!(
+                    "Failed to dead-letter message at offset {} to {}: {}",
+                    message.offset(),
+                    dead_letter.topic(),
+                    e
+                );
+            }
+        }
+    }
+
+    // True if the message type header names a type the processor doesn't handle
+    fn skip_by_header<M: Message>(&self, message: &M) -> bool {
+        header_value(message, MESSAGE_TYPE_HEADER)
+            .is_some_and(|message_type| !self.processor.handles(message_type))
+    }
+
+    // Format named by the producer, falling back to the configured one
+    fn format_of<M: Message>(&self, message: &M) -> SerializationFormat {
+        header_value(message, FORMAT_HEADER)
+            .and_then(|format| format.parse().ok())
+            .unwrap_or(self.format)
+    }
+
+    // Returns false if a hook dropped the message
+    fn apply_hooks(&self, message: &mut KafkaMessage) -> bool {
+        match &self.hooks {
+            Some(hooks) => hooks.apply_message(message),
+            None => true,
+        }
+    }
+
+    // Log once when the consumer falls behind the chain and once when it catches up
+    fn track_lag(&self, message: &KafkaMessage) {
+        self.progress
+            .record_block(message.block_height, message.block_time);
+        let stale = time::is_stale(message.block_time as i64, STALE_AFTER);
+        if self.behind.swap(stale, Ordering::Relaxed) != stale {
+            let lag = time::age(message.block_time as i64);
+            if stale {
+                warn!(
+                    "Consumer is behind the chain: block {} is {}s old",
+                    message.block_height,
+                    lag.as_secs()
+                );
+            } else {
+                info!("Consumer caught up at block {}", message.block_height);
+            }
+        }
+    }
+
+    // Decode, filter and process one message. Failures are logged and
+    // dead-lettered; either way the message counts as done.
+    async fn handle(&self, message: &BorrowedMessage<'_>) {
+        let outcome = self.handle_message(message).await;
+        self.progress.record_message();
+        metrics::consumer()
+            .messages
+            .with_label_values(&[self.group.as_str(), outcome])
+            .inc();
+    }
+
+    // Returns the outcome recorded in the message counter
+    async fn handle_message(&self, message: &BorrowedMessage<'_>) -> &'static str {
+        if self.skip_by_header(message) {
+            return "skipped";
+        }
+        let Some(payload) = message.payload() else {
+            error!("Received empty message");
+            return "undecodable";
+        };
+
+        self.log_received(payload);
+        match wire::decode(payload, self.format_of(message)) {
+            Ok(mut kafka_message) => {
+                self.track_lag(&kafka_message);
+                #[cfg(feature = "redis")]
+                let claim = match &self.dedup {
+                    Some(dedup) => {
+                        let key = dedup.key(&self.group, &kafka_message);
+                        if !dedup.claim(&self.group, &key).await {
+                            debug!(
+                                "{}: Skipping duplicate {:?} message at block {}",
+                                self.group, kafka_message.message_type, kafka_message.block_height
+                            );
+                            return "duplicate";
+                        }
+                        Some((dedup, key))
+                    }
+                    None => None,
+                };
+                if !self.apply_hooks(&mut kafka_message) {
+                    return "skipped";
+                }
+                let message_type = kafka_message.message_type.as_str();
+                let start = Instant::now();
+                let result = self.processor.process_message(kafka_message).await;
+                metrics::consumer()
+                    .processing_seconds
+                    .with_label_values(&[self.group.as_str(), message_type])
+                    .observe(start.elapsed().as_secs_f64());
+                match result {
+                    Ok(()) => "processed",
+                    Err(e) => {
+                        error!("Error processing message: {}", e);
+                        #[cfg(feature = "redis")]
+                        if let Some((dedup, key)) = claim {
+                            dedup.release(&key).await;
+                        }
+                        self.log_failed(payload, &e);
+                        self.send_to_dead_letter(message, FailureStage::Process, &e)
+                            .await;
+                        "failed"
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to deserialize message: {}", e);
+                self.log_failed(payload, &e);
+                self.send_to_dead_letter(message, FailureStage::Deserialize, &e)
+                    .await;
+                "undecodable"
+            }
+        }
+    }
+
+    // Offsets are only stored once a message is handled, so auto-commits and
+    // the commit before a rebalance never cover a message still in flight
+    fn mark_done(&self, message: &BorrowedMessage<'_>) {
+        if let Err(e) = self.consumer.store_offset_from_message(message) {
+            warn!(
+                "Failed to store offset {} of {} [{}]: {}",
+                message.offset(),
+                message.topic(),
+                message.partition(),
+                e
+            );
+        }
+    }
+
+    pub async fn start(&self) -> Result<(), ConsumerError> {
         info!(
             "Starting Kafka consumer for topic: {}",
             self.get_subscribed_topics().join(", ")
         );
 
+        let mut last_check = None;
         loop {
+            self.wait_until_ready(&mut last_check).await;
             match self.consumer.recv().await {
-                Ok(message) => match message.payload() {
-                    Some(payload) => match serde_json::from_slice::<KafkaMessage>(payload) {
-                        Ok(kafka_message) => {
-                            if let Err(e) = self.processor.process_message(kafka_message).await {
-                                error!("Error processing message: {}", e);
-                            }
-                        }
-                        Err(e) => {
-                            error!("Failed to deserialize message: {}", e);
-                        }
-                    },
-                    None => {
-                        error!("Received empty message");
-                    }
-                },
+                Ok(message) => {
+                    self.handle(&message).await;
+                    self.mark_done(&message);
+                }
                 Err(e) => {
                     error!("Error receiving message: {}", e);
                     tokio::time::sleep(Duration::from_secs(1)).await;
@@ -79,13 +428,21 @@ impl<P: MessageProcessor> KafkaConsumer<P> {
     pub async fn start_with_shutdown(
         &self,
         mut shutdown_signal: tokio::sync::oneshot::Receiver<()>,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    ) -> Result<(), ConsumerError> {
         info!(
             "Starting Kafka consumer for topic: {}",
             self.get_subscribed_topics().join(", ")
         );
 
+        let mut last_check = None;
         loop {
+            tokio::select! {
+                _ = &mut shutdown_signal => {
+                    info!("Received shutdown signal, stopping consumer");
+                    break;
+                }
+                _ = self.wait_until_ready(&mut last_check) => {}
+            }
             tokio::select! {
                 _ = &mut shutdown_signal => {
                     info!("Received shutdown signal, stopping consumer");
@@ -94,24 +451,9 @@ impl<P: MessageProcessor> KafkaConsumer<P> {
                 message_result = self.consumer.recv() => {
                     match message_result {
                         Ok(message) => {
-                            match message.payload() {
-                                Some(payload) => {
-                                    match serde_json::from_slice::<KafkaMessage>(payload) {
-                                        Ok(kafka_message) => {
-                                            if let Err(e) = self.processor.process_message(kafka_message).await {
-                                                error!("Error processing message: {}", e);
-                                            }
-                                        },
-                                        Err(e) => {
-                                            error!("Failed to deserialize message: {}", e);
-                                        }
-                                    }
-                                },
-                                None => {
-                                    error!("Received empty message");
-                                }
-                            }
-                        },
+                            self.handle(&message).await;
+                            self.mark_done(&message);
+                        }
                         Err(e) => {
                             error!("Error receiving message: {}", e);
                             tokio::time::sleep(Duration::from_secs(1)).await;
@@ -121,6 +463,9 @@ impl<P: MessageProcessor> KafkaConsumer<P> {
             }
         }
 
+        // A static member keeps its partitions while it restarts, so it
+        // resumes exactly from here
+        commit_processed(&self.consumer, &self.group);
         info!("Kafka consumer stopped");
         Ok(())
     }
@@ -139,3 +484,21 @@ impl<P: MessageProcessor> KafkaConsumer<P> {
         }
     }
 }
+
+// Synchronously commit the stored offsets; having none to commit is fine
+fn commit_processed<C: ConsumerContext, K: Consumer<C>>(consumer: &K, group: &str) {
+    match consumer.commit_consumer_state(CommitMode::Sync) {
+        Ok(()) => {}
+        Err(e) if e.rdkafka_error_code() == Some(RDKafkaErrorCode::NoOffset) => {}
+        Err(e) => warn!("Group {} failed to commit processed offsets: {}", group, e),
+    }
+}
+
+fn header_value<'a, M: Message>(message: &'a M, key: &str) -> Option<&'a str> {
+    message
+        .headers()?
+        .iter()
+        .find(|header| header.key == key)
+        .and_then(|header| header.value)
+        .and_then(|value| std::str::from_utf8(value).ok())
+}
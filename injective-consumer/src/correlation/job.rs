@@ -0,0 +1,142 @@
+use super::{correlation_matrix, CorrelationMatrix, CorrelationSink};
+use crate::candles::Resolution;
+use crate::clock::{self, Clock};
+use crate::redis_keys;
+use crate::udf::CandleSource;
+use log::{error, info, warn};
+use redis::{aio::ConnectionManager, AsyncCommands, Client};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::{task, time};
+
+// Configuration for the periodic correlation matrix job
+#[derive(Clone)]
+pub struct CorrelationJobConfig {
+    pub redis_url: String,
+    pub interval_secs: u64,
+    // Markets to correlate; every stored derivative market when empty
+    pub markets: Vec<String>,
+    pub resolution: Resolution,
+    // Candles per market the returns are taken from
+    pub lookback: usize,
+}
+
+impl Default for CorrelationJobConfig {
+    fn default() -> Self {
+        CorrelationJobConfig {
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            interval_secs: 3600,
+            markets: Vec::new(),
+            resolution: Resolution::OneHour,
+            lookback: 24,
+        }
+    }
+}
+
+// Recomputes the correlation matrix from candle history on a fixed interval
+// and stores it in Redis and any extra sinks
+pub struct CorrelationJob<S: CandleSource> {
+    config: CorrelationJobConfig,
+    candles: S,
+    connection: ConnectionManager,
+    sinks: Vec<Box<dyn CorrelationSink>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<S: CandleSource + 'static> CorrelationJob<S> {
+    pub async fn new(
+        config: CorrelationJobConfig,
+        candles: S,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let client = Client::open(config.redis_url.clone())?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(CorrelationJob {
+            config,
+            candles,
+            connection,
+            sinks: Vec::new(),
+            clock: clock::system(),
+        })
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_sink(mut self, sink: Box<dyn CorrelationSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    // Spawn the job loop in the background
+    pub fn spawn(self) -> task::JoinHandle<()> {
+        task::spawn(async move {
+            let mut interval_timer = time::interval(Duration::from_secs(self.config.interval_secs));
+            let mut connection = self.connection.clone();
+
+            loop {
+                interval_timer.tick().await;
+
+                match self.compute(&mut connection).await {
+                    Ok(matrix) => info!(
+                        "Correlation matrix updated for {} markets",
+                        matrix.markets.len()
+                    ),
+                    Err(e) => error!("Correlation job failed: {}", e),
+                }
+            }
+        })
+    }
+
+    // Compute and store one matrix
+    pub async fn compute(
+        &self,
+        connection: &mut ConnectionManager,
+    ) -> Result<CorrelationMatrix, Box<dyn Error + Send + Sync>> {
+        let markets = if self.config.markets.is_empty() {
+            let mut markets: Vec<String> = connection
+                .smembers(redis_keys::derivative_markets())
+                .await?;
+            markets.sort();
+            markets
+        } else {
+            self.config.markets.clone()
+        };
+
+        let now = self.clock.now_millis();
+        let resolution = self.config.resolution;
+        let to = resolution.bucket(now / 1000);
+        let from = to - self.config.lookback as i64 * resolution.seconds();
+
+        let mut closes = Vec::with_capacity(markets.len());
+        for market_id in markets {
+            let candles = match self.candles.candles(&market_id, resolution, from, to).await {
+                Ok(candles) => candles,
+                Err(e) => {
+                    warn!("Correlation job: no candles for {}: {}", market_id, e);
+                    Vec::new()
+                }
+            };
+            let series: Vec<(i64, f64)> = candles.iter().map(|c| (c.time, c.close)).collect();
+            closes.push((market_id, series));
+        }
+
+        let matrix = correlation_matrix(resolution.as_str(), &closes, resolution.seconds(), now);
+
+        connection
+            .set::<_, _, ()>(
+                redis_keys::correlation_matrix(resolution.as_str()),
+                serde_json::to_string(&matrix)?,
+            )
+            .await?;
+        for sink in &self.sinks {
+            if let Err(e) = sink.store(&matrix).await {
+                warn!("Failed to store correlation matrix: {}", e);
+            }
+        }
+
+        Ok(matrix)
+    }
+}
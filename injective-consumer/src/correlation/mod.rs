@@ -0,0 +1,104 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+
+#[cfg(feature = "api")]
+mod job;
+#[cfg(feature = "api")]
+pub use job::{CorrelationJob, CorrelationJobConfig};
+
+// Rolling return correlations between markets. Returns are log returns between
+// consecutive candle closes; a pair is only correlated over the candle times
+// both markets have.
+
+// Fewer common returns than this leave a pair without a correlation
+pub const MIN_SAMPLES: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorrelationMatrix {
+    // Candle resolution the returns were taken at, as TradingView names it
+    pub resolution: String,
+    pub markets: Vec<String>,
+    // correlations[i][j] between markets[i] and markets[j]; None when the pair
+    // has fewer than MIN_SAMPLES common returns
+    pub correlations: Vec<Vec<Option<f64>>>,
+    pub samples: Vec<Vec<usize>>,
+    pub computed_at: i64,
+}
+
+// Where computed matrices are stored besides Redis
+#[async_trait]
+pub trait CorrelationSink: Send + Sync {
+    async fn store(&self, matrix: &CorrelationMatrix) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+// Log returns keyed by the time of the later close. `step` is the candle
+// length; closes further apart than that (missing candles) give no return.
+fn log_returns(closes: &[(i64, f64)], step: i64) -> HashMap<i64, f64> {
+    closes
+        .windows(2)
+        .filter(|pair| pair[1].0 - pair[0].0 == step && pair[0].1 > 0.0 && pair[1].1 > 0.0)
+        .map(|pair| (pair[1].0, (pair[1].1 / pair[0].1).ln()))
+        .collect()
+}
+
+// Pearson correlation of the returns both series have, with the sample count
+fn pearson(a: &HashMap<i64, f64>, b: &HashMap<i64, f64>) -> (Option<f64>, usize) {
+    let pairs: Vec<(f64, f64)> = a
+        .iter()
+        .filter_map(|(time, x)| b.get(time).map(|y| (*x, *y)))
+        .collect();
+    let n = pairs.len();
+    if n < MIN_SAMPLES {
+        return (None, n);
+    }
+
+    let mean_x = pairs.iter().map(|(x, _)| x).sum::<f64>() / n as f64;
+    let mean_y = pairs.iter().map(|(_, y)| y).sum::<f64>() / n as f64;
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in &pairs {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        return (None, n);
+    }
+    (Some(cov / (var_x * var_y).sqrt()), n)
+}
+
+// Correlate every pair of markets from their closes, each sorted by time.
+// `step` is the candle length in the same unit as the close times.
+pub fn correlation_matrix(
+    resolution: &str,
+    closes: &[(String, Vec<(i64, f64)>)],
+    step: i64,
+    computed_at: i64,
+) -> CorrelationMatrix {
+    let returns: Vec<HashMap<i64, f64>> = closes
+        .iter()
+        .map(|(_, series)| log_returns(series, step))
+        .collect();
+
+    let n = closes.len();
+    let mut correlations = vec![vec![None; n]; n];
+    let mut samples = vec![vec![0; n]; n];
+    for i in 0..n {
+        for j in i..n {
+            let (correlation, count) = pearson(&returns[i], &returns[j]);
+            correlations[i][j] = correlation;
+            correlations[j][i] = correlation;
+            samples[i][j] = count;
+            samples[j][i] = count;
+        }
+    }
+
+    CorrelationMatrix {
+        resolution: resolution.to_string(),
+        markets: closes.iter().map(|(market, _)| market.clone()).collect(),
+        correlations,
+        samples,
+        computed_at,
+    }
+}
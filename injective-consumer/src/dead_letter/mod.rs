@@ -0,0 +1,210 @@
+use crate::config::KafkaConfig;
+use crate::error::ConsumerError;
+use crate::models::time::now_millis;
+use log::{info, warn};
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::message::{Header, Headers, Message, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::util::Timeout;
+use rdkafka::ClientConfig;
+use std::fmt::Display;
+use std::time::Duration;
+
+// Failed messages are republished unchanged to the dead-letter topic, with
+// their original headers plus these describing the failure
+pub const ERROR_HEADER: &str = "dlq.error";
+pub const STAGE_HEADER: &str = "dlq.stage";
+pub const CONSUMER_GROUP_HEADER: &str = "dlq.consumer_group";
+pub const SOURCE_TOPIC_HEADER: &str = "dlq.source_topic";
+pub const SOURCE_PARTITION_HEADER: &str = "dlq.source_partition";
+pub const SOURCE_OFFSET_HEADER: &str = "dlq.source_offset";
+pub const FAILED_AT_HEADER: &str = "dlq.failed_at";
+
+const DEAD_LETTER_PREFIX: &str = "dlq.";
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+// Replay stops once the dead-letter topic has been quiet this long
+const REPLAY_IDLE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureStage {
+    // The value could not be decoded into a KafkaMessage, or failed its
+    // validation
+    Deserialize,
+    // The processor returned an error
+    Process,
+}
+
+impl FailureStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureStage::Deserialize => "deserialize",
+            FailureStage::Process => "process",
+        }
+    }
+}
+
+pub struct DeadLetterQueue {
+    producer: FutureProducer,
+    topic: String,
+    consumer_group: String,
+}
+
+impl DeadLetterQueue {
+    pub fn new(kafka_config: &KafkaConfig, topic: &str) -> Result<Self, ConsumerError> {
+        let mut client = ClientConfig::new();
+        kafka_config.security.apply(&mut client);
+        let producer: FutureProducer = client
+            .set("bootstrap.servers", kafka_config.brokers.join(","))
+            .set("client.id", format!("{}-dlq", kafka_config.client_id))
+            .set("message.timeout.ms", "30000")
+            .create()?;
+
+        Ok(DeadLetterQueue {
+            producer,
+            topic: topic.to_string(),
+            consumer_group: kafka_config.consumer_group.clone(),
+        })
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    // Republish a failed message with why and where it failed
+    pub async fn send<M: Message>(
+        &self,
+        message: &M,
+        stage: FailureStage,
+        error: &(dyn Display + Sync),
+    ) -> Result<(), KafkaError> {
+        let error = error.to_string();
+        let partition = message.partition().to_string();
+        let offset = message.offset().to_string();
+        let failed_at = now_millis().to_string();
+
+        let mut headers = OwnedHeaders::new();
+        if let Some(original) = message.headers() {
+            for header in original.iter() {
+                headers = headers.insert(Header {
+                    key: header.key,
+                    value: header.value,
+                });
+            }
+        }
+        for (key, value) in [
+            (ERROR_HEADER, error.as_str()),
+            (STAGE_HEADER, stage.as_str()),
+            (CONSUMER_GROUP_HEADER, self.consumer_group.as_str()),
+            (SOURCE_TOPIC_HEADER, message.topic()),
+            (SOURCE_PARTITION_HEADER, partition.as_str()),
+            (SOURCE_OFFSET_HEADER, offset.as_str()),
+            (FAILED_AT_HEADER, failed_at.as_str()),
+        ] {
+            headers = headers.insert(Header {
+                key,
+                value: Some(value),
+            });
+        }
+
+        let mut record: FutureRecord<[u8], [u8]> = FutureRecord::to(&self.topic).headers(headers);
+        if let Some(key) = message.key() {
+            record = record.key(key);
+        }
+        if let Some(payload) = message.payload() {
+            record = record.payload(payload);
+        }
+
+        self.producer
+            .send(record, Timeout::After(SEND_TIMEOUT))
+            .await
+            .map(|_| ())
+            .map_err(|(e, _)| e)
+    }
+}
+
+// Re-inject dead-lettered messages into the topic each one failed on, or into
+// `target` when given, without the dead-letter headers. Progress is committed
+// under its own consumer group, so a replay picks up where the last one
+// stopped. Returns the number of messages replayed once the topic is drained.
+pub async fn replay(
+    kafka_config: &KafkaConfig,
+    dlq_topic: &str,
+    target: Option<&str>,
+) -> Result<u64, ConsumerError> {
+    let mut consumer_client = ClientConfig::new();
+    kafka_config.security.apply(&mut consumer_client);
+    let consumer: StreamConsumer = consumer_client
+        .set(
+            "group.id",
+            format!("{}-dlq-replay", kafka_config.consumer_group),
+        )
+        .set("bootstrap.servers", kafka_config.brokers.join(","))
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .create()?;
+    consumer.subscribe(&[dlq_topic])?;
+
+    let mut producer_client = ClientConfig::new();
+    kafka_config.security.apply(&mut producer_client);
+    let producer: FutureProducer = producer_client
+        .set("bootstrap.servers", kafka_config.brokers.join(","))
+        .set(
+            "client.id",
+            format!("{}-dlq-replay", kafka_config.client_id),
+        )
+        .set("message.timeout.ms", "30000")
+        .create()?;
+
+    let mut replayed = 0;
+    loop {
+        let message = match tokio::time::timeout(REPLAY_IDLE, consumer.recv()).await {
+            Ok(message) => message?,
+            Err(_) => break,
+        };
+
+        let mut headers = OwnedHeaders::new();
+        let mut source_topic = None;
+        if let Some(original) = message.headers() {
+            for header in original.iter() {
+                if header.key == SOURCE_TOPIC_HEADER {
+                    source_topic = header.value.and_then(|v| std::str::from_utf8(v).ok());
+                }
+                if header.key.starts_with(DEAD_LETTER_PREFIX) {
+                    continue;
+                }
+                headers = headers.insert(Header {
+                    key: header.key,
+                    value: header.value,
+                });
+            }
+        }
+
+        let Some(topic) = target.or(source_topic) else {
+            warn!(
+                "Skipping dead letter at offset {} without a source topic",
+                message.offset()
+            );
+            consumer.commit_message(&message, CommitMode::Async)?;
+            continue;
+        };
+
+        let mut record: FutureRecord<[u8], [u8]> = FutureRecord::to(topic).headers(headers);
+        if let Some(key) = message.key() {
+            record = record.key(key);
+        }
+        if let Some(payload) = message.payload() {
+            record = record.payload(payload);
+        }
+        producer
+            .send(record, Timeout::After(SEND_TIMEOUT))
+            .await
+            .map_err(|(e, _)| e)?;
+
+        consumer.commit_message(&message, CommitMode::Async)?;
+        replayed += 1;
+    }
+
+    info!("Replayed {} messages from {}", replayed, dlq_topic);
+    Ok(replayed)
+}
@@ -0,0 +1,209 @@
+use crate::models::time;
+use crate::pubsub::{EventType, StreamEvent};
+use log::warn;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+// What to do when a client's outbox is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlowConsumerPolicy {
+    // Close the connection with DisconnectReason::BufferFull
+    Disconnect,
+    // Drop the oldest queued event to make room
+    DropOldest,
+    // Replace the queued event for the same event type, market and subaccount,
+    // so the client only receives the newest state of each
+    Downsample,
+}
+
+impl std::str::FromStr for SlowConsumerPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "disconnect" => Ok(SlowConsumerPolicy::Disconnect),
+            "drop_oldest" => Ok(SlowConsumerPolicy::DropOldest),
+            "downsample" => Ok(SlowConsumerPolicy::Downsample),
+            other => Err(format!("Unknown slow consumer policy: {}", other)),
+        }
+    }
+}
+
+// Delivery limits for one client connection
+#[derive(Debug, Clone)]
+pub struct DeliveryConfig {
+    pub buffer_cap: usize,
+    pub policy: SlowConsumerPolicy,
+    // Disconnect when the event being delivered is older than this
+    pub max_lag: Option<Duration>,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        DeliveryConfig {
+            buffer_cap: 1_000,
+            policy: SlowConsumerPolicy::Downsample,
+            max_lag: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    BufferFull,
+    LagExceeded,
+}
+
+impl std::fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisconnectReason::BufferFull => write!(f, "slow consumer: buffer full"),
+            DisconnectReason::LagExceeded => write!(f, "slow consumer: lag exceeded"),
+        }
+    }
+}
+
+// Per-connection delivery counters
+#[derive(Debug, Default)]
+pub struct DeliveryStats {
+    pub queued: AtomicU64,
+    pub delivered: AtomicU64,
+    pub dropped: AtomicU64,
+    // Age of the most recently delivered event
+    pub lag_ms: AtomicU64,
+}
+
+// Point-in-time copy of DeliveryStats, plus the current queue depth
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeliveryStatsSnapshot {
+    pub queued: u64,
+    pub delivered: u64,
+    pub dropped: u64,
+    pub lag_ms: u64,
+    pub depth: usize,
+}
+
+struct OutboxState {
+    queue: VecDeque<StreamEvent>,
+    closed: Option<DisconnectReason>,
+}
+
+// Bounded queue between the event fan-out and one client connection. Pushing
+// never blocks the fan-out; a client that cannot keep up is handled by the
+// configured policy instead of growing memory.
+pub struct ClientOutbox {
+    client_id: String,
+    config: DeliveryConfig,
+    state: Mutex<OutboxState>,
+    notify: Notify,
+    stats: Arc<DeliveryStats>,
+}
+
+impl ClientOutbox {
+    pub fn new(client_id: &str, config: DeliveryConfig) -> Self {
+        ClientOutbox {
+            client_id: client_id.to_string(),
+            state: Mutex::new(OutboxState {
+                queue: VecDeque::with_capacity(config.buffer_cap.min(1_024)),
+                closed: None,
+            }),
+            config,
+            notify: Notify::new(),
+            stats: Arc::new(DeliveryStats::default()),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<DeliveryStats> {
+        self.stats.clone()
+    }
+
+    pub fn snapshot(&self) -> DeliveryStatsSnapshot {
+        DeliveryStatsSnapshot {
+            queued: self.stats.queued.load(Ordering::Relaxed),
+            delivered: self.stats.delivered.load(Ordering::Relaxed),
+            dropped: self.stats.dropped.load(Ordering::Relaxed),
+            lag_ms: self.stats.lag_ms.load(Ordering::Relaxed),
+            depth: self.state.lock().unwrap().queue.len(),
+        }
+    }
+
+    // Queue an event for the client. Returns the disconnect reason once the
+    // client has been cut off; the caller should then close the connection.
+    pub fn push(&self, event: StreamEvent) -> Result<(), DisconnectReason> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(reason) = state.closed {
+            return Err(reason);
+        }
+
+        if state.queue.len() >= self.config.buffer_cap {
+            match self.config.policy {
+                SlowConsumerPolicy::Disconnect => {
+                    return Err(self.close(&mut state, DisconnectReason::BufferFull));
+                }
+                SlowConsumerPolicy::DropOldest => {
+                    state.queue.pop_front();
+                    self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                SlowConsumerPolicy::Downsample => {
+                    let key = conflation_key(&event);
+                    match state.queue.iter().position(|e| conflation_key(e) == key) {
+                        Some(index) => state.queue.remove(index),
+                        None => state.queue.pop_front(),
+                    };
+                    self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        state.queue.push_back(event);
+        self.stats.queued.fetch_add(1, Ordering::Relaxed);
+        drop(state);
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    // Next event for the client, waiting until one is queued
+    pub async fn next(&self) -> Result<StreamEvent, DisconnectReason> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(reason) = state.closed {
+                    return Err(reason);
+                }
+                if let Some(event) = state.queue.pop_front() {
+                    let lag_ms = (time::now_millis() as u64).saturating_sub(event.timestamp);
+                    self.stats.lag_ms.store(lag_ms, Ordering::Relaxed);
+                    if self
+                        .config
+                        .max_lag
+                        .is_some_and(|max_lag| lag_ms > max_lag.as_millis() as u64)
+                    {
+                        return Err(self.close(&mut state, DisconnectReason::LagExceeded));
+                    }
+                    self.stats.delivered.fetch_add(1, Ordering::Relaxed);
+                    return Ok(event);
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    fn close(&self, state: &mut OutboxState, reason: DisconnectReason) -> DisconnectReason {
+        let pending = state.queue.len() as u64;
+        state.queue.clear();
+        state.closed = Some(reason);
+        self.stats.dropped.fetch_add(pending, Ordering::Relaxed);
+        warn!("Disconnecting client {}: {}", self.client_id, reason);
+        self.notify.notify_one();
+        reason
+    }
+}
+
+// Events that supersede each other when downsampling
+fn conflation_key(event: &StreamEvent) -> (EventType, Option<&str>, Option<&str>) {
+    let field = |name: &str| event.payload.get(name).and_then(|v| v.as_str());
+    (event.event_type, field("market_id"), field("subaccount_id"))
+}
@@ -0,0 +1,284 @@
+use crate::config::Config;
+#[cfg(feature = "scylla-sink")]
+use crate::config::{ScyllaDBConfig, StorageBackend};
+#[cfg(feature = "redis")]
+use crate::secrets::{self, RedisRole};
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::ClientConfig;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::time::Duration;
+use tonic::transport::Endpoint;
+
+// `--check-config`: validates the configuration against the services it
+// names and reports every problem at once, without starting any consumer.
+// Redis and ScyllaDB are located through REDIS_WRITE_URL (or REDIS_URL),
+// REDIS_SECONDARY_URL and SCYLLADB_NODES, with the same defaults the service
+// uses.
+
+// How long each network check may take before it counts as unreachable
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn push(&mut self, name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    // True when nothing failed; warnings alone do not fail the check
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Failed)
+    }
+
+    fn count(&self, status: CheckStatus) -> usize {
+        self.checks.iter().filter(|c| c.status == status).count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let tag = match check.status {
+                CheckStatus::Ok => " ok ",
+                CheckStatus::Warning => "warn",
+                CheckStatus::Failed => "FAIL",
+            };
+            writeln!(f, "  [{}] {}: {}", tag, check.name, check.detail)?;
+        }
+        write!(
+            f,
+            "{} ok, {} warnings, {} failed",
+            self.count(CheckStatus::Ok),
+            self.count(CheckStatus::Warning),
+            self.count(CheckStatus::Failed)
+        )
+    }
+}
+
+pub async fn check_config(config: &Config) -> Report {
+    let mut report = Report::default();
+
+    check_endpoint(
+        &mut report,
+        "grpc.stream_endpoint",
+        &config.grpc.stream_endpoint,
+    )
+    .await;
+    check_endpoint(
+        &mut report,
+        "grpc.query_endpoint",
+        &config.grpc.query_endpoint,
+    )
+    .await;
+    check_kafka(&mut report, config).await;
+
+    #[cfg(feature = "redis")]
+    for (name, url) in [
+        (
+            RedisRole::Writer.url_name(),
+            secrets::role_redis_url(RedisRole::Writer, Some("redis://127.0.0.1:6379")),
+        ),
+        (
+            "REDIS_SECONDARY_URL",
+            secrets::redis_url("REDIS_SECONDARY_URL", "REDIS_SECONDARY_PASSWORD", None),
+        ),
+    ] {
+        match url {
+            Ok(Some(url)) => check_redis(&mut report, name, &url).await,
+            Ok(None) => {}
+            Err(e) => report.push(name, CheckStatus::Failed, e.to_string()),
+        }
+    }
+
+    #[cfg(feature = "scylla-sink")]
+    if config.storage.backend == StorageBackend::Native {
+        let nodes: Vec<String> = std::env::var("SCYLLADB_NODES")
+            .unwrap_or_else(|_| "127.0.0.1:9042".to_string())
+            .split(',')
+            .map(|s| s.to_string())
+            .collect();
+        check_scylla(&mut report, &nodes, &config.scylladb).await;
+    }
+
+    check_hooks(&mut report, config);
+
+    report
+}
+
+async fn check_endpoint(report: &mut Report, name: &str, url: &str) {
+    let endpoint = match Endpoint::from_shared(url.to_string()) {
+        Ok(endpoint) => endpoint.connect_timeout(CHECK_TIMEOUT),
+        Err(e) => {
+            report.push(
+                name,
+                CheckStatus::Failed,
+                format!("{} is not a URL: {}", url, e),
+            );
+            return;
+        }
+    };
+    match endpoint.connect().await {
+        Ok(_) => report.push(name, CheckStatus::Ok, format!("{} reachable", url)),
+        // The consumers only query the chain for market preloading
+        Err(e) => report.push(
+            name,
+            CheckStatus::Warning,
+            format!("{} unreachable: {}", url, e),
+        ),
+    }
+}
+
+async fn check_kafka(report: &mut Report, config: &Config) {
+    let kafka = &config.kafka;
+    if kafka.brokers.is_empty() {
+        report.push(
+            "kafka.brokers",
+            CheckStatus::Failed,
+            "no brokers configured",
+        );
+        return;
+    }
+
+    if let Err(e) = kafka.security.validate() {
+        report.push("kafka.security", CheckStatus::Failed, e);
+        return;
+    }
+
+    let brokers = kafka.brokers.join(",");
+    let group = format!("{}-check", kafka.consumer_group);
+    let security = kafka.security.clone();
+    let metadata = tokio::task::spawn_blocking(move || {
+        let mut client = ClientConfig::new();
+        security.apply(&mut client);
+        let consumer: BaseConsumer = client
+            .set("bootstrap.servers", &brokers)
+            .set("group.id", &group)
+            .create()?;
+        consumer.fetch_metadata(None, CHECK_TIMEOUT)
+    })
+    .await;
+
+    let metadata = match metadata {
+        Ok(Ok(metadata)) => metadata,
+        Ok(Err(e)) => {
+            report.push(
+                "kafka.brokers",
+                CheckStatus::Failed,
+                format!("{} unreachable: {}", kafka.brokers.join(","), e),
+            );
+            return;
+        }
+        Err(e) => {
+            report.push("kafka.brokers", CheckStatus::Failed, e.to_string());
+            return;
+        }
+    };
+    report.push(
+        "kafka.brokers",
+        CheckStatus::Ok,
+        format!("{} brokers in the cluster", metadata.brokers().len()),
+    );
+
+    // Subscribed topics plus the markets, orderbook and dead-letter topics
+    let existing: BTreeSet<&str> = metadata.topics().iter().map(|t| t.name()).collect();
+    let wanted: BTreeSet<&str> = kafka
+        .subscribed_topics(true)
+        .into_iter()
+        .chain(kafka.markets_topic.as_deref())
+        .chain(kafka.dead_letter_topic.as_deref())
+        .collect();
+    for topic in wanted {
+        let name = format!("kafka topic {}", topic);
+        if existing.contains(topic) {
+            report.push(name, CheckStatus::Ok, "exists");
+        } else {
+            report.push(name, CheckStatus::Failed, "does not exist");
+        }
+    }
+}
+
+// A PING proves the URL parses, the server is reachable and any credentials
+// in the URL are accepted
+#[cfg(feature = "redis")]
+async fn check_redis(report: &mut Report, name: &str, url: &str) {
+    let result = tokio::time::timeout(CHECK_TIMEOUT, async {
+        let client = redis::Client::open(url)?;
+        let mut conn = client.get_multiplexed_async_connection().await?;
+        redis::cmd("PING").query_async::<String>(&mut conn).await
+    })
+    .await;
+
+    match result {
+        Ok(Ok(_)) => report.push(name, CheckStatus::Ok, "connected"),
+        Ok(Err(e)) => report.push(name, CheckStatus::Failed, e.to_string()),
+        Err(_) => report.push(name, CheckStatus::Failed, "timed out"),
+    }
+}
+
+#[cfg(feature = "scylla-sink")]
+async fn check_scylla(report: &mut Report, nodes: &[String], config: &ScyllaDBConfig) {
+    let result = tokio::time::timeout(CHECK_TIMEOUT, async {
+        let session = config
+            .session_builder(nodes)
+            .connection_timeout(CHECK_TIMEOUT)
+            .build()
+            .await?;
+        session
+            .query_unpaged("SELECT release_version FROM system.local", &[])
+            .await?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+    })
+    .await;
+
+    let name = "SCYLLADB_NODES";
+    match result {
+        Ok(Ok(())) => report.push(
+            name,
+            CheckStatus::Ok,
+            format!("{} connected", nodes.join(",")),
+        ),
+        Ok(Err(e)) => report.push(name, CheckStatus::Failed, e.to_string()),
+        Err(_) => report.push(name, CheckStatus::Failed, "timed out"),
+    }
+}
+
+fn check_hooks(report: &mut Report, config: &Config) {
+    for path in &config.hooks.scripts {
+        let name = format!("hook {}", path);
+
+        #[cfg(feature = "scripting")]
+        match crate::hooks::RhaiHook::from_file(path, config.hooks.max_operations) {
+            Ok(_) => report.push(name, CheckStatus::Ok, "compiles"),
+            Err(e) => report.push(name, CheckStatus::Failed, e.to_string()),
+        }
+
+        #[cfg(not(feature = "scripting"))]
+        report.push(
+            name,
+            CheckStatus::Warning,
+            "ignored, the scripting feature is not enabled",
+        );
+    }
+}
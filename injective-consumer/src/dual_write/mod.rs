@@ -0,0 +1,278 @@
+use crate::metrics;
+use log::{error, info, warn};
+use redis::aio::{ConnectionLike, ConnectionManager};
+use redis::{Client, Cmd, Pipeline, RedisFuture, RedisResult, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::{task, time};
+
+// Commands that modify data and therefore must be mirrored to the secondary.
+// PUBLISH is deliberately excluded so subscribers never see duplicate events.
+const WRITE_COMMANDS: &[&str] = &[
+    "SET",
+    "DEL",
+    "EXPIRE",
+    "HSET",
+    "HDEL",
+    "HINCRBY",
+    "HINCRBYFLOAT",
+    "SADD",
+    "SREM",
+    "ZADD",
+    "ZREM",
+    "LPUSH",
+    "RPUSH",
+    "LTRIM",
+    "INCRBY",
+    "INCRBYFLOAT",
+];
+
+// Metrics for the secondary side of a dual write
+#[derive(Default, Debug)]
+pub struct DualWriteMetrics {
+    pub mirrored_commands: AtomicU64,
+    pub secondary_errors: AtomicU64,
+}
+
+// A Redis connection that forwards every request to the primary and mirrors
+// write commands to an optional secondary. Reads are only served by the primary,
+// and secondary failures are logged without failing the write. Both sides are
+// connection managers, so the connection is cloned per task instead of locked;
+// clones share the secondary and the metrics.
+#[derive(Clone)]
+pub struct MirroredConnection {
+    primary: ConnectionManager,
+    secondary: Arc<OnceLock<ConnectionManager>>,
+    metrics: Arc<DualWriteMetrics>,
+}
+
+impl MirroredConnection {
+    pub fn new(primary: ConnectionManager) -> Self {
+        MirroredConnection {
+            primary,
+            secondary: Arc::new(OnceLock::new()),
+            metrics: Arc::new(DualWriteMetrics::default()),
+        }
+    }
+
+    // Applies to every clone, including those handed out before the call.
+    // The first secondary wins.
+    pub fn set_secondary(&self, secondary: ConnectionManager) {
+        if self.secondary.set(secondary).is_err() {
+            warn!("Secondary Redis already set, ignoring the new one");
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<DualWriteMetrics> {
+        self.metrics.clone()
+    }
+
+    // The secondary, when one is set and the request writes anything
+    fn mirror_target(&self, packed: &[u8]) -> Option<ConnectionManager> {
+        self.secondary
+            .get()
+            .filter(|_| contains_write(packed))
+            .cloned()
+    }
+
+    fn record_mirror(&self, result: RedisResult<()>) {
+        match result {
+            Ok(()) => {
+                self.metrics
+                    .mirrored_commands
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                self.metrics
+                    .secondary_errors
+                    .fetch_add(1, Ordering::Relaxed);
+                warn!("Failed to mirror write to secondary Redis: {}", e);
+            }
+        }
+    }
+}
+
+impl ConnectionLike for MirroredConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            metrics::consumer().redis_pipeline_commands.observe(1.0);
+            let result = self.primary.req_packed_command(cmd).await?;
+            if let Some(mut secondary) = self.mirror_target(&cmd.get_packed_command()) {
+                let mirrored = secondary.req_packed_command(cmd).await.map(|_| ());
+                self.record_mirror(mirrored);
+            }
+            Ok(result)
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            metrics::consumer()
+                .redis_pipeline_commands
+                .observe((offset + count) as f64);
+            let result = self.primary.req_packed_commands(cmd, offset, count).await?;
+            if let Some(mut secondary) = self.mirror_target(&cmd.get_packed_pipeline()) {
+                let mirrored = secondary
+                    .req_packed_commands(cmd, offset, count)
+                    .await
+                    .map(|_| ());
+                self.record_mirror(mirrored);
+            }
+            Ok(result)
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.primary.get_db()
+    }
+}
+
+// Scan the command names in a packed RESP request (one or more commands)
+// and report whether any of them is a write
+fn contains_write(packed: &[u8]) -> bool {
+    let mut rest = packed;
+    while let Some(start) = rest.iter().position(|b| *b == b'*') {
+        rest = &rest[start..];
+        // Skip the array header and the bulk string length of the command name
+        let name = rest
+            .split(|b| *b == b'\n')
+            .nth(2)
+            .map(|line| String::from_utf8_lossy(line).trim().to_ascii_uppercase());
+        if let Some(name) = name {
+            if WRITE_COMMANDS.contains(&name.as_str()) {
+                return true;
+            }
+        }
+        rest = &rest[1..];
+    }
+    false
+}
+
+// Configuration for the primary/secondary comparison sampler
+#[derive(Clone)]
+pub struct DualWriteSamplerConfig {
+    pub primary_url: String,
+    pub secondary_url: String,
+    pub interval_secs: u64,
+    pub sample_size: usize,
+}
+
+// Periodically compares random keys between primary and secondary Redis so a
+// migration can be verified before switching readers over
+pub struct DualWriteSampler {
+    config: DualWriteSamplerConfig,
+    primary: ConnectionManager,
+    secondary: ConnectionManager,
+}
+
+impl DualWriteSampler {
+    pub async fn new(config: DualWriteSamplerConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let primary = ConnectionManager::new(Client::open(config.primary_url.clone())?).await?;
+        let secondary = ConnectionManager::new(Client::open(config.secondary_url.clone())?).await?;
+        Ok(DualWriteSampler {
+            config,
+            primary,
+            secondary,
+        })
+    }
+
+    pub fn spawn(self) -> task::JoinHandle<()> {
+        task::spawn(async move {
+            let mut interval_timer = time::interval(Duration::from_secs(self.config.interval_secs));
+            let mut primary = self.primary.clone();
+            let mut secondary = self.secondary.clone();
+
+            loop {
+                interval_timer.tick().await;
+
+                match self.compare_sample(&mut primary, &mut secondary).await {
+                    Ok((checked, mismatched)) => {
+                        if mismatched > 0 {
+                            warn!(
+                                "Dual-write sampler: {}/{} sampled keys differ between primary and secondary",
+                                mismatched, checked
+                            );
+                        } else {
+                            info!("Dual-write sampler: {} sampled keys match", checked);
+                        }
+                    }
+                    Err(e) => error!("Dual-write sampler failed: {}", e),
+                }
+            }
+        })
+    }
+
+    // Compare a random sample of keys, returning (checked, mismatched)
+    async fn compare_sample(
+        &self,
+        primary: &mut ConnectionManager,
+        secondary: &mut ConnectionManager,
+    ) -> Result<(usize, usize), Box<dyn Error + Send + Sync>> {
+        let mut checked = 0;
+        let mut mismatched = 0;
+
+        for _ in 0..self.config.sample_size {
+            let key: Option<String> = redis::cmd("RANDOMKEY").query_async(primary).await?;
+            let Some(key) = key else {
+                break;
+            };
+
+            let key_type: String = redis::cmd("TYPE").arg(&key).query_async(primary).await?;
+            let matches = match key_type.as_str() {
+                "hash" => {
+                    let a: HashMap<String, String> =
+                        redis::cmd("HGETALL").arg(&key).query_async(primary).await?;
+                    let b: HashMap<String, String> = redis::cmd("HGETALL")
+                        .arg(&key)
+                        .query_async(secondary)
+                        .await?;
+                    a == b
+                }
+                "set" => {
+                    let a: BTreeSet<String> = redis::cmd("SMEMBERS")
+                        .arg(&key)
+                        .query_async(primary)
+                        .await?;
+                    let b: BTreeSet<String> = redis::cmd("SMEMBERS")
+                        .arg(&key)
+                        .query_async(secondary)
+                        .await?;
+                    a == b
+                }
+                "string" => {
+                    let a: Option<String> =
+                        redis::cmd("GET").arg(&key).query_async(primary).await?;
+                    let b: Option<String> =
+                        redis::cmd("GET").arg(&key).query_async(secondary).await?;
+                    a == b
+                }
+                _ => {
+                    let exists: bool = redis::cmd("EXISTS")
+                        .arg(&key)
+                        .query_async(secondary)
+                        .await?;
+                    exists
+                }
+            };
+
+            checked += 1;
+            if !matches {
+                mismatched += 1;
+                warn!(
+                    "Dual-write sampler: key {} ({}) differs on secondary",
+                    key, key_type
+                );
+            }
+        }
+
+        Ok((checked, mismatched))
+    }
+}
@@ -0,0 +1,195 @@
+use rdkafka::error::KafkaError;
+use std::error::Error;
+use thiserror::Error;
+
+// Typed errors returned at the library's public boundaries. Internals keep
+// using `Box<dyn Error + Send + Sync>` and are mapped into these on the way out;
+// every type converts back into a boxed error for callers that don't match on it.
+
+/// Any error returned by the library, by where it came from
+#[derive(Debug, Error)]
+pub enum IndexerError {
+    #[error(transparent)]
+    Consumer(#[from] ConsumerError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    PubSub(#[from] PubSubError),
+    /// A message that could not be decoded or applied
+    #[error("invalid data: {0}")]
+    Data(String),
+    #[error("{0}")]
+    Other(#[source] Box<dyn Error + Send + Sync>),
+}
+
+impl From<KafkaError> for IndexerError {
+    fn from(e: KafkaError) -> Self {
+        IndexerError::Consumer(e.into())
+    }
+}
+
+#[cfg(feature = "redis")]
+impl From<redis::RedisError> for IndexerError {
+    fn from(e: redis::RedisError) -> Self {
+        IndexerError::Storage(e.into())
+    }
+}
+
+#[cfg(feature = "scylla-sink")]
+impl From<scylla::transport::errors::NewSessionError> for IndexerError {
+    fn from(e: scylla::transport::errors::NewSessionError) -> Self {
+        IndexerError::Storage(e.into())
+    }
+}
+
+#[cfg(feature = "scylla-sink")]
+impl From<scylla::transport::errors::QueryError> for IndexerError {
+    fn from(e: scylla::transport::errors::QueryError) -> Self {
+        IndexerError::Storage(e.into())
+    }
+}
+
+impl From<serde_json::Error> for IndexerError {
+    fn from(e: serde_json::Error) -> Self {
+        IndexerError::Data(e.to_string())
+    }
+}
+
+impl From<String> for IndexerError {
+    fn from(e: String) -> Self {
+        IndexerError::Data(e)
+    }
+}
+
+impl From<&str> for IndexerError {
+    fn from(e: &str) -> Self {
+        IndexerError::Data(e.to_string())
+    }
+}
+
+// Boxed errors from the internals are sorted into the variant of the error
+// they hold; only errors of other types end up in Other
+impl From<Box<dyn Error + Send + Sync>> for IndexerError {
+    fn from(e: Box<dyn Error + Send + Sync>) -> Self {
+        let e = match e.downcast::<IndexerError>() {
+            Ok(e) => return *e,
+            Err(e) => e,
+        };
+        let e = match e.downcast::<ConsumerError>() {
+            Ok(e) => return IndexerError::Consumer(*e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<StorageError>() {
+            Ok(e) => return IndexerError::Storage(*e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<PubSubError>() {
+            Ok(e) => return IndexerError::PubSub(*e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<KafkaError>() {
+            Ok(e) => return (*e).into(),
+            Err(e) => e,
+        };
+        #[cfg(feature = "redis")]
+        let e = match e.downcast::<redis::RedisError>() {
+            Ok(e) => return (*e).into(),
+            Err(e) => e,
+        };
+        #[cfg(feature = "scylla-sink")]
+        let e = match e.downcast::<scylla::transport::errors::QueryError>() {
+            Ok(e) => return (*e).into(),
+            Err(e) => e,
+        };
+        #[cfg(feature = "scylla-sink")]
+        let e = match e.downcast::<scylla::transport::errors::NewSessionError>() {
+            Ok(e) => return (*e).into(),
+            Err(e) => e,
+        };
+        // Rows ScyllaDB returned that didn't decode as expected
+        #[cfg(feature = "scylla-sink")]
+        let e = match scylla_decode_error(e) {
+            Ok(e) => return IndexerError::Storage(e),
+            Err(e) => e,
+        };
+        match e.downcast::<serde_json::Error>() {
+            Ok(e) => (*e).into(),
+            Err(e) => IndexerError::Other(e),
+        }
+    }
+}
+
+#[cfg(feature = "scylla-sink")]
+fn scylla_decode_error(
+    e: Box<dyn Error + Send + Sync>,
+) -> Result<StorageError, Box<dyn Error + Send + Sync>> {
+    use scylla::deserialize::{DeserializationError, TypeCheckError};
+    use scylla::transport::query_result::{IntoRowsResultError, RowsError};
+
+    if e.is::<IntoRowsResultError>()
+        || e.is::<RowsError>()
+        || e.is::<DeserializationError>()
+        || e.is::<TypeCheckError>()
+    {
+        Ok(StorageError::Decode(e.to_string()))
+    } else {
+        Err(e)
+    }
+}
+
+/// Errors from the Kafka consumer loop
+#[derive(Debug, Error)]
+pub enum ConsumerError {
+    #[error("kafka error: {0}")]
+    Kafka(#[from] KafkaError),
+}
+
+/// Errors from reading or writing indexed state in Redis or Scylla
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[cfg(feature = "redis")]
+    #[error("redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+    #[cfg(feature = "scylla-sink")]
+    #[error("scylla session error: {0}")]
+    ScyllaSession(#[from] scylla::transport::errors::NewSessionError),
+    #[cfg(feature = "scylla-sink")]
+    #[error("scylla query error: {0}")]
+    ScyllaQuery(#[from] scylla::transport::errors::QueryError),
+    /// Stored data that could not be decoded
+    #[error("invalid stored data: {0}")]
+    Decode(String),
+    #[error("{0}")]
+    Other(#[from] Box<dyn Error + Send + Sync>),
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::Decode(e.to_string())
+    }
+}
+
+/// Errors from publishing events
+#[derive(Debug, Error)]
+pub enum PubSubError {
+    #[cfg(feature = "redis")]
+    #[error("redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+    #[error("failed to serialize event: {0}")]
+    Serialize(String),
+    /// The publisher workers have stopped
+    #[error("publishing queue is closed")]
+    QueueClosed,
+}
+
+impl From<serde_json::Error> for PubSubError {
+    fn from(e: serde_json::Error) -> Self {
+        PubSubError::Serialize(e.to_string())
+    }
+}
+
+impl From<bincode::Error> for PubSubError {
+    fn from(e: bincode::Error) -> Self {
+        PubSubError::Serialize(e.to_string())
+    }
+}
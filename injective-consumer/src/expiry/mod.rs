@@ -0,0 +1,46 @@
+use crate::models::DerivativeMarketPayload;
+use crate::scaling;
+
+// Expiry futures settle once: at expiry the chain takes a TWAP of the mark as
+// the settlement price, closes every position at it and marks the market
+// Expired. Market updates carry the expiry and, from then on, the price.
+
+// A settled expiry futures market
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketSettlement {
+    pub market_id: String,
+    // Seconds
+    pub expiration_timestamp: i64,
+    pub settlement_price: f64,
+}
+
+// When the market expires, in seconds; None for perpetuals
+pub fn expiration(market: &DerivativeMarketPayload) -> Option<i64> {
+    if market.is_perpetual {
+        return None;
+    }
+    market
+        .expiration_timestamp
+        .parse::<i64>()
+        .ok()
+        .filter(|expiry| *expiry > 0)
+}
+
+// The settlement of a market that has expired by `time_secs` and whose
+// settlement price the chain has set. Until the price is set the market is
+// still treated as live.
+pub fn settlement(market: &DerivativeMarketPayload, time_secs: i64) -> Option<MarketSettlement> {
+    let expiration_timestamp = expiration(market)?;
+    if market.status != "Expired" && time_secs < expiration_timestamp {
+        return None;
+    }
+    let settlement_price = scaling::price(&market.settlement_price);
+    if settlement_price <= 0.0 {
+        return None;
+    }
+    Some(MarketSettlement {
+        market_id: market.market_id.clone(),
+        expiration_timestamp,
+        settlement_price,
+    })
+}
@@ -0,0 +1,137 @@
+use crate::models::time::DAY_MILLIS;
+use std::collections::HashMap;
+
+// Funding metrics for perpetual markets, derived from successive cumulative
+// funding values. Cumulative funding is the quote amount paid per contract by
+// longs to shorts since launch, so its change between two market updates over
+// the mark price is the funding rate for that span.
+
+const YEAR_MILLIS: f64 = 365.0 * DAY_MILLIS as f64;
+
+// Cumulative funding observed at a block time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FundingPoint {
+    pub cumulative_funding: f64,
+    pub time_millis: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FundingMetrics {
+    // Annualized funding rate in percent; positive when longs pay shorts
+    pub funding_apr: f64,
+    // Funding earned by a short (paid by a long) per day, in basis points of notional
+    pub carry_bps_per_day: f64,
+}
+
+// Metrics for the funding paid between two points, or None when no funding
+// was paid or the points don't span any time
+pub fn funding_metrics(
+    previous: FundingPoint,
+    current: FundingPoint,
+    mark_price: f64,
+) -> Option<FundingMetrics> {
+    let elapsed = current.time_millis - previous.time_millis;
+    let paid = current.cumulative_funding - previous.cumulative_funding;
+    if elapsed <= 0 || paid == 0.0 || mark_price <= 0.0 {
+        return None;
+    }
+
+    let rate = paid / mark_price;
+    Some(FundingMetrics {
+        funding_apr: rate * YEAR_MILLIS / elapsed as f64 * 100.0,
+        carry_bps_per_day: rate * DAY_MILLIS as f64 / elapsed as f64 * 10_000.0,
+    })
+}
+
+// A funding payment seen between two updates of a market
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FundingSettlement {
+    pub previous_cumulative_funding: f64,
+    pub cumulative_funding: f64,
+    // Start of the funding interval the update fell in, or the update's own
+    // time when the market has no interval
+    pub settled_at_secs: i64,
+}
+
+impl FundingSettlement {
+    // Quote amount paid per contract by longs to shorts
+    pub fn funding_delta(&self) -> f64 {
+        self.cumulative_funding - self.previous_cumulative_funding
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SeenFunding {
+    cumulative_funding: f64,
+    interval_index: Option<i64>,
+    // The last interval a settlement was reported for
+    settled_index: Option<i64>,
+}
+
+// Remembers the last cumulative funding and funding interval seen per market,
+// so each settlement is reported once. Funding settles when cumulative funding
+// jumps, or when an update falls in a later interval than the one before it
+// even though nothing was paid, and at most once per interval. The first update
+// of a market after a restart only seeds it.
+#[derive(Debug, Default)]
+pub struct FundingSettlementTracker {
+    markets: HashMap<String, SeenFunding>,
+}
+
+impl FundingSettlementTracker {
+    pub fn new() -> Self {
+        FundingSettlementTracker::default()
+    }
+
+    pub fn observe(
+        &mut self,
+        market_id: &str,
+        cumulative_funding: f64,
+        time_secs: i64,
+        funding_interval_secs: i64,
+    ) -> Option<FundingSettlement> {
+        let interval_index =
+            (funding_interval_secs > 0).then(|| time_secs.div_euclid(funding_interval_secs));
+        let mut current = SeenFunding {
+            cumulative_funding,
+            interval_index,
+            settled_index: None,
+        };
+        let previous = self.markets.get(market_id).copied();
+        let Some(previous) = previous else {
+            self.markets.insert(market_id.to_string(), current);
+            return None;
+        };
+        current.settled_index = previous.settled_index;
+
+        // Updates from an older interval arrive late and say nothing new
+        if let (Some(seen), Some(index)) = (previous.interval_index, interval_index) {
+            if index < seen {
+                return None;
+            }
+        }
+
+        let jumped = cumulative_funding != previous.cumulative_funding;
+        let crossed = matches!(
+            (previous.interval_index, interval_index),
+            (Some(seen), Some(index)) if index > seen
+        );
+        // A payment posted after the update that crossed into its interval
+        // belongs to the settlement already reported
+        let settled = interval_index.is_some() && interval_index == previous.settled_index;
+        if (!jumped && !crossed) || settled {
+            self.markets.insert(market_id.to_string(), current);
+            return None;
+        }
+        current.settled_index = interval_index;
+        self.markets.insert(market_id.to_string(), current);
+        Some(FundingSettlement {
+            previous_cumulative_funding: previous.cumulative_funding,
+            cumulative_funding,
+            settled_at_secs: match interval_index {
+                Some(index) => index * funding_interval_secs,
+                None => time_secs,
+            },
+        })
+    }
+}
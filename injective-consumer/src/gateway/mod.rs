@@ -0,0 +1,600 @@
+use crate::delivery::{ClientOutbox, DeliveryConfig};
+use crate::models::time;
+use crate::pubsub::{EventSubscriber, RedisPubSubConfig, SequenceCheck, StreamEvent};
+use crate::secrets::{self, RedisRole};
+use crate::subscriptions::{SubscriptionFilter, SubscriptionManager};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use futures::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+// WebSocket front end for the Redis pub/sub event channels, so browsers and
+// other clients don't have to speak Redis. One Redis subscription feeds every
+// connection; each connection has its own bounded outbox, so a slow client
+// is downsampled or cut off instead of holding up the others.
+//
+// Clients send JSON requests:
+//   {"op":"subscribe","id":"trades","event_types":["TradeUpdate"],"market_ids":["0x.."]}
+//   {"op":"unsubscribe","id":"trades"}
+//   {"op":"subscriptions"}
+//   {"op":"ping"}
+// and receive messages tagged by "type": welcome, subscribed, unsubscribed,
+// subscriptions, pong, error, and event (a StreamEvent plus the ids of the
+// subscriptions it matched). Connecting with ?client_id=<id> restores the
+// subscriptions a client had before it dropped.
+
+#[derive(Debug, Clone)]
+pub struct GatewayConfig {
+    pub listen_addr: String,
+    pub redis_url: String,
+    // Must match the publishing consumer's channel prefix
+    pub channel_prefix: String,
+    // How often clients are pinged and their stored subscriptions refreshed
+    pub heartbeat_interval: Duration,
+    // Clients that send nothing, not even a pong, for this long are dropped
+    pub client_timeout: Duration,
+    // Subscriptions of a dropped client are kept this long for it to resume
+    pub subscription_ttl: Duration,
+    pub max_clients: usize,
+    pub delivery: DeliveryConfig,
+    pub metrics_interval_secs: u64,
+    // Don't store subscriptions, for a read-only Redis user
+    pub read_only: bool,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        GatewayConfig {
+            listen_addr: "0.0.0.0:8090".to_string(),
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            channel_prefix: RedisPubSubConfig::default().channel_prefix,
+            heartbeat_interval: Duration::from_secs(15),
+            client_timeout: Duration::from_secs(45),
+            subscription_ttl: Duration::from_secs(300),
+            max_clients: 10_000,
+            delivery: DeliveryConfig::default(),
+            metrics_interval_secs: 60,
+            read_only: false,
+        }
+    }
+}
+
+impl GatewayConfig {
+    pub fn from_env() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut config = GatewayConfig::default();
+
+        if let Ok(addr) = env::var("GATEWAY_LISTEN_ADDR") {
+            config.listen_addr = addr;
+        }
+
+        if let Some(url) = secrets::role_redis_url(RedisRole::Reader, None)? {
+            config.redis_url = url;
+        }
+
+        if let Ok(prefix) = env::var("PUBSUB_CHANNEL_PREFIX") {
+            config.channel_prefix = prefix;
+        }
+
+        if let Ok(secs) = env::var("GATEWAY_HEARTBEAT_SECS") {
+            config.heartbeat_interval = Duration::from_secs(secs.parse()?);
+        }
+
+        if let Ok(secs) = env::var("GATEWAY_CLIENT_TIMEOUT_SECS") {
+            config.client_timeout = Duration::from_secs(secs.parse()?);
+        }
+
+        if let Ok(secs) = env::var("GATEWAY_SUBSCRIPTION_TTL_SECS") {
+            config.subscription_ttl = Duration::from_secs(secs.parse()?);
+        }
+
+        if let Ok(max) = env::var("GATEWAY_MAX_CLIENTS") {
+            config.max_clients = max.parse()?;
+        }
+
+        if let Ok(cap) = env::var("GATEWAY_BUFFER_CAP") {
+            config.delivery.buffer_cap = cap.parse()?;
+        }
+
+        if let Ok(policy) = env::var("GATEWAY_SLOW_CONSUMER") {
+            config.delivery.policy = policy.parse()?;
+        }
+
+        // 0 turns the lag limit off
+        if let Ok(secs) = env::var("GATEWAY_MAX_LAG_SECS") {
+            let secs: u64 = secs.parse()?;
+            config.delivery.max_lag = (secs > 0).then(|| Duration::from_secs(secs));
+        }
+
+        if let Ok(read_only) = env::var("REDIS_READ_ONLY") {
+            config.read_only = read_only.parse()?;
+        }
+
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum ClientRequest {
+    Subscribe {
+        #[serde(default)]
+        id: Option<String>,
+        #[serde(flatten)]
+        filter: SubscriptionFilter,
+    },
+    Unsubscribe {
+        id: String,
+    },
+    Subscriptions,
+    Ping,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    Welcome {
+        client_id: &'a str,
+        subscriptions: HashMap<String, SubscriptionFilter>,
+    },
+    Subscribed {
+        id: String,
+    },
+    Unsubscribed {
+        id: String,
+    },
+    Subscriptions {
+        subscriptions: HashMap<String, SubscriptionFilter>,
+    },
+    Pong,
+    Error {
+        message: String,
+    },
+    Event {
+        subscriptions: Vec<String>,
+        #[serde(flatten)]
+        event: &'a StreamEvent,
+    },
+}
+
+impl ServerMessage<'_> {
+    fn to_message(&self) -> Message {
+        match serde_json::to_string(self) {
+            Ok(text) => Message::Text(text),
+            Err(e) => {
+                error!("Failed to serialize gateway message: {}", e);
+                Message::Text(r#"{"type":"error","message":"internal error"}"#.to_string())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GatewayMetrics {
+    pub connections: AtomicU64,
+    pub events_received: AtomicU64,
+    pub events_queued: AtomicU64,
+    pub decode_errors: AtomicU64,
+    pub slow_disconnects: AtomicU64,
+    // Events the publisher numbered but the gateway never received
+    pub events_missed: AtomicU64,
+    // Events received at or below a sequence already seen on their channel
+    pub stale_events: AtomicU64,
+}
+
+// One connected client
+struct GatewayClient {
+    outbox: ClientOutbox,
+    filters: RwLock<HashMap<String, SubscriptionFilter>>,
+}
+
+impl GatewayClient {
+    // Ids of the subscriptions an event matches
+    fn matching(&self, event: &StreamEvent) -> Vec<String> {
+        self.filters
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, filter)| filter.matches(event))
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    fn wants(&self, event: &StreamEvent) -> bool {
+        self.filters
+            .read()
+            .unwrap()
+            .values()
+            .any(|filter| filter.matches(event))
+    }
+}
+
+pub struct WsGateway {
+    config: GatewayConfig,
+    clients: DashMap<String, Arc<GatewayClient>>,
+    subscriptions: SubscriptionManager,
+    metrics: Arc<GatewayMetrics>,
+    next_id: AtomicU64,
+}
+
+impl WsGateway {
+    pub async fn new(config: GatewayConfig) -> Result<Arc<Self>, Box<dyn Error + Send + Sync>> {
+        let subscriptions = SubscriptionManager::new(&config.redis_url, config.subscription_ttl)
+            .await?
+            .with_read_only(config.read_only);
+        Ok(Arc::new(WsGateway {
+            config,
+            clients: DashMap::new(),
+            subscriptions,
+            metrics: Arc::new(GatewayMetrics::default()),
+            next_id: AtomicU64::new(1),
+        }))
+    }
+
+    pub fn metrics(&self) -> Arc<GatewayMetrics> {
+        self.metrics.clone()
+    }
+
+    // Serve clients until the shutdown flag flips to true
+    pub async fn run(
+        self: Arc<Self>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let listener = TcpListener::bind(&self.config.listen_addr).await?;
+        info!("WebSocket gateway listening on {}", self.config.listen_addr);
+
+        task::spawn(self.clone().run_fan_out());
+        task::spawn(self.clone().run_maintenance());
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (stream, peer) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            warn!("Failed to accept gateway connection: {}", e);
+                            continue;
+                        }
+                    };
+                    if self.clients.len() >= self.config.max_clients {
+                        warn!("Refusing {}: {} clients connected", peer, self.clients.len());
+                        continue;
+                    }
+                    let gateway = self.clone();
+                    task::spawn(async move {
+                        if let Err(e) = gateway.handle_connection(stream, peer).await {
+                            debug!("Gateway connection from {} ended: {}", peer, e);
+                        }
+                    });
+                }
+                _ = shutdown.changed() => {
+                    if *shutdown.borrow() {
+                        info!("WebSocket gateway shutting down");
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    // Read the event channels and queue every event for the clients it
+    // matches, resubscribing after Redis drops the connection
+    async fn run_fan_out(self: Arc<Self>) {
+        loop {
+            if let Err(e) = self.fan_out().await {
+                error!("Gateway pub/sub subscription failed: {}", e);
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn fan_out(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let prefix = &self.config.channel_prefix;
+        let mut subscriber = EventSubscriber::connect(&self.config.redis_url, prefix).await?;
+        info!("Gateway subscribed to {} channels", prefix);
+
+        while let Some(message) = subscriber.next().await {
+            let message = match message {
+                Ok(message) => message,
+                Err(e) => {
+                    self.metrics.decode_errors.fetch_add(1, Ordering::Relaxed);
+                    debug!("Skipping undecodable pub/sub message: {}", e);
+                    continue;
+                }
+            };
+            for (event, check) in message.events {
+                match check {
+                    SequenceCheck::Gap { missed } => {
+                        self.metrics
+                            .events_missed
+                            .fetch_add(missed, Ordering::Relaxed);
+                        debug!("Missed {} events on {}", missed, message.channel);
+                    }
+                    SequenceCheck::Stale { last } => {
+                        self.metrics.stale_events.fetch_add(1, Ordering::Relaxed);
+                        debug!(
+                            "Event {} on {} arrived after {}",
+                            event.sequence, message.channel, last
+                        );
+                    }
+                    SequenceCheck::Restarted => {
+                        info!("Publisher of {} restarted its sequence", message.channel)
+                    }
+                    SequenceCheck::InOrder | SequenceCheck::Unsequenced => {}
+                }
+                self.metrics.events_received.fetch_add(1, Ordering::Relaxed);
+                self.dispatch(&event);
+            }
+        }
+        Err("pub/sub connection closed".into())
+    }
+
+    // Never waits on a client: a full outbox is handled by its policy, and a
+    // client that was cut off is closed by its own connection task
+    fn dispatch(&self, event: &StreamEvent) {
+        for entry in self.clients.iter() {
+            let client = entry.value();
+            if client.wants(event) && client.outbox.push(event.clone()).is_ok() {
+                self.metrics.events_queued.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    async fn run_maintenance(self: Arc<Self>) {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.config.metrics_interval_secs));
+        loop {
+            interval.tick().await;
+
+            match self.subscriptions.prune_expired().await {
+                Ok(0) => {}
+                Ok(removed) => debug!("Pruned {} expired gateway clients", removed),
+                Err(e) => warn!("Failed to prune gateway clients: {}", e),
+            }
+
+            let dropped: u64 = self
+                .clients
+                .iter()
+                .map(|entry| entry.value().outbox.snapshot().dropped)
+                .sum();
+            info!(
+                "Gateway: {} clients, {} events received, {} queued, {} dropped by connected clients, {} slow disconnects, {} decode errors, {} missed, {} stale",
+                self.clients.len(),
+                self.metrics.events_received.load(Ordering::Relaxed),
+                self.metrics.events_queued.load(Ordering::Relaxed),
+                dropped,
+                self.metrics.slow_disconnects.load(Ordering::Relaxed),
+                self.metrics.decode_errors.load(Ordering::Relaxed),
+                self.metrics.events_missed.load(Ordering::Relaxed),
+                self.metrics.stale_events.load(Ordering::Relaxed),
+            );
+        }
+    }
+
+    #[allow(
+        clippy::result_large_err,
+        reason = "the handshake callback must return tungstenite's ErrorResponse"
+    )]
+    async fn handle_connection(
+        self: Arc<Self>,
+        stream: TcpStream,
+        peer: SocketAddr,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut requested_id = None;
+        let ws = tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response| {
+            requested_id = client_id_from_query(request.uri().query());
+            Ok::<Response, _>(response)
+        })
+        .await?;
+
+        let resuming = requested_id.is_some();
+        let client_id = requested_id.unwrap_or_else(|| self.generate_id("ws"));
+        let client = Arc::new(GatewayClient {
+            outbox: ClientOutbox::new(&client_id, self.config.delivery.clone()),
+            filters: RwLock::new(HashMap::new()),
+        });
+
+        // The id is claimed in one step, so two connections resuming the same
+        // client can't both take it
+        let claimed = match self.clients.entry(client_id.clone()) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(slot) => {
+                slot.insert(client.clone());
+                true
+            }
+        };
+        if !claimed {
+            let mut ws = ws;
+            let reply = ServerMessage::Error {
+                message: format!("client {} is already connected", client_id),
+            };
+            ws.send(reply.to_message()).await?;
+            ws.close(None).await?;
+            return Ok(());
+        }
+
+        // A resuming client gets its stored subscriptions back
+        if resuming {
+            let filters = self
+                .subscriptions
+                .subscriptions(&client_id)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to restore subscriptions of {}: {}", client_id, e);
+                    HashMap::new()
+                });
+            *client.filters.write().unwrap() = filters;
+        }
+        self.metrics.connections.fetch_add(1, Ordering::Relaxed);
+        info!("Gateway client {} connected from {}", client_id, peer);
+
+        let result = self.serve(&client_id, &client, ws).await;
+        self.clients.remove(&client_id);
+
+        // Clients that close deliberately don't come back for their subscriptions
+        match &result {
+            Ok(true) => {
+                if let Err(e) = self.subscriptions.remove_client(&client_id).await {
+                    warn!("Failed to remove subscriptions of {}: {}", client_id, e);
+                }
+            }
+            Ok(false) => {}
+            Err(e) => debug!("Gateway client {} failed: {}", client_id, e),
+        }
+        info!("Gateway client {} disconnected", client_id);
+        result.map(|_| ())
+    }
+
+    // Returns whether the client closed the connection itself
+    async fn serve(
+        &self,
+        client_id: &str,
+        client: &GatewayClient,
+        ws: WebSocketStream<TcpStream>,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let (mut sink, mut source) = ws.split();
+
+        let welcome = ServerMessage::Welcome {
+            client_id,
+            subscriptions: client.filters.read().unwrap().clone(),
+        };
+        sink.send(welcome.to_message()).await?;
+
+        let mut heartbeat = tokio::time::interval(self.config.heartbeat_interval);
+        heartbeat.tick().await;
+        let mut last_seen = Instant::now();
+
+        loop {
+            tokio::select! {
+                delivered = client.outbox.next() => match delivered {
+                    Ok(event) => {
+                        let subscriptions = client.matching(&event);
+                        if subscriptions.is_empty() {
+                            continue;
+                        }
+                        let message = ServerMessage::Event { subscriptions, event: &event };
+                        sink.send(message.to_message()).await?;
+                    }
+                    Err(reason) => {
+                        self.metrics.slow_disconnects.fetch_add(1, Ordering::Relaxed);
+                        sink.send(close_message(CloseCode::Again, reason)).await?;
+                        return Ok(false);
+                    }
+                },
+                incoming = source.next() => {
+                    let message = match incoming {
+                        Some(Ok(message)) => message,
+                        Some(Err(e)) => return Err(e.into()),
+                        None => return Ok(false),
+                    };
+                    last_seen = Instant::now();
+                    match message {
+                        Message::Text(text) => {
+                            let reply = self.handle_request(client_id, client, &text).await;
+                            sink.send(reply.to_message()).await?;
+                        }
+                        Message::Close(_) => {
+                            let _ = sink.close().await;
+                            return Ok(true);
+                        }
+                        // Pings are answered by tungstenite; pongs only count as activity
+                        _ => {}
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if last_seen.elapsed() > self.config.client_timeout {
+                        warn!("Gateway client {} timed out", client_id);
+                        sink.send(close_message(CloseCode::Away, "heartbeat timeout")).await?;
+                        return Ok(false);
+                    }
+                    sink.send(Message::Ping(Vec::new())).await?;
+                    if let Err(e) = self.subscriptions.touch(client_id).await {
+                        warn!("Failed to refresh subscriptions of {}: {}", client_id, e);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_request(
+        &self,
+        client_id: &str,
+        client: &GatewayClient,
+        text: &str,
+    ) -> ServerMessage<'static> {
+        let request: ClientRequest = match serde_json::from_str(text) {
+            Ok(request) => request,
+            Err(e) => {
+                return ServerMessage::Error {
+                    message: format!("invalid request: {}", e),
+                }
+            }
+        };
+
+        match request {
+            ClientRequest::Subscribe { id, filter } => {
+                let id = id.unwrap_or_else(|| self.generate_id("sub"));
+                if let Err(e) = self.subscriptions.subscribe(client_id, &id, &filter).await {
+                    warn!("Failed to store subscription of {}: {}", client_id, e);
+                }
+                client.filters.write().unwrap().insert(id.clone(), filter);
+                ServerMessage::Subscribed { id }
+            }
+            ClientRequest::Unsubscribe { id } => {
+                if client.filters.write().unwrap().remove(&id).is_none() {
+                    return ServerMessage::Error {
+                        message: format!("unknown subscription {}", id),
+                    };
+                }
+                if let Err(e) = self.subscriptions.unsubscribe(client_id, &id).await {
+                    warn!("Failed to remove subscription of {}: {}", client_id, e);
+                }
+                ServerMessage::Unsubscribed { id }
+            }
+            ClientRequest::Subscriptions => ServerMessage::Subscriptions {
+                subscriptions: client.filters.read().unwrap().clone(),
+            },
+            ClientRequest::Ping => ServerMessage::Pong,
+        }
+    }
+
+    fn generate_id(&self, prefix: &str) -> String {
+        format!(
+            "{}-{}-{}",
+            prefix,
+            time::now_millis(),
+            self.next_id.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+}
+
+fn client_id_from_query(query: Option<&str>) -> Option<String> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "client_id")
+        .map(|(_, value)| value.to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn close_message(code: CloseCode, reason: impl ToString) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.to_string().into(),
+    }))
+}
@@ -0,0 +1,83 @@
+use crate::models::KafkaMessage;
+#[cfg(feature = "pubsub")]
+use crate::pubsub::StreamEvent;
+use log::warn;
+use std::sync::Arc;
+
+#[cfg(feature = "scripting")]
+mod rhai_hook;
+#[cfg(feature = "scripting")]
+pub use rhai_hook::RhaiHook;
+
+// What a hook decided to do with a message or event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookOutcome {
+    Keep,
+    Drop,
+}
+
+// Extension point for operator-defined enrichment. Hooks may mutate what they
+// are given (add tags, rewrite fields) or drop it entirely.
+pub trait EventHook: Send + Sync {
+    fn name(&self) -> &str;
+
+    // Called for every Kafka message before any processor stores it
+    fn on_message(
+        &self,
+        _message: &mut KafkaMessage,
+    ) -> Result<HookOutcome, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(HookOutcome::Keep)
+    }
+
+    // Called for every PubSub event before it is published
+    #[cfg(feature = "pubsub")]
+    fn on_event(
+        &self,
+        _event: &mut StreamEvent,
+    ) -> Result<HookOutcome, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(HookOutcome::Keep)
+    }
+}
+
+// Ordered list of hooks. A failing hook is logged and skipped so a broken
+// script never stops the pipeline; the first hook to drop wins.
+#[derive(Clone, Default)]
+pub struct HookChain {
+    hooks: Vec<Arc<dyn EventHook>>,
+}
+
+impl HookChain {
+    pub fn new() -> Self {
+        HookChain::default()
+    }
+
+    pub fn with_hook(mut self, hook: Arc<dyn EventHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    // Returns false if the message should be discarded
+    pub fn apply_message(&self, message: &mut KafkaMessage) -> bool {
+        for hook in &self.hooks {
+            match hook.on_message(message) {
+                Ok(HookOutcome::Keep) => {}
+                Ok(HookOutcome::Drop) => return false,
+                Err(e) => warn!("Hook {} failed on message: {}", hook.name(), e),
+            }
+        }
+        true
+    }
+
+    // Returns false if the event should not be published
+    #[cfg(feature = "pubsub")]
+    pub fn apply_event(&self, event: &mut StreamEvent) -> bool {
+        for hook in &self.hooks {
+            match hook.on_event(event) {
+                Ok(HookOutcome::Keep) => {}
+                Ok(HookOutcome::Drop) => return false,
+                Err(e) => warn!("Hook {} failed on event: {}", hook.name(), e),
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,100 @@
+use super::{EventHook, HookOutcome};
+use crate::models::KafkaMessage;
+#[cfg(feature = "pubsub")]
+use crate::pubsub::StreamEvent;
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde::{de::DeserializeOwned, Serialize};
+use std::error::Error;
+use std::path::Path;
+
+// A hook backed by a Rhai script. The script may define either or both of
+//
+//   fn on_message(message) { ... }
+//   fn on_event(event) { ... }
+//
+// Each receives the message/event as an object map and returns the (possibly
+// modified) map to keep it, or () to drop it.
+pub struct RhaiHook {
+    name: String,
+    engine: Engine,
+    ast: AST,
+    has_on_message: bool,
+    #[cfg_attr(not(feature = "pubsub"), allow(dead_code))]
+    has_on_event: bool,
+}
+
+impl RhaiHook {
+    pub fn from_file(
+        path: &str,
+        max_operations: u64,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(max_operations);
+
+        let ast = engine
+            .compile_file(path.into())
+            .map_err(|e| format!("Failed to compile hook script {}: {}", path, e))?;
+        let has_fn = |name: &str| ast.iter_functions().any(|f| f.name == name);
+        let has_on_message = has_fn("on_message");
+        let has_on_event = has_fn("on_event");
+
+        let name = Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string());
+
+        Ok(RhaiHook {
+            name,
+            engine,
+            ast,
+            has_on_message,
+            has_on_event,
+        })
+    }
+
+    // Round-trip a value through the script function, replacing it in place
+    fn call<T: Serialize + DeserializeOwned>(
+        &self,
+        function: &str,
+        value: &mut T,
+    ) -> Result<HookOutcome, Box<dyn Error + Send + Sync>> {
+        let input = rhai::serde::to_dynamic(&*value).map_err(|e| e.to_string())?;
+        let output: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, function, (input,))
+            .map_err(|e| e.to_string())?;
+
+        if output.is_unit() {
+            return Ok(HookOutcome::Drop);
+        }
+        *value = rhai::serde::from_dynamic(&output).map_err(|e| e.to_string())?;
+        Ok(HookOutcome::Keep)
+    }
+}
+
+impl EventHook for RhaiHook {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_message(
+        &self,
+        message: &mut KafkaMessage,
+    ) -> Result<HookOutcome, Box<dyn Error + Send + Sync>> {
+        if !self.has_on_message {
+            return Ok(HookOutcome::Keep);
+        }
+        self.call("on_message", message)
+    }
+
+    #[cfg(feature = "pubsub")]
+    fn on_event(
+        &self,
+        event: &mut StreamEvent,
+    ) -> Result<HookOutcome, Box<dyn Error + Send + Sync>> {
+        if !self.has_on_event {
+            return Ok(HookOutcome::Keep);
+        }
+        self.call("on_event", event)
+    }
+}
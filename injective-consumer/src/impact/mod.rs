@@ -0,0 +1,78 @@
+use crate::orderbook::BookLevel;
+use serde::{Deserialize, Serialize};
+
+// Price impact of a market order walked through the aggregated L2 book. The
+// Redis processor keeps the book under redis_keys::derivative_depth; the
+// reader feeds it to estimate_fill.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FillEstimate {
+    pub market_id: String,
+    pub side: Side,
+    pub requested_quantity: f64,
+    // Less than requested when the stored book is too thin
+    pub filled_quantity: f64,
+    pub average_price: f64,
+    // Best price on the side being taken
+    pub reference_price: f64,
+    // Average price against the reference price, positive when worse
+    pub slippage_bps: f64,
+    pub levels_consumed: usize,
+    pub worst_price: f64,
+}
+
+// Walk `quantity` through the side a market order of `side` takes (asks for a
+// buy, bids for a sell). Levels must be best first, as stored. Returns None
+// when that side is empty or the quantity isn't positive.
+pub fn estimate_fill(
+    market_id: &str,
+    levels: &[BookLevel],
+    side: Side,
+    quantity: f64,
+) -> Option<FillEstimate> {
+    let best = levels.first()?;
+    if quantity <= 0.0 {
+        return None;
+    }
+
+    let mut remaining = quantity;
+    let mut notional = 0.0;
+    let mut levels_consumed = 0;
+    let mut worst_price = best.price;
+    for level in levels {
+        if remaining <= 0.0 {
+            break;
+        }
+        let take = remaining.min(level.quantity);
+        notional += take * level.price;
+        remaining -= take;
+        levels_consumed += 1;
+        worst_price = level.price;
+    }
+
+    let filled_quantity = quantity - remaining.max(0.0);
+    let average_price = notional / filled_quantity;
+    let direction = match side {
+        Side::Buy => 1.0,
+        Side::Sell => -1.0,
+    };
+
+    Some(FillEstimate {
+        market_id: market_id.to_string(),
+        side,
+        requested_quantity: quantity,
+        filled_quantity,
+        average_price,
+        reference_price: best.price,
+        slippage_bps: direction * (average_price - best.price) / best.price * 10_000.0,
+        levels_consumed,
+        worst_price,
+    })
+}
@@ -0,0 +1,195 @@
+use crate::redis_keys;
+use log::{error, info, warn};
+use redis::{aio::ConnectionManager, Client};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::{task, time};
+
+// Configuration for the Redis keyspace usage reporter
+#[derive(Clone)]
+pub struct KeyspaceMonitorConfig {
+    pub redis_url: String,
+    pub interval_secs: u64,
+    // Key prefixes to report on, by group name; the defaults follow the
+    // installed key schema
+    pub prefixes: Vec<(String, String)>,
+    // Number of keys per prefix whose MEMORY USAGE is sampled to estimate the total
+    pub sample_size: usize,
+    pub scan_count: usize,
+    pub batch_pause_ms: u64,
+    // Estimated byte budgets per group; exceeding one logs a warning
+    pub budgets: HashMap<String, u64>,
+}
+
+impl Default for KeyspaceMonitorConfig {
+    fn default() -> Self {
+        KeyspaceMonitorConfig {
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            interval_secs: 600,
+            prefixes: vec![
+                ("markets".to_string(), redis_keys::key_prefix(&["market"])),
+                (
+                    "positions".to_string(),
+                    redis_keys::key_prefix(&["position"]),
+                ),
+                (
+                    "orderbooks".to_string(),
+                    redis_keys::key_prefix(&["orderbook"]),
+                ),
+                ("trades".to_string(), redis_keys::key_prefix(&["trades"])),
+                (
+                    "aggregates".to_string(),
+                    redis_keys::key_prefix(&["aggregates"]),
+                ),
+            ],
+            sample_size: 50,
+            scan_count: 500,
+            batch_pause_ms: 20,
+            budgets: HashMap::new(),
+        }
+    }
+}
+
+// Usage estimate for one key prefix
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyspaceUsage {
+    pub group: String,
+    pub prefix: String,
+    pub key_count: u64,
+    pub sampled_keys: u64,
+    pub estimated_bytes: u64,
+    pub budget_bytes: Option<u64>,
+}
+
+impl KeyspaceUsage {
+    pub fn over_budget(&self) -> bool {
+        self.budget_bytes
+            .is_some_and(|budget| self.estimated_bytes > budget)
+    }
+}
+
+// Periodically samples Redis memory usage per key prefix
+pub struct KeyspaceMonitor {
+    config: KeyspaceMonitorConfig,
+    connection: ConnectionManager,
+    latest: Arc<Mutex<Vec<KeyspaceUsage>>>,
+}
+
+impl KeyspaceMonitor {
+    pub async fn new(config: KeyspaceMonitorConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let client = Client::open(config.redis_url.clone())?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(KeyspaceMonitor {
+            config,
+            connection,
+            latest: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    // Handle to the most recent usage report, shared with the background task
+    pub fn latest_usage(&self) -> Arc<Mutex<Vec<KeyspaceUsage>>> {
+        self.latest.clone()
+    }
+
+    // Spawn the reporting loop in the background
+    pub fn spawn(self) -> task::JoinHandle<()> {
+        task::spawn(async move {
+            let mut interval_timer = time::interval(Duration::from_secs(self.config.interval_secs));
+            let mut connection = self.connection.clone();
+
+            loop {
+                interval_timer.tick().await;
+
+                match self.sample(&mut connection).await {
+                    Ok(report) => {
+                        for usage in &report {
+                            info!(
+                                "Redis keyspace usage: group={}, keys={}, estimated_bytes={}",
+                                usage.group, usage.key_count, usage.estimated_bytes
+                            );
+                            if usage.over_budget() {
+                                warn!(
+                                    "Redis keyspace group {} exceeds its budget: {} > {} bytes",
+                                    usage.group,
+                                    usage.estimated_bytes,
+                                    usage.budget_bytes.unwrap_or_default()
+                                );
+                            }
+                        }
+                        *self.latest.lock().await = report;
+                    }
+                    Err(e) => error!("Redis keyspace sampling failed: {}", e),
+                }
+            }
+        })
+    }
+
+    // Estimate the memory used by every configured prefix
+    pub async fn sample(
+        &self,
+        connection: &mut ConnectionManager,
+    ) -> Result<Vec<KeyspaceUsage>, Box<dyn Error + Send + Sync>> {
+        let mut report = Vec::with_capacity(self.config.prefixes.len());
+
+        for (group, prefix) in &self.config.prefixes {
+            let pattern = format!("{}*", prefix);
+            let mut cursor: u64 = 0;
+            let mut key_count: u64 = 0;
+            let mut sampled_keys: u64 = 0;
+            let mut sampled_bytes: u64 = 0;
+
+            loop {
+                let (next_cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(&pattern)
+                    .arg("COUNT")
+                    .arg(self.config.scan_count)
+                    .query_async(connection)
+                    .await?;
+
+                key_count += keys.len() as u64;
+
+                for key in keys {
+                    if sampled_keys as usize >= self.config.sample_size {
+                        break;
+                    }
+                    let bytes: Option<u64> = redis::cmd("MEMORY")
+                        .arg("USAGE")
+                        .arg(&key)
+                        .query_async(connection)
+                        .await?;
+                    if let Some(bytes) = bytes {
+                        sampled_bytes += bytes;
+                        sampled_keys += 1;
+                    }
+                }
+
+                cursor = next_cursor;
+                if cursor == 0 {
+                    break;
+                }
+                time::sleep(Duration::from_millis(self.config.batch_pause_ms)).await;
+            }
+
+            let estimated_bytes = sampled_bytes
+                .checked_div(sampled_keys)
+                .map_or(0, |avg| avg * key_count);
+
+            report.push(KeyspaceUsage {
+                group: group.clone(),
+                prefix: prefix.clone(),
+                key_count,
+                sampled_keys,
+                estimated_bytes,
+                budget_bytes: self.config.budgets.get(group).copied(),
+            });
+        }
+
+        Ok(report)
+    }
+}
@@ -1,15 +1,86 @@
 // This file exposes our library components for both internal use and external consumers
 
 // Re-export the modules
+pub mod address;
+pub mod admin;
+#[cfg(feature = "redis")]
+pub mod block_dedup;
+pub mod candles;
+pub mod catchup;
+pub mod clock;
 pub mod compute;
 pub mod config;
 pub mod consumer;
+pub mod correlation;
+pub mod dead_letter;
+#[cfg(feature = "api")]
+pub mod delivery;
+pub mod diagnostics;
+#[cfg(feature = "redis-sink")]
+pub mod dual_write;
+pub mod error;
+pub mod expiry;
+pub mod funding;
+#[cfg(feature = "gateway")]
+pub mod gateway;
+pub mod hooks;
+pub mod impact;
+#[cfg(feature = "redis-sink")]
+pub mod keyspace;
+#[cfg(feature = "redis-sink")]
+pub mod liquidation;
+#[cfg(feature = "redis-sink")]
+pub mod market_preloader;
+pub mod market_summary;
+pub mod metrics;
+pub mod mid_price;
+#[cfg(feature = "redis")]
+pub mod migration;
 pub mod models;
+#[cfg(feature = "notifications")]
+pub mod notifications;
+pub mod orderbook;
+pub mod payload_log;
+pub mod position_diff;
+#[cfg(feature = "pubsub")]
 pub mod pubsub;
+#[cfg(feature = "api")]
+pub mod reader;
+pub mod readiness;
+#[cfg(feature = "redis-sink")]
+pub mod reaper;
+#[cfg(feature = "redis-sink")]
 pub mod redis_consumer;
+pub mod redis_keys;
+#[cfg(feature = "pubsub")]
+pub mod routing;
+pub mod scaling;
+#[cfg(feature = "scylla-sink")]
 pub mod scylladb_consumer;
+pub mod secrets;
+#[cfg(all(feature = "redis-sink", feature = "scylla-sink"))]
+pub mod service;
+pub mod storage;
+#[cfg(feature = "api")]
+pub mod subscriptions;
+#[cfg(feature = "api")]
+pub mod trade_history;
+#[cfg(feature = "trade-qa")]
+pub mod trade_qa;
+#[cfg(feature = "api")]
+pub mod udf;
+pub mod volatility;
+pub mod window;
+pub mod wire;
 // Re-export the key components for easier use
 pub use config::Config;
 pub use consumer::{KafkaConsumer, MessageProcessor};
+pub use error::{ConsumerError, IndexerError, PubSubError, StorageError};
+#[cfg(feature = "api")]
+pub use reader::RedisReader;
+#[cfg(feature = "redis-sink")]
 pub use redis_consumer::RedisProcessor;
+#[cfg(feature = "scylla-sink")]
 pub use scylladb_consumer::ScyllaDBProcessor;
+#[cfg(feature = "api")]
+pub use subscriptions::SubscriptionManager;
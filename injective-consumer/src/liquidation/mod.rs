@@ -0,0 +1,380 @@
+use crate::compute::{
+    calculate_liquidation_price, calculate_margin_ratio, calculate_unrealized_pnl,
+    distance_to_liquidation, is_liquidatable,
+};
+use crate::models::time::now_millis;
+use crate::pubsub::{EventType, RedisPubSubService, StreamEvent};
+use crate::redis_keys;
+use log::{error, info, warn};
+use redis::{aio::ConnectionManager, AsyncCommands, Client};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Interval;
+use tokio::{task, time};
+
+// Configuration for the periodic liquidation recompute
+#[derive(Clone)]
+pub struct LiquidationRecomputeConfig {
+    pub redis_url: String,
+    // Seconds between passes; 0 runs passes only when triggered
+    pub interval_secs: u64,
+    // Positions closest to liquidation published after every pass (0 disables)
+    pub at_risk_top_k: usize,
+}
+
+impl Default for LiquidationRecomputeConfig {
+    fn default() -> Self {
+        LiquidationRecomputeConfig {
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            interval_secs: 5,
+            at_risk_top_k: 50,
+        }
+    }
+}
+
+// Position fields the recompute needs, as stored by the Redis processor (scaled)
+type StoredPosition = (
+    Option<String>, // is_long
+    Option<String>, // quantity
+    Option<String>, // entry_price
+    Option<String>, // margin
+    Option<String>, // cumulative_funding_entry
+    Option<String>, // liquidation_price
+    Option<String>, // is_liquidatable
+);
+
+// Re-evaluates every stored position against the cached mark price and funding
+// of its market on a fixed interval, so liquidation state and alerts keep up
+// with prices even when no new position or market message arrives. A trigger
+// runs an extra pass at once, e.g. when funding settles and every margin moves.
+pub struct LiquidationRecomputer {
+    config: LiquidationRecomputeConfig,
+    connection: ConnectionManager,
+    pubsub: Option<Arc<RedisPubSubService>>,
+    trigger: Option<Arc<Notify>>,
+}
+
+impl LiquidationRecomputer {
+    pub async fn new(
+        config: LiquidationRecomputeConfig,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let client = Client::open(config.redis_url.clone())?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(LiquidationRecomputer {
+            config,
+            connection,
+            pubsub: None,
+            trigger: None,
+        })
+    }
+
+    pub fn with_pubsub(mut self, pubsub: Arc<RedisPubSubService>) -> Self {
+        self.pubsub = Some(pubsub);
+        self
+    }
+
+    // Run a pass whenever `trigger` is notified, on top of the interval.
+    // Notifications that arrive during a pass are folded into the next one.
+    pub fn with_trigger(mut self, trigger: Arc<Notify>) -> Self {
+        self.trigger = Some(trigger);
+        self
+    }
+
+    // Spawn the recompute loop in the background
+    pub fn spawn(self) -> task::JoinHandle<()> {
+        task::spawn(async move {
+            let mut interval_timer = (self.config.interval_secs > 0)
+                .then(|| time::interval(Duration::from_secs(self.config.interval_secs)));
+            let mut connection = self.connection.clone();
+
+            loop {
+                tokio::select! {
+                    _ = tick(&mut interval_timer) => {}
+                    _ = triggered(&self.trigger) => {
+                        info!("Liquidation recompute triggered");
+                    }
+                }
+
+                match self.recompute(&mut connection).await {
+                    Ok(newly_liquidatable) if newly_liquidatable > 0 => info!(
+                        "Liquidation recompute: {} positions became liquidatable",
+                        newly_liquidatable
+                    ),
+                    Ok(_) => {}
+                    Err(e) => error!("Liquidation recompute failed: {}", e),
+                }
+
+                if let Err(e) = self.publish_at_risk(&mut connection).await {
+                    warn!("Failed to publish at-risk positions: {}", e);
+                }
+            }
+        })
+    }
+
+    // Run one pass over every live market, returning how many positions became liquidatable
+    pub async fn recompute(
+        &self,
+        connection: &mut ConnectionManager,
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        // Settled expiry markets have no positions left to liquidate
+        let market_ids: Vec<String> = connection
+            .sdiff(&[
+                redis_keys::derivative_markets(),
+                redis_keys::settled_markets(),
+            ])
+            .await?;
+        let mut newly_liquidatable = 0;
+
+        for market_id in market_ids {
+            match self.recompute_market(connection, &market_id).await {
+                Ok(count) => newly_liquidatable += count,
+                Err(e) => warn!(
+                    "Liquidation recompute failed for market {}: {}",
+                    market_id, e
+                ),
+            }
+        }
+
+        Ok(newly_liquidatable)
+    }
+
+    async fn recompute_market(
+        &self,
+        connection: &mut ConnectionManager,
+        market_id: &str,
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let (mark_price, maintenance_margin_ratio, cumulative_funding, market_time): (
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<u64>,
+        ) = redis::cmd("HMGET")
+            .arg(redis_keys::derivative_market(market_id))
+            .arg("mark_price")
+            .arg("maintenance_margin_ratio")
+            .arg("cumulative_funding")
+            .arg("timestamp")
+            .query_async(connection)
+            .await?;
+        // Alerts carry the block time of the market state they were checked
+        // against, like the Redis processor's, so replicas publish them once
+        let market_time = market_time.unwrap_or_else(|| now_millis() as u64);
+
+        // Without a cached mark price there is nothing to compare against
+        let mark_price = match mark_price.and_then(|value| value.parse::<f64>().ok()) {
+            Some(price) if price > 0.0 => price,
+            _ => return Ok(0),
+        };
+        let maintenance_margin_ratio = parse_or(maintenance_margin_ratio, 0.05);
+        let cumulative_funding = parse_or(cumulative_funding, 0.0);
+
+        let subaccount_ids: Vec<String> = connection
+            .smembers(redis_keys::positions_by_market(market_id))
+            .await?;
+        if subaccount_ids.is_empty() {
+            return Ok(0);
+        }
+
+        // Read every position of the market in one round trip
+        let mut pipe = redis::pipe();
+        for subaccount_id in &subaccount_ids {
+            pipe.cmd("HMGET")
+                .arg(redis_keys::position(market_id, subaccount_id))
+                .arg("is_long")
+                .arg("quantity")
+                .arg("entry_price")
+                .arg("margin")
+                .arg("cumulative_funding_entry")
+                .arg("liquidation_price")
+                .arg("is_liquidatable");
+        }
+        let positions: Vec<StoredPosition> = pipe.query_async(connection).await?;
+
+        let mut newly_liquidatable = 0;
+        for (subaccount_id, stored) in subaccount_ids.iter().zip(positions) {
+            let (
+                is_long,
+                quantity,
+                entry_price,
+                margin,
+                funding_entry,
+                stored_liquidation_price,
+                was_liquidatable,
+            ) = stored;
+            let member = redis_keys::liquidatable_member(market_id, subaccount_id);
+            // Closed or reaped positions leave the hash empty
+            let Some(is_long) = is_long.and_then(|value| value.parse::<bool>().ok()) else {
+                connection
+                    .zrem::<_, _, ()>(redis_keys::at_risk_positions(), &member)
+                    .await?;
+                continue;
+            };
+            let quantity = parse_or(quantity, 0.0);
+            let entry_price = parse_or(entry_price, 0.0);
+            let margin = parse_or(margin, 0.0);
+            if quantity <= 0.0 || entry_price <= 0.0 || margin <= 0.0 {
+                continue;
+            }
+
+            let funding_entry = parse_or(funding_entry, 0.0);
+            let liquidation_price = calculate_liquidation_price(
+                is_long,
+                entry_price,
+                margin,
+                quantity,
+                maintenance_margin_ratio,
+                cumulative_funding,
+                funding_entry,
+            );
+            let liquidatable = is_liquidatable(is_long, liquidation_price, mark_price);
+            let was_liquidatable = was_liquidatable.as_deref() == Some("true");
+
+            let key = redis_keys::position(market_id, subaccount_id);
+            // The distance, PnL and margin ratio move with the mark price even
+            // when nothing else does
+            let distance = distance_to_liquidation(is_long, liquidation_price, mark_price);
+            let unrealized_pnl =
+                calculate_unrealized_pnl(is_long, entry_price, mark_price, quantity);
+            let margin_ratio = calculate_margin_ratio(
+                is_long,
+                entry_price,
+                mark_price,
+                quantity,
+                margin,
+                cumulative_funding,
+                funding_entry,
+            );
+            connection
+                .hset_multiple::<_, _, _, ()>(
+                    &key,
+                    &[
+                        ("liquidation_distance", distance.to_string()),
+                        ("unrealized_pnl", unrealized_pnl.to_string()),
+                        ("margin_ratio", margin_ratio.to_string()),
+                    ],
+                )
+                .await?;
+            connection
+                .zadd::<_, _, _, ()>(redis_keys::at_risk_positions(), &member, distance)
+                .await?;
+            // Funding moves the liquidation price between position updates
+            let liquidation_price_text = liquidation_price.to_string();
+            if stored_liquidation_price.as_deref() != Some(liquidation_price_text.as_str()) {
+                connection
+                    .hset::<_, _, _, ()>(&key, "liquidation_price", liquidation_price_text)
+                    .await?;
+            }
+            if liquidatable == was_liquidatable {
+                continue;
+            }
+            connection
+                .hset::<_, _, _, ()>(&key, "is_liquidatable", liquidatable.to_string())
+                .await?;
+
+            if !liquidatable {
+                connection
+                    .srem::<_, _, ()>(redis_keys::liquidatable_positions(), &member)
+                    .await?;
+                continue;
+            }
+
+            connection
+                .sadd::<_, _, ()>(redis_keys::liquidatable_positions(), &member)
+                .await?;
+            newly_liquidatable += 1;
+
+            // Same alert shape as the Redis processor publishes
+            let alert_data = serde_json::json!({
+                "market_id": market_id,
+                "subaccount_id": subaccount_id,
+                "is_long": is_long,
+                "liquidation_price": liquidation_price,
+                "mark_price": mark_price,
+                "quantity": quantity.to_string(),
+                "entry_price": entry_price.to_string(),
+                "margin": margin.to_string(),
+            });
+            connection
+                .publish::<_, _, ()>(
+                    redis_keys::liquidation_alerts_channel(),
+                    alert_data.to_string(),
+                )
+                .await?;
+            if let Some(pubsub) = &self.pubsub {
+                let liquidation_event = pubsub.create_liquidation_alert(market_time, alert_data);
+                if let Err(e) = pubsub.publish_event(liquidation_event).await {
+                    warn!("Failed to publish liquidation alert: {}", e);
+                }
+            }
+        }
+
+        Ok(newly_liquidatable)
+    }
+
+    // Publish the positions closest to liquidation across all markets
+    async fn publish_at_risk(
+        &self,
+        connection: &mut ConnectionManager,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(pubsub) = self
+            .pubsub
+            .as_ref()
+            .filter(|_| self.config.at_risk_top_k > 0)
+        else {
+            return Ok(());
+        };
+
+        let ranked: Vec<(String, f64)> = connection
+            .zrange_withscores(
+                redis_keys::at_risk_positions(),
+                0,
+                self.config.at_risk_top_k as isize - 1,
+            )
+            .await?;
+        let positions: Vec<serde_json::Value> = ranked
+            .iter()
+            .filter_map(|(member, distance)| {
+                let (market_id, subaccount_id) = redis_keys::parse_liquidatable_member(member)?;
+                Some(serde_json::json!({
+                    "market_id": market_id,
+                    "subaccount_id": subaccount_id,
+                    "distance_pct": distance,
+                }))
+            })
+            .collect();
+
+        let event = StreamEvent::new(
+            EventType::AtRiskPositions,
+            now_millis() as u64,
+            serde_json::json!({ "positions": positions }),
+        );
+        pubsub.publish_event(event).await?;
+        Ok(())
+    }
+}
+
+// Never completes without an interval
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+// Never completes without a trigger
+async fn triggered(trigger: &Option<Arc<Notify>>) {
+    match trigger {
+        Some(trigger) => trigger.notified().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn parse_or(value: Option<String>, default: f64) -> f64 {
+    value
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
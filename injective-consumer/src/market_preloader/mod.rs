@@ -1,25 +1,27 @@
 use crate::compute::{calculate_liquidation_price, is_liquidatable};
 use crate::consumer::MessageProcessor;
-use crate::models::{KafkaMessage, KafkaPayload};
+use crate::error::IndexerError;
+use crate::models::{time, KafkaMessage, KafkaPayload, MessageType};
 use crate::pubsub::{EventType, RedisPubSubService, StreamEvent};
+use crate::redis_consumer::index_oracle_symbols;
+use crate::redis_keys;
+use crate::scaling;
 use async_trait::async_trait;
 use log::{debug, error, info, warn};
-use redis::{Client, Commands, Connection};
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Client};
 use std::collections::HashSet;
 use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-const PRICE_DECIMAL: f64 = 1e24;
-const CHAIN_DECIMAL: f64 = 1e18;
-
 // A dedicated processor that only handles market data
 pub struct MarketPreloader {
-    _client: Client,
-    connection: Arc<Mutex<Connection>>,
+    connection: ConnectionManager,
     pubsub: Option<Arc<RedisPubSubService>>,
-    // Track processed markets for metrics
-    markets_processed: Arc<Mutex<u64>>,
+    // Set once the markets_ready event has been published
+    ready: AtomicBool,
     // Set of market IDs to track which ones we've seen
     known_markets: Arc<Mutex<HashSet<String>>>,
 }
@@ -29,23 +31,24 @@ impl MarketPreloader {
         redis_url: &str,
         pubsub: Arc<RedisPubSubService>,
     ) -> Result<Self, Box<dyn Error + Send + Sync>> {
-        let client = Client::open(redis_url)?;
-        let connection = client.get_connection()?;
+        let connection = ConnectionManager::new(Client::open(redis_url)?).await?;
 
         let preloader = MarketPreloader {
-            _client: client,
-            connection: Arc::new(Mutex::new(connection)),
+            connection,
             pubsub: Some(pubsub),
-            markets_processed: Arc::new(Mutex::new(0)),
+            ready: AtomicBool::new(false),
             known_markets: Arc::new(Mutex::new(HashSet::new())),
         };
 
         // Set initial state in Redis to show we're in markets phase
-        {
-            let mut conn = preloader.connection.lock().await;
-            conn.set::<_, _, ()>("processing_phase", "markets")?;
-            conn.set::<_, _, ()>("markets_ready", "false")?;
-        }
+        preloader
+            .connection
+            .clone()
+            .mset::<_, _, ()>(&[
+                (redis_keys::processing_phase(), "markets"),
+                (redis_keys::markets_ready(), "false"),
+            ])
+            .await?;
 
         Ok(preloader)
     }
@@ -57,37 +60,46 @@ impl MarketPreloader {
         block_height: u64,
         timestamp: u64,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let mut conn = self.connection.lock().await;
+        let mut conn = self.connection.clone();
 
         // Extract cumulative funding
-        let cumulative_funding =
-            market.cumulative_funding.parse::<f64>().unwrap_or(0.0) / PRICE_DECIMAL;
+        let cumulative_funding = scaling::funding(&market.cumulative_funding);
 
         // Parse other market data safely
-        let mark_price = market.mark_price.parse::<f64>().unwrap_or(0.0) / PRICE_DECIMAL;
-        let maintenance_margin_ratio = market
-            .maintenance_margin_ratio
-            .parse::<f64>()
-            .unwrap_or(5e16)
-            / CHAIN_DECIMAL;
+        let mark_price = scaling::price(&market.mark_price);
+        let maintenance_margin_ratio =
+            scaling::maintenance_margin_ratio(&market.maintenance_margin_ratio);
 
         // Store market data in Redis
-        let key = format!("market:derivative:{}", market.market_id);
-
-        conn.hset::<_, _, _, ()>(&key, "ticker", &market.ticker)?;
-        conn.hset::<_, _, _, ()>(&key, "mark_price", mark_price.to_string())?;
-        conn.hset::<_, _, _, ()>(
-            &key,
-            "maintenance_margin_ratio",
-            maintenance_margin_ratio.to_string(),
-        )?;
-        conn.hset::<_, _, _, ()>(&key, "cumulative_funding", cumulative_funding.to_string())?;
-        conn.hset::<_, _, _, ()>(&key, "block_height", block_height.to_string())?;
-        conn.hset::<_, _, _, ()>(&key, "timestamp", timestamp.to_string())?;
-        conn.hset::<_, _, _, ()>(&key, "status", &market.status)?;
-
-        // Add to markets set
-        conn.sadd::<_, _, ()>("markets:derivative", &market.market_id)?;
+        let key = redis_keys::derivative_market(&market.market_id);
+
+        let fields = [
+            ("ticker", market.ticker.clone()),
+            ("mark_price", mark_price.to_string()),
+            (
+                "maintenance_margin_ratio",
+                maintenance_margin_ratio.to_string(),
+            ),
+            ("cumulative_funding", cumulative_funding.to_string()),
+            ("block_height", block_height.to_string()),
+            ("timestamp", timestamp.to_string()),
+            ("status", market.status.clone()),
+        ];
+        // Store the market and add it to the markets set in one round trip
+        redis::pipe()
+            .hset_multiple(&key, &fields)
+            .ignore()
+            .sadd(redis_keys::derivative_markets(), &market.market_id)
+            .ignore()
+            .query_async::<()>(&mut conn)
+            .await?;
+        index_oracle_symbols(
+            &mut conn,
+            &market.market_id,
+            &market.oracle_base,
+            &market.oracle_quote,
+        )
+        .await?;
 
         // Add to our known markets set
         {
@@ -109,7 +121,8 @@ impl MarketPreloader {
             });
 
             // Create market update event
-            let market_event = pubsub.create_market_update(market_data);
+            let event_time = time::to_millis(timestamp as i64) as u64;
+            let market_event = pubsub.create_market_update(event_time, market_data);
 
             // Publish through HPC Redis PubSub
             if let Err(e) = pubsub.publish_event(market_event).await {
@@ -118,7 +131,7 @@ impl MarketPreloader {
 
             // Also publish price update for clients only interested in prices
             let price_event =
-                pubsub.create_price_update(&market.market_id, &mark_price.to_string());
+                pubsub.create_price_update(event_time, &market.market_id, &mark_price.to_string());
 
             if let Err(e) = pubsub.publish_event(price_event).await {
                 warn!("Failed to publish price update through PubSub: {}", e);
@@ -127,45 +140,45 @@ impl MarketPreloader {
         Ok(())
     }
 
-    // Check if we're done with the initial market loading phase
-    async fn check_phase_transition(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        // This is a simplified check - in a real system you might want to:
-        // 1. Wait until you've seen markets for X consecutive blocks
-        // 2. Wait until you've seen a minimum number of markets
-        // 3. Wait until a certain amount of time has passed
+    // Markets are ready once a snapshot has been stored. The flags are written
+    // after every snapshot, so they come back with the markets if the cache
+    // is lost; the event is only published the first time.
+    async fn mark_ready(&self, stored: usize) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if stored == 0 {
+            return Ok(());
+        }
+
+        self.connection
+            .clone()
+            .mset::<_, _, ()>(&[
+                (redis_keys::processing_phase(), "others"),
+                (redis_keys::markets_ready(), "true"),
+            ])
+            .await?;
+        if self.ready.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
 
-        let processed_count = *self.markets_processed.lock().await;
         let known_markets_count = self.known_markets.lock().await.len();
+        info!(
+            "Market preloader has loaded initial markets (count: {})",
+            known_markets_count
+        );
 
-        if processed_count >= 10 && known_markets_count > 0 {
-            info!(
-                "Market preloader has processed initial markets (count: {})",
-                processed_count
+        // Publish a system event to notify other components
+        if let Some(pubsub) = &self.pubsub {
+            let event = StreamEvent::new(
+                EventType::SystemEvent,
+                time::now_millis() as u64,
+                serde_json::json!({
+                    "event": "markets_ready",
+                    "processed_count": stored,
+                    "market_count": known_markets_count,
+                }),
             );
 
-            // Signal that markets are ready
-            let mut conn = self.connection.lock().await;
-            conn.set::<_, _, ()>("processing_phase", "others")?;
-            conn.set::<_, _, ()>("markets_ready", "true")?;
-
-            // Publish a system event to notify other components
-            if let Some(pubsub) = &self.pubsub {
-                let event = StreamEvent {
-                    event_type: EventType::SystemEvent,
-                    timestamp: std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_secs(),
-                    payload: serde_json::json!({
-                        "event": "markets_ready",
-                        "processed_count": processed_count,
-                        "market_count": known_markets_count,
-                    }),
-                };
-
-                if let Err(e) = pubsub.publish_event(event).await {
-                    warn!("Failed to publish markets_ready event: {}", e);
-                }
+            if let Err(e) = pubsub.publish_event(event).await {
+                warn!("Failed to publish markets_ready event: {}", e);
             }
         }
 
@@ -175,10 +188,13 @@ impl MarketPreloader {
 
 #[async_trait]
 impl MessageProcessor for MarketPreloader {
-    async fn process_message(
-        &self,
-        message: KafkaMessage,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Only market snapshots are parsed; everything else on the topic is
+    // skipped on its header
+    fn handles(&self, message_type: &str) -> bool {
+        message_type == MessageType::DerivativeMarket.as_str()
+    }
+
+    async fn process_message(&self, message: KafkaMessage) -> Result<(), IndexerError> {
         let block_height = message.block_height;
         let timestamp = message.block_time;
 
@@ -190,21 +206,22 @@ impl MessageProcessor for MarketPreloader {
                     block_height
                 );
 
+                let mut stored = 0;
                 for market in markets {
-                    if let Err(e) = self
+                    match self
                         .process_derivative_market(market, block_height, timestamp)
                         .await
                     {
-                        error!(
+                        Ok(()) => stored += 1,
+                        Err(e) => error!(
                             "Market preloader: Error processing derivative market: {}",
                             e
-                        );
+                        ),
                     }
                 }
 
-                // Check if we should transition phases
-                if let Err(e) = self.check_phase_transition().await {
-                    warn!("Error checking phase transition: {}", e);
+                if let Err(e) = self.mark_ready(stored).await {
+                    warn!("Error marking markets ready: {}", e);
                 }
             }
             _ => {
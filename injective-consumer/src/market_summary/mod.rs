@@ -0,0 +1,112 @@
+use crate::models::time::HOUR_MILLIS;
+use crate::models::PositionPayload;
+use crate::scaling;
+use crate::window::Aggregate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Hours covered by the rolling summary; also the number of ring slots
+pub const WINDOW_HOURS: i64 = 24;
+
+// Trade totals for one hour of one market, stored in a ring slot
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HourBucket {
+    // Hour start in milliseconds; a slot holding an older hour is stale
+    pub start: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+impl HourBucket {
+    pub fn new(start: i64, price: f64) -> Self {
+        HourBucket {
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: 0.0,
+        }
+    }
+
+    pub fn add_trade(&mut self, price: f64, quantity: f64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += price * quantity;
+    }
+}
+
+// A fill is (price, quantity)
+impl Aggregate for HourBucket {
+    type Event = (f64, f64);
+
+    fn first(start: i64, &(price, quantity): &(f64, f64)) -> Self {
+        let mut bucket = HourBucket::new(start, price);
+        bucket.add_trade(price, quantity);
+        bucket
+    }
+
+    fn add(&mut self, &(price, quantity): &(f64, f64)) {
+        self.add_trade(price, quantity);
+    }
+}
+
+// Ring slot for the hour starting at `hour_start` (milliseconds)
+pub fn slot(hour_start: i64) -> i64 {
+    (hour_start / HOUR_MILLIS).rem_euclid(WINDOW_HOURS)
+}
+
+// Trade-derived part of the 24h summary
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RollingStats {
+    pub last_price: f64,
+    pub open_24h: f64,
+    pub high_24h: f64,
+    pub low_24h: f64,
+    pub volume_24h: f64,
+    // Percentage change from the window open to the last price
+    pub change_24h: f64,
+}
+
+// Fold the buckets inside the 24h window ending with the hour `current_hour`.
+// Returns None when no trades fall inside the window.
+pub fn rolling_stats(buckets: &[HourBucket], current_hour: i64) -> Option<RollingStats> {
+    let window_start = current_hour - (WINDOW_HOURS - 1) * HOUR_MILLIS;
+    let mut live: Vec<&HourBucket> = buckets
+        .iter()
+        .filter(|b| b.start >= window_start && b.start <= current_hour)
+        .collect();
+    live.sort_by_key(|b| b.start);
+
+    let first = live.first()?;
+    let last = live.last()?;
+    let change_24h = if first.open != 0.0 {
+        (last.close - first.open) / first.open * 100.0
+    } else {
+        0.0
+    };
+
+    Some(RollingStats {
+        last_price: last.close,
+        open_24h: first.open,
+        high_24h: live.iter().map(|b| b.high).fold(f64::MIN, f64::max),
+        low_24h: live.iter().map(|b| b.low).fold(f64::MAX, f64::min),
+        volume_24h: live.iter().map(|b| b.volume).sum(),
+        change_24h,
+    })
+}
+
+// Open interest per market from a full positions snapshot. Every contract has
+// a long and a short side, so only the longs are counted.
+pub fn open_interest(positions: &[PositionPayload]) -> HashMap<String, f64> {
+    let mut open_interest: HashMap<String, f64> = HashMap::new();
+    for position in positions.iter().filter(|p| p.is_long) {
+        *open_interest.entry(position.market_id.clone()).or_default() +=
+            scaling::quantity(&position.quantity);
+    }
+    open_interest
+}
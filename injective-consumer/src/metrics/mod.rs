@@ -0,0 +1,189 @@
+use crate::admin;
+use axum::http::header::CONTENT_TYPE;
+use axum::routing::get;
+use axum::Router;
+use log::{error, info};
+use prometheus::{
+    register_gauge_vec, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, register_int_gauge_vec, Encoder, GaugeVec, Histogram, HistogramVec,
+    IntCounter, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use std::error::Error;
+use std::sync::OnceLock;
+use tokio::net::TcpListener;
+use tokio::task;
+
+// Prometheus metrics of the consumers and their sinks, registered in the
+// default registry. Rates (messages per second) come from the counters.
+pub struct ConsumerMetrics {
+    // Kafka messages by consumer group and outcome: processed, failed,
+    // undecodable, skipped or duplicate
+    pub messages: IntCounterVec,
+    pub processing_seconds: HistogramVec,
+    // Messages between the committed position and the end of each partition
+    pub lag: IntGaugeVec,
+    // Commands per request sent by the Redis processor; 1 for plain commands
+    pub redis_pipeline_commands: Histogram,
+    pub scylla_statement_seconds: HistogramVec,
+    pub scylla_errors: IntCounterVec,
+    pub pubsub_published: IntCounterVec,
+    pub pubsub_errors: IntCounter,
+    pub pubsub_publish_seconds: Histogram,
+    // Events not published because another replica claimed them first
+    pub pubsub_duplicates: IntCounter,
+    // Kafka messages skipped because a copy was already applied
+    pub ingest_duplicates: IntCounterVec,
+    // Events dropped by a windowed aggregator because their windows had closed
+    pub late_events: IntCounterVec,
+    // Catching up on a backlog: share done (0 to 1), seconds left, the latest
+    // block processed and the estimated head block, by consumer group
+    pub catchup_progress: GaugeVec,
+    pub catchup_eta_seconds: IntGaugeVec,
+    pub block_height: IntGaugeVec,
+    pub head_block_height: IntGaugeVec,
+}
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+pub fn consumer() -> &'static ConsumerMetrics {
+    static METRICS: OnceLock<ConsumerMetrics> = OnceLock::new();
+    METRICS.get_or_init(|| ConsumerMetrics {
+        messages: register_int_counter_vec!(
+            "injective_consumer_messages_total",
+            "Kafka messages handled, by consumer group and outcome",
+            &["group", "outcome"]
+        )
+        .expect("consumer metric registered twice"),
+        processing_seconds: register_histogram_vec!(
+            "injective_consumer_processing_seconds",
+            "Time spent processing one Kafka message",
+            &["group", "message_type"],
+            LATENCY_BUCKETS.to_vec()
+        )
+        .expect("consumer metric registered twice"),
+        lag: register_int_gauge_vec!(
+            "injective_consumer_lag",
+            "Messages the consumer group is behind the end of the partition",
+            &["group", "topic", "partition"]
+        )
+        .expect("consumer metric registered twice"),
+        redis_pipeline_commands: register_histogram!(
+            "injective_redis_pipeline_commands",
+            "Commands per request sent to Redis by the Redis processor",
+            vec![1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0]
+        )
+        .expect("consumer metric registered twice"),
+        scylla_statement_seconds: register_histogram_vec!(
+            "injective_scylla_statement_seconds",
+            "ScyllaDB statement latency by table",
+            &["table"],
+            LATENCY_BUCKETS.to_vec()
+        )
+        .expect("consumer metric registered twice"),
+        scylla_errors: register_int_counter_vec!(
+            "injective_scylla_errors_total",
+            "Failed ScyllaDB statements by table",
+            &["table"]
+        )
+        .expect("consumer metric registered twice"),
+        pubsub_published: register_int_counter_vec!(
+            "injective_pubsub_published_total",
+            "Events published to Redis, by transport",
+            &["transport"]
+        )
+        .expect("consumer metric registered twice"),
+        pubsub_errors: register_int_counter!(
+            "injective_pubsub_errors_total",
+            "Events that could not be published to Redis"
+        )
+        .expect("consumer metric registered twice"),
+        pubsub_publish_seconds: register_histogram!(
+            "injective_pubsub_publish_seconds",
+            "Time to publish one event to Redis",
+            LATENCY_BUCKETS.to_vec()
+        )
+        .expect("consumer metric registered twice"),
+        pubsub_duplicates: register_int_counter!(
+            "injective_pubsub_duplicates_total",
+            "Events skipped because another replica already published them"
+        )
+        .expect("consumer metric registered twice"),
+        ingest_duplicates: register_int_counter_vec!(
+            "injective_consumer_duplicate_messages_total",
+            "Messages skipped because a copy was already applied, by consumer group",
+            &["group"]
+        )
+        .expect("consumer metric registered twice"),
+        late_events: register_int_counter_vec!(
+            "injective_window_late_events_total",
+            "Events that arrived after their windows closed, by aggregator",
+            &["aggregator"]
+        )
+        .expect("consumer metric registered twice"),
+        catchup_progress: register_gauge_vec!(
+            "injective_consumer_catchup_progress_ratio",
+            "Share of the current backlog processed, by consumer group",
+            &["group"]
+        )
+        .expect("consumer metric registered twice"),
+        catchup_eta_seconds: register_int_gauge_vec!(
+            "injective_consumer_catchup_eta_seconds",
+            "Estimated seconds until the consumer group has caught up, 0 when it has",
+            &["group"]
+        )
+        .expect("consumer metric registered twice"),
+        block_height: register_int_gauge_vec!(
+            "injective_consumer_block_height",
+            "Latest block processed by the consumer group",
+            &["group"]
+        )
+        .expect("consumer metric registered twice"),
+        head_block_height: register_int_gauge_vec!(
+            "injective_consumer_head_block_height",
+            "Chain head estimated from the block time lag and block rate, by consumer group",
+            &["group"]
+        )
+        .expect("consumer metric registered twice"),
+    })
+}
+
+// Content type of the Prometheus text format
+pub const PROMETHEUS_TEXT: &str = "text/plain; version=0.0.4";
+
+// Every registered metric in the Prometheus text format
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        error!("Failed to encode metrics: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+// Answer GET /metrics, and GET /config with the effective configuration, on
+// `addr` until the process exits
+pub async fn serve(addr: &str) -> Result<task::JoinHandle<()>, Box<dyn Error + Send + Sync>> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving metrics on {}/metrics", addr);
+
+    let router = Router::new()
+        .route(
+            "/metrics",
+            get(|| async { ([(CONTENT_TYPE, PROMETHEUS_TEXT)], render()) }),
+        )
+        .route(
+            "/config",
+            get(|| async {
+                (
+                    [(CONTENT_TYPE, "application/json")],
+                    admin::runtime().render(),
+                )
+            }),
+        );
+    Ok(task::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            error!("Metrics server stopped: {}", e);
+        }
+    }))
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+// Mid prices of the top of book and how far the mark price sits from them. A
+// mark that drifts away from the book, or a book that stops following the
+// mark, is a common sign of a stale oracle or a stale orderbook. The Redis
+// processor keeps a recent series per market and raises alerts, and the
+// ScyllaDB processor keeps the full series.
+
+// One point of a market's mid-price series, in the units the book is kept in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MidPricePoint {
+    pub mid_price: f64,
+    pub best_bid: f64,
+    pub best_ask: f64,
+    // Derivative markets only
+    pub mark_price: Option<f64>,
+    // Mark against mid, in basis points of the mid
+    pub divergence_bps: Option<f64>,
+    pub block_height: u64,
+    pub timestamp: u64,
+}
+
+impl MidPricePoint {
+    // None unless both sides of the book have a price
+    pub fn new(
+        best_bid: Option<f64>,
+        best_ask: Option<f64>,
+        mark_price: Option<f64>,
+        block_height: u64,
+        timestamp: u64,
+    ) -> Option<Self> {
+        let (best_bid, best_ask) = best_bid.zip(best_ask)?;
+        if best_bid <= 0.0 || best_ask <= 0.0 {
+            return None;
+        }
+        let mid_price = (best_bid + best_ask) / 2.0;
+        let mark_price = mark_price.filter(|mark| *mark > 0.0);
+        Some(MidPricePoint {
+            mid_price,
+            best_bid,
+            best_ask,
+            mark_price,
+            divergence_bps: mark_price.map(|mark| divergence_bps(mark, mid_price)),
+            block_height,
+            timestamp,
+        })
+    }
+}
+
+// Mark price against the mid, in basis points of the mid; positive when the
+// mark is above the book
+pub fn divergence_bps(mark_price: f64, mid_price: f64) -> f64 {
+    (mark_price - mid_price) / mid_price * 10_000.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DivergenceAlert {
+    // The divergence reached the threshold
+    Raised,
+    // The divergence is back under the threshold
+    Cleared,
+}
+
+impl DivergenceAlert {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DivergenceAlert::Raised => "raised",
+            DivergenceAlert::Cleared => "cleared",
+        }
+    }
+}
+
+// Remembers which markets are past the alert threshold, so each crossing is
+// reported once instead of with every book
+#[derive(Debug, Default)]
+pub struct DivergenceMonitor {
+    threshold_bps: f64,
+    diverged: HashSet<String>,
+}
+
+impl DivergenceMonitor {
+    // A threshold of 0 never alerts
+    pub fn new(threshold_bps: f64) -> Self {
+        DivergenceMonitor {
+            threshold_bps,
+            diverged: HashSet::new(),
+        }
+    }
+
+    pub fn check(&mut self, market_id: &str, divergence_bps: f64) -> Option<DivergenceAlert> {
+        if self.threshold_bps <= 0.0 {
+            return None;
+        }
+        let diverged = divergence_bps.abs() >= self.threshold_bps;
+        if diverged == self.diverged.contains(market_id) {
+            return None;
+        }
+        if diverged {
+            self.diverged.insert(market_id.to_string());
+            Some(DivergenceAlert::Raised)
+        } else {
+            self.diverged.remove(market_id);
+            Some(DivergenceAlert::Cleared)
+        }
+    }
+
+    pub fn threshold_bps(&self) -> f64 {
+        self.threshold_bps
+    }
+}
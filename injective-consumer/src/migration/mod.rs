@@ -0,0 +1,128 @@
+use crate::redis_keys;
+use log::{info, warn};
+use redis::{aio::ConnectionManager, Client};
+use serde_json::Value;
+use std::error::Error;
+
+// Read the stored layout version. Keyspaces written before versioning was
+// introduced have no version key and are reported as version 1.
+pub async fn schema_version(
+    connection: &mut ConnectionManager,
+) -> Result<u32, Box<dyn Error + Send + Sync>> {
+    let version: Option<u32> = redis::cmd("GET")
+        .arg(redis_keys::schema_version_key())
+        .query_async(connection)
+        .await?;
+    Ok(version.unwrap_or(1))
+}
+
+// Convert a version 1 JSON market document into the version 2 hash fields.
+// Values may be stored as JSON strings or numbers.
+pub fn legacy_market_fields(document: &Value) -> Vec<(&'static str, String)> {
+    const FIELDS: &[&str] = &[
+        "ticker",
+        "mark_price",
+        "maintenance_margin_ratio",
+        "cumulative_funding",
+        "status",
+        "block_height",
+        "timestamp",
+    ];
+
+    FIELDS
+        .iter()
+        .filter_map(|field| {
+            let value = match document.get(*field)? {
+                Value::String(s) => s.clone(),
+                Value::Null => return None,
+                other => other.to_string(),
+            };
+            Some((*field, value))
+        })
+        .collect()
+}
+
+// One-shot migration of version 1 market keys into the version 2 layout.
+// Existing version 2 hashes win over legacy documents, so the migration is
+// safe to re-run while the consumer is writing.
+pub async fn migrate_keys(redis_url: &str) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let client = Client::open(redis_url)?;
+    let mut connection = ConnectionManager::new(client).await?;
+
+    let version = schema_version(&mut connection).await?;
+    if version >= redis_keys::SCHEMA_VERSION {
+        info!("Redis key layout already at version {}", version);
+        return Ok(0);
+    }
+
+    let mut migrated = 0;
+    let mut cursor: u64 = 0;
+    loop {
+        let (next_cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(redis_keys::LEGACY_MARKET_PATTERN)
+            .arg("COUNT")
+            .arg(500)
+            .query_async(&mut connection)
+            .await?;
+
+        for key in keys {
+            let Some(market_id) = redis_keys::parse_legacy_market(&key) else {
+                continue;
+            };
+
+            let document: Option<String> = redis::cmd("GET")
+                .arg(&key)
+                .query_async(&mut connection)
+                .await?;
+            let Some(document) = document else {
+                continue;
+            };
+            let fields = match serde_json::from_str::<Value>(&document) {
+                Ok(value) => legacy_market_fields(&value),
+                Err(e) => {
+                    warn!("Skipping unreadable legacy market key {}: {}", key, e);
+                    continue;
+                }
+            };
+
+            let target = redis_keys::derivative_market(market_id);
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            for (field, value) in &fields {
+                pipe.cmd("HSETNX")
+                    .arg(&target)
+                    .arg(*field)
+                    .arg(value)
+                    .ignore();
+            }
+            pipe.cmd("SADD")
+                .arg(redis_keys::derivative_markets())
+                .arg(market_id)
+                .ignore();
+            pipe.cmd("DEL").arg(&key).ignore();
+            let _: () = pipe.query_async(&mut connection).await?;
+
+            migrated += 1;
+        }
+
+        cursor = next_cursor;
+        if cursor == 0 {
+            break;
+        }
+    }
+
+    let _: () = redis::cmd("SET")
+        .arg(redis_keys::schema_version_key())
+        .arg(redis_keys::SCHEMA_VERSION)
+        .query_async(&mut connection)
+        .await?;
+
+    info!(
+        "Migrated {} legacy market keys to Redis key layout version {}",
+        migrated,
+        redis_keys::SCHEMA_VERSION
+    );
+    Ok(migrated)
+}
@@ -0,0 +1,76 @@
+use super::{fnv1a, KafkaMessage, KafkaPayload};
+use serde::Serialize;
+
+// Entities a message writes, for the entity idempotency guard of the Scylla
+// processor. A replayed block may be batched differently from the first
+// delivery, so its messages hash differently while carrying the same
+// positions, trades and markets; keying on the entity catches those too.
+// Only the payloads the guard covers have entities.
+
+impl KafkaMessage {
+    /// Ids of the entities in the payload: market:subaccount for positions,
+    /// the trade id for trades and the market id for markets
+    pub fn entity_ids(&self) -> Vec<String> {
+        match &self.payload {
+            KafkaPayload::StreamPositions(positions)
+            | KafkaPayload::ExchangePositions(positions) => positions
+                .iter()
+                .map(|p| format!("{}:{}", p.market_id, p.subaccount_id))
+                .collect(),
+            KafkaPayload::DerivativeTrades(trades) => trades
+                .iter()
+                .map(|t| trade_entity(&t.trade_id, t))
+                .collect(),
+            KafkaPayload::SpotTrades(trades) => trades
+                .iter()
+                .map(|t| trade_entity(&t.trade_id, t))
+                .collect(),
+            KafkaPayload::DerivativeMarkets(markets) => {
+                markets.iter().map(|m| m.market_id.clone()).collect()
+            }
+            KafkaPayload::SpotMarkets(markets) => {
+                markets.iter().map(|m| m.market_id.clone()).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Keep only the entities `keep` accepts. Returns whether any are left.
+    pub fn retain_entities(&mut self, keep: impl Fn(&str) -> bool) -> bool {
+        match &mut self.payload {
+            KafkaPayload::StreamPositions(positions)
+            | KafkaPayload::ExchangePositions(positions) => {
+                positions.retain(|p| keep(&format!("{}:{}", p.market_id, p.subaccount_id)));
+                !positions.is_empty()
+            }
+            KafkaPayload::DerivativeTrades(trades) => {
+                trades.retain(|t| keep(&trade_entity(&t.trade_id, t)));
+                !trades.is_empty()
+            }
+            KafkaPayload::SpotTrades(trades) => {
+                trades.retain(|t| keep(&trade_entity(&t.trade_id, t)));
+                !trades.is_empty()
+            }
+            KafkaPayload::DerivativeMarkets(markets) => {
+                markets.retain(|m| keep(&m.market_id));
+                !markets.is_empty()
+            }
+            KafkaPayload::SpotMarkets(markets) => {
+                markets.retain(|m| keep(&m.market_id));
+                !markets.is_empty()
+            }
+            _ => true,
+        }
+    }
+}
+
+// Trades from older producers may lack an id; the trade's content stands in
+fn trade_entity<T: Serialize>(trade_id: &str, trade: &T) -> String {
+    if !trade_id.is_empty() {
+        return trade_id.to_string();
+    }
+    format!(
+        "{:016x}",
+        fnv1a(&serde_json::to_vec(trade).unwrap_or_default())
+    )
+}
@@ -1,20 +1,25 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+mod entity;
+pub mod time;
+mod validate;
+
 // Market data structure
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct MarketData {
     pub market_id: String,
     pub ticker: String,
     pub mark_price: f64,
     pub maintenance_margin_ratio: f64,
     pub cumulative_funding: f64,
+    pub status: String,
     pub block_height: i64,
     pub timestamp: DateTime<Utc>,
 }
 
 // Position data structure
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct PositionData {
     pub market_id: String,
     pub subaccount_id: String,
@@ -24,11 +29,111 @@ pub struct PositionData {
     pub margin: f64,
     pub cumulative_funding_entry: f64,
     pub liquidation_price: f64,
+    pub is_liquidatable: bool,
+    pub block_height: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+// Top of book data structure
+#[derive(Clone, Debug, Serialize)]
+pub struct TopOfBook {
+    pub market_id: String,
+    pub best_bid: Option<f64>,
+    pub best_ask: Option<f64>,
+    pub best_bid_quantity: f64,
+    pub best_ask_quantity: f64,
+    pub block_height: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+// Rolling 24h market summary
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MarketSummary {
+    pub market_id: String,
+    pub last_price: f64,
+    pub open_24h: f64,
+    pub high_24h: f64,
+    pub low_24h: f64,
+    pub volume_24h: f64,
+    pub change_24h: f64,
+    pub open_interest: f64,
+    pub mark_price: f64,
+    pub cumulative_funding: f64,
+    // Annualized funding rate in percent over the last funding payment; positive when longs pay
+    pub funding_apr: f64,
+    // Daily funding earned by a short, in basis points of notional
+    pub carry_bps_per_day: f64,
+    // Last price against the oracle-derived mark, in basis points
+    pub basis_bps: f64,
+    pub block_height: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+// A position ranked by how close it is to liquidation
+#[derive(Clone, Debug)]
+pub struct AtRiskPosition {
+    pub position: PositionData,
+    // Percentage the mark price has to move to liquidate; negative once past it
+    pub distance_pct: f64,
+}
+
+// A liquidatable position priced against its market's mark
+#[derive(Clone, Debug, Serialize)]
+pub struct LiquidatablePosition {
+    #[serde(flatten)]
+    pub position: PositionData,
+    pub mark_price: f64,
+    // Quantity at the mark price
+    pub notional: f64,
+    // As in AtRiskPosition; zero or negative once a position is liquidatable
+    pub distance_pct: f64,
+}
+
+// Position aggregates across all subaccounts of one owner address
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AddressSummary {
+    pub address: String,
+    pub subaccount_ids: Vec<String>,
+    pub position_count: u64,
+    pub total_margin: f64,
+    pub unrealized_pnl: f64,
+    // Margin plus unrealized PnL of open positions; free balances are not included
+    pub total_equity: f64,
+    pub block_height: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+// One side of a spot or derivative trade as kept per subaccount for trade
+// history. Derivative values are scaled to human units; spot values stay in
+// chain units like the other spot data.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SubaccountTrade {
+    pub subaccount_id: String,
+    pub market_id: String,
+    pub market_type: MarketType,
+    pub trade_id: String,
+    pub is_buy: bool,
+    pub is_maker: bool,
+    pub price: f64,
+    pub quantity: f64,
+    pub fee: f64,
     pub block_height: i64,
     pub timestamp: DateTime<Utc>,
 }
 
-/// Wrapper types for Kafka messages
+/// 64-bit FNV-1a hash, for hashes that are persisted or compared across processes
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    bytes.iter().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Wrapper types for Kafka messages. Messages are built from stream responses by
+/// the producer (`Vec<KafkaMessage>::from(StreamResponse)` in `grpc::models`); this
+/// crate only decodes what it publishes, in the format `wire` describes.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KafkaMessage {
     pub message_type: MessageType,
@@ -37,6 +142,123 @@ pub struct KafkaMessage {
     pub payload: KafkaPayload,
 }
 
+impl KafkaMessage {
+    /// Stable 64-bit FNV-1a hash of the message type and payload, used to recognise
+    /// replays of the same logical message. Unlike `DefaultHasher` the value does not
+    /// change between Rust releases, so it can be persisted.
+    pub fn content_hash(&self) -> u64 {
+        fnv1a(&serde_json::to_vec(&(&self.message_type, &self.payload)).unwrap_or_default())
+    }
+
+    /// Positions carried by the message, labelled with where they came from. The
+    /// payload enum is untagged and both position variants have the same shape, so
+    /// the message type decides the source rather than the payload variant.
+    pub fn positions(&self) -> Option<(&[PositionPayload], PositionSource)> {
+        let positions = match &self.payload {
+            KafkaPayload::StreamPositions(positions)
+            | KafkaPayload::ExchangePositions(positions) => positions,
+            _ => return None,
+        };
+        let source = match self.message_type {
+            MessageType::StreamPosition => PositionSource::Stream,
+            MessageType::ExchangePosition => PositionSource::Heartbeat,
+            _ => return None,
+        };
+        Some((positions, source))
+    }
+
+    /// Full orderbook snapshots carried by the message, with the kind of market
+    /// they belong to. Like positions, spot and derivative books share a shape,
+    /// so the message type decides rather than the payload variant.
+    pub fn full_orderbooks(&self) -> Option<(&[FullLimitOrderbookPayload], MarketType)> {
+        let orderbooks = match &self.payload {
+            KafkaPayload::DerivativeFullOrderbooks(orderbooks)
+            | KafkaPayload::SpotFullOrderbooks(orderbooks) => orderbooks,
+            _ => return None,
+        };
+        let market_type = match self.message_type {
+            MessageType::DerivativeFullOrderbook => MarketType::Derivative,
+            MessageType::SpotFullOrderbook => MarketType::Spot,
+            _ => return None,
+        };
+        Some((orderbooks, market_type))
+    }
+}
+
+/// Kind of market a payload belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketType {
+    Spot,
+    Derivative,
+}
+
+impl MarketType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MarketType::Spot => "spot",
+            MarketType::Derivative => "derivative",
+        }
+    }
+}
+
+impl std::str::FromStr for MarketType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spot" => Ok(MarketType::Spot),
+            "derivative" => Ok(MarketType::Derivative),
+            other => Err(format!("Unknown market type: {}", other)),
+        }
+    }
+}
+
+/// Where a position update came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PositionSource {
+    /// Per-block changes from the chain stream
+    Stream,
+    /// Full snapshots queried by the producer heartbeat
+    Heartbeat,
+}
+
+impl PositionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PositionSource::Stream => "stream",
+            PositionSource::Heartbeat => "heartbeat",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "stream" => Some(PositionSource::Stream),
+            "heartbeat" => Some(PositionSource::Heartbeat),
+            _ => None,
+        }
+    }
+
+    /// Whether an update from this source at `block_height` may replace a position
+    /// last written at `stored_height` by `stored_source`. Older blocks never win,
+    /// and within a block a streamed update wins over a heartbeat snapshot.
+    pub fn supersedes(
+        self,
+        block_height: u64,
+        stored_height: u64,
+        stored_source: Option<PositionSource>,
+    ) -> bool {
+        match block_height.cmp(&stored_height) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Equal => {
+                self == PositionSource::Stream || stored_source != Some(PositionSource::Stream)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum MessageType {
     StreamBankBalance,
@@ -53,6 +275,71 @@ pub enum MessageType {
     ExchangeBalance,
     ExchangePosition,
     DerivativeFullOrderbook,
+    SpotMarket,
+    SpotFullOrderbook,
+}
+
+// Kafka header carrying the message type, so consumers can skip messages they
+// don't handle without parsing the payload
+pub const MESSAGE_TYPE_HEADER: &str = "message_type";
+
+// Kafka header naming the encoding of the message value (json, flatbuffers or
+// protobuf); messages without it are JSON unless the consumer is told otherwise
+pub const FORMAT_HEADER: &str = "format";
+
+impl MessageType {
+    // Same spelling as the serialized `message_type` field
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageType::StreamBankBalance => "StreamBankBalance",
+            MessageType::StreamSubaccountDeposit => "StreamSubaccountDeposit",
+            MessageType::StreamPosition => "StreamPosition",
+            MessageType::StreamSpotOrderbook => "StreamSpotOrderbook",
+            MessageType::StreamDerivativeOrderbook => "StreamDerivativeOrderbook",
+            MessageType::StreamOraclePrice => "StreamOraclePrice",
+            MessageType::SpotTrade => "SpotTrade",
+            MessageType::DerivativeTrade => "DerivativeTrade",
+            MessageType::SpotOrder => "SpotOrder",
+            MessageType::DerivativeOrder => "DerivativeOrder",
+            MessageType::DerivativeMarket => "DerivativeMarket",
+            MessageType::ExchangeBalance => "ExchangeBalance",
+            MessageType::ExchangePosition => "ExchangePosition",
+            MessageType::DerivativeFullOrderbook => "DerivativeFullOrderbook",
+            MessageType::SpotMarket => "SpotMarket",
+            MessageType::SpotFullOrderbook => "SpotFullOrderbook",
+        }
+    }
+
+    // Inverse of `as_str`, for encodings that carry the type as a string
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "StreamBankBalance" => Some(MessageType::StreamBankBalance),
+            "StreamSubaccountDeposit" => Some(MessageType::StreamSubaccountDeposit),
+            "StreamPosition" => Some(MessageType::StreamPosition),
+            "StreamSpotOrderbook" => Some(MessageType::StreamSpotOrderbook),
+            "StreamDerivativeOrderbook" => Some(MessageType::StreamDerivativeOrderbook),
+            "StreamOraclePrice" => Some(MessageType::StreamOraclePrice),
+            "SpotTrade" => Some(MessageType::SpotTrade),
+            "DerivativeTrade" => Some(MessageType::DerivativeTrade),
+            "SpotOrder" => Some(MessageType::SpotOrder),
+            "DerivativeOrder" => Some(MessageType::DerivativeOrder),
+            "DerivativeMarket" => Some(MessageType::DerivativeMarket),
+            "ExchangeBalance" => Some(MessageType::ExchangeBalance),
+            "ExchangePosition" => Some(MessageType::ExchangePosition),
+            "DerivativeFullOrderbook" => Some(MessageType::DerivativeFullOrderbook),
+            "SpotMarket" => Some(MessageType::SpotMarket),
+            "SpotFullOrderbook" => Some(MessageType::SpotFullOrderbook),
+            _ => None,
+        }
+    }
+
+    // Stream deltas and full books, the messages of the orderbook topic
+    pub const ORDERBOOKS: [MessageType; 4] = [
+        MessageType::StreamSpotOrderbook,
+        MessageType::StreamDerivativeOrderbook,
+        MessageType::DerivativeFullOrderbook,
+        MessageType::SpotFullOrderbook,
+    ];
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -72,174 +359,319 @@ pub enum KafkaPayload {
     ExchangePositions(Vec<PositionPayload>),
     ExchangeBalances(Vec<ExchangeBalancePayload>),
     DerivativeFullOrderbooks(Vec<FullLimitOrderbookPayload>),
+    SpotMarkets(Vec<SpotMarketPayload>),
+    // JSON decodes these as DerivativeFullOrderbooks; see full_orderbooks
+    SpotFullOrderbooks(Vec<FullLimitOrderbookPayload>),
 }
 
 // Custom serializable structs for each message type
-// These mirror the protobuf structs but are optimized for JSON serialization
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// These mirror the protobuf structs but are optimized for JSON serialization.
+// The prost tags define their protobuf wire format (see wire), so a field
+// keeps its tag for good once a producer has published it.
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct BankBalancePayload {
+    #[prost(string, tag = "1")]
     pub account: String,
+    #[prost(message, repeated, tag = "2")]
     pub balances: Vec<CoinPayload>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct CoinPayload {
+    #[prost(string, tag = "1")]
     pub denom: String,
+    #[prost(string, tag = "2")]
     pub amount: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct SubaccountDepositPayload {
+    #[prost(string, tag = "1")]
     pub subaccount_id: String,
+    #[prost(string, tag = "2")]
     pub denom: String,
+    #[prost(string, tag = "3")]
     pub available_balance: String,
+    #[prost(string, tag = "4")]
     pub total_balance: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct SpotTradePayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(bool, tag = "2")]
     pub is_buy: bool,
+    #[prost(string, tag = "3")]
     pub execution_type: String,
+    #[prost(string, tag = "4")]
     pub quantity: String,
+    #[prost(string, tag = "5")]
     pub price: String,
+    #[prost(string, tag = "6")]
     pub subaccount_id: String,
+    #[prost(string, tag = "7")]
     pub fee: String,
+    #[prost(string, tag = "8")]
     pub order_hash: String,
+    #[prost(string, tag = "9")]
     pub fee_recipient_address: String,
+    #[prost(string, tag = "10")]
     pub cid: String,
+    #[prost(string, tag = "11")]
     pub trade_id: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct DerivativeTradePayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(bool, tag = "2")]
     pub is_buy: bool,
+    #[prost(string, tag = "3")]
     pub execution_type: String,
+    #[prost(string, tag = "4")]
     pub subaccount_id: String,
+    #[prost(message, required, tag = "5")]
     pub position_delta: PositionDeltaPayload,
+    #[prost(string, tag = "6")]
     pub payout: String,
+    #[prost(string, tag = "7")]
     pub fee: String,
+    #[prost(string, tag = "8")]
     pub order_hash: String,
+    #[prost(string, tag = "9")]
     pub fee_recipient_address: String,
+    #[prost(string, tag = "10")]
     pub cid: String,
+    #[prost(string, tag = "11")]
     pub trade_id: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct PositionDeltaPayload {
+    #[prost(bool, tag = "1")]
     pub is_long: bool,
+    #[prost(string, tag = "2")]
     pub execution_quantity: String,
+    #[prost(string, tag = "3")]
     pub execution_margin: String,
+    #[prost(string, tag = "4")]
     pub execution_price: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct SpotOrderPayload {
+    #[prost(string, tag = "1")]
     pub status: String,
+    #[prost(string, tag = "2")]
     pub order_hash: String,
+    #[prost(string, tag = "3")]
     pub cid: String,
+    #[prost(string, tag = "4")]
     pub market_id: String,
+    #[prost(string, tag = "5")]
     pub subaccount_id: String,
+    #[prost(string, tag = "6")]
     pub price: String,
+    #[prost(string, tag = "7")]
     pub quantity: String,
+    #[prost(string, tag = "8")]
     pub fillable: String,
+    #[prost(bool, tag = "9")]
     pub is_buy: bool,
+    #[prost(string, tag = "10")]
     pub order_type: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct DerivativeOrderPayload {
+    #[prost(string, tag = "1")]
     pub status: String,
+    #[prost(string, tag = "2")]
     pub order_hash: String,
+    #[prost(string, tag = "3")]
     pub cid: String,
+    #[prost(string, tag = "4")]
     pub market_id: String,
+    #[prost(string, tag = "5")]
     pub subaccount_id: String,
+    #[prost(string, tag = "6")]
     pub price: String,
+    #[prost(string, tag = "7")]
     pub quantity: String,
+    #[prost(string, tag = "8")]
     pub margin: String,
+    #[prost(string, tag = "9")]
     pub fillable: String,
+    #[prost(bool, tag = "10")]
     pub is_buy: bool,
+    #[prost(string, tag = "11")]
     pub order_type: String,
+    #[prost(bool, tag = "12")]
     pub is_market: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct OrderbookPayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(message, repeated, tag = "2")]
     pub buy_levels: Vec<PriceLevelPayload>,
+    #[prost(message, repeated, tag = "3")]
     pub sell_levels: Vec<PriceLevelPayload>,
+    #[prost(uint64, tag = "4")]
     pub sequence: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct PriceLevelPayload {
+    #[prost(string, tag = "1")]
     pub price: String,
+    #[prost(string, tag = "2")]
     pub quantity: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct PositionPayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(string, tag = "2")]
     pub subaccount_id: String,
+    #[prost(bool, tag = "3")]
     pub is_long: bool,
+    #[prost(string, tag = "4")]
     pub quantity: String,
+    #[prost(string, tag = "5")]
     pub entry_price: String,
+    #[prost(string, tag = "6")]
     pub margin: String,
+    #[prost(string, tag = "7")]
     pub cumulative_funding_entry: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct OraclePricePayload {
+    #[prost(string, tag = "1")]
     pub symbol: String,
+    #[prost(string, tag = "2")]
     pub price: String,
+    #[prost(string, tag = "3")]
     pub oracle_type: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct DerivativeMarketPayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(string, tag = "2")]
     pub ticker: String,
+    #[prost(string, tag = "3")]
     pub oracle_base: String,
+    #[prost(string, tag = "4")]
     pub oracle_quote: String,
+    #[prost(string, tag = "5")]
     pub quote_denom: String,
+    #[prost(string, tag = "6")]
     pub maker_fee_rate: String,
+    #[prost(string, tag = "7")]
     pub taker_fee_rate: String,
+    #[prost(string, tag = "8")]
     pub initial_margin_ratio: String,
+    #[prost(string, tag = "9")]
     pub maintenance_margin_ratio: String,
+    #[prost(bool, tag = "10")]
     pub is_perpetual: bool,
+    #[prost(string, tag = "11")]
     pub status: String,
+    #[prost(string, tag = "12")]
     pub mark_price: String,
+    #[prost(string, tag = "13")]
     pub min_price_tick: String,
+    #[prost(string, tag = "14")]
     pub min_quantity_tick: String,
+    #[prost(string, tag = "15")]
     pub min_notional: String,
+    #[prost(string, tag = "16")]
     pub hfr: String,
+    #[prost(string, tag = "17")]
     pub hir: String,
+    #[prost(string, tag = "18")]
     pub funding_interval: String,
+    #[prost(string, tag = "19")]
     pub cumulative_funding: String,
+    #[prost(string, tag = "20")]
     pub cumulative_price: String,
+    // Expiry futures only: expiry in seconds, and the settlement price once
+    // the chain has settled the market
+    #[prost(string, tag = "21")]
+    pub expiration_timestamp: String,
+    #[prost(string, tag = "22")]
+    pub settlement_price: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Spot prices and quantities depend on the base and quote decimals, which
+// are carried along so readers can scale them
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
+pub struct SpotMarketPayload {
+    #[prost(string, tag = "1")]
+    pub market_id: String,
+    #[prost(string, tag = "2")]
+    pub ticker: String,
+    #[prost(string, tag = "3")]
+    pub base_denom: String,
+    #[prost(string, tag = "4")]
+    pub quote_denom: String,
+    #[prost(string, tag = "5")]
+    pub maker_fee_rate: String,
+    #[prost(string, tag = "6")]
+    pub taker_fee_rate: String,
+    #[prost(string, tag = "7")]
+    pub status: String,
+    #[prost(string, tag = "8")]
+    pub min_price_tick: String,
+    #[prost(string, tag = "9")]
+    pub min_quantity_tick: String,
+    #[prost(string, tag = "10")]
+    pub min_notional: String,
+    #[prost(uint32, tag = "11")]
+    pub base_decimals: u32,
+    #[prost(uint32, tag = "12")]
+    pub quote_decimals: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct ExchangeBalancePayload {
+    #[prost(string, tag = "1")]
     pub subaccount_id: String,
+    #[prost(string, tag = "2")]
     pub denom: String,
+    #[prost(string, tag = "3")]
     pub available_balance: String,
+    #[prost(string, tag = "4")]
     pub total_balance: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct FullLimitOrderbookPayload {
+    #[prost(string, tag = "1")]
     pub market_id: String,
+    #[prost(message, repeated, tag = "2")]
     pub bids: Vec<TrimmedLimitOrderPayload>,
+    #[prost(message, repeated, tag = "3")]
     pub asks: Vec<TrimmedLimitOrderPayload>,
+    #[prost(int64, tag = "4")]
     pub timestamp: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, prost::Message)]
 pub struct TrimmedLimitOrderPayload {
+    #[prost(string, tag = "1")]
     pub price: String,
+    #[prost(string, tag = "2")]
     pub quantity: String,
+    #[prost(string, tag = "3")]
     pub order_hash: String,
+    #[prost(string, tag = "4")]
     pub subaccount_id: String,
 }
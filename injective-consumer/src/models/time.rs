@@ -0,0 +1,60 @@
+use crate::clock::{Clock, SystemClock};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+// Block clock helpers. Every timestamp the processors persist or bucket by is
+// derived from the message's block time, never the wall clock, so replays
+// produce the same rows as the original run.
+
+pub const HOUR_MILLIS: i64 = 3_600_000;
+pub const DAY_MILLIS: i64 = 86_400_000;
+
+// Block times arrive in seconds from some producers and milliseconds from
+// others. Ten billion seconds is the year 2286, so anything above it is millis.
+const MILLIS_THRESHOLD: i64 = 10_000_000_000;
+
+pub fn to_micros(block_time: i64) -> i64 {
+    if block_time > MILLIS_THRESHOLD {
+        block_time * 1_000
+    } else {
+        block_time * 1_000_000
+    }
+}
+
+pub fn to_millis(block_time: i64) -> i64 {
+    to_micros(block_time) / 1_000
+}
+
+pub fn to_datetime(block_time: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_micros(to_micros(block_time)).unwrap_or_default()
+}
+
+// Round a millisecond timestamp down to a multiple of `bucket_millis`
+pub fn truncate_millis(millis: i64, bucket_millis: i64) -> i64 {
+    millis - millis.rem_euclid(bucket_millis)
+}
+
+// Start of the UTC hour containing the block time, in milliseconds
+pub fn hour_bucket(block_time: i64) -> i64 {
+    truncate_millis(to_millis(block_time), HOUR_MILLIS)
+}
+
+// Start of the UTC day containing the block time, in milliseconds
+pub fn day_bucket(block_time: i64) -> i64 {
+    truncate_millis(to_millis(block_time), DAY_MILLIS)
+}
+
+// Wall clock in milliseconds, for event emission times only
+pub fn now_millis() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+// How far the block time lags the wall clock. Block times ahead of the local
+// clock (skew) count as zero.
+pub fn age(block_time: i64) -> Duration {
+    SystemClock.age(block_time)
+}
+
+pub fn is_stale(block_time: i64, max_age: Duration) -> bool {
+    SystemClock.is_stale(block_time, max_age)
+}
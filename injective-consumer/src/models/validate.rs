@@ -0,0 +1,167 @@
+use super::{KafkaMessage, KafkaPayload, MessageType};
+
+// Checks on decoded messages before any processor sees them. The processors
+// read chain numbers with `unwrap_or(0.0)`, so a malformed value would
+// otherwise be stored as a zero. Only the payloads that become stored rows are
+// checked: positions, trades, markets, oracle prices and full orderbooks.
+// Fields the processors treat as optional may be empty, but must be numbers
+// when set.
+
+impl KafkaMessage {
+    /// Reject a message whose payload doesn't fit its type, or whose chain
+    /// numbers or ids are missing or malformed. The error names the field.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.carries_own_payload() {
+            return Err(format!(
+                "{} message with a payload of another type",
+                self.message_type.as_str()
+            ));
+        }
+
+        match &self.payload {
+            KafkaPayload::StreamPositions(positions)
+            | KafkaPayload::ExchangePositions(positions) => {
+                for position in positions {
+                    id("market_id", &position.market_id)?;
+                    id("subaccount_id", &position.subaccount_id)?;
+                    number("quantity", &position.quantity)?;
+                    number("entry_price", &position.entry_price)?;
+                    number("margin", &position.margin)?;
+                    optional_number(
+                        "cumulative_funding_entry",
+                        &position.cumulative_funding_entry,
+                    )?;
+                }
+            }
+            KafkaPayload::DerivativeTrades(trades) => {
+                for trade in trades {
+                    id("market_id", &trade.market_id)?;
+                    id("subaccount_id", &trade.subaccount_id)?;
+                    number("execution_price", &trade.position_delta.execution_price)?;
+                    number(
+                        "execution_quantity",
+                        &trade.position_delta.execution_quantity,
+                    )?;
+                    optional_number("fee", &trade.fee)?;
+                }
+            }
+            KafkaPayload::SpotTrades(trades) => {
+                for trade in trades {
+                    id("market_id", &trade.market_id)?;
+                    id("subaccount_id", &trade.subaccount_id)?;
+                    number("price", &trade.price)?;
+                    number("quantity", &trade.quantity)?;
+                    optional_number("fee", &trade.fee)?;
+                }
+            }
+            KafkaPayload::DerivativeMarkets(markets) => {
+                for market in markets {
+                    id("market_id", &market.market_id)?;
+                    number("mark_price", &market.mark_price)?;
+                    for (field, value) in [
+                        ("maintenance_margin_ratio", &market.maintenance_margin_ratio),
+                        ("cumulative_funding", &market.cumulative_funding),
+                        ("cumulative_price", &market.cumulative_price),
+                        ("expiration_timestamp", &market.expiration_timestamp),
+                        ("settlement_price", &market.settlement_price),
+                        ("hfr", &market.hfr),
+                        ("hir", &market.hir),
+                    ] {
+                        optional_number(field, value)?;
+                    }
+                }
+            }
+            KafkaPayload::SpotMarkets(markets) => {
+                for market in markets {
+                    id("market_id", &market.market_id)?;
+                }
+            }
+            KafkaPayload::StreamOraclePrices(prices) => {
+                for price in prices {
+                    id("symbol", &price.symbol)?;
+                    number("price", &price.price)?;
+                }
+            }
+            KafkaPayload::DerivativeFullOrderbooks(orderbooks)
+            | KafkaPayload::SpotFullOrderbooks(orderbooks) => {
+                for orderbook in orderbooks {
+                    id("market_id", &orderbook.market_id)?;
+                    for order in orderbook.bids.iter().chain(&orderbook.asks) {
+                        number("price", &order.price)?;
+                        number("quantity", &order.quantity)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // JSON payloads are untagged, so a payload that doesn't parse as its own
+    // type can still parse as another one. Payloads with the same shape are
+    // told apart by the message type (see `positions` and `full_orderbooks`);
+    // empty payloads fit every type.
+    fn carries_own_payload(&self) -> bool {
+        let payload = &self.payload;
+        let fits = match self.message_type {
+            MessageType::StreamPosition | MessageType::ExchangePosition => matches!(
+                payload,
+                KafkaPayload::StreamPositions(_) | KafkaPayload::ExchangePositions(_)
+            ),
+            MessageType::DerivativeTrade => matches!(payload, KafkaPayload::DerivativeTrades(_)),
+            MessageType::SpotTrade => matches!(payload, KafkaPayload::SpotTrades(_)),
+            MessageType::DerivativeMarket => {
+                matches!(payload, KafkaPayload::DerivativeMarkets(_))
+            }
+            MessageType::SpotMarket => matches!(payload, KafkaPayload::SpotMarkets(_)),
+            MessageType::StreamOraclePrice => {
+                matches!(payload, KafkaPayload::StreamOraclePrices(_))
+            }
+            MessageType::DerivativeFullOrderbook | MessageType::SpotFullOrderbook => matches!(
+                payload,
+                KafkaPayload::DerivativeFullOrderbooks(_) | KafkaPayload::SpotFullOrderbooks(_)
+            ),
+            _ => true,
+        };
+        // The first variant is what an empty list decodes as
+        fits || matches!(payload, KafkaPayload::StreamBankBalances(items) if items.is_empty())
+    }
+}
+
+fn id(field: &str, value: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err(format!("empty {}", field));
+    }
+    Ok(())
+}
+
+// A chain number: an optionally negative integer or decimal string. Rejects
+// what `f64` parsing would also take, such as "NaN", "inf" and exponents.
+fn number(field: &str, value: &str) -> Result<(), String> {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let valid = !(integer.is_empty() && fraction.is_empty())
+        && integer
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit());
+    if !valid {
+        return Err(format!("{} is not a number: {:?}", field, truncate(value)));
+    }
+    Ok(())
+}
+
+fn optional_number(field: &str, value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Ok(());
+    }
+    number(field, value)
+}
+
+// Keep error messages short when the value is huge
+fn truncate(value: &str) -> &str {
+    match value.char_indices().nth(32) {
+        Some((end, _)) => &value[..end],
+        None => value,
+    }
+}
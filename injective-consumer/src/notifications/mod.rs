@@ -0,0 +1,419 @@
+use crate::clock::{self, Clock};
+use crate::pubsub::{EventSubscriber, EventType, RedisPubSubConfig};
+use crate::secrets::{self, RedisRole, Secret};
+use futures::future::join_all;
+use log::{debug, error, info, warn};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio::task::{self, JoinHandle};
+use tokio::time;
+
+// Posts the liquidation alerts on the pub/sub channels to chat webhooks, so
+// nobody has to run their own Redis subscriber to hear about liquidatable
+// positions. A position that stays liquidatable is alerted on with every
+// update; it is only announced again once `repeat_after` has passed. Each
+// webhook has its own queue and rate limit, so a slow or throttled one
+// doesn't hold up the others.
+
+// Alerts waiting per webhook; more are dropped
+const QUEUE_SIZE: usize = 100;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// How long queued alerts get to go out on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+// Announcement times are pruned once this many positions are tracked
+const MAX_TRACKED: usize = 10_000;
+
+const DEFAULT_TEMPLATE: &str = "Liquidatable {side} position on {market_id}: subaccount {subaccount_id}, quantity {quantity}, liquidation price {liquidation_price}, mark price {mark_price}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookKind {
+    Discord,
+    Slack,
+    Telegram,
+    // Any endpoint taking {"text": ..., "alert": {...}}
+    Generic,
+}
+
+impl FromStr for WebhookKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "discord" => Ok(WebhookKind::Discord),
+            "slack" => Ok(WebhookKind::Slack),
+            "telegram" => Ok(WebhookKind::Telegram),
+            "generic" => Ok(WebhookKind::Generic),
+            other => Err(format!("Unknown webhook kind: {}", other)),
+        }
+    }
+}
+
+// One webhook. Its URL usually carries a token, so it never shows up in logs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    pub kind: WebhookKind,
+    pub url: Secret,
+    // Telegram's sendMessage needs the chat to post to
+    pub chat_id: Option<String>,
+}
+
+impl Webhook {
+    fn body(&self, text: &str, alert: &Value) -> Value {
+        match self.kind {
+            WebhookKind::Discord => serde_json::json!({ "content": text }),
+            WebhookKind::Slack => serde_json::json!({ "text": text }),
+            WebhookKind::Telegram => serde_json::json!({ "chat_id": self.chat_id, "text": text }),
+            WebhookKind::Generic => serde_json::json!({ "text": text, "alert": alert }),
+        }
+    }
+}
+
+// `kind=url`, or `telegram:<chat_id>=url` for Telegram
+impl FromStr for Webhook {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, url) = s
+            .split_once('=')
+            .ok_or("Webhooks must be given as kind=url")?;
+        let (kind, chat_id) = match kind.split_once(':') {
+            Some((kind, chat_id)) => (kind, Some(chat_id.trim().to_string())),
+            None => (kind, None),
+        };
+        let kind: WebhookKind = kind.trim().parse()?;
+        if kind == WebhookKind::Telegram && chat_id.is_none() {
+            return Err("Telegram webhooks must be given as telegram:<chat_id>=url".to_string());
+        }
+        Ok(Webhook {
+            kind,
+            url: Secret::new(url.trim()),
+            chat_id,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NotifierConfig {
+    pub redis_url: String,
+    // Must match the publishing consumer's channel prefix
+    pub channel_prefix: String,
+    pub webhooks: Vec<Webhook>,
+    // Message text; see `render` for the placeholders
+    pub template: String,
+    // Posts per webhook per minute; 0 turns the limit off
+    pub max_per_minute: u32,
+    pub repeat_after: Duration,
+}
+
+impl Default for NotifierConfig {
+    fn default() -> Self {
+        NotifierConfig {
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            channel_prefix: RedisPubSubConfig::default().channel_prefix,
+            webhooks: Vec::new(),
+            template: DEFAULT_TEMPLATE.to_string(),
+            max_per_minute: 20,
+            repeat_after: Duration::from_secs(300),
+        }
+    }
+}
+
+impl NotifierConfig {
+    pub fn from_env() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut config = NotifierConfig::default();
+
+        if let Some(url) = secrets::role_redis_url(RedisRole::Reader, None)? {
+            config.redis_url = url;
+        }
+
+        if let Ok(prefix) = env::var("PUBSUB_CHANNEL_PREFIX") {
+            config.channel_prefix = prefix;
+        }
+
+        // Comma-separated; a secret, as the URLs carry tokens
+        if let Some(webhooks) = secrets::load("NOTIFY_WEBHOOKS")? {
+            for webhook in webhooks.split(',').filter(|w| !w.trim().is_empty()) {
+                config.webhooks.push(webhook.parse()?);
+            }
+        }
+
+        if let Ok(template) = env::var("NOTIFY_TEMPLATE") {
+            config.template = template;
+        }
+
+        if let Ok(max) = env::var("NOTIFY_MAX_PER_MINUTE") {
+            config.max_per_minute = max.parse()?;
+        }
+
+        if let Ok(secs) = env::var("NOTIFY_REPEAT_AFTER_SECS") {
+            config.repeat_after = Duration::from_secs(secs.parse()?);
+        }
+
+        Ok(config)
+    }
+}
+
+// Fill the template's `{field}` placeholders with the alert's fields
+// (market_id, subaccount_id, quantity, liquidation_price, ...), plus `{side}`
+// for long or short. Placeholders the alert has no field for are left as is.
+pub fn render(template: &str, alert: &Value) -> String {
+    let mut text = template.to_string();
+    if let Some(fields) = alert.as_object() {
+        for (name, value) in fields {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                other => other.to_string(),
+            };
+            text = text.replace(&format!("{{{}}}", name), &value);
+        }
+    }
+    let side = match alert.get("is_long").and_then(Value::as_bool) {
+        Some(true) => "long",
+        Some(false) => "short",
+        None => "unknown",
+    };
+    text.replace("{side}", side)
+}
+
+struct Alert {
+    text: String,
+    payload: Value,
+}
+
+pub struct LiquidationNotifier {
+    config: NotifierConfig,
+    client: reqwest::Client,
+    // When each (market, subaccount) was last announced, in clock millis
+    announced: HashMap<(String, String), i64>,
+    clock: Arc<dyn Clock>,
+}
+
+impl LiquidationNotifier {
+    pub fn new(config: NotifierConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if config.webhooks.is_empty() {
+            return Err("No webhooks configured; set NOTIFY_WEBHOOKS".into());
+        }
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        Ok(LiquidationNotifier {
+            config,
+            client,
+            announced: HashMap::new(),
+            clock: clock::system(),
+        })
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Post alerts until the shutdown flag flips to true, resubscribing after
+    // Redis drops the connection
+    pub async fn run(
+        mut self,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (queues, workers): (Vec<_>, Vec<_>) = self
+            .config
+            .webhooks
+            .iter()
+            .map(|webhook| self.spawn_worker(webhook.clone()))
+            .unzip();
+
+        loop {
+            tokio::select! {
+                result = self.listen(&queues) => {
+                    if let Err(e) = result {
+                        error!("Notifier pub/sub subscription failed: {}", e);
+                    }
+                    time::sleep(Duration::from_secs(1)).await;
+                }
+                _ = shutdown.changed() => {
+                    if *shutdown.borrow() {
+                        info!("Liquidation notifier shutting down");
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Closing the queues lets the workers post what is left and stop
+        drop(queues);
+        if time::timeout(SHUTDOWN_TIMEOUT, join_all(workers))
+            .await
+            .is_err()
+        {
+            warn!(
+                "Gave up on queued liquidation alerts after {:?}",
+                SHUTDOWN_TIMEOUT
+            );
+        }
+        Ok(())
+    }
+
+    fn spawn_worker(&self, webhook: Webhook) -> (mpsc::Sender<Arc<Alert>>, JoinHandle<()>) {
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        let limiter = RateLimiter::new(self.config.max_per_minute, Duration::from_secs(60));
+        let worker = task::spawn(deliver(self.client.clone(), webhook, limiter, receiver));
+        (sender, worker)
+    }
+
+    async fn listen(
+        &mut self,
+        queues: &[mpsc::Sender<Arc<Alert>>],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let prefix = &self.config.channel_prefix;
+        let mut subscriber = EventSubscriber::connect(&self.config.redis_url, prefix).await?;
+        info!("Liquidation notifier subscribed to {} channels", prefix);
+
+        while let Some(message) = subscriber.next().await {
+            let message = match message {
+                Ok(message) => message,
+                Err(e) => {
+                    debug!("Skipping undecodable pub/sub message: {}", e);
+                    continue;
+                }
+            };
+            for (event, _) in message.events {
+                if event.event_type != EventType::LiquidationAlert
+                    || !self.should_announce(&event.payload)
+                {
+                    continue;
+                }
+                let alert = Arc::new(Alert {
+                    text: render(&self.config.template, &event.payload),
+                    payload: event.payload,
+                });
+                for queue in queues {
+                    if queue.try_send(alert.clone()).is_err() {
+                        warn!("Webhook queue is full, dropping a liquidation alert");
+                    }
+                }
+            }
+        }
+        Err("pub/sub connection closed".into())
+    }
+
+    // Whether an alert is for a position not announced within `repeat_after`,
+    // recording the announcement if so
+    pub fn should_announce(&mut self, alert: &Value) -> bool {
+        let field = |name: &str| {
+            alert
+                .get(name)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        let now = self.clock.now_millis();
+        let repeat_after = self.config.repeat_after.as_millis() as i64;
+        if self.announced.len() >= MAX_TRACKED {
+            self.announced.retain(|_, at| now - *at < repeat_after);
+        }
+        let position = (field("market_id"), field("subaccount_id"));
+        if self
+            .announced
+            .get(&position)
+            .is_some_and(|at| now - *at < repeat_after)
+        {
+            return false;
+        }
+        self.announced.insert(position, now);
+        true
+    }
+}
+
+// Allows `max` posts per sliding window; 0 allows any number
+struct RateLimiter {
+    max: usize,
+    window: Duration,
+    sent: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    fn new(max: u32, window: Duration) -> Self {
+        RateLimiter {
+            max: max as usize,
+            window,
+            sent: VecDeque::new(),
+        }
+    }
+
+    // Wait until another post is allowed and count it
+    async fn acquire(&mut self) {
+        if self.max == 0 {
+            return;
+        }
+        loop {
+            let now = Instant::now();
+            while self
+                .sent
+                .front()
+                .is_some_and(|at| now.duration_since(*at) >= self.window)
+            {
+                self.sent.pop_front();
+            }
+            match self.sent.front() {
+                Some(oldest) if self.sent.len() >= self.max => {
+                    time::sleep(self.window - now.duration_since(*oldest)).await
+                }
+                _ => {
+                    self.sent.push_back(now);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn deliver(
+    client: reqwest::Client,
+    webhook: Webhook,
+    mut limiter: RateLimiter,
+    mut alerts: mpsc::Receiver<Arc<Alert>>,
+) {
+    while let Some(alert) = alerts.recv().await {
+        limiter.acquire().await;
+        if let Err(e) = post(&client, &webhook, &alert).await {
+            warn!(
+                "Failed to post liquidation alert to {:?} webhook: {}",
+                webhook.kind, e
+            );
+        }
+    }
+}
+
+// Post one alert, retrying once when the webhook says to slow down
+async fn post(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    alert: &Alert,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let body = webhook.body(&alert.text, &alert.payload);
+    let mut retried = false;
+    loop {
+        let response = client.post(webhook.url.expose()).json(&body).send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        if status != reqwest::StatusCode::TOO_MANY_REQUESTS || retried {
+            return Err(format!("webhook responded with {}", status).into());
+        }
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(1);
+        time::sleep(Duration::from_secs(retry_after)).await;
+        retried = true;
+    }
+}
@@ -0,0 +1,256 @@
+use super::BookLevel;
+use crate::models::{FullLimitOrderbookPayload, OrderbookPayload, TrimmedLimitOrderPayload};
+use async_trait::async_trait;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::time::Duration;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{Channel, Endpoint};
+
+// Books kept current from the stream's orderbook deltas. Each delta carries
+// the new total quantity of the levels that changed (zero removes a level)
+// and a per-market sequence number one above the previous delta's. A book
+// missing a delta can't be repaired from later ones, so a gap, or the first
+// delta of a market, makes the tracker ask for an L3 snapshot to start over.
+
+// Price levels ordered by price; prices come from parsed decimals, never NaN
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Price(f64);
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// What applying a delta did to its market's book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaOutcome {
+    Applied,
+    // At or below the book's sequence; already reflected in it
+    Stale,
+    // No book yet; the book needs a snapshot
+    NeedsSnapshot,
+    // Deltas were missed; the book was dropped and needs a snapshot
+    Gap { expected: u64, received: u64 },
+}
+
+// One market's book, in the units the tracker's scales give
+#[derive(Debug, Clone, Default)]
+pub struct LiveBook {
+    sequence: u64,
+    bids: BTreeMap<Price, f64>,
+    asks: BTreeMap<Price, f64>,
+}
+
+impl LiveBook {
+    // Levels are summed from the snapshot's resting orders. The snapshot has
+    // no sequence of its own; `sequence` is the delta it is taken to follow.
+    pub fn from_snapshot(
+        snapshot: &FullLimitOrderbookPayload,
+        sequence: u64,
+        price_scale: f64,
+        quantity_scale: f64,
+    ) -> Self {
+        let side = |orders: &[TrimmedLimitOrderPayload]| {
+            let mut levels = BTreeMap::new();
+            for order in orders {
+                let price = order.price.parse::<f64>().unwrap_or(0.0) / price_scale;
+                let quantity = order.quantity.parse::<f64>().unwrap_or(0.0) / quantity_scale;
+                if price > 0.0 && quantity > 0.0 {
+                    *levels.entry(Price(price)).or_insert(0.0) += quantity;
+                }
+            }
+            levels
+        };
+        LiveBook {
+            sequence,
+            bids: side(&snapshot.bids),
+            asks: side(&snapshot.asks),
+        }
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn best_bid(&self) -> Option<BookLevel> {
+        self.bids
+            .iter()
+            .next_back()
+            .map(|(price, quantity)| BookLevel {
+                price: price.0,
+                quantity: *quantity,
+            })
+    }
+
+    pub fn best_ask(&self) -> Option<BookLevel> {
+        self.asks.iter().next().map(|(price, quantity)| BookLevel {
+            price: price.0,
+            quantity: *quantity,
+        })
+    }
+
+    // None unless both sides have a level
+    pub fn mid_price(&self) -> Option<f64> {
+        let (bid, ask) = self.best_bid().zip(self.best_ask())?;
+        Some((bid.price + ask.price) / 2.0)
+    }
+
+    fn apply(&mut self, delta: &OrderbookPayload, price_scale: f64, quantity_scale: f64) {
+        for (levels, updates) in [
+            (&mut self.bids, &delta.buy_levels),
+            (&mut self.asks, &delta.sell_levels),
+        ] {
+            for level in updates {
+                let price = level.price.parse::<f64>().unwrap_or(0.0) / price_scale;
+                let quantity = level.quantity.parse::<f64>().unwrap_or(0.0) / quantity_scale;
+                if price <= 0.0 {
+                    continue;
+                }
+                if quantity > 0.0 {
+                    levels.insert(Price(price), quantity);
+                } else {
+                    levels.remove(&Price(price));
+                }
+            }
+        }
+        self.sequence = delta.sequence;
+    }
+}
+
+// The live books of every market seen in the stream
+pub struct BookTracker {
+    books: HashMap<String, LiveBook>,
+    price_scale: f64,
+    quantity_scale: f64,
+}
+
+impl BookTracker {
+    pub fn new(price_scale: f64, quantity_scale: f64) -> Self {
+        BookTracker {
+            books: HashMap::new(),
+            price_scale,
+            quantity_scale,
+        }
+    }
+
+    pub fn book(&self, market_id: &str) -> Option<&LiveBook> {
+        self.books.get(market_id)
+    }
+
+    // Apply a delta if it is the next one for its market. On a gap the book
+    // is dropped, so later deltas keep asking for a snapshot until one lands.
+    pub fn apply(&mut self, delta: &OrderbookPayload) -> DeltaOutcome {
+        let Some(book) = self.books.get_mut(&delta.market_id) else {
+            return DeltaOutcome::NeedsSnapshot;
+        };
+        if delta.sequence <= book.sequence {
+            return DeltaOutcome::Stale;
+        }
+        if delta.sequence != book.sequence + 1 {
+            let expected = book.sequence + 1;
+            self.books.remove(&delta.market_id);
+            return DeltaOutcome::Gap {
+                expected,
+                received: delta.sequence,
+            };
+        }
+        book.apply(delta, self.price_scale, self.quantity_scale);
+        DeltaOutcome::Applied
+    }
+
+    // Start a market over from a snapshot taken after the delta at `sequence`
+    // was received. The snapshot already reflects that delta, so it isn't
+    // applied again, and the next delta continues from its sequence.
+    pub fn resync(&mut self, snapshot: &FullLimitOrderbookPayload, sequence: u64) -> &LiveBook {
+        let book =
+            LiveBook::from_snapshot(snapshot, sequence, self.price_scale, self.quantity_scale);
+        self.books.insert(snapshot.market_id.clone(), book);
+        &self.books[&snapshot.market_id]
+    }
+}
+
+// Where resting orders come from when a book has to start over
+#[async_trait]
+pub trait BookSnapshotSource: Send + Sync {
+    async fn l3_orderbook(
+        &self,
+        market_id: &str,
+    ) -> Result<FullLimitOrderbookPayload, Box<dyn Error + Send + Sync>>;
+}
+
+#[derive(Clone, prost::Message)]
+struct L3OrderbookRequest {
+    #[prost(string, tag = "1")]
+    market_id: String,
+}
+
+#[derive(Clone, prost::Message)]
+struct L3OrderbookResponse {
+    #[prost(message, repeated, tag = "1")]
+    bids: Vec<TrimmedLimitOrderPayload>,
+    #[prost(message, repeated, tag = "2")]
+    asks: Vec<TrimmedLimitOrderPayload>,
+}
+
+// The chain's L3DerivativeOrderBook query on the gRPC query endpoint. The
+// messages are declared here, matching the exchange module's protos, so the
+// consumer doesn't need the producer's generated code.
+#[derive(Clone)]
+pub struct ChainBookSnapshots {
+    channel: Channel,
+}
+
+impl ChainBookSnapshots {
+    // Connects on first use; each query gives up after `timeout`
+    pub fn new(
+        query_endpoint: &str,
+        timeout: Duration,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let channel = Endpoint::from_shared(query_endpoint.to_string())?
+            .timeout(timeout)
+            .connect_lazy();
+        Ok(ChainBookSnapshots { channel })
+    }
+}
+
+#[async_trait]
+impl BookSnapshotSource for ChainBookSnapshots {
+    async fn l3_orderbook(
+        &self,
+        market_id: &str,
+    ) -> Result<FullLimitOrderbookPayload, Box<dyn Error + Send + Sync>> {
+        let mut client = tonic::client::Grpc::new(self.channel.clone());
+        client.ready().await?;
+        let codec: ProstCodec<L3OrderbookRequest, L3OrderbookResponse> = ProstCodec::default();
+        let response = client
+            .unary(
+                tonic::Request::new(L3OrderbookRequest {
+                    market_id: market_id.to_string(),
+                }),
+                PathAndQuery::from_static(
+                    "/injective.exchange.v1beta1.Query/L3DerivativeOrderBook",
+                ),
+                codec,
+            )
+            .await?
+            .into_inner();
+        Ok(FullLimitOrderbookPayload {
+            market_id: market_id.to_string(),
+            bids: response.bids,
+            asks: response.asks,
+            timestamp: 0,
+        })
+    }
+}
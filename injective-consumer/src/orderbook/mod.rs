@@ -0,0 +1,195 @@
+use crate::models::{FullLimitOrderbookPayload, TrimmedLimitOrderPayload};
+use serde::{Deserialize, Serialize};
+
+mod live;
+pub use live::{BookSnapshotSource, BookTracker, ChainBookSnapshots, DeltaOutcome, LiveBook};
+
+// L2 view of the full L3 orderbooks: resting orders merged into price levels,
+// best first, optionally on a grid coarser than the market's own tick. The
+// Redis processor stores and publishes these levels and the reader regroups
+// them for callers. Best bids and asks alone come from top_of_book, which
+// doesn't build levels at all. Books between full snapshots are kept from the
+// stream's deltas by the live submodule.
+
+// Levels kept per side of an aggregated book
+pub const MAX_DEPTH_LEVELS: usize = 200;
+
+// One aggregated price level, in human units
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BookLevel {
+    pub price: f64,
+    pub quantity: f64,
+}
+
+// Both sides of an aggregated book, best level first
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct L2Book {
+    pub bids: Vec<BookLevel>,
+    pub asks: Vec<BookLevel>,
+}
+
+impl L2Book {
+    pub fn best_bid(&self) -> Option<&BookLevel> {
+        self.bids.first()
+    }
+
+    pub fn best_ask(&self) -> Option<&BookLevel> {
+        self.asks.first()
+    }
+
+    // The same book with levels `tick` wide
+    pub fn regroup(&self, tick: Option<f64>) -> L2Book {
+        let pair = |level: &BookLevel| (level.price, level.quantity);
+        L2Book {
+            bids: aggregate_levels(self.bids.iter().map(pair), true, tick),
+            asks: aggregate_levels(self.asks.iter().map(pair), false, tick),
+        }
+    }
+
+    // Only the best `levels` levels of each side
+    pub fn truncate(&mut self, levels: usize) {
+        self.bids.truncate(levels);
+        self.asks.truncate(levels);
+    }
+}
+
+// Aggregate a full orderbook. Chain prices and quantities are divided by the
+// scales first, so the levels come out in the units the caller keeps.
+pub fn aggregate_book(
+    orderbook: &FullLimitOrderbookPayload,
+    price_scale: f64,
+    quantity_scale: f64,
+    tick: Option<f64>,
+) -> L2Book {
+    let level = |order: &TrimmedLimitOrderPayload| {
+        (
+            order.price.parse::<f64>().unwrap_or(0.0) / price_scale,
+            order.quantity.parse::<f64>().unwrap_or(0.0) / quantity_scale,
+        )
+    };
+    L2Book {
+        bids: aggregate_levels(orderbook.bids.iter().map(level), true, tick),
+        asks: aggregate_levels(orderbook.asks.iter().map(level), false, tick),
+    }
+}
+
+// Best bid and ask of a full orderbook with the total quantity resting at
+// each, in the units the scales give. One pass per side; no levels are built.
+pub fn top_of_book(
+    orderbook: &FullLimitOrderbookPayload,
+    price_scale: f64,
+    quantity_scale: f64,
+) -> (Option<BookLevel>, Option<BookLevel>) {
+    let level = |order: &TrimmedLimitOrderPayload| {
+        (
+            order.price.parse::<f64>().unwrap_or(0.0) / price_scale,
+            order.quantity.parse::<f64>().unwrap_or(0.0) / quantity_scale,
+        )
+    };
+    (
+        best_level(orderbook.bids.iter().map(level), true),
+        best_level(orderbook.asks.iter().map(level), false),
+    )
+}
+
+// The best level of one side: the highest price for bids, the lowest for
+// asks, with the quantity of every order at it. Orders without a positive
+// quantity are ignored, as when aggregating.
+pub fn best_level(orders: impl Iterator<Item = (f64, f64)>, descending: bool) -> Option<BookLevel> {
+    let mut best: Option<BookLevel> = None;
+    for (price, quantity) in orders.filter(|(_, q)| *q > 0.0) {
+        match &mut best {
+            Some(level) if level.price == price => level.quantity += quantity,
+            Some(level) if (price > level.price) != descending => {}
+            _ => best = Some(BookLevel { price, quantity }),
+        }
+    }
+    best
+}
+
+// Aggregate orders into price levels, best first: bids descending, asks
+// ascending. With a tick, bid prices are rounded down and ask prices up to a
+// multiple of it, so a level never looks better than its orders. Orders
+// without a positive quantity are ignored, and only the best
+// MAX_DEPTH_LEVELS are kept.
+pub fn aggregate_levels(
+    orders: impl Iterator<Item = (f64, f64)>,
+    descending: bool,
+    tick: Option<f64>,
+) -> Vec<BookLevel> {
+    let tick = tick.filter(|tick| *tick > 0.0);
+    let mut orders: Vec<(f64, f64)> = orders
+        .filter(|(_, q)| *q > 0.0)
+        .map(|(price, quantity)| match tick {
+            Some(tick) => (snap(price, tick, descending), quantity),
+            None => (price, quantity),
+        })
+        .collect();
+    let best_first = |a: &(f64, f64), b: &(f64, f64)| {
+        if descending {
+            b.0.total_cmp(&a.0)
+        } else {
+            a.0.total_cmp(&b.0)
+        }
+    };
+
+    // Large books only sort their best MAX_DEPTH_LEVELS orders; the rest are
+    // sorted only if orders sharing a price left levels to fill
+    let split = orders.len().min(MAX_DEPTH_LEVELS);
+    if split < orders.len() {
+        orders.select_nth_unstable_by(split, best_first);
+    }
+    let (best, rest) = orders.split_at_mut(split);
+    best.sort_by(best_first);
+
+    let mut levels: Vec<BookLevel> = Vec::new();
+    push_levels(&mut levels, best);
+    if levels.len() < MAX_DEPTH_LEVELS {
+        rest.sort_by(best_first);
+        push_levels(&mut levels, rest);
+    } else if let Some(last) = levels.last_mut() {
+        // Orders past the split can still rest at the worst kept price
+        last.quantity += rest
+            .iter()
+            .filter(|(price, _)| *price == last.price)
+            .map(|(_, quantity)| quantity)
+            .sum::<f64>();
+    }
+    levels
+}
+
+// Merge sorted orders into the levels, up to MAX_DEPTH_LEVELS
+fn push_levels(levels: &mut Vec<BookLevel>, sorted: &[(f64, f64)]) {
+    for &(price, quantity) in sorted {
+        if let Some(level) = levels.last_mut() {
+            if level.price == price {
+                level.quantity += quantity;
+                continue;
+            }
+        }
+        if levels.len() == MAX_DEPTH_LEVELS {
+            break;
+        }
+        levels.push(BookLevel { price, quantity });
+    }
+}
+
+// Price rounded to a multiple of the tick, down or up. Prices already on the
+// grid stay put despite float error (0.3 over a tick of 0.1 is 2.9999...),
+// and ticks like 0.1 that divide one evenly give levels such as 0.3 rather
+// than 0.30000000000000004.
+fn snap(price: f64, tick: f64, down: bool) -> f64 {
+    const TOLERANCE: f64 = 1e-9;
+    let steps = price / tick;
+    let steps = if down {
+        (steps + TOLERANCE).floor()
+    } else {
+        (steps - TOLERANCE).ceil()
+    };
+    let per_unit = 1.0 / tick;
+    if (per_unit - per_unit.round()).abs() < TOLERANCE {
+        steps / per_unit.round()
+    } else {
+        steps * tick
+    }
+}
@@ -0,0 +1,223 @@
+use crate::config::{PayloadLogConfig, PayloadLogMode};
+use log::{error, info};
+use serde_json::Value;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+#[cfg(feature = "redis")]
+use {
+    crate::redis_keys,
+    log::warn,
+    redis::{aio::ConnectionManager, Client},
+    std::collections::HashMap,
+    std::error::Error,
+    std::sync::Arc,
+    std::time::Duration,
+    tokio::{task, time},
+};
+
+const REDACTED: &str = "<redacted>";
+
+// Applies the payload logging policy to raw Kafka payloads. The policy lives in
+// atomics so it can be swapped at runtime without locking the consume loop.
+pub struct PayloadLogger {
+    mode: AtomicU8,
+    sample_every: AtomicU64,
+    max_bytes: AtomicUsize,
+    redact_subaccounts: AtomicBool,
+    received: AtomicU64,
+}
+
+impl PayloadLogger {
+    pub fn new(config: &PayloadLogConfig) -> Self {
+        let logger = PayloadLogger {
+            mode: AtomicU8::new(0),
+            sample_every: AtomicU64::new(0),
+            max_bytes: AtomicUsize::new(0),
+            redact_subaccounts: AtomicBool::new(true),
+            received: AtomicU64::new(0),
+        };
+        logger.set_config(config);
+        logger
+    }
+
+    pub fn set_config(&self, config: &PayloadLogConfig) {
+        let mode = match config.mode {
+            PayloadLogMode::Off => 0,
+            PayloadLogMode::Sampled => 1,
+            PayloadLogMode::ErrorsOnly => 2,
+        };
+        self.mode.store(mode, Ordering::Relaxed);
+        self.sample_every
+            .store(config.sample_every.max(1), Ordering::Relaxed);
+        self.max_bytes.store(config.max_bytes, Ordering::Relaxed);
+        self.redact_subaccounts
+            .store(config.redact_subaccounts, Ordering::Relaxed);
+    }
+
+    fn mode(&self) -> PayloadLogMode {
+        match self.mode.load(Ordering::Relaxed) {
+            0 => PayloadLogMode::Off,
+            1 => PayloadLogMode::Sampled,
+            _ => PayloadLogMode::ErrorsOnly,
+        }
+    }
+
+    // Called for every payload received; logs one in every N when sampling
+    pub fn received(&self, payload: &[u8]) {
+        let count = self.received.fetch_add(1, Ordering::Relaxed);
+        if self.mode() == PayloadLogMode::Sampled
+            && count.is_multiple_of(self.sample_every.load(Ordering::Relaxed))
+        {
+            info!("Kafka payload (sampled): {}", self.render(payload));
+        }
+    }
+
+    // Called for payloads that failed to deserialize or process
+    pub fn failed(&self, payload: &[u8], reason: &dyn Display) {
+        if self.mode() != PayloadLogMode::Off {
+            error!(
+                "Kafka payload failed ({}): {}",
+                reason,
+                self.render(payload)
+            );
+        }
+    }
+
+    // Redacted and truncated text of a payload
+    pub fn render(&self, payload: &[u8]) -> String {
+        let mut text = if self.redact_subaccounts.load(Ordering::Relaxed) {
+            match serde_json::from_slice::<Value>(payload) {
+                Ok(mut value) => {
+                    redact_subaccounts(&mut value);
+                    value.to_string()
+                }
+                // Not JSON, so fields can't be told apart; hide every id
+                Err(_) => redact_ids(&String::from_utf8_lossy(payload)),
+            }
+        } else {
+            String::from_utf8_lossy(payload).into_owned()
+        };
+
+        let max_bytes = self.max_bytes.load(Ordering::Relaxed);
+        if text.len() > max_bytes {
+            let total = text.len();
+            let mut end = max_bytes;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text.truncate(end);
+            text.push_str(&format!("... ({} bytes)", total));
+        }
+        text
+    }
+
+    // Poll the runtime override hash and apply it whenever it changes. Fields
+    // missing from the hash fall back to `defaults`.
+    #[cfg(feature = "redis")]
+    pub async fn watch(
+        self: Arc<Self>,
+        redis_url: &str,
+        defaults: PayloadLogConfig,
+        interval: Duration,
+    ) -> Result<task::JoinHandle<()>, Box<dyn Error + Send + Sync>> {
+        let client = Client::open(redis_url)?;
+        let mut connection = ConnectionManager::new(client).await?;
+
+        Ok(task::spawn(async move {
+            let mut interval_timer = time::interval(interval);
+            let mut applied: Option<HashMap<String, String>> = None;
+
+            loop {
+                interval_timer.tick().await;
+
+                let overrides: HashMap<String, String> = match redis::cmd("HGETALL")
+                    .arg(redis_keys::payload_log_config())
+                    .query_async(&mut connection)
+                    .await
+                {
+                    Ok(overrides) => overrides,
+                    Err(e) => {
+                        warn!("Failed to read payload log overrides: {}", e);
+                        continue;
+                    }
+                };
+                if applied.as_ref() == Some(&overrides) {
+                    continue;
+                }
+
+                match apply_overrides(&defaults, &overrides) {
+                    Ok(config) => {
+                        info!("Payload logging policy: {:?}", config);
+                        self.set_config(&config);
+                    }
+                    Err(e) => error!("Invalid payload log overrides: {}", e),
+                }
+                applied = Some(overrides);
+            }
+        }))
+    }
+}
+
+#[cfg(feature = "redis")]
+fn apply_overrides(
+    defaults: &PayloadLogConfig,
+    overrides: &HashMap<String, String>,
+) -> Result<PayloadLogConfig, Box<dyn Error + Send + Sync>> {
+    let mut config = defaults.clone();
+    if let Some(mode) = overrides.get("mode") {
+        config.mode = mode.parse()?;
+    }
+    if let Some(every) = overrides.get("sample_every") {
+        config.sample_every = every.parse()?;
+    }
+    if let Some(max_bytes) = overrides.get("max_bytes") {
+        config.max_bytes = max_bytes.parse()?;
+    }
+    if let Some(redact) = overrides.get("redact_subaccounts") {
+        config.redact_subaccounts = redact.parse()?;
+    }
+    Ok(config)
+}
+
+// Replace the value of every field whose name mentions a subaccount
+fn redact_subaccounts(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                if key.to_ascii_lowercase().contains("subaccount") {
+                    *field = Value::String(REDACTED.to_string());
+                } else {
+                    redact_subaccounts(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_subaccounts),
+        _ => {}
+    }
+}
+
+// Replace every 0x-prefixed 32-byte hex id
+fn redact_ids(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut redacted = String::with_capacity(text.len());
+    let mut start = 0;
+    let mut i = 0;
+
+    while i + 66 <= bytes.len() {
+        if bytes[i] == b'0'
+            && bytes[i + 1] == b'x'
+            && bytes[i + 2..i + 66].iter().all(u8::is_ascii_hexdigit)
+            && bytes.get(i + 66).is_none_or(|b| !b.is_ascii_hexdigit())
+        {
+            redacted.push_str(&text[start..i]);
+            redacted.push_str(REDACTED);
+            i += 66;
+            start = i;
+        } else {
+            i += 1;
+        }
+    }
+    redacted.push_str(&text[start..]);
+    redacted
+}
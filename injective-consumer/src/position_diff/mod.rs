@@ -0,0 +1,87 @@
+use crate::models::PositionPayload;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+// (market_id, subaccount_id)
+pub type PositionKey = (String, String);
+
+// Result of comparing a positions snapshot against the previous one
+#[derive(Debug, Default)]
+pub struct PositionDiff {
+    // Positions that are new or changed since the previous snapshot
+    pub changed: HashSet<PositionKey>,
+    // Positions present in the previous snapshot but missing from this one
+    pub closed: Vec<PositionKey>,
+    // Every position should be emitted, marking a full snapshot
+    pub full_snapshot: bool,
+}
+
+impl PositionDiff {
+    pub fn should_emit(&self, position: &PositionPayload) -> bool {
+        self.full_snapshot
+            || self
+                .changed
+                .contains(&(position.market_id.clone(), position.subaccount_id.clone()))
+    }
+}
+
+// Tracks the last positions snapshot so heartbeat snapshots can be turned into
+// deltas. Every `full_snapshot_every` snapshots the whole set is emitted so
+// downstream consumers can resynchronise.
+pub struct PositionDiffer {
+    fingerprints: HashMap<PositionKey, u64>,
+    snapshots_seen: u64,
+    full_snapshot_every: u64,
+}
+
+impl PositionDiffer {
+    pub fn new(full_snapshot_every: u64) -> Self {
+        PositionDiffer {
+            fingerprints: HashMap::new(),
+            snapshots_seen: 0,
+            full_snapshot_every,
+        }
+    }
+
+    pub fn diff(&mut self, positions: &[PositionPayload]) -> PositionDiff {
+        let full_snapshot = self.full_snapshot_every > 0
+            && self.snapshots_seen.is_multiple_of(self.full_snapshot_every);
+        self.snapshots_seen += 1;
+
+        let mut current = HashMap::with_capacity(positions.len());
+        let mut changed = HashSet::new();
+        for position in positions {
+            let key = (position.market_id.clone(), position.subaccount_id.clone());
+            let fingerprint = fingerprint(position);
+            if self.fingerprints.get(&key) != Some(&fingerprint) {
+                changed.insert(key.clone());
+            }
+            current.insert(key, fingerprint);
+        }
+
+        let closed = self
+            .fingerprints
+            .keys()
+            .filter(|key| !current.contains_key(*key))
+            .cloned()
+            .collect();
+        self.fingerprints = current;
+
+        PositionDiff {
+            changed,
+            closed,
+            full_snapshot,
+        }
+    }
+}
+
+fn fingerprint(position: &PositionPayload) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    position.is_long.hash(&mut hasher);
+    position.quantity.hash(&mut hasher);
+    position.entry_price.hash(&mut hasher);
+    position.margin.hash(&mut hasher);
+    position.cumulative_funding_entry.hash(&mut hasher);
+    hasher.finish()
+}
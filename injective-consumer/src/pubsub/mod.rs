@@ -1,14 +1,39 @@
+use crate::error::PubSubError;
+use crate::hooks::HookChain;
+use crate::models::fnv1a;
+use crate::redis_keys;
+use crate::routing::{RoutingConfig, Transport};
 use futures::future::join_all;
+use futures::{Stream, StreamExt};
 use log::{debug, error, info, warn};
 use redis::{aio::ConnectionManager, AsyncCommands, Client, RedisResult};
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
-use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, Mutex};
 use tokio::{task, time};
 
+// Ordering contract. Every event published to a channel carries `sequence`,
+// which counts up by one per event on that channel (per transport and channel
+// name), starting at 1 when the publisher starts. A channel is always served
+// by the same publisher worker, so events reach Redis in sequence order.
+// Subscribers can therefore tell:
+//   - a gap (sequence jumps ahead): events were dropped, by a full connection
+//     pool, a failed publish or a slow Redis subscriber;
+//   - a stale event (sequence at or below the last one seen): a duplicate or
+//     an event delivered out of order;
+//   - a restart (sequence back at 1): numbering started over.
+// Events dropped by hooks or claimed by another replica are never numbered.
+// Only one publisher should write a channel prefix: replicas that share one
+// through deduplication each number the events they publish, so subscribers
+// see gaps and stale events while both are active. GapDetector implements
+// these checks and EventSubscriber applies them while it reads.
+
 // Stream event types
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[repr(u8)]
@@ -20,6 +45,13 @@ pub enum EventType {
     OrderbookUpdate = 4,
     TradeUpdate = 5,
     SystemEvent = 6,
+    SummaryUpdate = 7,
+    AtRiskPositions = 8,
+    CandleClose = 9,
+    DepthSnapshot = 10,
+    MarkDivergence = 11,
+    FundingSettled = 12,
+    MarketSettled = 13,
 }
 
 // Stream event
@@ -28,13 +60,80 @@ pub struct StreamEvent {
     pub event_type: EventType,
     pub timestamp: u64,
     pub payload: serde_json::Value,
+    // Position on the channel it was published to, set by the publisher; 0
+    // before publishing and from publishers that predate sequence numbers
+    #[serde(default)]
+    pub sequence: u64,
+}
+
+impl StreamEvent {
+    pub fn new(event_type: EventType, timestamp: u64, payload: serde_json::Value) -> Self {
+        StreamEvent {
+            event_type,
+            timestamp,
+            payload,
+            sequence: 0,
+        }
+    }
+
+    // Stable hash of what the event says, ignoring its sequence number; equal
+    // for the same event built by different replicas
+    pub fn content_hash(&self) -> u64 {
+        fnv1a(
+            &serde_json::to_vec(&(self.event_type, self.timestamp, &self.payload))
+                .unwrap_or_default(),
+        )
+    }
+}
+
+// Events headed for one channel: a single event, or a batch published as one
+// array. Sequence numbers are assigned by the worker that publishes it.
+struct Outgoing {
+    transport: Transport,
+    channel: String,
+    events: Vec<StreamEvent>,
+    batch: bool,
+    // Keeps the service from counting as drained until the worker is done
+    _pending: Pending,
+}
+
+// Counts a publish in progress for as long as it is held
+struct Pending(Arc<AtomicUsize>);
+
+impl Pending {
+    fn start(count: &Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        Pending(count.clone())
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
+
 #[derive(Clone)]
 pub enum SerializationProtocol {
     Bincode,
     Json,
 }
 
+// Wire payload of one publish: the event itself, or an array for batches
+fn encode(
+    protocol: &SerializationProtocol,
+    events: &[StreamEvent],
+    batch: bool,
+) -> Result<Vec<u8>, PubSubError> {
+    let payload = match (protocol, batch) {
+        (SerializationProtocol::Bincode, true) => bincode::serialize(events)?,
+        (SerializationProtocol::Json, true) => serde_json::to_vec(events)?,
+        (SerializationProtocol::Bincode, false) => bincode::serialize(&events[0])?,
+        (SerializationProtocol::Json, false) => serde_json::to_vec(&events[0])?,
+    };
+    Ok(payload)
+}
+
 // Configuration for Redis PubSub
 #[derive(Clone)]
 pub struct RedisPubSubConfig {
@@ -44,8 +143,17 @@ pub struct RedisPubSubConfig {
     pub channel_prefix: String,
     pub protocol: SerializationProtocol,
     pub metrics_interval_secs: u64,
+    // Shared by the workers; each worker queues up to its share
     pub publisher_queue_size: usize,
+    // Each channel is always published by the same worker, keeping it in order
     pub publisher_workers: usize,
+    // Operator hooks applied to every event before it is published
+    pub hooks: Option<Arc<HookChain>>,
+    // Extra channel namespaces / transports selected per event
+    pub routing: Option<Arc<RoutingConfig>>,
+    // Claim every event in Redis for this long before publishing it, so
+    // replicas processing the same blocks publish it once (None disables)
+    pub dedup_ttl: Option<Duration>,
 }
 
 impl Default for RedisPubSubConfig {
@@ -59,6 +167,9 @@ impl Default for RedisPubSubConfig {
             metrics_interval_secs: 10,
             publisher_queue_size: 10000, // Large queue for handling spikes
             publisher_workers: 8,        // Multiple publisher workers
+            hooks: None,
+            routing: None,
+            dedup_ttl: None,
         }
     }
 }
@@ -71,6 +182,7 @@ pub struct PubSubMetrics {
     pub avg_publish_time_us: std::sync::atomic::AtomicU64,
     pub max_publish_time_us: std::sync::atomic::AtomicU64,
     pub queue_depth: std::sync::atomic::AtomicU64,
+    pub duplicates_skipped: std::sync::atomic::AtomicU64,
 }
 
 // The main Redis PubSub service optimized for Dragonfly
@@ -78,13 +190,17 @@ pub struct RedisPubSubService {
     config: RedisPubSubConfig,
     // Connection pool for publishers
     pub_connections: Arc<Mutex<Vec<ConnectionManager>>>,
-    // Queue for publishing messages
-    pub_queue: mpsc::Sender<(String, Vec<u8>)>,
+    // One queue per publisher worker, picked by channel
+    pub_queues: Vec<mpsc::Sender<Outgoing>>,
+    // Connection for publish claims, when deduplication is on
+    dedup: Option<ConnectionManager>,
     metrics: Arc<PubSubMetrics>,
+    // Events being claimed, queued or published
+    pending: Arc<AtomicUsize>,
 }
 
 impl RedisPubSubService {
-    pub async fn new(config: RedisPubSubConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+    pub async fn new(config: RedisPubSubConfig) -> Result<Self, PubSubError> {
         let client = Client::open(config.redis_url.clone())?;
 
         // Create connection pool for publishers
@@ -94,20 +210,30 @@ impl RedisPubSubService {
             connections.push(conn);
         }
 
-        // Create publishing queue
-        let (tx, rx) = mpsc::channel(config.publisher_queue_size);
+        // Create one publishing queue per worker
+        let workers = config.publisher_workers.max(1);
+        let queue_size = (config.publisher_queue_size / workers).max(1);
+        let (senders, receivers): (Vec<_>, Vec<_>) =
+            (0..workers).map(|_| mpsc::channel(queue_size)).unzip();
+
+        let dedup = match config.dedup_ttl {
+            Some(_) => Some(ConnectionManager::new(client.clone()).await?),
+            None => None,
+        };
 
         let metrics = Arc::new(PubSubMetrics::default());
 
         let service = RedisPubSubService {
             config: config.clone(),
             pub_connections: Arc::new(Mutex::new(connections)),
-            pub_queue: tx,
+            pub_queues: senders,
+            dedup,
             metrics: metrics.clone(),
+            pending: Arc::new(AtomicUsize::new(0)),
         };
 
         // Start publisher workers
-        service.spawn_publisher_workers(rx).await;
+        service.spawn_publisher_workers(receivers);
 
         // Start metrics reporter
         service.spawn_metrics_reporter();
@@ -115,35 +241,54 @@ impl RedisPubSubService {
         Ok(service)
     }
 
-    // Spawn workers to handle publishing messages
-    async fn spawn_publisher_workers(&self, rx: mpsc::Receiver<(String, Vec<u8>)>) {
+    // Spawn one worker per queue. A worker numbers the events of its channels
+    // and publishes them in the order they were queued.
+    fn spawn_publisher_workers(&self, receivers: Vec<mpsc::Receiver<Outgoing>>) {
         let connections = self.pub_connections.clone();
         let metrics = self.metrics.clone();
-        let worker_count = self.config.publisher_workers;
-
-        let rx = Arc::new(Mutex::new(rx));
+        let protocol = self.config.protocol.clone();
+        let stream_max_len = self
+            .config
+            .routing
+            .as_ref()
+            .map_or(RoutingConfig::default().stream_max_len, |routing| {
+                routing.stream_max_len
+            });
 
-        for i in 0..worker_count {
+        for (i, mut rx) in receivers.into_iter().enumerate() {
             let connections = connections.clone();
             let metrics = metrics.clone();
-            let rx = rx.clone();
+            let protocol = protocol.clone();
 
             task::spawn(async move {
                 info!("Starting Redis publisher worker #{}", i);
+                // Last sequence number used per channel this worker serves
+                let mut sequences: HashMap<(Transport, String), u64> = HashMap::new();
+
+                while let Some(outgoing) = rx.recv().await {
+                    let Outgoing {
+                        transport,
+                        channel,
+                        mut events,
+                        batch,
+                        _pending,
+                    } = outgoing;
+                    let sequence = sequences.entry((transport, channel.clone())).or_insert(0);
+                    for event in &mut events {
+                        *sequence += 1;
+                        event.sequence = *sequence;
+                    }
 
-                loop {
-                    let message = {
-                        let mut rx_lock = rx.lock().await;
-                        match rx_lock.recv().await {
-                            Some(msg) => msg,
-                            None => {
-                                debug!("Publisher queue closed, exiting worker #{}", i);
-                                break;
-                            }
+                    let payload = match encode(&protocol, &events, batch) {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            error!("Failed to serialize events for {}: {}", channel, e);
+                            metrics
+                                .publish_errors
+                                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                            continue;
                         }
                     };
-
-                    let (channel, payload) = message;
                     let start_time = Instant::now();
 
                     let conn_result = {
@@ -160,14 +305,40 @@ impl RedisPubSubService {
                     };
 
                     if let Some(mut conn) = conn_result {
-                        let result: RedisResult<()> = conn.publish(&channel, payload.clone()).await;
+                        let result: RedisResult<()> = match transport {
+                            Transport::PubSub => conn.publish(&channel, &payload).await,
+                            Transport::Stream => {
+                                redis::cmd("XADD")
+                                    .arg(&channel)
+                                    .arg("MAXLEN")
+                                    .arg("~")
+                                    .arg(stream_max_len)
+                                    .arg("*")
+                                    .arg("data")
+                                    .arg(&payload)
+                                    .query_async(&mut conn)
+                                    .await
+                            }
+                        };
 
                         {
                             let mut conn_guard = connections.lock().await;
                             conn_guard.push(conn);
                         }
 
+                        let prometheus = crate::metrics::consumer();
                         if result.is_ok() {
+                            let transport_label = match transport {
+                                Transport::PubSub => "pubsub",
+                                Transport::Stream => "stream",
+                            };
+                            prometheus
+                                .pubsub_published
+                                .with_label_values(&[transport_label])
+                                .inc();
+                            prometheus
+                                .pubsub_publish_seconds
+                                .observe(start_time.elapsed().as_secs_f64());
                             metrics
                                 .messages_published
                                 .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
@@ -195,16 +366,34 @@ impl RedisPubSubService {
                             }
                         } else if let Err(e) = result {
                             error!("Error publishing to Redis: {}", e);
+                            prometheus.pubsub_errors.inc();
                             metrics
                                 .publish_errors
                                 .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                         }
                     }
                 }
+                debug!("Publisher queue closed, exiting worker #{}", i);
             });
         }
     }
 
+    // Wait until every event handed to the service has been published or
+    // dropped. Returns false if some are still pending after the timeout.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Lets publishes spawned just before the call get started
+            time::sleep(Duration::from_millis(10)).await;
+            if self.pending.load(Ordering::SeqCst) == 0 {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+        }
+    }
+
     // Spawn a task to report metrics periodically
     fn spawn_metrics_reporter(&self) {
         let metrics = self.metrics.clone();
@@ -231,10 +420,13 @@ impl RedisPubSubService {
                 let queue = metrics
                     .queue_depth
                     .load(std::sync::atomic::Ordering::Relaxed);
+                let duplicates = metrics
+                    .duplicates_skipped
+                    .load(std::sync::atomic::Ordering::Relaxed);
 
                 info!(
-                    "Redis PubSub metrics: published={}, errors={}, avg_time={}µs, max_time={}µs, queue={}",
-                    published, errors, avg_us, max_us, queue
+                    "Redis PubSub metrics: published={}, errors={}, avg_time={}µs, max_time={}µs, queue={}, duplicates={}",
+                    published, errors, avg_us, max_us, queue, duplicates
                 );
             }
         });
@@ -242,133 +434,326 @@ impl RedisPubSubService {
 
     // Get a channel name based on event type
     pub fn get_channel_for_event(&self, event_type: EventType) -> String {
+        self.channel_in_namespace(&self.config.channel_prefix, event_type)
+    }
+
+    fn channel_in_namespace(&self, prefix: &str, event_type: EventType) -> String {
         if self.config.sharded_channels {
-            format!("{}:{:?}", self.config.channel_prefix, event_type)
+            format!("{}:{:?}", prefix, event_type)
         } else {
-            self.config.channel_prefix.clone()
+            prefix.to_string()
         }
     }
 
-    // High-performance publish method
-    pub async fn publish_event(
-        &self,
-        event: StreamEvent,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let channel = self.get_channel_for_event(event.event_type);
-
-        // Serialize based on protocol choice using a match on self.config.protocol.
-        let payload = match self.config.protocol {
-            SerializationProtocol::Bincode => bincode::serialize(&event)?,
-            SerializationProtocol::Json => serde_json::to_vec(&event)?,
+    // Every (transport, channel) an event should be delivered to
+    fn targets_for_event(&self, event: &StreamEvent) -> Vec<(Transport, String)> {
+        let default_target = (
+            Transport::PubSub,
+            self.get_channel_for_event(event.event_type),
+        );
+        let Some(routing) = &self.config.routing else {
+            return vec![default_target];
         };
 
-        // Update queue depth metric
+        let (routes, include_default) = routing.routes(event);
+        let mut targets: Vec<(Transport, String)> = routes
+            .into_iter()
+            .map(|route| {
+                (
+                    route.transport,
+                    self.channel_in_namespace(&route.channel_prefix, event.event_type),
+                )
+            })
+            .collect();
+        if include_default {
+            targets.push(default_target);
+        }
+        targets
+    }
+
+    // Queue of the worker that owns the channel
+    fn queue_for(&self, transport: Transport, channel: &str) -> &mpsc::Sender<Outgoing> {
+        let mut hasher = DefaultHasher::new();
+        (transport, channel).hash(&mut hasher);
+        &self.pub_queues[hasher.finish() as usize % self.pub_queues.len()]
+    }
+
+    async fn enqueue(&self, outgoing: Outgoing) -> Result<(), PubSubError> {
         self.metrics
             .queue_depth
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let result = self
+            .queue_for(outgoing.transport, &outgoing.channel)
+            .send(outgoing)
+            .await;
+        self.metrics
+            .queue_depth
+            .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
 
-        match self.pub_queue.send((channel, payload)).await {
-            Ok(_) => {
-                self.metrics
-                    .queue_depth
-                    .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
-                Ok(())
-            }
-            Err(e) => {
-                self.metrics
-                    .publish_errors
-                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                self.metrics
-                    .queue_depth
-                    .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
-                Err(format!("Failed to send to publishing queue: {}", e).into())
-            }
+        if let Err(e) = result {
+            self.metrics
+                .publish_errors
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            error!("Failed to send to publishing queue: {}", e);
+            return Err(PubSubError::QueueClosed);
         }
+        Ok(())
     }
 
-    // Batch publish method for higher throughput
-    pub async fn publish_events_batch(
-        &self,
-        events: Vec<StreamEvent>,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Keep the events no other replica has claimed yet. A claim is a SET NX
+    // marker on the event's content hash that expires after the dedup TTL.
+    // If Redis can't be asked every event is kept, since a duplicate is
+    // better than a lost event.
+    async fn unclaimed(&self, events: Vec<StreamEvent>) -> Vec<StreamEvent> {
+        let (Some(conn), Some(ttl)) = (&self.dedup, self.config.dedup_ttl) else {
+            return events;
+        };
         if events.is_empty() {
-            return Ok(());
+            return events;
         }
 
-        let mut channel_events: HashMap<String, Vec<StreamEvent>> = HashMap::new();
-        for event in events {
-            let channel = self.get_channel_for_event(event.event_type);
-            channel_events
-                .entry(channel)
-                .or_insert_with(Vec::new)
-                .push(event);
+        let mut pipe = redis::pipe();
+        for event in &events {
+            pipe.cmd("SET")
+                .arg(redis_keys::pubsub_dedup(event.content_hash()))
+                .arg(1)
+                .arg("NX")
+                .arg("EX")
+                .arg(ttl.as_secs().max(1));
         }
+        let claims: Vec<Option<String>> = match pipe.query_async(&mut conn.clone()).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                warn!(
+                    "Failed to claim events, publishing without deduplication: {}",
+                    e
+                );
+                return events;
+            }
+        };
 
-        let mut publish_futures = Vec::new();
-
-        for (channel, events) in channel_events {
-            let payload = match self.config.protocol {
-                SerializationProtocol::Bincode => bincode::serialize(&events)?,
-                SerializationProtocol::Json => serde_json::to_vec(&events)?,
-            };
-
+        let total = events.len();
+        let kept: Vec<StreamEvent> = events
+            .into_iter()
+            .zip(claims)
+            .filter_map(|(event, claim)| claim.map(|_| event))
+            .collect();
+        let duplicates = (total - kept.len()) as u64;
+        if duplicates > 0 {
             self.metrics
-                .queue_depth
-                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                .duplicates_skipped
+                .fetch_add(duplicates, std::sync::atomic::Ordering::Relaxed);
+            crate::metrics::consumer()
+                .pubsub_duplicates
+                .inc_by(duplicates);
+        }
+        kept
+    }
 
-            let queue = self.pub_queue.clone();
-            let metrics = self.metrics.clone();
-            let future = async move {
-                let result = queue.send((channel, payload)).await;
-                metrics
-                    .queue_depth
-                    .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
-                result
-            };
-            publish_futures.push(future);
+    // High-performance publish method. The event is serialized by the worker
+    // once it has its sequence number.
+    pub async fn publish_event(&self, mut event: StreamEvent) -> Result<(), PubSubError> {
+        let _pending = Pending::start(&self.pending);
+        if let Some(hooks) = &self.config.hooks {
+            if !hooks.apply_event(&mut event) {
+                return Ok(());
+            }
         }
+        let Some(event) = self.unclaimed(vec![event]).await.pop() else {
+            return Ok(());
+        };
 
-        let results = join_all(publish_futures).await;
+        for (transport, channel) in self.targets_for_event(&event) {
+            self.enqueue(Outgoing {
+                transport,
+                channel,
+                events: vec![event.clone()],
+                batch: false,
+                _pending: Pending::start(&self.pending),
+            })
+            .await?;
+        }
 
-        for result in results {
-            if result.is_err() {
-                self.metrics
-                    .publish_errors
-                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                return Err("Error publishing batch to Redis".into());
+        Ok(())
+    }
+
+    // Batch publish method for higher throughput
+    pub async fn publish_events_batch(&self, events: Vec<StreamEvent>) -> Result<(), PubSubError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let _pending = Pending::start(&self.pending);
+
+        let events = match &self.config.hooks {
+            Some(hooks) => events
+                .into_iter()
+                .filter_map(|mut event| hooks.apply_event(&mut event).then_some(event))
+                .collect(),
+            None => events,
+        };
+
+        let mut channel_events: HashMap<(Transport, String), Vec<StreamEvent>> = HashMap::new();
+        for event in self.unclaimed(events).await {
+            for target in self.targets_for_event(&event) {
+                channel_events
+                    .entry(target)
+                    .or_default()
+                    .push(event.clone());
             }
         }
 
+        let publish_futures = channel_events
+            .into_iter()
+            .map(|((transport, channel), events)| {
+                self.enqueue(Outgoing {
+                    transport,
+                    channel,
+                    events,
+                    batch: true,
+                    _pending: Pending::start(&self.pending),
+                })
+            });
+
+        for result in join_all(publish_futures).await {
+            result?;
+        }
+
         Ok(())
     }
 
-    // Helper methods to create common event types
-    pub fn create_market_update(&self, data: serde_json::Value) -> StreamEvent {
-        StreamEvent {
-            event_type: EventType::MarketUpdate,
-            timestamp: chrono::Utc::now().timestamp_millis() as u64,
-            payload: serde_json::json!(data),
-        }
+    // Helper methods to create common event types. They are stamped with the
+    // block time in milliseconds, so replicas build the same events.
+    pub fn create_market_update(&self, timestamp: u64, data: serde_json::Value) -> StreamEvent {
+        StreamEvent::new(EventType::MarketUpdate, timestamp, serde_json::json!(data))
     }
 
-    pub fn create_price_update(&self, market_id: &str, price: &str) -> StreamEvent {
-        StreamEvent {
-            event_type: EventType::PriceUpdate,
-            timestamp: chrono::Utc::now().timestamp_millis() as u64,
-            payload: serde_json::json!({
+    pub fn create_price_update(&self, timestamp: u64, market_id: &str, price: &str) -> StreamEvent {
+        StreamEvent::new(
+            EventType::PriceUpdate,
+            timestamp,
+            serde_json::json!({
                 "market_id": market_id,
                 "price": price
             }),
-        }
+        )
     }
 
-    pub fn create_liquidation_alert(&self, data: serde_json::Value) -> StreamEvent {
-        StreamEvent {
-            event_type: EventType::LiquidationAlert,
-            timestamp: chrono::Utc::now().timestamp_millis() as u64,
-            payload: serde_json::json!(
-                 data
-            ),
+    pub fn create_liquidation_alert(&self, timestamp: u64, data: serde_json::Value) -> StreamEvent {
+        StreamEvent::new(
+            EventType::LiquidationAlert,
+            timestamp,
+            serde_json::json!(data),
+        )
+    }
+}
+
+// What a received sequence number says about the channel it arrived on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceCheck {
+    // The next event, or the first one seen on the channel
+    InOrder,
+    // Events were dropped between the last one seen and this one
+    Gap { missed: u64 },
+    // At or below the last sequence seen: a duplicate or a reordered event
+    Stale { last: u64 },
+    // The publisher restarted and numbering began again at 1
+    Restarted,
+    // Published without a sequence number
+    Unsequenced,
+}
+
+// Tracks the last sequence number seen per channel
+#[derive(Debug, Default)]
+pub struct GapDetector {
+    last: HashMap<String, u64>,
+}
+
+impl GapDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Check an event's sequence against the channel it arrived on. Stale
+    // events don't move the channel back.
+    pub fn check(&mut self, channel: &str, sequence: u64) -> SequenceCheck {
+        if sequence == 0 {
+            return SequenceCheck::Unsequenced;
+        }
+        let Some(last) = self.last.get_mut(channel) else {
+            self.last.insert(channel.to_string(), sequence);
+            return SequenceCheck::InOrder;
+        };
+
+        let previous = *last;
+        if sequence == 1 && previous > 1 {
+            *last = sequence;
+            SequenceCheck::Restarted
+        } else if sequence <= previous {
+            SequenceCheck::Stale { last: previous }
+        } else {
+            *last = sequence;
+            if sequence == previous + 1 {
+                SequenceCheck::InOrder
+            } else {
+                SequenceCheck::Gap {
+                    missed: sequence - previous - 1,
+                }
+            }
         }
     }
 }
+
+// Payloads hold one JSON event, or an array from a batch publish. Bincode
+// payloads can't carry the JSON event bodies and are not supported.
+pub fn decode_events(payload: &[u8]) -> Result<Vec<StreamEvent>, serde_json::Error> {
+    match payload.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'[') => serde_json::from_slice(payload),
+        _ => serde_json::from_slice(payload).map(|event| vec![event]),
+    }
+}
+
+// The events of one pub/sub message, each with its sequence check
+#[derive(Debug)]
+pub struct ChannelEvents {
+    pub channel: String,
+    pub events: Vec<(StreamEvent, SequenceCheck)>,
+}
+
+// Reads every event channel under a prefix and checks sequences as it goes
+pub struct EventSubscriber {
+    messages: Pin<Box<dyn Stream<Item = redis::Msg> + Send>>,
+    gaps: GapDetector,
+}
+
+impl EventSubscriber {
+    // Unsharded publishers use the prefix itself, sharded ones prefix:EventType
+    pub async fn connect(redis_url: &str, channel_prefix: &str) -> Result<Self, PubSubError> {
+        let client = Client::open(redis_url)?;
+        let mut pubsub = client.get_async_pubsub().await?;
+        pubsub.subscribe(channel_prefix).await?;
+        pubsub.psubscribe(format!("{}:*", channel_prefix)).await?;
+        Ok(EventSubscriber {
+            messages: Box::pin(pubsub.into_on_message()),
+            gaps: GapDetector::new(),
+        })
+    }
+
+    // The next message's events, or None once the connection closes. A
+    // message that doesn't decode is returned as an error and skipped.
+    pub async fn next(&mut self) -> Option<Result<ChannelEvents, serde_json::Error>> {
+        let message = self.messages.next().await?;
+        let channel = message.get_channel_name().to_string();
+        let events = match decode_events(message.get_payload_bytes()) {
+            Ok(events) => events,
+            Err(e) => return Some(Err(e)),
+        };
+        let events = events
+            .into_iter()
+            .map(|event| {
+                let check = self.gaps.check(&channel, event.sequence);
+                (event, check)
+            })
+            .collect();
+        Some(Ok(ChannelEvents { channel, events }))
+    }
+}
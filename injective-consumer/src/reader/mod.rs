@@ -0,0 +1,634 @@
+use crate::candles::{Candle, Resolution};
+use crate::compute::distance_to_liquidation;
+use crate::correlation::CorrelationMatrix;
+use crate::error::StorageError;
+use crate::impact::{self, FillEstimate, Side};
+use crate::market_summary::HourBucket;
+use crate::mid_price::MidPricePoint;
+use crate::migration::legacy_market_fields;
+use crate::models::{
+    time, AddressSummary, AtRiskPosition, LiquidatablePosition, MarketData, MarketSummary,
+    PositionData, SubaccountTrade, TopOfBook,
+};
+use crate::orderbook::{BookLevel, L2Book};
+use crate::redis_keys;
+use crate::trade_history::{TradeCursor, TradeHistorySource};
+use crate::udf::{CandleSource, MarketSource};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use redis::{aio::ConnectionManager, Client};
+use std::collections::HashMap;
+use std::error::Error;
+
+// Read-side view of the Redis layout written by RedisProcessor, for applications
+// embedding the consumer that need to read back what it stored
+#[derive(Clone)]
+pub struct RedisReader {
+    connection: ConnectionManager,
+}
+
+impl RedisReader {
+    pub async fn new(redis_url: &str) -> Result<Self, StorageError> {
+        let client = Client::open(redis_url)?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(RedisReader { connection })
+    }
+
+    // Latest state of a derivative market, or None if it has not been stored yet.
+    // Falls back to the version 1 JSON layout for keyspaces not yet migrated.
+    pub async fn get_market(&self, market_id: &str) -> Result<Option<MarketData>, StorageError> {
+        let mut fields = self
+            .hgetall(&redis_keys::derivative_market(market_id))
+            .await?;
+        if fields.is_empty() {
+            fields = self.legacy_market(market_id).await?;
+        }
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(MarketData {
+            market_id: market_id.to_string(),
+            ticker: fields.get("ticker").cloned().unwrap_or_default(),
+            mark_price: parse_field(&fields, "mark_price"),
+            maintenance_margin_ratio: parse_field(&fields, "maintenance_margin_ratio"),
+            cumulative_funding: parse_field(&fields, "cumulative_funding"),
+            status: fields.get("status").cloned().unwrap_or_default(),
+            block_height: parse_field(&fields, "block_height"),
+            timestamp: parse_timestamp(&fields),
+        }))
+    }
+
+    // Every derivative market that has been stored. Markets whose hash has
+    // already been removed are skipped.
+    pub async fn get_markets(&self) -> Result<Vec<MarketData>, StorageError> {
+        let mut conn = self.connection.clone();
+        let market_ids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(redis_keys::derivative_markets())
+            .query_async(&mut conn)
+            .await?;
+
+        let mut markets = Vec::with_capacity(market_ids.len());
+        for market_id in &market_ids {
+            if let Some(market) = self.get_market(market_id).await? {
+                markets.push(market);
+            }
+        }
+
+        Ok(markets)
+    }
+
+    // Latest state of a position, or None if it has not been stored yet
+    pub async fn get_position(
+        &self,
+        market_id: &str,
+        subaccount_id: &str,
+    ) -> Result<Option<PositionData>, StorageError> {
+        let fields = self
+            .hgetall(&redis_keys::position(market_id, subaccount_id))
+            .await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(position_from_fields(
+            market_id,
+            subaccount_id,
+            &fields,
+        )))
+    }
+
+    // All positions currently flagged as liquidatable. Members whose position
+    // hash has already been removed are skipped.
+    pub async fn get_liquidatable_positions(&self) -> Result<Vec<PositionData>, StorageError> {
+        let mut conn = self.connection.clone();
+        let members: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(redis_keys::liquidatable_positions())
+            .query_async(&mut conn)
+            .await?;
+
+        let mut positions = Vec::with_capacity(members.len());
+        for member in &members {
+            let Some((market_id, subaccount_id)) = redis_keys::parse_liquidatable_member(member)
+            else {
+                continue;
+            };
+            if let Some(position) = self.get_position(market_id, subaccount_id).await? {
+                positions.push(position);
+            }
+        }
+
+        Ok(positions)
+    }
+
+    // Liquidatable positions with their notional and distance at the current
+    // mark, optionally of one market only. The distance stored with the
+    // position is used when there is one. Positions of markets without a mark
+    // price are skipped.
+    pub async fn get_liquidatable_details(
+        &self,
+        market_id: Option<&str>,
+    ) -> Result<Vec<LiquidatablePosition>, StorageError> {
+        let members = self.smembers(&redis_keys::liquidatable_positions()).await?;
+
+        let mut mark_prices: HashMap<String, Option<f64>> = HashMap::new();
+        let mut positions = Vec::with_capacity(members.len());
+        for member in &members {
+            let Some((member_market, subaccount_id)) =
+                redis_keys::parse_liquidatable_member(member)
+            else {
+                continue;
+            };
+            if market_id.is_some_and(|market_id| market_id != member_market) {
+                continue;
+            }
+
+            let mark_price = match mark_prices.get(member_market) {
+                Some(mark_price) => *mark_price,
+                None => {
+                    let mark_price = self
+                        .get_market(member_market)
+                        .await?
+                        .map(|market| market.mark_price)
+                        .filter(|mark_price| *mark_price > 0.0);
+                    mark_prices.insert(member_market.to_string(), mark_price);
+                    mark_price
+                }
+            };
+            let Some(mark_price) = mark_price else {
+                continue;
+            };
+
+            let fields = self
+                .hgetall(&redis_keys::position(member_market, subaccount_id))
+                .await?;
+            if fields.is_empty() {
+                continue;
+            }
+            let position = position_from_fields(member_market, subaccount_id, &fields);
+            let distance_pct = fields
+                .get("liquidation_distance")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_else(|| {
+                    distance_to_liquidation(
+                        position.is_long,
+                        position.liquidation_price,
+                        mark_price,
+                    )
+                });
+            positions.push(LiquidatablePosition {
+                notional: position.quantity * mark_price,
+                mark_price,
+                distance_pct,
+                position,
+            });
+        }
+
+        Ok(positions)
+    }
+
+    // The `limit` positions closest to liquidation across all markets, closest
+    // first. Positions already past their liquidation price come first.
+    pub async fn get_at_risk_positions(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<AtRiskPosition>, StorageError> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.connection.clone();
+        let ranked: Vec<(String, f64)> = redis::cmd("ZRANGE")
+            .arg(redis_keys::at_risk_positions())
+            .arg(0)
+            .arg(limit - 1)
+            .arg("WITHSCORES")
+            .query_async(&mut conn)
+            .await?;
+
+        let mut positions = Vec::with_capacity(ranked.len());
+        for (member, distance_pct) in ranked {
+            let Some((market_id, subaccount_id)) = redis_keys::parse_liquidatable_member(&member)
+            else {
+                continue;
+            };
+            if let Some(position) = self.get_position(market_id, subaccount_id).await? {
+                positions.push(AtRiskPosition {
+                    position,
+                    distance_pct,
+                });
+            }
+        }
+
+        Ok(positions)
+    }
+
+    // Best bid and ask of a derivative market, or None if no orderbook has been stored
+    pub async fn get_top_of_book(
+        &self,
+        market_id: &str,
+    ) -> Result<Option<TopOfBook>, StorageError> {
+        let fields = self
+            .hgetall(&redis_keys::derivative_orderbook(market_id))
+            .await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(TopOfBook {
+            market_id: market_id.to_string(),
+            best_bid: fields.get("best_bid").and_then(|v| v.parse().ok()),
+            best_ask: fields.get("best_ask").and_then(|v| v.parse().ok()),
+            best_bid_quantity: parse_field(&fields, "best_bid_quantity"),
+            best_ask_quantity: parse_field(&fields, "best_ask_quantity"),
+            block_height: parse_field(&fields, "block_height"),
+            timestamp: parse_timestamp(&fields),
+        }))
+    }
+
+    // Latest correlation matrix computed from candles of `resolution`
+    pub async fn get_correlation_matrix(
+        &self,
+        resolution: &str,
+    ) -> Result<Option<CorrelationMatrix>, StorageError> {
+        let mut conn = self.connection.clone();
+        let matrix: Option<String> = redis::cmd("GET")
+            .arg(redis_keys::correlation_matrix(resolution))
+            .query_async(&mut conn)
+            .await?;
+        Ok(matrix.map(|m| serde_json::from_str(&m)).transpose()?)
+    }
+
+    // Hourly candles of the last 24h, from the ring the market summary is
+    // computed from
+    async fn hourly_candles(&self, market_id: &str) -> Result<Vec<Candle>, StorageError> {
+        let ring = self
+            .hgetall(&redis_keys::summary_buckets(market_id))
+            .await?;
+        let mut candles: Vec<Candle> = ring
+            .values()
+            .filter_map(|v| serde_json::from_str::<HourBucket>(v).ok())
+            .map(|bucket| Candle {
+                time: bucket.start / 1000,
+                open: bucket.open,
+                high: bucket.high,
+                low: bucket.low,
+                close: bucket.close,
+                volume: bucket.volume,
+            })
+            .collect();
+        candles.sort_by_key(|c| c.time);
+        Ok(candles)
+    }
+
+    // Expected average price, slippage and levels consumed for a market order
+    // of `quantity` against the stored book. None if that side of the book is
+    // empty or no book has been stored.
+    pub async fn estimate_fill(
+        &self,
+        market_id: &str,
+        side: Side,
+        quantity: f64,
+    ) -> Result<Option<FillEstimate>, StorageError> {
+        let field = match side {
+            Side::Buy => "asks",
+            Side::Sell => "bids",
+        };
+        let mut conn = self.connection.clone();
+        let levels: Option<String> = redis::cmd("HGET")
+            .arg(redis_keys::derivative_depth(market_id))
+            .arg(field)
+            .query_async(&mut conn)
+            .await?;
+        let Some(levels) = levels else {
+            return Ok(None);
+        };
+
+        let levels: Vec<BookLevel> = serde_json::from_str(&levels)?;
+        Ok(impact::estimate_fill(market_id, &levels, side, quantity))
+    }
+
+    // The best `levels` price levels of each side of the stored book, merged
+    // into levels `tick` wide if one is given. None if no book has been stored.
+    pub async fn get_depth(
+        &self,
+        market_id: &str,
+        tick: Option<f64>,
+        levels: usize,
+    ) -> Result<Option<L2Book>, StorageError> {
+        let mut conn = self.connection.clone();
+        let (bids, asks): (Option<String>, Option<String>) = redis::cmd("HMGET")
+            .arg(redis_keys::derivative_depth(market_id))
+            .arg("bids")
+            .arg("asks")
+            .query_async(&mut conn)
+            .await?;
+        let (Some(bids), Some(asks)) = (bids, asks) else {
+            return Ok(None);
+        };
+
+        let book = L2Book {
+            bids: serde_json::from_str(&bids)?,
+            asks: serde_json::from_str(&asks)?,
+        };
+        let mut book = match tick {
+            Some(_) => book.regroup(tick),
+            None => book,
+        };
+        book.truncate(levels);
+        Ok(Some(book))
+    }
+
+    // Up to `limit` of the market's most recent mid prices, newest first.
+    // Entries that no longer parse are skipped.
+    pub async fn get_mid_prices(
+        &self,
+        market_id: &str,
+        limit: usize,
+    ) -> Result<Vec<MidPricePoint>, StorageError> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.connection.clone();
+        let entries: Vec<String> = redis::cmd("LRANGE")
+            .arg(redis_keys::mid_prices(market_id))
+            .arg(0)
+            .arg(limit as isize - 1)
+            .query_async(&mut conn)
+            .await?;
+        Ok(entries
+            .iter()
+            .filter_map(|entry| serde_json::from_str(entry).ok())
+            .collect())
+    }
+
+    // Rolling 24h summary of a derivative market, or None before its first trade
+    // or market update
+    pub async fn get_market_summary(
+        &self,
+        market_id: &str,
+    ) -> Result<Option<MarketSummary>, StorageError> {
+        let fields = self.hgetall(&redis_keys::market_summary(market_id)).await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(MarketSummary {
+            market_id: market_id.to_string(),
+            last_price: parse_field(&fields, "last_price"),
+            open_24h: parse_field(&fields, "open_24h"),
+            high_24h: parse_field(&fields, "high_24h"),
+            low_24h: parse_field(&fields, "low_24h"),
+            volume_24h: parse_field(&fields, "volume_24h"),
+            change_24h: parse_field(&fields, "change_24h"),
+            open_interest: parse_field(&fields, "open_interest"),
+            mark_price: parse_field(&fields, "mark_price"),
+            cumulative_funding: parse_field(&fields, "cumulative_funding"),
+            funding_apr: parse_field(&fields, "funding_apr"),
+            carry_bps_per_day: parse_field(&fields, "carry_bps_per_day"),
+            basis_bps: parse_field(&fields, "basis_bps"),
+            block_height: parse_field(&fields, "block_height"),
+            timestamp: parse_timestamp(&fields),
+        }))
+    }
+
+    // Summaries of every stored derivative market, the `/markets/summary` view
+    pub async fn get_market_summaries(&self) -> Result<Vec<MarketSummary>, StorageError> {
+        let mut conn = self.connection.clone();
+        let market_ids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(redis_keys::derivative_markets())
+            .query_async(&mut conn)
+            .await?;
+
+        let mut summaries = Vec::with_capacity(market_ids.len());
+        for market_id in &market_ids {
+            if let Some(summary) = self.get_market_summary(market_id).await? {
+                summaries.push(summary);
+            }
+        }
+
+        Ok(summaries)
+    }
+
+    // Position aggregates for an owner address (bech32 `inj1...`), or None if
+    // it holds no positions
+    pub async fn get_address_summary(
+        &self,
+        owner: &str,
+    ) -> Result<Option<AddressSummary>, StorageError> {
+        let fields = self.hgetall(&redis_keys::address(owner)).await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        let mut subaccount_ids = self
+            .smembers(&redis_keys::address_subaccounts(owner))
+            .await?;
+        subaccount_ids.sort();
+
+        Ok(Some(AddressSummary {
+            address: owner.to_string(),
+            subaccount_ids,
+            position_count: parse_field(&fields, "position_count"),
+            total_margin: parse_field(&fields, "total_margin"),
+            unrealized_pnl: parse_field(&fields, "unrealized_pnl"),
+            total_equity: parse_field(&fields, "total_equity"),
+            block_height: parse_field(&fields, "block_height"),
+            timestamp: parse_timestamp(&fields),
+        }))
+    }
+
+    // Every position held by any subaccount of an owner address
+    pub async fn get_address_positions(
+        &self,
+        owner: &str,
+    ) -> Result<Vec<PositionData>, StorageError> {
+        let mut positions = Vec::new();
+        for subaccount_id in self
+            .smembers(&redis_keys::address_subaccounts(owner))
+            .await?
+        {
+            positions.extend(self.get_subaccount_positions(&subaccount_id).await?);
+        }
+        Ok(positions)
+    }
+
+    // Every position held by one subaccount, across markets
+    pub async fn get_subaccount_positions(
+        &self,
+        subaccount_id: &str,
+    ) -> Result<Vec<PositionData>, StorageError> {
+        let market_ids = self
+            .smembers(&redis_keys::positions_by_subaccount(subaccount_id))
+            .await?;
+        let mut positions = Vec::with_capacity(market_ids.len());
+        for market_id in &market_ids {
+            if let Some(position) = self.get_position(market_id, subaccount_id).await? {
+                positions.push(position);
+            }
+        }
+        Ok(positions)
+    }
+
+    // The subaccount's cached recent trade sides, newest first. Entries that
+    // no longer parse are skipped.
+    pub async fn get_recent_subaccount_trades(
+        &self,
+        subaccount_id: &str,
+    ) -> Result<Vec<SubaccountTrade>, StorageError> {
+        let mut conn = self.connection.clone();
+        let entries: Vec<String> = redis::cmd("LRANGE")
+            .arg(redis_keys::subaccount_trades(subaccount_id))
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut conn)
+            .await?;
+
+        // Sides of one block are pushed in block order; sort them like the
+        // ScyllaDB table so cursors mean the same in both
+        let mut trades: Vec<SubaccountTrade> = entries
+            .iter()
+            .filter_map(|entry| serde_json::from_str(entry).ok())
+            .collect();
+        trades.sort_by_key(|trade| std::cmp::Reverse(TradeCursor::of(trade)));
+        Ok(trades)
+    }
+
+    async fn legacy_market(
+        &self,
+        market_id: &str,
+    ) -> Result<HashMap<String, String>, StorageError> {
+        let mut conn = self.connection.clone();
+        let document: Option<String> = redis::cmd("GET")
+            .arg(redis_keys::legacy_market(market_id))
+            .query_async(&mut conn)
+            .await?;
+
+        let Some(document) = document else {
+            return Ok(HashMap::new());
+        };
+        let value: serde_json::Value = serde_json::from_str(&document)?;
+        Ok(legacy_market_fields(&value)
+            .into_iter()
+            .map(|(field, value)| (field.to_string(), value))
+            .collect())
+    }
+
+    async fn smembers(&self, key: &str) -> Result<Vec<String>, StorageError> {
+        let mut conn = self.connection.clone();
+        let members: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(key)
+            .query_async(&mut conn)
+            .await?;
+        Ok(members)
+    }
+
+    async fn hgetall(&self, key: &str) -> Result<HashMap<String, String>, StorageError> {
+        let mut conn = self.connection.clone();
+        let fields: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(key)
+            .query_async(&mut conn)
+            .await?;
+        Ok(fields)
+    }
+}
+
+// Redis only keeps the hourly buckets behind the 24h summaries, so it can
+// serve 1h candles for the last day and nothing else
+#[async_trait]
+impl CandleSource for RedisReader {
+    async fn candles(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Candle>, Box<dyn Error + Send + Sync>> {
+        if resolution != Resolution::OneHour {
+            return Err(
+                format!("Redis only stores 1h candles, not {}", resolution.as_str()).into(),
+            );
+        }
+        let mut candles = self.hourly_candles(market_id).await?;
+        candles.retain(|c| c.time >= from && c.time <= to);
+        Ok(candles)
+    }
+
+    async fn candle_before(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        before: i64,
+    ) -> Result<Option<Candle>, Box<dyn Error + Send + Sync>> {
+        if resolution != Resolution::OneHour {
+            return Ok(None);
+        }
+        let candles = self.hourly_candles(market_id).await?;
+        Ok(candles.into_iter().rev().find(|c| c.time < before))
+    }
+}
+
+#[async_trait]
+impl MarketSource for RedisReader {
+    async fn market(
+        &self,
+        market_id: &str,
+    ) -> Result<Option<MarketData>, Box<dyn Error + Send + Sync>> {
+        Ok(self.get_market(market_id).await?)
+    }
+
+    async fn markets(&self) -> Result<Vec<MarketData>, Box<dyn Error + Send + Sync>> {
+        Ok(self.get_markets().await?)
+    }
+}
+
+// The list is capped, so pages past the oldest cached trade come back short;
+// wrap the reader in a TieredTradeHistory to continue from ScyllaDB
+#[async_trait]
+impl TradeHistorySource for RedisReader {
+    async fn subaccount_trades(
+        &self,
+        subaccount_id: &str,
+        before: Option<&TradeCursor>,
+        limit: usize,
+    ) -> Result<Vec<SubaccountTrade>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .get_recent_subaccount_trades(subaccount_id)
+            .await?
+            .into_iter()
+            .filter(|trade| before.is_none_or(|cursor| cursor.precedes(trade)))
+            .take(limit)
+            .collect())
+    }
+}
+
+// Parse a hash field, falling back to the type's default when missing or malformed
+fn position_from_fields(
+    market_id: &str,
+    subaccount_id: &str,
+    fields: &HashMap<String, String>,
+) -> PositionData {
+    PositionData {
+        market_id: market_id.to_string(),
+        subaccount_id: subaccount_id.to_string(),
+        is_long: parse_field(fields, "is_long"),
+        quantity: parse_field(fields, "quantity"),
+        entry_price: parse_field(fields, "entry_price"),
+        margin: parse_field(fields, "margin"),
+        cumulative_funding_entry: parse_field(fields, "cumulative_funding_entry"),
+        liquidation_price: parse_field(fields, "liquidation_price"),
+        is_liquidatable: parse_field(fields, "is_liquidatable"),
+        block_height: parse_field(fields, "block_height"),
+        timestamp: parse_timestamp(fields),
+    }
+}
+
+fn parse_field<T: std::str::FromStr + Default>(fields: &HashMap<String, String>, name: &str) -> T {
+    fields
+        .get(name)
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_default()
+}
+
+fn parse_timestamp(fields: &HashMap<String, String>) -> DateTime<Utc> {
+    time::to_datetime(parse_field(fields, "timestamp"))
+}
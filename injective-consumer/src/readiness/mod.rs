@@ -0,0 +1,135 @@
+use async_trait::async_trait;
+use log::{info, warn};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "redis")]
+use crate::redis_keys;
+#[cfg(feature = "redis")]
+use redis::aio::ConnectionManager;
+
+// Boot order between the consumers. A processor declares what has to be in
+// place before it can apply messages (MessageProcessor::prerequisites), and
+// its Kafka consumer holds back until a ReadinessGate finds all of it there.
+// The gate is asked again while consuming, so losing the cache (a flushed or
+// failed-over Redis) pauses the dependent consumers until the market
+// preloader has loaded the markets again.
+
+// How often an unmet prerequisite is checked again
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// How often a running consumer makes sure its prerequisites still hold
+const RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Prerequisite {
+    // The market preloader has stored the derivative markets: markets_ready
+    // is set and the market set isn't empty
+    MarketsReady,
+}
+
+impl Prerequisite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Prerequisite::MarketsReady => "markets_ready",
+        }
+    }
+}
+
+// Answers whether a prerequisite is currently met
+#[async_trait]
+pub trait ReadinessCheck: Send + Sync {
+    async fn is_met(
+        &self,
+        prerequisite: Prerequisite,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>>;
+}
+
+// Prerequisites as the market preloader records them in Redis
+#[cfg(feature = "redis")]
+pub struct RedisReadiness {
+    connection: ConnectionManager,
+}
+
+#[cfg(feature = "redis")]
+impl RedisReadiness {
+    pub async fn new(redis_url: &str) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(redis_url)?;
+        Ok(RedisReadiness {
+            connection: ConnectionManager::new(client).await?,
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl ReadinessCheck for RedisReadiness {
+    async fn is_met(
+        &self,
+        prerequisite: Prerequisite,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        match prerequisite {
+            Prerequisite::MarketsReady => {
+                // The flag alone survives a partial loss of the markets, so
+                // the markets themselves have to be there too
+                let (ready, markets): (Option<String>, u64) = redis::pipe()
+                    .get(redis_keys::markets_ready())
+                    .scard(redis_keys::derivative_markets())
+                    .query_async(&mut self.connection.clone())
+                    .await?;
+                Ok(ready.as_deref() == Some("true") && markets > 0)
+            }
+        }
+    }
+}
+
+pub struct ReadinessGate {
+    check: Arc<dyn ReadinessCheck>,
+}
+
+impl ReadinessGate {
+    pub fn new(check: Arc<dyn ReadinessCheck>) -> Self {
+        ReadinessGate { check }
+    }
+
+    pub fn recheck_interval(&self) -> Duration {
+        RECHECK_INTERVAL
+    }
+
+    // Prerequisites not met right now. A check that fails counts as unmet,
+    // since whatever it depends on is unreachable too.
+    pub async fn unmet(&self, prerequisites: &[Prerequisite]) -> Vec<Prerequisite> {
+        let mut unmet = Vec::new();
+        for prerequisite in prerequisites {
+            match self.check.is_met(*prerequisite).await {
+                Ok(true) => {}
+                Ok(false) => unmet.push(*prerequisite),
+                Err(e) => {
+                    warn!("Failed to check {}: {}", prerequisite.as_str(), e);
+                    unmet.push(*prerequisite);
+                }
+            }
+        }
+        unmet
+    }
+
+    // Return once every prerequisite is met, logging what `name` waits for
+    pub async fn wait(&self, name: &str, prerequisites: &[Prerequisite]) {
+        let mut waiting = false;
+        loop {
+            let unmet = self.unmet(prerequisites).await;
+            if unmet.is_empty() {
+                if waiting {
+                    info!("{}: Prerequisites met, resuming", name);
+                }
+                return;
+            }
+            if !waiting {
+                let names: Vec<&str> = unmet.iter().map(Prerequisite::as_str).collect();
+                info!("{}: Waiting for {}", name, names.join(", "));
+                waiting = true;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::redis_keys;
+use log::{debug, error, info};
+use redis::{aio::ConnectionManager, Client};
+use std::error::Error;
+use std::time::Duration;
+use tokio::{task, time};
+
+// Configuration for the Redis index set reaper
+#[derive(Clone)]
+pub struct ReaperConfig {
+    pub redis_url: String,
+    pub interval_secs: u64,
+    // Number of keys / set members fetched per SCAN / SSCAN round trip
+    pub scan_count: usize,
+    // Pause between batches so the reaper never monopolises Redis
+    pub batch_pause_ms: u64,
+}
+
+impl Default for ReaperConfig {
+    fn default() -> Self {
+        ReaperConfig {
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            interval_secs: 300,
+            scan_count: 200,
+            batch_pause_ms: 50,
+        }
+    }
+}
+
+// An index set and how its members map back to the keys they reference. Sets
+// are named by their key segments, so they follow the installed key schema.
+struct IndexSet {
+    segments: &'static [&'static str],
+    wildcard: bool,
+    member_key: fn(&str, &str) -> String,
+}
+
+impl IndexSet {
+    // What the set keys start with; the whole key when there is only one
+    fn prefix(&self) -> String {
+        if self.wildcard {
+            redis_keys::key_prefix(self.segments)
+        } else {
+            redis_keys::schema().key(self.segments)
+        }
+    }
+}
+
+const INDEX_SETS: &[IndexSet] = &[
+    // positions:market:{market_id} -> subaccount ids
+    IndexSet {
+        segments: &["positions", "market"],
+        wildcard: true,
+        member_key: |market_id, subaccount_id| redis_keys::position(market_id, subaccount_id),
+    },
+    // positions:subaccount:{subaccount_id} -> market ids
+    IndexSet {
+        segments: &["positions", "subaccount"],
+        wildcard: true,
+        member_key: |subaccount_id, market_id| redis_keys::position(market_id, subaccount_id),
+    },
+    // liquidatable_positions -> market_id:subaccount_id
+    IndexSet {
+        segments: &["liquidatable_positions"],
+        wildcard: false,
+        member_key: |_, member| {
+            // A malformed member references no position and is reaped
+            let (market_id, subaccount_id) =
+                redis_keys::parse_liquidatable_member(member).unwrap_or((member, ""));
+            redis_keys::position(market_id, subaccount_id)
+        },
+    },
+    // markets:derivative -> market ids
+    IndexSet {
+        segments: &["markets", "derivative"],
+        wildcard: false,
+        member_key: |_, market_id| redis_keys::derivative_market(market_id),
+    },
+];
+
+// Periodically removes index set members whose referenced keys no longer exist
+pub struct IndexReaper {
+    config: ReaperConfig,
+    connection: ConnectionManager,
+}
+
+impl IndexReaper {
+    pub async fn new(config: ReaperConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if config.interval_secs == 0 {
+            return Err("reaper interval_secs must be above 0".into());
+        }
+        let client = Client::open(config.redis_url.clone())?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(IndexReaper { config, connection })
+    }
+
+    // Spawn the reaper loop in the background
+    pub fn spawn(self) -> task::JoinHandle<()> {
+        task::spawn(async move {
+            let mut interval_timer = time::interval(Duration::from_secs(self.config.interval_secs));
+            let mut connection = self.connection.clone();
+
+            loop {
+                interval_timer.tick().await;
+
+                match self.reap(&mut connection).await {
+                    Ok(removed) => {
+                        info!(
+                            "Redis index reaper pass complete: removed {} orphans",
+                            removed
+                        )
+                    }
+                    Err(e) => error!("Redis index reaper pass failed: {}", e),
+                }
+            }
+        })
+    }
+
+    // Run one full pass over all index sets, returning the number of members removed
+    pub async fn reap(
+        &self,
+        connection: &mut ConnectionManager,
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let mut removed = 0;
+
+        for index in INDEX_SETS {
+            let prefix = index.prefix();
+            let pattern = if index.wildcard {
+                format!("{}*", prefix)
+            } else {
+                prefix.clone()
+            };
+            let mut cursor: u64 = 0;
+            loop {
+                let (next_cursor, sets): (u64, Vec<String>) = redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(&pattern)
+                    .arg("COUNT")
+                    .arg(self.config.scan_count)
+                    .query_async(connection)
+                    .await?;
+
+                for set in sets {
+                    removed += self.reap_set(connection, &set, &prefix, index).await?;
+                }
+
+                cursor = next_cursor;
+                if cursor == 0 {
+                    break;
+                }
+                time::sleep(Duration::from_millis(self.config.batch_pause_ms)).await;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    // Validate the members of a single set against their referenced keys
+    async fn reap_set(
+        &self,
+        connection: &mut ConnectionManager,
+        set: &str,
+        prefix: &str,
+        index: &IndexSet,
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let mut removed = 0;
+        let mut cursor: u64 = 0;
+
+        loop {
+            let (next_cursor, members): (u64, Vec<String>) = redis::cmd("SSCAN")
+                .arg(set)
+                .arg(cursor)
+                .arg("COUNT")
+                .arg(self.config.scan_count)
+                .query_async(connection)
+                .await?;
+
+            if !members.is_empty() {
+                let mut pipe = redis::pipe();
+                for member in &members {
+                    // The set's own id is whatever follows the prefix
+                    let set_id = set.strip_prefix(prefix).unwrap_or_default();
+                    pipe.exists((index.member_key)(set_id, member));
+                }
+                let exists: Vec<bool> = pipe.query_async(connection).await?;
+
+                let orphans: Vec<&String> = members
+                    .iter()
+                    .zip(exists)
+                    .filter(|(_, exists)| !exists)
+                    .map(|(member, _)| member)
+                    .collect();
+
+                if !orphans.is_empty() {
+                    debug!("Removing {} orphaned members from {}", orphans.len(), set);
+                    let _: () = redis::cmd("SREM")
+                        .arg(set)
+                        .arg(&orphans)
+                        .query_async(connection)
+                        .await?;
+                    removed += orphans.len() as u64;
+                }
+            }
+
+            cursor = next_cursor;
+            if cursor == 0 {
+                break;
+            }
+            time::sleep(Duration::from_millis(self.config.batch_pause_ms)).await;
+        }
+
+        Ok(removed)
+    }
+}
@@ -1,59 +1,311 @@
-use crate::compute::{calculate_liquidation_price, is_liquidatable};
+use crate::address;
+use crate::compute::{calculate_margin_ratio, calculate_unrealized_pnl, distance_to_liquidation};
+use crate::config::{DepthConfig, MidPriceConfig};
 use crate::consumer::MessageProcessor;
+use crate::dual_write::MirroredConnection;
+use crate::error::{IndexerError, StorageError};
+use crate::expiry::{self, MarketSettlement};
+use crate::funding::{self, FundingPoint, FundingSettlement, FundingSettlementTracker};
+use crate::market_summary::{self, HourBucket};
+use crate::mid_price::{DivergenceAlert, DivergenceMonitor, MidPricePoint};
+use crate::models::time::{self, HOUR_MILLIS};
 use crate::models::{
-    DerivativeMarketPayload, KafkaMessage, KafkaPayload, MessageType, PositionPayload,
+    DerivativeMarketPayload, DerivativeTradePayload, FullLimitOrderbookPayload, KafkaMessage,
+    KafkaPayload, MarketData, MarketType, MessageType, OraclePricePayload, OrderbookPayload,
+    PositionData, PositionPayload, PositionSource, SpotMarketPayload, SpotTradePayload,
+    SubaccountTrade, TopOfBook,
 };
+use crate::orderbook::{self, BookSnapshotSource, BookTracker, DeltaOutcome};
+use crate::position_diff::{PositionDiff, PositionDiffer};
 use crate::pubsub::{EventType, RedisPubSubService, StreamEvent};
+use crate::readiness::Prerequisite;
+use crate::redis_keys;
+use crate::scaling::{self, CHAIN_DECIMAL, PRICE_DECIMAL};
+use crate::storage::{self, RedisStateStore, StateStore};
+use crate::volatility::VolatilityTracker;
+use crate::window::{WindowSpec, WindowedAggregator};
 use async_trait::async_trait;
-use log::{error, info, warn};
-use redis::{Client, Commands, Connection};
-use std::collections::HashSet;
+use futures::future::join_all;
+use log::{debug, error, info, warn};
+use redis::aio::{ConnectionLike, ConnectionManager};
+use redis::{AsyncCommands, Client, Pipeline};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify};
+
+// Keep a market in the reverse index of its oracle symbols, moving it if its
+// oracle changed since it was last stored
+pub(crate) async fn index_oracle_symbols<C: ConnectionLike + Send>(
+    conn: &mut C,
+    market_id: &str,
+    oracle_base: &str,
+    oracle_quote: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (old_base, old_quote): (Option<String>, Option<String>) = redis::cmd("HMGET")
+        .arg(redis_keys::derivative_market(market_id))
+        .arg("oracle_base")
+        .arg("oracle_quote")
+        .query_async(conn)
+        .await?;
+
+    let mut pipe = redis::pipe();
+    queue_oracle_index(
+        &mut pipe,
+        market_id,
+        [old_base, old_quote],
+        oracle_base,
+        oracle_quote,
+    );
+    pipe.query_async::<()>(conn).await?;
+    Ok(())
+}
+
+// Queue the oracle index writes for a market whose stored symbols were `old`
+fn queue_oracle_index(
+    pipe: &mut Pipeline,
+    market_id: &str,
+    old: [Option<String>; 2],
+    oracle_base: &str,
+    oracle_quote: &str,
+) {
+    for old in old.into_iter().flatten() {
+        if old != oracle_base && old != oracle_quote {
+            pipe.srem(redis_keys::oracle_markets(&old), market_id)
+                .ignore();
+        }
+    }
+    pipe.hset_multiple(
+        redis_keys::derivative_market(market_id),
+        &[("oracle_base", oracle_base), ("oracle_quote", oracle_quote)],
+    )
+    .ignore()
+    .sadd(redis_keys::oracle_markets(oracle_base), market_id)
+    .ignore()
+    .sadd(redis_keys::oracle_markets(oracle_quote), market_id)
+    .ignore();
+}
+
+// Funding fields of a market summary: the reference funding point, its time
+// and the APR it produced
+type StoredFunding = (Option<f64>, Option<i64>, Option<f64>);
+
+// Unrealized PnL and margin ratio of a position at the mark price of its
+// market, both 0 without a mark price
+fn position_health(state: &PositionData, market: &MarketData) -> (f64, f64) {
+    if market.mark_price <= 0.0 {
+        return (0.0, 0.0);
+    }
+    let unrealized_pnl = calculate_unrealized_pnl(
+        state.is_long,
+        state.entry_price,
+        market.mark_price,
+        state.quantity,
+    );
+    let margin_ratio = calculate_margin_ratio(
+        state.is_long,
+        state.entry_price,
+        market.mark_price,
+        state.quantity,
+        state.margin,
+        market.cumulative_funding,
+        state.cumulative_funding_entry,
+    );
+    (unrealized_pnl, margin_ratio)
+}
 
-// Enum to represent different processing phases
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum ProcessingPhase {
-    Markets,
-    Others,
+// Summary fields to write for a market update. The funding APR and carry are
+// refreshed whenever cumulative funding has moved since the last funding
+// payment seen; the returned APR holds between payments. The mark price and
+// cumulative funding are always written.
+fn funding_summary_fields(
+    (stored_funding, stored_time, stored_apr): StoredFunding,
+    cumulative_funding: f64,
+    mark_price: f64,
+    timestamp: u64,
+) -> (Vec<(&'static str, String)>, f64) {
+    let current = FundingPoint {
+        cumulative_funding,
+        time_millis: time::to_millis(timestamp as i64),
+    };
+    let metrics = match (stored_funding, stored_time) {
+        (Some(cumulative_funding), Some(time_millis)) => funding::funding_metrics(
+            FundingPoint {
+                cumulative_funding,
+                time_millis,
+            },
+            current,
+            mark_price,
+        ),
+        _ => None,
+    };
+    let mut fields = vec![
+        ("mark_price", mark_price.to_string()),
+        ("cumulative_funding", cumulative_funding.to_string()),
+    ];
+    // Only a payment moves the reference point, so APRs cover whole funding intervals
+    if stored_funding != Some(cumulative_funding) {
+        fields.push(("funding_point_cumulative", cumulative_funding.to_string()));
+        fields.push(("funding_point_time", current.time_millis.to_string()));
+    }
+    let funding_apr = match metrics {
+        Some(metrics) => {
+            fields.push(("funding_apr", metrics.funding_apr.to_string()));
+            fields.push(("carry_bps_per_day", metrics.carry_bps_per_day.to_string()));
+            metrics.funding_apr
+        }
+        None => stored_apr.unwrap_or(0.0),
+    };
+    (fields, funding_apr)
 }
-const PRICE_DECIMAL: f64 = 1e24;
-const CHAIN_DECIMAL: f64 = 1e18;
 
+// Each method clones the multiplexed connection instead of locking a shared
+// one, and sends the writes of one update as a single pipeline
 pub struct RedisProcessor {
-    _client: Client,
-    connection: Arc<Mutex<Connection>>,
+    connection: MirroredConnection,
     pubsub: Option<Arc<RedisPubSubService>>,
-    // Processing phase tracker
-    phase: Arc<Mutex<ProcessingPhase>>,
-    // Queue for non-market messages
-    deferred_messages: Arc<Mutex<Vec<KafkaMessage>>>,
-    // Track market IDs we need to process
-    market_ids: Arc<Mutex<HashSet<String>>>,
+    // Turns position snapshots into deltas when enabled
+    position_differ: Option<Arc<Mutex<PositionDiffer>>>,
+    // Recent trades kept per spot market; spot trades are skipped when None
+    spot_recent_trades: Option<usize>,
+    // Recent trade sides kept per subaccount; not cached when None
+    subaccount_recent_trades: Option<usize>,
+    // Minute closes of taker trades per derivative market
+    volatility: Arc<Mutex<VolatilityTracker>>,
+    // Hourly buckets of the 24h summaries still open, keyed by ring key
+    summary_windows: Mutex<WindowedAggregator<HourBucket>>,
+    // Latest market, position and book state; Redis-only indexes and
+    // aggregates are still written through `connection`
+    state: Arc<dyn StateStore>,
+    // Tick sizes and publishing of the stored L2 levels
+    depth: DepthConfig,
+    // Mid-price series kept per market and the divergence alert threshold
+    mid_prices: MidPriceConfig,
+    // Markets whose mark is past the divergence threshold
+    divergence: Mutex<DivergenceMonitor>,
+    // Derivative books kept from stream deltas
+    live_books: Mutex<BookTracker>,
+    // Where live books are resnapshotted from; they aren't kept when None
+    book_snapshots: Option<Arc<dyn BookSnapshotSource>>,
+    // How long a liquidation alert claim blocks a replay from alerting the
+    // same position at the same block again; unclaimed when None
+    alert_claim_ttl: Option<Duration>,
+    // Last cumulative funding and funding interval seen per market
+    funding_settlements: Mutex<FundingSettlementTracker>,
+    // Woken when a market settles funding, to recompute every liquidation
+    recompute_trigger: Option<Arc<Notify>>,
 }
 
 impl RedisProcessor {
-    pub fn new(redis_url: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
-        let client = Client::open(redis_url)?;
-        let connection = client.get_connection()?;
+    pub async fn new(redis_url: &str) -> Result<Self, StorageError> {
+        let connection =
+            MirroredConnection::new(ConnectionManager::new(Client::open(redis_url)?).await?);
 
         Ok(RedisProcessor {
-            _client: client,
-            connection: Arc::new(Mutex::new(connection)),
+            state: Arc::new(RedisStateStore::new(connection.clone())),
+            connection,
             pubsub: None,
-            phase: Arc::new(Mutex::new(ProcessingPhase::Markets)),
-            deferred_messages: Arc::new(Mutex::new(Vec::new())),
-            market_ids: Arc::new(Mutex::new(HashSet::new())),
+            position_differ: None,
+            spot_recent_trades: None,
+            subaccount_recent_trades: None,
+            volatility: Arc::new(Mutex::new(VolatilityTracker::new())),
+            summary_windows: Mutex::new(WindowedAggregator::new(WindowSpec::tumbling(
+                Duration::from_millis(HOUR_MILLIS as u64),
+            ))),
+            depth: DepthConfig::default(),
+            mid_prices: MidPriceConfig::default(),
+            divergence: Mutex::new(DivergenceMonitor::new(
+                MidPriceConfig::default().divergence_alert_bps,
+            )),
+            live_books: Mutex::new(BookTracker::new(PRICE_DECIMAL, CHAIN_DECIMAL)),
+            book_snapshots: None,
+            alert_claim_ttl: None,
+            funding_settlements: Mutex::new(FundingSettlementTracker::new()),
+            recompute_trigger: None,
         })
     }
 
+    // Keep market, position and book state in another store instead of Redis
+    pub fn with_state_store(mut self, state: Arc<dyn StateStore>) -> Self {
+        self.state = state;
+        self
+    }
+
+    // Mirror all writes to a secondary Redis, used while migrating between clusters
+    pub async fn with_secondary(self, secondary_url: &str) -> Result<Self, StorageError> {
+        let secondary = ConnectionManager::new(Client::open(secondary_url)?).await?;
+        self.connection.set_secondary(secondary);
+        Ok(self)
+    }
+
+    // Alert a liquidatable position once per block, even across replays
+    pub fn with_alert_dedup(mut self, ttl: Duration) -> Self {
+        self.alert_claim_ttl = Some(ttl);
+        self
+    }
+
+    // Wake the liquidation recompute whenever a market settles funding
+    pub fn with_recompute_trigger(mut self, trigger: Arc<Notify>) -> Self {
+        self.recompute_trigger = Some(trigger);
+        self
+    }
+
     // Add a method to set the PubSub service
     pub fn with_pubsub(mut self, pubsub: Arc<RedisPubSubService>) -> Self {
         self.pubsub = Some(pubsub);
         self
     }
 
+    // Publish only changed positions, with a full snapshot every N position messages
+    pub fn with_position_diff(mut self, full_snapshot_every: u64) -> Self {
+        self.position_differ = Some(Arc::new(Mutex::new(PositionDiffer::new(
+            full_snapshot_every,
+        ))));
+        self
+    }
+
+    // Cache, aggregate and publish spot trades
+    pub fn with_spot_trades(mut self, recent_trades: usize) -> Self {
+        self.spot_recent_trades = Some(recent_trades);
+        self
+    }
+
+    // Cache each subaccount's most recent spot and derivative trade sides
+    pub fn with_subaccount_trades(mut self, recent_trades: usize) -> Self {
+        self.subaccount_recent_trades = Some(recent_trades);
+        self
+    }
+
+    // Aggregate stored depth at the configured tick sizes, and publish it
+    pub fn with_depth(mut self, depth: DepthConfig) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    // Keep derivative books from the stream's deltas, starting each market
+    // over from `snapshots` when deltas go missing
+    pub fn with_live_books(mut self, snapshots: Arc<dyn BookSnapshotSource>) -> Self {
+        self.book_snapshots = Some(snapshots);
+        self
+    }
+
+    // Keep recent mid prices per market and alert when the mark diverges
+    pub fn with_mid_prices(mut self, mid_prices: MidPriceConfig) -> Self {
+        self.divergence = Mutex::new(DivergenceMonitor::new(mid_prices.divergence_alert_bps));
+        self.mid_prices = mid_prices;
+        self
+    }
+
+    // Keep summary hours open this long after they end, for trades from
+    // blocks that arrive late
+    pub fn with_allowed_lateness(mut self, allowed_lateness: Duration) -> Self {
+        self.summary_windows
+            .get_mut()
+            .set_allowed_lateness(allowed_lateness);
+        self
+    }
+
     async fn process_derivative_market(
         &self,
         market: &DerivativeMarketPayload,
@@ -61,71 +313,74 @@ impl RedisProcessor {
         timestamp: u64,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
         info!("DEBUG-10: Starting to process market {}", market.market_id);
-        let mut conn = self.connection.lock().await;
-
-        // Extract and scale cumulative funding
-        let cumulative_funding =
-            market.cumulative_funding.parse::<f64>().unwrap_or(0.0) / PRICE_DECIMAL;
-
-        // Parse and scale other market data safely
-        let mark_price = market.mark_price.parse::<f64>().unwrap_or(0.0) / PRICE_DECIMAL;
-
-        let maintenance_margin_ratio = market
-            .maintenance_margin_ratio
-            .parse::<f64>()
-            .unwrap_or(0.05)
-            / CHAIN_DECIMAL;
-
-        // Store market data in Redis (already scaled)
-        let key = format!("market:derivative:{}", market.market_id);
-        info!("DEBUG-11: Storing market data to Redis key: {}", key);
-
-        conn.hset::<_, _, _, ()>(&key, "ticker", &market.ticker)?;
-        conn.hset::<_, _, _, ()>(&key, "mark_price", mark_price.to_string())?;
-        conn.hset::<_, _, _, ()>(
-            &key,
-            "maintenance_margin_ratio",
-            maintenance_margin_ratio.to_string(),
-        )?;
-        conn.hset::<_, _, _, ()>(&key, "cumulative_funding", cumulative_funding.to_string())?;
-        conn.hset::<_, _, _, ()>(&key, "block_height", block_height.to_string())?;
-        conn.hset::<_, _, _, ()>(&key, "timestamp", timestamp.to_string())?;
-        conn.hset::<_, _, _, ()>(&key, "status", &market.status)?;
-
-        // Add to markets set
-        conn.sadd::<_, _, ()>("markets:derivative", &market.market_id)?;
-
-        // Remove from pending markets set
-        {
-            info!(
-                "DEBUG-12: About to remove market {} from pending",
-                market.market_id
-            );
-            let mut market_ids = self.market_ids.lock().await;
-            let was_removed = market_ids.remove(&market.market_id);
-            info!(
-                "DEBUG-13: Market {} removed from pending: {}",
-                market.market_id, was_removed
-            );
 
-            // If this was the last market, update phase
-            if market_ids.is_empty() {
-                info!("DEBUG-14: All markets processed. Switching to Others phase.");
-                *self.phase.lock().await = ProcessingPhase::Others;
+        // Store the scaled market state
+        let state = storage::market_from_payload(market, block_height, timestamp);
+        info!("DEBUG-11: Storing market data for {}", market.market_id);
+        self.state.put_market(&state).await?;
+        let mark_price = state.mark_price;
+        let maintenance_margin_ratio = state.maintenance_margin_ratio;
+        let cumulative_funding = state.cumulative_funding;
+
+        // Read the stored oracle symbols and funding point, then write the
+        // oracle index and the funding side of the 24h summary, in one round
+        // trip each
+        let summary_key = redis_keys::market_summary(&market.market_id);
+        let mut conn = self.connection.clone();
+        let ((old_base, old_quote), stored_funding): (
+            (Option<String>, Option<String>),
+            StoredFunding,
+        ) = redis::pipe()
+            .cmd("HMGET")
+            .arg(redis_keys::derivative_market(&market.market_id))
+            .arg("oracle_base")
+            .arg("oracle_quote")
+            .cmd("HMGET")
+            .arg(&summary_key)
+            .arg("funding_point_cumulative")
+            .arg("funding_point_time")
+            .arg("funding_apr")
+            .query_async(&mut conn)
+            .await?;
+
+        let (funding_fields, funding_apr) =
+            funding_summary_fields(stored_funding, cumulative_funding, mark_price, timestamp);
+        let mut pipe = redis::pipe();
+        queue_oracle_index(
+            &mut pipe,
+            &market.market_id,
+            [old_base, old_quote],
+            &market.oracle_base,
+            &market.oracle_quote,
+        );
+        pipe.hset_multiple(&summary_key, &funding_fields).ignore();
+        if let Some(expiration) = expiry::expiration(market) {
+            pipe.hset(
+                redis_keys::derivative_market(&market.market_id),
+                "expiration_timestamp",
+                expiration,
+            )
+            .ignore();
+        }
+        pipe.query_async::<()>(&mut conn).await?;
 
-                // Mark in Redis that markets are ready
-                conn.set::<_, _, ()>("markets_ready", "true")?;
+        let time_secs = time::to_millis(timestamp as i64) / 1_000;
+        if let Some(settlement) = expiry::settlement(market, time_secs) {
+            self.settle_market(market, &settlement, block_height)
+                .await?;
+        }
 
-                // Process queued messages
-                drop(conn); // Release connection lock before processing
-                info!("DEBUG-15: About to process queued messages");
-                match self.process_queued_messages().await {
-                    Ok(_) => info!("DEBUG-16: Successfully processed queued messages"),
-                    Err(e) => error!("DEBUG-16: Error processing queued messages: {}", e),
-                }
-            } else {
-                info!("DEBUG-17: Still have {} markets pending", market_ids.len());
-            }
+        // Margins move with the settlement, so it is reported only once the
+        // new cumulative funding is stored
+        let settlement = self.funding_settlements.lock().await.observe(
+            &market.market_id,
+            cumulative_funding,
+            time::to_millis(timestamp as i64) / 1_000,
+            market.funding_interval.parse().unwrap_or(0),
+        );
+        if let Some(settlement) = settlement {
+            self.funding_settled(market, &settlement, mark_price, block_height)
+                .await;
         }
 
         // Publish market update through high-performance PubSub
@@ -135,13 +390,15 @@ impl RedisProcessor {
                 "mark_price": mark_price.to_string(),
                 "maintenance_margin_ratio": maintenance_margin_ratio.to_string(),
                 "cumulative_funding": cumulative_funding.to_string(),
+                "funding_apr": funding_apr.to_string(),
                 "block_height": block_height.to_string(),
                 "timestamp": timestamp.to_string(),
                 "status": market.status,
             });
 
             // Create market update event
-            let market_event = pubsub.create_market_update(market_data);
+            let event_time = time::to_millis(timestamp as i64) as u64;
+            let market_event = pubsub.create_market_update(event_time, market_data);
 
             // Publish through HPC Redis PubSub
             if let Err(e) = pubsub.publish_event(market_event).await {
@@ -150,7 +407,7 @@ impl RedisProcessor {
 
             // Also publish price update for clients only interested in prices
             let price_event =
-                pubsub.create_price_update(&market.market_id, &mark_price.to_string());
+                pubsub.create_price_update(event_time, &market.market_id, &mark_price.to_string());
 
             if let Err(e) = pubsub.publish_event(price_event).await {
                 warn!("Failed to publish price update through PubSub: {}", e);
@@ -159,159 +416,320 @@ impl RedisProcessor {
         Ok(())
     }
 
-    // Process any queued non-market messages
-    async fn process_queued_messages(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let messages = {
-            let mut deferred = self.deferred_messages.lock().await;
-            info!("DEBUG-18: Taking {} messages from queue", deferred.len());
-            std::mem::take(&mut *deferred)
-        };
-
-        info!("DEBUG-19: Processing {} queued messages", messages.len());
-
-        for (i, message) in messages.iter().enumerate() {
-            info!(
-                "DEBUG-20: Processing queued message {} of {}",
-                i + 1,
-                messages.len()
-            );
-            if let Err(e) = self.process_non_market_message(&message).await {
-                error!("DEBUG-21: Error processing queued message {}: {}", i + 1, e);
-            }
+    // Mark every position of a settled expiry market as settled at the
+    // settlement price and take it out of the liquidation indexes. Adding the
+    // market to the settled set is the claim: only the first processor to
+    // settle it publishes MarketSettled, and the writes before are safe to
+    // repeat.
+    async fn settle_market(
+        &self,
+        market: &DerivativeMarketPayload,
+        settlement: &MarketSettlement,
+        block_height: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.connection.clone();
+        let subaccount_ids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(redis_keys::positions_by_market(&market.market_id))
+            .query_async(&mut conn)
+            .await?;
+
+        let settlement_price = settlement.settlement_price.to_string();
+        let mut pipe = redis::pipe();
+        pipe.hset_multiple(
+            redis_keys::derivative_market(&market.market_id),
+            &[
+                ("settlement_price", settlement_price.clone()),
+                ("settled_block_height", block_height.to_string()),
+            ],
+        )
+        .ignore();
+        for subaccount_id in &subaccount_ids {
+            let member = redis_keys::liquidatable_member(&market.market_id, subaccount_id);
+            pipe.hset_multiple(
+                redis_keys::position(&market.market_id, subaccount_id),
+                &[
+                    ("settled", "true".to_string()),
+                    ("settlement_price", settlement_price.clone()),
+                    ("is_liquidatable", "false".to_string()),
+                ],
+            )
+            .ignore();
+            pipe.srem(redis_keys::liquidatable_positions(), &member)
+                .ignore();
+            pipe.zrem(redis_keys::at_risk_positions(), &member).ignore();
+        }
+        pipe.sadd(redis_keys::settled_markets(), &market.market_id);
+        let (newly_settled,): (i64,) = pipe.query_async(&mut conn).await?;
+        if newly_settled == 0 {
+            return Ok(());
         }
 
+        info!(
+            "Market {} settled at {} on expiry, {} positions closed",
+            market.market_id,
+            settlement.settlement_price,
+            subaccount_ids.len()
+        );
+        let Some(pubsub) = &self.pubsub else {
+            return Ok(());
+        };
+        let settled_data = serde_json::json!({
+            "market_id": market.market_id,
+            "ticker": market.ticker,
+            "expiration_timestamp": settlement.expiration_timestamp,
+            "settlement_price": settlement_price,
+            "positions": subaccount_ids.len(),
+            "block_height": block_height,
+        });
+        let event = StreamEvent::new(
+            EventType::MarketSettled,
+            time::to_millis(settlement.expiration_timestamp) as u64,
+            settled_data,
+        );
+        if let Err(e) = pubsub.publish_event(event).await {
+            warn!("Failed to publish market settlement: {}", e);
+        }
         Ok(())
     }
 
-    // Process a position message
-    async fn process_position(
+    async fn funding_settled(
         &self,
-        position: &PositionPayload,
+        market: &DerivativeMarketPayload,
+        settlement: &FundingSettlement,
+        mark_price: f64,
         block_height: u64,
-        timestamp: u64,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    ) {
         info!(
-            "DEBUG-22: Processing position for market={}, subaccount={}",
-            position.market_id, position.subaccount_id
+            "Funding settled for {} at block {}: {} per contract",
+            market.market_id,
+            block_height,
+            settlement.funding_delta()
+        );
+        if let Some(trigger) = &self.recompute_trigger {
+            trigger.notify_one();
+        }
+        let Some(pubsub) = &self.pubsub else {
+            return;
+        };
+        let funding_rate = if mark_price > 0.0 {
+            settlement.funding_delta() / mark_price
+        } else {
+            0.0
+        };
+        let settled_data = serde_json::json!({
+            "market_id": market.market_id,
+            "ticker": market.ticker,
+            "previous_cumulative_funding": settlement.previous_cumulative_funding.to_string(),
+            "cumulative_funding": settlement.cumulative_funding.to_string(),
+            "funding_delta": settlement.funding_delta().to_string(),
+            "funding_rate": funding_rate.to_string(),
+            "mark_price": mark_price.to_string(),
+            "funding_interval": market.funding_interval,
+            "settled_at": settlement.settled_at_secs,
+            "block_height": block_height,
+        });
+        let event = StreamEvent::new(
+            EventType::FundingSettled,
+            time::to_millis(settlement.settled_at_secs) as u64,
+            settled_data,
         );
-        let mut conn = self.connection.lock().await;
+        if let Err(e) = pubsub.publish_event(event).await {
+            warn!("Failed to publish funding settlement: {}", e);
+        }
+    }
 
-        // Check if market exists
-        let market_key = format!("market:derivative:{}", position.market_id);
-        let market_exists: bool = conn.exists(&market_key)?;
+    // Compare an incoming position update with the block height and source
+    // stored in the position hash; positions never stored are always fresh
+    async fn position_is_fresh(
+        &self,
+        conn: &mut MirroredConnection,
+        key: &str,
+        source: PositionSource,
+        block_height: u64,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let (stored_source, stored_height): (Option<String>, Option<u64>) = redis::cmd("HMGET")
+            .arg(key)
+            .arg("source")
+            .arg("block_height")
+            .query_async(conn)
+            .await?;
+        Ok(stored_height.is_none_or(|stored_height| {
+            source.supersedes(
+                block_height,
+                stored_height,
+                stored_source.as_deref().and_then(PositionSource::parse),
+            )
+        }))
+    }
 
-        if !market_exists {
-            warn!(
-                "DEBUG-23: Market {} not found for position {}. Skipping position processing.",
-                position.market_id, position.subaccount_id
-            );
+    // Store a batch of positions in a fixed number of round trips: one read
+    // per distinct market, one for the freshness checks, one for the position
+    // state and one for the liquidation indexes. `publish[i]` says whether the
+    // i-th position gets a PositionUpdate event.
+    async fn store_positions(
+        &self,
+        positions: &[PositionPayload],
+        publish: &[bool],
+        source: PositionSource,
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if positions.is_empty() {
             return Ok(());
         }
 
-        info!("DEBUG-24: Market exists for position, continuing processing");
-
-        // Parse and scale position data
-        let is_long = position.is_long;
-        let quantity = position.quantity.parse::<f64>().unwrap_or(0.0) / CHAIN_DECIMAL;
-        let entry_price = position.entry_price.parse::<f64>().unwrap_or(0.0) / PRICE_DECIMAL;
-        let margin = position.margin.parse::<f64>().unwrap_or(0.0) / PRICE_DECIMAL;
-        let cumulative_funding_entry = position
-            .cumulative_funding_entry
-            .parse::<f64>()
-            .unwrap_or(0.0)
-            / PRICE_DECIMAL;
-
-        // Skip positions with invalid data
-        if quantity <= 0.0 || entry_price <= 0.0 || margin <= 0.0 {
-            warn!(
-                "DEBUG-25: Invalid position data (q={}, p={}, m={}) for market {} subaccount {}, skipping",
-                quantity, entry_price, margin, position.market_id, position.subaccount_id
-            );
-            return Ok(());
+        let mut markets: HashMap<&str, Option<MarketData>> = HashMap::new();
+        for position in positions {
+            if !markets.contains_key(position.market_id.as_str()) {
+                let market = self.state.get_market(&position.market_id).await?;
+                markets.insert(position.market_id.as_str(), market);
+            }
         }
 
-        // Get market data for calculating liquidation price
-        // Values from Redis are already scaled, no need to scale again
-        let mark_price: f64 = match conn.hget::<_, _, Option<String>>(&market_key, "mark_price") {
-            Ok(Some(value)) => value.parse().unwrap_or(0.0),
-            _ => {
+        // Only apply updates that are at least as fresh as the stored positions
+        let mut pipe = redis::pipe();
+        for position in positions {
+            pipe.cmd("HMGET")
+                .arg(redis_keys::position(
+                    &position.market_id,
+                    &position.subaccount_id,
+                ))
+                .arg("source")
+                .arg("block_height");
+        }
+        let mut conn = self.connection.clone();
+        let stored: Vec<(Option<String>, Option<u64>)> = pipe.query_async(&mut conn).await?;
+
+        let mut updates = Vec::with_capacity(positions.len());
+        let mut states = Vec::with_capacity(positions.len());
+        for ((position, publish_update), (stored_source, stored_height)) in
+            positions.iter().zip(publish).zip(stored)
+        {
+            let Some(market) = markets
+                .get(position.market_id.as_str())
+                .and_then(Option::as_ref)
+            else {
                 warn!(
-                    "Missing mark_price for market {}, using default",
-                    position.market_id
+                    "DEBUG-23: Market {} not found for position {}. Skipping position processing.",
+                    position.market_id, position.subaccount_id
+                );
+                continue;
+            };
+
+            let fresh = stored_height.is_none_or(|stored_height| {
+                source.supersedes(
+                    block_height,
+                    stored_height,
+                    stored_source.as_deref().and_then(PositionSource::parse),
+                )
+            });
+            if !fresh {
+                info!(
+                    "Skipping stale {} position for market={}, subaccount={} at block {}",
+                    source.as_str(),
+                    position.market_id,
+                    position.subaccount_id,
+                    block_height
                 );
-                0.0
+                continue;
             }
-        };
 
-        // Maintenance margin is a ratio, no scaling needed
-        let maintenance_margin_ratio: f64 =
-            match conn.hget::<_, _, Option<String>>(&market_key, "maintenance_margin_ratio") {
-                Ok(Some(value)) => value.parse().unwrap_or(0.05),
-                _ => {
-                    warn!(
-                        "Missing maintenance_margin_ratio for market {}, using default",
-                        position.market_id
-                    );
-                    0.05
-                }
+            // Scale the position and price its liquidation against the market
+            let Some(state) =
+                storage::position_from_payload(position, market, block_height, timestamp)
+            else {
+                warn!(
+                    "DEBUG-25: Invalid position data (q={}, p={}, m={}) for market {} subaccount {}, skipping",
+                    position.quantity,
+                    position.entry_price,
+                    position.margin,
+                    position.market_id,
+                    position.subaccount_id
+                );
+                continue;
             };
+            updates.push((position, market, *publish_update));
+            states.push(state);
+        }
+        if states.is_empty() {
+            return Ok(());
+        }
 
-        let market_cumulative_funding: f64 =
-            match conn.hget::<_, _, Option<String>>(&market_key, "cumulative_funding") {
-                Ok(Some(value)) => value.parse().unwrap_or(0.0),
-                _ => {
-                    warn!(
-                        "Missing cumulative_funding for market {}, using default",
-                        position.market_id
-                    );
-                    0.0
-                }
-            };
+        info!("DEBUG-26: Storing {} positions to Redis", states.len());
+        self.state.put_positions(&states).await?;
+
+        let mut pipe = redis::pipe();
+        for ((position, market, _), state) in updates.iter().zip(&states) {
+            let key = redis_keys::position(&position.market_id, &position.subaccount_id);
+            let member =
+                redis_keys::liquidatable_member(&position.market_id, &position.subaccount_id);
+            pipe.hset(&key, "source", source.as_str()).ignore();
+
+            // Rank the position by how far the mark price is from liquidating it
+            if market.mark_price > 0.0 {
+                let distance = distance_to_liquidation(
+                    state.is_long,
+                    state.liquidation_price,
+                    market.mark_price,
+                );
+                pipe.hset(&key, "liquidation_distance", distance.to_string())
+                    .ignore()
+                    .zadd(redis_keys::at_risk_positions(), &member, distance)
+                    .ignore();
+                let (unrealized_pnl, margin_ratio) = position_health(state, market);
+                pipe.hset(&key, "unrealized_pnl", unrealized_pnl.to_string())
+                    .ignore()
+                    .hset(&key, "margin_ratio", margin_ratio.to_string())
+                    .ignore();
+            }
+
+            // Keep the liquidatable set in step with the position
+            if state.is_liquidatable {
+                pipe.sadd(redis_keys::liquidatable_positions(), &member)
+                    .ignore();
+            } else {
+                pipe.srem(redis_keys::liquidatable_positions(), &member)
+                    .ignore();
+            }
+        }
+        pipe.query_async::<()>(&mut conn).await?;
+
+        for ((position, market, publish_update), state) in updates.into_iter().zip(states) {
+            self.publish_position(position, market, state, source, timestamp, publish_update)
+                .await?;
+        }
+
+        Ok(())
+    }
 
-        // Calculate liquidation price (all values already scaled)
-        let liquidation_price = calculate_liquidation_price(
+    // Publish the update of a stored position, and a liquidation alert when
+    // it is liquidatable
+    async fn publish_position(
+        &self,
+        position: &PositionPayload,
+        market: &MarketData,
+        state: PositionData,
+        source: PositionSource,
+        timestamp: u64,
+        publish_update: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (unrealized_pnl, margin_ratio) = position_health(&state, market);
+        let PositionData {
+            block_height,
             is_long,
+            quantity,
             entry_price,
             margin,
-            quantity,
-            maintenance_margin_ratio,
-            market_cumulative_funding,
             cumulative_funding_entry,
-        );
-
-        // Store position data (all values already scaled)
-        let key = format!("position:{}:{}", position.market_id, position.subaccount_id);
-        info!("DEBUG-26: Storing position to Redis key: {}", key);
-
-        conn.hset::<_, _, _, ()>(&key, "is_long", position.is_long.to_string())?;
-        conn.hset::<_, _, _, ()>(&key, "quantity", quantity.to_string())?;
-        conn.hset::<_, _, _, ()>(&key, "entry_price", entry_price.to_string())?;
-        conn.hset::<_, _, _, ()>(&key, "margin", margin.to_string())?;
-        conn.hset::<_, _, _, ()>(
-            &key,
-            "cumulative_funding_entry",
-            cumulative_funding_entry.to_string(),
-        )?;
-        conn.hset::<_, _, _, ()>(&key, "liquidation_price", liquidation_price.to_string())?;
-        conn.hset::<_, _, _, ()>(&key, "block_height", block_height.to_string())?;
-        conn.hset::<_, _, _, ()>(&key, "timestamp", timestamp.to_string())?;
-
-        // Add to position sets
-        conn.sadd::<_, _, ()>(
-            format!("positions:market:{}", position.market_id),
-            &position.subaccount_id,
-        )?;
-        conn.sadd::<_, _, ()>(
-            format!("positions:subaccount:{}", position.subaccount_id),
-            &position.market_id,
-        )?;
-
-        // Check if liquidatable (all values already scaled)
-        let is_liquidatable = is_liquidatable(is_long, liquidation_price, mark_price);
-        conn.hset::<_, _, _, ()>(&key, "is_liquidatable", is_liquidatable.to_string())?;
-
-        // Create position update data for PubSub
-        if let Some(pubsub) = &self.pubsub {
+            liquidation_price,
+            is_liquidatable,
+            ..
+        } = state;
+        let mark_price = market.mark_price;
+        let market_cumulative_funding = market.cumulative_funding;
+
+        // Create position update data for PubSub, unless the position is unchanged
+        if let Some(pubsub) = self.pubsub.as_ref().filter(|_| publish_update) {
             let position_data = serde_json::json!({
                 "is_long": is_long,
                 "quantity": quantity.to_string(),
@@ -322,23 +740,19 @@ impl RedisProcessor {
                 "market_funding": market_cumulative_funding.to_string(),
                 "mark_price": mark_price.to_string(),
                 "is_liquidatable": is_liquidatable,
+                "unrealized_pnl": unrealized_pnl.to_string(),
+                "margin_ratio": margin_ratio.to_string(),
                 "block_height": block_height.to_string(),
                 "market_id": position.market_id,
-                "subaccount_id": position.subaccount_id
+                "subaccount_id": position.subaccount_id,
+                "source": source.as_str()
             });
 
             // Create position update event
-            let position_event = StreamEvent {
-                event_type: EventType::PositionUpdate,
-                timestamp,
-                payload: serde_json::json!(
-                    position_data.clone()
-                ),
-            };
+            let position_event =
+                StreamEvent::new(EventType::PositionUpdate, timestamp, position_data);
 
             let pubsub_clone = pubsub.clone();
-            let _market_id = position.market_id.clone();
-            let _subaccount_id = position.subaccount_id.clone();
 
             // Spawn a task to publish the position update
             tokio::spawn(async move {
@@ -348,13 +762,8 @@ impl RedisProcessor {
             });
         }
 
-        // Update liquidatable positions and publish alerts
-        if is_liquidatable {
-            conn.sadd::<_, _, ()>(
-                "liquidatable_positions",
-                format!("{}:{}", position.market_id, position.subaccount_id),
-            )?;
-
+        // Publish alerts for liquidatable positions
+        if is_liquidatable && self.claim_alert(position, block_height).await {
             // Create liquidation alert data
             let alert_data = serde_json::json!({
                 "market_id": position.market_id,
@@ -365,14 +774,22 @@ impl RedisProcessor {
                 "quantity": quantity.to_string(),
                 "entry_price": entry_price.to_string(),
                 "margin": margin.to_string(),
+                "block_height": block_height,
             });
 
-            // Legacy Redis publish for backward compatibility
-            conn.publish::<_, _, ()>("liquidation_alerts", alert_data.to_string())?;
+            // Legacy Redis publish for backward compatibility. Kept out of the
+            // write pipelines, which are mirrored during dual writes.
+            let mut conn = self.connection.clone();
+            conn.publish::<_, _, ()>(
+                redis_keys::liquidation_alerts_channel(),
+                alert_data.to_string(),
+            )
+            .await?;
 
             // Publish through HPC Redis PubSub
             if let Some(pubsub) = &self.pubsub {
-                let liquidation_event = pubsub.create_liquidation_alert(alert_data);
+                let liquidation_event = pubsub
+                    .create_liquidation_alert(time::to_millis(timestamp as i64) as u64, alert_data);
 
                 // Fixed: Use direct publish for liquidation events (higher priority)
                 if let Err(e) = pubsub.publish_event(liquidation_event).await {
@@ -384,15 +801,101 @@ impl RedisProcessor {
                 "Liquidatable position: market={}, subaccount={}, liq_price={}, mark_price={}",
                 position.market_id, position.subaccount_id, liquidation_price, mark_price
             );
-        } else {
-            conn.srem::<_, _, ()>(
-                "liquidatable_positions",
-                format!("{}:{}", position.market_id, position.subaccount_id),
-            )?;
         }
 
-        info!("DEBUG-27: Position successfully processed and stored in Redis");
+        Ok(())
+    }
+
+    // Claim the alert of a liquidatable position at a block. The claim is a
+    // SET NX marker, so a replay of the block finds it taken. If Redis can't
+    // be asked the alert goes out, since a duplicate is better than a lost
+    // alert.
+    async fn claim_alert(&self, position: &PositionPayload, block_height: i64) -> bool {
+        let Some(ttl) = self.alert_claim_ttl else {
+            return true;
+        };
+        let claim: Result<Option<String>, _> = redis::cmd("SET")
+            .arg(redis_keys::liquidation_alert_claim(
+                &position.market_id,
+                &position.subaccount_id,
+                block_height,
+            ))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl.as_secs().max(1))
+            .query_async(&mut self.connection.clone())
+            .await;
+        match claim {
+            Ok(claimed) => {
+                if claimed.is_none() {
+                    debug!(
+                        "Liquidation alert for {}:{} at block {} already published",
+                        position.market_id, position.subaccount_id, block_height
+                    );
+                }
+                claimed.is_some()
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to claim liquidation alert, publishing anyway: {}",
+                    e
+                );
+                true
+            }
+        }
+    }
+
+    // Process a batch of positions. Heartbeat batches are full snapshots and
+    // drive the differ and the aggregates; streamed batches only carry the
+    // positions that changed in the block, so each one is published as is.
+    async fn process_positions(
+        &self,
+        positions: &[PositionPayload],
+        source: PositionSource,
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        info!(
+            "DEBUG-29: Processing {} {} positions from message",
+            positions.len(),
+            source.as_str()
+        );
+        let diff = match (&self.position_differ, source) {
+            (Some(differ), PositionSource::Heartbeat) => Some(differ.lock().await.diff(positions)),
+            _ => None,
+        };
+        if let Some(diff) = &diff {
+            self.apply_position_diff(diff, positions.len(), block_height, timestamp)
+                .await?;
+        }
+        let publish: Vec<bool> = positions
+            .iter()
+            .map(|position| diff.as_ref().is_none_or(|d| d.should_emit(position)))
+            .collect();
+        if let Err(e) = self
+            .store_positions(positions, &publish, source, block_height, timestamp)
+            .await
+        {
+            error!(
+                "DEBUG-31: Error processing {} positions: {}",
+                positions.len(),
+                e
+            );
+        }
+        info!("DEBUG-32: Finished processing all positions");
 
+        if source == PositionSource::Heartbeat {
+            if let Err(e) = self.update_open_interest(positions).await {
+                warn!("Failed to update open interest: {}", e);
+            }
+            if let Err(e) = self
+                .update_address_aggregates(positions, block_height, timestamp)
+                .await
+            {
+                warn!("Failed to update address aggregates: {}", e);
+            }
+        }
         Ok(())
     }
 
@@ -410,79 +913,87 @@ impl RedisProcessor {
             msg_type, block_height
         );
 
-        // Direct handling based on message type instead of relying on payload variant
+        // Streamed and heartbeat positions share one path
+        if let Some((positions, source)) = message.positions() {
+            return self
+                .process_positions(positions, source, block_height, timestamp)
+                .await;
+        }
+
+        // Spot and derivative full books share a payload variant
+        if let Some((orderbooks, market_type)) = message.full_orderbooks() {
+            info!(
+                "Processing {} {:?} full orderbooks",
+                orderbooks.len(),
+                market_type
+            );
+            for orderbook in orderbooks {
+                match market_type {
+                    MarketType::Derivative => {
+                        self.process_top_of_book(orderbook, block_height, timestamp)
+                            .await?
+                    }
+                    MarketType::Spot => {
+                        self.process_spot_top_of_book(orderbook, block_height, timestamp)
+                            .await?
+                    }
+                }
+            }
+            return Ok(());
+        }
+
         if *msg_type == MessageType::ExchangePosition {
-            // Position messages need special handling
-            if let KafkaPayload::ExchangePositions(positions) = &message.payload {
-                info!(
-                    "DEBUG-29: Processing {} positions from message",
-                    positions.len()
-                );
-                // Process positions
-                for (i, position) in positions.iter().enumerate() {
+            // Fallback: the untagged payload matched another variant
+            error!("Message type is ExchangePosition but payload isn't ExchangePositions!");
+
+            // Attempt manual deserialization
+            match serde_json::from_value::<Vec<PositionPayload>>(
+                serde_json::to_value(&message.payload).unwrap_or_default(),
+            ) {
+                Ok(positions) => {
                     info!(
-                        "DEBUG-30: Processing position {} of {} for market={}, subaccount={}",
-                        i + 1,
-                        positions.len(),
-                        position.market_id,
-                        position.subaccount_id
+                        "DEBUG-29: Manually deserialized {} positions from message",
+                        positions.len()
                     );
-                    if let Err(e) = self
-                        .process_position(position, block_height, timestamp)
-                        .await
-                    {
-                        error!(
-                            "DEBUG-31: Error processing position {} of {}: {}",
-                            i + 1,
-                            positions.len(),
-                            e
-                        );
-                    }
+                    return self
+                        .process_positions(
+                            &positions,
+                            PositionSource::Heartbeat,
+                            block_height,
+                            timestamp,
+                        )
+                        .await;
                 }
-                info!("DEBUG-32: Finished processing all positions");
-                return Ok(());
-            } else {
-                // Fallback: try manual deserialization if payload variant doesn't match
-                error!("Message type is ExchangePosition but payload isn't ExchangePositions!");
-
-                // Attempt manual deserialization
-                match serde_json::from_value::<Vec<PositionPayload>>(
-                    serde_json::to_value(&message.payload).unwrap_or_default(),
-                ) {
-                    Ok(positions) => {
-                        info!(
-                            "DEBUG-29: Manually deserialized {} positions from message",
-                            positions.len()
-                        );
-                        for (i, position) in positions.iter().enumerate() {
-                            info!("DEBUG-30: Processing position {} of {} for market={}, subaccount={}", 
-                             i+1, positions.len(), position.market_id, position.subaccount_id);
-                            if let Err(e) = self
-                                .process_position(position, block_height, timestamp)
-                                .await
-                            {
-                                error!(
-                                    "DEBUG-31: Error processing position {} of {}: {}",
-                                    i + 1,
-                                    positions.len(),
-                                    e
-                                );
-                            }
-                        }
-                        info!("DEBUG-32: Finished processing all positions");
-                    }
-                    Err(e) => {
-                        error!("Failed to manually deserialize position data: {}", e);
-                    }
+                Err(e) => {
+                    error!("Failed to manually deserialize position data: {}", e);
                 }
-                return Ok(());
             }
+            return Ok(());
         }
 
         // Continue with previous logic for other message types
         match &message.payload {
             KafkaPayload::DerivativeTrades(trades) => {
                 info!("Processing {} derivative trades", trades.len());
+                if let Err(e) = self
+                    .update_market_summaries(trades, block_height, timestamp)
+                    .await
+                {
+                    warn!("Failed to update market summaries: {}", e);
+                }
+                let subaccount_trades: Vec<SubaccountTrade> = trades
+                    .iter()
+                    .map(|trade| {
+                        storage::subaccount_trade(&storage::trade_from_payload(
+                            trade,
+                            block_height,
+                            timestamp,
+                        ))
+                    })
+                    .collect();
+                if let Err(e) = self.cache_subaccount_trades(&subaccount_trades).await {
+                    warn!("Failed to cache subaccount trades: {}", e);
+                }
                 // Process trades
                 if let Some(pubsub) = &self.pubsub {
                     let mut trade_events = Vec::with_capacity(trades.len());
@@ -500,11 +1011,7 @@ impl RedisProcessor {
                             "timestamp": timestamp.to_string(),
                         });
 
-                        let event = StreamEvent {
-                            event_type: EventType::TradeUpdate,
-                            timestamp,
-                            payload: trade_data,
-                        };
+                        let event = StreamEvent::new(EventType::TradeUpdate, timestamp, trade_data);
 
                         trade_events.push(event);
                     }
@@ -532,11 +1039,8 @@ impl RedisProcessor {
                             "timestamp": timestamp.to_string(),
                         });
 
-                        let event = StreamEvent {
-                            event_type: EventType::OrderbookUpdate,
-                            timestamp,
-                            payload: orderbook_data,
-                        };
+                        let event =
+                            StreamEvent::new(EventType::OrderbookUpdate, timestamp, orderbook_data);
 
                         orderbook_events.push(event);
                     }
@@ -548,6 +1052,31 @@ impl RedisProcessor {
                         }
                     }
                 }
+                self.update_live_books(orderbooks, block_height, timestamp)
+                    .await?;
+            }
+            KafkaPayload::SpotTrades(trades) => match self.spot_recent_trades {
+                Some(recent_trades) => {
+                    info!("Processing {} spot trades", trades.len());
+                    self.process_spot_trades(trades, recent_trades, block_height, timestamp)
+                        .await?;
+                }
+                None => {
+                    info!(
+                        "Spot trade processing disabled, skipping {} trades",
+                        trades.len()
+                    );
+                }
+            },
+            KafkaPayload::StreamOraclePrices(prices) => {
+                info!("Processing {} oracle prices", prices.len());
+                self.process_oracle_prices(prices, block_height, timestamp)
+                    .await?;
+            }
+            KafkaPayload::SpotMarkets(markets) => {
+                info!("Processing {} spot markets", markets.len());
+                self.process_spot_markets(markets, block_height, timestamp)
+                    .await?;
             }
             _ => {
                 info!(
@@ -559,119 +1088,997 @@ impl RedisProcessor {
 
         Ok(())
     }
-}
-#[async_trait]
-impl MessageProcessor for RedisProcessor {
-    async fn process_message(
+
+    // Fold a block's trades into each market's hourly ring and refresh its 24h
+    // summary. Every match is reported once per side, so only the taker side
+    // is counted.
+    async fn update_market_summaries(
         &self,
-        message: KafkaMessage,
+        trades: &[DerivativeTradePayload],
+        block_height: u64,
+        timestamp: u64,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        // DEBUG-1: Log message type and phase at entry
-        let phase = *self.phase.lock().await;
-        // Use a reference to message_type instead of moving it
-        let msg_type = &message.message_type;
-        info!(
-            "DEBUG-1: Processing message type {:?} with phase {:?}",
-            msg_type, phase
-        );
-
-        match msg_type {
-            MessageType::DerivativeMarket => {
-                // Process market messages regardless of phase
-                if let KafkaPayload::DerivativeMarkets(markets) = &message.payload {
-                    info!(
-                        "Processing {} derivative markets at block {}",
-                        markets.len(),
-                        message.block_height
-                    );
+        let mut fills_by_market: HashMap<&str, Vec<(f64, f64)>> = HashMap::new();
+        for trade in trades
+            .iter()
+            .filter(|t| t.execution_type != "LimitMatchRestingOrder")
+        {
+            let price = scaling::price(&trade.position_delta.execution_price);
+            let quantity = scaling::quantity(&trade.position_delta.execution_quantity);
+            fills_by_market
+                .entry(trade.market_id.as_str())
+                .or_default()
+                .push((price, quantity));
+        }
 
-                    // Register all markets we need to process
-                    {
-                        let mut market_ids = self.market_ids.lock().await;
-                        for market in markets {
-                            market_ids.insert(market.market_id.clone());
-                        }
-                        info!(
-                            "BREAKPOINT 2: Added {} markets to pending, total pending: {}",
-                            markets.len(),
-                            market_ids.len()
-                        );
+        for (market_id, fills) in &fills_by_market {
+            let (price, _) = fills[fills.len() - 1];
+            self.update_volatility(market_id, price, block_height, timestamp)
+                .await?;
+        }
 
-                        // DEBUG-2: Log all market IDs we're tracking
-                        info!("DEBUG-2: Current pending market IDs: {:?}", market_ids);
-                    }
+        self.apply_summary_fills(
+            fills_by_market,
+            redis_keys::market_summary,
+            block_height,
+            timestamp,
+        )
+        .await
+    }
 
-                    // Process each market
-                    for market in markets {
-                        // DEBUG-3: Log before processing each market
-                        info!("DEBUG-3: About to process market {}", market.market_id);
+    // Sample the market's last taker price and rewrite its volatility hash once
+    // per new minute
+    async fn update_volatility(
+        &self,
+        market_id: &str,
+        price: f64,
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let estimates = {
+            let mut volatility = self.volatility.lock().await;
+            if !volatility.record(market_id, time::to_millis(timestamp as i64), price) {
+                return Ok(());
+            }
+            volatility.estimates(market_id)
+        };
+        if estimates.is_empty() {
+            return Ok(());
+        }
 
-                        if let Err(e) = self
-                            .process_derivative_market(
-                                market,
-                                message.block_height,
-                                message.block_time,
-                            )
-                            .await
-                        {
-                            error!("Error processing derivative market: {}", e);
-                        }
+        let mut fields = Vec::with_capacity(estimates.len() * 2 + 2);
+        for estimate in &estimates {
+            let window = estimate.window.as_str();
+            fields.push((
+                format!("rv_{}", window),
+                estimate.realized_variance.to_string(),
+            ));
+            fields.push((
+                format!("vol_{}", window),
+                estimate.annualized_vol.to_string(),
+            ));
+        }
+        fields.push(("block_height".to_string(), block_height.to_string()));
+        fields.push(("timestamp".to_string(), timestamp.to_string()));
 
-                        // DEBUG-4: Check if this market is still in pending
+        let mut conn = self.connection.clone();
+        conn.hset_multiple::<_, _, _, ()>(redis_keys::volatility(market_id), &fields)
+            .await?;
+        Ok(())
+    }
+
+    // Add (price, quantity) fills to each market's hourly ring, rewrite its 24h
+    // summary under `summary_key` and publish SummaryUpdate events
+    async fn apply_summary_fills(
+        &self,
+        fills_by_market: HashMap<&str, Vec<(f64, f64)>>,
+        summary_key: fn(&str) -> String,
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let time_millis = time::to_millis(timestamp as i64);
+        let current_hour = time::hour_bucket(timestamp as i64);
+        let slot = market_summary::slot(current_hour);
+        self.summary_windows.lock().await.advance(time_millis);
+
+        let mut summary_events = Vec::with_capacity(fills_by_market.len());
+        for (market_id, fills) in fills_by_market {
+            let summary: HashMap<String, String> = {
+                let mut conn = self.connection.clone();
+                let ring_key = redis_keys::summary_buckets(market_id);
+                let stored: HashMap<i64, String> = conn.hgetall(&ring_key).await?;
+
+                let bucket = {
+                    let mut windows = self.summary_windows.lock().await;
+                    if !windows.accepts(time_millis) {
+                        crate::metrics::consumer()
+                            .late_events
+                            .with_label_values(&["market_summary"])
+                            .inc();
+                        warn!(
+                            "Dropping late trades of {} at block {}, hour already closed",
+                            market_id, block_height
+                        );
+                        continue;
+                    }
+                    if !windows.is_tracking(&ring_key, current_hour) {
+                        // A slot still holding an hour from the previous day starts over
+                        if let Some(stored_bucket) = stored
+                            .get(&slot)
+                            .and_then(|v| serde_json::from_str::<HourBucket>(v).ok())
+                            .filter(|b| b.start == current_hour)
                         {
-                            let market_ids = self.market_ids.lock().await;
-                            info!(
-                                "DEBUG-4: After processing market {}, still in pending: {}",
-                                market.market_id,
-                                market_ids.contains(&market.market_id)
-                            );
+                            windows.resume(&ring_key, current_hour, stored_bucket);
                         }
                     }
-                } else {
-                    error!("Received DerivativeMarket message type but payload is not DerivativeMarkets");
+                    for fill in &fills {
+                        windows.add(&ring_key, time_millis, fill);
+                    }
+                    match windows.get(&ring_key, current_hour) {
+                        Some(bucket) => *bucket,
+                        None => continue,
+                    }
+                };
+                let bucket_json = serde_json::to_string(&bucket)?;
+
+                let mut buckets: Vec<HourBucket> = stored
+                    .iter()
+                    .filter(|(s, _)| **s != slot)
+                    .filter_map(|(_, v)| serde_json::from_str(v).ok())
+                    .collect();
+                buckets.push(bucket);
+                let Some(stats) = market_summary::rolling_stats(&buckets, current_hour) else {
+                    conn.hset::<_, _, _, ()>(&ring_key, slot, bucket_json)
+                        .await?;
+                    continue;
+                };
+
+                // Store the bucket, rewrite the summary and read it back in one round trip
+                let key = summary_key(market_id);
+                let fields = [
+                    ("last_price", stats.last_price.to_string()),
+                    ("open_24h", stats.open_24h.to_string()),
+                    ("high_24h", stats.high_24h.to_string()),
+                    ("low_24h", stats.low_24h.to_string()),
+                    ("volume_24h", stats.volume_24h.to_string()),
+                    ("change_24h", stats.change_24h.to_string()),
+                    ("block_height", block_height.to_string()),
+                    ("timestamp", timestamp.to_string()),
+                ];
+                let (summary,): (HashMap<String, String>,) = redis::pipe()
+                    .hset(&ring_key, slot, bucket_json)
+                    .ignore()
+                    .hset_multiple(&key, &fields)
+                    .ignore()
+                    .hgetall(&key)
+                    .query_async(&mut conn)
+                    .await?;
+                summary
+            };
+
+            let mut payload = serde_json::json!(summary);
+            payload["market_id"] = serde_json::json!(market_id);
+            summary_events.push(StreamEvent::new(
+                EventType::SummaryUpdate,
+                timestamp,
+                payload,
+            ));
+        }
+
+        if let Some(pubsub) = &self.pubsub {
+            if !summary_events.is_empty() {
+                pubsub.publish_events_batch(summary_events).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Push trade sides onto their subaccounts' recent-trade lists in one round
+    // trip, so trade history pages are served without touching the markets
+    async fn cache_subaccount_trades(
+        &self,
+        trades: &[SubaccountTrade],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(recent_trades) = self.subaccount_recent_trades else {
+            return Ok(());
+        };
+        if recent_trades == 0 || trades.is_empty() {
+            return Ok(());
+        }
+
+        let mut pipe = redis::pipe();
+        for trade in trades {
+            let key = redis_keys::subaccount_trades(&trade.subaccount_id);
+            pipe.lpush(&key, serde_json::to_string(trade)?)
+                .ignore()
+                .ltrim(&key, 0, recent_trades as isize - 1)
+                .ignore();
+        }
+        let mut conn = self.connection.clone();
+        pipe.query_async::<()>(&mut conn).await?;
+        Ok(())
+    }
+
+    // Keep the most recent trades per market, fold taker fills into the spot
+    // summaries and publish TradeUpdate events. Spot prices depend on each
+    // market's base and quote decimals, so values stay in chain units.
+    async fn process_spot_trades(
+        &self,
+        trades: &[SpotTradePayload],
+        recent_trades: usize,
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut trade_events = Vec::with_capacity(trades.len());
+        let mut fills_by_market: HashMap<&str, Vec<(f64, f64)>> = HashMap::new();
+        let mut subaccount_trades = Vec::with_capacity(trades.len());
+        let mut pipe = redis::pipe();
+        for trade in trades {
+            subaccount_trades.push(storage::spot_subaccount_trade(
+                trade,
+                block_height,
+                timestamp,
+            ));
+            let trade_data = serde_json::json!({
+                "market_id": trade.market_id,
+                "market_type": "spot",
+                "is_buy": trade.is_buy,
+                "execution_type": trade.execution_type,
+                "subaccount_id": trade.subaccount_id,
+                "price": trade.price,
+                "quantity": trade.quantity,
+                "fee": trade.fee,
+                "trade_id": trade.trade_id,
+                "block_height": block_height.to_string(),
+                "timestamp": timestamp.to_string(),
+            });
+
+            if recent_trades > 0 {
+                let key = redis_keys::spot_trades(&trade.market_id);
+                pipe.lpush(&key, trade_data.to_string())
+                    .ignore()
+                    .ltrim(&key, 0, recent_trades as isize - 1)
+                    .ignore();
+            }
+
+            if trade.execution_type != "LimitMatchRestingOrder" {
+                fills_by_market
+                    .entry(trade.market_id.as_str())
+                    .or_default()
+                    .push((
+                        trade.price.parse::<f64>().unwrap_or(0.0),
+                        trade.quantity.parse::<f64>().unwrap_or(0.0),
+                    ));
+            }
+
+            trade_events.push(StreamEvent::new(
+                EventType::TradeUpdate,
+                timestamp,
+                trade_data,
+            ));
+        }
+
+        // One round trip for the recent trades of every market
+        if recent_trades > 0 {
+            let mut conn = self.connection.clone();
+            pipe.query_async::<()>(&mut conn).await?;
+        }
+
+        if let Err(e) = self.cache_subaccount_trades(&subaccount_trades).await {
+            warn!("Failed to cache subaccount trades: {}", e);
+        }
+
+        if let Err(e) = self
+            .apply_summary_fills(
+                fills_by_market,
+                redis_keys::spot_market_summary,
+                block_height,
+                timestamp,
+            )
+            .await
+        {
+            warn!("Failed to update spot market summaries: {}", e);
+        }
+
+        if let Some(pubsub) = &self.pubsub {
+            if !trade_events.is_empty() {
+                pubsub.publish_events_batch(trade_events).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Open interest per market from a full positions snapshot. Markets with no
+    // positions in the snapshot drop to zero.
+    async fn update_open_interest(
+        &self,
+        positions: &[PositionPayload],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let open_interest = market_summary::open_interest(positions);
+
+        let mut conn = self.connection.clone();
+        let market_ids: Vec<String> = conn.smembers(redis_keys::derivative_markets()).await?;
+        if market_ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut pipe = redis::pipe();
+        for market_id in market_ids {
+            let total = open_interest.get(&market_id).copied().unwrap_or(0.0);
+            pipe.hset(
+                redis_keys::market_summary(&market_id),
+                "open_interest",
+                total.to_string(),
+            )
+            .ignore();
+        }
+        pipe.query_async::<()>(&mut conn).await?;
+
+        Ok(())
+    }
+
+    // Aggregate a full positions snapshot by owner address, since risk is
+    // managed per address rather than per subaccount. Addresses that no longer
+    // hold positions are removed.
+    async fn update_address_aggregates(
+        &self,
+        positions: &[PositionPayload],
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        #[derive(Default)]
+        struct Aggregate<'a> {
+            subaccounts: HashSet<&'a str>,
+            position_count: u64,
+            total_margin: f64,
+            unrealized_pnl: f64,
+        }
+
+        let mut conn = self.connection.clone();
+        let mut mark_prices: HashMap<&str, f64> = HashMap::new();
+        let mut aggregates: HashMap<String, Aggregate> = HashMap::new();
+
+        for position in positions {
+            let Some(owner) = address::owner_address(&position.subaccount_id) else {
+                continue;
+            };
+            let mark_price = match mark_prices.get(position.market_id.as_str()) {
+                Some(price) => *price,
+                None => {
+                    let price: Option<String> = conn
+                        .hget(
+                            redis_keys::derivative_market(&position.market_id),
+                            "mark_price",
+                        )
+                        .await?;
+                    let price = price.and_then(|p| p.parse().ok()).unwrap_or(0.0);
+                    mark_prices.insert(&position.market_id, price);
+                    price
                 }
+            };
+
+            let quantity = scaling::quantity(&position.quantity);
+            let entry_price = scaling::price(&position.entry_price);
+            let margin = scaling::margin(&position.margin);
+
+            let aggregate = aggregates.entry(owner).or_default();
+            aggregate.subaccounts.insert(&position.subaccount_id);
+            aggregate.position_count += 1;
+            aggregate.total_margin += margin;
+            if mark_price > 0.0 {
+                aggregate.unrealized_pnl +=
+                    calculate_unrealized_pnl(position.is_long, entry_price, mark_price, quantity);
             }
-            _ => {
-                // For all other message types (including positions)
-                match phase {
-                    ProcessingPhase::Markets => {
-                        // In Markets phase, defer non-market messages
-                        info!(
-                            "Deferring message type {:?} at block {} (in Markets phase)",
-                            msg_type, message.block_height
-                        );
-                        let mut deferred = self.deferred_messages.lock().await;
-                        deferred.push(message);
-                        info!(
-                            "DEBUG-6: Deferred message, queue now has {} messages",
-                            deferred.len()
-                        );
+        }
+
+        let previous: HashSet<String> = conn.smembers(redis_keys::addresses()).await?;
+        let mut pipe = redis::pipe();
+        for owner in previous.iter().filter(|a| !aggregates.contains_key(*a)) {
+            pipe.del(redis_keys::address(owner))
+                .ignore()
+                .del(redis_keys::address_subaccounts(owner))
+                .ignore()
+                .srem(redis_keys::addresses(), owner)
+                .ignore();
+        }
+
+        for (owner, aggregate) in &aggregates {
+            let subaccounts_key = redis_keys::address_subaccounts(owner);
+            let subaccounts: Vec<&str> = aggregate.subaccounts.iter().copied().collect();
+            let fields = [
+                ("position_count", aggregate.position_count.to_string()),
+                ("total_margin", aggregate.total_margin.to_string()),
+                ("unrealized_pnl", aggregate.unrealized_pnl.to_string()),
+                (
+                    "total_equity",
+                    (aggregate.total_margin + aggregate.unrealized_pnl).to_string(),
+                ),
+                ("block_height", block_height.to_string()),
+                ("timestamp", timestamp.to_string()),
+            ];
+
+            pipe.del(&subaccounts_key)
+                .ignore()
+                .sadd(&subaccounts_key, subaccounts)
+                .ignore()
+                .hset_multiple(redis_keys::address(owner), &fields)
+                .ignore()
+                .sadd(redis_keys::addresses(), owner)
+                .ignore();
+        }
+        pipe.query_async::<()>(&mut conn).await?;
+
+        Ok(())
+    }
+
+    // Publish the snapshot marker and remove positions that have been closed
+    async fn apply_position_diff(
+        &self,
+        diff: &PositionDiff,
+        position_count: usize,
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        info!(
+            "Position diff at block {}: {} changed, {} closed, full_snapshot={}",
+            block_height,
+            diff.changed.len(),
+            diff.closed.len(),
+            diff.full_snapshot
+        );
+
+        if diff.full_snapshot {
+            if let Some(pubsub) = &self.pubsub {
+                let event = StreamEvent::new(
+                    EventType::SystemEvent,
+                    timestamp,
+                    serde_json::json!({
+                        "event": "position_snapshot",
+                        "block_height": block_height.to_string(),
+                        "position_count": position_count,
+                    }),
+                );
+                if let Err(e) = pubsub.publish_event(event).await {
+                    warn!("Failed to publish position snapshot marker: {}", e);
+                }
+            }
+        }
+
+        for (market_id, subaccount_id) in &diff.closed {
+            // A newer streamed update may have reopened the position
+            let mut conn = self.connection.clone();
+            let key = redis_keys::position(market_id, subaccount_id);
+            let fresh = self
+                .position_is_fresh(&mut conn, &key, PositionSource::Heartbeat, block_height)
+                .await?;
+            if !fresh {
+                continue;
+            }
+            self.state.remove_position(market_id, subaccount_id).await?;
+
+            if let Some(pubsub) = &self.pubsub {
+                let event = StreamEvent::new(
+                    EventType::PositionUpdate,
+                    timestamp,
+                    serde_json::json!({
+                        "market_id": market_id,
+                        "subaccount_id": subaccount_id,
+                        "quantity": "0",
+                        "closed": true,
+                        "block_height": block_height.to_string(),
+                    }),
+                );
+                if let Err(e) = pubsub.publish_event(event).await {
+                    warn!("Failed to publish position close: {}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Store the new oracle prices, publish a PriceUpdate per symbol, and
+    // recompute the estimated mark and basis of only the markets that use one
+    // of the updated symbols
+    async fn process_oracle_prices(
+        &self,
+        prices: &[OraclePricePayload],
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut conn = self.connection.clone();
+
+        // Store every price and look up the markets using each symbol in one round trip
+        let mut pipe = redis::pipe();
+        let mut price_events = Vec::new();
+        for price in prices {
+            let value = scaling::oracle_price(&price.price);
+            if value <= 0.0 {
+                continue;
+            }
+            pipe.hset(
+                redis_keys::oracle_prices(),
+                &price.symbol,
+                value.to_string(),
+            )
+            .ignore()
+            .hset_multiple(
+                redis_keys::oracle_price(&price.symbol),
+                &[
+                    ("price", value.to_string()),
+                    ("oracle_type", price.oracle_type.clone()),
+                    ("block_height", block_height.to_string()),
+                    ("timestamp", timestamp.to_string()),
+                ],
+            )
+            .ignore()
+            .smembers(redis_keys::oracle_markets(&price.symbol));
+            price_events.push(StreamEvent::new(
+                EventType::PriceUpdate,
+                timestamp,
+                serde_json::json!({
+                    "symbol": price.symbol,
+                    "price": value.to_string(),
+                    "oracle_type": price.oracle_type,
+                    "block_height": block_height.to_string(),
+                    "timestamp": timestamp.to_string(),
+                }),
+            ));
+        }
+        let market_ids: Vec<Vec<String>> = pipe.query_async(&mut conn).await?;
+        let affected: HashSet<String> = market_ids.into_iter().flatten().collect();
+
+        if let Some(pubsub) = &self.pubsub {
+            if !price_events.is_empty() {
+                if let Err(e) = pubsub.publish_events_batch(price_events).await {
+                    warn!("Failed to publish oracle price updates: {}", e);
+                }
+            }
+        }
+
+        for market_id in affected {
+            let key = redis_keys::derivative_market(&market_id);
+            let (base, quote): (Option<String>, Option<String>) = redis::cmd("HMGET")
+                .arg(&key)
+                .arg("oracle_base")
+                .arg("oracle_quote")
+                .query_async(&mut conn)
+                .await?;
+            let (Some(base), Some(quote)) = (base, quote) else {
+                continue;
+            };
+            let (base_price, quote_price): (Option<f64>, Option<f64>) = redis::cmd("HMGET")
+                .arg(redis_keys::oracle_prices())
+                .arg(&base)
+                .arg(&quote)
+                .query_async(&mut conn)
+                .await?;
+            let (Some(base_price), Some(quote_price)) = (base_price, quote_price) else {
+                continue;
+            };
+
+            // Both legs are in the same unit, so their ratio is the price of
+            // the market in human units
+            let estimated_mark = base_price / quote_price;
+            conn.hset::<_, _, _, ()>(&key, "estimated_mark", estimated_mark.to_string())
+                .await?;
+
+            // Basis of the last traded price against the oracle
+            let summary_key = redis_keys::market_summary(&market_id);
+            let last_price: Option<f64> = conn.hget(&summary_key, "last_price").await?;
+            if let Some(last_price) = last_price.filter(|p| *p > 0.0) {
+                let basis_bps = (last_price - estimated_mark) / estimated_mark * 10_000.0;
+                conn.hset::<_, _, _, ()>(&summary_key, "basis_bps", basis_bps.to_string())
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Push a point onto the market's mid-price series, and report the mark
+    // crossing the divergence threshold in either direction
+    async fn record_mid_price(
+        &self,
+        market_id: &str,
+        point: MidPricePoint,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let recent_points = self.mid_prices.recent_points;
+        if recent_points > 0 {
+            let key = redis_keys::mid_prices(market_id);
+            let mut conn = self.connection.clone();
+            redis::pipe()
+                .lpush(&key, serde_json::to_string(&point)?)
+                .ignore()
+                .ltrim(&key, 0, recent_points as isize - 1)
+                .ignore()
+                .query_async::<()>(&mut conn)
+                .await?;
+        }
+
+        let Some(divergence_bps) = point.divergence_bps else {
+            return Ok(());
+        };
+        let (alert, threshold_bps) = {
+            let mut monitor = self.divergence.lock().await;
+            (
+                monitor.check(market_id, divergence_bps),
+                monitor.threshold_bps(),
+            )
+        };
+        let Some(alert) = alert else {
+            return Ok(());
+        };
+        match alert {
+            DivergenceAlert::Raised => warn!(
+                "Mark price of {} is {:.1} bps from the mid ({:?} against {}); the oracle or the book may be stale",
+                market_id, divergence_bps, point.mark_price, point.mid_price
+            ),
+            DivergenceAlert::Cleared => info!(
+                "Mark price of {} is back within {} bps of the mid",
+                market_id, threshold_bps
+            ),
+        }
+
+        if let Some(pubsub) = &self.pubsub {
+            let alert_data = serde_json::json!({
+                "market_id": market_id,
+                "alert": alert.as_str(),
+                "mark_price": point.mark_price,
+                "mid_price": point.mid_price,
+                "divergence_bps": divergence_bps,
+                "threshold_bps": threshold_bps,
+                "block_height": point.block_height.to_string(),
+                "timestamp": point.timestamp.to_string(),
+            });
+            let event = StreamEvent::new(EventType::MarkDivergence, point.timestamp, alert_data);
+            if let Err(e) = pubsub.publish_event(event).await {
+                warn!("Failed to publish mark divergence alert: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    // Store the best bid and ask of a full orderbook snapshot, plus its
+    // aggregated levels for impact estimates and depth readers. The levels
+    // are on the market's configured tick; the best prices are always exact.
+    // Apply stream deltas to the live books and store the tops of the books
+    // that changed. Markets without a book, or with a gap, are resnapshotted
+    // with one L3 query each, concurrently; a failed query leaves the market
+    // to be tried again with its next delta. A gap also asks the producer to
+    // republish the market's full book, for everything built from full books,
+    // and is announced as a SystemEvent.
+    async fn update_live_books(
+        &self,
+        orderbooks: &[OrderbookPayload],
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(snapshots) = &self.book_snapshots else {
+            return Ok(());
+        };
+
+        let mut changed = HashSet::new();
+        let mut resync = HashMap::new();
+        let mut gaps = Vec::new();
+        {
+            let mut tracker = self.live_books.lock().await;
+            for delta in orderbooks.iter().filter(|d| !d.market_id.is_empty()) {
+                match tracker.apply(delta) {
+                    DeltaOutcome::Applied => {
+                        changed.insert(delta.market_id.as_str());
                     }
-                    ProcessingPhase::Others => {
-                        // In Others phase, process non-market messages
-                        info!(
-                            "Processing message type {:?} at block {}",
-                            msg_type, message.block_height
-                        );
+                    DeltaOutcome::Stale => {}
+                    DeltaOutcome::NeedsSnapshot => {
+                        resync.insert(delta.market_id.as_str(), delta.sequence);
+                    }
+                    DeltaOutcome::Gap { expected, received } => {
+                        resync.insert(delta.market_id.as_str(), delta.sequence);
+                        gaps.push((delta.market_id.as_str(), expected, received));
+                    }
+                }
+            }
+        }
 
-                        // Add special debug for positions
-                        if *msg_type == MessageType::ExchangePosition {
-                            if let KafkaPayload::ExchangePositions(positions) = &message.payload {
-                                info!(
-                                    "DEBUG-5: Received {} positions at block {}",
-                                    positions.len(),
-                                    message.block_height
-                                );
-                            }
-                        }
+        for (market_id, expected, received) in gaps {
+            self.report_sequence_gap(market_id, expected, received, block_height, timestamp)
+                .await?;
+        }
+
+        let fetched = join_all(resync.into_iter().map(|(market_id, sequence)| async move {
+            (market_id, sequence, snapshots.l3_orderbook(market_id).await)
+        }))
+        .await;
+        let mut tracker = self.live_books.lock().await;
+        for (market_id, sequence, snapshot) in fetched {
+            match snapshot {
+                Ok(snapshot) => {
+                    info!(
+                        "Resnapshotted live book of {} at sequence {}",
+                        market_id, sequence
+                    );
+                    tracker.resync(&snapshot, sequence);
+                    changed.insert(market_id);
+                }
+                Err(e) => warn!("Failed to resnapshot live book of {}: {}", market_id, e),
+            }
+        }
+
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let mut pipe = redis::pipe();
+        for market_id in changed {
+            let Some(book) = tracker.book(market_id) else {
+                continue;
+            };
+            let (best_bid, best_ask) = (book.best_bid(), book.best_ask());
+            self.state
+                .put_book(&TopOfBook {
+                    market_id: market_id.to_string(),
+                    best_bid: best_bid.map(|level| level.price),
+                    best_ask: best_ask.map(|level| level.price),
+                    best_bid_quantity: best_bid.map_or(0.0, |level| level.quantity),
+                    best_ask_quantity: best_ask.map_or(0.0, |level| level.quantity),
+                    block_height: block_height as i64,
+                    timestamp: time::to_datetime(timestamp as i64),
+                })
+                .await?;
+
+            let key = redis_keys::derivative_orderbook(market_id);
+            pipe.hset(&key, "sequence", book.sequence()).ignore();
+            match book.mid_price() {
+                Some(mid_price) => pipe.hset(&key, "mid_price", mid_price.to_string()),
+                None => pipe.hdel(&key, "mid_price"),
+            }
+            .ignore();
+        }
+        drop(tracker);
+
+        let mut conn = self.connection.clone();
+        pipe.query_async::<()>(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn report_sequence_gap(
+        &self,
+        market_id: &str,
+        expected: u64,
+        received: u64,
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        warn!(
+            "Orderbook delta gap in {}: expected sequence {}, received {}",
+            market_id, expected, received
+        );
+        let gap = serde_json::json!({
+            "market_id": market_id,
+            "expected": expected,
+            "received": received,
+            "block_height": block_height.to_string(),
+        });
+
+        // Kept out of the write pipelines, like the liquidation alerts
+        let mut conn = self.connection.clone();
+        conn.publish::<_, _, ()>(redis_keys::orderbook_resync_channel(), gap.to_string())
+            .await?;
+
+        if let Some(pubsub) = &self.pubsub {
+            let mut payload = gap;
+            payload["event"] = serde_json::Value::from("orderbook_gap");
+            let event = StreamEvent::new(EventType::SystemEvent, timestamp, payload);
+            if let Err(e) = pubsub.publish_event(event).await {
+                warn!("Failed to publish orderbook gap event: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn process_top_of_book(
+        &self,
+        orderbook: &FullLimitOrderbookPayload,
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (best_bid, best_ask) = orderbook::top_of_book(orderbook, PRICE_DECIMAL, CHAIN_DECIMAL);
+        let best_bid = best_bid.map(|level| (level.price, level.quantity));
+        let best_ask = best_ask.map(|level| (level.price, level.quantity));
+
+        self.state
+            .put_book(&TopOfBook {
+                market_id: orderbook.market_id.clone(),
+                best_bid: best_bid.map(|(price, _)| price),
+                best_ask: best_ask.map(|(price, _)| price),
+                best_bid_quantity: best_bid.map_or(0.0, |(_, quantity)| quantity),
+                best_ask_quantity: best_ask.map_or(0.0, |(_, quantity)| quantity),
+                block_height: block_height as i64,
+                timestamp: time::to_datetime(timestamp as i64),
+            })
+            .await?;
+
+        let mut conn = self.connection.clone();
+        let mark_price: Option<f64> = conn
+            .hget(
+                redis_keys::derivative_market(&orderbook.market_id),
+                "mark_price",
+            )
+            .await?;
+        if let Some(point) = MidPricePoint::new(
+            best_bid.map(|(price, _)| price),
+            best_ask.map(|(price, _)| price),
+            mark_price,
+            block_height,
+            timestamp,
+        ) {
+            self.record_mid_price(&orderbook.market_id, point).await?;
+        }
+
+        let tick = self.depth.tick_sizes.get(&orderbook.market_id).copied();
+        let mut depth = orderbook::aggregate_book(orderbook, PRICE_DECIMAL, CHAIN_DECIMAL, tick);
+
+        conn.hset_multiple::<_, _, _, ()>(
+            redis_keys::derivative_depth(&orderbook.market_id),
+            &[
+                ("bids", serde_json::to_string(&depth.bids)?),
+                ("asks", serde_json::to_string(&depth.asks)?),
+                ("block_height", block_height.to_string()),
+            ],
+        )
+        .await?;
+
+        if let (true, Some(pubsub)) = (self.depth.publish, &self.pubsub) {
+            depth.truncate(self.depth.publish_levels);
+            let depth_data = serde_json::json!({
+                "market_id": orderbook.market_id,
+                "bids": depth.bids,
+                "asks": depth.asks,
+                "block_height": block_height.to_string(),
+                "timestamp": timestamp.to_string(),
+            });
+            let event = StreamEvent::new(EventType::DepthSnapshot, timestamp, depth_data);
+            if let Err(e) = pubsub.publish_event(event).await {
+                warn!("Failed to publish depth snapshot: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Spot markets are kept as the chain reports them; there is no derived
+    // state to compute for them
+    async fn process_spot_markets(
+        &self,
+        markets: &[SpotMarketPayload],
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if markets.is_empty() {
+            return Ok(());
+        }
+
+        let mut pipe = redis::pipe();
+        for market in markets {
+            let fields = [
+                ("ticker", market.ticker.clone()),
+                ("base_denom", market.base_denom.clone()),
+                ("quote_denom", market.quote_denom.clone()),
+                ("base_decimals", market.base_decimals.to_string()),
+                ("quote_decimals", market.quote_decimals.to_string()),
+                ("maker_fee_rate", market.maker_fee_rate.clone()),
+                ("taker_fee_rate", market.taker_fee_rate.clone()),
+                ("min_price_tick", market.min_price_tick.clone()),
+                ("min_quantity_tick", market.min_quantity_tick.clone()),
+                ("min_notional", market.min_notional.clone()),
+                ("status", market.status.clone()),
+                ("block_height", block_height.to_string()),
+                ("timestamp", timestamp.to_string()),
+            ];
+            pipe.hset_multiple(redis_keys::spot_market(&market.market_id), &fields)
+                .ignore()
+                .sadd(redis_keys::spot_markets(), &market.market_id)
+                .ignore();
+        }
+
+        let mut conn = self.connection.clone();
+        pipe.query_async::<()>(&mut conn).await?;
+        Ok(())
+    }
+
+    // Best bid, ask and mid of a spot book. Spot prices and quantities stay in
+    // chain units, like the spot trades and summaries.
+    async fn process_spot_top_of_book(
+        &self,
+        orderbook: &FullLimitOrderbookPayload,
+        block_height: u64,
+        timestamp: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (best_bid, best_ask) = orderbook::top_of_book(orderbook, 1.0, 1.0);
+        if let Some(point) = MidPricePoint::new(
+            best_bid.map(|level| level.price),
+            best_ask.map(|level| level.price),
+            None,
+            block_height,
+            timestamp,
+        ) {
+            self.record_mid_price(&orderbook.market_id, point).await?;
+        }
 
-                        if let Err(e) = self.process_non_market_message(&message).await {
-                            error!("DEBUG-7: Error processing message: {}", e);
-                            return Err(e);
+        let key = redis_keys::spot_orderbook(&orderbook.market_id);
+        let mut pipe = redis::pipe();
+        // An empty side clears its fields, as for derivative books
+        for (side, best) in [("best_bid", best_bid), ("best_ask", best_ask)] {
+            let quantity_field = format!("{}_quantity", side);
+            match best {
+                Some(level) => pipe.hset_multiple(
+                    &key,
+                    &[
+                        (side, level.price.to_string()),
+                        (quantity_field.as_str(), level.quantity.to_string()),
+                    ],
+                ),
+                None => pipe.hdel(&key, &[side, quantity_field.as_str()]),
+            }
+            .ignore();
+        }
+        pipe.hset_multiple(
+            &key,
+            &[
+                ("block_height", block_height.to_string()),
+                ("timestamp", timestamp.to_string()),
+            ],
+        )
+        .ignore();
+
+        let mut conn = self.connection.clone();
+        pipe.query_async::<()>(&mut conn).await?;
+
+        Ok(())
+    }
+}
+#[async_trait]
+impl MessageProcessor for RedisProcessor {
+    // Positions are priced against the markets the preloader stores, so the
+    // consumer waits for them instead of deferring messages itself
+    fn prerequisites(&self) -> &[Prerequisite] {
+        &[Prerequisite::MarketsReady]
+    }
+
+    async fn process_message(&self, message: KafkaMessage) -> Result<(), IndexerError> {
+        match &message.message_type {
+            MessageType::DerivativeMarket => {
+                if let KafkaPayload::DerivativeMarkets(markets) = &message.payload {
+                    info!(
+                        "Processing {} derivative markets at block {}",
+                        markets.len(),
+                        message.block_height
+                    );
+                    for market in markets {
+                        if let Err(e) = self
+                            .process_derivative_market(
+                                market,
+                                message.block_height,
+                                message.block_time,
+                            )
+                            .await
+                        {
+                            error!("Error processing derivative market: {}", e);
                         }
                     }
+                } else {
+                    error!("Received DerivativeMarket message type but payload is not DerivativeMarkets");
                 }
             }
+            msg_type => {
+                debug!(
+                    "Processing message type {:?} at block {}",
+                    msg_type, message.block_height
+                );
+                self.process_non_market_message(&message).await?;
+            }
         }
 
         Ok(())
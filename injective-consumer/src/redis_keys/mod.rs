@@ -0,0 +1,324 @@
+// Redis key layout shared by the processors that write it and the readers that
+// query it. Nothing outside this module should format keys by hand.
+//
+// Every key below, and the two plain channels, is namespaced by the installed
+// RedisKeySchema: `{prefix}:{environment}:market:derivative:{id}` with the
+// default `:` separator, or the layout as listed when neither is set. Several
+// indexers can then share one Redis.
+//
+// Layout version 2:
+//   market:derivative:{market_id}            hash   market state (scaled)
+//   markets:derivative                       set    market ids
+//   markets:settled                          set    expiry futures market ids settled at expiry
+//   market:spot:{market_id}                  hash   spot market state
+//   markets:spot                             set    spot market ids
+//   position:{market_id}:{subaccount_id}     hash   position state (scaled)
+//   positions:market:{market_id}             set    subaccount ids
+//   positions:subaccount:{subaccount_id}     set    market ids
+//   liquidatable_positions                   set    {market_id}:{subaccount_id}
+//   positions:at_risk                        zset   {market_id}:{subaccount_id} by % distance to liquidation
+//   orderbook:derivative:{market_id}         hash   top of book, plus mid_price and sequence of the live book
+//   orderbook:depth:{market_id}              hash   aggregated L2 levels per side (JSON)
+//   orderbook:spot:{market_id}               hash   spot top of book (chain units)
+//   prices:mid:{market_id}                   list   recent mid prices with mark divergence (JSON), newest first
+//   summary:derivative:{market_id}           hash   rolling 24h market summary
+//   summary:spot:{market_id}                 hash   rolling 24h spot summary (chain units)
+//   summary:buckets:{market_id}              hash   ring slot -> JSON hour bucket
+//   trades:spot:{market_id}                  list   recent spot trades, newest first
+//   trades:subaccount:{subaccount_id}        list   recent spot and derivative trade sides (JSON), newest first
+//   addresses                                set    owner addresses with positions
+//   address:{address}                        hash   aggregates across subaccounts
+//   address:subaccounts:{address}            set    subaccount ids with positions
+//   volatility:{market_id}                   hash   realized variance and vol per window
+//   correlation:{resolution}                 string JSON return correlation matrix
+//   oracle:markets:{symbol}                  set    market ids using the symbol as base or quote
+//   oracle:prices                            hash   oracle symbol -> latest price
+//   oracle:price:{symbol}                    hash   latest price, oracle type, block height and time
+//   schema:version                           string layout version
+//   gateway:clients                          zset   client ids scored by expiry
+//   gateway:subscriptions:{client_id}        hash   subscription id -> filter
+//   config:payload_log                       hash   runtime payload logging policy
+//   pubsub:dedup:{event_hash}                string publish claim, expires after the dedup TTL
+//   ingest:claim:{group}:{type}:{height}:{hash} string message claim per consumer group, expires after the dedup TTL
+//
+// Version 1 stored markets as JSON strings under market:{market_id}:data. Those
+// keys are still read as a fallback until `migrate-keys` has been run; they
+// predate the schema and are never namespaced.
+
+use crate::config::RedisKeySchema;
+use log::warn;
+use std::sync::OnceLock;
+
+static SCHEMA: OnceLock<RedisKeySchema> = OnceLock::new();
+
+// Set the schema every key in this process is built with. Call it once at
+// startup, before anything touches Redis; until then keys use the default
+// layout, and later calls with another schema are ignored.
+pub fn install(schema: RedisKeySchema) {
+    if let Err(schema) = SCHEMA.set(schema) {
+        if &schema != self::schema() {
+            warn!("Redis key schema already installed, ignoring {:?}", schema);
+        }
+    }
+}
+
+pub fn schema() -> &'static RedisKeySchema {
+    SCHEMA.get_or_init(RedisKeySchema::default)
+}
+
+impl RedisKeySchema {
+    // The namespace followed by the segments, joined by the separator
+    pub fn key(&self, segments: &[&str]) -> String {
+        let namespace = [Some(self.prefix.as_str()), self.environment.as_deref()];
+        namespace
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.is_empty())
+            .chain(segments.iter().copied())
+            .collect::<Vec<_>>()
+            .join(&self.separator)
+    }
+
+    // What every key under the segments starts with
+    pub fn key_prefix(&self, segments: &[&str]) -> String {
+        format!("{}{}", self.key(segments), self.separator)
+    }
+}
+
+fn key(segments: &[&str]) -> String {
+    schema().key(segments)
+}
+
+// Prefix shared by the keys under `segments`, e.g. `&["position"]`, for SCAN
+// patterns and for stripping back to the ids
+pub fn key_prefix(segments: &[&str]) -> String {
+    schema().key_prefix(segments)
+}
+
+// Current layout version, stored under schema_version_key() once migrated
+pub const SCHEMA_VERSION: u32 = 2;
+
+pub fn schema_version_key() -> String {
+    key(&["schema", "version"])
+}
+
+pub fn derivative_markets() -> String {
+    key(&["markets", "derivative"])
+}
+
+// Expiry futures markets that have settled; the liquidation recompute skips them
+pub fn settled_markets() -> String {
+    key(&["markets", "settled"])
+}
+
+pub fn spot_markets() -> String {
+    key(&["markets", "spot"])
+}
+
+pub fn liquidatable_positions() -> String {
+    key(&["liquidatable_positions"])
+}
+
+// Scored by distance to liquidation in percent of the mark price; members are
+// formatted like the liquidatable positions set
+pub fn at_risk_positions() -> String {
+    key(&["positions", "at_risk"])
+}
+
+pub fn markets_ready() -> String {
+    key(&["markets_ready"])
+}
+
+pub fn processing_phase() -> String {
+    key(&["processing_phase"])
+}
+
+pub fn liquidation_alerts_channel() -> String {
+    key(&["liquidation_alerts"])
+}
+
+// Orderbook resync requests for the producer, JSON with the market and the gap
+pub fn orderbook_resync_channel() -> String {
+    key(&["orderbook_resync"])
+}
+
+pub fn addresses() -> String {
+    key(&["addresses"])
+}
+
+pub fn oracle_prices() -> String {
+    key(&["oracle", "prices"])
+}
+
+// Hash with the latest state of a derivative market
+pub fn derivative_market(market_id: &str) -> String {
+    key(&["market", "derivative", market_id])
+}
+
+// Hash with the latest state of a spot market
+pub fn spot_market(market_id: &str) -> String {
+    key(&["market", "spot", market_id])
+}
+
+// Hash with the latest state of a position
+pub fn position(market_id: &str, subaccount_id: &str) -> String {
+    key(&["position", market_id, subaccount_id])
+}
+
+// Set of subaccount ids holding a position in a market
+pub fn positions_by_market(market_id: &str) -> String {
+    key(&["positions", "market", market_id])
+}
+
+// Set of market ids a subaccount holds positions in
+pub fn positions_by_subaccount(subaccount_id: &str) -> String {
+    key(&["positions", "subaccount", subaccount_id])
+}
+
+// Member of the liquidatable positions set. Members are values, not keys, so
+// they keep the `:` whatever the separator.
+pub fn liquidatable_member(market_id: &str, subaccount_id: &str) -> String {
+    format!("{}:{}", market_id, subaccount_id)
+}
+
+// Split a liquidatable positions set member back into (market_id, subaccount_id)
+pub fn parse_liquidatable_member(member: &str) -> Option<(&str, &str)> {
+    member.split_once(':')
+}
+
+// Hash with the top of book of a derivative market, and the mid and delta
+// sequence of its live book when stream deltas are followed
+pub fn derivative_orderbook(market_id: &str) -> String {
+    key(&["orderbook", "derivative", market_id])
+}
+
+// Hash with the aggregated price levels of a derivative market's book
+pub fn derivative_depth(market_id: &str) -> String {
+    key(&["orderbook", "depth", market_id])
+}
+
+// Hash with the top of book of a spot market, in chain units
+pub fn spot_orderbook(market_id: &str) -> String {
+    key(&["orderbook", "spot", market_id])
+}
+
+// List of a market's most recent mid prices as JSON, newest first
+pub fn mid_prices(market_id: &str) -> String {
+    key(&["prices", "mid", market_id])
+}
+
+// Hash with the rolling 24h summary of a derivative market
+pub fn market_summary(market_id: &str) -> String {
+    key(&["summary", "derivative", market_id])
+}
+
+// Hash with the rolling 24h summary of a spot market, in chain units
+pub fn spot_market_summary(market_id: &str) -> String {
+    key(&["summary", "spot", market_id])
+}
+
+// List of a spot market's most recent trades as JSON, newest first
+pub fn spot_trades(market_id: &str) -> String {
+    key(&["trades", "spot", market_id])
+}
+
+// List of a subaccount's most recent trade sides as JSON, newest first
+pub fn subaccount_trades(subaccount_id: &str) -> String {
+    key(&["trades", "subaccount", subaccount_id])
+}
+
+// Hash holding the hourly ring a 24h summary is computed from. Market ids are
+// unique across spot and derivative markets, so both share this prefix.
+pub fn summary_buckets(market_id: &str) -> String {
+    key(&["summary", "buckets", market_id])
+}
+
+// Hash with position aggregates across an owner address's subaccounts
+pub fn address(address: &str) -> String {
+    key(&["address", address])
+}
+
+// Set of an owner address's subaccounts that hold positions
+pub fn address_subaccounts(address: &str) -> String {
+    key(&["address", "subaccounts", address])
+}
+
+// Hash with realized variance (`rv_{window}`) and annualized volatility
+// (`vol_{window}`) of a derivative market for the 1h, 24h and 7d windows
+pub fn volatility(market_id: &str) -> String {
+    key(&["volatility", market_id])
+}
+
+// Set of derivative market ids whose oracle base or quote is `symbol`
+pub fn oracle_markets(symbol: &str) -> String {
+    key(&["oracle", "markets", symbol])
+}
+
+// Hash with the latest price of an oracle symbol and the block it came with
+pub fn oracle_price(symbol: &str) -> String {
+    key(&["oracle", "price", symbol])
+}
+
+// JSON correlation matrix computed from candles of the given resolution
+pub fn correlation_matrix(resolution: &str) -> String {
+    key(&["correlation", resolution])
+}
+
+// Version 1 JSON market key
+pub fn legacy_market(market_id: &str) -> String {
+    format!("market:{}:data", market_id)
+}
+
+// SCAN pattern matching every version 1 market key
+pub const LEGACY_MARKET_PATTERN: &str = "market:*:data";
+
+// Extract the market id from a version 1 market key
+pub fn parse_legacy_market(key: &str) -> Option<&str> {
+    key.strip_prefix("market:")?.strip_suffix(":data")
+}
+
+// Gateway client subscriptions, kept apart from the indexed state
+pub fn gateway_clients() -> String {
+    key(&["gateway", "clients"])
+}
+
+// Hash of subscription id -> JSON filter for one gateway client
+pub fn gateway_subscriptions(client_id: &str) -> String {
+    key(&["gateway", "subscriptions", client_id])
+}
+
+// Claim on publishing an event, keyed by its content hash, so replicas that
+// process the same blocks publish it once
+pub fn pubsub_dedup(event_hash: u64) -> String {
+    key(&["pubsub", "dedup", &format!("{:016x}", event_hash)])
+}
+
+// Claim on applying a Kafka message, so a consumer group applies the copies
+// published by redundant ingesters, or redelivered after a rebalance, once
+pub fn ingest_claim(group: &str, message_type: &str, block_height: u64, hash: u64) -> String {
+    key(&[
+        "ingest",
+        "claim",
+        group,
+        message_type,
+        &block_height.to_string(),
+        &format!("{:016x}", hash),
+    ])
+}
+
+// Claim on alerting a liquidatable position at a block, so a replayed block
+// doesn't alert it again
+pub fn liquidation_alert_claim(market_id: &str, subaccount_id: &str, block_height: i64) -> String {
+    key(&[
+        "liquidation",
+        "alert",
+        market_id,
+        subaccount_id,
+        &block_height.to_string(),
+    ])
+}
+
+// Operator overrides read by running consumers
+pub fn payload_log_config() -> String {
+    key(&["config", "payload_log"])
+}
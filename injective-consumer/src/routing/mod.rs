@@ -0,0 +1,120 @@
+use crate::pubsub::{EventType, StreamEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// How a routed event is delivered
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    // Fire-and-forget Redis PUBLISH
+    #[default]
+    PubSub,
+    // Capped Redis stream (XADD), so consumers can resume after a disconnect
+    Stream,
+}
+
+// A single routing rule. Empty match lists match everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoutingRule {
+    pub event_types: Vec<EventType>,
+    // Tier names from RoutingConfig::market_tiers
+    pub market_tiers: Vec<String>,
+    // Only match events for subaccounts on this watchlist
+    pub watchlist: Option<String>,
+    pub channel_prefix: String,
+    pub transport: Transport,
+    // Matching events are not also published to the default namespace
+    pub exclusive: bool,
+}
+
+// Routing configuration mapping events to channel namespaces. Events matching
+// no exclusive rule are always published to the default namespace as before.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoutingConfig {
+    pub rules: Vec<RoutingRule>,
+    // market_id -> tier name
+    pub market_tiers: HashMap<String, String>,
+    // watchlist name -> subaccount ids
+    pub watchlists: HashMap<String, HashSet<String>>,
+    // Approximate maximum length of streams written by Stream routes
+    pub stream_max_len: usize,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        RoutingConfig {
+            rules: Vec::new(),
+            market_tiers: HashMap::new(),
+            watchlists: HashMap::new(),
+            stream_max_len: 10_000,
+        }
+    }
+}
+
+// A destination namespace for an event
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Route {
+    pub channel_prefix: String,
+    pub transport: Transport,
+}
+
+impl RoutingConfig {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        let config: RoutingConfig = serde_json::from_str(&contents)?;
+        Ok(config)
+    }
+
+    // Routes for an event, and whether it should also go to the default namespace
+    pub fn routes(&self, event: &StreamEvent) -> (Vec<Route>, bool) {
+        let market_id = event.payload.get("market_id").and_then(|v| v.as_str());
+        let subaccount_id = event.payload.get("subaccount_id").and_then(|v| v.as_str());
+        let tier = market_id.and_then(|id| self.market_tiers.get(id));
+
+        let mut routes = Vec::new();
+        let mut include_default = true;
+
+        for rule in &self.rules {
+            if !rule.event_types.is_empty() && !rule.event_types.contains(&event.event_type) {
+                continue;
+            }
+            if !rule.market_tiers.is_empty()
+                && !tier.is_some_and(|tier| rule.market_tiers.contains(tier))
+            {
+                continue;
+            }
+            if let Some(watchlist) = &rule.watchlist {
+                let watched = subaccount_id.is_some_and(|subaccount_id| {
+                    self.watchlists
+                        .get(watchlist)
+                        .is_some_and(|members| members.contains(subaccount_id))
+                });
+                if !watched {
+                    continue;
+                }
+            }
+
+            let route = Route {
+                channel_prefix: rule.channel_prefix.clone(),
+                transport: rule.transport,
+            };
+            if !routes.contains(&route) {
+                routes.push(route);
+            }
+            if rule.exclusive {
+                include_default = false;
+            }
+        }
+
+        (routes, include_default)
+    }
+}
@@ -0,0 +1,93 @@
+// How the chain scales each kind of derivative value, in one place. Values
+// arrive as integer strings: prices, margins, fees and funding in 1e24 (the
+// quote asset's 6 decimals on top of the 1e18 fixed point), quantities, ratios
+// and rates in 1e18. Oracle prices are plain 1e18 fixed point. The functions
+// return human units; an unparsable value reads as zero. Spot values also
+// depend on the market's decimals; see spot_price.
+
+// Fixed point of prices, margins, fees and funding
+pub const PRICE_DECIMAL: f64 = 1e24;
+// Fixed point of quantities, ratios, rates and oracle prices
+pub const CHAIN_DECIMAL: f64 = 1e18;
+
+// The same scales as decimal places, for compute::decimal::from_chain
+pub const PRICE_DECIMALS: u32 = 24;
+pub const CHAIN_DECIMALS: u32 = 18;
+
+// Maintenance margin ratio assumed when a market doesn't report one
+pub const DEFAULT_MAINTENANCE_MARGIN_RATIO: f64 = 0.05;
+
+fn scaled(raw: &str, scale: f64) -> f64 {
+    raw.parse::<f64>().unwrap_or(0.0) / scale
+}
+
+// Mark, entry, execution, settlement and order prices
+pub fn price(raw: &str) -> f64 {
+    scaled(raw, PRICE_DECIMAL)
+}
+
+// Position and order margin
+pub fn margin(raw: &str) -> f64 {
+    scaled(raw, PRICE_DECIMAL)
+}
+
+// Trade fees, paid in the quote asset
+pub fn fee(raw: &str) -> f64 {
+    scaled(raw, PRICE_DECIMAL)
+}
+
+// Cumulative funding of a market or at a position's entry
+pub fn funding(raw: &str) -> f64 {
+    scaled(raw, PRICE_DECIMAL)
+}
+
+// Position, order and execution quantities
+pub fn quantity(raw: &str) -> f64 {
+    scaled(raw, CHAIN_DECIMAL)
+}
+
+// Margin ratios, fee rates, funding rate caps, interest rates and the
+// market's cumulative price (the funding premium)
+pub fn ratio(raw: &str) -> f64 {
+    scaled(raw, CHAIN_DECIMAL)
+}
+
+// Maintenance margin ratio, or the default when it is missing or unparsable
+pub fn maintenance_margin_ratio(raw: &str) -> f64 {
+    raw.parse::<f64>()
+        .map_or(DEFAULT_MAINTENANCE_MARGIN_RATIO, |ratio| {
+            ratio / CHAIN_DECIMAL
+        })
+}
+
+// Maintenance margin ratio as read back from ScyllaDB's markets table, which
+// stores it scaled. Older rows hold the chain value; a ratio is never above
+// one, so anything larger is scaled here.
+pub fn stored_maintenance_margin_ratio(stored: &str) -> f64 {
+    match stored.parse::<f64>() {
+        Ok(ratio) if ratio > 1.0 => ratio / CHAIN_DECIMAL,
+        Ok(ratio) if ratio > 0.0 => ratio,
+        _ => DEFAULT_MAINTENANCE_MARGIN_RATIO,
+    }
+}
+
+// Spot prices are 1e18 fixed point in quote per base, both in the assets'
+// smallest units, so the market's decimals are needed to read them
+pub fn spot_price(raw: &str, base_decimals: u32, quote_decimals: u32) -> f64 {
+    scaled(raw, CHAIN_DECIMAL) * 10f64.powi(base_decimals as i32 - quote_decimals as i32)
+}
+
+// Spot quantities, in the base asset's smallest units
+pub fn spot_quantity(raw: &str, base_decimals: u32) -> f64 {
+    scaled(raw, CHAIN_DECIMAL) / 10f64.powi(base_decimals as i32)
+}
+
+// Spot trade fees, in the quote asset's smallest units
+pub fn spot_fee(raw: &str, quote_decimals: u32) -> f64 {
+    scaled(raw, CHAIN_DECIMAL) / 10f64.powi(quote_decimals as i32)
+}
+
+// Prices of oracle symbols
+pub fn oracle_price(raw: &str) -> f64 {
+    scaled(raw, CHAIN_DECIMAL)
+}
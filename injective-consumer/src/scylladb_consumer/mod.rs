@@ -1,31 +1,675 @@
-use crate::compute::{calculate_liquidation_price, is_liquidatable};
+use crate::candles::{Candle, CandleAggregator, ClosedCandle, Resolution};
+use crate::compute::{self, calculate_liquidation_price, is_liquidatable};
+use crate::config::{IdempotencyMode, ScyllaDBConfig, WriteTimestampSource};
 use crate::consumer::MessageProcessor;
-use crate::models::{KafkaMessage, KafkaPayload};
+use crate::correlation::{CorrelationMatrix, CorrelationSink};
+use crate::error::{IndexerError, StorageError};
+use crate::expiry::{self, MarketSettlement};
+use crate::metrics;
+use crate::mid_price::MidPricePoint;
+use crate::models::time::{self, HOUR_MILLIS};
+#[cfg(feature = "api")]
+use crate::models::SubaccountTrade;
+use crate::models::{
+    DerivativeTradePayload, FullLimitOrderbookPayload, KafkaMessage, KafkaPayload, MarketType,
+    OraclePricePayload, PositionSource, SpotMarketPayload, SpotTradePayload,
+};
+use crate::orderbook;
+use crate::position_diff::PositionDiffer;
+#[cfg(feature = "pubsub")]
+use crate::pubsub::{EventType, RedisPubSubService, StreamEvent};
+use crate::scaling::{self, CHAIN_DECIMAL, DEFAULT_MAINTENANCE_MARGIN_RATIO, PRICE_DECIMAL};
+use crate::storage::{self, HistoryStore, ScyllaHistoryStore};
+#[cfg(feature = "api")]
+use crate::trade_history::{TradeCursor, TradeHistorySource};
+#[cfg(feature = "api")]
+use crate::udf::CandleSource;
+use crate::volatility::VolatilityTracker;
+use crate::window::{Aggregate, WindowSpec, WindowedAggregator};
 use async_trait::async_trait;
-use chrono::{DateTime, LocalResult, TimeZone, Utc};
-use log::{error, info, warn};
-use scylla::{Session, SessionBuilder};
-use scylla::frame::value::CqlTimestamp;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use log::{debug, error, info, warn};
+use scylla::batch::{Batch, BatchType};
+use scylla::frame::response::result::{CqlValue, Row};
+use scylla::frame::value::{Counter, CqlTimestamp};
+use scylla::prepared_statement::PreparedStatement;
+use scylla::serialize::batch::BatchValues;
+use scylla::serialize::row::SerializeRow;
+use scylla::transport::errors::QueryError;
+use scylla::QueryResult;
+use scylla::Session;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 
-// Add these constants to match the other file
-const PRICE_DECIMAL: f64 = 1e24;
-const QUANTITY_DECIMAL: f64 = 1e18;
+/// Rows written to a table during one hour for one message type
+#[derive(Debug, Clone, Serialize)]
+pub struct WriteCount {
+    pub table_name: String,
+    pub message_type: String,
+    pub rows: i64,
+}
+
+/// Trade classification for one market and hour. Every match is reported
+/// once for the resting (maker) order and once for the incoming (taker) order;
+/// the taker side also gives the aggressor direction.
+#[derive(Debug, Clone, Serialize)]
+pub struct TradeClassification {
+    pub market_id: String,
+    pub date_hour: DateTime<Utc>,
+    pub maker_volume: f64,
+    pub taker_volume: f64,
+    pub taker_buy_volume: f64,
+    pub taker_sell_volume: f64,
+    pub taker_buy_count: i64,
+    pub taker_sell_count: i64,
+    // Share of taker volume that bought
+    pub buy_volume_ratio: f64,
+}
+
+// Running trade totals for one market and hour
+#[derive(Debug, Default, Clone, Copy)]
+struct HourlyTradeStats {
+    volume: f64,
+    trade_count: i64,
+    taker_buy_count: i64,
+    maker_volume: f64,
+    taker_buy_volume: f64,
+}
+
+// Folds the totals of one block into the hour
+impl Aggregate for HourlyTradeStats {
+    type Event = HourlyTradeStats;
+
+    fn first(_start: i64, block: &HourlyTradeStats) -> Self {
+        *block
+    }
+
+    fn add(&mut self, block: &HourlyTradeStats) {
+        self.volume += block.volume;
+        self.trade_count += block.trade_count;
+        self.taker_buy_count += block.taker_buy_count;
+        self.maker_volume += block.maker_volume;
+        self.taker_buy_volume += block.taker_buy_volume;
+    }
+}
+
+// Share of `part` in `total`, 0 when there is nothing to divide
+fn ratio(part: f64, total: f64) -> f64 {
+    if total > 0.0 {
+        part / total
+    } else {
+        0.0
+    }
+}
+
+// Columns added to market_statistics after it was first created
+const MARKET_STATISTICS_ADDED_COLUMNS: &[&str] = &[
+    "maker_volume double",
+    "taker_buy_volume double",
+    "taker_sell_volume double",
+    "taker_sell_count bigint",
+    "buy_volume_ratio double",
+];
+
+// Columns added to funding_history after it was first created
+const FUNDING_HISTORY_ADDED_COLUMNS: &[&str] = &[
+    "funding_delta double",
+    "paid_funding_rate double",
+    "estimated_funding_rate double",
+    "hourly_funding_rate_cap double",
+    "hourly_interest_rate double",
+];
+
+// Added to positions and market_positions after they were first created
+const POSITION_ADDED_COLUMNS: &[&str] = &["funding_payment text", "settlement_price text"];
+
+// Entity markers per unlogged batch; a batch stays in one partition
+const ENTITY_MARKER_BATCH: usize = 200;
+
+// Every statement the processor runs, prepared once at startup so the
+// server parses each CQL string only once. Timeouts, tracing and write
+// timestamps are set per execution on a copy (see ScyllaDBProcessor::run).
+struct PreparedStatements {
+    // Bookkeeping
+    write_counter_update: PreparedStatement,
+    write_counts_select: PreparedStatement,
+    processed_select: PreparedStatement,
+    processed_claim: PreparedStatement,
+    processed_insert: PreparedStatement,
+    processed_release: PreparedStatement,
+    processed_entities_select: PreparedStatement,
+    processed_entity_insert: PreparedStatement,
+    // Derivative markets and positions
+    market_insert: PreparedStatement,
+    market_select: PreparedStatement,
+    market_positions_select: PreparedStatement,
+    position_insert: PreparedStatement,
+    market_position_insert: PreparedStatement,
+    position_liquidation_update: PreparedStatement,
+    market_position_liquidation_update: PreparedStatement,
+    market_settlement_insert: PreparedStatement,
+    position_settle: PreparedStatement,
+    market_position_settle: PreparedStatement,
+    position_close: PreparedStatement,
+    market_position_close: PreparedStatement,
+    liquidatable_insert: PreparedStatement,
+    liquidatable_update: PreparedStatement,
+    liquidatable_delete: PreparedStatement,
+    liquidatable_history_insert: PreparedStatement,
+    liquidatable_history_latest: PreparedStatement,
+    // Spot markets and trades
+    spot_market_insert: PreparedStatement,
+    spot_market_decimals_select: PreparedStatement,
+    spot_trade_insert: PreparedStatement,
+    spot_subaccount_trade_insert: PreparedStatement,
+    // Orderbooks; order rows are the highest volume insert
+    orderbook_order_insert: PreparedStatement,
+    orderbook_snapshot_insert: PreparedStatement,
+    orderbook_statistics_update: PreparedStatement,
+    mid_price_insert: PreparedStatement,
+    oracle_price_insert: PreparedStatement,
+    // Statistics, volatility and candles
+    trade_statistics_select: PreparedStatement,
+    trade_statistics_update: PreparedStatement,
+    volatility_insert: PreparedStatement,
+    candle_select: PreparedStatement,
+    candle_insert: PreparedStatement,
+    // Read sources and sinks handed out by the processor
+    #[cfg(feature = "api")]
+    candle_range_select: PreparedStatement,
+    #[cfg(feature = "api")]
+    candle_before_select: PreparedStatement,
+    #[cfg(feature = "api")]
+    subaccount_trades_select: PreparedStatement,
+    #[cfg(feature = "api")]
+    subaccount_trades_before_select: PreparedStatement,
+    correlation_insert: PreparedStatement,
+}
+
+impl PreparedStatements {
+    // Needs the schema in place, since preparing checks tables and columns
+    async fn prepare(session: &Session) -> Result<Self, QueryError> {
+        let prepare = |cql: &'static str| session.prepare(cql);
+        Ok(PreparedStatements {
+            // Bookkeeping
+            write_counter_update: prepare(
+                "UPDATE injective.write_counters SET row_count = row_count + ?
+                    WHERE hour = ? AND table_name = ? AND message_type = ?",
+            )
+            .await?,
+            write_counts_select: prepare(
+                "SELECT table_name, message_type, row_count FROM injective.write_counters WHERE hour = ?",
+            )
+            .await?,
+            processed_select: prepare(
+                "SELECT content_hash FROM injective.processed_messages
+                    WHERE message_type = ? AND block_height = ? AND content_hash = ?",
+            )
+            .await?,
+            processed_claim: prepare(
+                "INSERT INTO injective.processed_messages (
+                    message_type, block_height, content_hash, processed_at
+                ) VALUES (?, ?, ?, ?) IF NOT EXISTS",
+            )
+            .await?,
+            processed_insert: prepare(
+                "INSERT INTO injective.processed_messages (
+                    message_type, block_height, content_hash, processed_at
+                ) VALUES (?, ?, ?, ?)",
+            )
+            .await?,
+            processed_release: prepare(
+                "DELETE FROM injective.processed_messages
+                    WHERE message_type = ? AND block_height = ? AND content_hash = ?",
+            )
+            .await?,
+            processed_entities_select: prepare(
+                "SELECT entity_id FROM injective.processed_entities
+                    WHERE message_type = ? AND block_height = ?",
+            )
+            .await?,
+            processed_entity_insert: prepare(
+                "INSERT INTO injective.processed_entities (
+                    message_type, block_height, entity_id, processed_at
+                ) VALUES (?, ?, ?, ?)",
+            )
+            .await?,
+            // Derivative markets and positions
+            market_insert: prepare(
+                "INSERT INTO injective.markets (
+                    market_id, block_height, timestamp, ticker, mark_price, maintenance_margin_ratio, cumulative_funding
+                ) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            market_select: prepare(
+                "SELECT mark_price, maintenance_margin_ratio, cumulative_funding
+                    FROM injective.markets
+                    WHERE market_id = ?
+                    LIMIT 1",
+            )
+            .await?,
+            market_positions_select: prepare(
+                "SELECT subaccount_id, is_long, quantity, entry_price, margin, cumulative_funding_entry, block_height
+                    FROM injective.market_positions
+                    WHERE market_id = ?
+                    LIMIT 1000",
+            )
+            .await?,
+            position_insert: prepare(
+                "INSERT INTO injective.positions (
+                    market_id, subaccount_id, block_height, timestamp, is_long, quantity,
+                    entry_price, margin, cumulative_funding_entry, liquidation_price,
+                    funding_payment
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            market_position_insert: prepare(
+                "INSERT INTO injective.market_positions (
+                    market_id, subaccount_id, block_height, timestamp, is_long, quantity,
+                    entry_price, margin, cumulative_funding_entry, liquidation_price,
+                    funding_payment
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            position_liquidation_update: prepare(
+                "UPDATE injective.positions
+                    SET liquidation_price = ?, funding_payment = ?
+                    WHERE market_id = ? AND subaccount_id = ? AND block_height = ?",
+            )
+            .await?,
+            market_position_liquidation_update: prepare(
+                "UPDATE injective.market_positions
+                    SET liquidation_price = ?, funding_payment = ?
+                    WHERE market_id = ? AND subaccount_id = ? AND block_height = ?",
+            )
+            .await?,
+            market_settlement_insert: prepare(
+                "INSERT INTO injective.market_settlements (
+                    market_id, expiration_timestamp, settlement_price, block_height, timestamp
+                ) VALUES (?, ?, ?, ?, ?)",
+            )
+            .await?,
+            position_settle: prepare(
+                "UPDATE injective.positions
+                    SET settlement_price = ?
+                    WHERE market_id = ? AND subaccount_id = ? AND block_height = ?",
+            )
+            .await?,
+            market_position_settle: prepare(
+                "UPDATE injective.market_positions
+                    SET settlement_price = ?
+                    WHERE market_id = ? AND subaccount_id = ? AND block_height = ?",
+            )
+            .await?,
+            position_close: prepare(
+                "INSERT INTO injective.positions (
+                    market_id, subaccount_id, block_height, timestamp, quantity
+                ) VALUES (?, ?, ?, ?, '0')",
+            )
+            .await?,
+            market_position_close: prepare(
+                "INSERT INTO injective.market_positions (
+                    market_id, subaccount_id, block_height, timestamp, quantity
+                ) VALUES (?, ?, ?, ?, '0')",
+            )
+            .await?,
+            liquidatable_insert: prepare(
+                "INSERT INTO injective.liquidatable_positions (
+                    market_id, subaccount_id, block_height, timestamp, is_long, quantity,
+                    entry_price, margin, liquidation_price, mark_price
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+            )
+            .await?,
+            liquidatable_update: prepare(
+                "UPDATE injective.liquidatable_positions
+                    SET block_height = ?, timestamp = ?, is_long = ?, quantity = ?,
+                        entry_price = ?, margin = ?, liquidation_price = ?, mark_price = ?
+                    WHERE market_id = ? AND subaccount_id = ?
+                    IF block_height <= ?",
+            )
+            .await?,
+            liquidatable_delete: prepare(
+                "DELETE FROM injective.liquidatable_positions
+                    WHERE market_id = ? AND subaccount_id = ?
+                    IF block_height <= ?",
+            )
+            .await?,
+            liquidatable_history_insert: prepare(
+                "INSERT INTO injective.liquidatable_history (
+                    market_id, subaccount_id, block_height, timestamp, is_liquidatable, is_long,
+                    quantity, entry_price, margin, liquidation_price, mark_price, liquidation_distance
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            liquidatable_history_latest: prepare(
+                "SELECT block_height FROM injective.liquidatable_history
+                    WHERE market_id = ? AND subaccount_id = ?
+                    LIMIT 1",
+            )
+            .await?,
+            // Spot markets and trades
+            spot_market_insert: prepare(
+                "INSERT INTO injective.spot_markets (
+                    market_id, block_height, timestamp, ticker, base_denom, quote_denom,
+                    base_decimals, quote_decimals, maker_fee_rate, taker_fee_rate,
+                    min_price_tick, min_quantity_tick, min_notional, status
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            spot_market_decimals_select: prepare(
+                "SELECT base_decimals, quote_decimals FROM injective.spot_markets
+                    WHERE market_id = ? LIMIT 1",
+            )
+            .await?,
+            spot_trade_insert: prepare(
+                "INSERT INTO injective.spot_trades (
+                    market_id, day, executed_at, trade_id, is_maker, subaccount_id,
+                    is_buy, price, quantity, fee, block_height
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            spot_subaccount_trade_insert: prepare(
+                "INSERT INTO injective.trades_by_subaccount (
+                    subaccount_id, timestamp, trade_id, is_maker, market_id, market_type,
+                    is_buy, price, quantity, fee, block_height
+                ) VALUES (?, ?, ?, ?, ?, 'spot', ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            // Orderbooks; order rows are the highest volume insert
+            orderbook_order_insert: prepare(
+                "INSERT INTO injective.orderbook_orders (
+                    orderbook_id, side, order_hash, price, quantity, subaccount_id
+                ) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            orderbook_snapshot_insert: prepare(
+                "INSERT INTO injective.orderbook_snapshots (
+                    market_id, date_hour, timestamp, block_height, orderbook_id,
+                    bid_count, ask_count, best_bid, best_ask
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            orderbook_statistics_update: prepare(
+                "UPDATE injective.market_statistics SET best_bid = ?, best_ask = ?, mid_price = ?
+                    WHERE market_id = ? AND date_hour = ?",
+            )
+            .await?,
+            mid_price_insert: prepare(
+                "INSERT INTO injective.mid_prices (
+                    market_id, date_hour, timestamp, block_height, mid_price, best_bid, best_ask,
+                    mark_price, divergence_bps
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            oracle_price_insert: prepare(
+                "INSERT INTO injective.oracle_prices (
+                    symbol, date_hour, timestamp, block_height, price, oracle_type
+                ) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            // Statistics, volatility and candles
+            trade_statistics_select: prepare(
+                "SELECT volume, trade_count, taker_buy_count, maker_volume, taker_buy_volume
+                    FROM injective.market_statistics WHERE market_id = ? AND date_hour = ?",
+            )
+            .await?,
+            trade_statistics_update: prepare(
+                "UPDATE injective.market_statistics SET volume = ?, trade_count = ?,
+                    taker_buy_count = ?, taker_buy_ratio = ?, maker_volume = ?,
+                    taker_buy_volume = ?, taker_sell_volume = ?, taker_sell_count = ?,
+                    buy_volume_ratio = ?
+                    WHERE market_id = ? AND date_hour = ?",
+            )
+            .await?,
+            volatility_insert: prepare(
+                "INSERT INTO injective.market_volatility (
+                    market_id, vol_window, ts, realized_variance, annualized_vol, returns
+                ) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            candle_select: prepare(
+                "SELECT open, high, low, close, volume FROM injective.candles
+                    WHERE market_id = ? AND resolution = ? AND time = ?",
+            )
+            .await?,
+            candle_insert: prepare(
+                "INSERT INTO injective.candles (
+                    market_id, resolution, time, open, high, low, close, volume, closed
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+            // Read sources and sinks handed out by the processor
+            #[cfg(feature = "api")]
+            candle_range_select: prepare(
+                "SELECT time, open, high, low, close, volume FROM injective.candles
+                    WHERE market_id = ? AND resolution = ? AND time >= ? AND time <= ?
+                    ORDER BY time ASC",
+            )
+            .await?,
+            #[cfg(feature = "api")]
+            candle_before_select: prepare(
+                "SELECT time, open, high, low, close, volume FROM injective.candles
+                    WHERE market_id = ? AND resolution = ? AND time < ? LIMIT 1",
+            )
+            .await?,
+            #[cfg(feature = "api")]
+            subaccount_trades_select: prepare(
+                "SELECT timestamp, trade_id, is_maker, market_id, market_type, is_buy,
+                    price, quantity, fee, block_height
+                    FROM injective.trades_by_subaccount
+                    WHERE subaccount_id = ? LIMIT ?",
+            )
+            .await?,
+            #[cfg(feature = "api")]
+            subaccount_trades_before_select: prepare(
+                "SELECT timestamp, trade_id, is_maker, market_id, market_type, is_buy,
+                    price, quantity, fee, block_height
+                    FROM injective.trades_by_subaccount
+                    WHERE subaccount_id = ? AND (timestamp, trade_id, is_maker) < (?, ?, ?)
+                    LIMIT ?",
+            )
+            .await?,
+            correlation_insert: prepare(
+                "INSERT INTO injective.market_correlations
+                    (market_a, market_b, resolution, computed_at, correlation, samples)
+                    VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .await?,
+        })
+    }
+}
 
 pub struct ScyllaDBProcessor {
     session: Arc<Session>,
+    config: ScyllaDBConfig,
+    // Rows written per table for the message currently being processed
+    pending_writes: Arc<Mutex<HashMap<&'static str, i64>>>,
+    // Statements executed so far, drives tracing sampling
+    statements_executed: AtomicU64,
+    // Only changed positions are written to history when enabled
+    position_differ: Option<Mutex<PositionDiffer>>,
+    // Shared with the read sources and sinks handed out by the processor
+    statements: Arc<PreparedStatements>,
+    // Hourly trade totals per market for the hours still being written
+    trade_stats: Mutex<WindowedAggregator<HourlyTradeStats>>,
+    // Newest block and source applied per (market_id, subaccount_id), so late
+    // updates never replace fresher latest-state rows
+    position_heights: Mutex<HashMap<(String, String), (i64, PositionSource)>>,
+    // Newest liquidation check applied per (market_id, subaccount_id): its
+    // block and whether the position was liquidatable
+    liquidation_checks: Mutex<HashMap<(String, String), (i64, bool)>>,
+    // Minute closes of taker trades per market for realized volatility
+    volatility: Mutex<VolatilityTracker>,
+    // Trade and funding history
+    history: Arc<dyn HistoryStore>,
+    // Newest block and cumulative funding recorded per market
+    funding_heads: Mutex<HashMap<String, (i64, f64)>>,
+    // Newest mark price per derivative market, for the mid-price divergence
+    mark_prices: Mutex<HashMap<String, f64>>,
+    // Base and quote decimals per spot market, for spot trades in human units
+    spot_decimals: Mutex<HashMap<String, (u32, u32)>>,
+    // OHLCV bars built from taker fills, when enabled
+    candles: Option<Mutex<CandleAggregator>>,
+    // How long windows stay open after they end
+    allowed_lateness: Duration,
+    // Where candle closes are published
+    #[cfg(feature = "pubsub")]
+    pubsub: Option<Arc<RedisPubSubService>>,
 }
 
 impl ScyllaDBProcessor {
-    pub async fn new(nodes: Vec<String>) -> Result<Self, Box<dyn Error + Send + Sync>> {
-        let session = SessionBuilder::new().known_nodes(&nodes).build().await?;
+    pub async fn new(nodes: Vec<String>, config: &ScyllaDBConfig) -> Result<Self, StorageError> {
+        let session = config.session_builder(&nodes).build().await?;
         Self::initialize_schema(&session).await?;
+        let statements = Arc::new(PreparedStatements::prepare(&session).await?);
+        let session = Arc::new(session);
         Ok(ScyllaDBProcessor {
-            session: Arc::new(session),
+            history: Arc::new(ScyllaHistoryStore::new(session.clone()).await?),
+            session,
+            config: config.clone(),
+            pending_writes: Arc::new(Mutex::new(HashMap::new())),
+            statements_executed: AtomicU64::new(0),
+            position_differ: None,
+            statements,
+            trade_stats: Mutex::new(WindowedAggregator::new(WindowSpec::tumbling(
+                Duration::from_millis(HOUR_MILLIS as u64),
+            ))),
+            position_heights: Mutex::new(HashMap::new()),
+            liquidation_checks: Mutex::new(HashMap::new()),
+            volatility: Mutex::new(VolatilityTracker::new()),
+            funding_heads: Mutex::new(HashMap::new()),
+            mark_prices: Mutex::new(HashMap::new()),
+            spot_decimals: Mutex::new(HashMap::new()),
+            candles: None,
+            allowed_lateness: Duration::ZERO,
+            #[cfg(feature = "pubsub")]
+            pubsub: None,
         })
     }
 
+    // Append trade and funding history to another store instead of ScyllaDB
+    pub fn with_history_store(mut self, history: Arc<dyn HistoryStore>) -> Self {
+        self.history = history;
+        self
+    }
+
+    // Write only changed positions, with a full snapshot every N position messages
+    pub fn with_position_diff(mut self, full_snapshot_every: u64) -> Self {
+        self.position_differ = Some(Mutex::new(PositionDiffer::new(full_snapshot_every)));
+        self
+    }
+
+    // Maintain OHLCV bars of the given resolutions in the candles table
+    pub fn with_candles(mut self, resolutions: Vec<Resolution>) -> Self {
+        let mut aggregator = CandleAggregator::new(resolutions);
+        aggregator.set_allowed_lateness(self.allowed_lateness);
+        self.candles = Some(Mutex::new(aggregator));
+        self
+    }
+
+    // Keep hourly statistics and candles open this long after they end, for
+    // trades from blocks that arrive late
+    pub fn with_allowed_lateness(mut self, allowed_lateness: Duration) -> Self {
+        self.allowed_lateness = allowed_lateness;
+        self.trade_stats
+            .get_mut()
+            .set_allowed_lateness(allowed_lateness);
+        if let Some(candles) = &mut self.candles {
+            candles.get_mut().set_allowed_lateness(allowed_lateness);
+        }
+        self
+    }
+
+    // Publish candle closes as CandleClose events
+    #[cfg(feature = "pubsub")]
+    pub fn with_pubsub(mut self, pubsub: Arc<RedisPubSubService>) -> Self {
+        self.pubsub = Some(pubsub);
+        self
+    }
+
+    // Write timestamp (microseconds) for mutations derived from the block, so that
+    // replaying older messages never overwrites rows written for newer blocks
+    fn write_timestamp(&self, block_height: i64, block_time: i64) -> Option<i64> {
+        match self.config.write_timestamp_source {
+            WriteTimestampSource::BlockTime => Some(time::to_micros(block_time)),
+            // Heights are only comparable with other height-based writes, so this
+            // mode must not be mixed with server timestamps in the same keyspace
+            WriteTimestampSource::BlockHeight => Some(block_height),
+            WriteTimestampSource::Server => None,
+        }
+    }
+
+    // Execute a batch within the configured timeout for its table. Batches
+    // don't take a per-request timeout, so it is enforced around the call.
+    async fn run_batch(
+        &self,
+        table: &str,
+        batch: &Batch,
+        values: impl BatchValues,
+    ) -> Result<QueryResult, QueryError> {
+        let timeout = self.config.statement_timeout(table);
+        let start = Instant::now();
+        let result = match tokio::time::timeout(timeout, self.session.batch(batch, values)).await {
+            Ok(result) => result,
+            Err(_) => Err(QueryError::RequestTimeout(format!(
+                "{} batch timed out after {:?}",
+                table, timeout
+            ))),
+        };
+        record_statement(table, start.elapsed(), result.is_ok());
+        result
+    }
+
+    // Execute a prepared statement with the given write timestamp, the
+    // configured timeout for its table, sampled tracing and slow-query logging
+    async fn run(
+        &self,
+        statement: &PreparedStatement,
+        write_timestamp: Option<i64>,
+        values: impl SerializeRow,
+    ) -> Result<QueryResult, QueryError> {
+        let mut statement = statement.clone();
+        let table = statement_table(statement.get_statement()).to_string();
+        statement.set_timestamp(write_timestamp);
+        statement.set_request_timeout(Some(self.config.statement_timeout(&table)));
+
+        let executed = self.statements_executed.fetch_add(1, Ordering::Relaxed);
+        let sample_every = self.config.tracing_sample_every;
+        if sample_every > 0 && executed.is_multiple_of(sample_every) {
+            statement.set_tracing(true);
+        }
+
+        let start = Instant::now();
+        let result = self.session.execute_unpaged(&statement, values).await;
+        let elapsed = start.elapsed();
+        let elapsed_ms = elapsed.as_millis() as u64;
+        record_statement(&table, elapsed, result.is_ok());
+
+        if elapsed_ms >= self.config.slow_query_threshold_ms {
+            warn!(
+                "Slow ScyllaDB statement on {}: {}ms (threshold {}ms)",
+                table, elapsed_ms, self.config.slow_query_threshold_ms
+            );
+        }
+
+        if let Ok(query_result) = &result {
+            if let Some(tracing_id) = query_result.tracing_id() {
+                info!(
+                    "ScyllaDB trace for statement on {} ({}ms): session {}",
+                    table, elapsed_ms, tracing_id
+                );
+            }
+        }
+
+        result
+    }
+
     async fn initialize_schema(session: &Session) -> Result<(), Box<dyn Error + Send + Sync>> {
         session.query_unpaged(
             "CREATE KEYSPACE IF NOT EXISTS injective WITH REPLICATION = {'class': 'SimpleStrategy', 'replication_factor': 1}",
@@ -61,6 +705,7 @@ impl ScyllaDBProcessor {
                 margin text,
                 cumulative_funding_entry text,
                 liquidation_price text,
+                funding_payment text,
                 PRIMARY KEY ((market_id, subaccount_id), block_height)
             ) WITH CLUSTERING ORDER BY (block_height DESC)",
                 &[],
@@ -81,11 +726,21 @@ impl ScyllaDBProcessor {
                 margin text,
                 cumulative_funding_entry text,
                 liquidation_price text,
+                funding_payment text,
                 PRIMARY KEY (market_id, subaccount_id, block_height)
             ) WITH CLUSTERING ORDER BY (subaccount_id ASC, block_height DESC)",
                 &[],
             )
             .await?;
+        // Tables created before positions carried their funding payment
+        for table in ["positions", "market_positions"] {
+            for column in POSITION_ADDED_COLUMNS {
+                let alter = format!("ALTER TABLE injective.{} ADD {}", table, column);
+                if let Err(e) = session.query_unpaged(alter, &[]).await {
+                    debug!("ScyllaDB: {} column {} not added: {}", table, column, e);
+                }
+            }
+        }
 
         session
             .query_unpaged(
@@ -106,431 +761,2424 @@ impl ScyllaDBProcessor {
             )
             .await?;
 
-        Ok(())
-    }
+        // Every liquidation check per position, newest first. Also tells
+        // whether a newer check cleared a position from liquidatable_positions.
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.liquidatable_history (
+                market_id text,
+                subaccount_id text,
+                block_height bigint,
+                timestamp timestamp,
+                is_liquidatable boolean,
+                is_long boolean,
+                quantity text,
+                entry_price text,
+                margin text,
+                liquidation_price text,
+                mark_price text,
+                liquidation_distance double,
+                PRIMARY KEY ((market_id, subaccount_id), block_height)
+            ) WITH CLUSTERING ORDER BY (block_height DESC)
+              AND default_time_to_live = 2592000",
+                &[],
+            )
+            .await?;
 
-    async fn process_derivative_market(
-        &self,
-        market: &crate::models::DerivativeMarketPayload,
-        block_height: i64,
-        timestamp: i64,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        // Apply proper scaling to prices
-        let cumulative_funding =
-            market.cumulative_funding.parse::<f64>().unwrap_or(0.0) / PRICE_DECIMAL;
-        let mark_price = market.mark_price.parse::<f64>().unwrap_or(0.0) / PRICE_DECIMAL;
+        // One row per orderbook snapshot, pointing at its orders. Partitioned by
+        // market and hour so a market's partitions stay bounded.
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.orderbook_snapshots (
+                market_id text,
+                date_hour timestamp,
+                timestamp timestamp,
+                block_height bigint,
+                orderbook_id text,
+                bid_count int,
+                ask_count int,
+                best_bid text,
+                best_ask text,
+                PRIMARY KEY ((market_id, date_hour), timestamp)
+            ) WITH CLUSTERING ORDER BY (timestamp DESC)",
+                &[],
+            )
+            .await?;
 
-        // No scaling for ratio
-        let maintenance_margin_ratio = market
-            .maintenance_margin_ratio
-            .parse::<f64>()
-            .unwrap_or(0.05);
+        // Mid price of every orderbook snapshot with a bid and an ask, and the
+        // mark's divergence from it for derivative markets
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.mid_prices (
+                market_id text,
+                date_hour timestamp,
+                timestamp timestamp,
+                block_height bigint,
+                mid_price double,
+                best_bid double,
+                best_ask double,
+                mark_price double,
+                divergence_bps double,
+                PRIMARY KEY ((market_id, date_hour), timestamp, block_height)
+            ) WITH CLUSTERING ORDER BY (timestamp DESC, block_height DESC)",
+                &[],
+            )
+            .await?;
 
-        if mark_price <= 0.0 || maintenance_margin_ratio <= 0.0 {
-            warn!("Invalid market data for {}, skipping", market.market_id);
-            return Ok(());
-        }
+        // Every oracle price tick per symbol, to chart against mark and mid prices
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.oracle_prices (
+                symbol text,
+                date_hour timestamp,
+                timestamp timestamp,
+                block_height bigint,
+                price double,
+                oracle_type text,
+                PRIMARY KEY ((symbol, date_hour), timestamp, block_height)
+            ) WITH CLUSTERING ORDER BY (timestamp DESC, block_height DESC)",
+                &[],
+            )
+            .await?;
 
-        // Convert timestamp to CqlTimestamp
-        let datetime: DateTime<Utc> = match Utc.timestamp_opt(timestamp, 0) {
-            LocalResult::Single(dt) => dt,
-            _ => Utc::now(), // Fallback to current time if invalid timestamp
-        };
-        let cql_timestamp = CqlTimestamp(datetime.timestamp_millis());
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.orderbook_orders (
+                orderbook_id text,
+                side text,
+                order_hash text,
+                price text,
+                quantity text,
+                subaccount_id text,
+                PRIMARY KEY (orderbook_id, side, order_hash)
+            )",
+                &[],
+            )
+            .await?;
 
-        // Store the scaled values as strings
-        let market_query = "INSERT INTO injective.markets (
-            market_id, block_height, timestamp, ticker, mark_price, maintenance_margin_ratio, cumulative_funding
-        ) VALUES (?, ?, ?, ?, ?, ?, ?)";
+        // Hourly per-market statistics: volume and taker flow from trades, top
+        // of book from the latest orderbook snapshot in the hour
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.market_statistics (
+                market_id text,
+                date_hour timestamp,
+                volume double,
+                trade_count bigint,
+                taker_buy_count bigint,
+                taker_buy_ratio double,
+                best_bid double,
+                best_ask double,
+                mid_price double,
+                maker_volume double,
+                taker_buy_volume double,
+                taker_sell_volume double,
+                taker_sell_count bigint,
+                buy_volume_ratio double,
+                PRIMARY KEY (market_id, date_hour)
+            ) WITH CLUSTERING ORDER BY (date_hour DESC)",
+                &[],
+            )
+            .await?;
+        // Tables created before the classification columns existed
+        for column in MARKET_STATISTICS_ADDED_COLUMNS {
+            let alter = format!("ALTER TABLE injective.market_statistics ADD {}", column);
+            if let Err(e) = session.query_unpaged(alter, &[]).await {
+                debug!(
+                    "ScyllaDB: market_statistics column {} not added: {}",
+                    column, e
+                );
+            }
+        }
 
-        self.session
+        // Realized volatility per market and window, one row per minute with trades
+        session
             .query_unpaged(
-                market_query,
-                (
-                    &market.market_id,
-                    block_height,
-                    cql_timestamp,
-                    &market.ticker,
-                    mark_price.to_string(), // Store scaled value
-                    &market.maintenance_margin_ratio,
-                    cumulative_funding.to_string(), // Store scaled value
-                ),
+                "CREATE TABLE IF NOT EXISTS injective.market_volatility (
+                market_id text,
+                vol_window text,
+                ts timestamp,
+                realized_variance double,
+                annualized_vol double,
+                returns int,
+                PRIMARY KEY ((market_id, vol_window), ts)
+            ) WITH CLUSTERING ORDER BY (ts DESC)",
+                &[],
             )
-            .await
-            .map_err(|e| {
-                error!("Failed to insert market data: {}", e);
-                e
-            })?;
+            .await?;
 
-        // Fetch positions for this market from the market_positions table
-        let positions_query = "SELECT subaccount_id, is_long, quantity, entry_price, margin, cumulative_funding_entry, block_height 
-            FROM injective.market_positions 
-            WHERE market_id = ? 
-            LIMIT 1000";  // Set a reasonable limit
+        // Return correlation history per market pair, one row per computed matrix
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.market_correlations (
+                market_a text,
+                market_b text,
+                resolution text,
+                computed_at timestamp,
+                correlation double,
+                samples int,
+                PRIMARY KEY ((market_a, market_b), resolution, computed_at)
+            ) WITH CLUSTERING ORDER BY (resolution ASC, computed_at DESC)",
+                &[],
+            )
+            .await?;
 
-        let positions_result = self
-            .session
-            .query_unpaged(positions_query, (&market.market_id,))
-            .await
-            .map_err(|e| {
-                error!(
-                    "Failed to fetch positions for market {}: {}",
-                    market.market_id, e
-                );
-                e
-            })?;
+        // Every side of every derivative trade, partitioned by market and day
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.derivative_trades (
+                market_id text,
+                day timestamp,
+                executed_at timestamp,
+                trade_id text,
+                is_maker boolean,
+                subaccount_id text,
+                is_buy boolean,
+                price double,
+                quantity double,
+                fee double,
+                block_height bigint,
+                PRIMARY KEY ((market_id, day), executed_at, trade_id, is_maker)
+            )",
+                &[],
+            )
+            .await?;
 
-        // Convert the QueryResult into rows via into_rows_result()
-        let rows_result = positions_result.into_rows_result().map_err(|e| {
-            error!("Failed to convert query result into rows: {}", e);
-            e
-        })?;
-        let mut rows_iter =
-            rows_result.rows::<(String, bool, String, String, String, String, i64)>()?;
-        while let Some(row) = rows_iter.next().transpose()? {
-            let (
-                subaccount_id,
-                is_long,
-                quantity,
-                entry_price,
-                margin,
-                cumulative_funding_entry,
-                pos_block_height,
-            ) = row;
+        // Spot markets as the chain reports them, one row per heartbeat
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.spot_markets (
+                market_id text,
+                block_height bigint,
+                timestamp timestamp,
+                ticker text,
+                base_denom text,
+                quote_denom text,
+                base_decimals int,
+                quote_decimals int,
+                maker_fee_rate text,
+                taker_fee_rate text,
+                min_price_tick text,
+                min_quantity_tick text,
+                min_notional text,
+                status text,
+                PRIMARY KEY (market_id, block_height)
+            ) WITH CLUSTERING ORDER BY (block_height DESC)",
+                &[],
+            )
+            .await?;
 
-            // Values from database are already scaled, no need to re-scale
-            let quantity_val = quantity.parse::<f64>().unwrap_or(0.0);
-            let entry_price_val = entry_price.parse::<f64>().unwrap_or(0.0);
-            let margin_val = margin.parse::<f64>().unwrap_or(0.0);
-            let cumulative_funding_entry_val =
-                cumulative_funding_entry.parse::<f64>().unwrap_or(0.0);
+        // Every side of every spot trade, partitioned like derivative_trades
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.spot_trades (
+                market_id text,
+                day timestamp,
+                executed_at timestamp,
+                trade_id text,
+                is_maker boolean,
+                subaccount_id text,
+                is_buy boolean,
+                price double,
+                quantity double,
+                fee double,
+                block_height bigint,
+                PRIMARY KEY ((market_id, day), executed_at, trade_id, is_maker)
+            )",
+                &[],
+            )
+            .await?;
 
-            if quantity_val <= 0.0 || entry_price_val <= 0.0 || margin_val <= 0.0 {
+        // Every side of every spot and derivative trade, partitioned by
+        // subaccount so trade history pages never scan a market
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.trades_by_subaccount (
+                subaccount_id text,
+                timestamp timestamp,
+                trade_id text,
+                is_maker boolean,
+                market_id text,
+                market_type text,
+                is_buy boolean,
+                price double,
+                quantity double,
+                fee double,
+                block_height bigint,
+                PRIMARY KEY (subaccount_id, timestamp, trade_id, is_maker)
+            ) WITH CLUSTERING ORDER BY (timestamp DESC, trade_id DESC, is_maker DESC)",
+                &[],
+            )
+            .await?;
+
+        // OHLCV bars per market and resolution; `closed` is set once a bar is final
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.candles (
+                market_id text,
+                resolution text,
+                time timestamp,
+                open double,
+                high double,
+                low double,
+                close double,
+                volume double,
+                closed boolean,
+                PRIMARY KEY ((market_id, resolution), time)
+            ) WITH CLUSTERING ORDER BY (time DESC)",
+                &[],
+            )
+            .await?;
+
+        // Cumulative funding per market at every block it changed, with the
+        // funding paid since the previous row and the rate of the next one
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.funding_history (
+                market_id text,
+                block_height bigint,
+                timestamp timestamp,
+                cumulative_funding double,
+                mark_price double,
+                funding_delta double,
+                paid_funding_rate double,
+                estimated_funding_rate double,
+                hourly_funding_rate_cap double,
+                hourly_interest_rate double,
+                PRIMARY KEY (market_id, block_height)
+            ) WITH CLUSTERING ORDER BY (block_height DESC)",
+                &[],
+            )
+            .await?;
+        // Expiry futures markets the chain has settled, with the price their
+        // positions were closed at
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.market_settlements (
+                market_id text,
+                expiration_timestamp bigint,
+                settlement_price text,
+                block_height bigint,
+                timestamp timestamp,
+                PRIMARY KEY (market_id)
+            )",
+                &[],
+            )
+            .await?;
+
+        // Tables created before the funding rate columns existed
+        for column in FUNDING_HISTORY_ADDED_COLUMNS {
+            let alter = format!("ALTER TABLE injective.funding_history ADD {}", column);
+            if let Err(e) = session.query_unpaged(alter, &[]).await {
+                debug!(
+                    "ScyllaDB: funding_history column {} not added: {}",
+                    column, e
+                );
+            }
+        }
+
+        // Markers for messages already applied, used by the idempotency guard
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.processed_messages (
+                message_type text,
+                block_height bigint,
+                content_hash bigint,
+                processed_at timestamp,
+                PRIMARY KEY ((message_type, block_height), content_hash)
+            ) WITH default_time_to_live = 604800",
+                &[],
+            )
+            .await?;
+
+        // The same per position, trade or market, for the entity guard
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.processed_entities (
+                message_type text,
+                block_height bigint,
+                entity_id text,
+                processed_at timestamp,
+                PRIMARY KEY ((message_type, block_height), entity_id)
+            ) WITH default_time_to_live = 604800",
+                &[],
+            )
+            .await?;
+
+        // Write audit counters, partitioned by hour so each partition stays small
+        session
+            .query_unpaged(
+                "CREATE TABLE IF NOT EXISTS injective.write_counters (
+                hour timestamp,
+                table_name text,
+                message_type text,
+                row_count counter,
+                PRIMARY KEY (hour, table_name, message_type)
+            )",
+                &[],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Count a successful row write against the message being processed
+    async fn record_write(&self, table_name: &'static str) {
+        self.record_writes(table_name, 1).await;
+    }
+
+    async fn record_writes(&self, table_name: &'static str, rows: i64) {
+        let mut pending = self.pending_writes.lock().await;
+        *pending.entry(table_name).or_insert(0) += rows;
+    }
+
+    // Flush the per-table row counts for a message into the hourly counters
+    async fn flush_write_counters(
+        &self,
+        message: &KafkaMessage,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let counts = std::mem::take(&mut *self.pending_writes.lock().await);
+        if counts.is_empty() {
+            return Ok(());
+        }
+
+        let message_type = format!("{:?}", message.message_type);
+        let hour = CqlTimestamp(time::hour_bucket(message.block_time as i64));
+
+        for (table_name, rows) in counts {
+            self.run(
+                &self.statements.write_counter_update,
+                None,
+                (Counter(rows), hour, table_name, &message_type),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Rows written per table and message type during the hour starting at `hour`
+    pub async fn write_counts(
+        &self,
+        hour: DateTime<Utc>,
+    ) -> Result<Vec<WriteCount>, Box<dyn Error + Send + Sync>> {
+        let hour = CqlTimestamp(time::truncate_millis(hour.timestamp_millis(), HOUR_MILLIS));
+
+        let result = self
+            .run(&self.statements.write_counts_select, None, (hour,))
+            .await?;
+
+        let rows_result = result.into_rows_result()?;
+        let mut counts = Vec::new();
+        for row in rows_result.rows::<(String, String, Counter)>()? {
+            let (table_name, message_type, rows) = row?;
+            counts.push(WriteCount {
+                table_name,
+                message_type,
+                rows: rows.0,
+            });
+        }
+        Ok(counts)
+    }
+
+    /// Sink writing correlation matrices to `market_correlations` over this
+    /// processor's session
+    pub fn correlation_sink(&self) -> ScyllaCorrelationSink {
+        ScyllaCorrelationSink {
+            session: self.session.clone(),
+            statements: self.statements.clone(),
+        }
+    }
+
+    /// Candle history from the candles table, for the UDF datafeed
+    #[cfg(feature = "api")]
+    pub fn candle_source(&self) -> ScyllaCandleSource {
+        ScyllaCandleSource {
+            session: self.session.clone(),
+            statements: self.statements.clone(),
+        }
+    }
+
+    /// Per-subaccount trade history from trades_by_subaccount, for trade
+    /// history pages
+    #[cfg(feature = "api")]
+    pub fn trade_history(&self) -> ScyllaTradeHistory {
+        ScyllaTradeHistory {
+            session: self.session.clone(),
+            statements: self.statements.clone(),
+        }
+    }
+
+    /// Maker/taker volume and aggressor split of a market for the hour
+    /// starting at `hour`, or `None` if no trades were recorded
+    pub async fn trade_classification(
+        &self,
+        market_id: &str,
+        hour: DateTime<Utc>,
+    ) -> Result<Option<TradeClassification>, Box<dyn Error + Send + Sync>> {
+        let date_hour = time::truncate_millis(hour.timestamp_millis(), HOUR_MILLIS);
+        let stats = self.load_trade_stats(market_id, date_hour).await?;
+        if stats.trade_count == 0 && stats.maker_volume == 0.0 {
+            return Ok(None);
+        }
+
+        Ok(Some(TradeClassification {
+            market_id: market_id.to_string(),
+            date_hour: DateTime::from_timestamp_millis(date_hour).unwrap_or(hour),
+            maker_volume: stats.maker_volume,
+            taker_volume: stats.volume,
+            taker_buy_volume: stats.taker_buy_volume,
+            taker_sell_volume: stats.volume - stats.taker_buy_volume,
+            taker_buy_count: stats.taker_buy_count,
+            taker_sell_count: stats.trade_count - stats.taker_buy_count,
+            buy_volume_ratio: ratio(stats.taker_buy_volume, stats.volume),
+        }))
+    }
+
+    // Check whether a message has already been applied. With lightweight
+    // transactions the marker is claimed here; in content-hash mode it is only
+    // written once processing has finished (see mark_processed).
+    async fn already_processed(
+        &self,
+        message: &KafkaMessage,
+        content_hash: i64,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let message_type = format!("{:?}", message.message_type);
+        let block_height = message.block_height as i64;
+
+        match self.config.idempotency {
+            // Entity mode checks each entity instead (see processed_entities)
+            IdempotencyMode::Off | IdempotencyMode::Entity => Ok(false),
+            IdempotencyMode::ContentHash => {
+                let result = self
+                    .run(
+                        &self.statements.processed_select,
+                        None,
+                        (&message_type, block_height, content_hash),
+                    )
+                    .await?;
+                Ok(result.into_rows_result()?.rows_num() > 0)
+            }
+            IdempotencyMode::LightweightTransactions => {
+                let result = self
+                    .run(
+                        &self.statements.processed_claim,
+                        None,
+                        (
+                            &message_type,
+                            block_height,
+                            content_hash,
+                            CqlTimestamp(time::now_millis()),
+                        ),
+                    )
+                    .await?;
+
+                let (applied, _) = lwt_outcome(result)?;
+                Ok(!applied)
+            }
+        }
+    }
+
+    async fn mark_processed(
+        &self,
+        message: &KafkaMessage,
+        content_hash: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.run(
+            &self.statements.processed_insert,
+            None,
+            (
+                format!("{:?}", message.message_type),
+                message.block_height as i64,
+                content_hash,
+                CqlTimestamp(time::now_millis()),
+            ),
+        )
+        .await?;
+        Ok(())
+    }
+
+    // Give up a lightweight-transaction claim on a message that failed, so
+    // its redelivery is applied instead of skipped
+    async fn release_processed(
+        &self,
+        message: &KafkaMessage,
+        content_hash: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.run(
+            &self.statements.processed_release,
+            None,
+            (
+                format!("{:?}", message.message_type),
+                message.block_height as i64,
+                content_hash,
+            ),
+        )
+        .await?;
+        Ok(())
+    }
+
+    // Entities of the message's type already applied at its block. The
+    // markers of one block share a partition, so this is a single read.
+    async fn processed_entities(
+        &self,
+        message: &KafkaMessage,
+    ) -> Result<HashSet<String>, Box<dyn Error + Send + Sync>> {
+        let result = self
+            .run(
+                &self.statements.processed_entities_select,
+                None,
+                (
+                    format!("{:?}", message.message_type),
+                    message.block_height as i64,
+                ),
+            )
+            .await?;
+
+        let mut processed = HashSet::new();
+        for row in result.into_rows_result()?.rows::<(String,)>()? {
+            let (entity_id,) = row?;
+            processed.insert(entity_id);
+        }
+        Ok(processed)
+    }
+
+    async fn mark_entities_processed(
+        &self,
+        message: &KafkaMessage,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let message_type = format!("{:?}", message.message_type);
+        let block_height = message.block_height as i64;
+        let processed_at = CqlTimestamp(time::now_millis());
+
+        for chunk in message.entity_ids().chunks(ENTITY_MARKER_BATCH) {
+            let mut batch = Batch::new(BatchType::Unlogged);
+            let mut values = Vec::with_capacity(chunk.len());
+            for entity_id in chunk {
+                batch.append_statement(self.statements.processed_entity_insert.clone());
+                values.push((&message_type, block_height, entity_id, processed_at));
+            }
+            self.run_batch("processed_entities", &batch, values).await?;
+        }
+        Ok(())
+    }
+
+    // Write the message's entities. Every entity is attempted; the first
+    // failure is returned once all of them have been tried.
+    async fn apply(
+        &self,
+        message: &KafkaMessage,
+        block_height: i64,
+        timestamp: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut failed = None;
+        match &message.payload {
+            KafkaPayload::DerivativeMarkets(markets) => {
+                for market in markets {
+                    if let Err(e) = self
+                        .process_derivative_market(market, block_height, timestamp)
+                        .await
+                    {
+                        error!("ScyllaDB: Error processing derivative market: {}", e);
+                        failed.get_or_insert(e);
+                    }
+                }
+            }
+            KafkaPayload::StreamPositions(_) | KafkaPayload::ExchangePositions(_) => {
+                if let Some((positions, source)) = message.positions() {
+                    if let Err(e) = self
+                        .process_positions(positions, source, block_height, timestamp)
+                        .await
+                    {
+                        failed.get_or_insert(e);
+                    }
+                }
+            }
+            KafkaPayload::DerivativeTrades(trades) => {
+                if let Err(e) = self.process_trades(trades, block_height, timestamp).await {
+                    error!("ScyllaDB: Error updating market statistics: {}", e);
+                    failed.get_or_insert(e);
+                }
+                let fills = trades
+                    .iter()
+                    .filter(|t| t.execution_type != "LimitMatchRestingOrder")
+                    .map(|t| {
+                        (
+                            t.market_id.as_str(),
+                            scaling::price(&t.position_delta.execution_price),
+                            scaling::quantity(&t.position_delta.execution_quantity),
+                        )
+                    })
+                    .collect();
+                if let Err(e) = self.update_candles(fills, block_height, timestamp).await {
+                    error!("ScyllaDB: Error updating candles: {}", e);
+                    failed.get_or_insert(e);
+                }
+            }
+            KafkaPayload::DerivativeFullOrderbooks(_) | KafkaPayload::SpotFullOrderbooks(_) => {
+                if let Some((orderbooks, market_type)) = message.full_orderbooks() {
+                    let (price_scale, quantity_scale) = match market_type {
+                        MarketType::Derivative => (PRICE_DECIMAL, CHAIN_DECIMAL),
+                        MarketType::Spot => (1.0, 1.0),
+                    };
+                    for orderbook in orderbooks {
+                        if let Err(e) = self
+                            .process_orderbook(
+                                orderbook,
+                                price_scale,
+                                quantity_scale,
+                                block_height,
+                                timestamp,
+                            )
+                            .await
+                        {
+                            error!("ScyllaDB: Error persisting orderbook: {}", e);
+                            failed.get_or_insert(e);
+                        }
+                    }
+                }
+            }
+            KafkaPayload::SpotMarkets(markets) => {
+                for market in markets {
+                    if let Err(e) = self
+                        .process_spot_market(market, block_height, timestamp)
+                        .await
+                    {
+                        error!("ScyllaDB: Error processing spot market: {}", e);
+                        failed.get_or_insert(e);
+                    }
+                }
+            }
+            KafkaPayload::SpotTrades(trades) => {
+                if let Err(e) = self
+                    .process_spot_trades(trades, block_height, timestamp)
+                    .await
+                {
+                    error!("ScyllaDB: Error persisting spot trades: {}", e);
+                    failed.get_or_insert(e);
+                }
+                // Spot candles are in chain units, like the rest of the spot data
+                let fills = trades
+                    .iter()
+                    .filter(|t| t.execution_type != "LimitMatchRestingOrder")
+                    .map(|t| {
+                        (
+                            t.market_id.as_str(),
+                            t.price.parse::<f64>().unwrap_or(0.0),
+                            t.quantity.parse::<f64>().unwrap_or(0.0),
+                        )
+                    })
+                    .collect();
+                if let Err(e) = self.update_candles(fills, block_height, timestamp).await {
+                    error!("ScyllaDB: Error updating candles: {}", e);
+                    failed.get_or_insert(e);
+                }
+            }
+            KafkaPayload::StreamOraclePrices(prices) => {
+                if let Err(e) = self
+                    .process_oracle_prices(prices, block_height, timestamp)
+                    .await
+                {
+                    error!("ScyllaDB: Error persisting oracle prices: {}", e);
+                    failed.get_or_insert(e);
+                }
+            }
+            _ => {}
+        }
+        failed.map_or(Ok(()), Err)
+    }
+
+    async fn process_derivative_market(
+        &self,
+        market: &crate::models::DerivativeMarketPayload,
+        block_height: i64,
+        timestamp: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // A settled market has no positions left to price
+        let time_secs = time::to_millis(timestamp) / 1_000;
+        if let Some(settlement) = expiry::settlement(market, time_secs) {
+            return self
+                .settle_market(&settlement, block_height, timestamp)
+                .await;
+        }
+
+        let cumulative_funding = scaling::funding(&market.cumulative_funding);
+        let mark_price = scaling::price(&market.mark_price);
+        let maintenance_margin_ratio =
+            scaling::maintenance_margin_ratio(&market.maintenance_margin_ratio);
+
+        if mark_price <= 0.0 || maintenance_margin_ratio <= 0.0 {
+            warn!("Invalid market data for {}, skipping", market.market_id);
+            return Ok(());
+        }
+
+        let cql_timestamp = CqlTimestamp(time::to_millis(timestamp));
+        let write_ts = self.write_timestamp(block_height, timestamp);
+
+        // Store the scaled values as strings
+        self.run(
+            &self.statements.market_insert,
+            write_ts,
+            (
+                &market.market_id,
+                block_height,
+                cql_timestamp,
+                &market.ticker,
+                mark_price.to_string(),               // Store scaled value
+                maintenance_margin_ratio.to_string(), // Store scaled value
+                cumulative_funding.to_string(),       // Store scaled value
+            ),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to insert market data: {}", e);
+            e
+        })?;
+        self.record_write("markets").await;
+        self.mark_prices
+            .lock()
+            .await
+            .insert(market.market_id.clone(), mark_price);
+
+        // Funding paid is the change since the newest block recorded for the
+        // market; late blocks and the first block after a restart have none
+        let previous_cumulative_funding = {
+            let mut heads = self.funding_heads.lock().await;
+            match heads.get(&market.market_id).copied() {
+                Some((height, _)) if height >= block_height => None,
+                head => {
+                    heads.insert(market.market_id.clone(), (block_height, cumulative_funding));
+                    head.map(|(_, cumulative_funding)| cumulative_funding)
+                }
+            }
+        };
+        self.history
+            .append_funding(&storage::funding_from_payload(
+                market,
+                previous_cumulative_funding,
+                block_height as u64,
+                timestamp as u64,
+            ))
+            .await?;
+        self.record_write("funding_history").await;
+
+        // Fetch positions for this market from the market_positions table
+        let positions_result = self
+            .run(
+                &self.statements.market_positions_select,
+                None,
+                (&market.market_id,),
+            )
+            .await
+            .map_err(|e| {
+                error!(
+                    "Failed to fetch positions for market {}: {}",
+                    market.market_id, e
+                );
+                e
+            })?;
+
+        // Convert the QueryResult into rows via into_rows_result()
+        let rows_result = positions_result.into_rows_result().map_err(|e| {
+            error!("Failed to convert query result into rows: {}", e);
+            e
+        })?;
+        let mut rows_iter =
+            rows_result.rows::<(String, bool, String, String, String, String, i64)>()?;
+        while let Some(row) = rows_iter.next().transpose()? {
+            let (
+                subaccount_id,
+                is_long,
+                quantity,
+                entry_price,
+                margin,
+                cumulative_funding_entry,
+                pos_block_height,
+            ) = row;
+
+            // Values from database are already scaled, no need to re-scale
+            let quantity_val = quantity.parse::<f64>().unwrap_or(0.0);
+            let entry_price_val = entry_price.parse::<f64>().unwrap_or(0.0);
+            let margin_val = margin.parse::<f64>().unwrap_or(0.0);
+            let cumulative_funding_entry_val =
+                cumulative_funding_entry.parse::<f64>().unwrap_or(0.0);
+
+            if quantity_val <= 0.0 || entry_price_val <= 0.0 || margin_val <= 0.0 {
+                warn!(
+                    "Invalid position data for market {} subaccount {}, skipping",
+                    market.market_id, subaccount_id
+                );
+                continue;
+            }
+
+            // All inputs to calculate_liquidation_price are already scaled
+            let liquidation_price = calculate_liquidation_price(
+                is_long,
+                entry_price_val,
+                margin_val,
+                quantity_val,
+                maintenance_margin_ratio,
+                cumulative_funding,
+                cumulative_funding_entry_val,
+            );
+            let funding_payment = compute::funding_payment(
+                is_long,
+                quantity_val,
+                cumulative_funding,
+                cumulative_funding_entry_val,
+            );
+
+            // Update both position tables with the new liquidation price and
+            // the funding paid since the position was opened
+            if let Err(e) = self
+                .run(
+                    &self.statements.position_liquidation_update,
+                    write_ts,
+                    (
+                        liquidation_price.to_string(),
+                        funding_payment.to_string(),
+                        &market.market_id,
+                        &subaccount_id,
+                        pos_block_height,
+                    ),
+                )
+                .await
+            {
+                error!(
+                    "Failed to update liquidation price in positions table: {}",
+                    e
+                );
+                // Continue with other updates even if this one fails
+            } else {
+                self.record_write("positions").await;
+            }
+
+            if let Err(e) = self
+                .run(
+                    &self.statements.market_position_liquidation_update,
+                    write_ts,
+                    (
+                        liquidation_price.to_string(),
+                        funding_payment.to_string(),
+                        &market.market_id,
+                        &subaccount_id,
+                        pos_block_height,
+                    ),
+                )
+                .await
+            {
+                error!(
+                    "Failed to update liquidation price in market_positions table: {}",
+                    e
+                );
+                continue;
+            }
+            self.record_write("market_positions").await;
+
+            // Checked at the market's block, against the newest position
+            let check = LiquidationCheck {
+                market_id: &market.market_id,
+                subaccount_id: &subaccount_id,
+                block_height,
+                timestamp,
+                is_long,
+                quantity: quantity_val,
+                entry_price: entry_price_val,
+                margin: margin_val,
+                liquidation_price,
+                mark_price,
+            };
+            match self.apply_liquidation_check(&check).await {
+                Ok(true) => info!(
+                    "Liquidatable position inserted: market={}, subaccount={}, liq_price={}, mark_price={}",
+                    market.market_id, subaccount_id, liquidation_price, mark_price
+                ),
+                Ok(false) => {}
+                Err(e) => error!("Failed to apply liquidation check: {}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn process_position(
+        &self,
+        position: &crate::models::PositionPayload,
+        block_height: i64,
+        timestamp: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let is_long = position.is_long;
+        let quantity = scaling::quantity(&position.quantity);
+        let entry_price = scaling::price(&position.entry_price);
+        let margin = scaling::margin(&position.margin);
+        let cumulative_funding_entry = scaling::funding(&position.cumulative_funding_entry);
+
+        if quantity <= 0.0 || entry_price <= 0.0 || margin <= 0.0 {
+            warn!(
+                "Invalid position data for market {} subaccount {}, skipping",
+                position.market_id, position.subaccount_id
+            );
+            return Ok(());
+        }
+
+        let market_result = self
+            .run(&self.statements.market_select, None, (&position.market_id,))
+            .await
+            .map_err(|e| {
+                error!("Failed to fetch market data: {}", e);
+                e
+            })?;
+
+        let (mark_price, maintenance_margin_ratio, market_cumulative_funding) = {
+            // Convert the QueryResult into rows.
+            if let Ok(rows_result) = market_result.into_rows_result() {
+                // Use a tuple of &str for lifetime convenience.
+                let mut rows_iter = rows_result.rows::<(&str, &str, &str)>()?;
+                if let Some(row) = rows_iter.next().transpose()? {
+                    let (mp, mmr, mcf) = row;
+                    (
+                        mp.parse::<f64>().unwrap_or(0.0), // Already scaled in DB
+                        scaling::stored_maintenance_margin_ratio(mmr),
+                        mcf.parse::<f64>().unwrap_or(0.0), // Already scaled in DB
+                    )
+                } else {
+                    (0.0, DEFAULT_MAINTENANCE_MARGIN_RATIO, 0.0)
+                }
+            } else {
+                (0.0, DEFAULT_MAINTENANCE_MARGIN_RATIO, 0.0)
+            }
+        };
+
+        // All inputs now properly scaled
+        let liquidation_price = calculate_liquidation_price(
+            is_long,
+            entry_price,
+            margin,
+            quantity,
+            maintenance_margin_ratio,
+            market_cumulative_funding,
+            cumulative_funding_entry,
+        );
+        let funding_payment = compute::funding_payment(
+            is_long,
+            quantity,
+            market_cumulative_funding,
+            cumulative_funding_entry,
+        );
+
+        let cql_timestamp = CqlTimestamp(time::to_millis(timestamp));
+        let write_ts = self.write_timestamp(block_height, timestamp);
+
+        // Insert into the original positions table
+        self.run(
+            &self.statements.position_insert,
+            write_ts,
+            (
+                &position.market_id,
+                &position.subaccount_id,
+                block_height,
+                cql_timestamp,
+                is_long,
+                quantity.to_string(),                 // Store scaled value
+                entry_price.to_string(),              // Store scaled value
+                margin.to_string(),                   // Store scaled value
+                cumulative_funding_entry.to_string(), // Store scaled value
+                liquidation_price.to_string(),
+                funding_payment.to_string(),
+            ),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to insert position: {}", e);
+            e
+        })?;
+        self.record_write("positions").await;
+
+        // Also insert into the market-optimized positions table
+        self.run(
+            &self.statements.market_position_insert,
+            write_ts,
+            (
+                &position.market_id,
+                &position.subaccount_id,
+                block_height,
+                cql_timestamp,
+                is_long,
+                quantity.to_string(),                 // Store scaled value
+                entry_price.to_string(),              // Store scaled value
+                margin.to_string(),                   // Store scaled value
+                cumulative_funding_entry.to_string(), // Store scaled value
+                liquidation_price.to_string(),
+                funding_payment.to_string(),
+            ),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to insert market position: {}", e);
+            e
+        })?;
+        self.record_write("market_positions").await;
+
+        let check = LiquidationCheck {
+            market_id: &position.market_id,
+            subaccount_id: &position.subaccount_id,
+            block_height,
+            timestamp,
+            is_long,
+            quantity,
+            entry_price,
+            margin,
+            liquidation_price,
+            mark_price,
+        };
+        match self.apply_liquidation_check(&check).await {
+            Ok(true) => info!(
+                "Liquidatable position updated: market={}, subaccount={}, liq_price={}, mark_price={}",
+                position.market_id, position.subaccount_id, liquidation_price, mark_price
+            ),
+            Ok(false) => {}
+            Err(e) => error!("Failed to apply liquidation check: {}", e),
+        }
+
+        Ok(())
+    }
+
+    // Apply a liquidation check to liquidatable_positions unless a check at a
+    // newer block already has been, and record it in liquidatable_history.
+    // Returns whether the position is liquidatable.
+    async fn apply_liquidation_check(
+        &self,
+        check: &LiquidationCheck<'_>,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let liquidatable =
+            is_liquidatable(check.is_long, check.liquidation_price, check.mark_price);
+        let key = (check.market_id.to_string(), check.subaccount_id.to_string());
+
+        // Checks applied by this processor settle most updates without a
+        // lightweight transaction
+        let applied = match self.liquidation_checks.lock().await.get(&key).copied() {
+            Some((height, _)) if height > check.block_height => false,
+            Some((_, false)) if !liquidatable => true,
+            _ => self.write_liquidatable(check, liquidatable).await?,
+        };
+        if applied {
+            self.liquidation_checks
+                .lock()
+                .await
+                .insert(key, (check.block_height, liquidatable));
+        } else {
+            debug!(
+                "ScyllaDB: Keeping newer liquidation check for market={}, subaccount={} over block {}",
+                check.market_id, check.subaccount_id, check.block_height
+            );
+        }
+
+        // History is keyed by block, so replayed checks rewrite their own rows
+        self.run(
+            &self.statements.liquidatable_history_insert,
+            self.write_timestamp(check.block_height, check.timestamp),
+            (
+                check.market_id,
+                check.subaccount_id,
+                check.block_height,
+                CqlTimestamp(time::to_millis(check.timestamp)),
+                liquidatable,
+                check.is_long,
+                check.quantity.to_string(),
+                check.entry_price.to_string(),
+                check.margin.to_string(),
+                check.liquidation_price.to_string(),
+                check.mark_price.to_string(),
+                compute::distance_to_liquidation(
+                    check.is_long,
+                    check.liquidation_price,
+                    check.mark_price,
+                ),
+            ),
+        )
+        .await?;
+        self.record_write("liquidatable_history").await;
+
+        Ok(liquidatable)
+    }
+
+    // Conditionally write a check to liquidatable_positions, whose rows carry
+    // the block they were checked at. Returns false when a newer check is in
+    // place. Lightweight transactions can't take a write timestamp, so these
+    // run with the server's.
+    async fn write_liquidatable(
+        &self,
+        check: &LiquidationCheck<'_>,
+        liquidatable: bool,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        if !liquidatable {
+            let result = self
+                .run(
+                    &self.statements.liquidatable_delete,
+                    None,
+                    (check.market_id, check.subaccount_id, check.block_height),
+                )
+                .await?;
+            let (deleted, stored_height) = lwt_outcome(result)?;
+            if deleted {
+                self.record_write("liquidatable_positions").await;
+            }
+            // Nothing to clear when there is no row
+            return Ok(deleted || stored_height.is_none());
+        }
+
+        let cql_timestamp = CqlTimestamp(time::to_millis(check.timestamp));
+        let result = self
+            .run(
+                &self.statements.liquidatable_update,
+                None,
+                (
+                    check.block_height,
+                    cql_timestamp,
+                    check.is_long,
+                    check.quantity.to_string(),
+                    check.entry_price.to_string(),
+                    check.margin.to_string(),
+                    check.liquidation_price.to_string(),
+                    check.mark_price.to_string(),
+                    check.market_id,
+                    check.subaccount_id,
+                    check.block_height,
+                ),
+            )
+            .await?;
+        let (updated, stored_height) = lwt_outcome(result)?;
+        if updated {
+            self.record_write("liquidatable_positions").await;
+            return Ok(true);
+        }
+        if stored_height.is_some() {
+            return Ok(false);
+        }
+
+        // No row, either never liquidatable or cleared by a newer check,
+        // which only the history tells apart
+        let latest = self
+            .run(
+                &self.statements.liquidatable_history_latest,
+                None,
+                (check.market_id, check.subaccount_id),
+            )
+            .await?
+            .into_rows_result()?
+            .maybe_first_row::<(i64,)>()?;
+        if latest.is_some_and(|(height,)| height > check.block_height) {
+            return Ok(false);
+        }
+
+        let result = self
+            .run(
+                &self.statements.liquidatable_insert,
+                None,
+                (
+                    check.market_id,
+                    check.subaccount_id,
+                    check.block_height,
+                    cql_timestamp,
+                    check.is_long,
+                    check.quantity.to_string(),
+                    check.entry_price.to_string(),
+                    check.margin.to_string(),
+                    check.liquidation_price.to_string(),
+                    check.mark_price.to_string(),
+                ),
+            )
+            .await?;
+        let (inserted, _) = lwt_outcome(result)?;
+        if inserted {
+            self.record_write("liquidatable_positions").await;
+        }
+        Ok(inserted)
+    }
+
+    async fn process_spot_market(
+        &self,
+        market: &SpotMarketPayload,
+        block_height: i64,
+        timestamp: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.run(
+            &self.statements.spot_market_insert,
+            self.write_timestamp(block_height, timestamp),
+            (
+                &market.market_id,
+                block_height,
+                CqlTimestamp(time::to_millis(timestamp)),
+                &market.ticker,
+                &market.base_denom,
+                &market.quote_denom,
+                market.base_decimals as i32,
+                market.quote_decimals as i32,
+                &market.maker_fee_rate,
+                &market.taker_fee_rate,
+                &market.min_price_tick,
+                &market.min_quantity_tick,
+                &market.min_notional,
+                &market.status,
+            ),
+        )
+        .await?;
+        self.record_write("spot_markets").await;
+        self.spot_decimals.lock().await.insert(
+            market.market_id.clone(),
+            (market.base_decimals, market.quote_decimals),
+        );
+        Ok(())
+    }
+
+    // Spot trades are appended as they come, by market and by subaccount, in
+    // human units like derivative trades; rows are keyed by trade, so a
+    // replayed message rewrites the same rows
+    async fn process_spot_trades(
+        &self,
+        trades: &[SpotTradePayload],
+        block_height: i64,
+        timestamp: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let write_ts = self.write_timestamp(block_height, timestamp);
+        let day = CqlTimestamp(time::day_bucket(timestamp));
+        let cql_timestamp = CqlTimestamp(time::to_millis(timestamp));
+        for trade in trades {
+            let (base_decimals, quote_decimals) = self.spot_decimals(&trade.market_id).await?;
+            let price = scaling::spot_price(&trade.price, base_decimals, quote_decimals);
+            let quantity = scaling::spot_quantity(&trade.quantity, base_decimals);
+            let fee = scaling::spot_fee(&trade.fee, quote_decimals);
+            self.run(
+                &self.statements.spot_trade_insert,
+                write_ts,
+                (
+                    &trade.market_id,
+                    day,
+                    cql_timestamp,
+                    &trade.trade_id,
+                    trade.execution_type == "LimitMatchRestingOrder",
+                    &trade.subaccount_id,
+                    trade.is_buy,
+                    price,
+                    quantity,
+                    fee,
+                    block_height,
+                ),
+            )
+            .await?;
+            self.record_write("spot_trades").await;
+
+            self.run(
+                &self.statements.spot_subaccount_trade_insert,
+                write_ts,
+                (
+                    &trade.subaccount_id,
+                    cql_timestamp,
+                    &trade.trade_id,
+                    trade.execution_type == "LimitMatchRestingOrder",
+                    &trade.market_id,
+                    trade.is_buy,
+                    price,
+                    quantity,
+                    fee,
+                    block_height,
+                ),
+            )
+            .await?;
+            self.record_write("trades_by_subaccount").await;
+        }
+        Ok(())
+    }
+
+    // Base and quote decimals of a spot market, from the markets seen so far
+    // or else its newest spot_markets row. Trades of a market that was never
+    // seen fail, rather than being stored in the wrong units.
+    async fn spot_decimals(
+        &self,
+        market_id: &str,
+    ) -> Result<(u32, u32), Box<dyn Error + Send + Sync>> {
+        if let Some(&decimals) = self.spot_decimals.lock().await.get(market_id) {
+            return Ok(decimals);
+        }
+        let result = self
+            .run(
+                &self.statements.spot_market_decimals_select,
+                None,
+                (market_id,),
+            )
+            .await?;
+        let Some((base_decimals, quote_decimals)) =
+            result.into_rows_result()?.maybe_first_row::<(i32, i32)>()?
+        else {
+            return Err(format!("no decimals known for spot market {}", market_id).into());
+        };
+        let decimals = (base_decimals as u32, quote_decimals as u32);
+        self.spot_decimals
+            .lock()
+            .await
+            .insert(market_id.to_string(), decimals);
+        Ok(decimals)
+    }
+
+    // Persist a full orderbook: one snapshot row plus its orders, written as
+    // unlogged batches (all rows share the orderbook_id partition) capped by
+    // row count and size, with bounded concurrency. Prices and quantities are
+    // divided by the given scales; spot books are passed through in chain units.
+    async fn process_orderbook(
+        &self,
+        orderbook: &FullLimitOrderbookPayload,
+        price_scale: f64,
+        quantity_scale: f64,
+        block_height: i64,
+        timestamp: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let orderbook_id = format!("{}:{}", orderbook.market_id, block_height);
+        let write_ts = self.write_timestamp(block_height, timestamp);
+        let cql_timestamp = CqlTimestamp(time::to_millis(timestamp));
+        let date_hour = CqlTimestamp(time::hour_bucket(timestamp));
+
+        let scale = |price: &str| price.parse::<f64>().unwrap_or(0.0) / price_scale;
+        let (best_bid, best_ask) = orderbook::top_of_book(orderbook, price_scale, quantity_scale);
+        let best_bid = best_bid.map(|level| level.price);
+        let best_ask = best_ask.map(|level| level.price);
+
+        let rows: Vec<(String, &str, String, String, String, String)> = orderbook
+            .bids
+            .iter()
+            .map(|o| ("bid", o))
+            .chain(orderbook.asks.iter().map(|o| ("ask", o)))
+            .map(|(side, order)| {
+                (
+                    orderbook_id.clone(),
+                    side,
+                    order.order_hash.clone(),
+                    scale(&order.price).to_string(),
+                    (order.quantity.parse::<f64>().unwrap_or(0.0) / quantity_scale).to_string(),
+                    order.subaccount_id.clone(),
+                )
+            })
+            .collect();
+
+        let chunks = batch_chunks(
+            &rows,
+            self.config.orderbook_batch_size.max(1),
+            self.config.orderbook_batch_max_bytes,
+            |(id, side, hash, price, quantity, subaccount)| {
+                id.len() + side.len() + hash.len() + price.len() + quantity.len() + subaccount.len()
+            },
+        );
+        // The writes are built up front so the stream holds plain futures
+        // rather than a closure over borrowed rows, which keeps it Send
+        let writes: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                let mut batch = Batch::new(BatchType::Unlogged);
+                for _ in chunk {
+                    batch.append_statement(self.statements.orderbook_order_insert.clone());
+                }
+                batch.set_timestamp(write_ts);
+                async move {
+                    self.run_batch("orderbook_orders", &batch, chunk).await?;
+                    Ok::<_, QueryError>(chunk.len())
+                }
+            })
+            .collect();
+        let results: Vec<Result<usize, QueryError>> = stream::iter(writes)
+            .buffer_unordered(self.config.orderbook_write_concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut written = 0;
+        for result in results {
+            written += result? as i64;
+        }
+        self.record_writes("orderbook_orders", written).await;
+
+        self.run(
+            &self.statements.orderbook_snapshot_insert,
+            write_ts,
+            (
+                &orderbook.market_id,
+                date_hour,
+                cql_timestamp,
+                block_height,
+                &orderbook_id,
+                orderbook.bids.len() as i32,
+                orderbook.asks.len() as i32,
+                best_bid.map(|p| p.to_string()),
+                best_ask.map(|p| p.to_string()),
+            ),
+        )
+        .await?;
+        self.record_write("orderbook_snapshots").await;
+
+        self.run(
+            &self.statements.orderbook_statistics_update,
+            write_ts,
+            (
+                best_bid,
+                best_ask,
+                best_bid.zip(best_ask).map(|(bid, ask)| (bid + ask) / 2.0),
+                &orderbook.market_id,
+                date_hour,
+            ),
+        )
+        .await?;
+        self.record_write("market_statistics").await;
+
+        let mark_price = self
+            .mark_prices
+            .lock()
+            .await
+            .get(&orderbook.market_id)
+            .copied();
+        if let Some(point) = MidPricePoint::new(
+            best_bid,
+            best_ask,
+            mark_price,
+            block_height as u64,
+            timestamp as u64,
+        ) {
+            self.run(
+                &self.statements.mid_price_insert,
+                write_ts,
+                (
+                    &orderbook.market_id,
+                    date_hour,
+                    cql_timestamp,
+                    block_height,
+                    point.mid_price,
+                    point.best_bid,
+                    point.best_ask,
+                    point.mark_price,
+                    point.divergence_bps,
+                ),
+            )
+            .await?;
+            self.record_write("mid_prices").await;
+        }
+
+        Ok(())
+    }
+
+    // Append the block's oracle prices; a replayed message rewrites the same rows
+    async fn process_oracle_prices(
+        &self,
+        prices: &[OraclePricePayload],
+        block_height: i64,
+        timestamp: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let write_ts = self.write_timestamp(block_height, timestamp);
+        let date_hour = CqlTimestamp(time::hour_bucket(timestamp));
+        let cql_timestamp = CqlTimestamp(time::to_millis(timestamp));
+        for price in prices {
+            let value = scaling::oracle_price(&price.price);
+            if value <= 0.0 {
+                continue;
+            }
+            self.run(
+                &self.statements.oracle_price_insert,
+                write_ts,
+                (
+                    &price.symbol,
+                    date_hour,
+                    cql_timestamp,
+                    block_height,
+                    value,
+                    &price.oracle_type,
+                ),
+            )
+            .await?;
+            self.record_write("oracle_prices").await;
+        }
+        Ok(())
+    }
+
+    // Fold a block's trades into the hourly market statistics. Every match is
+    // reported once per side: taker trades count towards volume and the
+    // aggressor split, resting-order trades towards maker volume.
+    async fn process_trades(
+        &self,
+        trades: &[DerivativeTradePayload],
+        block_height: i64,
+        timestamp: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let date_hour = time::hour_bucket(timestamp);
+
+        let mut block_stats: HashMap<&str, HourlyTradeStats> = HashMap::new();
+        let mut last_prices: HashMap<&str, f64> = HashMap::new();
+        for trade in trades {
+            self.history
+                .append_trade(&storage::trade_from_payload(
+                    trade,
+                    block_height as u64,
+                    timestamp as u64,
+                ))
+                .await?;
+            self.record_write("trades").await;
+
+            let price = scaling::price(&trade.position_delta.execution_price);
+            let quantity = scaling::quantity(&trade.position_delta.execution_quantity);
+
+            let stats = block_stats.entry(trade.market_id.as_str()).or_default();
+            if trade.execution_type == "LimitMatchRestingOrder" {
+                stats.maker_volume += price * quantity;
+                continue;
+            }
+
+            last_prices.insert(trade.market_id.as_str(), price);
+            stats.volume += price * quantity;
+            stats.trade_count += 1;
+            if trade.is_buy {
+                stats.taker_buy_count += 1;
+                stats.taker_buy_volume += price * quantity;
+            }
+        }
+
+        let write_ts = self.write_timestamp(block_height, timestamp);
+        let time_millis = time::to_millis(timestamp);
+        let mut trade_stats = self.trade_stats.lock().await;
+        // Hours that ended before the allowed lateness can no longer change
+        trade_stats.advance(time_millis);
+
+        for (market_id, block) in block_stats {
+            if !trade_stats.accepts(time_millis) {
+                metrics::consumer()
+                    .late_events
+                    .with_label_values(&["market_statistics"])
+                    .inc();
                 warn!(
-                    "Invalid position data for market {} subaccount {}, skipping",
-                    market.market_id, subaccount_id
+                    "ScyllaDB: Dropping late trades of {} at block {}, hour already closed",
+                    market_id, block_height
+                );
+                continue;
+            }
+            if !trade_stats.is_tracking(market_id, date_hour) {
+                // Resume from whatever an earlier run already wrote for this hour
+                let stored = self.load_trade_stats(market_id, date_hour).await?;
+                trade_stats.resume(market_id, date_hour, stored);
+            }
+            trade_stats.add(market_id, time_millis, &block);
+            let Some(totals) = trade_stats.get(market_id, date_hour).copied() else {
+                continue;
+            };
+
+            let taker_buy_ratio = ratio(totals.taker_buy_count as f64, totals.trade_count as f64);
+            let buy_volume_ratio = ratio(totals.taker_buy_volume, totals.volume);
+            self.run(
+                &self.statements.trade_statistics_update,
+                write_ts,
+                (
+                    totals.volume,
+                    totals.trade_count,
+                    totals.taker_buy_count,
+                    taker_buy_ratio,
+                    totals.maker_volume,
+                    totals.taker_buy_volume,
+                    totals.volume - totals.taker_buy_volume,
+                    totals.trade_count - totals.taker_buy_count,
+                    buy_volume_ratio,
+                    market_id,
+                    CqlTimestamp(date_hour),
+                ),
+            )
+            .await?;
+            self.record_write("market_statistics").await;
+        }
+        drop(trade_stats);
+
+        for (market_id, price) in last_prices {
+            self.write_volatility(market_id, price, timestamp, write_ts)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Sample the market's last taker price and, once per new minute, write the
+    // realized volatility of every window
+    async fn write_volatility(
+        &self,
+        market_id: &str,
+        price: f64,
+        timestamp: i64,
+        write_ts: Option<i64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let time_millis = time::to_millis(timestamp);
+        let estimates = {
+            let mut volatility = self.volatility.lock().await;
+            if !volatility.record(market_id, time_millis, price) {
+                return Ok(());
+            }
+            volatility.estimates(market_id)
+        };
+
+        for estimate in estimates {
+            self.run(
+                &self.statements.volatility_insert,
+                write_ts,
+                (
+                    market_id,
+                    estimate.window.as_str(),
+                    CqlTimestamp(time_millis),
+                    estimate.realized_variance,
+                    estimate.annualized_vol,
+                    estimate.returns as i32,
+                ),
+            )
+            .await?;
+            self.record_write("market_volatility").await;
+        }
+
+        Ok(())
+    }
+
+    async fn load_trade_stats(
+        &self,
+        market_id: &str,
+        date_hour: i64,
+    ) -> Result<HourlyTradeStats, Box<dyn Error + Send + Sync>> {
+        let result = self
+            .run(
+                &self.statements.trade_statistics_select,
+                None,
+                (market_id, CqlTimestamp(date_hour)),
+            )
+            .await?;
+
+        let stats = result
+            .into_rows_result()?
+            .maybe_first_row::<(
+                Option<f64>,
+                Option<i64>,
+                Option<i64>,
+                Option<f64>,
+                Option<f64>,
+            )>()?
+            .map(
+                |(volume, trade_count, taker_buy_count, maker_volume, taker_buy_volume)| {
+                    HourlyTradeStats {
+                        volume: volume.unwrap_or(0.0),
+                        trade_count: trade_count.unwrap_or(0),
+                        taker_buy_count: taker_buy_count.unwrap_or(0),
+                        maker_volume: maker_volume.unwrap_or(0.0),
+                        taker_buy_volume: taker_buy_volume.unwrap_or(0.0),
+                    }
+                },
+            )
+            .unwrap_or_default();
+        Ok(stats)
+    }
+
+    // Fold a block's taker fills into the open candles, then write every bar
+    // the block touched or closed and publish the closed ones. Bars of quiet
+    // markets are closed by the block time of the next trades message.
+    async fn update_candles(
+        &self,
+        fills: Vec<(&str, f64, f64)>,
+        block_height: i64,
+        timestamp: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(candles) = &self.candles else {
+            return Ok(());
+        };
+        let time = time::to_millis(timestamp) / 1_000;
+        let write_ts = self.write_timestamp(block_height, timestamp);
+
+        let mut aggregator = candles.lock().await;
+        let resolutions = aggregator.resolutions();
+        let closed = aggregator.close_elapsed(time);
+        let mut touched = BTreeSet::new();
+        for (market_id, price, quantity) in fills {
+            for &resolution in &resolutions {
+                if aggregator.needs_resume(market_id, resolution, time) {
+                    // Resume whatever an earlier run already wrote for this bar
+                    let bucket = resolution.bucket(time);
+                    if let Some(candle) = self.load_candle(market_id, resolution, bucket).await? {
+                        aggregator.resume(market_id, resolution, candle);
+                    }
+                }
+            }
+            if aggregator.apply(market_id, time, price, quantity) {
+                touched.insert(market_id);
+            } else {
+                metrics::consumer()
+                    .late_events
+                    .with_label_values(&["candles"])
+                    .inc();
+            }
+        }
+
+        for bar in &closed {
+            self.write_candle(&bar.market_id, bar.resolution, &bar.candle, true, write_ts)
+                .await?;
+        }
+        for market_id in touched {
+            for &resolution in &resolutions {
+                for candle in aggregator.open_candles(market_id, resolution) {
+                    self.write_candle(market_id, resolution, candle, false, write_ts)
+                        .await?;
+                }
+            }
+        }
+        drop(aggregator);
+
+        self.publish_candle_closes(&closed, timestamp).await;
+        Ok(())
+    }
+
+    async fn load_candle(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        time: i64,
+    ) -> Result<Option<Candle>, Box<dyn Error + Send + Sync>> {
+        let result = self
+            .run(
+                &self.statements.candle_select,
+                None,
+                (market_id, resolution.as_str(), CqlTimestamp(time * 1_000)),
+            )
+            .await?;
+
+        let candle = result
+            .into_rows_result()?
+            .maybe_first_row::<(f64, f64, f64, f64, f64)>()?
+            .map(|(open, high, low, close, volume)| Candle {
+                time,
+                open,
+                high,
+                low,
+                close,
+                volume,
+            });
+        Ok(candle)
+    }
+
+    async fn write_candle(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        candle: &Candle,
+        closed: bool,
+        write_ts: Option<i64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.run(
+            &self.statements.candle_insert,
+            write_ts,
+            (
+                market_id,
+                resolution.as_str(),
+                CqlTimestamp(candle.time * 1_000),
+                candle.open,
+                candle.high,
+                candle.low,
+                candle.close,
+                candle.volume,
+                closed,
+            ),
+        )
+        .await?;
+        self.record_write("candles").await;
+        Ok(())
+    }
+
+    #[cfg(feature = "pubsub")]
+    async fn publish_candle_closes(&self, closed: &[ClosedCandle], timestamp: i64) {
+        let Some(pubsub) = &self.pubsub else {
+            return;
+        };
+        let events: Vec<StreamEvent> = closed
+            .iter()
+            .filter_map(|bar| serde_json::to_value(bar).ok())
+            .map(|payload| StreamEvent::new(EventType::CandleClose, timestamp as u64, payload))
+            .collect();
+        if let Err(e) = pubsub.publish_events_batch(events).await {
+            warn!("ScyllaDB: Failed to publish candle closes: {}", e);
+        }
+    }
+
+    #[cfg(not(feature = "pubsub"))]
+    async fn publish_candle_closes(&self, _closed: &[ClosedCandle], _timestamp: i64) {}
+
+    // Record a position that disappeared from the snapshot as a zero-quantity
+    // history row and drop it from the liquidatable set
+    // Only heartbeat snapshots are complete, so only they are diffed and can
+    // close positions; streamed changes are written as they arrive
+    async fn process_positions(
+        &self,
+        positions: &[crate::models::PositionPayload],
+        source: PositionSource,
+        block_height: i64,
+        timestamp: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let diff = match (&self.position_differ, source) {
+            (Some(differ), PositionSource::Heartbeat) => Some(differ.lock().await.diff(positions)),
+            _ => None,
+        };
+
+        let mut failed = None;
+        for position in positions {
+            if !diff.as_ref().is_none_or(|d| d.should_emit(position)) {
+                continue;
+            }
+            if !self
+                .claim_position(
+                    &position.market_id,
+                    &position.subaccount_id,
+                    source,
+                    block_height,
+                )
+                .await
+            {
+                debug!(
+                    "ScyllaDB: Skipping stale {} position for market={}, subaccount={} at block {}",
+                    source.as_str(),
+                    position.market_id,
+                    position.subaccount_id,
+                    block_height
                 );
                 continue;
             }
-
-            // All inputs to calculate_liquidation_price are already scaled
-            let liquidation_price = calculate_liquidation_price(
-                is_long,
-                entry_price_val,
-                margin_val,
-                quantity_val,
-                maintenance_margin_ratio,
-                cumulative_funding,
-                cumulative_funding_entry_val,
-            );
-
-            // Update both position tables with the new liquidation price
-            let update_positions_query = "UPDATE injective.positions 
-                SET liquidation_price = ? 
-                WHERE market_id = ? AND subaccount_id = ? AND block_height = ?";
-
             if let Err(e) = self
-                .session
-                .query_unpaged(
-                    update_positions_query,
-                    (
-                        liquidation_price.to_string(),
-                        &market.market_id,
-                        &subaccount_id,
-                        pos_block_height,
-                    ),
-                )
+                .process_position(position, block_height, timestamp)
                 .await
             {
-                error!("Failed to update liquidation price in positions table: {}", e);
-                // Continue with other updates even if this one fails
+                error!("ScyllaDB: Error processing position: {}", e);
+                failed.get_or_insert(e);
             }
+        }
 
-            let update_market_positions_query = "UPDATE injective.market_positions 
-                SET liquidation_price = ? 
-                WHERE market_id = ? AND subaccount_id = ? AND block_height = ?";
-
-            if let Err(e) = self
-                .session
-                .query_unpaged(
-                    update_market_positions_query,
-                    (
-                        liquidation_price.to_string(),
-                        &market.market_id,
-                        &subaccount_id,
-                        pos_block_height,
-                    ),
-                )
+        for (market_id, subaccount_id) in diff.iter().flat_map(|d| &d.closed) {
+            // A newer streamed update may have reopened the position
+            if !self
+                .claim_position(market_id, subaccount_id, source, block_height)
                 .await
             {
-                error!("Failed to update liquidation price in market_positions table: {}", e);
                 continue;
             }
+            if let Err(e) = self
+                .close_position(market_id, subaccount_id, block_height, timestamp)
+                .await
+            {
+                error!("ScyllaDB: Error recording closed position: {}", e);
+                failed.get_or_insert(e);
+            }
+        }
+        failed.map_or(Ok(()), Err)
+    }
 
-            // Both inputs already scaled
-            let liquidatable = is_liquidatable(is_long, liquidation_price, mark_price);
-            if liquidatable {
-                let liquidatable_query = "INSERT INTO injective.liquidatable_positions (
-                    market_id, subaccount_id, block_height, timestamp, is_long, quantity, 
-                    entry_price, margin, liquidation_price, mark_price
-                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+    // Record an update as the newest applied to the position, or return false
+    // when it is older than the one already applied
+    async fn claim_position(
+        &self,
+        market_id: &str,
+        subaccount_id: &str,
+        source: PositionSource,
+        block_height: i64,
+    ) -> bool {
+        let mut heights = self.position_heights.lock().await;
+        let key = (market_id.to_string(), subaccount_id.to_string());
+        if let Some(&(stored_height, stored_source)) = heights.get(&key) {
+            if !source.supersedes(
+                block_height as u64,
+                stored_height as u64,
+                Some(stored_source),
+            ) {
+                return false;
+            }
+        }
+        heights.insert(key, (block_height, source));
+        true
+    }
 
-                if let Err(e) = self
-                    .session
-                    .query_unpaged(
-                        liquidatable_query,
-                        (
-                            &market.market_id,
-                            &subaccount_id,
-                            pos_block_height,
-                            cql_timestamp,
-                            is_long,
-                            quantity,
-                            entry_price,
-                            margin,
-                            liquidation_price.to_string(),
-                            mark_price.to_string(),
-                        ),
-                    )
-                    .await
-                {
-                    error!("Failed to insert liquidatable position: {}", e);
-                    continue;
-                }
+    // Record the settlement of an expiry market and stamp its positions with
+    // the settlement price. Settled positions can't be liquidated, so their
+    // liquidatable rows are cleared. Every write is keyed, so repeated market
+    // updates after the settlement rewrite the same rows.
+    async fn settle_market(
+        &self,
+        settlement: &MarketSettlement,
+        block_height: i64,
+        timestamp: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let market_id = settlement.market_id.as_str();
+        let settlement_price = settlement.settlement_price.to_string();
+        let write_ts = self.write_timestamp(block_height, timestamp);
+        self.run(
+            &self.statements.market_settlement_insert,
+            write_ts,
+            (
+                market_id,
+                settlement.expiration_timestamp,
+                &settlement_price,
+                block_height,
+                CqlTimestamp(time::to_millis(timestamp)),
+            ),
+        )
+        .await?;
+        self.record_write("market_settlements").await;
 
-                info!("Liquidatable position inserted: market={}, subaccount={}, liq_price={}, mark_price={}",
-                      market.market_id, subaccount_id, liquidation_price, mark_price);
-            } else {
-                let delete_query = "DELETE FROM injective.liquidatable_positions 
-                    WHERE market_id = ? AND subaccount_id = ?";
+        let rows_result = self
+            .run(&self.statements.market_positions_select, None, (market_id,))
+            .await?
+            .into_rows_result()?;
+        let mut rows_iter =
+            rows_result.rows::<(String, bool, String, String, String, String, i64)>()?;
+        while let Some(row) = rows_iter.next().transpose()? {
+            let (subaccount_id, _, _, _, _, _, position_height) = row;
+            for (table, statement) in [
+                ("positions", &self.statements.position_settle),
+                ("market_positions", &self.statements.market_position_settle),
+            ] {
+                self.run(
+                    statement,
+                    write_ts,
+                    (
+                        &settlement_price,
+                        market_id,
+                        &subaccount_id,
+                        position_height,
+                    ),
+                )
+                .await?;
+                self.record_write(table).await;
+            }
 
-                if let Err(e) = self
-                    .session
-                    .query_unpaged(delete_query, (&market.market_id, &subaccount_id))
-                    .await
-                {
-                    error!("Failed to delete non-liquidatable position: {}", e);
-                }
+            let result = self
+                .run(
+                    &self.statements.liquidatable_delete,
+                    None,
+                    (market_id, &subaccount_id, block_height),
+                )
+                .await?;
+            if lwt_outcome(result)?.0 {
+                self.record_write("liquidatable_positions").await;
             }
+            self.liquidation_checks.lock().await.insert(
+                (market_id.to_string(), subaccount_id),
+                (block_height, false),
+            );
         }
-
         Ok(())
     }
 
-    async fn process_position(
+    async fn close_position(
         &self,
-        position: &crate::models::PositionPayload,
+        market_id: &str,
+        subaccount_id: &str,
         block_height: i64,
         timestamp: i64,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        // Apply proper scaling
-        let is_long = position.is_long;
-        let quantity = position.quantity.parse::<f64>().unwrap_or(0.0) / QUANTITY_DECIMAL;
-        let entry_price = position.entry_price.parse::<f64>().unwrap_or(0.0) / PRICE_DECIMAL;
-        let margin = position.margin.parse::<f64>().unwrap_or(0.0) / PRICE_DECIMAL;
-        let cumulative_funding_entry = position
-            .cumulative_funding_entry
-            .parse::<f64>()
-            .unwrap_or(0.0)
-            / PRICE_DECIMAL;
+        let cql_timestamp = CqlTimestamp(time::to_millis(timestamp));
+        let write_ts = self.write_timestamp(block_height, timestamp);
 
-        if quantity <= 0.0 || entry_price <= 0.0 || margin <= 0.0 {
-            warn!(
-                "Invalid position data for market {} subaccount {}, skipping",
-                position.market_id, position.subaccount_id
-            );
-            return Ok(());
+        for (table, statement) in [
+            ("positions", &self.statements.position_close),
+            ("market_positions", &self.statements.market_position_close),
+        ] {
+            self.run(
+                statement,
+                write_ts,
+                (market_id, subaccount_id, block_height, cql_timestamp),
+            )
+            .await?;
+            self.record_write(table).await;
         }
 
-        let market_query = "SELECT mark_price, maintenance_margin_ratio, cumulative_funding 
-            FROM injective.markets 
-            WHERE market_id = ? 
-            LIMIT 1";
+        let result = self
+            .run(
+                &self.statements.liquidatable_delete,
+                None,
+                (market_id, subaccount_id, block_height),
+            )
+            .await?;
+        if lwt_outcome(result)?.0 {
+            self.record_write("liquidatable_positions").await;
+        }
+        self.liquidation_checks.lock().await.insert(
+            (market_id.to_string(), subaccount_id.to_string()),
+            (block_height, false),
+        );
 
-        let market_result = self
-            .session
-            .query_unpaged(market_query, (&position.market_id,))
-            .await
-            .map_err(|e| {
-                error!("Failed to fetch market data: {}", e);
-                e
-            })?;
+        Ok(())
+    }
+}
 
-        let (mark_price, maintenance_margin_ratio, market_cumulative_funding) = {
-            // Convert the QueryResult into rows.
-            if let Ok(rows_result) = market_result.into_rows_result() {
-                // Use a tuple of &str for lifetime convenience.
-                let mut rows_iter = rows_result.rows::<(&str, &str, &str)>()?;
-                if let Some(row) = rows_iter.next().transpose()? {
-                    let (mp, mmr, mcf) = row;
-                    (
-                        mp.parse::<f64>().unwrap_or(0.0),   // Already scaled in DB
-                        mmr.parse::<f64>().unwrap_or(0.05), // Ratio, no scaling
-                        mcf.parse::<f64>().unwrap_or(0.0),  // Already scaled in DB
-                    )
-                } else {
-                    (0.0, 0.05, 0.0)
-                }
-            } else {
-                (0.0, 0.05, 0.0)
-            }
-        };
+// A position checked against the mark price of its market at a block, with
+// every value already scaled
+struct LiquidationCheck<'a> {
+    market_id: &'a str,
+    subaccount_id: &'a str,
+    block_height: i64,
+    timestamp: i64,
+    is_long: bool,
+    quantity: f64,
+    entry_price: f64,
+    margin: f64,
+    liquidation_price: f64,
+    mark_price: f64,
+}
 
-        // All inputs now properly scaled
-        let liquidation_price = calculate_liquidation_price(
-            is_long,
-            entry_price,
-            margin,
-            quantity,
-            maintenance_margin_ratio,
-            market_cumulative_funding,
-            cumulative_funding_entry,
-        );
+// The [applied] flag of a lightweight transaction, which comes first in its
+// result, and the block_height it was checked against when not applied
+fn lwt_outcome(result: QueryResult) -> Result<(bool, Option<i64>), Box<dyn Error + Send + Sync>> {
+    let row = result.into_rows_result()?.first_row::<Row>()?;
+    let applied = matches!(row.columns.first(), Some(Some(CqlValue::Boolean(true))));
+    let block_height = row.columns.iter().skip(1).find_map(|column| match column {
+        Some(CqlValue::BigInt(height)) => Some(*height),
+        _ => None,
+    });
+    Ok((applied, block_height))
+}
 
-        // Convert timestamp to CqlTimestamp
-        let datetime: DateTime<Utc> = match Utc.timestamp_opt(timestamp, 0) {
-            LocalResult::Single(dt) => dt,
-            _ => Utc::now(), // Fallback to current time if invalid timestamp
-        };
-        let cql_timestamp = CqlTimestamp(datetime.timestamp_millis());
+// Split rows into batches of at most `max_rows` rows whose values add up to
+// at most `max_bytes`. A single row larger than `max_bytes` still gets a
+// batch of its own.
+fn batch_chunks<T>(
+    rows: &[T],
+    max_rows: usize,
+    max_bytes: usize,
+    row_bytes: impl Fn(&T) -> usize,
+) -> Vec<&[T]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut bytes = 0;
+    for (i, row) in rows.iter().enumerate() {
+        let size = row_bytes(row);
+        if i > start && (i - start == max_rows || bytes + size > max_bytes) {
+            chunks.push(&rows[start..i]);
+            start = i;
+            bytes = 0;
+        }
+        bytes += size;
+    }
+    if start < rows.len() {
+        chunks.push(&rows[start..]);
+    }
+    chunks
+}
 
-        // Insert into the original positions table
-        let position_query = "INSERT INTO injective.positions (
-            market_id, subaccount_id, block_height, timestamp, is_long, quantity, 
-            entry_price, margin, cumulative_funding_entry, liquidation_price
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+fn record_statement(table: &str, elapsed: std::time::Duration, succeeded: bool) {
+    let metrics = metrics::consumer();
+    metrics
+        .scylla_statement_seconds
+        .with_label_values(&[table])
+        .observe(elapsed.as_secs_f64());
+    if !succeeded {
+        metrics.scylla_errors.with_label_values(&[table]).inc();
+    }
+}
 
-        self.session
-            .query_unpaged(
-                position_query,
+// Name of the injective.* table a statement targets, used for per-table settings
+fn statement_table(cql: &str) -> &str {
+    cql.split("injective.")
+        .nth(1)
+        .and_then(|rest| {
+            rest.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .next()
+        })
+        .unwrap_or("unknown")
+}
+
+#[cfg(feature = "api")]
+pub struct ScyllaCandleSource {
+    session: Arc<Session>,
+    statements: Arc<PreparedStatements>,
+}
+
+#[cfg(feature = "api")]
+#[async_trait]
+impl CandleSource for ScyllaCandleSource {
+    async fn candles(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Candle>, Box<dyn Error + Send + Sync>> {
+        let result = self
+            .session
+            .execute_unpaged(
+                &self.statements.candle_range_select,
                 (
-                    &position.market_id,
-                    &position.subaccount_id,
-                    block_height,
-                    cql_timestamp,
-                    is_long,
-                    quantity.to_string(),                 // Store scaled value
-                    entry_price.to_string(),              // Store scaled value
-                    margin.to_string(),                   // Store scaled value
-                    cumulative_funding_entry.to_string(), // Store scaled value
-                    liquidation_price.to_string(),
+                    market_id,
+                    resolution.as_str(),
+                    CqlTimestamp(from * 1_000),
+                    CqlTimestamp(to * 1_000),
                 ),
             )
-            .await
-            .map_err(|e| {
-                error!("Failed to insert position: {}", e);
-                e
-            })?;
+            .await?;
 
-        // Also insert into the market-optimized positions table
-        let market_position_query = "INSERT INTO injective.market_positions (
-            market_id, subaccount_id, block_height, timestamp, is_long, quantity, 
-            entry_price, margin, cumulative_funding_entry, liquidation_price
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+        let mut candles = Vec::new();
+        for row in result
+            .into_rows_result()?
+            .rows::<(CqlTimestamp, f64, f64, f64, f64, f64)>()?
+        {
+            candles.push(candle_from_row(row?));
+        }
+        Ok(candles)
+    }
 
-        self.session
-            .query_unpaged(
-                market_position_query,
-                (
-                    &position.market_id,
-                    &position.subaccount_id,
-                    block_height,
-                    cql_timestamp,
-                    is_long,
-                    quantity.to_string(),                 // Store scaled value
-                    entry_price.to_string(),              // Store scaled value
-                    margin.to_string(),                   // Store scaled value
-                    cumulative_funding_entry.to_string(), // Store scaled value
-                    liquidation_price.to_string(),
-                ),
+    async fn candle_before(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        before: i64,
+    ) -> Result<Option<Candle>, Box<dyn Error + Send + Sync>> {
+        // Rows are clustered newest first
+        let result = self
+            .session
+            .execute_unpaged(
+                &self.statements.candle_before_select,
+                (market_id, resolution.as_str(), CqlTimestamp(before * 1_000)),
             )
-            .await
-            .map_err(|e| {
-                error!("Failed to insert market position: {}", e);
-                e
-            })?;
+            .await?;
 
-        // Both inputs already scaled
-        let liquidatable = is_liquidatable(is_long, liquidation_price, mark_price);
+        Ok(result
+            .into_rows_result()?
+            .maybe_first_row::<(CqlTimestamp, f64, f64, f64, f64, f64)>()?
+            .map(candle_from_row))
+    }
+}
 
-        if liquidatable {
-            let liquidatable_query = "INSERT INTO injective.liquidatable_positions (
-                market_id, subaccount_id, block_height, timestamp, is_long, quantity, 
-                entry_price, margin, liquidation_price, mark_price
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+#[cfg(feature = "api")]
+fn candle_from_row(
+    (time, open, high, low, close, volume): (CqlTimestamp, f64, f64, f64, f64, f64),
+) -> Candle {
+    Candle {
+        time: time.0 / 1_000,
+        open,
+        high,
+        low,
+        close,
+        volume,
+    }
+}
 
-            if let Err(e) = self
-                .session
-                .query_unpaged(
-                    liquidatable_query,
-                    (
-                        &position.market_id,
-                        &position.subaccount_id,
-                        block_height,
-                        cql_timestamp,
-                        is_long,
-                        quantity.to_string(),    // Store scaled value
-                        entry_price.to_string(), // Store scaled value
-                        margin.to_string(),      // Store scaled value
-                        liquidation_price.to_string(),
-                        mark_price.to_string(),
-                    ),
-                )
-                .await
-            {
-                error!("Failed to insert liquidatable position: {}", e);
-            } else {
-                info!("Liquidatable position updated: market={}, subaccount={}, liq_price={}, mark_price={}",
-                    position.market_id, position.subaccount_id, liquidation_price, mark_price);
+#[cfg(feature = "api")]
+pub struct ScyllaTradeHistory {
+    session: Arc<Session>,
+    statements: Arc<PreparedStatements>,
+}
+
+#[cfg(feature = "api")]
+type SubaccountTradeRow = (
+    CqlTimestamp,
+    String,
+    bool,
+    String,
+    String,
+    bool,
+    f64,
+    f64,
+    f64,
+    i64,
+);
+
+#[cfg(feature = "api")]
+#[async_trait]
+impl TradeHistorySource for ScyllaTradeHistory {
+    async fn subaccount_trades(
+        &self,
+        subaccount_id: &str,
+        before: Option<&TradeCursor>,
+        limit: usize,
+    ) -> Result<Vec<SubaccountTrade>, Box<dyn Error + Send + Sync>> {
+        // Rows are clustered newest first, in cursor order
+        let limit = limit as i32;
+        let result = match before {
+            Some(cursor) => {
+                self.session
+                    .execute_unpaged(
+                        &self.statements.subaccount_trades_before_select,
+                        (
+                            subaccount_id,
+                            CqlTimestamp(cursor.timestamp),
+                            &cursor.trade_id,
+                            cursor.is_maker,
+                            limit,
+                        ),
+                    )
+                    .await?
             }
-        } else {
-            let delete_query = "DELETE FROM injective.liquidatable_positions 
-                WHERE market_id = ? AND subaccount_id = ?";
-            if let Err(e) = self
-                .session
-                .query_unpaged(delete_query, (&position.market_id, &position.subaccount_id))
-                .await
-            {
-                error!("Failed to delete non-liquidatable position: {}", e);
+            None => {
+                self.session
+                    .execute_unpaged(
+                        &self.statements.subaccount_trades_select,
+                        (subaccount_id, limit),
+                    )
+                    .await?
             }
+        };
+
+        let mut trades = Vec::new();
+        for row in result.into_rows_result()?.rows::<SubaccountTradeRow>()? {
+            let (
+                timestamp,
+                trade_id,
+                is_maker,
+                market_id,
+                market_type,
+                is_buy,
+                price,
+                quantity,
+                fee,
+                block_height,
+            ) = row?;
+            trades.push(SubaccountTrade {
+                subaccount_id: subaccount_id.to_string(),
+                market_id,
+                market_type: market_type.parse()?,
+                trade_id,
+                is_buy,
+                is_maker,
+                price,
+                quantity,
+                fee,
+                block_height,
+                timestamp: DateTime::from_timestamp_millis(timestamp.0).unwrap_or_default(),
+            });
         }
+        Ok(trades)
+    }
+}
+
+pub struct ScyllaCorrelationSink {
+    session: Arc<Session>,
+    statements: Arc<PreparedStatements>,
+}
 
+#[async_trait]
+impl CorrelationSink for ScyllaCorrelationSink {
+    // One row per pair with a correlation, each pair stored once
+    async fn store(&self, matrix: &CorrelationMatrix) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let computed_at = CqlTimestamp(matrix.computed_at);
+        for (i, market_a) in matrix.markets.iter().enumerate() {
+            for (j, market_b) in matrix.markets.iter().enumerate().skip(i + 1) {
+                let Some(correlation) = matrix.correlations[i][j] else {
+                    continue;
+                };
+                self.session
+                    .execute_unpaged(
+                        &self.statements.correlation_insert,
+                        (
+                            market_a,
+                            market_b,
+                            &matrix.resolution,
+                            computed_at,
+                            correlation,
+                            matrix.samples[i][j] as i32,
+                        ),
+                    )
+                    .await?;
+            }
+        }
         Ok(())
     }
 }
 
 #[async_trait]
 impl MessageProcessor for ScyllaDBProcessor {
-    async fn process_message(
-        &self,
-        message: KafkaMessage,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    async fn process_message(&self, mut message: KafkaMessage) -> Result<(), IndexerError> {
         let block_height = message.block_height as i64;
         let timestamp = message.block_time as i64;
 
-        match &message.payload {
-            KafkaPayload::DerivativeMarkets(markets) => {
-                for market in markets {
-                    if let Err(e) = self
-                        .process_derivative_market(market, block_height, timestamp)
-                        .await
-                    {
-                        error!("ScyllaDB: Error processing derivative market: {}", e);
+        // Latest-state writers and the trade accumulators need guarding
+        // against replays
+        let guarded = self.config.idempotency != IdempotencyMode::Off
+            && matches!(
+                message.payload,
+                KafkaPayload::DerivativeMarkets(_)
+                    | KafkaPayload::StreamPositions(_)
+                    | KafkaPayload::ExchangePositions(_)
+                    | KafkaPayload::DerivativeTrades(_)
+                    | KafkaPayload::SpotTrades(_)
+            );
+        // Only the message-level modes key their markers by content hash
+        let content_hash = (guarded
+            && matches!(
+                self.config.idempotency,
+                IdempotencyMode::ContentHash | IdempotencyMode::LightweightTransactions
+            ))
+        .then(|| message.content_hash() as i64);
+
+        if let Some(content_hash) = content_hash {
+            match self.already_processed(&message, content_hash).await {
+                Ok(true) => {
+                    debug!(
+                        "ScyllaDB: Skipping replayed {:?} message at block {}",
+                        message.message_type, block_height
+                    );
+                    return Ok(());
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    warn!(
+                        "ScyllaDB: Idempotency check failed, processing anyway: {}",
+                        e
+                    );
+                }
+            }
+        }
+
+        // Drop the entities a replay already applied, and the message when
+        // none are left
+        if guarded && self.config.idempotency == IdempotencyMode::Entity {
+            match self.processed_entities(&message).await {
+                Ok(processed) if !processed.is_empty() => {
+                    if !message.retain_entities(|id| !processed.contains(id)) {
+                        debug!(
+                            "ScyllaDB: Skipping replayed {:?} message at block {}",
+                            message.message_type, block_height
+                        );
+                        return Ok(());
                     }
                 }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!(
+                        "ScyllaDB: Idempotency check failed, processing anyway: {}",
+                        e
+                    );
+                }
             }
-            KafkaPayload::ExchangePositions(positions) => {
-                for position in positions {
-                    if let Err(e) = self
-                        .process_position(position, block_height, timestamp)
-                        .await
-                    {
-                        error!("ScyllaDB: Error processing position: {}", e);
+        }
+
+        let applied = self.apply(&message, block_height, timestamp).await;
+
+        if let Err(e) = self.flush_write_counters(&message).await {
+            warn!("ScyllaDB: Failed to update write counters: {}", e);
+        }
+
+        // A failed message is left unmarked, and a claim on it released, so
+        // its redelivery is applied again
+        if let Err(e) = applied {
+            if let Some(content_hash) = content_hash {
+                if self.config.idempotency == IdempotencyMode::LightweightTransactions {
+                    if let Err(e) = self.release_processed(&message, content_hash).await {
+                        warn!("ScyllaDB: Failed to release processed message claim: {}", e);
                     }
                 }
             }
-            _ => {}
+            return Err(e.into());
+        }
+
+        if let Some(content_hash) = content_hash {
+            if self.config.idempotency == IdempotencyMode::ContentHash {
+                if let Err(e) = self.mark_processed(&message, content_hash).await {
+                    warn!("ScyllaDB: Failed to record processed message: {}", e);
+                }
+            }
+        }
+
+        // Entities are only marked once all of them were written, so a
+        // redelivery retries the ones that failed
+        if guarded && self.config.idempotency == IdempotencyMode::Entity {
+            if let Err(e) = self.mark_entities_processed(&message).await {
+                warn!("ScyllaDB: Failed to record processed entities: {}", e);
+            }
         }
+
         Ok(())
     }
-}
\ No newline at end of file
+}
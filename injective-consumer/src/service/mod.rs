@@ -0,0 +1,611 @@
+use futures::future::join_all;
+use log::{error, info, warn};
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+use tokio::sync::{oneshot, watch, Notify};
+use tokio::task;
+use tokio::time::Duration;
+
+use crate::admin;
+use crate::block_dedup::DedupWindow;
+#[cfg(feature = "api")]
+use crate::candles::Resolution;
+use crate::catchup::CatchUpReporter;
+use crate::config::{Config, StorageBackend};
+use crate::consumer::KafkaConsumer;
+#[cfg(feature = "api")]
+use crate::correlation::{CorrelationJob, CorrelationJobConfig};
+use crate::dead_letter::DeadLetterQueue;
+use crate::dual_write::{DualWriteSampler, DualWriteSamplerConfig};
+use crate::hooks::HookChain;
+use crate::keyspace::{KeyspaceMonitor, KeyspaceMonitorConfig};
+use crate::liquidation::{LiquidationRecomputeConfig, LiquidationRecomputer};
+use crate::market_preloader::MarketPreloader;
+use crate::orderbook::ChainBookSnapshots;
+use crate::payload_log::PayloadLogger;
+use crate::pubsub::{RedisPubSubConfig, RedisPubSubService};
+#[cfg(feature = "api")]
+use crate::reader::RedisReader;
+use crate::readiness::{ReadinessGate, RedisReadiness};
+use crate::reaper::{IndexReaper, ReaperConfig};
+use crate::redis_consumer::RedisProcessor;
+use crate::redis_keys;
+use crate::routing::RoutingConfig;
+use crate::scylladb_consumer::ScyllaDBProcessor;
+use crate::secrets::{self, RedisRole};
+use crate::storage::MemoryStore;
+#[cfg(feature = "trade-qa")]
+use crate::trade_qa;
+
+// How long a stopping service waits for queued PubSub events to go out
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Runs the whole consumer side: the market preloader plus the Redis and
+// ScyllaDB consumers and their maintenance tasks, until the shutdown flag
+// flips to true. Redis and ScyllaDB locations come from REDIS_WRITE_URL (or
+// REDIS_URL) and SCYLLADB_NODES, like the rest of the environment-only
+// settings.
+pub async fn run(
+    config: Config,
+    mut shutdown_rx: watch::Receiver<bool>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Every key built from here on is namespaced by the configured schema
+    redis_keys::install(config.redis_keys.clone());
+
+    // Get additional configuration from environment
+    let redis_url = secrets::role_redis_url(RedisRole::Writer, Some("redis://127.0.0.1:6379"))?
+        .unwrap_or_default();
+    let scylladb_nodes = env::var("SCYLLADB_NODES")
+        .unwrap_or_else(|_| "127.0.0.1:9042".to_string())
+        .split(',')
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>();
+    admin::runtime().set_config("redis_write_url", &redis_url);
+    admin::runtime().set_config("scylladb_nodes", &scylladb_nodes);
+
+    // Load operator enrichment hooks
+    let hooks = Arc::new(load_hooks(&config)?);
+
+    // Raw payload logging, adjustable at runtime through the config:payload_log hash
+    let payload_log = Arc::new(PayloadLogger::new(&config.payload_log));
+    if let Err(e) = payload_log
+        .clone()
+        .watch(
+            &redis_url,
+            config.payload_log.clone(),
+            Duration::from_secs(10),
+        )
+        .await
+    {
+        error!("Failed to watch payload log overrides: {}", e);
+    }
+
+    // Failed messages from every consumer go to one dead-letter topic
+    let dead_letter = match &config.kafka.dead_letter_topic {
+        Some(topic) => {
+            info!("Dead-lettering failed messages to {}", topic);
+            Some(Arc::new(DeadLetterQueue::new(&config.kafka, topic)?))
+        }
+        None => None,
+    };
+
+    // Optional per-event routing to additional channel namespaces
+    let routing = match env::var("PUBSUB_ROUTING_FILE") {
+        Ok(path) => {
+            info!("Loading PubSub routing rules from {}", path);
+            let routing = RoutingConfig::from_file(&path)?;
+            admin::runtime().set_filter("pubsub_routing", &routing);
+            Some(Arc::new(routing))
+        }
+        Err(_) => None,
+    };
+
+    // Initialize Redis PubSub service
+    info!("Initializing Redis PubSub service");
+    let pubsub_config = RedisPubSubConfig {
+        redis_url: redis_url.clone(),
+        hooks: Some(hooks.clone()),
+        routing,
+        dedup_ttl: config
+            .pubsub_dedup
+            .enabled
+            .then(|| Duration::from_secs(config.pubsub_dedup.ttl_secs)),
+        // Customize other options as needed
+        ..RedisPubSubConfig::default()
+    };
+
+    let pubsub_service = match RedisPubSubService::new(pubsub_config).await {
+        Ok(service) => {
+            info!("Redis PubSub service initialized");
+            Arc::new(service)
+        }
+        Err(e) => {
+            error!("Failed to initialize Redis PubSub service: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    // Initialize Redis processor with PubSub service
+    info!("Connecting to Redis at {}", redis_url);
+    let redis_processor = match RedisProcessor::new(&redis_url).await {
+        Ok(processor) => {
+            info!("Connected to Redis: {}", redis_url);
+            processor.with_pubsub(pubsub_service.clone()) // Add PubSub service here
+        }
+        Err(e) => {
+            error!("Failed to connect to Redis: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    // Optionally mirror writes to a secondary Redis while migrating clusters
+    let redis_processor =
+        match secrets::redis_url("REDIS_SECONDARY_URL", "REDIS_SECONDARY_PASSWORD", None)? {
+            Some(secondary_url) => {
+                let processor = redis_processor.with_secondary(&secondary_url).await?;
+                info!("Dual-write enabled, mirroring Redis writes to secondary");
+
+                let sampler_config = DualWriteSamplerConfig {
+                    primary_url: redis_url.clone(),
+                    secondary_url,
+                    interval_secs: 60,
+                    sample_size: 100,
+                };
+                match DualWriteSampler::new(sampler_config).await {
+                    Ok(sampler) => {
+                        sampler.spawn();
+                    }
+                    Err(e) => {
+                        error!("Failed to start dual-write comparison sampler: {}", e);
+                    }
+                }
+                processor
+            }
+            None => redis_processor,
+        };
+
+    // Publish position deltas instead of full snapshots
+    let redis_processor = if config.position_diff.enabled {
+        redis_processor.with_position_diff(config.position_diff.full_snapshot_every)
+    } else {
+        redis_processor
+    };
+
+    let redis_processor = if config.spot_trades.enabled {
+        redis_processor.with_spot_trades(config.spot_trades.recent_trades)
+    } else {
+        redis_processor
+    };
+
+    let redis_processor = if config.subaccount_trades.enabled {
+        redis_processor.with_subaccount_trades(config.subaccount_trades.recent_trades)
+    } else {
+        redis_processor
+    };
+
+    // Windows stay open this long for trades from late blocks
+    let allowed_lateness = Duration::from_secs(config.window.allowed_lateness_secs);
+    let redis_processor = redis_processor.with_allowed_lateness(allowed_lateness);
+
+    // L2 levels at the configured tick sizes, optionally published
+    let redis_processor = redis_processor.with_depth(config.depth.clone());
+
+    // Recent mid prices and mark divergence alerts
+    let redis_processor = redis_processor.with_mid_prices(config.mid_price.clone());
+
+    // Liquidation alerts claimed per position and block, so replays don't repeat them
+    let redis_processor = if config.liquidation.alert_dedup_ttl_secs > 0 {
+        redis_processor
+            .with_alert_dedup(Duration::from_secs(config.liquidation.alert_dedup_ttl_secs))
+    } else {
+        redis_processor
+    };
+
+    // Funding settlements wake the liquidation recompute
+    let recompute_trigger = config
+        .liquidation
+        .recompute_on_funding
+        .then(|| Arc::new(Notify::new()));
+    let redis_processor = match &recompute_trigger {
+        Some(trigger) => redis_processor.with_recompute_trigger(trigger.clone()),
+        None => redis_processor,
+    };
+
+    // Derivative books kept from stream deltas, resnapshotted over the L3 query
+    let redis_processor = if config.live_books.enabled {
+        let snapshots = ChainBookSnapshots::new(
+            &config.grpc.query_endpoint,
+            Duration::from_secs(config.live_books.snapshot_timeout_secs),
+        )?;
+        redis_processor.with_live_books(Arc::new(snapshots))
+    } else {
+        redis_processor
+    };
+
+    // The memory backend replaces the Redis state and ScyllaDB history; the
+    // Redis-only aggregates and indexes are still written to Redis
+    let memory_store = match config.storage.backend {
+        StorageBackend::Native => None,
+        StorageBackend::Memory => {
+            info!("Keeping market, position and book state and history in memory");
+            Some(Arc::new(
+                MemoryStore::new().with_history_limit(config.storage.memory_history_limit),
+            ))
+        }
+    };
+    let redis_processor = match &memory_store {
+        Some(store) => redis_processor.with_state_store(store.clone()),
+        None => redis_processor,
+    };
+
+    // Start the reaper that prunes stale members from the Redis index sets
+    if config.reaper.enabled {
+        let reaper_config = ReaperConfig {
+            redis_url: redis_url.clone(),
+            interval_secs: config.reaper.interval_secs,
+            scan_count: config.reaper.scan_count,
+            batch_pause_ms: config.reaper.batch_pause_ms,
+        };
+        match IndexReaper::new(reaper_config).await {
+            Ok(reaper) => {
+                reaper.spawn();
+                info!("Redis index reaper started");
+            }
+            Err(e) => {
+                error!("Failed to start Redis index reaper: {}", e);
+            }
+        }
+    }
+
+    // Start the keyspace usage reporter, with budgets in bytes per key group
+    let mut keyspace_config = KeyspaceMonitorConfig {
+        redis_url: redis_url.clone(),
+        ..KeyspaceMonitorConfig::default()
+    };
+    if let Ok(budgets) = env::var("REDIS_KEYSPACE_BUDGETS") {
+        // Format: group=bytes,group=bytes
+        for entry in budgets.split(',') {
+            if let Some((group, bytes)) = entry.split_once('=') {
+                match bytes.trim().parse::<u64>() {
+                    Ok(bytes) => {
+                        keyspace_config
+                            .budgets
+                            .insert(group.trim().to_string(), bytes);
+                    }
+                    Err(e) => error!("Invalid keyspace budget {}: {}", entry, e),
+                }
+            }
+        }
+    }
+    match KeyspaceMonitor::new(keyspace_config).await {
+        Ok(monitor) => {
+            monitor.spawn();
+            info!("Redis keyspace usage reporter started");
+        }
+        Err(e) => {
+            error!("Failed to start Redis keyspace usage reporter: {}", e);
+        }
+    }
+
+    // Re-evaluate liquidations on a timer so alerts keep flowing if market
+    // messages stall, and whenever funding settles
+    if config.liquidation.recompute_interval_secs > 0 || recompute_trigger.is_some() {
+        let recompute_config = LiquidationRecomputeConfig {
+            redis_url: redis_url.clone(),
+            interval_secs: config.liquidation.recompute_interval_secs,
+            at_risk_top_k: config.liquidation.at_risk_top_k,
+        };
+        match LiquidationRecomputer::new(recompute_config).await {
+            Ok(recomputer) => {
+                let recomputer = recomputer.with_pubsub(pubsub_service.clone());
+                match recompute_trigger {
+                    Some(trigger) => recomputer.with_trigger(trigger).spawn(),
+                    None => recomputer.spawn(),
+                };
+                info!(
+                    "Liquidation recompute started (every {}s, on funding settlement: {})",
+                    config.liquidation.recompute_interval_secs,
+                    config.liquidation.recompute_on_funding
+                );
+            }
+            Err(e) => {
+                error!("Failed to start liquidation recompute: {}", e);
+            }
+        }
+    }
+
+    // Initialize ScyllaDB processor
+    info!("Connecting to ScyllaDB at {}", scylladb_nodes.join(","));
+    let scylladb_processor =
+        match ScyllaDBProcessor::new(scylladb_nodes.clone(), &config.scylladb).await {
+            Ok(processor) => {
+                info!("Connected to ScyllaDB: {}", scylladb_nodes.join(","));
+                processor.with_allowed_lateness(allowed_lateness)
+            }
+            Err(e) => {
+                error!("Failed to connect to ScyllaDB: {}", e);
+                return Err(e.into());
+            }
+        };
+    let scylladb_processor = if config.position_diff.enabled {
+        scylladb_processor.with_position_diff(config.position_diff.full_snapshot_every)
+    } else {
+        scylladb_processor
+    };
+
+    let scylladb_processor = match &memory_store {
+        Some(store) => scylladb_processor.with_history_store(store.clone()),
+        None => scylladb_processor,
+    };
+
+    // OHLCV bars from trades, with closes published through PubSub
+    let scylladb_processor = if config.candles.enabled {
+        scylladb_processor
+            .with_candles(config.candles.resolutions.clone())
+            .with_pubsub(pubsub_service.clone())
+    } else {
+        scylladb_processor
+    };
+
+    // Rolling return correlations from the hourly candles kept in Redis
+    #[cfg(feature = "api")]
+    if config.correlation.interval_secs > 0 {
+        let job_config = CorrelationJobConfig {
+            redis_url: redis_url.clone(),
+            interval_secs: config.correlation.interval_secs,
+            markets: config.correlation.markets.clone(),
+            resolution: Resolution::OneHour,
+            lookback: config.correlation.lookback_hours,
+        };
+        let job = match RedisReader::new(&redis_url).await {
+            Ok(reader) => CorrelationJob::new(job_config, reader).await,
+            Err(e) => Err(e.into()),
+        };
+        match job {
+            Ok(job) => {
+                job.with_sink(Box::new(scylladb_processor.correlation_sink()))
+                    .spawn();
+                info!(
+                    "Correlation job started, every {}s",
+                    config.correlation.interval_secs
+                );
+            }
+            Err(e) => {
+                error!("Failed to start correlation job: {}", e);
+            }
+        }
+    }
+
+    // Create a dedicated market preloader
+    let market_preloader = MarketPreloader::new(&redis_url, pubsub_service.clone()).await?;
+
+    // Create separate Kafka configs for market preloader, Redis, and ScyllaDB consumers
+    let mut market_kafka_config = config.kafka.clone();
+    market_kafka_config.consumer_group = format!("{}-markets", config.kafka.consumer_group);
+    if let Some(topic) = &config.kafka.markets_topic {
+        market_kafka_config.topics = vec![topic.clone()];
+    }
+
+    let mut redis_kafka_config = config.kafka.clone();
+    redis_kafka_config.consumer_group = format!("{}-redis", config.kafka.consumer_group);
+
+    let mut scylladb_kafka_config = config.kafka.clone();
+    scylladb_kafka_config.consumer_group = format!("{}-scylladb", config.kafka.consumer_group);
+
+    // With redundant ingesters every block arrives twice, and a rebalance
+    // can redeliver what the previous owner applied; each consumer group
+    // applies the first copy of a message and skips the others
+    let dedup = if config.ingest_dedup.enabled {
+        info!(
+            "Deduplicating messages in {:?} for {}s",
+            config.ingest_dedup.backend, config.ingest_dedup.ttl_secs
+        );
+        Some(Arc::new(
+            DedupWindow::from_config(&config.ingest_dedup, &redis_url).await?,
+        ))
+    } else {
+        None
+    };
+
+    // Consumers hold back until the markets their processors depend on are
+    // loaded, and pause again if the cache loses them
+    let readiness = Arc::new(ReadinessGate::new(Arc::new(
+        RedisReadiness::new(&redis_url).await?,
+    )));
+
+    // Create market preloader consumer with its own consumer group
+    info!(
+        "Creating Market Preloader Kafka consumer with group: {}",
+        market_kafka_config.consumer_group
+    );
+    let market_consumer = match KafkaConsumer::new(&market_kafka_config, market_preloader) {
+        Ok(consumer) => consumer
+            .with_hooks(hooks.clone())
+            .with_payload_log(payload_log.clone())
+            .with_dead_letter(dead_letter.clone())
+            .with_dedup(dedup.clone()),
+        Err(e) => {
+            error!("Failed to create Market Preloader consumer: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    // Create Redis consumer with its own consumer group
+    info!(
+        "Creating Redis Kafka consumer with group: {}",
+        redis_kafka_config.consumer_group
+    );
+    let redis_consumer = match KafkaConsumer::new(&redis_kafka_config, redis_processor) {
+        Ok(consumer) => consumer
+            .with_hooks(hooks.clone())
+            .with_payload_log(payload_log.clone())
+            .with_dead_letter(dead_letter.clone())
+            .with_readiness(readiness.clone())
+            .with_dedup(dedup.clone()),
+        Err(e) => {
+            error!("Failed to create Redis consumer: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    // Create ScyllaDB consumer with its own consumer group
+    info!(
+        "Creating ScyllaDB Kafka consumer with group: {}",
+        scylladb_kafka_config.consumer_group
+    );
+    let scylladb_consumer = match KafkaConsumer::new(&scylladb_kafka_config, scylladb_processor) {
+        Ok(consumer) => consumer
+            .with_hooks(hooks.clone())
+            .with_payload_log(payload_log.clone())
+            .with_dead_letter(dead_letter.clone())
+            .with_readiness(readiness.clone())
+            .with_dedup(dedup.clone()),
+        Err(e) => {
+            error!("Failed to create ScyllaDB consumer: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    // Report progress while the consumers work through a backlog
+    if config.catch_up.report_interval_secs > 0 {
+        CatchUpReporter::new(
+            vec![
+                market_consumer.catch_up_progress(),
+                redis_consumer.catch_up_progress(),
+                scylladb_consumer.catch_up_progress(),
+            ],
+            Duration::from_secs(config.catch_up.report_interval_secs),
+        )
+        .with_pubsub(pubsub_service.clone())
+        .spawn();
+    }
+
+    // Consumers the shutdown waits for, so each commits what it processed
+    let mut consumer_handles = Vec::new();
+
+    // Optionally compare our trade stream against the public indexer feed
+    #[cfg(feature = "trade-qa")]
+    if let Some(ws_url) = secrets::load("TRADE_QA_WS_URL")? {
+        let recorder = trade_qa::TradeQaRecorder::new(trade_qa::TradeQaConfig {
+            ws_url,
+            // May carry an API key for the public feed
+            subscribe_message: secrets::load("TRADE_QA_SUBSCRIBE_MESSAGE")?,
+            ..trade_qa::TradeQaConfig::default()
+        });
+        recorder.spawn();
+
+        let mut qa_kafka_config = config.kafka.clone();
+        qa_kafka_config.consumer_group = format!("{}-trade-qa", config.kafka.consumer_group);
+        let qa_consumer = KafkaConsumer::new(&qa_kafka_config, recorder)?;
+        let (qa_shutdown_tx, qa_shutdown_rx) = oneshot::channel::<()>();
+        let mut qa_stop = shutdown_rx.clone();
+        task::spawn(async move {
+            if qa_stop.wait_for(|stop| *stop).await.is_ok() {
+                let _ = qa_shutdown_tx.send(());
+            }
+        });
+        consumer_handles.push(task::spawn(async move {
+            if let Err(e) = qa_consumer.start_with_shutdown(qa_shutdown_rx).await {
+                error!("Trade QA consumer error: {}", e);
+            }
+        }));
+        info!("Trade QA recorder started");
+    }
+
+    // Create shutdown channels
+    let (market_shutdown_tx, market_shutdown_rx) = oneshot::channel::<()>();
+    let (redis_shutdown_tx, redis_shutdown_rx) = oneshot::channel::<()>();
+    let (scylladb_shutdown_tx, scylladb_shutdown_rx) = oneshot::channel::<()>();
+
+    // Start market preloader first
+    info!("Starting market preloader");
+    let market_handle = task::spawn(async move {
+        if let Err(e) = market_consumer
+            .start_with_shutdown(market_shutdown_rx)
+            .await
+        {
+            error!("Market preloader error: {}", e);
+        }
+    });
+
+    // Start other consumers in separate tasks with shutdown receivers; each
+    // waits for its processor's prerequisites before consuming
+    info!("Starting Redis and ScyllaDB consumers");
+    let redis_handle = task::spawn(async move {
+        if let Err(e) = redis_consumer.start_with_shutdown(redis_shutdown_rx).await {
+            error!("Redis consumer error: {}", e);
+        }
+    });
+
+    let scylladb_handle = task::spawn(async move {
+        if let Err(e) = scylladb_consumer
+            .start_with_shutdown(scylladb_shutdown_rx)
+            .await
+        {
+            error!("ScyllaDB consumer error: {}", e);
+        }
+    });
+
+    // Forward the shutdown flag to every consumer
+    let shutdown_handle = task::spawn(async move {
+        if shutdown_rx.wait_for(|stop| *stop).await.is_err() {
+            // Sender dropped without a shutdown; keep running
+            std::future::pending::<()>().await;
+        }
+        info!("Received shutdown signal, stopping consumers...");
+        if let Err(e) = market_shutdown_tx.send(()) {
+            error!(
+                "Failed to send shutdown signal to Market preloader: {:?}",
+                e
+            );
+        }
+        if let Err(e) = redis_shutdown_tx.send(()) {
+            error!("Failed to send shutdown signal to Redis consumer: {:?}", e);
+        }
+        if let Err(e) = scylladb_shutdown_tx.send(()) {
+            error!(
+                "Failed to send shutdown signal to ScyllaDB consumer: {:?}",
+                e
+            );
+        }
+    });
+
+    // Wait for the consumers to stop. Each finishes the message it is on and
+    // commits its offsets first.
+    consumer_handles.extend([market_handle, redis_handle, scylladb_handle]);
+    let _ = join_all(consumer_handles).await;
+    shutdown_handle.abort();
+
+    // Publish the events the processors queued before they stopped
+    if !pubsub_service.drain(SHUTDOWN_DRAIN_TIMEOUT).await {
+        warn!(
+            "PubSub events still unpublished after {:?}, dropping them",
+            SHUTDOWN_DRAIN_TIMEOUT
+        );
+    }
+
+    Ok(())
+}
+
+// Build the hook chain from the configured scripts
+fn load_hooks(config: &Config) -> Result<HookChain, Box<dyn Error + Send + Sync>> {
+    #[cfg(feature = "scripting")]
+    {
+        let mut chain = HookChain::new();
+        for path in &config.hooks.scripts {
+            let hook = crate::hooks::RhaiHook::from_file(path, config.hooks.max_operations)?;
+            info!("Loaded hook script {}", path);
+            chain = chain.with_hook(Arc::new(hook));
+        }
+        Ok(chain)
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    {
+        if !config.hooks.scripts.is_empty() {
+            error!("Hook scripts are configured but the scripting feature is not enabled; ignoring them");
+        }
+        Ok(HookChain::new())
+    }
+}
@@ -0,0 +1,142 @@
+use super::{FundingRecord, HistoryStore, StateStore, TradeRecord};
+use crate::error::StorageError;
+use crate::models::{MarketData, PositionData, TopOfBook};
+use async_trait::async_trait;
+use dashmap::DashMap;
+
+// StateStore and HistoryStore kept in process memory, for tests and embedded
+// consumers that run without Redis or ScyllaDB. Nothing survives a restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    markets: DashMap<String, MarketData>,
+    positions: DashMap<(String, String), PositionData>,
+    books: DashMap<String, TopOfBook>,
+    trades: DashMap<String, Vec<TradeRecord>>,
+    funding: DashMap<String, Vec<FundingRecord>>,
+    // Newest history entries kept per market; unbounded when None
+    history_limit: Option<usize>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = Some(limit);
+        self
+    }
+
+    // Snapshots of the stored state, sorted so two runs compare equal
+
+    pub fn markets(&self) -> Vec<MarketData> {
+        let mut markets: Vec<MarketData> = self.markets.iter().map(|e| e.value().clone()).collect();
+        markets.sort_by(|a, b| a.market_id.cmp(&b.market_id));
+        markets
+    }
+
+    pub fn positions(&self) -> Vec<PositionData> {
+        let mut positions: Vec<PositionData> =
+            self.positions.iter().map(|e| e.value().clone()).collect();
+        positions.sort_by(|a, b| {
+            (&a.market_id, &a.subaccount_id).cmp(&(&b.market_id, &b.subaccount_id))
+        });
+        positions
+    }
+
+    pub fn books(&self) -> Vec<TopOfBook> {
+        let mut books: Vec<TopOfBook> = self.books.iter().map(|e| e.value().clone()).collect();
+        books.sort_by(|a, b| a.market_id.cmp(&b.market_id));
+        books
+    }
+
+    // Trades of one market, oldest first
+    pub fn trades(&self, market_id: &str) -> Vec<TradeRecord> {
+        self.trades
+            .get(market_id)
+            .map(|trades| trades.clone())
+            .unwrap_or_default()
+    }
+
+    // Funding points of one market, oldest first
+    pub fn funding(&self, market_id: &str) -> Vec<FundingRecord> {
+        self.funding
+            .get(market_id)
+            .map(|funding| funding.clone())
+            .unwrap_or_default()
+    }
+}
+
+fn push_bounded<T>(entries: &mut Vec<T>, entry: T, limit: Option<usize>) {
+    entries.push(entry);
+    if let Some(limit) = limit {
+        let excess = entries.len().saturating_sub(limit);
+        entries.drain(..excess);
+    }
+}
+
+#[async_trait]
+impl StateStore for MemoryStore {
+    async fn get_market(&self, market_id: &str) -> Result<Option<MarketData>, StorageError> {
+        Ok(self.markets.get(market_id).map(|market| market.clone()))
+    }
+
+    async fn put_market(&self, market: &MarketData) -> Result<(), StorageError> {
+        self.markets
+            .insert(market.market_id.clone(), market.clone());
+        Ok(())
+    }
+
+    async fn get_position(
+        &self,
+        market_id: &str,
+        subaccount_id: &str,
+    ) -> Result<Option<PositionData>, StorageError> {
+        Ok(self
+            .positions
+            .get(&(market_id.to_string(), subaccount_id.to_string()))
+            .map(|position| position.clone()))
+    }
+
+    async fn put_position(&self, position: &PositionData) -> Result<(), StorageError> {
+        self.positions.insert(
+            (position.market_id.clone(), position.subaccount_id.clone()),
+            position.clone(),
+        );
+        Ok(())
+    }
+
+    async fn remove_position(
+        &self,
+        market_id: &str,
+        subaccount_id: &str,
+    ) -> Result<(), StorageError> {
+        self.positions
+            .remove(&(market_id.to_string(), subaccount_id.to_string()));
+        Ok(())
+    }
+
+    async fn get_book(&self, market_id: &str) -> Result<Option<TopOfBook>, StorageError> {
+        Ok(self.books.get(market_id).map(|book| book.clone()))
+    }
+
+    async fn put_book(&self, book: &TopOfBook) -> Result<(), StorageError> {
+        self.books.insert(book.market_id.clone(), book.clone());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HistoryStore for MemoryStore {
+    async fn append_trade(&self, trade: &TradeRecord) -> Result<(), StorageError> {
+        let mut trades = self.trades.entry(trade.market_id.clone()).or_default();
+        push_bounded(&mut *trades, trade.clone(), self.history_limit);
+        Ok(())
+    }
+
+    async fn append_funding(&self, funding: &FundingRecord) -> Result<(), StorageError> {
+        let mut points = self.funding.entry(funding.market_id.clone()).or_default();
+        push_bounded(&mut *points, funding.clone(), self.history_limit);
+        Ok(())
+    }
+}
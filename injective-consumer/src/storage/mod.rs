@@ -0,0 +1,293 @@
+use crate::compute::{decimal, estimated_funding_rate, is_liquidatable, paid_funding_rate};
+use crate::error::StorageError;
+use crate::models::{
+    time, DerivativeMarketPayload, DerivativeTradePayload, FullLimitOrderbookPayload, MarketData,
+    MarketType, PositionData, PositionPayload, SpotTradePayload, SubaccountTrade, TopOfBook,
+};
+use crate::orderbook;
+use crate::scaling::{self, CHAIN_DECIMAL, CHAIN_DECIMALS, PRICE_DECIMAL, PRICE_DECIMALS};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+
+mod memory;
+mod processor;
+#[cfg(feature = "redis-sink")]
+mod redis_store;
+#[cfg(feature = "scylla-sink")]
+mod scylla_store;
+pub use memory::MemoryStore;
+pub use processor::StateProcessor;
+#[cfg(feature = "redis-sink")]
+pub use redis_store::RedisStateStore;
+#[cfg(feature = "scylla-sink")]
+pub use scylla_store::ScyllaHistoryStore;
+
+// Storage seams the processors write through. StateStore holds the latest
+// state of markets, positions and books; HistoryStore appends what happened.
+// Values are always scaled to human units before they reach a store.
+
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    async fn get_market(&self, market_id: &str) -> Result<Option<MarketData>, StorageError>;
+
+    async fn put_market(&self, market: &MarketData) -> Result<(), StorageError>;
+
+    async fn get_position(
+        &self,
+        market_id: &str,
+        subaccount_id: &str,
+    ) -> Result<Option<PositionData>, StorageError>;
+
+    async fn put_position(&self, position: &PositionData) -> Result<(), StorageError>;
+
+    // Several positions at once; stores that can batch writes override this
+    async fn put_positions(&self, positions: &[PositionData]) -> Result<(), StorageError> {
+        for position in positions {
+            self.put_position(position).await?;
+        }
+        Ok(())
+    }
+
+    async fn remove_position(
+        &self,
+        market_id: &str,
+        subaccount_id: &str,
+    ) -> Result<(), StorageError>;
+
+    async fn get_book(&self, market_id: &str) -> Result<Option<TopOfBook>, StorageError>;
+
+    async fn put_book(&self, book: &TopOfBook) -> Result<(), StorageError>;
+}
+
+#[async_trait]
+pub trait HistoryStore: Send + Sync {
+    async fn append_trade(&self, trade: &TradeRecord) -> Result<(), StorageError>;
+
+    async fn append_funding(&self, funding: &FundingRecord) -> Result<(), StorageError>;
+}
+
+// One side of a derivative trade
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeRecord {
+    pub market_id: String,
+    pub trade_id: String,
+    pub subaccount_id: String,
+    pub is_buy: bool,
+    // Resting side of the match
+    pub is_maker: bool,
+    pub price: f64,
+    pub quantity: f64,
+    pub fee: f64,
+    pub block_height: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+// Cumulative funding of a perpetual market at a block
+#[derive(Debug, Clone, PartialEq)]
+pub struct FundingRecord {
+    pub market_id: String,
+    pub cumulative_funding: f64,
+    pub mark_price: f64,
+    // Change since the market's previous record, when that one is known
+    pub funding_delta: Option<f64>,
+    // The change as a fraction of the mark price
+    pub paid_funding_rate: Option<f64>,
+    // Hourly rate the next funding is heading for
+    pub estimated_funding_rate: f64,
+    pub hourly_funding_rate_cap: f64,
+    pub hourly_interest_rate: f64,
+    pub block_height: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+// Scale a market payload into the stored market state
+pub fn market_from_payload(
+    market: &DerivativeMarketPayload,
+    block_height: u64,
+    block_time: u64,
+) -> MarketData {
+    MarketData {
+        market_id: market.market_id.clone(),
+        ticker: market.ticker.clone(),
+        mark_price: scaling::price(&market.mark_price),
+        maintenance_margin_ratio: scaling::maintenance_margin_ratio(
+            &market.maintenance_margin_ratio,
+        ),
+        cumulative_funding: scaling::funding(&market.cumulative_funding),
+        status: market.status.clone(),
+        block_height: block_height as i64,
+        timestamp: time::to_datetime(block_time as i64),
+    }
+}
+
+// Scale a market payload into a funding record. The delta and paid rate are
+// left empty without the market's previous cumulative funding. Fundings are
+// assumed to fall on multiples of the funding interval, as they do for the
+// chain's hourly markets.
+pub fn funding_from_payload(
+    market: &DerivativeMarketPayload,
+    previous_cumulative_funding: Option<f64>,
+    block_height: u64,
+    block_time: u64,
+) -> FundingRecord {
+    let cumulative_funding = scaling::funding(&market.cumulative_funding);
+    let mark_price = scaling::price(&market.mark_price);
+    let hourly_funding_rate_cap = scaling::ratio(&market.hfr);
+    let hourly_interest_rate = scaling::ratio(&market.hir);
+    let funding_interval = market.funding_interval.parse::<i64>().unwrap_or(0);
+    let secs_since_funding = if funding_interval > 0 {
+        (time::to_millis(block_time as i64) / 1_000).rem_euclid(funding_interval)
+    } else {
+        0
+    };
+
+    FundingRecord {
+        market_id: market.market_id.clone(),
+        cumulative_funding,
+        mark_price,
+        funding_delta: previous_cumulative_funding.map(|previous| cumulative_funding - previous),
+        paid_funding_rate: previous_cumulative_funding
+            .map(|previous| paid_funding_rate(previous, cumulative_funding, mark_price)),
+        estimated_funding_rate: estimated_funding_rate(
+            scaling::ratio(&market.cumulative_price),
+            secs_since_funding,
+            hourly_interest_rate,
+            hourly_funding_rate_cap,
+        ),
+        hourly_funding_rate_cap,
+        hourly_interest_rate,
+        block_height: block_height as i64,
+        timestamp: time::to_datetime(block_time as i64),
+    }
+}
+
+// Scale a position payload and price its liquidation against the market.
+// The liquidation price is computed from the chain strings in Decimal, so
+// large notional positions don't lose digits to f64 before the division.
+// None for positions with a non-positive quantity, entry price or margin.
+pub fn position_from_payload(
+    position: &PositionPayload,
+    market: &MarketData,
+    block_height: u64,
+    block_time: u64,
+) -> Option<PositionData> {
+    let from_chain =
+        |raw: &str, decimals: u32| decimal::from_chain(raw, decimals).unwrap_or_default();
+    let quantity = from_chain(&position.quantity, CHAIN_DECIMALS);
+    let entry_price = from_chain(&position.entry_price, PRICE_DECIMALS);
+    let margin = from_chain(&position.margin, PRICE_DECIMALS);
+    let cumulative_funding_entry = from_chain(&position.cumulative_funding_entry, PRICE_DECIMALS);
+    if quantity <= Decimal::ZERO || entry_price <= Decimal::ZERO || margin <= Decimal::ZERO {
+        return None;
+    }
+
+    let from_f64 = |value: f64| Decimal::from_f64(value).unwrap_or_default();
+    let liquidation_price = decimal::liquidation_price(
+        position.is_long,
+        entry_price,
+        margin,
+        quantity,
+        from_f64(market.maintenance_margin_ratio),
+        from_f64(market.cumulative_funding),
+        cumulative_funding_entry,
+    )
+    .and_then(|price| price.to_f64())
+    .unwrap_or(0.0);
+    let quantity = quantity.to_f64().unwrap_or(0.0);
+    let entry_price = entry_price.to_f64().unwrap_or(0.0);
+    let margin = margin.to_f64().unwrap_or(0.0);
+    let cumulative_funding_entry = cumulative_funding_entry.to_f64().unwrap_or(0.0);
+
+    Some(PositionData {
+        market_id: position.market_id.clone(),
+        subaccount_id: position.subaccount_id.clone(),
+        is_long: position.is_long,
+        quantity,
+        entry_price,
+        margin,
+        cumulative_funding_entry,
+        liquidation_price,
+        is_liquidatable: is_liquidatable(position.is_long, liquidation_price, market.mark_price),
+        block_height: block_height as i64,
+        timestamp: time::to_datetime(block_time as i64),
+    })
+}
+
+// Scale one side of a derivative trade
+pub fn trade_from_payload(
+    trade: &DerivativeTradePayload,
+    block_height: u64,
+    block_time: u64,
+) -> TradeRecord {
+    TradeRecord {
+        market_id: trade.market_id.clone(),
+        trade_id: trade.trade_id.clone(),
+        subaccount_id: trade.subaccount_id.clone(),
+        is_buy: trade.is_buy,
+        is_maker: trade.execution_type == "LimitMatchRestingOrder",
+        price: scaling::price(&trade.position_delta.execution_price),
+        quantity: scaling::quantity(&trade.position_delta.execution_quantity),
+        fee: scaling::fee(&trade.fee),
+        block_height: block_height as i64,
+        timestamp: time::to_datetime(block_time as i64),
+    }
+}
+
+// A derivative trade side as kept in its subaccount's trade history
+pub fn subaccount_trade(trade: &TradeRecord) -> SubaccountTrade {
+    SubaccountTrade {
+        subaccount_id: trade.subaccount_id.clone(),
+        market_id: trade.market_id.clone(),
+        market_type: MarketType::Derivative,
+        trade_id: trade.trade_id.clone(),
+        is_buy: trade.is_buy,
+        is_maker: trade.is_maker,
+        price: trade.price,
+        quantity: trade.quantity,
+        fee: trade.fee,
+        block_height: trade.block_height,
+        timestamp: trade.timestamp,
+    }
+}
+
+// A spot trade side as kept in its subaccount's trade history, in chain units
+pub fn spot_subaccount_trade(
+    trade: &SpotTradePayload,
+    block_height: u64,
+    block_time: u64,
+) -> SubaccountTrade {
+    SubaccountTrade {
+        subaccount_id: trade.subaccount_id.clone(),
+        market_id: trade.market_id.clone(),
+        market_type: MarketType::Spot,
+        trade_id: trade.trade_id.clone(),
+        is_buy: trade.is_buy,
+        is_maker: trade.execution_type == "LimitMatchRestingOrder",
+        price: trade.price.parse::<f64>().unwrap_or(0.0),
+        quantity: trade.quantity.parse::<f64>().unwrap_or(0.0),
+        fee: trade.fee.parse::<f64>().unwrap_or(0.0),
+        block_height: block_height as i64,
+        timestamp: time::to_datetime(block_time as i64),
+    }
+}
+
+// Best bid and ask of a full orderbook, with the quantity resting at each
+pub fn book_from_payload(
+    orderbook: &FullLimitOrderbookPayload,
+    block_height: u64,
+    block_time: u64,
+) -> TopOfBook {
+    let (best_bid, best_ask) = orderbook::top_of_book(orderbook, PRICE_DECIMAL, CHAIN_DECIMAL);
+
+    TopOfBook {
+        market_id: orderbook.market_id.clone(),
+        best_bid: best_bid.map(|l| l.price),
+        best_ask: best_ask.map(|l| l.price),
+        best_bid_quantity: best_bid.map_or(0.0, |l| l.quantity),
+        best_ask_quantity: best_ask.map_or(0.0, |l| l.quantity),
+        block_height: block_height as i64,
+        timestamp: time::to_datetime(block_time as i64),
+    }
+}
@@ -0,0 +1,110 @@
+use super::{
+    book_from_payload, funding_from_payload, market_from_payload, position_from_payload,
+    trade_from_payload, HistoryStore, StateStore,
+};
+use crate::consumer::MessageProcessor;
+use crate::error::IndexerError;
+use crate::models::{KafkaMessage, KafkaPayload, MarketType, PositionPayload};
+use async_trait::async_trait;
+use log::debug;
+use std::error::Error;
+use std::sync::Arc;
+
+// Applies markets, positions, books, trades and funding to a StateStore and a
+// HistoryStore: the store-backed part of the Redis and ScyllaDB processors,
+// without their Redis-only aggregates, alerts and PubSub. Paired with
+// MemoryStore it runs the pipeline with no external services.
+pub struct StateProcessor {
+    state: Arc<dyn StateStore>,
+    history: Arc<dyn HistoryStore>,
+}
+
+impl StateProcessor {
+    pub fn new(state: Arc<dyn StateStore>, history: Arc<dyn HistoryStore>) -> Self {
+        StateProcessor { state, history }
+    }
+
+    async fn apply_position(
+        &self,
+        position: &PositionPayload,
+        block_height: u64,
+        block_time: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(market) = self.state.get_market(&position.market_id).await? else {
+            debug!(
+                "Market {} not stored yet, skipping position of {}",
+                position.market_id, position.subaccount_id
+            );
+            return Ok(());
+        };
+
+        // Never let an older block replace a newer position
+        if let Some(stored) = self
+            .state
+            .get_position(&position.market_id, &position.subaccount_id)
+            .await?
+        {
+            if stored.block_height > block_height as i64 {
+                return Ok(());
+            }
+        }
+
+        if let Some(state) = position_from_payload(position, &market, block_height, block_time) {
+            self.state.put_position(&state).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MessageProcessor for StateProcessor {
+    async fn process_message(&self, message: KafkaMessage) -> Result<(), IndexerError> {
+        let block_height = message.block_height;
+        let block_time = message.block_time;
+
+        if let Some((positions, _)) = message.positions() {
+            for position in positions {
+                self.apply_position(position, block_height, block_time)
+                    .await?;
+            }
+            return Ok(());
+        }
+
+        match &message.payload {
+            KafkaPayload::DerivativeMarkets(markets) => {
+                for market in markets {
+                    let previous = self.state.get_market(&market.market_id).await?;
+                    let state = market_from_payload(market, block_height, block_time);
+                    self.state.put_market(&state).await?;
+                    self.history
+                        .append_funding(&funding_from_payload(
+                            market,
+                            previous.map(|previous| previous.cumulative_funding),
+                            block_height,
+                            block_time,
+                        ))
+                        .await?;
+                }
+            }
+            KafkaPayload::DerivativeTrades(trades) => {
+                for trade in trades {
+                    self.history
+                        .append_trade(&trade_from_payload(trade, block_height, block_time))
+                        .await?;
+                }
+            }
+            // The stores only model derivative books
+            KafkaPayload::DerivativeFullOrderbooks(_) => {
+                if let Some((orderbooks, MarketType::Derivative)) = message.full_orderbooks() {
+                    for orderbook in orderbooks {
+                        self.state
+                            .put_book(&book_from_payload(orderbook, block_height, block_time))
+                            .await?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+use super::StateStore;
+use crate::dual_write::MirroredConnection;
+use crate::error::StorageError;
+use crate::models::{time, MarketData, PositionData, TopOfBook};
+use crate::redis_keys;
+use async_trait::async_trait;
+use redis::{AsyncCommands, Pipeline};
+use std::collections::HashMap;
+
+// StateStore over the version 2 Redis layout (see redis_keys). Shares the
+// Redis processor's connection, so dual writes cover it as well. Each write,
+// and each batch of positions, is sent as one pipeline.
+#[derive(Clone)]
+pub struct RedisStateStore {
+    connection: MirroredConnection,
+}
+
+impl RedisStateStore {
+    pub fn new(connection: MirroredConnection) -> Self {
+        RedisStateStore { connection }
+    }
+
+    async fn hgetall(&self, key: &str) -> Result<HashMap<String, String>, StorageError> {
+        let mut conn = self.connection.clone();
+        Ok(conn.hgetall(key).await?)
+    }
+}
+
+// The position hash and both position index sets
+fn queue_position(pipe: &mut Pipeline, position: &PositionData) {
+    let key = redis_keys::position(&position.market_id, &position.subaccount_id);
+    let fields = [
+        ("is_long", position.is_long.to_string()),
+        ("quantity", position.quantity.to_string()),
+        ("entry_price", position.entry_price.to_string()),
+        ("margin", position.margin.to_string()),
+        (
+            "cumulative_funding_entry",
+            position.cumulative_funding_entry.to_string(),
+        ),
+        ("liquidation_price", position.liquidation_price.to_string()),
+        ("is_liquidatable", position.is_liquidatable.to_string()),
+        ("block_height", position.block_height.to_string()),
+        (
+            "timestamp",
+            position.timestamp.timestamp_millis().to_string(),
+        ),
+    ];
+
+    pipe.hset_multiple(&key, &fields)
+        .ignore()
+        .sadd(
+            redis_keys::positions_by_market(&position.market_id),
+            &position.subaccount_id,
+        )
+        .ignore()
+        .sadd(
+            redis_keys::positions_by_subaccount(&position.subaccount_id),
+            &position.market_id,
+        )
+        .ignore();
+}
+
+#[async_trait]
+impl StateStore for RedisStateStore {
+    async fn get_market(&self, market_id: &str) -> Result<Option<MarketData>, StorageError> {
+        let fields = self
+            .hgetall(&redis_keys::derivative_market(market_id))
+            .await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(MarketData {
+            market_id: market_id.to_string(),
+            ticker: fields.get("ticker").cloned().unwrap_or_default(),
+            mark_price: parse_field(&fields, "mark_price"),
+            maintenance_margin_ratio: parse_field(&fields, "maintenance_margin_ratio"),
+            cumulative_funding: parse_field(&fields, "cumulative_funding"),
+            status: fields.get("status").cloned().unwrap_or_default(),
+            block_height: parse_field(&fields, "block_height"),
+            timestamp: time::to_datetime(parse_field(&fields, "timestamp")),
+        }))
+    }
+
+    async fn put_market(&self, market: &MarketData) -> Result<(), StorageError> {
+        let key = redis_keys::derivative_market(&market.market_id);
+        let fields = [
+            ("ticker", market.ticker.clone()),
+            ("mark_price", market.mark_price.to_string()),
+            (
+                "maintenance_margin_ratio",
+                market.maintenance_margin_ratio.to_string(),
+            ),
+            ("cumulative_funding", market.cumulative_funding.to_string()),
+            ("block_height", market.block_height.to_string()),
+            ("timestamp", market.timestamp.timestamp_millis().to_string()),
+            ("status", market.status.clone()),
+        ];
+
+        let mut conn = self.connection.clone();
+        redis::pipe()
+            .hset_multiple(&key, &fields)
+            .ignore()
+            .sadd(redis_keys::derivative_markets(), &market.market_id)
+            .ignore()
+            .query_async::<()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_position(
+        &self,
+        market_id: &str,
+        subaccount_id: &str,
+    ) -> Result<Option<PositionData>, StorageError> {
+        let fields = self
+            .hgetall(&redis_keys::position(market_id, subaccount_id))
+            .await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(PositionData {
+            market_id: market_id.to_string(),
+            subaccount_id: subaccount_id.to_string(),
+            is_long: parse_field(&fields, "is_long"),
+            quantity: parse_field(&fields, "quantity"),
+            entry_price: parse_field(&fields, "entry_price"),
+            margin: parse_field(&fields, "margin"),
+            cumulative_funding_entry: parse_field(&fields, "cumulative_funding_entry"),
+            liquidation_price: parse_field(&fields, "liquidation_price"),
+            is_liquidatable: parse_field(&fields, "is_liquidatable"),
+            block_height: parse_field(&fields, "block_height"),
+            timestamp: time::to_datetime(parse_field(&fields, "timestamp")),
+        }))
+    }
+
+    async fn put_position(&self, position: &PositionData) -> Result<(), StorageError> {
+        self.put_positions(std::slice::from_ref(position)).await
+    }
+
+    async fn put_positions(&self, positions: &[PositionData]) -> Result<(), StorageError> {
+        if positions.is_empty() {
+            return Ok(());
+        }
+
+        let mut pipe = redis::pipe();
+        for position in positions {
+            queue_position(&mut pipe, position);
+        }
+        let mut conn = self.connection.clone();
+        pipe.query_async::<()>(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn remove_position(
+        &self,
+        market_id: &str,
+        subaccount_id: &str,
+    ) -> Result<(), StorageError> {
+        let member = redis_keys::liquidatable_member(market_id, subaccount_id);
+        let mut conn = self.connection.clone();
+
+        redis::pipe()
+            .del(redis_keys::position(market_id, subaccount_id))
+            .ignore()
+            .srem(redis_keys::positions_by_market(market_id), subaccount_id)
+            .ignore()
+            .srem(
+                redis_keys::positions_by_subaccount(subaccount_id),
+                market_id,
+            )
+            .ignore()
+            .srem(redis_keys::liquidatable_positions(), &member)
+            .ignore()
+            .zrem(redis_keys::at_risk_positions(), &member)
+            .ignore()
+            .query_async::<()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_book(&self, market_id: &str) -> Result<Option<TopOfBook>, StorageError> {
+        let fields = self
+            .hgetall(&redis_keys::derivative_orderbook(market_id))
+            .await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(TopOfBook {
+            market_id: market_id.to_string(),
+            best_bid: fields.get("best_bid").and_then(|v| v.parse().ok()),
+            best_ask: fields.get("best_ask").and_then(|v| v.parse().ok()),
+            best_bid_quantity: parse_field(&fields, "best_bid_quantity"),
+            best_ask_quantity: parse_field(&fields, "best_ask_quantity"),
+            block_height: parse_field(&fields, "block_height"),
+            timestamp: time::to_datetime(parse_field(&fields, "timestamp")),
+        }))
+    }
+
+    async fn put_book(&self, book: &TopOfBook) -> Result<(), StorageError> {
+        let key = redis_keys::derivative_orderbook(&book.market_id);
+        let mut pipe = redis::pipe();
+
+        // An empty side clears its fields so readers never see a stale price
+        for (price, quantity, price_field, quantity_field) in [
+            (
+                book.best_bid,
+                book.best_bid_quantity,
+                "best_bid",
+                "best_bid_quantity",
+            ),
+            (
+                book.best_ask,
+                book.best_ask_quantity,
+                "best_ask",
+                "best_ask_quantity",
+            ),
+        ] {
+            match price {
+                Some(price) => pipe.hset_multiple(
+                    &key,
+                    &[
+                        (price_field, price.to_string()),
+                        (quantity_field, quantity.to_string()),
+                    ],
+                ),
+                None => pipe.hdel(&key, &[price_field, quantity_field]),
+            }
+            .ignore();
+        }
+        pipe.hset_multiple(
+            &key,
+            &[
+                ("block_height", book.block_height.to_string()),
+                ("timestamp", book.timestamp.timestamp_millis().to_string()),
+            ],
+        )
+        .ignore();
+
+        let mut conn = self.connection.clone();
+        pipe.query_async::<()>(&mut conn).await?;
+        Ok(())
+    }
+}
+
+// Parse a hash field, falling back to the type's default when missing or malformed
+fn parse_field<T: std::str::FromStr + Default>(fields: &HashMap<String, String>, name: &str) -> T {
+    fields
+        .get(name)
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_default()
+}
@@ -0,0 +1,120 @@
+use super::{FundingRecord, HistoryStore, TradeRecord};
+use crate::error::StorageError;
+use crate::models::time::{self, DAY_MILLIS};
+use async_trait::async_trait;
+use scylla::frame::value::CqlTimestamp;
+use scylla::prepared_statement::PreparedStatement;
+use scylla::Session;
+use std::sync::Arc;
+
+// HistoryStore over the `derivative_trades`, `trades_by_subaccount` and `funding_history`
+// tables, which the ScyllaDB processor creates with the rest of its schema.
+// Trades go to both trade tables, by market and by subaccount.
+#[derive(Clone)]
+pub struct ScyllaHistoryStore {
+    session: Arc<Session>,
+    trade_insert: PreparedStatement,
+    subaccount_trade_insert: PreparedStatement,
+    funding_insert: PreparedStatement,
+}
+
+impl ScyllaHistoryStore {
+    // Prepares the inserts, so the tables must exist already
+    pub async fn new(session: Arc<Session>) -> Result<Self, StorageError> {
+        let trade_insert = session
+            .prepare(
+                "INSERT INTO injective.derivative_trades (
+                    market_id, day, executed_at, trade_id, is_maker, subaccount_id,
+                    is_buy, price, quantity, fee, block_height
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?;
+        let subaccount_trade_insert = session
+            .prepare(
+                "INSERT INTO injective.trades_by_subaccount (
+                    subaccount_id, timestamp, trade_id, is_maker, market_id, market_type,
+                    is_buy, price, quantity, fee, block_height
+                ) VALUES (?, ?, ?, ?, ?, 'derivative', ?, ?, ?, ?, ?)",
+            )
+            .await?;
+        let funding_insert = session
+            .prepare(
+                "INSERT INTO injective.funding_history (
+                    market_id, block_height, timestamp, cumulative_funding, mark_price,
+                    funding_delta, paid_funding_rate, estimated_funding_rate,
+                    hourly_funding_rate_cap, hourly_interest_rate
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .await?;
+        Ok(ScyllaHistoryStore {
+            session,
+            trade_insert,
+            subaccount_trade_insert,
+            funding_insert,
+        })
+    }
+}
+
+#[async_trait]
+impl HistoryStore for ScyllaHistoryStore {
+    async fn append_trade(&self, trade: &TradeRecord) -> Result<(), StorageError> {
+        let millis = trade.timestamp.timestamp_millis();
+        self.session
+            .execute_unpaged(
+                &self.trade_insert,
+                (
+                    &trade.market_id,
+                    CqlTimestamp(time::truncate_millis(millis, DAY_MILLIS)),
+                    CqlTimestamp(millis),
+                    &trade.trade_id,
+                    trade.is_maker,
+                    &trade.subaccount_id,
+                    trade.is_buy,
+                    trade.price,
+                    trade.quantity,
+                    trade.fee,
+                    trade.block_height,
+                ),
+            )
+            .await?;
+        self.session
+            .execute_unpaged(
+                &self.subaccount_trade_insert,
+                (
+                    &trade.subaccount_id,
+                    CqlTimestamp(millis),
+                    &trade.trade_id,
+                    trade.is_maker,
+                    &trade.market_id,
+                    trade.is_buy,
+                    trade.price,
+                    trade.quantity,
+                    trade.fee,
+                    trade.block_height,
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn append_funding(&self, funding: &FundingRecord) -> Result<(), StorageError> {
+        self.session
+            .execute_unpaged(
+                &self.funding_insert,
+                (
+                    &funding.market_id,
+                    funding.block_height,
+                    CqlTimestamp(funding.timestamp.timestamp_millis()),
+                    funding.cumulative_funding,
+                    funding.mark_price,
+                    funding.funding_delta,
+                    funding.paid_funding_rate,
+                    funding.estimated_funding_rate,
+                    funding.hourly_funding_rate_cap,
+                    funding.hourly_interest_rate,
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+}
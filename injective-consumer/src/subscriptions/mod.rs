@@ -0,0 +1,205 @@
+use crate::clock::{self, Clock};
+use crate::error::StorageError;
+use crate::pubsub::{EventType, StreamEvent};
+use crate::redis_keys;
+use redis::{aio::ConnectionManager, Client};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+// What a client wants delivered. Empty lists match everything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubscriptionFilter {
+    pub event_types: Vec<EventType>,
+    pub market_ids: Vec<String>,
+    pub subaccount_ids: Vec<String>,
+}
+
+impl SubscriptionFilter {
+    pub fn matches(&self, event: &StreamEvent) -> bool {
+        let field = |name: &str| event.payload.get(name).and_then(|v| v.as_str());
+
+        (self.event_types.is_empty() || self.event_types.contains(&event.event_type))
+            && (self.market_ids.is_empty()
+                || field("market_id").is_some_and(|id| self.market_ids.iter().any(|m| m == id)))
+            && (self.subaccount_ids.is_empty()
+                || field("subaccount_id")
+                    .is_some_and(|id| self.subaccount_ids.iter().any(|s| s == id)))
+    }
+}
+
+// Persists streaming gateway client subscriptions in Redis so a client can
+// resume its subscriptions after reconnecting, and operators can see what is
+// subscribed. A client's subscriptions expire `ttl` after it was last touched.
+// A read-only manager stores nothing, for gateways running as a read-only
+// Redis user; its clients subscribe again after reconnecting.
+#[derive(Clone)]
+pub struct SubscriptionManager {
+    connection: ConnectionManager,
+    ttl: Duration,
+    read_only: bool,
+    clock: Arc<dyn Clock>,
+}
+
+impl SubscriptionManager {
+    pub async fn new(redis_url: &str, ttl: Duration) -> Result<Self, StorageError> {
+        let client = Client::open(redis_url)?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(SubscriptionManager {
+            connection,
+            ttl,
+            read_only: false,
+            clock: clock::system(),
+        })
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub async fn subscribe(
+        &self,
+        client_id: &str,
+        subscription_id: &str,
+        filter: &SubscriptionFilter,
+    ) -> Result<(), StorageError> {
+        if self.read_only {
+            return Ok(());
+        }
+        let key = redis_keys::gateway_subscriptions(client_id);
+        let mut conn = self.connection.clone();
+        let _: () = redis::pipe()
+            .atomic()
+            .hset(&key, subscription_id, serde_json::to_string(filter)?)
+            .ignore()
+            .expire(&key, self.ttl.as_secs() as i64)
+            .ignore()
+            .zadd(redis_keys::gateway_clients(), client_id, self.expires_at())
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn unsubscribe(
+        &self,
+        client_id: &str,
+        subscription_id: &str,
+    ) -> Result<(), StorageError> {
+        if self.read_only {
+            return Ok(());
+        }
+        let mut conn = self.connection.clone();
+        let _: () = redis::cmd("HDEL")
+            .arg(redis_keys::gateway_subscriptions(client_id))
+            .arg(subscription_id)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    // Extend a connected client's subscriptions by another TTL
+    pub async fn touch(&self, client_id: &str) -> Result<(), StorageError> {
+        if self.read_only {
+            return Ok(());
+        }
+        let mut conn = self.connection.clone();
+        let _: () = redis::pipe()
+            .atomic()
+            .expire(
+                redis_keys::gateway_subscriptions(client_id),
+                self.ttl.as_secs() as i64,
+            )
+            .ignore()
+            .zadd(redis_keys::gateway_clients(), client_id, self.expires_at())
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    // Subscriptions to restore when a client reconnects, by subscription id.
+    // Entries that no longer parse are skipped.
+    pub async fn subscriptions(
+        &self,
+        client_id: &str,
+    ) -> Result<HashMap<String, SubscriptionFilter>, StorageError> {
+        if self.read_only {
+            return Ok(HashMap::new());
+        }
+        let mut conn = self.connection.clone();
+        let stored: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(redis_keys::gateway_subscriptions(client_id))
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(stored
+            .into_iter()
+            .filter_map(|(id, filter)| Some((id, serde_json::from_str(&filter).ok()?)))
+            .collect())
+    }
+
+    // Drop everything for a client that disconnected deliberately
+    pub async fn remove_client(&self, client_id: &str) -> Result<(), StorageError> {
+        if self.read_only {
+            return Ok(());
+        }
+        let mut conn = self.connection.clone();
+        let _: () = redis::pipe()
+            .atomic()
+            .del(redis_keys::gateway_subscriptions(client_id))
+            .ignore()
+            .zrem(redis_keys::gateway_clients(), client_id)
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    // Client ids with live subscriptions
+    pub async fn clients(&self) -> Result<Vec<String>, StorageError> {
+        if self.read_only {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.connection.clone();
+        let clients: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(redis_keys::gateway_clients())
+            .arg(self.now_secs())
+            .arg("+inf")
+            .query_async(&mut conn)
+            .await?;
+        Ok(clients)
+    }
+
+    // Remove expired clients from the index. Their subscription hashes expire
+    // on their own. Returns the number of clients removed.
+    pub async fn prune_expired(&self) -> Result<usize, StorageError> {
+        if self.read_only {
+            return Ok(0);
+        }
+        let mut conn = self.connection.clone();
+        let removed: usize = redis::cmd("ZREMRANGEBYSCORE")
+            .arg(redis_keys::gateway_clients())
+            .arg("-inf")
+            .arg(format!("({}", self.now_secs()))
+            .query_async(&mut conn)
+            .await?;
+        Ok(removed)
+    }
+
+    fn now_secs(&self) -> i64 {
+        self.clock.now_millis() / 1_000
+    }
+
+    fn expires_at(&self) -> i64 {
+        self.now_secs() + self.ttl.as_secs() as i64
+    }
+}
@@ -0,0 +1,156 @@
+use crate::models::SubaccountTrade;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::error::Error;
+
+// Paginated "my trades" queries. Trades are keyed per subaccount in both
+// Redis (a capped list of the most recent ones) and ScyllaDB (the
+// trades_by_subaccount table), so a page never scans a market's trades.
+//
+// Pages run newest first. The cursor names the last trade side of a page, so
+// trades arriving between requests do not shift later pages.
+
+// Largest page served, whatever the caller asks for
+pub const MAX_PAGE_SIZE: usize = 500;
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+// Position in a subaccount's history; trade sides are ordered by time, then
+// trade id, then maker flag, all descending
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TradeCursor {
+    pub timestamp: i64,
+    pub trade_id: String,
+    pub is_maker: bool,
+}
+
+impl TradeCursor {
+    pub fn of(trade: &SubaccountTrade) -> Self {
+        TradeCursor {
+            timestamp: trade.timestamp.timestamp_millis(),
+            trade_id: trade.trade_id.clone(),
+            is_maker: trade.is_maker,
+        }
+    }
+
+    // Whether the trade comes after the cursor in a newest-first page
+    pub fn precedes(&self, trade: &SubaccountTrade) -> bool {
+        TradeCursor::of(trade) < *self
+    }
+}
+
+impl std::fmt::Display for TradeCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let side = if self.is_maker { "m" } else { "t" };
+        write!(f, "{}:{}:{}", self.timestamp, self.trade_id, side)
+    }
+}
+
+impl std::str::FromStr for TradeCursor {
+    type Err = String;
+
+    // `{timestamp}:{trade_id}:{m|t}`; trade ids may contain colons
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid trade cursor: {}", s);
+        let (timestamp, rest) = s.split_once(':').ok_or_else(invalid)?;
+        let (trade_id, side) = rest.rsplit_once(':').ok_or_else(invalid)?;
+        let is_maker = match side {
+            "m" => true,
+            "t" => false,
+            _ => return Err(invalid()),
+        };
+        Ok(TradeCursor {
+            timestamp: timestamp.parse().map_err(|_| invalid())?,
+            trade_id: trade_id.to_string(),
+            is_maker,
+        })
+    }
+}
+
+// Response of a trade history request. `next_cursor` is None on the last page.
+#[derive(Debug, Clone, Serialize)]
+pub struct TradePage {
+    pub trades: Vec<SubaccountTrade>,
+    pub next_cursor: Option<String>,
+}
+
+// Where per-subaccount trade history comes from
+#[async_trait]
+pub trait TradeHistorySource: Send + Sync {
+    // Up to `limit` trade sides of the subaccount older than `before` (or the
+    // newest ones without a cursor), newest first
+    async fn subaccount_trades(
+        &self,
+        subaccount_id: &str,
+        before: Option<&TradeCursor>,
+        limit: usize,
+    ) -> Result<Vec<SubaccountTrade>, Box<dyn Error + Send + Sync>>;
+}
+
+// Serves pages from the recent-trade cache and continues into the archive
+// once the cache runs out
+pub struct TieredTradeHistory<R: TradeHistorySource, A: TradeHistorySource> {
+    recent: R,
+    archive: A,
+}
+
+impl<R: TradeHistorySource, A: TradeHistorySource> TieredTradeHistory<R, A> {
+    pub fn new(recent: R, archive: A) -> Self {
+        TieredTradeHistory { recent, archive }
+    }
+}
+
+#[async_trait]
+impl<R: TradeHistorySource, A: TradeHistorySource> TradeHistorySource for TieredTradeHistory<R, A> {
+    async fn subaccount_trades(
+        &self,
+        subaccount_id: &str,
+        before: Option<&TradeCursor>,
+        limit: usize,
+    ) -> Result<Vec<SubaccountTrade>, Box<dyn Error + Send + Sync>> {
+        let mut trades = self
+            .recent
+            .subaccount_trades(subaccount_id, before, limit)
+            .await?;
+        if trades.len() < limit {
+            let cursor = trades.last().map(TradeCursor::of);
+            let older = self
+                .archive
+                .subaccount_trades(
+                    subaccount_id,
+                    cursor.as_ref().or(before),
+                    limit - trades.len(),
+                )
+                .await?;
+            trades.extend(older);
+        }
+        Ok(trades)
+    }
+}
+
+// One page of a subaccount's trade history. `cursor` is the `next_cursor` of
+// the previous page; `limit` defaults to DEFAULT_PAGE_SIZE and is capped at
+// MAX_PAGE_SIZE.
+pub async fn page<S: TradeHistorySource + ?Sized>(
+    source: &S,
+    subaccount_id: &str,
+    cursor: Option<&str>,
+    limit: Option<usize>,
+) -> Result<TradePage, Box<dyn Error + Send + Sync>> {
+    let before = cursor.map(str::parse::<TradeCursor>).transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let trades = source
+        .subaccount_trades(subaccount_id, before.as_ref(), limit)
+        .await?;
+    let next_cursor = if trades.len() == limit {
+        trades
+            .last()
+            .map(|trade| TradeCursor::of(trade).to_string())
+    } else {
+        None
+    };
+    Ok(TradePage {
+        trades,
+        next_cursor,
+    })
+}
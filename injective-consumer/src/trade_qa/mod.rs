@@ -0,0 +1,288 @@
+use crate::consumer::MessageProcessor;
+use crate::error::IndexerError;
+use crate::models::{KafkaMessage, KafkaPayload, MessageType};
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::{task, time};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+// Configuration for the trade feed QA recorder
+#[derive(Clone)]
+pub struct TradeQaConfig {
+    // Public indexer websocket trade feed
+    pub ws_url: String,
+    // Sent once after connecting, e.g. a subscription request
+    pub subscribe_message: Option<String>,
+    // Divisor taking our chain-scaled prices to the feed's price units
+    pub price_scale: f64,
+    // Maximum relative price difference before a trade counts as mismatched
+    pub price_tolerance: f64,
+    // Blocks are compared once they are this far behind the newest block seen,
+    // giving both feeds time to deliver
+    pub settle_blocks: u64,
+    pub compare_interval_secs: u64,
+}
+
+impl Default for TradeQaConfig {
+    fn default() -> Self {
+        TradeQaConfig {
+            ws_url: String::new(),
+            subscribe_message: None,
+            price_scale: 1e18,
+            price_tolerance: 1e-6,
+            settle_blocks: 20,
+            compare_interval_secs: 30,
+        }
+    }
+}
+
+// Running discrepancy totals
+#[derive(Default, Debug)]
+pub struct TradeQaMetrics {
+    pub blocks_compared: AtomicU64,
+    pub trades_matched: AtomicU64,
+    pub missing_from_ours: AtomicU64,
+    pub missing_from_feed: AtomicU64,
+    pub price_mismatches: AtomicU64,
+}
+
+// A recorded trade: market and price (in feed units)
+#[derive(Debug, Clone)]
+struct RecordedTrade {
+    market_id: String,
+    price: f64,
+}
+
+// Trades seen in one block, keyed by trade id, from each side
+#[derive(Default)]
+struct BlockTrades {
+    ours: HashMap<String, RecordedTrade>,
+    feed: HashMap<String, RecordedTrade>,
+}
+
+// Records the public indexer's trade feed alongside our own trade stream and
+// compares them block by block. Runs as a Kafka message processor for our side.
+#[derive(Clone)]
+pub struct TradeQaRecorder {
+    config: TradeQaConfig,
+    blocks: Arc<Mutex<BTreeMap<u64, BlockTrades>>>,
+    metrics: Arc<TradeQaMetrics>,
+}
+
+impl TradeQaRecorder {
+    pub fn new(config: TradeQaConfig) -> Self {
+        TradeQaRecorder {
+            config,
+            blocks: Arc::new(Mutex::new(BTreeMap::new())),
+            metrics: Arc::new(TradeQaMetrics::default()),
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<TradeQaMetrics> {
+        self.metrics.clone()
+    }
+
+    // Start the feed recorder and the periodic comparison
+    pub fn spawn(&self) {
+        let recorder = self.clone();
+        task::spawn(async move {
+            loop {
+                if let Err(e) = recorder.record_feed().await {
+                    error!("Trade QA feed connection failed: {}", e);
+                }
+                time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+
+        let recorder = self.clone();
+        task::spawn(async move {
+            let mut interval_timer =
+                time::interval(Duration::from_secs(recorder.config.compare_interval_secs));
+            loop {
+                interval_timer.tick().await;
+                recorder.compare_settled().await;
+            }
+        });
+    }
+
+    // Read the websocket feed until it disconnects
+    async fn record_feed(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (mut ws, _) = connect_async(self.config.ws_url.as_str()).await?;
+        info!("Trade QA recorder connected to {}", self.config.ws_url);
+
+        if let Some(subscribe) = &self.config.subscribe_message {
+            ws.send(Message::Text(subscribe.clone())).await?;
+        }
+
+        while let Some(message) = ws.next().await {
+            let text = match message? {
+                Message::Text(text) => text,
+                Message::Ping(data) => {
+                    ws.send(Message::Pong(data)).await?;
+                    continue;
+                }
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            let value: Value = match serde_json::from_str(&text) {
+                Ok(value) => value,
+                Err(e) => {
+                    debug!("Ignoring non-JSON trade feed message: {}", e);
+                    continue;
+                }
+            };
+            if let Some((block, trade_id, trade)) = parse_feed_trade(&value) {
+                self.blocks
+                    .lock()
+                    .await
+                    .entry(block)
+                    .or_default()
+                    .feed
+                    .insert(trade_id, trade);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Compare and discard every block that has settled
+    async fn compare_settled(&self) {
+        let settled = {
+            let mut blocks = self.blocks.lock().await;
+            let Some(latest) = blocks.keys().next_back().copied() else {
+                return;
+            };
+            let cutoff = latest.saturating_sub(self.config.settle_blocks);
+            let newer = blocks.split_off(&(cutoff + 1));
+            std::mem::replace(&mut *blocks, newer)
+        };
+
+        for (block, trades) in settled {
+            self.compare_block(block, trades);
+        }
+    }
+
+    fn compare_block(&self, block: u64, trades: BlockTrades) {
+        let mut matched = 0;
+        let mut missing_from_ours = 0;
+        let mut price_mismatches = 0;
+
+        for (trade_id, feed_trade) in &trades.feed {
+            match trades.ours.get(trade_id) {
+                Some(our_trade) => {
+                    let diff = (our_trade.price - feed_trade.price).abs()
+                        / feed_trade.price.abs().max(f64::MIN_POSITIVE);
+                    if diff > self.config.price_tolerance {
+                        price_mismatches += 1;
+                        warn!(
+                            "Trade QA: price mismatch for trade {} in {} at block {}: ours={} feed={}",
+                            trade_id, feed_trade.market_id, block, our_trade.price, feed_trade.price
+                        );
+                    } else {
+                        matched += 1;
+                    }
+                }
+                None => {
+                    missing_from_ours += 1;
+                    warn!(
+                        "Trade QA: trade {} in {} at block {} missing from our stream",
+                        trade_id, feed_trade.market_id, block
+                    );
+                }
+            }
+        }
+        let missing_from_feed = trades
+            .ours
+            .keys()
+            .filter(|trade_id| !trades.feed.contains_key(*trade_id))
+            .count() as u64;
+
+        if missing_from_ours + missing_from_feed + price_mismatches > 0 {
+            warn!(
+                "Trade QA block {}: ours={}, feed={}, missing_from_ours={}, missing_from_feed={}, price_mismatches={}",
+                block,
+                trades.ours.len(),
+                trades.feed.len(),
+                missing_from_ours,
+                missing_from_feed,
+                price_mismatches
+            );
+        }
+
+        self.metrics.blocks_compared.fetch_add(1, Ordering::Relaxed);
+        self.metrics
+            .trades_matched
+            .fetch_add(matched, Ordering::Relaxed);
+        self.metrics
+            .missing_from_ours
+            .fetch_add(missing_from_ours, Ordering::Relaxed);
+        self.metrics
+            .missing_from_feed
+            .fetch_add(missing_from_feed, Ordering::Relaxed);
+        self.metrics
+            .price_mismatches
+            .fetch_add(price_mismatches, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl MessageProcessor for TradeQaRecorder {
+    fn handles(&self, message_type: &str) -> bool {
+        message_type == MessageType::DerivativeTrade.as_str()
+    }
+
+    async fn process_message(&self, message: KafkaMessage) -> Result<(), IndexerError> {
+        if let KafkaPayload::DerivativeTrades(trades) = &message.payload {
+            let mut blocks = self.blocks.lock().await;
+            let block = blocks.entry(message.block_height).or_default();
+            for trade in trades {
+                let price = trade
+                    .position_delta
+                    .execution_price
+                    .parse::<f64>()
+                    .unwrap_or(0.0)
+                    / self.config.price_scale;
+                block.ours.insert(
+                    trade.trade_id.clone(),
+                    RecordedTrade {
+                        market_id: trade.market_id.clone(),
+                        price,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+// Pull a trade out of an indexer feed message. Trade ids are prefixed with the
+// block height they executed in ("{height}_{index}..."), which is how feed
+// trades are assigned to blocks.
+fn parse_feed_trade(value: &Value) -> Option<(u64, String, RecordedTrade)> {
+    let trade = value
+        .get("trade")
+        .or_else(|| value.get("result").and_then(|r| r.get("trade")))
+        .unwrap_or(value);
+
+    let trade_id = trade.get("tradeId")?.as_str()?.to_string();
+    let block = trade_id.split('_').next()?.parse::<u64>().ok()?;
+    let market_id = trade.get("marketId")?.as_str()?.to_string();
+    let price = trade
+        .get("positionDelta")
+        .and_then(|delta| delta.get("executionPrice"))
+        .or_else(|| trade.get("price"))?
+        .as_str()?
+        .parse::<f64>()
+        .ok()?;
+
+    Some((block, trade_id, RecordedTrade { market_id, price }))
+}
@@ -0,0 +1,235 @@
+use crate::candles::{fill_gaps, Candle, CandleQuery, Resolution, UdfHistory};
+use crate::models::{time, MarketData};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::error::Error;
+use std::sync::Arc;
+
+const EXCHANGE: &str = "Injective";
+
+// Where candle history comes from
+#[async_trait]
+pub trait CandleSource: Send + Sync {
+    // Stored candles with start times in [from, to], oldest first
+    async fn candles(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Candle>, Box<dyn Error + Send + Sync>>;
+
+    // The newest stored candle starting before `before`
+    async fn candle_before(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        before: i64,
+    ) -> Result<Option<Candle>, Box<dyn Error + Send + Sync>>;
+}
+
+// Where the markets behind the symbols come from
+#[async_trait]
+pub trait MarketSource: Send + Sync {
+    async fn market(
+        &self,
+        market_id: &str,
+    ) -> Result<Option<MarketData>, Box<dyn Error + Send + Sync>>;
+
+    async fn markets(&self) -> Result<Vec<MarketData>, Box<dyn Error + Send + Sync>>;
+}
+
+// Shared sources, such as the API's, serve the datafeed too
+#[async_trait]
+impl<T: CandleSource + ?Sized> CandleSource for Arc<T> {
+    async fn candles(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Candle>, Box<dyn Error + Send + Sync>> {
+        (**self).candles(market_id, resolution, from, to).await
+    }
+
+    async fn candle_before(
+        &self,
+        market_id: &str,
+        resolution: Resolution,
+        before: i64,
+    ) -> Result<Option<Candle>, Box<dyn Error + Send + Sync>> {
+        (**self).candle_before(market_id, resolution, before).await
+    }
+}
+
+#[async_trait]
+impl<T: MarketSource + ?Sized> MarketSource for Arc<T> {
+    async fn market(
+        &self,
+        market_id: &str,
+    ) -> Result<Option<MarketData>, Box<dyn Error + Send + Sync>> {
+        (**self).market(market_id).await
+    }
+
+    async fn markets(&self) -> Result<Vec<MarketData>, Box<dyn Error + Send + Sync>> {
+        (**self).markets().await
+    }
+}
+
+// UDF `/config` response
+#[derive(Debug, Clone, Serialize)]
+pub struct UdfConfig {
+    pub supported_resolutions: Vec<&'static str>,
+    pub supports_group_request: bool,
+    pub supports_marks: bool,
+    pub supports_search: bool,
+    pub supports_timescale_marks: bool,
+    pub supports_time: bool,
+}
+
+// UDF `/symbols` response
+#[derive(Debug, Clone, Serialize)]
+pub struct UdfSymbol {
+    pub name: String,
+    pub ticker: String,
+    pub description: String,
+    #[serde(rename = "type")]
+    pub symbol_type: &'static str,
+    pub session: &'static str,
+    pub timezone: &'static str,
+    pub exchange: &'static str,
+    pub listed_exchange: &'static str,
+    pub minmov: u32,
+    pub pricescale: u64,
+    pub has_intraday: bool,
+    pub has_daily: bool,
+    pub supported_resolutions: Vec<&'static str>,
+    pub volume_precision: u32,
+    pub data_status: &'static str,
+}
+
+// TradingView UDF datafeed over the indexed markets and candles. Each method
+// returns the body of the UDF endpoint of the same name; symbols are market
+// tickers, with market ids accepted as well.
+pub struct UdfDatafeed<M: MarketSource, S: CandleSource> {
+    markets: M,
+    candles: S,
+}
+
+impl<M: MarketSource, S: CandleSource> UdfDatafeed<M, S> {
+    pub fn new(markets: M, candles: S) -> Self {
+        UdfDatafeed { markets, candles }
+    }
+
+    pub fn config(&self) -> UdfConfig {
+        UdfConfig {
+            supported_resolutions: supported_resolutions(),
+            supports_group_request: false,
+            supports_marks: false,
+            supports_search: false,
+            supports_timescale_marks: false,
+            supports_time: true,
+        }
+    }
+
+    // Server time in unix seconds
+    pub fn time(&self) -> i64 {
+        time::now_millis() / 1_000
+    }
+
+    pub async fn symbols(
+        &self,
+        symbol: &str,
+    ) -> Result<Option<UdfSymbol>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .resolve(symbol)
+            .await?
+            .map(|market| symbol_info(&market)))
+    }
+
+    pub async fn history(
+        &self,
+        symbol: &str,
+        resolution: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<UdfHistory, Box<dyn Error + Send + Sync>> {
+        let Some(market) = self.resolve(symbol).await? else {
+            return Err(format!("Unknown symbol: {}", symbol).into());
+        };
+        let query = CandleQuery::new(&market.market_id, resolution, from, to)?;
+        candle_history(&self.candles, &query).await
+    }
+
+    async fn resolve(
+        &self,
+        symbol: &str,
+    ) -> Result<Option<MarketData>, Box<dyn Error + Send + Sync>> {
+        if let Some(market) = self.markets.market(symbol).await? {
+            return Ok(Some(market));
+        }
+        Ok(self
+            .markets
+            .markets()
+            .await?
+            .into_iter()
+            .find(|market| market.ticker.eq_ignore_ascii_case(symbol)))
+    }
+}
+
+// The candles of `query` with gaps filled, in the UDF `/history` shape
+pub async fn candle_history<S: CandleSource + ?Sized>(
+    candles: &S,
+    query: &CandleQuery,
+) -> Result<UdfHistory, Box<dyn Error + Send + Sync>> {
+    let stored = candles
+        .candles(&query.market_id, query.resolution, query.from, query.to)
+        .await?;
+    let previous = candles
+        .candle_before(&query.market_id, query.resolution, query.from)
+        .await?;
+    let filled = fill_gaps(
+        &stored,
+        previous.as_ref(),
+        query.resolution,
+        query.from,
+        query.to,
+    );
+
+    Ok(UdfHistory::new(&filled, previous.as_ref()))
+}
+
+fn supported_resolutions() -> Vec<&'static str> {
+    Resolution::ALL.iter().map(|r| r.as_str()).collect()
+}
+
+fn symbol_info(market: &MarketData) -> UdfSymbol {
+    UdfSymbol {
+        name: market.ticker.clone(),
+        ticker: market.ticker.clone(),
+        description: market.ticker.clone(),
+        symbol_type: "futures",
+        session: "24x7",
+        timezone: "Etc/UTC",
+        exchange: EXCHANGE,
+        listed_exchange: EXCHANGE,
+        minmov: 1,
+        pricescale: price_scale(market.mark_price),
+        has_intraday: true,
+        has_daily: true,
+        supported_resolutions: supported_resolutions(),
+        volume_precision: 4,
+        data_status: "streaming",
+    }
+}
+
+// The chain tick size is not cached, so show about six significant digits of
+// the current mark price
+fn price_scale(mark_price: f64) -> u64 {
+    let integer_digits = if mark_price >= 1.0 {
+        mark_price.log10().floor() as i32 + 1
+    } else {
+        0
+    };
+    10u64.pow((6 - integer_digits).clamp(0, 8) as u32)
+}
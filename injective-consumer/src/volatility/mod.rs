@@ -0,0 +1,128 @@
+use crate::models::time::{truncate_millis, DAY_MILLIS, HOUR_MILLIS};
+use std::collections::{HashMap, VecDeque};
+
+// Realized volatility from trade prices, shared by the Redis and Scylla
+// processors so both report the same numbers. Prices are sampled as one close
+// per minute; realized variance over a window is the sum of squared log
+// returns between consecutive closes inside it.
+
+pub const SAMPLE_MILLIS: i64 = 60_000;
+const YEAR_MILLIS: f64 = 365.0 * DAY_MILLIS as f64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolatilityWindow {
+    Hour,
+    Day,
+    Week,
+}
+
+impl VolatilityWindow {
+    pub const ALL: [VolatilityWindow; 3] = [
+        VolatilityWindow::Hour,
+        VolatilityWindow::Day,
+        VolatilityWindow::Week,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VolatilityWindow::Hour => "1h",
+            VolatilityWindow::Day => "24h",
+            VolatilityWindow::Week => "7d",
+        }
+    }
+
+    pub fn millis(&self) -> i64 {
+        match self {
+            VolatilityWindow::Hour => HOUR_MILLIS,
+            VolatilityWindow::Day => DAY_MILLIS,
+            VolatilityWindow::Week => 7 * DAY_MILLIS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolatilityEstimate {
+    pub window: VolatilityWindow,
+    // Sum of squared log returns over the window, not annualized
+    pub realized_variance: f64,
+    // Realized variance scaled to a year, as a standard deviation
+    pub annualized_vol: f64,
+    // Number of returns the estimate is built from
+    pub returns: usize,
+}
+
+// Per-market minute closes covering the longest window
+#[derive(Debug, Default)]
+pub struct VolatilityTracker {
+    closes: HashMap<String, VecDeque<(i64, f64)>>,
+}
+
+impl VolatilityTracker {
+    pub fn new() -> Self {
+        VolatilityTracker::default()
+    }
+
+    // Record a trade price at a block time in milliseconds. Returns true when
+    // the trade opened a new minute, which is when estimates are worth refreshing.
+    pub fn record(&mut self, market_id: &str, time_millis: i64, price: f64) -> bool {
+        if price <= 0.0 {
+            return false;
+        }
+        let minute = truncate_millis(time_millis, SAMPLE_MILLIS);
+        let closes = self.closes.entry(market_id.to_string()).or_default();
+
+        let new_minute = match closes.back_mut() {
+            Some((last, close)) if *last == minute => {
+                *close = price;
+                false
+            }
+            // Replayed trades from an earlier minute don't rewrite history
+            Some((last, _)) if *last > minute => false,
+            _ => {
+                closes.push_back((minute, price));
+                true
+            }
+        };
+
+        let oldest = minute - VolatilityWindow::Week.millis();
+        while closes.front().is_some_and(|(start, _)| *start < oldest) {
+            closes.pop_front();
+        }
+        new_minute
+    }
+
+    // Estimates for every window ending at the market's latest close. Windows
+    // without at least one return are left out.
+    pub fn estimates(&self, market_id: &str) -> Vec<VolatilityEstimate> {
+        let Some(closes) = self.closes.get(market_id) else {
+            return Vec::new();
+        };
+        let Some(&(latest, _)) = closes.back() else {
+            return Vec::new();
+        };
+
+        VolatilityWindow::ALL
+            .iter()
+            .filter_map(|window| {
+                let start = latest - window.millis();
+                let mut realized_variance = 0.0;
+                let mut returns = 0;
+                let mut previous: Option<f64> = None;
+                for &(_, close) in closes.iter().filter(|(minute, _)| *minute >= start) {
+                    if let Some(previous) = previous {
+                        realized_variance += (close / previous).ln().powi(2);
+                        returns += 1;
+                    }
+                    previous = Some(close);
+                }
+                (returns > 0).then(|| VolatilityEstimate {
+                    window: *window,
+                    realized_variance,
+                    annualized_vol: (realized_variance * YEAR_MILLIS / window.millis() as f64)
+                        .sqrt(),
+                    returns,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+// Event-time windows keyed by market, shared by the candle, trade statistics
+// and 24h summary aggregators. Times are block times in milliseconds, never
+// the wall clock, so replays close the same windows as the original run.
+//
+// The watermark is the newest block time seen. A window [start, end) stays
+// open until the watermark passes end + allowed lateness; events that only
+// fall in windows closed by then are late and dropped.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowSpec {
+    // Back-to-back windows of `size`
+    Tumbling { size: i64 },
+    // Windows of `size` starting every `slide`, so each event is in
+    // size / slide of them
+    Sliding { size: i64, slide: i64 },
+}
+
+impl WindowSpec {
+    pub fn tumbling(size: Duration) -> Self {
+        WindowSpec::Tumbling {
+            size: size.as_millis() as i64,
+        }
+    }
+
+    pub fn sliding(size: Duration, slide: Duration) -> Self {
+        WindowSpec::Sliding {
+            size: size.as_millis() as i64,
+            slide: slide.as_millis() as i64,
+        }
+    }
+
+    pub fn size(&self) -> i64 {
+        match self {
+            WindowSpec::Tumbling { size } | WindowSpec::Sliding { size, .. } => *size,
+        }
+    }
+
+    fn slide(&self) -> i64 {
+        match self {
+            WindowSpec::Tumbling { size } => *size,
+            WindowSpec::Sliding { slide, .. } => *slide,
+        }
+    }
+
+    // Start of the newest window containing `time`
+    pub fn latest_start(&self, time: i64) -> i64 {
+        time - time.rem_euclid(self.slide())
+    }
+
+    // Starts of every window containing `time`, oldest first
+    pub fn starts(&self, time: i64) -> Vec<i64> {
+        let latest = self.latest_start(time);
+        let mut starts = Vec::new();
+        let mut start = latest;
+        while start > time - self.size() {
+            starts.push(start);
+            start -= self.slide();
+        }
+        starts.reverse();
+        starts
+    }
+}
+
+// Folds events into a window's running value
+pub trait Aggregate {
+    type Event;
+
+    // A window opened by its first event
+    fn first(start: i64, event: &Self::Event) -> Self;
+    fn add(&mut self, event: &Self::Event);
+}
+
+// A window that will not change any more
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosedWindow<A> {
+    pub key: String,
+    pub start: i64,
+    pub end: i64,
+    pub value: A,
+}
+
+#[derive(Debug)]
+pub struct WindowedAggregator<A> {
+    spec: WindowSpec,
+    allowed_lateness: i64,
+    watermark: Option<i64>,
+    open: HashMap<String, BTreeMap<i64, A>>,
+}
+
+impl<A: Aggregate> WindowedAggregator<A> {
+    pub fn new(spec: WindowSpec) -> Self {
+        WindowedAggregator {
+            spec,
+            allowed_lateness: 0,
+            watermark: None,
+            open: HashMap::new(),
+        }
+    }
+
+    pub fn set_allowed_lateness(&mut self, allowed_lateness: Duration) {
+        self.allowed_lateness = allowed_lateness.as_millis() as i64;
+    }
+
+    fn is_closed(&self, start: i64) -> bool {
+        self.watermark
+            .is_some_and(|watermark| start + self.spec.size() + self.allowed_lateness <= watermark)
+    }
+
+    // Whether an event at `time` would still land in an open window
+    pub fn accepts(&self, time: i64) -> bool {
+        !self.is_closed(self.spec.latest_start(time))
+    }
+
+    pub fn is_tracking(&self, key: &str, start: i64) -> bool {
+        self.open
+            .get(key)
+            .is_some_and(|windows| windows.contains_key(&start))
+    }
+
+    pub fn get(&self, key: &str, start: i64) -> Option<&A> {
+        self.open.get(key)?.get(&start)
+    }
+
+    // Open windows of a key, oldest first
+    pub fn windows(&self, key: &str) -> impl Iterator<Item = (i64, &A)> {
+        self.open
+            .get(key)
+            .into_iter()
+            .flat_map(|windows| windows.iter().map(|(start, value)| (*start, value)))
+    }
+
+    // Continue a window written before a restart instead of starting it empty.
+    // Closed windows and windows already tracked are left alone.
+    pub fn resume(&mut self, key: &str, start: i64, value: A) {
+        if self.is_closed(start) {
+            return;
+        }
+        self.open
+            .entry(key.to_string())
+            .or_default()
+            .entry(start)
+            .or_insert(value);
+    }
+
+    // Fold an event into every open window containing `time`. Returns false
+    // when the event is late and was dropped.
+    pub fn add(&mut self, key: &str, time: i64, event: &A::Event) -> bool {
+        let starts: Vec<i64> = self
+            .spec
+            .starts(time)
+            .into_iter()
+            .filter(|start| !self.is_closed(*start))
+            .collect();
+        if starts.is_empty() {
+            return false;
+        }
+
+        let windows = self.open.entry(key.to_string()).or_default();
+        for start in starts {
+            match windows.get_mut(&start) {
+                Some(value) => value.add(event),
+                None => {
+                    windows.insert(start, A::first(start, event));
+                }
+            }
+        }
+        true
+    }
+
+    // Move the watermark to `time` and return the windows that closed, ordered
+    // by key and start. The watermark never moves back.
+    pub fn advance(&mut self, time: i64) -> Vec<ClosedWindow<A>> {
+        if self.watermark.is_some_and(|watermark| watermark >= time) {
+            return Vec::new();
+        }
+        self.watermark = Some(time);
+
+        let size = self.spec.size();
+        let horizon = time - size - self.allowed_lateness;
+        let mut closed = Vec::new();
+        for (key, windows) in self.open.iter_mut() {
+            let still_open = windows.split_off(&(horizon + 1));
+            for (start, value) in std::mem::replace(windows, still_open) {
+                closed.push(ClosedWindow {
+                    key: key.clone(),
+                    start,
+                    end: start + size,
+                    value,
+                });
+            }
+        }
+        self.open.retain(|_, windows| !windows.is_empty());
+
+        closed.sort_by(|a, b| (&a.key, a.start).cmp(&(&b.key, b.start)));
+        closed
+    }
+}
@@ -0,0 +1,66 @@
+use flatbuffers::{
+    Follow, ForwardsUOffset, InvalidFlatbuffer, Table, VOffsetT, Vector, Verifiable, Verifier,
+};
+
+// Read side of the `KafkaMessage` table in flatbuf/kafka_message.fbs, written
+// out by hand in the shape flatc generates so the build needs no flatc
+const VT_MESSAGE_TYPE: VOffsetT = 4;
+const VT_BLOCK_HEIGHT: VOffsetT = 6;
+const VT_BLOCK_TIME: VOffsetT = 8;
+const VT_PAYLOAD: VOffsetT = 10;
+
+pub struct KafkaEnvelope<'a> {
+    table: Table<'a>,
+}
+
+impl<'a> Follow<'a> for KafkaEnvelope<'a> {
+    type Inner = KafkaEnvelope<'a>;
+
+    unsafe fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        KafkaEnvelope {
+            table: Table::new(buf, loc),
+        }
+    }
+}
+
+impl Verifiable for KafkaEnvelope<'_> {
+    fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        v.visit_table(pos)?
+            .visit_field::<ForwardsUOffset<&str>>("message_type", VT_MESSAGE_TYPE, true)?
+            .visit_field::<u64>("block_height", VT_BLOCK_HEIGHT, false)?
+            .visit_field::<u64>("block_time", VT_BLOCK_TIME, false)?
+            .visit_field::<ForwardsUOffset<Vector<'_, u8>>>("payload", VT_PAYLOAD, true)?
+            .finish();
+        Ok(())
+    }
+}
+
+// The accessors rely on the buffer having been verified, which
+// `flatbuffers::root` does before handing out an envelope
+impl<'a> KafkaEnvelope<'a> {
+    pub fn message_type(&self) -> &'a str {
+        unsafe {
+            self.table
+                .get::<ForwardsUOffset<&str>>(VT_MESSAGE_TYPE, None)
+                .unwrap_or_default()
+        }
+    }
+
+    pub fn block_height(&self) -> u64 {
+        unsafe { self.table.get::<u64>(VT_BLOCK_HEIGHT, Some(0)).unwrap_or(0) }
+    }
+
+    pub fn block_time(&self) -> u64 {
+        unsafe { self.table.get::<u64>(VT_BLOCK_TIME, Some(0)).unwrap_or(0) }
+    }
+
+    // Protobuf-encoded WirePayload
+    pub fn payload(&self) -> &'a [u8] {
+        unsafe {
+            self.table
+                .get::<ForwardsUOffset<Vector<'a, u8>>>(VT_PAYLOAD, None)
+                .map(|payload| payload.bytes())
+                .unwrap_or_default()
+        }
+    }
+}
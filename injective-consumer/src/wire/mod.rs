@@ -0,0 +1,189 @@
+use crate::config::SerializationFormat;
+use crate::models::{
+    BankBalancePayload, DerivativeMarketPayload, DerivativeOrderPayload, DerivativeTradePayload,
+    ExchangeBalancePayload, FullLimitOrderbookPayload, KafkaMessage, KafkaPayload, MessageType,
+    OraclePricePayload, OrderbookPayload, PositionPayload, SpotMarketPayload, SpotOrderPayload,
+    SpotTradePayload, SubaccountDepositPayload,
+};
+use prost::Message;
+use std::error::Error;
+
+mod envelope;
+
+use envelope::KafkaEnvelope;
+
+// Binary encodings of KafkaMessage, matching the producer's wire module. The
+// schemas are wire/kafka_message.proto and flatbuf/kafka_message.fbs.
+
+// Protobuf has no repeated oneof fields, so each payload list is wrapped
+macro_rules! payload_lists {
+    ($($name:ident($item:ty);)*) => {
+        $(
+            #[derive(Clone, prost::Message)]
+            pub struct $name {
+                #[prost(message, repeated, tag = "1")]
+                pub items: Vec<$item>,
+            }
+        )*
+    };
+}
+
+payload_lists! {
+    BankBalanceList(BankBalancePayload);
+    SubaccountDepositList(SubaccountDepositPayload);
+    OrderbookList(OrderbookPayload);
+    PositionList(PositionPayload);
+    OraclePriceList(OraclePricePayload);
+    SpotTradeList(SpotTradePayload);
+    DerivativeTradeList(DerivativeTradePayload);
+    SpotOrderList(SpotOrderPayload);
+    DerivativeOrderList(DerivativeOrderPayload);
+    DerivativeMarketList(DerivativeMarketPayload);
+    ExchangeBalanceList(ExchangeBalancePayload);
+    FullOrderbookList(FullLimitOrderbookPayload);
+    SpotMarketList(SpotMarketPayload);
+}
+
+#[derive(Clone, prost::Oneof)]
+pub enum Kind {
+    #[prost(message, tag = "1")]
+    BankBalances(BankBalanceList),
+    #[prost(message, tag = "2")]
+    SubaccountDeposits(SubaccountDepositList),
+    #[prost(message, tag = "3")]
+    SpotOrderbooks(OrderbookList),
+    #[prost(message, tag = "4")]
+    DerivativeOrderbooks(OrderbookList),
+    #[prost(message, tag = "5")]
+    StreamPositions(PositionList),
+    #[prost(message, tag = "6")]
+    OraclePrices(OraclePriceList),
+    #[prost(message, tag = "7")]
+    SpotTrades(SpotTradeList),
+    #[prost(message, tag = "8")]
+    DerivativeTrades(DerivativeTradeList),
+    #[prost(message, tag = "9")]
+    SpotOrders(SpotOrderList),
+    #[prost(message, tag = "10")]
+    DerivativeOrders(DerivativeOrderList),
+    #[prost(message, tag = "11")]
+    DerivativeMarkets(DerivativeMarketList),
+    #[prost(message, tag = "12")]
+    ExchangePositions(PositionList),
+    #[prost(message, tag = "13")]
+    ExchangeBalances(ExchangeBalanceList),
+    #[prost(message, tag = "14")]
+    DerivativeFullOrderbooks(FullOrderbookList),
+    #[prost(message, tag = "15")]
+    SpotMarkets(SpotMarketList),
+    #[prost(message, tag = "16")]
+    SpotFullOrderbooks(FullOrderbookList),
+}
+
+#[derive(Clone, prost::Message)]
+pub struct WirePayload {
+    #[prost(
+        oneof = "Kind",
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16"
+    )]
+    pub kind: Option<Kind>,
+}
+
+#[derive(Clone, prost::Message)]
+pub struct WireMessage {
+    #[prost(string, tag = "1")]
+    pub message_type: String,
+    #[prost(uint64, tag = "2")]
+    pub block_height: u64,
+    #[prost(uint64, tag = "3")]
+    pub block_time: u64,
+    #[prost(message, optional, tag = "4")]
+    pub payload: Option<WirePayload>,
+}
+
+impl From<Kind> for KafkaPayload {
+    fn from(kind: Kind) -> Self {
+        match kind {
+            Kind::BankBalances(list) => KafkaPayload::StreamBankBalances(list.items),
+            Kind::SubaccountDeposits(list) => KafkaPayload::StreamSubaccountDeposits(list.items),
+            Kind::SpotOrderbooks(list) => KafkaPayload::StreamSpotOrderbooks(list.items),
+            Kind::DerivativeOrderbooks(list) => {
+                KafkaPayload::StreamDerivativeOrderbooks(list.items)
+            }
+            Kind::StreamPositions(list) => KafkaPayload::StreamPositions(list.items),
+            Kind::OraclePrices(list) => KafkaPayload::StreamOraclePrices(list.items),
+            Kind::SpotTrades(list) => KafkaPayload::SpotTrades(list.items),
+            Kind::DerivativeTrades(list) => KafkaPayload::DerivativeTrades(list.items),
+            Kind::SpotOrders(list) => KafkaPayload::SpotOrders(list.items),
+            Kind::DerivativeOrders(list) => KafkaPayload::DerivativeOrders(list.items),
+            Kind::DerivativeMarkets(list) => KafkaPayload::DerivativeMarkets(list.items),
+            Kind::ExchangePositions(list) => KafkaPayload::ExchangePositions(list.items),
+            Kind::ExchangeBalances(list) => KafkaPayload::ExchangeBalances(list.items),
+            Kind::DerivativeFullOrderbooks(list) => {
+                KafkaPayload::DerivativeFullOrderbooks(list.items)
+            }
+            Kind::SpotMarkets(list) => KafkaPayload::SpotMarkets(list.items),
+            Kind::SpotFullOrderbooks(list) => KafkaPayload::SpotFullOrderbooks(list.items),
+        }
+    }
+}
+
+// Decode a message value in the given format. Messages that decode but fail
+// `KafkaMessage::validate` are errors too, so they are dead-lettered like
+// undecodable ones instead of reaching the processors.
+pub fn decode(
+    bytes: &[u8],
+    format: SerializationFormat,
+) -> Result<KafkaMessage, Box<dyn Error + Send + Sync>> {
+    let message = decode_unchecked(bytes, format)?;
+    message.validate()?;
+    Ok(message)
+}
+
+fn decode_unchecked(
+    bytes: &[u8],
+    format: SerializationFormat,
+) -> Result<KafkaMessage, Box<dyn Error + Send + Sync>> {
+    match format {
+        SerializationFormat::Json => Ok(serde_json::from_slice(bytes)?),
+        SerializationFormat::Protobuf => {
+            let message = WireMessage::decode(bytes)?;
+            from_wire(
+                &message.message_type,
+                message.block_height,
+                message.block_time,
+                message.payload.unwrap_or_default(),
+            )
+        }
+        SerializationFormat::Flatbuffers => {
+            let envelope = flatbuffers::root::<KafkaEnvelope>(bytes)?;
+            let payload = WirePayload::decode(envelope.payload())?;
+            from_wire(
+                envelope.message_type(),
+                envelope.block_height(),
+                envelope.block_time(),
+                payload,
+            )
+        }
+    }
+}
+
+fn from_wire(
+    message_type: &str,
+    block_height: u64,
+    block_time: u64,
+    payload: WirePayload,
+) -> Result<KafkaMessage, Box<dyn Error + Send + Sync>> {
+    let message_type = MessageType::parse(message_type)
+        .ok_or_else(|| format!("unknown message type: {:?}", message_type))?;
+    let payload = payload
+        .kind
+        .ok_or_else(|| format!("{} message without a payload", message_type.as_str()))?;
+
+    Ok(KafkaMessage {
+        message_type,
+        block_height,
+        block_time,
+        payload: payload.into(),
+    })
+}
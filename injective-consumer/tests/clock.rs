@@ -0,0 +1,65 @@
+// Time-dependent logic driven by a MockClock instead of the wall clock, so
+// rates, lags and ETAs come out exactly.
+use injective_consumer::catchup::CatchUpProgress;
+use injective_consumer::clock::{Clock, MockClock};
+use std::sync::Arc;
+use std::time::Duration;
+
+// 2024-01-01T00:00:00Z
+const START: i64 = 1_704_067_200_000;
+
+#[test]
+fn mock_clock_only_moves_when_told() {
+    let clock = MockClock::new(START);
+    assert_eq!(clock.now_millis(), START);
+
+    clock.advance(Duration::from_secs(90));
+    assert_eq!(clock.now_millis(), START + 90_000);
+    // Block times in seconds and in millis lag the same
+    assert_eq!(clock.age(START / 1_000), Duration::from_secs(90));
+    assert_eq!(clock.age(START), Duration::from_secs(90));
+    assert!(clock.is_stale(START, Duration::from_secs(60)));
+
+    // Blocks ahead of the clock are not behind at all
+    clock.set(START - 5_000);
+    assert_eq!(clock.age(START), Duration::ZERO);
+}
+
+#[test]
+fn catch_up_progress_measures_against_the_clock() {
+    let clock = Arc::new(MockClock::new(START));
+    let progress = CatchUpProgress::new("group").with_clock(clock.clone());
+
+    // An hour behind with 300 messages still to go
+    progress.record_lag("topic", 0, Some(300));
+    for height in 0..100u64 {
+        progress.record_message();
+        // One block a second
+        progress.record_block(
+            1_000 + height,
+            ((START - 3_600_000) / 1_000) as u64 + height,
+        );
+    }
+    clock.advance(Duration::from_secs(10));
+
+    let snapshot = progress.snapshot().unwrap();
+    assert!(!snapshot.caught_up);
+    assert_eq!(snapshot.processed, 100);
+    assert_eq!(snapshot.remaining, 300);
+    assert_eq!(snapshot.percent, 25.0);
+    // 10 messages a second leaves 30 seconds for the rest
+    assert_eq!(snapshot.eta_secs, Some(30));
+    assert_eq!(snapshot.block_height, 1_099);
+    assert_eq!(snapshot.lag_secs, 3_511);
+    assert_eq!(snapshot.head_block_height, Some(1_099 + 3_511));
+
+    // Within a minute of the clock counts as caught up
+    progress.record_lag("topic", 0, Some(0));
+    progress.record_block(4_700, (START / 1_000) as u64);
+    clock.advance(Duration::from_secs(20));
+    let snapshot = progress.snapshot().unwrap();
+    assert!(snapshot.caught_up);
+    assert_eq!(snapshot.percent, 100.0);
+    assert_eq!(snapshot.eta_secs, None);
+    assert_eq!(snapshot.lag_secs, 30);
+}
@@ -0,0 +1,306 @@
+// Liquidation and funding math against worked examples of the chain's
+// formulas. Liquidation inputs are given the way the chain streams them:
+// integer strings scaled by 1e18 for quantities and ratios and by 1e24 for
+// prices, margin and funding. Expected prices were computed with exact
+// rational arithmetic and rounded to 12 decimal places.
+use injective_consumer::compute::{self, decimal};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+struct Position {
+    is_long: bool,
+    quantity: &'static str,
+    entry_price: &'static str,
+    margin: &'static str,
+    cumulative_funding_entry: &'static str,
+}
+
+struct Market {
+    maintenance_margin_ratio: &'static str,
+    cumulative_funding: &'static str,
+}
+
+fn dec(value: &str) -> Decimal {
+    Decimal::from_str(value).unwrap()
+}
+
+fn liquidation_price(position: &Position, market: &Market) -> Option<Decimal> {
+    decimal::liquidation_price(
+        position.is_long,
+        decimal::from_chain(position.entry_price, 24)?,
+        decimal::from_chain(position.margin, 24)?,
+        decimal::from_chain(position.quantity, 18)?,
+        decimal::from_chain(market.maintenance_margin_ratio, 18)?,
+        decimal::from_chain(market.cumulative_funding, 24)?,
+        decimal::from_chain(position.cumulative_funding_entry, 24)?,
+    )
+}
+
+fn assert_price(position: &Position, market: &Market, expected: &str) {
+    let price = liquidation_price(position, market).unwrap();
+    assert_eq!(price.round_dp(12), dec(expected));
+}
+
+const NO_FUNDING: Market = Market {
+    maintenance_margin_ratio: "50000000000000000",
+    cumulative_funding: "0",
+};
+
+#[test]
+fn from_chain_scales_exactly() {
+    assert_eq!(
+        decimal::from_chain("2000000000000000000", 18),
+        Some(dec("2"))
+    );
+    assert_eq!(
+        decimal::from_chain("30000000000000000000000000000", 24),
+        Some(dec("30000"))
+    );
+    assert_eq!(
+        decimal::from_chain("1234567891234567891234567", 18),
+        Some(dec("1234567.891234567891234567"))
+    );
+    assert_eq!(
+        decimal::from_chain("-2345678901234567", 24),
+        Some(dec("-0.000000002345678901234567"))
+    );
+    assert_eq!(
+        decimal::from_chain("1.5", 18),
+        Some(dec("0.0000000000000000015"))
+    );
+    assert_eq!(decimal::from_chain("0", 24), Some(Decimal::ZERO));
+    assert_eq!(decimal::from_chain("", 18), None);
+    assert_eq!(decimal::from_chain("12e5", 18), None);
+}
+
+#[test]
+fn from_chain_truncates_past_28_digits() {
+    // 35 significant digits; the last 7 don't fit in a Decimal
+    assert_eq!(
+        decimal::from_chain("12345678901234567890123456789012345", 24),
+        Some(dec("12345678901.23456789012345678"))
+    );
+    // Scale 30; the last two digits are below what a Decimal can represent
+    assert_eq!(
+        decimal::from_chain("123456", 30),
+        Some(dec("0.0000000000000000000000001234"))
+    );
+}
+
+#[test]
+fn long_without_funding() {
+    // 2 at 30000 with 4000 margin
+    let position = Position {
+        is_long: true,
+        quantity: "2000000000000000000",
+        entry_price: "30000000000000000000000000000",
+        margin: "4000000000000000000000000000",
+        cumulative_funding_entry: "0",
+    };
+    assert_price(&position, &NO_FUNDING, "29473.684210526316");
+}
+
+#[test]
+fn short_without_funding() {
+    // 1.5 at 2000 with 300 margin
+    let position = Position {
+        is_long: false,
+        quantity: "1500000000000000000",
+        entry_price: "2000000000000000000000000000",
+        margin: "300000000000000000000000000",
+        cumulative_funding_entry: "0",
+    };
+    assert_price(&position, &NO_FUNDING, "2095.238095238095");
+}
+
+#[test]
+fn funding_moves_longs_and_shorts_apart() {
+    // 10 at 100 with 200 margin; funding went from 0.1 to 0.5 per unit, which
+    // longs pay and shorts receive
+    let market = Market {
+        maintenance_margin_ratio: "50000000000000000",
+        cumulative_funding: "500000000000000000000000",
+    };
+    let mut position = Position {
+        is_long: true,
+        quantity: "10000000000000000000",
+        entry_price: "100000000000000000000000000",
+        margin: "200000000000000000000000000",
+        cumulative_funding_entry: "100000000000000000000000",
+    };
+    assert_price(&position, &market, "84.631578947368");
+    position.is_long = false;
+    assert_price(&position, &market, "114.666666666667");
+}
+
+#[test]
+fn large_notional_keeps_its_digits() {
+    // About 8 billion notional with every input using its full precision
+    let market = Market {
+        maintenance_margin_ratio: "62500000000000000",
+        cumulative_funding: "12345678901234567",
+    };
+    let mut position = Position {
+        is_long: true,
+        quantity: "1234567891234567891234567",
+        entry_price: "6543212345678901234567890123",
+        margin: "808080808080808080808080808080808",
+        cumulative_funding_entry: "2345678901234567",
+    };
+    assert_price(&position, &market, "6281.244678231070");
+    position.is_long = false;
+    assert_price(&position, &market, "6774.360287563458");
+}
+
+#[test]
+fn invalid_positions_have_no_price() {
+    let position = Position {
+        is_long: true,
+        quantity: "0",
+        entry_price: "30000000000000000000000000000",
+        margin: "4000000000000000000000000000",
+        cumulative_funding_entry: "0",
+    };
+    assert_eq!(liquidation_price(&position, &NO_FUNDING), None);
+    let no_ratio = Market {
+        maintenance_margin_ratio: "0",
+        cumulative_funding: "0",
+    };
+    let position = Position {
+        quantity: "2000000000000000000",
+        ..position
+    };
+    assert_eq!(liquidation_price(&position, &no_ratio), None);
+    assert_eq!(
+        compute::calculate_liquidation_price(true, 30000.0, 4000.0, 2.0, 0.0, 0.0, 0.0),
+        0.0
+    );
+}
+
+#[test]
+fn f64_helpers_agree_with_decimal() {
+    let price = compute::calculate_liquidation_price(true, 30000.0, 4000.0, 2.0, 0.05, 0.0, 0.0);
+    assert!((price - 29473.684210526316).abs() < 1e-9);
+
+    let liquidation = dec("29473.684210526316");
+    assert!(decimal::is_liquidatable(true, liquidation, dec("29000")));
+    assert!(!decimal::is_liquidatable(true, liquidation, dec("29500")));
+    assert!(decimal::is_liquidatable(false, dec("2095.24"), dec("2100")));
+    assert_eq!(
+        decimal::distance_to_liquidation(true, dec("90"), dec("100")),
+        Some(dec("10"))
+    );
+    assert_eq!(
+        decimal::distance_to_liquidation(false, dec("90"), dec("100")),
+        Some(dec("-10"))
+    );
+    assert_eq!(
+        decimal::distance_to_liquidation(true, dec("90"), Decimal::ZERO),
+        None
+    );
+    assert_eq!(compute::distance_to_liquidation(true, 90.0, 100.0), 10.0);
+}
+
+#[test]
+fn funding_payments_move_from_longs_to_shorts() {
+    // 10 contracts opened at cumulative funding 0.1, now at 0.5
+    let long = decimal::funding_payment(true, dec("10"), dec("0.5"), dec("0.1"));
+    let short = decimal::funding_payment(false, dec("10"), dec("0.5"), dec("0.1"));
+    assert_eq!(long, Some(dec("-4")));
+    assert_eq!(short, Some(dec("4")));
+    assert_eq!(compute::funding_payment(false, 10.0, 0.5, 0.1), 4.0);
+    // Negative funding flows the other way
+    assert_eq!(
+        decimal::funding_payment(true, dec("2"), dec("-0.25"), dec("0.25")),
+        Some(dec("1"))
+    );
+}
+
+#[test]
+fn funding_rates_are_capped() {
+    // Hourly interest of 0.000125 and a 0.000625 cap
+    let rate = compute::funding_rate(0.0002, 0.000125, 0.000625);
+    assert!((rate - 0.000325).abs() < 1e-15);
+    assert_eq!(compute::funding_rate(0.01, 0.000125, 0.000625), 0.000625);
+    assert_eq!(compute::funding_rate(-0.01, 0.000125, 0.000625), -0.000625);
+
+    // A daily premium of 0.0048 held for the half hour since the last funding
+    // averages to 0.0002 an hour
+    let estimated = compute::estimated_funding_rate(0.0048 * 1800.0, 1800, 0.000125, 0.000625);
+    assert!((estimated - 0.000325).abs() < 1e-15);
+    // Right at a funding there is no premium yet
+    assert_eq!(
+        compute::estimated_funding_rate(0.0, 0, 0.000125, 0.000625),
+        0.000125
+    );
+
+    assert_eq!(compute::paid_funding_rate(1.5, 4.5, 30000.0), 0.0001);
+    assert_eq!(compute::paid_funding_rate(1.5, 4.5, 0.0), 0.0);
+}
+
+#[test]
+fn margin_ratio_reaches_maintenance_at_liquidation() {
+    // 2 at 30000 with 4000 margin, marked at 31000
+    let pnl = decimal::unrealized_pnl(true, dec("30000"), dec("31000"), dec("2"));
+    assert_eq!(pnl, Some(dec("2000")));
+    assert_eq!(
+        decimal::unrealized_pnl(false, dec("30000"), dec("31000"), dec("2")),
+        Some(dec("-2000"))
+    );
+    let ratio = decimal::margin_ratio(
+        true,
+        dec("30000"),
+        dec("31000"),
+        dec("2"),
+        dec("4000"),
+        Decimal::ZERO,
+        Decimal::ZERO,
+    );
+    assert_eq!(ratio.unwrap().round_dp(12), dec("0.096774193548"));
+
+    // At the liquidation price the ratio is the maintenance margin ratio,
+    // funding included
+    let market = Market {
+        maintenance_margin_ratio: "50000000000000000",
+        cumulative_funding: "500000000000000000000000",
+    };
+    for is_long in [true, false] {
+        let position = Position {
+            is_long,
+            quantity: "10000000000000000000",
+            entry_price: "100000000000000000000000000",
+            margin: "200000000000000000000000000",
+            cumulative_funding_entry: "100000000000000000000000",
+        };
+        let liquidation = liquidation_price(&position, &market).unwrap();
+        let ratio = decimal::margin_ratio(
+            is_long,
+            dec("100"),
+            liquidation,
+            dec("10"),
+            dec("200"),
+            dec("0.5"),
+            dec("0.1"),
+        );
+        assert_eq!(ratio.unwrap().round_dp(12), dec("0.05"));
+    }
+
+    assert_eq!(
+        decimal::margin_ratio(
+            true,
+            dec("100"),
+            Decimal::ZERO,
+            dec("10"),
+            dec("200"),
+            Decimal::ZERO,
+            Decimal::ZERO
+        ),
+        None
+    );
+    assert_eq!(
+        compute::calculate_unrealized_pnl(false, 2000.0, 1900.0, 1.5),
+        150.0
+    );
+    let ratio = compute::calculate_margin_ratio(true, 30000.0, 31000.0, 2.0, 4000.0, 0.0, 0.0);
+    assert!((ratio - 0.096774193548).abs() < 1e-9);
+}
@@ -0,0 +1,49 @@
+// The in-memory dedup window: a claimed message blocks its copies until the
+// claim expires, is released or is pushed out by newer claims.
+use injective_consumer::block_dedup::RecentKeys;
+use std::time::{Duration, Instant};
+
+#[test]
+fn copies_are_blocked_until_the_claim_expires() {
+    let mut recent = RecentKeys::new(100, Duration::from_secs(60));
+    let start = Instant::now();
+
+    assert!(recent.claim("group:DerivativeTrade:100:ab", start));
+    assert!(!recent.claim(
+        "group:DerivativeTrade:100:ab",
+        start + Duration::from_secs(1)
+    ));
+    // Other messages of the block are claimed on their own
+    assert!(recent.claim("group:DerivativeTrade:100:cd", start));
+
+    assert!(recent.claim(
+        "group:DerivativeTrade:100:ab",
+        start + Duration::from_secs(61)
+    ));
+}
+
+#[test]
+fn released_claims_can_be_taken_again() {
+    let mut recent = RecentKeys::new(100, Duration::from_secs(60));
+    let start = Instant::now();
+
+    assert!(recent.claim("a", start));
+    recent.release("a");
+    assert!(recent.claim("a", start + Duration::from_secs(1)));
+    assert!(!recent.claim("a", start + Duration::from_secs(2)));
+}
+
+#[test]
+fn the_oldest_claims_make_room_for_new_ones() {
+    let mut recent = RecentKeys::new(2, Duration::from_secs(60));
+    let start = Instant::now();
+
+    assert!(recent.claim("a", start));
+    assert!(recent.claim("b", start));
+    assert!(recent.claim("c", start));
+    assert_eq!(recent.len(), 2);
+
+    // "a" was forgotten, "c" is still remembered
+    assert!(!recent.claim("c", start));
+    assert!(recent.claim("a", start));
+}
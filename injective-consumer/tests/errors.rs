@@ -0,0 +1,29 @@
+// Errors the processors return internally, boxed, sorted into IndexerError
+// variants on the way out
+use injective_consumer::error::{IndexerError, StorageError};
+use scylla::transport::errors::QueryError;
+use std::error::Error;
+
+#[test]
+fn boxed_scylla_query_errors_are_storage_errors() {
+    let e: Box<dyn Error + Send + Sync> =
+        Box::new(QueryError::RequestTimeout("orderbook_orders".to_string()));
+    match IndexerError::from(e) {
+        IndexerError::Storage(StorageError::ScyllaQuery(QueryError::RequestTimeout(table))) => {
+            assert_eq!(table, "orderbook_orders")
+        }
+        other => panic!("expected a ScyllaDB query error, got {:?}", other),
+    }
+}
+
+#[test]
+fn boxed_typed_errors_keep_their_variant() {
+    let e: Box<dyn Error + Send + Sync> = Box::new(IndexerError::Data("bad price".to_string()));
+    assert!(matches!(IndexerError::from(e), IndexerError::Data(reason) if reason == "bad price"));
+}
+
+#[test]
+fn unknown_errors_are_other() {
+    let e: Box<dyn Error + Send + Sync> = "no such market".into();
+    assert!(matches!(IndexerError::from(e), IndexerError::Other(_)));
+}
@@ -0,0 +1,55 @@
+// Settlement of expiry futures as market updates reveal it: the expiry comes
+// with every update, the settlement price only once the chain has settled.
+use injective_consumer::expiry;
+use injective_consumer::models::DerivativeMarketPayload;
+
+const MARKET: &str = "0xfutures";
+// 2024-03-29T08:00:00Z
+const EXPIRY: i64 = 1_711_699_200;
+
+fn futures(status: &str, settlement_price: &str) -> DerivativeMarketPayload {
+    DerivativeMarketPayload {
+        market_id: MARKET.to_string(),
+        ticker: "BTC/USDT 29MAR24".to_string(),
+        status: status.to_string(),
+        is_perpetual: false,
+        expiration_timestamp: EXPIRY.to_string(),
+        settlement_price: settlement_price.to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn live_futures_are_not_settled() {
+    let market = futures("Active", "");
+    assert_eq!(expiry::expiration(&market), Some(EXPIRY));
+    assert_eq!(expiry::settlement(&market, EXPIRY - 1), None);
+}
+
+#[test]
+fn expired_futures_settle_once_the_price_is_set() {
+    // Past expiry, before the chain has taken the settlement price
+    assert_eq!(expiry::settlement(&futures("Active", ""), EXPIRY + 5), None);
+    assert_eq!(
+        expiry::settlement(&futures("Expired", "0"), EXPIRY + 5),
+        None
+    );
+
+    let price = format!("70123{}", "0".repeat(24));
+    let settlement = expiry::settlement(&futures("Expired", &price), EXPIRY + 5).unwrap();
+    assert_eq!(settlement.market_id, MARKET);
+    assert_eq!(settlement.expiration_timestamp, EXPIRY);
+    assert_eq!(settlement.settlement_price, 70123.0);
+
+    // An Expired status settles even when the update's clock lags the expiry
+    assert!(expiry::settlement(&futures("Expired", &price), EXPIRY - 60).is_some());
+}
+
+#[test]
+fn perpetuals_never_expire() {
+    let mut market = futures("Active", "");
+    market.is_perpetual = true;
+    market.expiration_timestamp = "-1".to_string();
+    assert_eq!(expiry::expiration(&market), None);
+    assert_eq!(expiry::settlement(&market, EXPIRY + 5), None);
+}
@@ -0,0 +1,72 @@
+// Funding settlements as the Redis processor sees them: successive market
+// updates of an hourly market, timed in block seconds.
+use injective_consumer::funding::FundingSettlementTracker;
+
+const MARKET: &str = "0xmarket";
+const HOUR: i64 = 3_600;
+// 2024-01-01T00:00:00Z
+const MIDNIGHT: i64 = 1_704_067_200;
+
+#[test]
+fn a_jump_in_cumulative_funding_is_a_settlement() {
+    let mut tracker = FundingSettlementTracker::new();
+    assert_eq!(tracker.observe(MARKET, 10.0, MIDNIGHT - 10, HOUR), None);
+    assert_eq!(tracker.observe(MARKET, 10.0, MIDNIGHT - 5, HOUR), None);
+
+    let settlement = tracker.observe(MARKET, 12.5, MIDNIGHT + 1, HOUR).unwrap();
+    assert_eq!(settlement.previous_cumulative_funding, 10.0);
+    assert_eq!(settlement.cumulative_funding, 12.5);
+    assert_eq!(settlement.funding_delta(), 2.5);
+    assert_eq!(settlement.settled_at_secs, MIDNIGHT);
+
+    // Later updates in the same hour don't settle again
+    assert_eq!(tracker.observe(MARKET, 12.5, MIDNIGHT + 60, HOUR), None);
+}
+
+#[test]
+fn crossing_an_interval_settles_without_a_payment() {
+    let mut tracker = FundingSettlementTracker::new();
+    tracker.observe(MARKET, 10.0, MIDNIGHT - 10, HOUR);
+
+    let settlement = tracker.observe(MARKET, 10.0, MIDNIGHT + 2, HOUR).unwrap();
+    assert_eq!(settlement.funding_delta(), 0.0);
+    assert_eq!(settlement.settled_at_secs, MIDNIGHT);
+}
+
+#[test]
+fn an_interval_settles_once_when_the_payment_follows_the_boundary() {
+    let mut tracker = FundingSettlementTracker::new();
+    tracker.observe(MARKET, 10.0, MIDNIGHT - 10, HOUR);
+    assert!(tracker.observe(MARKET, 10.0, MIDNIGHT + 2, HOUR).is_some());
+
+    // The payment posts a block later, in the hour that already settled
+    assert_eq!(tracker.observe(MARKET, 12.5, MIDNIGHT + 4, HOUR), None);
+
+    // The next hour settles from the value the payment left behind
+    let settlement = tracker
+        .observe(MARKET, 12.5, MIDNIGHT + HOUR + 1, HOUR)
+        .unwrap();
+    assert_eq!(settlement.previous_cumulative_funding, 12.5);
+    assert_eq!(settlement.settled_at_secs, MIDNIGHT + HOUR);
+}
+
+#[test]
+fn late_updates_from_an_earlier_interval_are_ignored() {
+    let mut tracker = FundingSettlementTracker::new();
+    tracker.observe(MARKET, 10.0, MIDNIGHT - 10, HOUR);
+    assert!(tracker.observe(MARKET, 12.5, MIDNIGHT + 1, HOUR).is_some());
+
+    assert_eq!(tracker.observe(MARKET, 10.0, MIDNIGHT - 2, HOUR), None);
+    assert_eq!(tracker.observe(MARKET, 12.5, MIDNIGHT + 3, HOUR), None);
+}
+
+#[test]
+fn markets_without_an_interval_settle_on_jumps_only() {
+    let mut tracker = FundingSettlementTracker::new();
+    tracker.observe(MARKET, 10.0, MIDNIGHT - 10, 0);
+    assert_eq!(tracker.observe(MARKET, 10.0, MIDNIGHT + 10, 0), None);
+
+    let settlement = tracker.observe(MARKET, 9.0, MIDNIGHT + 20, 0).unwrap();
+    assert_eq!(settlement.funding_delta(), -1.0);
+    assert_eq!(settlement.settled_at_secs, MIDNIGHT + 20);
+}
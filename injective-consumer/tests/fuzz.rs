@@ -0,0 +1,202 @@
+// Malformed and adversarial input fed through the consumer's decoding path.
+// Whatever arrives, decoding has to return an error, which the consumer
+// dead-letters, rather than panic. A message that does decode must not carry
+// numbers the processors would read as zero or store as NaN.
+use injective_consumer::config::SerializationFormat;
+use injective_consumer::models::KafkaMessage;
+use injective_consumer::storage::{MemoryStore, StateProcessor};
+use injective_consumer::{wire, MessageProcessor};
+use proptest::collection::{btree_map, vec};
+use proptest::prelude::*;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::sync::Arc;
+
+const MARKET: &str = "0x4ca0f92fc28be0c9761326016b5a1a2177dd6375558365116b5bdda9abc229ce";
+const SUBACCOUNT: &str = "0x0000000000000000000000000000000000000001000000000000000000000000";
+
+const FORMATS: [SerializationFormat; 3] = [
+    SerializationFormat::Json,
+    SerializationFormat::Protobuf,
+    SerializationFormat::Flatbuffers,
+];
+
+const MESSAGE_TYPES: [&str; 8] = [
+    "DerivativeTrade",
+    "SpotTrade",
+    "StreamPosition",
+    "ExchangePosition",
+    "DerivativeMarket",
+    "StreamOraclePrice",
+    "DerivativeFullOrderbook",
+    "NotAMessageType",
+];
+
+// Strings that f64 parsing takes, or that only look like numbers
+const ADVERSARIAL: [&str; 16] = [
+    "", " ", "NaN", "inf", "-inf", "1e30", "0x1f", " 1", "1 ", "1.2.3", "-", ".", "+1", "١٢٣",
+    "1_000", "null",
+];
+
+fn trade(price: &str, quantity: &str) -> Value {
+    json!({
+        "market_id": MARKET,
+        "is_buy": true,
+        "execution_type": "LimitMatchNewOrder",
+        "subaccount_id": SUBACCOUNT,
+        "position_delta": {
+            "is_long": true,
+            "execution_quantity": quantity,
+            "execution_margin": "1000000000000000000000000000",
+            "execution_price": price,
+        },
+        "payout": "0",
+        "fee": "15000000000000000000000",
+        "order_hash": "0x01",
+        "fee_recipient_address": "inj1feerecipient",
+        "cid": "",
+        "trade_id": "1_0",
+    })
+}
+
+fn position(quantity: &str, entry_price: &str, margin: &str) -> Value {
+    json!({
+        "market_id": MARKET,
+        "subaccount_id": SUBACCOUNT,
+        "is_long": true,
+        "quantity": quantity,
+        "entry_price": entry_price,
+        "margin": margin,
+        "cumulative_funding_entry": "0",
+    })
+}
+
+fn message(message_type: &str, payload: Value) -> Vec<u8> {
+    serde_json::to_vec(&json!({
+        "message_type": message_type,
+        "block_height": 1000,
+        "block_time": 1_704_067_200_000u64,
+        "payload": payload,
+    }))
+    .unwrap()
+}
+
+fn decode_json(bytes: &[u8]) -> Result<KafkaMessage, Box<dyn Error + Send + Sync>> {
+    wire::decode(bytes, SerializationFormat::Json)
+}
+
+#[test]
+fn well_formed_messages_decode() {
+    let price = "30000000000000000000000000000";
+    let quantity = "2000000000000000000";
+    let margin = "6000000000000000000000000000";
+    assert!(decode_json(&message("DerivativeTrade", json!([trade(price, quantity)]))).is_ok());
+    assert!(decode_json(&message(
+        "StreamPosition",
+        json!([position(quantity, price, margin)])
+    ))
+    .is_ok());
+    // Decimal strings are chain numbers too
+    assert!(decode_json(&message(
+        "DerivativeTrade",
+        json!([trade("30000.5", "-0.25")])
+    ))
+    .is_ok());
+}
+
+#[test]
+fn adversarial_numbers_are_rejected() {
+    for bad in ADVERSARIAL {
+        for payload in [
+            message("DerivativeTrade", json!([trade(bad, "1")])),
+            message("DerivativeTrade", json!([trade("1", bad)])),
+            message("StreamPosition", json!([position(bad, "1", "1")])),
+            message("ExchangePosition", json!([position("1", "1", bad)])),
+        ] {
+            assert!(decode_json(&payload).is_err(), "accepted {:?}", bad);
+        }
+    }
+}
+
+#[test]
+fn payloads_of_another_type_are_rejected() {
+    let trades = json!([trade("1", "1")]);
+    let positions = json!([position("1", "1", "1")]);
+    assert!(decode_json(&message("DerivativeMarket", trades.clone())).is_err());
+    assert!(decode_json(&message("StreamOraclePrice", trades)).is_err());
+    assert!(decode_json(&message("DerivativeTrade", positions)).is_err());
+    // An empty payload is empty whatever its type
+    assert!(decode_json(&message("DerivativeTrade", json!([]))).is_ok());
+}
+
+fn chain_number() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "-?[0-9]{1,30}(\\.[0-9]{1,18})?",
+        proptest::sample::select(ADVERSARIAL.to_vec()).prop_map(str::to_string),
+        any::<String>(),
+    ]
+}
+
+fn json_value() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        any::<f64>().prop_map(Value::from),
+        ".*".prop_map(Value::from),
+    ];
+    leaf.prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            vec(inner.clone(), 0..8).prop_map(Value::Array),
+            btree_map("[a-z_]{1,24}", inner, 0..8)
+                .prop_map(|fields| Value::Object(fields.into_iter().collect())),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_bytes_never_panic(bytes in vec(any::<u8>(), 0..512)) {
+        for format in FORMATS {
+            let _ = wire::decode(&bytes, format);
+        }
+    }
+
+    #[test]
+    fn arbitrary_payloads_never_panic(
+        message_type in proptest::sample::select(MESSAGE_TYPES.to_vec()),
+        payload in json_value(),
+    ) {
+        let _ = decode_json(&message(message_type, payload));
+    }
+
+    #[test]
+    fn decoded_messages_hold_real_numbers(
+        price in chain_number(),
+        quantity in chain_number(),
+        margin in chain_number(),
+    ) {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        for bytes in [
+            message("DerivativeTrade", json!([trade(&price, &quantity)])),
+            message("StreamPosition", json!([position(&quantity, &price, &margin)])),
+        ] {
+            let Ok(decoded) = decode_json(&bytes) else {
+                continue;
+            };
+            for value in [&price, &quantity] {
+                prop_assert!(value.parse::<f64>().is_ok_and(f64::is_finite), "accepted {:?}", value);
+            }
+
+            let store = Arc::new(MemoryStore::new());
+            let processor = StateProcessor::new(store.clone(), store.clone());
+            let _ = runtime.block_on(processor.process_message(decoded));
+            for trade in store.trades(MARKET) {
+                prop_assert!(trade.price.is_finite() && trade.quantity.is_finite());
+            }
+            for position in store.positions() {
+                prop_assert!(position.entry_price > 0.0 && position.margin > 0.0);
+            }
+        }
+    }
+}
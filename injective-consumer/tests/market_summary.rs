@@ -0,0 +1,27 @@
+// Open interest from a positions snapshot, with quantities given the way the
+// chain streams them: integer strings scaled by 1e18.
+use injective_consumer::market_summary;
+use injective_consumer::models::PositionPayload;
+
+const MARKET: &str = "0xmarket";
+
+fn position(subaccount_id: &str, is_long: bool, quantity: &str) -> PositionPayload {
+    PositionPayload {
+        market_id: MARKET.to_string(),
+        subaccount_id: subaccount_id.to_string(),
+        is_long,
+        quantity: quantity.to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn a_matched_long_and_short_are_one_side_of_open_interest() {
+    let positions = [
+        position("0xlong", true, "2500000000000000000"),
+        position("0xshort", false, "2500000000000000000"),
+    ];
+
+    let open_interest = market_summary::open_interest(&positions);
+    assert_eq!(open_interest.get(MARKET), Some(&2.5));
+}
@@ -0,0 +1,235 @@
+// Resting L3 orders merged into L2 price levels, as the Redis processor
+// stores them: summed per price, best first, optionally on a coarser grid.
+// Then the live books kept from the sequenced delta stream.
+use injective_consumer::models::{
+    FullLimitOrderbookPayload, OrderbookPayload, PriceLevelPayload, TrimmedLimitOrderPayload,
+};
+use injective_consumer::orderbook::{self, BookLevel, BookTracker, DeltaOutcome, MAX_DEPTH_LEVELS};
+
+fn order(price: &str, quantity: &str) -> TrimmedLimitOrderPayload {
+    TrimmedLimitOrderPayload {
+        price: price.to_string(),
+        quantity: quantity.to_string(),
+        ..Default::default()
+    }
+}
+
+fn level(price: f64, quantity: f64) -> BookLevel {
+    BookLevel { price, quantity }
+}
+
+fn book(
+    bids: Vec<TrimmedLimitOrderPayload>,
+    asks: Vec<TrimmedLimitOrderPayload>,
+) -> FullLimitOrderbookPayload {
+    FullLimitOrderbookPayload {
+        market_id: "0xmarket".to_string(),
+        bids,
+        asks,
+        timestamp: 0,
+    }
+}
+
+#[test]
+fn orders_at_one_price_are_summed_best_first() {
+    let book = book(
+        vec![order("99", "1"), order("100", "2"), order("99", "3")],
+        vec![order("102", "1"), order("101", "4"), order("102", "0.5")],
+    );
+    let l2 = orderbook::aggregate_book(&book, 1.0, 1.0, None);
+
+    assert_eq!(l2.bids, vec![level(100.0, 2.0), level(99.0, 4.0)]);
+    assert_eq!(l2.asks, vec![level(101.0, 4.0), level(102.0, 1.5)]);
+    assert_eq!(l2.best_bid(), Some(&level(100.0, 2.0)));
+    assert_eq!(l2.best_ask(), Some(&level(101.0, 4.0)));
+}
+
+#[test]
+fn chain_values_are_divided_by_the_scales() {
+    // 30,000.5 USDT at 1e24 and 2.5 contracts at 1e18
+    let book = book(
+        vec![order(
+            "30000500000000000000000000000",
+            "2500000000000000000",
+        )],
+        vec![],
+    );
+    let l2 = orderbook::aggregate_book(&book, 1e24, 1e18, None);
+    assert_eq!(l2.bids, vec![level(30_000.5, 2.5)]);
+    assert!(l2.asks.is_empty());
+}
+
+#[test]
+fn a_tick_buckets_bids_down_and_asks_up() {
+    let book = book(
+        vec![
+            order("100.04", "1"),
+            order("100.01", "2"),
+            order("99.99", "1"),
+        ],
+        vec![
+            order("100.11", "1"),
+            order("100.19", "3"),
+            order("100.2", "1"),
+        ],
+    );
+    let l2 = orderbook::aggregate_book(&book, 1.0, 1.0, Some(0.1));
+
+    // A level never looks better than the orders in it
+    assert_eq!(l2.bids, vec![level(100.0, 3.0), level(99.9, 1.0)]);
+    assert_eq!(l2.asks, vec![level(100.2, 5.0)]);
+}
+
+#[test]
+fn empty_orders_are_ignored_and_depth_is_capped() {
+    let bids = (1..=MAX_DEPTH_LEVELS + 10)
+        .map(|price| order(&price.to_string(), "1"))
+        .chain([order("5000", "0")])
+        .collect();
+    let l2 = orderbook::aggregate_book(&book(bids, vec![]), 1.0, 1.0, None);
+
+    assert_eq!(l2.bids.len(), MAX_DEPTH_LEVELS);
+    assert_eq!(l2.bids[0], level((MAX_DEPTH_LEVELS + 10) as f64, 1.0));
+}
+
+#[test]
+fn regrouping_merges_levels_onto_the_coarser_grid() {
+    let l2 = orderbook::aggregate_book(
+        &book(
+            vec![order("100.5", "1"), order("100.2", "2"), order("99.7", "1")],
+            vec![order("101.2", "1"), order("101.8", "1")],
+        ),
+        1.0,
+        1.0,
+        None,
+    );
+    let mut coarse = l2.regroup(Some(1.0));
+    assert_eq!(coarse.bids, vec![level(100.0, 3.0), level(99.0, 1.0)]);
+    assert_eq!(coarse.asks, vec![level(102.0, 2.0)]);
+
+    coarse.truncate(1);
+    assert_eq!(coarse.bids.len(), 1);
+}
+
+#[test]
+fn top_of_book_matches_the_best_levels() {
+    let book = book(
+        vec![order("99", "1"), order("100", "2"), order("100", "1")],
+        vec![order("101", "4"), order("101", "1"), order("103", "1")],
+    );
+    let (bid, ask) = orderbook::top_of_book(&book, 1.0, 1.0);
+    assert_eq!(bid, Some(level(100.0, 3.0)));
+    assert_eq!(ask, Some(level(101.0, 5.0)));
+}
+
+fn delta(sequence: u64, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> OrderbookPayload {
+    let levels = |side: &[(&str, &str)]| {
+        side.iter()
+            .map(|(price, quantity)| PriceLevelPayload {
+                price: price.to_string(),
+                quantity: quantity.to_string(),
+            })
+            .collect()
+    };
+    OrderbookPayload {
+        market_id: "0xmarket".to_string(),
+        buy_levels: levels(bids),
+        sell_levels: levels(asks),
+        sequence,
+    }
+}
+
+fn tracker_at(sequence: u64) -> BookTracker {
+    let mut tracker = BookTracker::new(1.0, 1.0);
+    tracker.resync(
+        &book(
+            vec![order("100", "1"), order("99", "2")],
+            vec![order("101", "1"), order("102", "3")],
+        ),
+        sequence,
+    );
+    tracker
+}
+
+#[test]
+fn deltas_wait_for_a_snapshot() {
+    let mut tracker = BookTracker::new(1.0, 1.0);
+    assert_eq!(
+        tracker.apply(&delta(1, &[("100", "1")], &[])),
+        DeltaOutcome::NeedsSnapshot
+    );
+    assert!(tracker.book("0xmarket").is_none());
+}
+
+#[test]
+fn the_next_delta_updates_its_levels() {
+    let mut tracker = tracker_at(5);
+    let outcome = tracker.apply(&delta(6, &[("100.5", "2")], &[("101", "4")]));
+    assert_eq!(outcome, DeltaOutcome::Applied);
+
+    let live = tracker.book("0xmarket").unwrap();
+    assert_eq!(live.sequence(), 6);
+    assert_eq!(live.best_bid(), Some(level(100.5, 2.0)));
+    // Delta quantities replace the level rather than adding to it
+    assert_eq!(live.best_ask(), Some(level(101.0, 4.0)));
+    assert_eq!(live.mid_price(), Some(100.75));
+}
+
+#[test]
+fn a_zero_quantity_removes_the_level() {
+    let mut tracker = tracker_at(5);
+    let outcome = tracker.apply(&delta(6, &[("100", "0")], &[("101", "0")]));
+    assert_eq!(outcome, DeltaOutcome::Applied);
+
+    let live = tracker.book("0xmarket").unwrap();
+    assert_eq!(live.best_bid(), Some(level(99.0, 2.0)));
+    assert_eq!(live.best_ask(), Some(level(102.0, 3.0)));
+}
+
+#[test]
+fn old_deltas_are_stale() {
+    let mut tracker = tracker_at(5);
+    assert_eq!(
+        tracker.apply(&delta(5, &[("100", "0")], &[])),
+        DeltaOutcome::Stale
+    );
+    assert_eq!(
+        tracker.apply(&delta(3, &[("100", "0")], &[])),
+        DeltaOutcome::Stale
+    );
+    assert_eq!(
+        tracker.book("0xmarket").unwrap().best_bid(),
+        Some(level(100.0, 1.0))
+    );
+}
+
+#[test]
+fn a_gap_drops_the_book_until_a_resync() {
+    let mut tracker = tracker_at(5);
+    let outcome = tracker.apply(&delta(8, &[("100.5", "1")], &[]));
+    assert_eq!(
+        outcome,
+        DeltaOutcome::Gap {
+            expected: 6,
+            received: 8
+        }
+    );
+    assert!(tracker.book("0xmarket").is_none());
+    assert_eq!(
+        tracker.apply(&delta(9, &[], &[])),
+        DeltaOutcome::NeedsSnapshot
+    );
+
+    // The snapshot follows delta 9, so the stream picks up at 10
+    let live = tracker.resync(&book(vec![order("98", "1")], vec![order("103", "1")]), 9);
+    assert_eq!(live.sequence(), 9);
+    assert_eq!(tracker.apply(&delta(9, &[], &[])), DeltaOutcome::Stale);
+    assert_eq!(
+        tracker.apply(&delta(10, &[("98.5", "1")], &[])),
+        DeltaOutcome::Applied
+    );
+    assert_eq!(
+        tracker.book("0xmarket").unwrap().best_bid(),
+        Some(level(98.5, 1.0))
+    );
+}
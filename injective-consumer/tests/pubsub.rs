@@ -0,0 +1,58 @@
+// Sequence checks on subscribed channels: each channel counts on its own,
+// and duplicates or reordered events never move it back.
+use injective_consumer::pubsub::{GapDetector, SequenceCheck};
+
+#[test]
+fn consecutive_sequences_are_in_order() {
+    let mut detector = GapDetector::new();
+    for sequence in 7..=10 {
+        assert_eq!(detector.check("prices", sequence), SequenceCheck::InOrder);
+    }
+}
+
+#[test]
+fn a_skipped_sequence_reports_the_missed_count() {
+    let mut detector = GapDetector::new();
+    detector.check("prices", 1);
+    assert_eq!(
+        detector.check("prices", 5),
+        SequenceCheck::Gap { missed: 3 }
+    );
+    // The gap is only reported once
+    assert_eq!(detector.check("prices", 6), SequenceCheck::InOrder);
+}
+
+#[test]
+fn duplicates_and_reordered_events_are_stale() {
+    let mut detector = GapDetector::new();
+    detector.check("prices", 3);
+    detector.check("prices", 4);
+    assert_eq!(
+        detector.check("prices", 4),
+        SequenceCheck::Stale { last: 4 }
+    );
+    assert_eq!(
+        detector.check("prices", 2),
+        SequenceCheck::Stale { last: 4 }
+    );
+    assert_eq!(detector.check("prices", 5), SequenceCheck::InOrder);
+}
+
+#[test]
+fn channels_are_tracked_separately() {
+    let mut detector = GapDetector::new();
+    detector.check("prices", 10);
+    assert_eq!(detector.check("markets", 3), SequenceCheck::InOrder);
+    assert_eq!(detector.check("prices", 11), SequenceCheck::InOrder);
+    assert_eq!(detector.check("markets", 4), SequenceCheck::InOrder);
+}
+
+#[test]
+fn restarts_and_unsequenced_events() {
+    let mut detector = GapDetector::new();
+    detector.check("prices", 40);
+    assert_eq!(detector.check("prices", 1), SequenceCheck::Restarted);
+    assert_eq!(detector.check("prices", 2), SequenceCheck::InOrder);
+    assert_eq!(detector.check("prices", 0), SequenceCheck::Unsequenced);
+    assert_eq!(detector.check("prices", 3), SequenceCheck::InOrder);
+}
@@ -0,0 +1,91 @@
+// Key schemas: the default keeps the documented layout, and a prefix,
+// environment tag or separator namespaces every key, so two indexers sharing
+// one Redis never write the same key.
+use injective_consumer::config::RedisKeySchema;
+use injective_consumer::redis_keys;
+
+const MARKET: &str = "0x4ca0f92fc28be0c9761326016b5a1a2177dd6375558365116b5bdda9abc229ce";
+const SUBACCOUNT: &str = "0x0000000000000000000000000000000000000001000000000000000000000000";
+
+fn schema(prefix: &str, separator: &str, environment: Option<&str>) -> RedisKeySchema {
+    RedisKeySchema {
+        prefix: prefix.to_string(),
+        separator: separator.to_string(),
+        environment: environment.map(str::to_string),
+    }
+}
+
+#[test]
+fn default_schema_keeps_the_layout() {
+    let keys = RedisKeySchema::default();
+    assert_eq!(
+        keys.key(&["market", "derivative", MARKET]),
+        format!("market:derivative:{}", MARKET)
+    );
+    assert_eq!(
+        keys.key(&["liquidatable_positions"]),
+        "liquidatable_positions"
+    );
+    assert_eq!(
+        keys.key_prefix(&["positions", "market"]),
+        "positions:market:"
+    );
+}
+
+#[test]
+fn prefix_environment_and_separator_namespace_keys() {
+    let keys = schema("indexer-a", ":", Some("testnet"));
+    assert_eq!(
+        keys.key(&["position", MARKET, SUBACCOUNT]),
+        format!("indexer-a:testnet:position:{}:{}", MARKET, SUBACCOUNT)
+    );
+    assert_eq!(
+        keys.key_prefix(&["position"]),
+        "indexer-a:testnet:position:"
+    );
+
+    // Either part of the namespace may be left out
+    assert_eq!(
+        schema("", ":", Some("testnet")).key(&["addresses"]),
+        "testnet:addresses"
+    );
+    assert_eq!(
+        schema("indexer-a", ":", None).key(&["addresses"]),
+        "indexer-a:addresses"
+    );
+    assert_eq!(
+        schema("indexer-a", "|", None).key(&["schema", "version"]),
+        "indexer-a|schema|version"
+    );
+}
+
+#[test]
+fn installed_schema_applies_to_every_key() {
+    redis_keys::install(schema("indexer-b", ":", Some("mainnet")));
+
+    assert_eq!(
+        redis_keys::derivative_market(MARKET),
+        format!("indexer-b:mainnet:market:derivative:{}", MARKET)
+    );
+    assert_eq!(
+        redis_keys::derivative_markets(),
+        "indexer-b:mainnet:markets:derivative"
+    );
+    assert_eq!(
+        redis_keys::liquidation_alerts_channel(),
+        "indexer-b:mainnet:liquidation_alerts"
+    );
+    assert_eq!(
+        redis_keys::pubsub_dedup(0xab),
+        "indexer-b:mainnet:pubsub:dedup:00000000000000ab"
+    );
+    // Set members and version 1 keys are not keys of the schema
+    assert_eq!(
+        redis_keys::liquidatable_member(MARKET, SUBACCOUNT),
+        format!("{}:{}", MARKET, SUBACCOUNT)
+    );
+    assert_eq!(
+        redis_keys::legacy_market(MARKET),
+        format!("market:{}:data", MARKET)
+    );
+}
@@ -0,0 +1,99 @@
+// Per-field scaling of chain values, against values as the chain streams them
+// for a BTC/USDT perpetual around 30,000 and an oracle feed.
+use injective_consumer::compute::decimal;
+use injective_consumer::scaling::{self, CHAIN_DECIMALS, PRICE_DECIMALS};
+use rust_decimal::prelude::ToPrimitive;
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() <= expected.abs() * 1e-12,
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn prices_margins_fees_and_funding_are_1e24() {
+    // 30,000.5 USDT
+    assert_close(scaling::price("30000500000000000000000000000"), 30_000.5);
+    // 1,500 USDT of margin
+    assert_close(scaling::margin("1500000000000000000000000000"), 1_500.0);
+    // A 0.06 USDT fee
+    assert_close(scaling::fee("60000000000000000000000"), 0.06);
+    // Cumulative funding goes negative when shorts have paid
+    assert_close(scaling::funding("-12345000000000000000000"), -0.012345);
+}
+
+#[test]
+fn quantities_ratios_and_oracle_prices_are_1e18() {
+    assert_close(scaling::quantity("2500000000000000000"), 2.5);
+    // 5% maintenance margin, 0.05% taker fee, 0.000625 hourly funding cap
+    assert_close(scaling::ratio("50000000000000000"), 0.05);
+    assert_close(scaling::ratio("500000000000000"), 0.0005);
+    assert_close(scaling::ratio("625000000000000"), 0.000625);
+    assert_close(scaling::oracle_price("30012340000000000000000"), 30_012.34);
+}
+
+#[test]
+fn missing_values_read_as_zero_or_the_default_ratio() {
+    assert_eq!(scaling::price(""), 0.0);
+    assert_eq!(scaling::quantity("not a number"), 0.0);
+    assert_close(
+        scaling::maintenance_margin_ratio("62500000000000000"),
+        0.0625,
+    );
+    assert_eq!(
+        scaling::maintenance_margin_ratio(""),
+        scaling::DEFAULT_MAINTENANCE_MARGIN_RATIO
+    );
+}
+
+#[test]
+fn stored_maintenance_margin_ratios_read_back_scaled() {
+    // The markets table stores the ratio scaled, as the processor writes it
+    let written = scaling::maintenance_margin_ratio("62500000000000000").to_string();
+    assert_close(scaling::stored_maintenance_margin_ratio(&written), 0.0625);
+
+    // Rows written before that hold the chain value
+    assert_close(
+        scaling::stored_maintenance_margin_ratio("62500000000000000"),
+        0.0625,
+    );
+    assert_eq!(
+        scaling::stored_maintenance_margin_ratio(""),
+        scaling::DEFAULT_MAINTENANCE_MARGIN_RATIO
+    );
+}
+
+#[test]
+fn f64_and_decimal_scales_agree() {
+    for (raw, decimals, scaled) in [
+        (
+            "30000500000000000000000000000",
+            PRICE_DECIMALS,
+            scaling::price as fn(&str) -> f64,
+        ),
+        ("2500000000000000000", CHAIN_DECIMALS, scaling::quantity),
+        ("50000000000000000", CHAIN_DECIMALS, scaling::ratio),
+    ] {
+        let exact = decimal::from_chain(raw, decimals).unwrap();
+        assert_close(scaled(raw), exact.to_f64().unwrap());
+    }
+}
+
+#[test]
+fn spot_values_scale_with_the_market_decimals() {
+    // INJ/USDT: 18 base decimals, 6 quote decimals. 25.5 USDT per INJ is
+    // 25.5e-12 quote units per base unit, in 1e18 fixed point.
+    assert_close(scaling::spot_price("25500000", 18, 6), 25.5);
+    // 2 INJ and a 0.051 USDT fee
+    assert_close(
+        scaling::spot_quantity(&format!("2{}", "0".repeat(36)), 18),
+        2.0,
+    );
+    assert_close(
+        scaling::spot_fee(&format!("51{}", "0".repeat(21)), 6),
+        0.051,
+    );
+}
@@ -0,0 +1,114 @@
+// Event-time windows: they close when block time passes their end plus the
+// allowed lateness, and events for windows closed by then are dropped.
+use injective_consumer::window::{Aggregate, ClosedWindow, WindowSpec, WindowedAggregator};
+use std::time::Duration;
+
+const MINUTE: i64 = 60_000;
+
+// Volume traded in a window
+#[derive(Debug, Clone, PartialEq)]
+struct Volume(f64);
+
+impl Aggregate for Volume {
+    type Event = f64;
+
+    fn first(_start: i64, event: &f64) -> Self {
+        Volume(*event)
+    }
+
+    fn add(&mut self, event: &f64) {
+        self.0 += event;
+    }
+}
+
+fn minutes() -> WindowedAggregator<Volume> {
+    WindowedAggregator::new(WindowSpec::tumbling(Duration::from_secs(60)))
+}
+
+#[test]
+fn a_window_closes_once_block_time_passes_its_end() {
+    let mut windows = minutes();
+    assert!(windows.add("btc", 10_000, &1.0));
+    assert!(windows.add("btc", 50_000, &2.0));
+    assert!(windows.advance(MINUTE - 1).is_empty());
+
+    let closed = windows.advance(MINUTE);
+    assert_eq!(
+        closed,
+        vec![ClosedWindow {
+            key: "btc".to_string(),
+            start: 0,
+            end: MINUTE,
+            value: Volume(3.0),
+        }]
+    );
+    assert!(windows.windows("btc").next().is_none());
+}
+
+#[test]
+fn the_watermark_never_moves_back() {
+    let mut windows = minutes();
+    windows.add("btc", 10_000, &1.0);
+    windows.advance(30_000);
+    windows.add("btc", 70_000, &1.0);
+
+    // An older block time neither closes nor reopens anything
+    assert!(windows.advance(20_000).is_empty());
+    let closed = windows.advance(2 * MINUTE);
+    assert_eq!(
+        closed.iter().map(|w| w.start).collect::<Vec<_>>(),
+        [0, MINUTE]
+    );
+}
+
+#[test]
+fn late_events_are_dropped() {
+    let mut windows = minutes();
+    windows.add("btc", 10_000, &1.0);
+    windows.advance(MINUTE + 5_000);
+
+    assert!(!windows.accepts(59_000));
+    assert!(!windows.add("btc", 59_000, &4.0));
+    assert!(windows.get("btc", 0).is_none());
+    // Still on time for the window the watermark is in
+    assert!(windows.add("btc", MINUTE + 1_000, &4.0));
+}
+
+#[test]
+fn allowed_lateness_keeps_a_window_open() {
+    let mut windows = minutes();
+    windows.set_allowed_lateness(Duration::from_secs(30));
+    windows.add("btc", 10_000, &1.0);
+
+    assert!(windows.advance(MINUTE + 10_000).is_empty());
+    assert!(windows.add("btc", 59_000, &2.0));
+    assert_eq!(windows.get("btc", 0), Some(&Volume(3.0)));
+
+    let closed = windows.advance(MINUTE + 30_000);
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].value, Volume(3.0));
+    assert!(!windows.add("btc", 59_500, &1.0));
+}
+
+#[test]
+fn closed_windows_are_ordered_by_key_and_start() {
+    let mut windows = minutes();
+    windows.add("eth", MINUTE + 1, &1.0);
+    windows.add("btc", MINUTE + 1, &1.0);
+    windows.add("eth", 1, &1.0);
+
+    let closed = windows.advance(5 * MINUTE);
+    let order: Vec<_> = closed.iter().map(|w| (w.key.as_str(), w.start)).collect();
+    assert_eq!(order, [("btc", MINUTE), ("eth", 0), ("eth", MINUTE)]);
+}
+
+#[test]
+fn sliding_windows_count_an_event_in_each_window_containing_it() {
+    let spec = WindowSpec::sliding(Duration::from_secs(60), Duration::from_secs(20));
+    assert_eq!(spec.starts(50_000), [0, 20_000, 40_000]);
+
+    let mut windows = WindowedAggregator::<Volume>::new(spec);
+    windows.add("btc", 50_000, &2.0);
+    let open: Vec<_> = windows.windows("btc").map(|(start, _)| start).collect();
+    assert_eq!(open, [0, 20_000, 40_000]);
+}